@@ -3,6 +3,7 @@ use futures::StreamExt;
 use lru::LruCache;
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
 use std::num::NonZeroUsize;
 use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
@@ -53,6 +54,63 @@ impl Provider {
         }
     }
 
+    /// Splits a model reference into the provider that serves it and the
+    /// model name. An explicit `provider:model` or `provider/model` prefix
+    /// wins; otherwise well-known model families are recognized. Anything
+    /// else is `None`, so callers can fall back to the configured provider.
+    pub fn for_model(reference: &str) -> Option<(Provider, String)> {
+        let reference = reference.trim();
+        if let Some((prefix, model)) = reference.split_once([':', '/']) {
+            if !prefix.starts_with("http") {
+                if let Ok(provider) = Provider::from_string(prefix) {
+                    return Some((provider, model.to_string()));
+                }
+            }
+        }
+
+        let lower = reference.to_lowercase();
+        let provider = if lower.starts_with("gpt-")
+            || lower.starts_with("chatgpt")
+            || ["o1", "o3", "o4"].iter().any(|p| lower == *p || lower.starts_with(&format!("{}-", p)))
+        {
+            Provider::OpenAI
+        } else if lower.starts_with("claude") {
+            Provider::Claude
+        } else if lower.starts_with("gemini") {
+            Provider::Gemini
+        } else if lower.starts_with("grok") {
+            Provider::Grok
+        } else if ["mistral", "mixtral", "codestral", "ministral", "pixtral", "open-mistral"]
+            .iter()
+            .any(|p| lower.starts_with(p))
+        {
+            Provider::Mistral
+        } else if lower.starts_with("command") {
+            Provider::Cohere
+        } else {
+            return None;
+        };
+        Some((provider, reference.to_string()))
+    }
+
+    /// Whether `send_message_stream` receives the reply incrementally, as
+    /// OpenAI-style server-sent events.
+    pub fn supports_streaming(&self) -> bool {
+        matches!(
+            self,
+            Provider::Chat | Provider::ChatAnywhere | Provider::OpenAI | Provider::Grok | Provider::Mistral | Provider::Custom(_)
+        )
+    }
+
+    /// Whether the provider can be held to a JSON Schema natively, through
+    /// `response_format`, Gemini's `responseSchema` or Ollama's `format`.
+    pub fn supports_json_schema(&self) -> bool {
+        matches!(
+            self,
+            Provider::ChatAnywhere | Provider::OpenAI | Provider::Grok | Provider::Ollama | Provider::Gemini | Provider::Custom(_)
+        )
+    }
+
     pub fn requires_api_key(&self) -> bool {
         !matches!(self, Provider::Chat | Provider::Ollama)
     }
@@ -103,6 +161,8 @@ pub struct CohereResponse {
 #[derive(Serialize, Deserialize, Debug)]
 pub struct GeminiRequest {
     pub contents: Vec<GeminiContent>,
+    #[serde(rename = "generationConfig", skip_serializing_if = "Option::is_none")]
+    pub generation_config: Option<Value>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -153,10 +213,58 @@ impl GeminiRequest {
 
         Ok(Self {
             contents: vec![GeminiContent { parts }],
+            generation_config: None,
         })
     }
 }
 
+/// A JSON Schema replies must follow, on providers that support it natively.
+#[derive(Debug, Clone)]
+pub struct ResponseSchema {
+    /// Letters, digits, `_` and `-` only, as OpenAI requires
+    pub name: String,
+    pub schema: Value,
+}
+
+impl ResponseSchema {
+    /// Gemini's `responseSchema` is an OpenAPI subset that rejects keywords
+    /// such as `$schema` and `additionalProperties`, so only the ones it
+    /// knows are kept.
+    fn for_gemini(schema: &Value) -> Value {
+        const KEPT: [&str; 11] = [
+            "type", "format", "description", "nullable", "enum", "properties", "required", "items", "minItems",
+            "maxItems", "anyOf",
+        ];
+        match schema {
+            Value::Object(object) => Value::Object(
+                object
+                    .iter()
+                    .filter(|(key, _)| KEPT.contains(&key.as_str()))
+                    .map(|(key, value)| {
+                        let value = match key.as_str() {
+                            "properties" => Value::Object(
+                                value
+                                    .as_object()
+                                    .map(|properties| {
+                                        properties.iter().map(|(name, s)| (name.clone(), Self::for_gemini(s))).collect()
+                                    })
+                                    .unwrap_or_default(),
+                            ),
+                            "items" => Self::for_gemini(value),
+                            "anyOf" => Value::Array(
+                                value.as_array().map(|options| options.iter().map(Self::for_gemini).collect()).unwrap_or_default(),
+                            ),
+                            _ => value.clone(),
+                        };
+                        (key.clone(), value)
+                    })
+                    .collect(),
+            ),
+            other => other.clone(),
+        }
+    }
+}
+
 #[derive(Clone, Debug)]
 struct CacheEntry {
     response: String,
@@ -178,6 +286,8 @@ impl Clone for ApiClient {
             api_key: self.api_key.clone(),
             timeout: self.timeout,
             cache: Arc::clone(&self.cache),
+            use_cache: self.use_cache,
+            response_schema: self.response_schema.clone(),
         }
     }
 }
@@ -304,6 +414,39 @@ pub struct Delta {
     pub content: Option<String>,
 }
 
+/// Timings captured while a streamed response arrives. Content deltas are
+/// roughly one token each, so gaps between them stand in for inter-token
+/// latency.
+#[derive(Debug, Clone, Default)]
+pub struct StreamTiming {
+    /// Until the first bytes of the response body
+    pub first_byte: Option<Duration>,
+    /// Until the first non-empty content delta
+    pub first_token: Option<Duration>,
+    /// Time between successive content deltas
+    pub token_gaps: Vec<Duration>,
+    /// Until the stream ended
+    pub total: Duration,
+}
+
+impl StreamTiming {
+    /// Time spent generating after the first token arrived.
+    pub fn generation_time(&self) -> Option<Duration> {
+        self.first_token.map(|first| self.total.saturating_sub(first))
+    }
+
+    /// Nearest-rank percentile of the inter-token gaps.
+    pub fn token_gap_percentile(&self, pct: f64) -> Option<Duration> {
+        if self.token_gaps.is_empty() {
+            return None;
+        }
+        let mut sorted = self.token_gaps.clone();
+        sorted.sort_unstable();
+        let rank = ((pct / 100.0) * sorted.len() as f64).ceil() as usize;
+        Some(sorted[rank.clamp(1, sorted.len()) - 1])
+    }
+}
+
 #[derive(Debug)]
 pub struct ApiClient {
     client: Arc<Client>,
@@ -312,6 +455,8 @@ pub struct ApiClient {
     #[allow(dead_code)]
     timeout: Duration,
     cache: Arc<Mutex<LruCache<String, CacheEntry>>>,
+    use_cache: bool,
+    response_schema: Option<ResponseSchema>,
 }
 
 impl ApiClient {
@@ -341,9 +486,51 @@ impl ApiClient {
             api_key: api_key.or_else(|| std::env::var("ECHOMIND_API_KEY").ok()),
             timeout: Duration::from_secs(timeout),
             cache: Arc::new(Mutex::new(LruCache::new(NonZeroUsize::new(100).unwrap()))), // Cache up to 100 entries
+            use_cache: true,
+            response_schema: None,
         })
     }
 
+    pub fn provider(&self) -> &Provider {
+        &self.provider
+    }
+
+    /// Turns off answering repeated requests from the response cache, for
+    /// measurements such as benchmarks.
+    pub fn set_cache_enabled(&mut self, enabled: bool) {
+        self.use_cache = enabled;
+    }
+
+    /// Holds replies to `schema` on providers with a native structured
+    /// output mode. Other providers ignore it, so callers still validate.
+    pub fn set_response_schema(&mut self, schema: Option<ResponseSchema>) {
+        self.response_schema = schema;
+    }
+
+    // The JSON body for OpenAI-style endpoints, with the response schema in
+    // the shape the provider expects
+    fn request_body(&self, request: &ChatRequest) -> Result<Value> {
+        let mut body = serde_json::to_value(request)?;
+        if let (Some(schema), Some(fields)) = (&self.response_schema, body.as_object_mut()) {
+            match self.provider {
+                Provider::Ollama => {
+                    fields.insert("format".to_string(), schema.schema.clone());
+                }
+                ref provider if provider.supports_json_schema() => {
+                    fields.insert(
+                        "response_format".to_string(),
+                        json!({
+                            "type": "json_schema",
+                            "json_schema": {"name": schema.name, "schema": schema.schema, "strict": false}
+                        }),
+                    );
+                }
+                _ => {}
+            }
+        }
+        Ok(body)
+    }
+
     // List available models for Gemini
     #[allow(dead_code)]
     pub async fn list_models(&self) -> Result<Vec<GeminiModel>> {
@@ -405,6 +592,10 @@ impl ApiClient {
             tokens.hash(&mut hasher);
         }
 
+        if let Some(schema) = &self.response_schema {
+            schema.schema.to_string().hash(&mut hasher);
+        }
+
         // Hash message contents
         for message in &request.messages {
             message.role.hash(&mut hasher);
@@ -418,7 +609,7 @@ impl ApiClient {
 
     pub async fn send_message(&self, request: ChatRequest) -> Result<String> {
         // Check cache first (only for non-streaming requests)
-        if request.stream.is_none() || !request.stream.unwrap_or(false) {
+        if self.use_cache && !request.stream.unwrap_or(false) {
             let cache_key = self.generate_cache_key(&request);
             if let Ok(mut cache) = self.cache.lock() {
                 if let Some(entry) = cache.get(&cache_key) {
@@ -490,7 +681,13 @@ impl ApiClient {
                 let model = request.model.as_deref().unwrap_or("gemini-pro");
                 let endpoint = format!("{}/v1beta/models/{}:generateContent", base_endpoint, model);
 
-                let gemini_request = GeminiRequest::from_chat_request(&request)?;
+                let mut gemini_request = GeminiRequest::from_chat_request(&request)?;
+                if let Some(schema) = &self.response_schema {
+                    gemini_request.generation_config = Some(json!({
+                        "responseMimeType": "application/json",
+                        "responseSchema": ResponseSchema::for_gemini(&schema.schema)
+                    }));
+                }
 
                 let mut req_builder = self.client.post(&endpoint).json(&gemini_request);
 
@@ -544,7 +741,7 @@ impl ApiClient {
             _ => {
                 let endpoint = self.provider.endpoint();
 
-            let mut req_builder = self.client.post(endpoint).json(&request);
+            let mut req_builder = self.client.post(endpoint).json(&self.request_body(&request)?);
 
             // Add authorization header if API key is available
             if let Some(ref key) = self.api_key {
@@ -604,21 +801,45 @@ impl ApiClient {
     pub async fn send_message_stream<F>(
         &self,
         request: ChatRequest,
-        mut callback: F,
+        callback: F,
     ) -> Result<String>
     where
         F: FnMut(&str),
     {
+        self.send_message_stream_timed(request, callback)
+            .await
+            .map(|(text, _)| text)
+    }
+
+    /// Like `send_message_stream`, also reporting when the first byte and
+    /// first token arrived and the gaps between tokens.
+    pub async fn send_message_stream_timed<F>(
+        &self,
+        request: ChatRequest,
+        mut callback: F,
+    ) -> Result<(String, StreamTiming)>
+    where
+        F: FnMut(&str),
+    {
+        let start = Instant::now();
+
         // Cohere and Gemini do not use OpenAI-style SSE here; emulate streaming by a single callback
         if matches!(self.provider, Provider::Gemini | Provider::Cohere) {
             let text = self.send_message(request).await?;
+            let total = start.elapsed();
             callback(&text);
-            return Ok(text);
+            let timing = StreamTiming {
+                first_byte: Some(total),
+                first_token: Some(total),
+                token_gaps: Vec::new(),
+                total,
+            };
+            return Ok((text, timing));
         }
 
         let endpoint = self.provider.endpoint();
 
-        let mut req_builder = self.client.post(endpoint).json(&request);
+        let mut req_builder = self.client.post(endpoint).json(&self.request_body(&request)?);
 
         // Add authorization header if API key is available
         if let Some(ref key) = self.api_key {
@@ -651,9 +872,27 @@ impl ApiClient {
         let mut full_content = String::with_capacity(4096); // Pre-allocate reasonable capacity
         let mut stream = response.bytes_stream();
         let mut buffer = String::with_capacity(1024); // Buffer for accumulating partial lines
+        let mut first_byte = None;
+        let mut first_token = None;
+        let mut last_token: Option<Instant> = None;
+        let mut token_gaps = Vec::new();
+        let mut emit = |content: &str| {
+            if content.is_empty() {
+                return;
+            }
+            let now = Instant::now();
+            match last_token {
+                Some(last) => token_gaps.push(now - last),
+                None => first_token = Some(now - start),
+            }
+            last_token = Some(now);
+            callback(content);
+            full_content.push_str(content);
+        };
 
         while let Some(chunk) = stream.next().await {
             let chunk = chunk.map_err(|e| EchomindError::NetworkError(e.to_string()))?;
+            first_byte.get_or_insert_with(|| start.elapsed());
             let text = String::from_utf8_lossy(&chunk);
 
             // Accumulate text in buffer to handle partial lines
@@ -673,8 +912,7 @@ impl ApiClient {
                     if let Ok(chunk_data) = serde_json::from_str::<StreamChunk>(data) {
                         if let Some(choice) = chunk_data.choices.first() {
                             if let Some(content) = &choice.delta.content {
-                                callback(content);
-                                full_content.push_str(content);
+                                emit(content);
                             }
                         }
                     }
@@ -690,8 +928,7 @@ impl ApiClient {
                     if let Ok(chunk_data) = serde_json::from_str::<StreamChunk>(data) {
                         if let Some(choice) = chunk_data.choices.first() {
                             if let Some(content) = &choice.delta.content {
-                                callback(content);
-                                full_content.push_str(content);
+                                emit(content);
                             }
                         }
                     }
@@ -699,6 +936,12 @@ impl ApiClient {
             }
         }
 
-        Ok(full_content)
+        let timing = StreamTiming {
+            first_byte,
+            first_token,
+            token_gaps,
+            total: start.elapsed(),
+        };
+        Ok((full_content, timing))
     }
 }
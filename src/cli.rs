@@ -1,4 +1,4 @@
-use clap::Parser;
+use clap::{Parser, Subcommand};
 
 #[derive(Parser, Debug, Clone)]
 #[command(name = "echomind")]
@@ -14,6 +14,32 @@ Examples:
   echomind --interactive
   echomind --init-config
   echo 'explain quantum computing' | echomind --provider openai --model gpt-4
+  echomind --workflow review.yaml --var file=src/main.rs
+  echomind workflow validate review.yaml
+  echomind --workflow review.yaml --dry-run
+  echomind workflow runs
+  echomind workflow resume 3f2a9c
+  echomind --schedule '0 9 * * 1-5' 'Summarize overnight alerts' --output digest.md
+  echomind schedule list
+  echomind daemon
+  echomind template add review --file review.txt
+  git diff | echomind --template review --var focus=security
+  echomind pack install https://github.com/acme/review-pack.git
+  echomind --csv sales.csv 'which region grew fastest?'
+  echomind --csv sales.csv --sql 'SELECT region, SUM(revenue) AS total GROUP BY region ORDER BY total DESC'
+  echomind --csv sales.csv --chart bar --x region --y revenue --output revenue.svg
+  echomind --csv people.csv --transform 'normalize signup dates, lowercase emails, dedupe by email' -o clean.csv
+  echomind --excel budget.xlsx --sheet Q3 'which department is over budget?'
+  echomind --benchmark-compare openai:gpt-4o-mini,claude:claude-3-5-haiku-latest --suite smoke.yaml -o bench.md
+  echomind eval support.yaml --models gpt-4o-mini,claude-3-5-haiku-latest --baseline eval-baseline.json
+  echomind usage --period monthly --by preset
+  echomind --batch tickets.jsonl --batch-concurrency 8 -o results.jsonl
+  echo 'Explain CRDTs' | echomind --compare gpt-4o-mini,claude-3-5-haiku-latest --judge gpt-4o
+  cat invoice.txt | echomind --schema invoice.schema.json 'extract the invoice fields'
+  echo 'Write a haiku' | echomind --stream --format ndjson --to-clipboard | jq -c 'select(.type == \"delta\")'
+  echomind --batch tickets.jsonl --batch-api && echomind -o results.jsonl batch fetch <JOB_ID> --wait
+  echomind --stress --rps 20 --concurrency 50 --duration 60 -p http://gateway:8080/v1/chat/completions 'Say hi'
+  echomind pack list
 
 Features:
   • Multiple AI providers (OpenAI, Claude, Gemini, Ollama, Grok, Mistral, Cohere, ChatAnywhere, ch.at)
@@ -30,6 +56,9 @@ Features:
   • Performance optimized with async I/O and caching"
 )]
 pub struct Args {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
     /// Enable coder mode (generates clean code without explanations)
     #[arg(short = 'c', long)]
     pub coder: bool,
@@ -118,14 +147,28 @@ pub struct Args {
     #[arg(long)]
     pub history: Option<String>,
 
-    /// Compare responses from multiple models (comma-separated)
+    /// Compare responses from multiple models (comma-separated provider:model or model
+    /// names); with --tui the responses open in a split view
     #[arg(long)]
     pub compare: Option<String>,
 
-    /// Output format: text, json, or template:<template>
+    /// How --compare shows differences from the first model's response
+    #[arg(long, value_name = "STYLE", default_value = "side-by-side", value_parser = ["side-by-side", "unified", "none"], requires = "compare")]
+    pub diff: String,
+
+    /// Model that judges the --compare responses and picks the best
+    #[arg(long, value_name = "MODEL", requires = "compare")]
+    pub judge: Option<String>,
+
+    /// Output format: text, json, ndjson (delta/reset/done/error events), or template:<template>
     #[arg(long)]
     pub format: Option<String>,
 
+    /// JSON Schema file the reply must match; the reply is validated and
+    /// repaired once if it doesn't
+    #[arg(long, value_name = "FILE.json", conflicts_with_all = ["stream", "compare"])]
+    pub schema: Option<String>,
+
     // /// Image file to include with the request (for vision models)
     // #[arg(long)]
     // pub image: Option<String>,
@@ -138,10 +181,29 @@ pub struct Args {
     #[arg(long)]
     pub preset: Option<String>,
 
-    /// Process multiple queries from a file, one per line
+    /// Process multiple queries from a file: one query per line, or JSONL records
+    /// with id, input, template, vars, system and model. Results are JSON Lines;
+    /// with --output the file is also a checkpoint, so a rerun skips finished records
     #[arg(long)]
     pub batch: Option<String>,
 
+    /// Batch records to run at once
+    #[arg(long, value_name = "N", default_value_t = 4, requires = "batch")]
+    pub batch_concurrency: usize,
+
+    /// Extra attempts for a batch record whose request fails
+    #[arg(long, value_name = "N", default_value_t = 2, requires = "batch")]
+    pub batch_retries: u32,
+
+    /// Submit --batch through the OpenAI Batch API (half price, results within 24h)
+    /// instead of calling the model directly; follow it with `echomind batch status`
+    #[arg(long, requires = "batch")]
+    pub batch_api: bool,
+
+    /// Wait for the submitted batch to finish and fetch its results
+    #[arg(long, requires = "batch_api")]
+    pub batch_wait: bool,
+
     // Voice features (disabled)
     // /// Enable voice input from microphone
     // #[arg(long)]
@@ -194,7 +256,7 @@ pub struct Args {
     // pub batch_images: Option<String>,
 
     // Workflow features
-    /// Execute workflow from file
+    /// Execute workflow from file (JSON, YAML or TOML) or by saved name
     #[arg(long)]
     pub workflow: Option<String>,
 
@@ -202,6 +264,14 @@ pub struct Args {
     #[arg(long)]
     pub list_workflows: bool,
 
+    /// Set a workflow/template variable (key=value, repeatable)
+    #[arg(long = "var", value_name = "KEY=VALUE")]
+    pub vars: Vec<String>,
+
+    /// Print the planned workflow path with rendered prompts without calling any provider
+    #[arg(long, requires = "workflow")]
+    pub dry_run: bool,
+
     // Collaboration features
     /// Share conversation
     #[arg(long)]
@@ -225,14 +295,42 @@ pub struct Args {
     pub audit_log: bool,
 
     // Performance features
-    /// Benchmark model performance
+    /// Benchmark the configured model on the prompt or a --suite, reporting latency, TTFT, tokens/s, cost and pass rate
     #[arg(long)]
     pub benchmark: bool,
 
-    /// Compare model performance
-    #[arg(long)]
+    /// Benchmark several models side by side (comma-separated provider:model)
+    #[arg(long, value_name = "MODELS", value_delimiter = ',')]
     pub benchmark_compare: Option<Vec<String>>,
 
+    /// Benchmark suite file (YAML, JSON or TOML) of prompts with expected response properties
+    #[arg(long, value_name = "FILE")]
+    pub suite: Option<String>,
+
+    /// Runs per prompt and model when benchmarking (default: the suite's, else 3)
+    #[arg(long, value_name = "N")]
+    pub runs: Option<usize>,
+
+    /// Load-test the configured model with the prompt and report throughput, latency and errors
+    #[arg(long)]
+    pub stress: bool,
+
+    /// Target requests per second for --stress (open loop); without it, --concurrency workers send back to back
+    #[arg(long, value_name = "RATE", requires = "stress")]
+    pub rps: Option<f64>,
+
+    /// Most requests in flight at once during --stress
+    #[arg(long, value_name = "N", default_value_t = 10, requires = "stress")]
+    pub concurrency: usize,
+
+    /// Seconds of --stress traffic sent before measuring starts
+    #[arg(long, value_name = "SECS", default_value_t = 5, requires = "stress")]
+    pub warmup: u64,
+
+    /// Seconds of measured --stress traffic
+    #[arg(long, value_name = "SECS", default_value_t = 30, requires = "stress")]
+    pub duration: u64,
+
     // Developer tools
     /// Enable debug mode
     #[arg(long)]
@@ -243,12 +341,12 @@ pub struct Args {
     pub test_mode: bool,
 
     // Content management
-    /// Use template
-    #[arg(long)]
+    /// Render a saved template (fill variables with --var) and send it, followed by any piped input
+    #[arg(long, value_name = "NAME")]
     pub template: Option<String>,
 
-    /// Use snippet
-    #[arg(long)]
+    /// Prepend a saved snippet to the input
+    #[arg(long, value_name = "NAME")]
     pub snippet: Option<String>,
 
     /// List available snippets
@@ -256,23 +354,75 @@ pub struct Args {
     pub list_snippets: bool,
 
     // Data processing
-    /// Process CSV file
-    #[arg(long)]
+    /// Answer the PROMPT question about a CSV file (describes the file without one); CSV and JSON may be gzipped
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["json_file", "excel"])]
     pub csv: Option<String>,
 
-    /// Process JSON file
-    #[arg(long)]
+    /// Answer the PROMPT question about a JSON file (an array of objects, or JSON Lines)
+    #[arg(long, value_name = "FILE", conflicts_with = "excel")]
     pub json_file: Option<String>,
 
-    /// Process Excel file
-    #[arg(long)]
+    /// Answer the PROMPT question about an Excel (xlsx, xls, xlsb) or ODS spreadsheet; describes every sheet without one
+    #[arg(long, value_name = "FILE")]
     pub excel: Option<String>,
 
-    // Scheduling
-    /// Schedule task for later execution
+    /// Read this sheet of the --excel file, by name or number (from 1); defaults to the first sheet with data
+    #[arg(long, value_name = "SHEET", requires = "excel")]
+    pub sheet: Option<String>,
+
+    /// Run a SQL-like query over the data file locally (SELECT/WHERE/GROUP BY/ORDER BY/LIMIT)
+    #[arg(long, value_name = "QUERY", conflicts_with = "to_sql")]
+    pub sql: Option<String>,
+
+    /// Have the model translate the PROMPT question into a query, then run it locally
     #[arg(long)]
+    pub to_sql: bool,
+
+    /// Have the model turn the PROMPT instruction into a transform (clean, normalize dates, dedupe, derive columns) and run it over the whole file; writes to --output (format from --format or the file extension) or prints CSV
+    #[arg(long, conflicts_with_all = ["to_sql", "sql", "chart"])]
+    pub transform: bool,
+
+    /// Run a transform spec saved as JSON over the data file, without the model
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["transform", "sql", "chart"])]
+    pub transform_spec: Option<String>,
+
+    /// Changed rows to show before a transform's result is written
+    #[arg(long, value_name = "N", default_value_t = 5)]
+    pub preview: usize,
+
+    /// Chart the data file (or the --sql result): printed to the terminal, shown in the TUI with --tui, or saved as SVG with --output FILE.svg
+    #[arg(long, value_name = "TYPE", value_parser = ["bar", "line", "scatter", "pie", "histogram", "heatmap"], requires = "x")]
+    pub chart: Option<String>,
+
+    /// Column for the chart's x axis, categories or bins
+    #[arg(long, value_name = "COLUMN", requires = "chart")]
+    pub x: Option<String>,
+
+    /// Column for the chart's y axis (summed per category), or the heatmap rows
+    #[arg(long, value_name = "COLUMN", requires = "chart")]
+    pub y: Option<String>,
+
+    /// Chart title
+    #[arg(long, value_name = "TITLE", requires = "chart")]
+    pub chart_title: Option<String>,
+
+    /// Chart size in SVG pixels, e.g. 800x500 (terminal charts use one cell per 10x20 pixels)
+    #[arg(long, value_name = "WxH", requires = "chart")]
+    pub chart_size: Option<String>,
+
+    /// Chart colours: default, blues, greens, reds, viridis, mono or comma-separated hex colours
+    #[arg(long, value_name = "SCHEME", requires = "chart")]
+    pub colors: Option<String>,
+
+    // Scheduling
+    /// Schedule the prompt, preset or workflow with a cron expression (run by `echomind daemon`)
+    #[arg(long, value_name = "CRON")]
     pub schedule: Option<String>,
 
+    /// POST scheduled job results to this URL
+    #[arg(long, requires = "schedule")]
+    pub webhook: Option<String>,
+
     // Quality assurance
     /// Enable response quality scoring
     #[arg(long)]
@@ -287,6 +437,249 @@ pub struct Args {
     pub bias_detect: bool,
 }
 
+#[derive(Subcommand, Debug, Clone)]
+pub enum Command {
+    /// Manage and inspect workflows
+    Workflow {
+        #[command(subcommand)]
+        action: WorkflowCommand,
+    },
+    /// Check on and fetch jobs submitted with --batch-api
+    Batch {
+        #[command(subcommand)]
+        action: BatchCommand,
+    },
+    /// Manage scheduled jobs
+    Schedule {
+        #[command(subcommand)]
+        action: ScheduleCommand,
+    },
+    /// Manage the prompt template library
+    Template {
+        #[command(subcommand)]
+        action: TemplateCommand,
+    },
+    /// Manage saved snippets
+    Snippet {
+        #[command(subcommand)]
+        action: SnippetCommand,
+    },
+    /// Install and manage prompt packs (presets, templates, snippets, workflows)
+    Pack {
+        #[command(subcommand)]
+        action: PackCommand,
+    },
+    /// Run prompt regression tests and compare them with a saved baseline
+    Eval {
+        /// Eval suite (YAML, JSON or TOML) of cases with assertions
+        file: String,
+        /// Models to run (comma-separated provider:model; default: the suite's, else the configured model)
+        #[arg(long, value_name = "MODELS", value_delimiter = ',')]
+        models: Vec<String>,
+        /// Model that scores judge assertions (default: the suite's, else the configured model)
+        #[arg(long, value_name = "MODEL")]
+        judge: Option<String>,
+        /// Report of an earlier run to diff against; cases that passed there and fail now are regressions
+        #[arg(long, value_name = "FILE")]
+        baseline: Option<String>,
+        /// Save this run as the new baseline
+        #[arg(long, requires = "baseline")]
+        update_baseline: bool,
+    },
+    /// Report requests, tokens and estimated cost from the usage ledger
+    Usage {
+        /// Period to total by
+        #[arg(long, default_value = "daily", value_parser = ["daily", "weekly", "monthly"])]
+        period: String,
+        /// Break each period down by model, provider, preset or command
+        #[arg(long, default_value = "model", value_parser = ["model", "provider", "preset", "command"])]
+        by: String,
+        /// Only include requests on or after this date (YYYY-MM-DD)
+        #[arg(long, value_name = "DATE")]
+        since: Option<String>,
+    },
+    /// Run scheduled jobs as they come due
+    Daemon {
+        /// Seconds between checks for due jobs
+        #[arg(long, default_value_t = 30)]
+        interval: u64,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum WorkflowCommand {
+    /// Check a workflow for dangling references, unreachable steps and cycles
+    Validate {
+        /// Workflow file (JSON, YAML or TOML) or name of a saved workflow
+        file: String,
+    },
+    /// List persisted workflow runs, most recent first
+    Runs,
+    /// Continue a failed or interrupted run from the step it stopped at
+    Resume {
+        /// Run id (a unique prefix is enough)
+        run_id: String,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum BatchCommand {
+    /// Show submitted batch jobs, refreshing their status from the API
+    Status {
+        /// Job id (a unique prefix is enough); all unfinished jobs if omitted
+        job_id: Option<String>,
+    },
+    /// Download a finished job's results as JSONL joined to the inputs (to --output, else stdout)
+    Fetch {
+        /// Job id (a unique prefix is enough)
+        job_id: String,
+        /// Poll until the job finishes instead of failing while it runs
+        #[arg(long)]
+        wait: bool,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum ScheduleCommand {
+    /// List scheduled jobs with their next run and last result
+    List,
+    /// Remove a scheduled job
+    Cancel {
+        /// Job id (a unique prefix is enough)
+        job_id: String,
+    },
+    /// Run a scheduled job immediately
+    RunNow {
+        /// Job id (a unique prefix is enough)
+        job_id: String,
+    },
+    /// Show recorded runs, optionally for one job
+    Runs {
+        /// Job id (a unique prefix is enough)
+        job_id: Option<String>,
+    },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum TemplateCommand {
+    /// Save a new template (content from --file, stdin or $EDITOR)
+    Add {
+        name: String,
+        /// Read the template content from this file
+        #[arg(long)]
+        file: Option<String>,
+        #[arg(long)]
+        description: Option<String>,
+        #[arg(long)]
+        category: Option<String>,
+        /// Tag the template (repeatable)
+        #[arg(long = "tag")]
+        tags: Vec<String>,
+    },
+    /// Change a template; without --file the content opens in $EDITOR
+    Edit {
+        name: String,
+        /// Replace the content with this file
+        #[arg(long)]
+        file: Option<String>,
+        /// Rename the template
+        #[arg(long)]
+        rename: Option<String>,
+        #[arg(long)]
+        description: Option<String>,
+        #[arg(long)]
+        category: Option<String>,
+        /// Replace the tags (repeatable)
+        #[arg(long = "tag")]
+        tags: Vec<String>,
+    },
+    /// List templates
+    List {
+        /// Only show templates in this category
+        #[arg(long)]
+        category: Option<String>,
+    },
+    /// Print a template with its variables
+    Show { name: String },
+    /// Declare a variable's type, description, default or whether it is required
+    Var {
+        /// Template name
+        name: String,
+        /// Variable name
+        variable: String,
+        /// string, number, boolean, date, file, list or select
+        #[arg(long = "type")]
+        var_type: Option<String>,
+        /// Allowed values for a select variable (comma-separated)
+        #[arg(long, value_delimiter = ',')]
+        choices: Vec<String>,
+        #[arg(long)]
+        description: Option<String>,
+        #[arg(long)]
+        default: Option<String>,
+        /// Mark the variable optional (true) or required (false)
+        #[arg(long)]
+        optional: Option<bool>,
+    },
+    /// Delete a template
+    Delete { name: String },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum SnippetCommand {
+    /// Save a new snippet (content from --file, stdin or $EDITOR)
+    Add {
+        name: String,
+        /// Read the snippet content from this file
+        #[arg(long)]
+        file: Option<String>,
+        #[arg(long)]
+        description: Option<String>,
+        #[arg(long)]
+        language: Option<String>,
+    },
+    /// Delete a snippet
+    Delete { name: String },
+}
+
+#[derive(Subcommand, Debug, Clone)]
+pub enum PackCommand {
+    /// List installed packs
+    List,
+    /// Install a pack from a directory, .zip archive or git URL
+    Install {
+        source: String,
+        /// Install under this namespace instead of the pack's own
+        #[arg(long)]
+        namespace: Option<String>,
+        /// Overwrite conflicting items that the pack does not own
+        #[arg(long)]
+        force: bool,
+    },
+    /// Re-fetch a pack from its source and install the new version
+    Update {
+        namespace: String,
+        #[arg(long)]
+        force: bool,
+    },
+    /// Uninstall a pack and every item it installed
+    Remove { namespace: String },
+    /// Write presets, templates, snippets and workflows to a pack directory
+    Export {
+        /// Directory to create
+        dir: String,
+        #[arg(long)]
+        name: String,
+        #[arg(long, default_value = "0.1.0")]
+        version: String,
+        #[arg(long)]
+        description: Option<String>,
+        /// Export the items under this namespace (default: locally created items)
+        #[arg(long)]
+        namespace: Option<String>,
+    },
+}
+
 impl Args {
     pub fn resolve_coder_and_output(&self) -> (bool, Option<String>) {
         if let Some(co_file) = &self.co {
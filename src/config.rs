@@ -14,6 +14,55 @@ pub struct Config {
 
     #[serde(default)]
     pub presets: std::collections::HashMap<String, Preset>,
+
+    #[serde(default)]
+    pub workflow: WorkflowConfig,
+
+    #[serde(default)]
+    pub budget: BudgetConfig,
+}
+
+/// Spending caps checked against the usage ledger before each request.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct BudgetConfig {
+    /// Dollars per calendar day (local time)
+    #[serde(default)]
+    pub daily: Option<f64>,
+
+    /// Dollars per calendar month (local time)
+    #[serde(default)]
+    pub monthly: Option<f64>,
+
+    /// What to do when a request would exceed a cap
+    #[serde(default)]
+    pub action: BudgetAction,
+
+    /// Per-model prices overriding the built-in estimates
+    #[serde(default)]
+    pub prices: std::collections::HashMap<String, ModelPrice>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum BudgetAction {
+    #[default]
+    Warn,
+    Block,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModelPrice {
+    /// Dollars per 1,000 input tokens
+    pub input_per_1k: f64,
+    /// Dollars per 1,000 output tokens
+    pub output_per_1k: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct WorkflowConfig {
+    /// Programs that workflow Shell steps are allowed to run (matched exactly)
+    #[serde(default)]
+    pub shell_allowlist: Vec<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -157,6 +206,43 @@ impl Config {
         Ok(config_dir.join("echomind").join("config.toml"))
     }
 
+    /// Directory for echomind's persistent data (workflows, run state, ledgers).
+    pub fn data_dir() -> Result<PathBuf> {
+        let data_dir = dirs::data_dir().ok_or_else(|| {
+            EchomindError::ConfigError("Could not determine data directory".to_string())
+        })?;
+
+        Ok(data_dir.join("echomind"))
+    }
+
+    pub fn workflows_dir() -> Result<PathBuf> {
+        Ok(Self::data_dir()?.join("workflows"))
+    }
+
+    pub fn workflow_runs_dir() -> Result<PathBuf> {
+        Ok(Self::data_dir()?.join("workflow_runs"))
+    }
+
+    pub fn schedule_dir() -> Result<PathBuf> {
+        Ok(Self::data_dir()?.join("schedule"))
+    }
+
+    pub fn content_library_path() -> Result<PathBuf> {
+        Ok(Self::data_dir()?.join("content_library.json"))
+    }
+
+    pub fn packs_dir() -> Result<PathBuf> {
+        Ok(Self::data_dir()?.join("packs"))
+    }
+
+    pub fn batch_jobs_dir() -> Result<PathBuf> {
+        Ok(Self::data_dir()?.join("batches"))
+    }
+
+    pub fn usage_ledger_path() -> Result<PathBuf> {
+        Ok(Self::data_dir()?.join("usage.jsonl"))
+    }
+
     pub fn init_default_config() -> Result<()> {
         let config = Config::default();
         config.save()?;
@@ -36,6 +36,25 @@ pub enum EchomindError {
     Other(String),
 }
 
+impl EchomindError {
+    /// The variant name, for grouping errors in reports.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            EchomindError::InputError(_) => "InputError",
+            EchomindError::NetworkError(_) => "NetworkError",
+            EchomindError::ApiError { .. } => "ApiError",
+            EchomindError::TimeoutError(_) => "TimeoutError",
+            EchomindError::ParseError(_) => "ParseError",
+            EchomindError::ConfigError(_) => "ConfigError",
+            EchomindError::InvalidProvider(_) => "InvalidProvider",
+            EchomindError::MissingApiKey(_) => "MissingApiKey",
+            EchomindError::EmptyResponse => "EmptyResponse",
+            EchomindError::FileError(_) => "FileError",
+            EchomindError::Other(_) => "Other",
+        }
+    }
+}
+
 impl From<reqwest::Error> for EchomindError {
     fn from(err: reqwest::Error) -> Self {
         if err.is_timeout() {
@@ -0,0 +1,352 @@
+//! Batch runs over a file of prompts. Each line is either a plain query or a
+//! JSON record with an id, input, template and variables. Records run
+//! concurrently with per-record retries, and results are written as JSON
+//! Lines to a file that doubles as the checkpoint: rerunning the same batch
+//! into the same file skips records that already succeeded.
+
+use crate::api::{ApiClient, ChatRequest, Message};
+use crate::error::{EchomindError, Result};
+use crate::features::content::ContentManager;
+use crate::features::performance::{estimate_cost, estimate_tokens};
+use crate::features::templating;
+use crate::features::usage::UsageMeter;
+use futures::stream::{self, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use tokio::time::sleep;
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BatchRecord {
+    /// Defaults to the record's line number
+    #[serde(default)]
+    pub id: String,
+    /// The prompt; `{{ name }}` placeholders are filled from `vars`
+    #[serde(default)]
+    pub input: String,
+    /// Content library template rendered with `vars` ahead of the input
+    #[serde(default)]
+    pub template: Option<String>,
+    #[serde(default)]
+    pub vars: HashMap<String, Value>,
+    /// Replaces the batch's system prompt for this record
+    #[serde(default)]
+    pub system: Option<String>,
+    /// Overrides the batch's model for this record
+    #[serde(default)]
+    pub model: Option<String>,
+}
+
+impl BatchRecord {
+    /// The conversation for this record: `base` (preset messages and system
+    /// prompt) followed by the rendered user message.
+    pub fn messages(&self, base: &[Message], library: Option<&ContentManager>) -> Result<Vec<Message>> {
+        let mut messages: Vec<Message> = match &self.system {
+            Some(system) => std::iter::once(Message::text("system".to_string(), system.clone()))
+                .chain(base.iter().filter(|m| m.role != "system").cloned())
+                .collect(),
+            None => base.to_vec(),
+        };
+        messages.push(Message::text("user".to_string(), self.prompt(library)?));
+        Ok(messages)
+    }
+
+    /// The rendered user prompt.
+    pub fn prompt(&self, library: Option<&ContentManager>) -> Result<String> {
+        let mut parts = Vec::new();
+        if let Some(name) = &self.template {
+            let template = library
+                .and_then(|library| library.find_template(name))
+                .ok_or_else(|| EchomindError::Other(format!("Template '{}' not found", name)))?;
+            let vars: HashMap<String, String> = self
+                .vars
+                .iter()
+                .map(|(name, value)| {
+                    let text = match value {
+                        Value::String(s) => s.clone(),
+                        other => other.to_string(),
+                    };
+                    (name.clone(), text)
+                })
+                .collect();
+            parts.push(template.render(&vars)?);
+        }
+        if !self.input.trim().is_empty() {
+            // Plain inputs are sent untouched so literal braces survive
+            let input = if self.vars.is_empty() {
+                self.input.clone()
+            } else {
+                templating::render(&self.input, &self.vars)?
+            };
+            parts.push(input);
+        }
+        if parts.is_empty() {
+            return Err(EchomindError::Other("Record has neither an input nor a template".to_string()));
+        }
+        Ok(parts.join("\n\n"))
+    }
+}
+
+/// Reads batch records. Blank lines and `#` comments are skipped, lines
+/// starting with `{` are JSON records and anything else is a plain query.
+pub fn load_records(path: &str) -> Result<Vec<BatchRecord>> {
+    let contents = fs::read_to_string(path)
+        .map_err(|e| EchomindError::FileError(format!("Failed to read batch file: {}", e)))?;
+
+    let mut records = Vec::new();
+    let mut ids = HashSet::new();
+    for (i, line) in contents.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let mut record = if line.starts_with('{') {
+            serde_json::from_str(line)
+                .map_err(|e| EchomindError::ParseError(format!("Batch line {}: {}", i + 1, e)))?
+        } else {
+            BatchRecord {
+                input: line.to_string(),
+                ..Default::default()
+            }
+        };
+        if record.id.is_empty() {
+            record.id = (i + 1).to_string();
+        }
+        if !ids.insert(record.id.clone()) {
+            return Err(EchomindError::ParseError(format!(
+                "Batch line {}: id '{}' is used more than once",
+                i + 1,
+                record.id
+            )));
+        }
+        records.push(record);
+    }
+    Ok(records)
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BatchUsage {
+    pub input_tokens: u32,
+    pub output_tokens: u32,
+    /// Estimated dollars
+    pub cost: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchResult {
+    pub id: String,
+    /// The rendered prompt that was sent
+    pub input: String,
+    #[serde(default)]
+    pub output: Option<String>,
+    #[serde(default)]
+    pub usage: Option<BatchUsage>,
+    #[serde(default)]
+    pub error: Option<String>,
+    /// Wall time for the record, retries included
+    pub latency_ms: u64,
+    pub attempts: u32,
+    /// Set when the record went out over a budget whose action is warn
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub budget_warning: Option<String>,
+}
+
+impl BatchResult {
+    pub fn succeeded(&self) -> bool {
+        self.error.is_none()
+    }
+}
+
+/// The results file of a batch run. Opening it keeps the successful results
+/// of an earlier run and drops the failed ones so they are retried.
+pub struct BatchCheckpoint {
+    file: fs::File,
+    completed: Vec<BatchResult>,
+}
+
+impl BatchCheckpoint {
+    pub fn open(path: &Path) -> Result<Self> {
+        let completed: Vec<BatchResult> = if path.exists() {
+            fs::read_to_string(path)
+                .map_err(|e| EchomindError::FileError(format!("Failed to read {}: {}", path.display(), e)))?
+                .lines()
+                .filter_map(|line| serde_json::from_str::<BatchResult>(line).ok())
+                .filter(BatchResult::succeeded)
+                .collect()
+        } else {
+            Vec::new()
+        };
+
+        // Failed results are dropped so they run again. The kept ones are
+        // written to a temporary file first, so a crash while rewriting
+        // can't lose them.
+        let mut kept = String::new();
+        for result in &completed {
+            let line = serde_json::to_string(result)
+                .map_err(|e| EchomindError::ParseError(format!("Failed to serialize batch result: {}", e)))?;
+            kept.push_str(&line);
+            kept.push('\n');
+        }
+        let mut tmp = path.as_os_str().to_owned();
+        tmp.push(".tmp");
+        fs::write(&tmp, kept)
+            .and_then(|_| fs::rename(&tmp, path))
+            .map_err(|e| EchomindError::FileError(format!("Failed to write {}: {}", path.display(), e)))?;
+
+        let file = fs::OpenOptions::new()
+            .append(true)
+            .open(path)
+            .map_err(|e| EchomindError::FileError(format!("Failed to open {}: {}", path.display(), e)))?;
+        Ok(Self { file, completed })
+    }
+
+    pub fn completed(&self) -> &[BatchResult] {
+        &self.completed
+    }
+
+    pub fn is_completed(&self, id: &str) -> bool {
+        self.completed.iter().any(|r| r.id == id)
+    }
+
+    pub fn write(&mut self, result: &BatchResult) -> Result<()> {
+        let line = serde_json::to_string(result)
+            .map_err(|e| EchomindError::ParseError(format!("Failed to serialize batch result: {}", e)))?;
+        writeln!(self.file, "{}", line).map_err(|e| EchomindError::FileError(format!("Failed to write batch result: {}", e)))
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct BatchOptions {
+    /// Records in flight at once
+    pub concurrency: usize,
+    /// Extra attempts after a failed request
+    pub retries: u32,
+    /// Base delay between attempts, multiplied by the attempt number
+    pub backoff: Duration,
+}
+
+impl Default for BatchOptions {
+    fn default() -> Self {
+        Self {
+            concurrency: 4,
+            retries: 2,
+            backoff: Duration::from_millis(500),
+        }
+    }
+}
+
+/// Runs `records` against `client`. `template` supplies the model, the
+/// sampling settings and the messages that go before each record's prompt.
+/// A record that can't be rendered, is over budget or fails every attempt
+/// gets a result with an error; the rest of the batch carries on.
+/// `on_result` sees results as they finish, and the returned list is in
+/// record order.
+pub async fn run_batch<F>(
+    client: &ApiClient,
+    records: &[BatchRecord],
+    library: Option<&ContentManager>,
+    template: &ChatRequest,
+    options: BatchOptions,
+    usage: Option<&UsageMeter>,
+    mut on_result: F,
+) -> Vec<BatchResult>
+where
+    F: FnMut(&BatchResult),
+{
+    let mut results: Vec<(usize, BatchResult)> = stream::iter(records.iter().enumerate())
+        .map(|(index, record)| async move {
+            (index, run_record(client, record, library, template, options, usage).await)
+        })
+        .buffer_unordered(options.concurrency.max(1))
+        .inspect(|(_, result)| on_result(result))
+        .collect()
+        .await;
+    results.sort_by_key(|(index, _)| *index);
+    results.into_iter().map(|(_, result)| result).collect()
+}
+
+async fn run_record(
+    client: &ApiClient,
+    record: &BatchRecord,
+    library: Option<&ContentManager>,
+    template: &ChatRequest,
+    options: BatchOptions,
+    usage: Option<&UsageMeter>,
+) -> BatchResult {
+    let start = Instant::now();
+    let failed = |input: String, error: String, attempts: u32| BatchResult {
+        id: record.id.clone(),
+        input,
+        output: None,
+        usage: None,
+        error: Some(error),
+        latency_ms: start.elapsed().as_millis() as u64,
+        attempts,
+        budget_warning: None,
+    };
+
+    let messages = match record.messages(&template.messages, library) {
+        Ok(messages) => messages,
+        Err(e) => return failed(record.input.clone(), e.to_string(), 0),
+    };
+    let input = messages.last().and_then(|m| m.get_text()).unwrap_or_default().to_string();
+    let request = ChatRequest {
+        messages,
+        model: record.model.clone().or_else(|| template.model.clone()),
+        ..template.clone()
+    };
+
+    let provider = client.provider().name();
+    let mut budget_warning = None;
+    let mut attempts = 0;
+    loop {
+        // Retries are checked too; the first attempt's cost is recorded by then
+        let mut sent = false;
+        let attempt = UsageMeter::send(usage, provider, &request, |warning| {
+            budget_warning = budget_warning.take().or(warning);
+            sent = true;
+            attempts += 1;
+            client.send_message(request.clone())
+        })
+        .await;
+
+        match attempt {
+            Ok(output) => {
+                let input_tokens: u32 = request.messages.iter().filter_map(|m| m.get_text()).map(estimate_tokens).sum();
+                let output_tokens = estimate_tokens(&output);
+                let model = request.model.as_deref().unwrap_or_default();
+                let cost = match usage {
+                    Some(usage) => usage.price(provider, model, input_tokens, output_tokens),
+                    None => estimate_cost(provider, model, input_tokens, output_tokens),
+                };
+                return BatchResult {
+                    id: record.id.clone(),
+                    input,
+                    output: Some(output),
+                    usage: Some(BatchUsage {
+                        input_tokens,
+                        output_tokens,
+                        cost,
+                    }),
+                    error: None,
+                    latency_ms: start.elapsed().as_millis() as u64,
+                    attempts,
+                    budget_warning,
+                };
+            }
+            // Over a blocking budget nothing was sent, and a retry won't be
+            Err(e) if !sent || attempts > options.retries => {
+                return BatchResult {
+                    budget_warning,
+                    ..failed(input, e.to_string(), attempts)
+                };
+            }
+            // Linear backoff between attempts, as for workflow steps
+            Err(_) => sleep(options.backoff * attempts).await,
+        }
+    }
+}
@@ -0,0 +1,440 @@
+//! Offline jobs through the OpenAI Batch API. A `--batch` file becomes a
+//! JSONL upload of chat completion requests, a batch is created for it, and
+//! the job is tracked under the data directory so `echomind batch status`
+//! and `echomind batch fetch` can pick it up later. Fetched results are
+//! joined back to the inputs by record id.
+
+use crate::api::{ChatRequest, Provider};
+use crate::error::{EchomindError, Result};
+use crate::features::batch::{BatchRecord, BatchResult, BatchUsage};
+use crate::features::content::ContentManager;
+use crate::features::performance::estimate_cost;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Batch requests are billed at half the synchronous price.
+pub const BATCH_PRICE_FACTOR: f64 = 0.5;
+
+const CHAT_COMPLETIONS: &str = "/v1/chat/completions";
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RequestCounts {
+    #[serde(default)]
+    pub total: u64,
+    #[serde(default)]
+    pub completed: u64,
+    #[serde(default)]
+    pub failed: u64,
+}
+
+/// A batch as the API reports it.
+#[derive(Debug, Clone, Deserialize)]
+pub struct RemoteBatch {
+    pub id: String,
+    pub status: String,
+    #[serde(default)]
+    pub output_file_id: Option<String>,
+    #[serde(default)]
+    pub error_file_id: Option<String>,
+    #[serde(default)]
+    pub request_counts: Option<RequestCounts>,
+}
+
+/// The files and batches endpoints of an OpenAI-compatible API.
+pub struct BatchApiClient {
+    http: reqwest::Client,
+    base_url: String,
+    api_key: Option<String>,
+}
+
+impl BatchApiClient {
+    pub fn new(base_url: &str, api_key: Option<String>) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            base_url: base_url.trim_end_matches('/').to_string(),
+            api_key,
+        }
+    }
+
+    /// The API root for a provider that speaks the Batch API: OpenAI itself,
+    /// or a custom endpoint with its `/chat/completions` suffix removed.
+    pub fn base_url_for(provider: &Provider) -> Result<String> {
+        match provider {
+            Provider::OpenAI | Provider::Custom(_) => {
+                let endpoint = provider.endpoint().trim_end_matches('/');
+                Ok(endpoint.strip_suffix("/chat/completions").unwrap_or(endpoint).to_string())
+            }
+            other => Err(EchomindError::Other(format!(
+                "The Batch API needs an OpenAI-compatible provider (openai or a custom URL), not '{}'",
+                other.name()
+            ))),
+        }
+    }
+
+    pub fn base_url(&self) -> &str {
+        &self.base_url
+    }
+
+    /// Uploads a JSONL file for batch use and returns its file id.
+    pub async fn upload(&self, filename: &str, contents: &[u8]) -> Result<String> {
+        // reqwest's multipart support isn't enabled, and the form is small
+        // enough to write out by hand
+        let boundary = format!("echomind-{}", uuid::Uuid::new_v4().simple());
+        let mut body = format!(
+            "--{b}\r\nContent-Disposition: form-data; name=\"purpose\"\r\n\r\nbatch\r\n\
+             --{b}\r\nContent-Disposition: form-data; name=\"file\"; filename=\"{f}\"\r\n\
+             Content-Type: application/jsonl\r\n\r\n",
+            b = boundary,
+            f = filename
+        )
+        .into_bytes();
+        body.extend_from_slice(contents);
+        body.extend_from_slice(format!("\r\n--{}--\r\n", boundary).as_bytes());
+
+        let request = self
+            .http
+            .post(format!("{}/files", self.base_url))
+            .header("Content-Type", format!("multipart/form-data; boundary={}", boundary))
+            .body(body);
+        let file: Value = self.send(request).await?.json().await?;
+        file["id"]
+            .as_str()
+            .map(str::to_string)
+            .ok_or_else(|| EchomindError::ParseError("File upload response has no id".to_string()))
+    }
+
+    pub async fn create(&self, input_file_id: &str, source: &str) -> Result<RemoteBatch> {
+        let request = self.http.post(format!("{}/batches", self.base_url)).json(&json!({
+            "input_file_id": input_file_id,
+            "endpoint": CHAT_COMPLETIONS,
+            "completion_window": "24h",
+            "metadata": {"source": source, "client": "echomind"},
+        }));
+        Ok(self.send(request).await?.json().await?)
+    }
+
+    pub async fn retrieve(&self, batch_id: &str) -> Result<RemoteBatch> {
+        let request = self.http.get(format!("{}/batches/{}", self.base_url, batch_id));
+        Ok(self.send(request).await?.json().await?)
+    }
+
+    pub async fn download(&self, file_id: &str) -> Result<String> {
+        let request = self.http.get(format!("{}/files/{}/content", self.base_url, file_id));
+        Ok(self.send(request).await?.text().await?)
+    }
+
+    async fn send(&self, request: reqwest::RequestBuilder) -> Result<reqwest::Response> {
+        let request = match &self.api_key {
+            Some(key) => request.header("Authorization", format!("Bearer {}", key)),
+            None => request,
+        };
+        let response = request.send().await?;
+        if !response.status().is_success() {
+            let status = response.status().as_u16();
+            let message = response.text().await.unwrap_or_else(|_| "Unknown error".to_string());
+            let suggestion = match status {
+                401 => "Check your API key is correct and has the right permissions.",
+                404 => "Check the batch or file id, and that the provider supports the Batch API.",
+                429 => "Rate limit or batch queue limit reached. Try again later.",
+                500..=599 => "Server error. The API service may be down, try again later.",
+                _ => "Check the API documentation for this status code.",
+            };
+            return Err(EchomindError::ApiError {
+                status,
+                message,
+                suggestion: suggestion.to_string(),
+            });
+        }
+        Ok(response)
+    }
+}
+
+/// One submitted record, kept so results can be joined back to it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobInput {
+    pub id: String,
+    pub input: String,
+    pub model: String,
+}
+
+/// A submitted batch as tracked locally.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BatchJob {
+    /// The provider's batch id
+    pub id: String,
+    /// Provider name, used for cost estimates
+    pub provider: String,
+    pub base_url: String,
+    /// The `--batch` file the job was built from
+    pub source: String,
+    pub input_file_id: String,
+    pub status: String,
+    #[serde(default)]
+    pub output_file_id: Option<String>,
+    #[serde(default)]
+    pub error_file_id: Option<String>,
+    #[serde(default)]
+    pub request_counts: RequestCounts,
+    pub inputs: Vec<JobInput>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    /// Set once the results have been fetched and their usage recorded
+    #[serde(default)]
+    pub fetched_at: Option<DateTime<Utc>>,
+}
+
+impl BatchJob {
+    /// Whether the batch has stopped running, successfully or not.
+    pub fn is_finished(&self) -> bool {
+        matches!(self.status.as_str(), "completed" | "failed" | "expired" | "cancelled")
+    }
+
+    pub fn update(&mut self, remote: RemoteBatch) {
+        self.status = remote.status;
+        self.output_file_id = remote.output_file_id.or(self.output_file_id.take());
+        self.error_file_id = remote.error_file_id.or(self.error_file_id.take());
+        if let Some(counts) = remote.request_counts {
+            self.request_counts = counts;
+        }
+        self.updated_at = Utc::now();
+    }
+
+    /// Matches downloaded output and error lines to the submitted records,
+    /// in submission order. Records the API returned nothing for get an
+    /// error naming the batch status.
+    pub fn join_results(&self, output: &str, errors: &str) -> Vec<BatchResult> {
+        let lines: HashMap<String, Value> = output
+            .lines()
+            .chain(errors.lines())
+            .filter_map(|line| serde_json::from_str::<Value>(line).ok())
+            .filter_map(|line| Some((line["custom_id"].as_str()?.to_string(), line)))
+            .collect();
+
+        self.inputs
+            .iter()
+            .map(|input| {
+                let mut result = BatchResult {
+                    id: input.id.clone(),
+                    input: input.input.clone(),
+                    output: None,
+                    usage: None,
+                    error: None,
+                    latency_ms: 0,
+                    attempts: 1,
+                    budget_warning: None,
+                };
+                let Some(line) = lines.get(&input.id) else {
+                    result.error = Some(format!("No result returned (batch {})", self.status));
+                    return result;
+                };
+
+                let response = &line["response"];
+                let status = response["status_code"].as_u64().unwrap_or(0);
+                let body = &response["body"];
+                match body["choices"][0]["message"]["content"].as_str() {
+                    Some(content) if status == 200 => {
+                        let input_tokens = body["usage"]["prompt_tokens"].as_u64().unwrap_or(0) as u32;
+                        let output_tokens = body["usage"]["completion_tokens"].as_u64().unwrap_or(0) as u32;
+                        result.output = Some(content.to_string());
+                        result.usage = Some(BatchUsage {
+                            input_tokens,
+                            output_tokens,
+                            cost: estimate_cost(&self.provider, &input.model, input_tokens, output_tokens)
+                                * BATCH_PRICE_FACTOR,
+                        });
+                    }
+                    _ => {
+                        let message = line["error"]["message"]
+                            .as_str()
+                            .or_else(|| body["error"]["message"].as_str())
+                            .unwrap_or("no completion in the response");
+                        result.error = Some(if status > 0 {
+                            format!("HTTP {}: {}", status, message)
+                        } else {
+                            message.to_string()
+                        });
+                    }
+                }
+                result
+            })
+            .collect()
+    }
+}
+
+/// Builds the JSONL upload for `records`, one chat completion request per
+/// record with the record id as `custom_id`. Records that can't be rendered
+/// stop the submission rather than being paid for half-built.
+pub fn build_requests(
+    records: &[BatchRecord],
+    library: Option<&ContentManager>,
+    template: &ChatRequest,
+) -> Result<(String, Vec<JobInput>)> {
+    let mut lines = Vec::with_capacity(records.len());
+    let mut inputs = Vec::with_capacity(records.len());
+    for record in records {
+        let messages = record
+            .messages(&template.messages, library)
+            .map_err(|e| EchomindError::Other(format!("Record '{}': {}", record.id, e)))?;
+        let model = record
+            .model
+            .clone()
+            .or_else(|| template.model.clone())
+            .ok_or_else(|| EchomindError::Other("The Batch API needs a model".to_string()))?;
+
+        let mut body = json!({"model": model, "messages": messages});
+        if let Some(temperature) = template.temperature {
+            body["temperature"] = json!(temperature);
+        }
+        if let Some(max_tokens) = template.max_tokens {
+            body["max_tokens"] = json!(max_tokens);
+        }
+        if let Some(top_p) = template.top_p {
+            body["top_p"] = json!(top_p);
+        }
+        lines.push(
+            json!({"custom_id": record.id, "method": "POST", "url": CHAT_COMPLETIONS, "body": body}).to_string(),
+        );
+        inputs.push(JobInput {
+            id: record.id.clone(),
+            input: messages.last().and_then(|m| m.get_text()).unwrap_or_default().to_string(),
+            model,
+        });
+    }
+    Ok((lines.join("\n") + "\n", inputs))
+}
+
+/// Uploads `records` and creates a batch for them.
+pub async fn submit(
+    api: &BatchApiClient,
+    provider: &str,
+    records: &[BatchRecord],
+    library: Option<&ContentManager>,
+    template: &ChatRequest,
+    source: &str,
+) -> Result<BatchJob> {
+    if records.is_empty() {
+        return Err(EchomindError::Other("The batch file has no records".to_string()));
+    }
+    let (requests, inputs) = build_requests(records, library, template)?;
+    let filename = Path::new(source)
+        .file_stem()
+        .map(|stem| format!("{}.jsonl", stem.to_string_lossy()))
+        .unwrap_or_else(|| "batch.jsonl".to_string());
+    let input_file_id = api.upload(&filename, requests.as_bytes()).await?;
+    let remote = api.create(&input_file_id, source).await?;
+
+    let now = Utc::now();
+    let mut job = BatchJob {
+        id: remote.id.clone(),
+        provider: provider.to_string(),
+        base_url: api.base_url().to_string(),
+        source: source.to_string(),
+        input_file_id,
+        status: String::new(),
+        output_file_id: None,
+        error_file_id: None,
+        request_counts: RequestCounts::default(),
+        inputs,
+        created_at: now,
+        updated_at: now,
+        fetched_at: None,
+    };
+    job.update(remote);
+    Ok(job)
+}
+
+/// Polls the batch every `interval` until it finishes, calling `on_update`
+/// after each poll.
+pub async fn wait<F>(api: &BatchApiClient, job: &mut BatchJob, interval: Duration, mut on_update: F) -> Result<()>
+where
+    F: FnMut(&BatchJob),
+{
+    loop {
+        job.update(api.retrieve(&job.id).await?);
+        on_update(job);
+        if job.is_finished() {
+            return Ok(());
+        }
+        tokio::time::sleep(interval).await;
+    }
+}
+
+/// Downloads a finished batch's output and error files and joins them to
+/// the inputs.
+pub async fn fetch_results(api: &BatchApiClient, job: &BatchJob) -> Result<Vec<BatchResult>> {
+    if !job.is_finished() {
+        return Err(EchomindError::Other(format!(
+            "Batch {} is still {} ({}/{} done); try again later or pass --wait",
+            job.id, job.status, job.request_counts.completed, job.request_counts.total
+        )));
+    }
+    let output = match &job.output_file_id {
+        Some(file_id) => api.download(file_id).await?,
+        None => String::new(),
+    };
+    let errors = match &job.error_file_id {
+        Some(file_id) => api.download(file_id).await?,
+        None => String::new(),
+    };
+    Ok(job.join_results(&output, &errors))
+}
+
+/// Submitted jobs, kept in `jobs.json` in the store directory.
+pub struct BatchJobStore {
+    dir: PathBuf,
+    jobs: Vec<BatchJob>,
+}
+
+impl BatchJobStore {
+    pub fn open(dir: &Path) -> Result<Self> {
+        let path = dir.join("jobs.json");
+        let jobs = if path.exists() {
+            let contents = fs::read_to_string(&path)
+                .map_err(|e| EchomindError::FileError(format!("Failed to read batch jobs: {}", e)))?;
+            serde_json::from_str(&contents)
+                .map_err(|e| EchomindError::ParseError(format!("Failed to parse batch jobs: {}", e)))?
+        } else {
+            Vec::new()
+        };
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            jobs,
+        })
+    }
+
+    pub fn jobs(&self) -> &[BatchJob] {
+        &self.jobs
+    }
+
+    /// Looks up a job by id or unique id prefix.
+    pub fn get(&self, job_id: &str) -> Result<&BatchJob> {
+        let matches: Vec<&BatchJob> = self.jobs.iter().filter(|j| j.id.starts_with(job_id)).collect();
+        match matches.len() {
+            0 => Err(EchomindError::Other(format!("Batch job {} not found", job_id))),
+            1 => Ok(matches[0]),
+            n => Err(EchomindError::Other(format!("Batch id prefix {} is ambiguous ({} jobs)", job_id, n))),
+        }
+    }
+
+    /// Adds or replaces a job and saves the store.
+    pub fn save_job(&mut self, job: &BatchJob) -> Result<()> {
+        match self.jobs.iter_mut().find(|j| j.id == job.id) {
+            Some(existing) => *existing = job.clone(),
+            None => self.jobs.push(job.clone()),
+        }
+        fs::create_dir_all(&self.dir)
+            .map_err(|e| EchomindError::FileError(format!("Failed to create batch directory: {}", e)))?;
+        let json = serde_json::to_string_pretty(&self.jobs)
+            .map_err(|e| EchomindError::ParseError(format!("Failed to serialize batch jobs: {}", e)))?;
+        let path = self.dir.join("jobs.json");
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, json)
+            .and_then(|_| fs::rename(&tmp, &path))
+            .map_err(|e| EchomindError::FileError(format!("Failed to write batch jobs: {}", e)))
+    }
+}
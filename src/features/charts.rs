@@ -0,0 +1,1033 @@
+//! Chart rendering for `DataProcessor::generate_visualization`: the same
+//! chart can be drawn as Unicode text for the terminal, as a standalone SVG
+//! document, or with ratatui's widgets inside the TUI.
+//!
+//! Sizes in `VisualizationConfig` are SVG pixels; terminal charts use one
+//! character cell per 10×20 pixels, so the default 600×400 chart is 60
+//! columns by 20 rows.
+
+use crate::error::{EchomindError, Result};
+use crate::features::data_processing::{ChartType, VisualizationConfig};
+use colored::Colorize;
+use ratatui::{
+    layout::{Direction, Rect},
+    style::{Color, Style},
+    symbols::Marker,
+    text::{Line, Span},
+    widgets::{Axis, Bar, BarChart, BarGroup, Block, Borders, Chart as ChartWidget, Dataset, GraphType, Paragraph},
+    Frame,
+};
+
+pub const DEFAULT_WIDTH: u32 = 600;
+pub const DEFAULT_HEIGHT: u32 = 400;
+
+const CELL_WIDTH: u32 = 10;
+const CELL_HEIGHT: u32 = 20;
+
+pub type Rgb = (u8, u8, u8);
+
+const DEFAULT_PALETTE: [Rgb; 10] = [
+    (0x4e, 0x79, 0xa7),
+    (0xf2, 0x8e, 0x2b),
+    (0xe1, 0x57, 0x59),
+    (0x76, 0xb7, 0xb2),
+    (0x59, 0xa1, 0x4f),
+    (0xed, 0xc9, 0x48),
+    (0xb0, 0x7a, 0xa1),
+    (0xff, 0x9d, 0xa7),
+    (0x9c, 0x75, 0x5f),
+    (0xba, 0xb0, 0xac),
+];
+
+/// Colour of an empty heatmap cell; cells shade from this towards the first
+/// palette colour.
+const HEATMAP_LOW: Rgb = (0xf7, 0xfb, 0xff);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ChartData {
+    /// Bar and pie charts: one value per category
+    Categories(Vec<(String, f64)>),
+    /// Line and scatter charts; `labels` name the x positions when the x
+    /// column is not numeric
+    Points {
+        points: Vec<(f64, f64)>,
+        labels: Option<Vec<String>>,
+    },
+    /// Histogram bins as `(start, end, count)`
+    Bins(Vec<(f64, f64, usize)>),
+    /// Heatmap counts, indexed `values[row][column]`
+    Grid {
+        rows: Vec<String>,
+        columns: Vec<String>,
+        values: Vec<Vec<f64>>,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub struct Chart {
+    pub chart_type: ChartType,
+    pub title: String,
+    pub x_label: String,
+    pub y_label: String,
+    pub width: u32,
+    pub height: u32,
+    pub palette: Vec<Rgb>,
+    pub data: ChartData,
+}
+
+impl Chart {
+    /// Builds a chart from computed data, taking the title, size and colours
+    /// from `config` and falling back to `default_title`.
+    pub fn new(
+        config: &VisualizationConfig,
+        default_title: String,
+        x_label: &str,
+        y_label: &str,
+        data: ChartData,
+    ) -> Result<Self> {
+        let width = config.width.unwrap_or(DEFAULT_WIDTH);
+        let height = config.height.unwrap_or(DEFAULT_HEIGHT);
+        if width < 200 || height < 100 {
+            return Err(EchomindError::Other(format!(
+                "Chart size {}x{} is too small (at least 200x100)",
+                width, height
+            )));
+        }
+        Ok(Self {
+            chart_type: config.chart_type.clone(),
+            title: config.title.clone().unwrap_or(default_title),
+            x_label: x_label.to_string(),
+            y_label: y_label.to_string(),
+            width,
+            height,
+            palette: palette(config.color_scheme.as_deref())?,
+            data,
+        })
+    }
+
+    fn color(&self, index: usize) -> Rgb {
+        self.palette[index % self.palette.len()]
+    }
+
+    // -----------------------------------------------------------------------
+    // Terminal
+    // -----------------------------------------------------------------------
+
+    /// Draws the chart with Unicode block and braille characters, using ANSI
+    /// true colour when `color` is set.
+    pub fn render_terminal(&self, color: bool) -> String {
+        let cols = (self.width / CELL_WIDTH).clamp(20, 200) as usize;
+        let rows = (self.height / CELL_HEIGHT).clamp(5, 60) as usize;
+
+        let mut lines = vec![if color { self.title.bold().to_string() } else { self.title.clone() }];
+        lines.extend(match &self.data {
+            ChartData::Categories(items) if matches!(self.chart_type, ChartType::Pie) => {
+                self.terminal_pie(items, cols, color)
+            }
+            ChartData::Categories(items) => self.terminal_bars(items, cols, color),
+            ChartData::Points { points, labels } => self.terminal_points(points, labels.as_deref(), cols, rows, color),
+            ChartData::Bins(bins) => self.terminal_histogram(bins, cols, rows, color),
+            ChartData::Grid { rows: row_labels, columns, values } => {
+                self.terminal_heatmap(row_labels, columns, values, cols, color)
+            }
+        });
+        lines.join("\n")
+    }
+
+    fn terminal_bars(&self, items: &[(String, f64)], cols: usize, color: bool) -> Vec<String> {
+        let label_width = items.iter().map(|(label, _)| label.chars().count()).max().unwrap_or(0).min(20);
+        let values: Vec<String> = items.iter().map(|(_, value)| format_number(*value)).collect();
+        let value_width = values.iter().map(|v| v.len()).max().unwrap_or(0);
+        let space = cols.saturating_sub(label_width + value_width + 3).max(5) as f64;
+        let max = items.iter().map(|(_, value)| *value).fold(0.0, f64::max);
+
+        let mut lines: Vec<String> = items
+            .iter()
+            .zip(&values)
+            .map(|((label, value), shown)| {
+                let length = if max > 0.0 && *value > 0.0 { value / max * space } else { 0.0 };
+                format!(
+                    "{:<width$} │{} {}",
+                    clip(label, label_width),
+                    paint(&horizontal_blocks(length), self.color(0), color),
+                    shown,
+                    width = label_width
+                )
+            })
+            .collect();
+        lines.push(dim(&format!("{:<width$} └ {}", "", self.y_label, width = label_width), color));
+        lines
+    }
+
+    fn terminal_pie(&self, items: &[(String, f64)], cols: usize, color: bool) -> Vec<String> {
+        const FILLS: [&str; 6] = ["█", "▓", "▒", "░", "▚", "▞"];
+        let total: f64 = items.iter().map(|(_, value)| value.max(0.0)).sum();
+        if total <= 0.0 {
+            return vec!["(no positive values to show)".to_string()];
+        }
+
+        let shares: Vec<f64> = items.iter().map(|(_, value)| value.max(0.0) / total).collect();
+        let widths = apportion(&shares, cols);
+        let swatch = |index: usize, width: usize| {
+            if color {
+                paint(&"█".repeat(width), self.color(index), true)
+            } else {
+                FILLS[index % FILLS.len()].repeat(width)
+            }
+        };
+
+        let bar: String = widths
+            .iter()
+            .enumerate()
+            .map(|(index, width)| swatch(index, *width))
+            .collect();
+        let label_width = items.iter().map(|(label, _)| label.chars().count()).max().unwrap_or(0).min(24);
+        let mut lines = vec![bar, String::new()];
+        for (index, ((label, value), share)) in items.iter().zip(&shares).enumerate() {
+            lines.push(format!(
+                "{} {:<width$} {:>5.1}%  {}",
+                swatch(index, 2),
+                clip(label, label_width),
+                share * 100.0,
+                format_number(*value),
+                width = label_width
+            ));
+        }
+        lines
+    }
+
+    fn terminal_histogram(&self, bins: &[(f64, f64, usize)], cols: usize, rows: usize, color: bool) -> Vec<String> {
+        let max = bins.iter().map(|(_, _, count)| *count).max().unwrap_or(0);
+        let axis_width = max.to_string().len();
+        let bin_width = (cols.saturating_sub(axis_width + 2) / bins.len().max(1)).max(1);
+
+        let mut lines = Vec::new();
+        for row in (0..rows).rev() {
+            let cells: String = bins
+                .iter()
+                .map(|(_, _, count)| {
+                    let height = if max > 0 { *count as f64 / max as f64 * rows as f64 } else { 0.0 };
+                    vertical_block(height - row as f64).repeat(bin_width)
+                })
+                .collect();
+            let label = match row {
+                r if r + 1 == rows => max.to_string(),
+                0 => "0".to_string(),
+                _ => String::new(),
+            };
+            lines.push(format!("{:>width$} │{}", label, paint(&cells, self.color(0), color), width = axis_width));
+        }
+
+        let plot_width = bin_width * bins.len();
+        lines.push(format!("{:>width$} └{}", "", "─".repeat(plot_width), width = axis_width));
+        if let (Some(first), Some(last)) = (bins.first(), bins.last()) {
+            lines.push(format!(
+                "{:>width$}  {}",
+                "",
+                spread(&format_number(first.0), &format_number(last.1), plot_width),
+                width = axis_width
+            ));
+        }
+        lines.push(dim(&format!("{:>width$}  {} (count per bin)", "", self.x_label, width = axis_width), color));
+        lines
+    }
+
+    fn terminal_points(
+        &self,
+        points: &[(f64, f64)],
+        labels: Option<&[String]>,
+        cols: usize,
+        rows: usize,
+        color: bool,
+    ) -> Vec<String> {
+        let (x_min, x_max) = padded_range(points.iter().map(|p| p.0));
+        let (y_min, y_max) = padded_range(points.iter().map(|p| p.1));
+        let (top, bottom) = (format_number(y_max), format_number(y_min));
+        let axis_width = top.len().max(bottom.len());
+        let plot_cols = cols.saturating_sub(axis_width + 2).max(10);
+
+        let mut canvas = Braille::new(plot_cols, rows);
+        let to_dot = |(x, y): (f64, f64)| {
+            let dx = (x - x_min) / (x_max - x_min) * (canvas.dot_width() - 1) as f64;
+            let dy = (y_max - y) / (y_max - y_min) * (canvas.dot_height() - 1) as f64;
+            (dx.round() as usize, dy.round() as usize)
+        };
+        let dots: Vec<(usize, usize)> = points.iter().map(|p| to_dot(*p)).collect();
+        if matches!(self.chart_type, ChartType::Line) {
+            for pair in dots.windows(2) {
+                canvas.line(pair[0], pair[1]);
+            }
+        }
+        for dot in &dots {
+            canvas.set(dot.0, dot.1);
+        }
+
+        let mut lines = Vec::new();
+        for (row, cells) in canvas.rows().into_iter().enumerate() {
+            let (label, tick) = match row {
+                0 => (top.as_str(), '┤'),
+                r if r + 1 == rows => (bottom.as_str(), '┤'),
+                _ => ("", '│'),
+            };
+            lines.push(format!("{:>width$} {}{}", label, tick, paint(&cells, self.color(0), color), width = axis_width));
+        }
+        lines.push(format!("{:>width$} └{}", "", "─".repeat(plot_cols), width = axis_width));
+
+        let (left, right) = match labels {
+            Some(labels) => (
+                labels.first().cloned().unwrap_or_default(),
+                labels.last().cloned().unwrap_or_default(),
+            ),
+            None => (format_number(x_min), format_number(x_max)),
+        };
+        lines.push(format!("{:>width$}  {}", "", spread(&left, &right, plot_cols), width = axis_width));
+        lines.push(dim(
+            &format!("{:>width$}  x: {}  y: {}", "", self.x_label, self.y_label, width = axis_width),
+            color,
+        ));
+        lines
+    }
+
+    fn terminal_heatmap(
+        &self,
+        row_labels: &[String],
+        columns: &[String],
+        values: &[Vec<f64>],
+        cols: usize,
+        color: bool,
+    ) -> Vec<String> {
+        const SHADES: [&str; 5] = [" ", "░", "▒", "▓", "█"];
+        let max = values.iter().flatten().copied().fold(0.0, f64::max);
+        let label_width = row_labels.iter().map(|l| l.chars().count()).max().unwrap_or(0).min(16);
+        let widest_column = columns.iter().map(|c| c.chars().count()).max().unwrap_or(1);
+        // One column of each cell separates it from its neighbour
+        let cell_width = (widest_column.clamp(3, 8) + 1)
+            .min(cols.saturating_sub(label_width + 1) / columns.len().max(1))
+            .max(2);
+
+        let header: String = columns
+            .iter()
+            .map(|column| format!("{:>width$}", clip(column, cell_width - 1), width = cell_width))
+            .collect();
+        let mut lines = vec![format!("{:<width$} {}", "", header, width = label_width)];
+        for (label, row) in row_labels.iter().zip(values) {
+            let cells: String = row
+                .iter()
+                .map(|value| {
+                    let level = if max > 0.0 { value / max } else { 0.0 };
+                    let shade = SHADES[(level * 4.0).ceil() as usize].repeat(cell_width - 1);
+                    format!(" {}", paint(&shade, blend(HEATMAP_LOW, self.color(0), level.max(0.35)), color))
+                })
+                .collect();
+            lines.push(format!("{:<width$} {}", clip(label, label_width), cells, width = label_width));
+        }
+        lines.push(dim(
+            &format!(
+                "{:<width$} ░ low  █ high (max {}); rows: {}, columns: {}",
+                "",
+                format_number(max),
+                self.y_label,
+                self.x_label,
+                width = label_width
+            ),
+            color,
+        ));
+        lines
+    }
+
+    // -----------------------------------------------------------------------
+    // SVG
+    // -----------------------------------------------------------------------
+
+    /// A standalone SVG document for the chart.
+    pub fn to_svg(&self) -> String {
+        let (w, h) = (self.width as f64, self.height as f64);
+        let mut svg = format!(
+            concat!(
+                r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}" font-family="sans-serif" font-size="12">"#,
+                "\n",
+                r#"<rect width="100%" height="100%" fill="white"/>"#,
+                "\n",
+                r#"<text x="{cx}" y="26" text-anchor="middle" font-size="16" font-weight="bold">{title}</text>"#,
+                "\n"
+            ),
+            w = self.width,
+            h = self.height,
+            cx = w / 2.0,
+            title = xml_escape(&self.title)
+        );
+
+        let plot = Area {
+            left: 64.0,
+            top: 48.0,
+            right: w - 24.0,
+            bottom: h - 56.0,
+        };
+        match &self.data {
+            ChartData::Categories(items) if matches!(self.chart_type, ChartType::Pie) => self.svg_pie(&mut svg, items),
+            ChartData::Categories(items) => self.svg_bars(&mut svg, items, plot),
+            ChartData::Points { points, labels } => self.svg_points(&mut svg, points, labels.as_deref(), plot),
+            ChartData::Bins(bins) => self.svg_histogram(&mut svg, bins, plot),
+            ChartData::Grid { rows, columns, values } => self.svg_heatmap(&mut svg, rows, columns, values),
+        }
+        svg.push_str("</svg>\n");
+        svg
+    }
+
+    fn svg_bars(&self, svg: &mut String, items: &[(String, f64)], plot: Area) {
+        let low = items.iter().map(|(_, v)| *v).fold(0.0, f64::min);
+        let high = items.iter().map(|(_, v)| *v).fold(0.0, f64::max);
+        let ticks = nice_ticks(low, if high > low { high } else { low + 1.0 });
+        let (low, high) = (ticks[0], ticks[ticks.len() - 1]);
+        svg_y_axis(svg, plot, &ticks, &self.y_label);
+
+        let band = plot.width() / items.len().max(1) as f64;
+        let y = |value: f64| plot.bottom - (value - low) / (high - low) * plot.height();
+        for (index, (label, value)) in items.iter().enumerate() {
+            let x = plot.left + band * index as f64;
+            let (top, bottom) = if *value >= 0.0 { (y(*value), y(0.0)) } else { (y(0.0), y(*value)) };
+            svg.push_str(&format!(
+                "<rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" fill=\"{}\"><title>{}: {}</title></rect>\n",
+                x + band * 0.1,
+                top,
+                band * 0.8,
+                (bottom - top).max(0.5),
+                hex(self.color(0)),
+                xml_escape(label),
+                format_number(*value)
+            ));
+            svg_x_label(svg, x + band / 2.0, plot.bottom, label, band);
+        }
+        svg_x_title(svg, plot, &self.x_label);
+    }
+
+    fn svg_histogram(&self, svg: &mut String, bins: &[(f64, f64, usize)], plot: Area) {
+        let max = bins.iter().map(|(_, _, count)| *count).max().unwrap_or(0).max(1);
+        let ticks = nice_ticks(0.0, max as f64);
+        let high = ticks[ticks.len() - 1];
+        svg_y_axis(svg, plot, &ticks, "count");
+
+        let band = plot.width() / bins.len().max(1) as f64;
+        let every = bins.len().div_ceil(10).max(1);
+        for (index, (start, end, count)) in bins.iter().enumerate() {
+            let x = plot.left + band * index as f64;
+            let height = *count as f64 / high * plot.height();
+            svg.push_str(&format!(
+                "<rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" fill=\"{}\" stroke=\"white\"><title>{} to {}: {}</title></rect>\n",
+                x,
+                plot.bottom - height,
+                band,
+                height,
+                hex(self.color(0)),
+                format_number(*start),
+                format_number(*end),
+                count
+            ));
+            if index % every == 0 {
+                svg_tick_label(svg, x, plot.bottom, &format_number(*start));
+            }
+        }
+        if let Some((_, end, _)) = bins.last() {
+            svg_tick_label(svg, plot.right, plot.bottom, &format_number(*end));
+        }
+        svg_x_title(svg, plot, &self.x_label);
+    }
+
+    fn svg_points(&self, svg: &mut String, points: &[(f64, f64)], labels: Option<&[String]>, plot: Area) {
+        let (y_low, y_high) = padded_range(points.iter().map(|p| p.1));
+        let y_ticks = nice_ticks(y_low, y_high);
+        let (y_low, y_high) = (y_ticks[0], y_ticks[y_ticks.len() - 1]);
+        svg_y_axis(svg, plot, &y_ticks, &self.y_label);
+
+        let (x_low, x_high) = match labels {
+            Some(_) => padded_range(points.iter().map(|p| p.0)),
+            None => {
+                let (low, high) = padded_range(points.iter().map(|p| p.0));
+                let ticks = nice_ticks(low, high);
+                (ticks[0], ticks[ticks.len() - 1])
+            }
+        };
+        let x = |value: f64| plot.left + (value - x_low) / (x_high - x_low) * plot.width();
+        let y = |value: f64| plot.bottom - (value - y_low) / (y_high - y_low) * plot.height();
+
+        match labels {
+            Some(labels) => {
+                let every = labels.len().div_ceil(10).max(1);
+                let band = plot.width() / labels.len().div_ceil(every).max(1) as f64;
+                for (index, label) in labels.iter().enumerate().step_by(every) {
+                    svg_x_label(svg, x(index as f64), plot.bottom, label, band);
+                }
+            }
+            None => {
+                for tick in nice_ticks(x_low, x_high) {
+                    svg_tick_label(svg, x(tick), plot.bottom, &format_number(tick));
+                }
+            }
+        }
+
+        let color = hex(self.color(0));
+        if matches!(self.chart_type, ChartType::Line) {
+            let path: Vec<String> = points.iter().map(|(px, py)| format!("{:.1},{:.1}", x(*px), y(*py))).collect();
+            svg.push_str(&format!(
+                "<polyline points=\"{}\" fill=\"none\" stroke=\"{}\" stroke-width=\"2\"/>\n",
+                path.join(" "),
+                color
+            ));
+        }
+        let (radius, opacity) = match self.chart_type {
+            ChartType::Line if points.len() > 60 => (0.0, 1.0),
+            ChartType::Line => (3.0, 1.0),
+            _ => (3.5, 0.7),
+        };
+        if radius > 0.0 {
+            for (index, (px, py)) in points.iter().enumerate() {
+                let shown_x = labels
+                    .and_then(|labels| labels.get(index).cloned())
+                    .unwrap_or_else(|| format_number(*px));
+                svg.push_str(&format!(
+                    "<circle cx=\"{:.1}\" cy=\"{:.1}\" r=\"{}\" fill=\"{}\" fill-opacity=\"{}\"><title>{}, {}</title></circle>\n",
+                    x(*px),
+                    y(*py),
+                    radius,
+                    color,
+                    opacity,
+                    xml_escape(&shown_x),
+                    format_number(*py)
+                ));
+            }
+        }
+        svg_x_title(svg, plot, &self.x_label);
+    }
+
+    fn svg_pie(&self, svg: &mut String, items: &[(String, f64)]) {
+        let (w, h) = (self.width as f64, self.height as f64);
+        let total: f64 = items.iter().map(|(_, value)| value.max(0.0)).sum();
+        let radius = ((w * 0.6).min(h - 80.0) / 2.0 - 10.0).max(20.0);
+        let (cx, cy) = (24.0 + radius, 48.0 + (h - 72.0) / 2.0);
+
+        let mut angle = -std::f64::consts::FRAC_PI_2;
+        for (index, (label, value)) in items.iter().enumerate() {
+            let share = if total > 0.0 { value.max(0.0) / total } else { 0.0 };
+            let color = hex(self.color(index));
+            let tooltip = format!("<title>{}: {} ({:.1}%)</title>", xml_escape(label), format_number(*value), share * 100.0);
+            if share >= 0.9999 {
+                svg.push_str(&format!(
+                    "<circle cx=\"{:.1}\" cy=\"{:.1}\" r=\"{:.1}\" fill=\"{}\">{}</circle>\n",
+                    cx, cy, radius, color, tooltip
+                ));
+            } else if share > 0.0 {
+                let end = angle + share * std::f64::consts::TAU;
+                svg.push_str(&format!(
+                    "<path d=\"M{:.1},{:.1} L{:.1},{:.1} A{:.1},{:.1} 0 {} 1 {:.1},{:.1} Z\" fill=\"{}\" stroke=\"white\">{}</path>\n",
+                    cx,
+                    cy,
+                    cx + radius * angle.cos(),
+                    cy + radius * angle.sin(),
+                    radius,
+                    radius,
+                    if share > 0.5 { 1 } else { 0 },
+                    cx + radius * end.cos(),
+                    cy + radius * end.sin(),
+                    color,
+                    tooltip
+                ));
+                angle = end;
+            }
+
+            let legend_y = 60.0 + index as f64 * 20.0;
+            if legend_y < h - 12.0 {
+                let legend_x = cx + radius + 32.0;
+                svg.push_str(&format!(
+                    "<rect x=\"{:.1}\" y=\"{:.1}\" width=\"12\" height=\"12\" fill=\"{}\"/>\n<text x=\"{:.1}\" y=\"{:.1}\">{} ({:.1}%)</text>\n",
+                    legend_x,
+                    legend_y - 10.0,
+                    color,
+                    legend_x + 18.0,
+                    legend_y,
+                    xml_escape(&clip(label, 28)),
+                    share * 100.0
+                ));
+            }
+        }
+    }
+
+    fn svg_heatmap(&self, svg: &mut String, rows: &[String], columns: &[String], values: &[Vec<f64>]) {
+        let (w, h) = (self.width as f64, self.height as f64);
+        let label_width = rows.iter().map(|l| l.chars().count().min(20)).max().unwrap_or(0) as f64 * 7.0 + 12.0;
+        let plot = Area {
+            left: label_width.min(w / 3.0),
+            top: 48.0,
+            right: w - 24.0,
+            bottom: h - 72.0,
+        };
+        let cell_w = plot.width() / columns.len().max(1) as f64;
+        let cell_h = plot.height() / rows.len().max(1) as f64;
+        let max = values.iter().flatten().copied().fold(0.0, f64::max);
+
+        for (r, (label, row)) in rows.iter().zip(values).enumerate() {
+            let y = plot.top + cell_h * r as f64;
+            svg.push_str(&format!(
+                "<text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"end\" dominant-baseline=\"middle\">{}</text>\n",
+                plot.left - 6.0,
+                y + cell_h / 2.0,
+                xml_escape(&clip(label, 20))
+            ));
+            for (c, value) in row.iter().enumerate() {
+                let level = if max > 0.0 { value / max } else { 0.0 };
+                let x = plot.left + cell_w * c as f64;
+                svg.push_str(&format!(
+                    "<rect x=\"{:.1}\" y=\"{:.1}\" width=\"{:.1}\" height=\"{:.1}\" fill=\"{}\" stroke=\"white\"><title>{} / {}: {}</title></rect>\n",
+                    x,
+                    y,
+                    cell_w,
+                    cell_h,
+                    hex(blend(HEATMAP_LOW, self.color(0), level)),
+                    xml_escape(label),
+                    xml_escape(&columns[c]),
+                    format_number(*value)
+                ));
+                if cell_w >= 28.0 && cell_h >= 16.0 {
+                    svg.push_str(&format!(
+                        "<text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"middle\" dominant-baseline=\"middle\" fill=\"{}\">{}</text>\n",
+                        x + cell_w / 2.0,
+                        y + cell_h / 2.0,
+                        if level > 0.5 { "white" } else { "black" },
+                        format_number(*value)
+                    ));
+                }
+            }
+        }
+        for (c, column) in columns.iter().enumerate() {
+            svg_x_label(svg, plot.left + cell_w * (c as f64 + 0.5), plot.bottom, column, cell_w);
+        }
+        svg_x_title(svg, Area { bottom: plot.bottom + 16.0, ..plot }, &self.x_label);
+    }
+
+    // -----------------------------------------------------------------------
+    // TUI
+    // -----------------------------------------------------------------------
+
+    /// Draws the chart with ratatui's bar chart and chart widgets.
+    pub fn render(&self, frame: &mut Frame, area: Rect) {
+        let block = Block::default().borders(Borders::ALL).title(format!(" {} ", self.title));
+        let style = |rgb: Rgb| Style::default().fg(Color::Rgb(rgb.0, rgb.1, rgb.2));
+
+        match &self.data {
+            ChartData::Categories(items) => {
+                let total: f64 = items.iter().map(|(_, value)| value.max(0.0)).sum();
+                let pie = matches!(self.chart_type, ChartType::Pie);
+                let values: Vec<(String, f64, String)> = items
+                    .iter()
+                    .map(|(label, value)| {
+                        let shown = if pie && total > 0.0 {
+                            format!("{:.1}%", value.max(0.0) / total * 100.0)
+                        } else {
+                            format_number(*value)
+                        };
+                        (label.clone(), *value, shown)
+                    })
+                    .collect();
+                let bars = tui_bars(&values, |index| style(if pie { self.color(index) } else { self.color(0) }));
+                let chart = BarChart::default()
+                    .block(block)
+                    .direction(Direction::Horizontal)
+                    .bar_width(1)
+                    .bar_gap(0)
+                    .data(BarGroup::default().bars(&bars));
+                frame.render_widget(chart, area);
+            }
+            ChartData::Bins(bins) => {
+                let values: Vec<(String, f64, String)> = bins
+                    .iter()
+                    .map(|(start, _, count)| (format_number(*start), *count as f64, count.to_string()))
+                    .collect();
+                let bars = tui_bars(&values, |_| style(self.color(0)));
+                let chart = BarChart::default()
+                    .block(block)
+                    .direction(Direction::Horizontal)
+                    .bar_width(1)
+                    .bar_gap(0)
+                    .data(BarGroup::default().bars(&bars));
+                frame.render_widget(chart, area);
+            }
+            ChartData::Points { points, labels } => {
+                let (x_low, x_high) = padded_range(points.iter().map(|p| p.0));
+                let (y_low, y_high) = padded_range(points.iter().map(|p| p.1));
+                let x_labels = match labels {
+                    Some(labels) => vec![
+                        labels.first().cloned().unwrap_or_default(),
+                        labels.last().cloned().unwrap_or_default(),
+                    ],
+                    None => vec![format_number(x_low), format_number((x_low + x_high) / 2.0), format_number(x_high)],
+                };
+                let dataset = Dataset::default()
+                    .marker(Marker::Braille)
+                    .graph_type(match self.chart_type {
+                        ChartType::Line => GraphType::Line,
+                        _ => GraphType::Scatter,
+                    })
+                    .style(style(self.color(0)))
+                    .data(points);
+                let chart = ChartWidget::new(vec![dataset])
+                    .block(block)
+                    .x_axis(
+                        Axis::default()
+                            .title(self.x_label.clone())
+                            .bounds([x_low, x_high])
+                            .labels(x_labels.into_iter().map(Span::raw).collect()),
+                    )
+                    .y_axis(
+                        Axis::default()
+                            .title(self.y_label.clone())
+                            .bounds([y_low, y_high])
+                            .labels(
+                                [y_low, (y_low + y_high) / 2.0, y_high]
+                                    .into_iter()
+                                    .map(|v| Span::raw(format_number(v)))
+                                    .collect(),
+                            ),
+                    );
+                frame.render_widget(chart, area);
+            }
+            ChartData::Grid { rows, columns, values } => {
+                let max = values.iter().flatten().copied().fold(0.0, f64::max);
+                let label_width = rows.iter().map(|l| l.chars().count()).max().unwrap_or(0).min(16);
+                let cell_width = columns.iter().map(|c| c.chars().count()).max().unwrap_or(1).clamp(3, 8) + 1;
+                let header: String = columns
+                    .iter()
+                    .map(|column| format!("{:>width$}", clip(column, cell_width - 1), width = cell_width))
+                    .collect();
+                let mut lines = vec![Line::raw(format!("{:<width$} {}", "", header, width = label_width))];
+                for (label, row) in rows.iter().zip(values) {
+                    let mut spans = vec![Span::raw(format!("{:<width$} ", clip(label, label_width), width = label_width))];
+                    spans.extend(row.iter().map(|value| {
+                        let level = if max > 0.0 { value / max } else { 0.0 };
+                        let (r, g, b) = blend(HEATMAP_LOW, self.color(0), level);
+                        let text = if level > 0.5 { Color::White } else { Color::Black };
+                        Span::styled(
+                            format!("{:>width$}", format_number(*value), width = cell_width),
+                            Style::default().bg(Color::Rgb(r, g, b)).fg(text),
+                        )
+                    }));
+                    lines.push(Line::from(spans));
+                }
+                frame.render_widget(Paragraph::new(lines).block(block), area);
+            }
+        }
+    }
+}
+
+/// Bars scaled to integers for ratatui, labelled with the original values.
+fn tui_bars(values: &[(String, f64, String)], style: impl Fn(usize) -> Style) -> Vec<Bar<'static>> {
+    let max = values.iter().map(|(_, value, _)| *value).fold(0.0, f64::max);
+    values
+        .iter()
+        .enumerate()
+        .map(|(index, (label, value, shown))| {
+            let scaled = if max > 0.0 { (value.max(0.0) / max * 1000.0).round() as u64 } else { 0 };
+            Bar::default()
+                .label(Line::from(clip(label, 16)))
+                .value(scaled)
+                .text_value(shown.clone())
+                .style(style(index))
+        })
+        .collect()
+}
+
+/// Parses a colour scheme: one of the named palettes or a comma-separated
+/// list of hex colours such as `#1f77b4,#ff7f0e`.
+pub fn palette(scheme: Option<&str>) -> Result<Vec<Rgb>> {
+    let named: &[u32] = match scheme.map(|s| s.trim().to_lowercase()).as_deref() {
+        None | Some("") | Some("default") => return Ok(DEFAULT_PALETTE.to_vec()),
+        Some("blues") => &[0x08519c, 0x3182bd, 0x6baed6, 0x9ecae1, 0xc6dbef],
+        Some("greens") => &[0x006d2c, 0x31a354, 0x74c476, 0xa1d99b, 0xc7e9c0],
+        Some("reds") => &[0xa50f15, 0xde2d26, 0xfb6a4a, 0xfc9272, 0xfcbba1],
+        Some("viridis") => &[0x440154, 0x3b528b, 0x21918c, 0x5ec962, 0xfde725],
+        Some("mono") => &[0x252525, 0x636363, 0x969696, 0xbdbdbd, 0xd9d9d9],
+        Some(list) => {
+            return list
+                .split(',')
+                .map(|item| parse_hex(item.trim()))
+                .collect::<Option<Vec<Rgb>>>()
+                .ok_or_else(|| {
+                    EchomindError::Other(format!(
+                        "Unknown colour scheme '{}' (use default, blues, greens, reds, viridis, mono or comma-separated hex colours)",
+                        list
+                    ))
+                })
+        }
+    };
+    Ok(named.iter().map(|c| ((c >> 16) as u8, (c >> 8) as u8, *c as u8)).collect())
+}
+
+fn parse_hex(text: &str) -> Option<Rgb> {
+    let digits = text.strip_prefix('#').unwrap_or(text);
+    let digits = match digits.len() {
+        3 => digits.chars().flat_map(|c| [c, c]).collect(),
+        6 => digits.to_string(),
+        _ => return None,
+    };
+    let value = u32::from_str_radix(&digits, 16).ok()?;
+    Some(((value >> 16) as u8, (value >> 8) as u8, value as u8))
+}
+
+fn hex((r, g, b): Rgb) -> String {
+    format!("#{:02x}{:02x}{:02x}", r, g, b)
+}
+
+fn blend(from: Rgb, to: Rgb, amount: f64) -> Rgb {
+    let amount = amount.clamp(0.0, 1.0);
+    let mix = |a: u8, b: u8| (a as f64 + (b as f64 - a as f64) * amount).round() as u8;
+    (mix(from.0, to.0), mix(from.1, to.1), mix(from.2, to.2))
+}
+
+fn paint(text: &str, (r, g, b): Rgb, color: bool) -> String {
+    if color {
+        text.truecolor(r, g, b).to_string()
+    } else {
+        text.to_string()
+    }
+}
+
+fn dim(text: &str, color: bool) -> String {
+    if color {
+        text.dimmed().to_string()
+    } else {
+        text.to_string()
+    }
+}
+
+/// Whole numbers without decimals, others with at most two.
+pub fn format_number(value: f64) -> String {
+    if value.fract() == 0.0 && value.abs() < 1e15 {
+        format!("{}", value as i64)
+    } else {
+        let text = format!("{:.2}", value);
+        text.trim_end_matches('0').trim_end_matches('.').to_string()
+    }
+}
+
+fn clip(text: &str, max_chars: usize) -> String {
+    if text.chars().count() <= max_chars {
+        text.to_string()
+    } else if max_chars <= 1 {
+        text.chars().take(max_chars).collect()
+    } else {
+        format!("{}…", text.chars().take(max_chars - 1).collect::<String>())
+    }
+}
+
+/// `left` at the start and `right` flush with the end of `width` columns.
+fn spread(left: &str, right: &str, width: usize) -> String {
+    let gap = width.saturating_sub(left.chars().count() + right.chars().count()).max(1);
+    format!("{}{}{}", left, " ".repeat(gap), right)
+}
+
+fn horizontal_blocks(length: f64) -> String {
+    const PARTIAL: [&str; 8] = ["", "▏", "▎", "▍", "▌", "▋", "▊", "▉"];
+    let eighths = (length * 8.0).round() as usize;
+    format!("{}{}", "█".repeat(eighths / 8), PARTIAL[eighths % 8])
+}
+
+fn vertical_block(fill: f64) -> &'static str {
+    const PARTIAL: [&str; 9] = [" ", "▁", "▂", "▃", "▄", "▅", "▆", "▇", "█"];
+    PARTIAL[(fill.clamp(0.0, 1.0) * 8.0).round() as usize]
+}
+
+/// Splits `total` columns between `shares` so the widths add up exactly.
+fn apportion(shares: &[f64], total: usize) -> Vec<usize> {
+    let exact: Vec<f64> = shares.iter().map(|share| share * total as f64).collect();
+    let mut widths: Vec<usize> = exact.iter().map(|w| w.floor() as usize).collect();
+    let mut order: Vec<usize> = (0..shares.len()).collect();
+    order.sort_by(|a, b| (exact[*b] - exact[*b].floor()).total_cmp(&(exact[*a] - exact[*a].floor())));
+    let remaining = total.saturating_sub(widths.iter().sum());
+    for index in order.into_iter().take(remaining) {
+        widths[index] += 1;
+    }
+    widths
+}
+
+/// The range of `values`, widened when all values are equal.
+fn padded_range(values: impl Iterator<Item = f64>) -> (f64, f64) {
+    let (low, high) = values.fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), v| (lo.min(v), hi.max(v)));
+    if !low.is_finite() || !high.is_finite() {
+        (0.0, 1.0)
+    } else if low == high {
+        (low - 1.0, high + 1.0)
+    } else {
+        (low, high)
+    }
+}
+
+/// Round tick values covering `low..=high`, about five of them.
+fn nice_ticks(low: f64, high: f64) -> Vec<f64> {
+    let raw = (high - low) / 5.0;
+    let magnitude = 10f64.powf(raw.log10().floor());
+    let step = [1.0, 2.0, 2.5, 5.0, 10.0]
+        .into_iter()
+        .map(|m| m * magnitude)
+        .find(|step| *step >= raw)
+        .unwrap_or(10.0 * magnitude);
+    let start = (low / step).floor() * step;
+    let mut ticks = vec![start];
+    while ticks[ticks.len() - 1] < high - step * 1e-9 {
+        ticks.push(start + step * ticks.len() as f64);
+    }
+    ticks
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Area {
+    left: f64,
+    top: f64,
+    right: f64,
+    bottom: f64,
+}
+
+impl Area {
+    fn width(&self) -> f64 {
+        self.right - self.left
+    }
+
+    fn height(&self) -> f64 {
+        self.bottom - self.top
+    }
+}
+
+fn svg_y_axis(svg: &mut String, plot: Area, ticks: &[f64], title: &str) {
+    let (low, high) = (ticks[0], ticks[ticks.len() - 1]);
+    for tick in ticks {
+        let y = plot.bottom - (tick - low) / (high - low) * plot.height();
+        svg.push_str(&format!(
+            "<line x1=\"{:.1}\" y1=\"{:.1}\" x2=\"{:.1}\" y2=\"{:.1}\" stroke=\"#e0e0e0\"/>\n<text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"end\" dominant-baseline=\"middle\" fill=\"#555\">{}</text>\n",
+            plot.left,
+            y,
+            plot.right,
+            y,
+            plot.left - 6.0,
+            y,
+            format_number(*tick)
+        ));
+    }
+    svg.push_str(&format!(
+        "<line x1=\"{l:.1}\" y1=\"{t:.1}\" x2=\"{l:.1}\" y2=\"{b:.1}\" stroke=\"#888\"/>\n<line x1=\"{l:.1}\" y1=\"{b:.1}\" x2=\"{r:.1}\" y2=\"{b:.1}\" stroke=\"#888\"/>\n",
+        l = plot.left,
+        t = plot.top,
+        b = plot.bottom,
+        r = plot.right
+    ));
+    svg.push_str(&format!(
+        "<text x=\"14\" y=\"{:.1}\" text-anchor=\"middle\" transform=\"rotate(-90 14 {:.1})\" fill=\"#333\">{}</text>\n",
+        plot.top + plot.height() / 2.0,
+        plot.top + plot.height() / 2.0,
+        xml_escape(title)
+    ));
+}
+
+fn svg_tick_label(svg: &mut String, x: f64, bottom: f64, label: &str) {
+    svg.push_str(&format!(
+        "<text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"middle\" fill=\"#555\">{}</text>\n",
+        x,
+        bottom + 16.0,
+        xml_escape(label)
+    ));
+}
+
+/// A category label under the x axis, slanted when its band is narrow.
+fn svg_x_label(svg: &mut String, x: f64, bottom: f64, label: &str, band: f64) {
+    if band >= 60.0 {
+        let max_chars = (band / 7.0) as usize;
+        svg_tick_label(svg, x, bottom, &clip(label, max_chars));
+    } else {
+        let y = bottom + 12.0;
+        svg.push_str(&format!(
+            "<text x=\"{x:.1}\" y=\"{y:.1}\" text-anchor=\"end\" transform=\"rotate(-35 {x:.1} {y:.1})\" fill=\"#555\">{}</text>\n",
+            xml_escape(&clip(label, 14)),
+            x = x,
+            y = y
+        ));
+    }
+}
+
+fn svg_x_title(svg: &mut String, plot: Area, title: &str) {
+    svg.push_str(&format!(
+        "<text x=\"{:.1}\" y=\"{:.1}\" text-anchor=\"middle\" fill=\"#333\">{}</text>\n",
+        plot.left + plot.width() / 2.0,
+        plot.bottom + 44.0,
+        xml_escape(title)
+    ));
+}
+
+fn xml_escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// A grid of braille characters, each holding 2×4 dots.
+struct Braille {
+    width: usize,
+    height: usize,
+    cells: Vec<u8>,
+}
+
+impl Braille {
+    fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            cells: vec![0; width * height],
+        }
+    }
+
+    fn dot_width(&self) -> usize {
+        self.width * 2
+    }
+
+    fn dot_height(&self) -> usize {
+        self.height * 4
+    }
+
+    fn set(&mut self, x: usize, y: usize) {
+        const BITS: [[u8; 4]; 2] = [[0x01, 0x02, 0x04, 0x40], [0x08, 0x10, 0x20, 0x80]];
+        if x < self.dot_width() && y < self.dot_height() {
+            self.cells[(y / 4) * self.width + x / 2] |= BITS[x % 2][y % 4];
+        }
+    }
+
+    /// Bresenham's line between two dots.
+    fn line(&mut self, from: (usize, usize), to: (usize, usize)) {
+        let (mut x, mut y) = (from.0 as i64, from.1 as i64);
+        let (x1, y1) = (to.0 as i64, to.1 as i64);
+        let (dx, dy) = ((x1 - x).abs(), -(y1 - y).abs());
+        let (sx, sy) = (if x < x1 { 1 } else { -1 }, if y < y1 { 1 } else { -1 });
+        let mut error = dx + dy;
+        loop {
+            self.set(x as usize, y as usize);
+            if x == x1 && y == y1 {
+                break;
+            }
+            let doubled = 2 * error;
+            if doubled >= dy {
+                error += dy;
+                x += sx;
+            }
+            if doubled <= dx {
+                error += dx;
+                y += sy;
+            }
+        }
+    }
+
+    fn rows(&self) -> Vec<String> {
+        self.cells
+            .chunks(self.width)
+            .map(|row| {
+                row.iter()
+                    .map(|bits| char::from_u32(0x2800 + *bits as u32).unwrap_or(' '))
+                    .collect()
+            })
+            .collect()
+    }
+}
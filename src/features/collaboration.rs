@@ -251,7 +251,7 @@ impl CollaborationManager {
                 
                 md.push_str("## Participants\n\n");
                 for participant in &session.participants {
-                    md.push_str(&format!("- **{}** ({})\n", participant.name, format!("{:?}", participant.role)));
+                    md.push_str(&format!("- **{}** ({:?})\n", participant.name, participant.role));
                 }
                 
                 md.push_str("\n## Messages\n\n");
@@ -0,0 +1,232 @@
+//! `--compare`: one prompt sent to several models at once, with latency,
+//! token and cost estimates per model, line diffs between the responses
+//! and an optional verdict from a judge model.
+
+use crate::api::{ChatRequest, Message};
+use crate::error::{EchomindError, Result};
+use crate::features::json_schema::extract_json;
+use crate::features::performance::{estimate_cost, estimate_tokens, send_timed_metered, BenchmarkTarget};
+use crate::features::usage::UsageMeter;
+use serde::Serialize;
+use serde_json::Value;
+use similar::{ChangeTag, DiffTag, TextDiff};
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+const JUDGE_PROMPT: &str = "You compare answers that different models gave to the same prompt. \
+Judge them on correctness, completeness and clarity. Reply with JSON only, in the form \
+{\"winner\": \"<label>\", \"scores\": {\"<label>\": <0-10>, ...}, \"reason\": \"<one or two sentences>\"}.";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ComparisonEntry {
+    /// `provider:model`
+    pub model: String,
+    pub response: Option<String>,
+    pub error: Option<String>,
+    pub latency_ms: u64,
+    /// Time to first token, for streamed responses
+    pub ttft_ms: Option<u64>,
+    pub input_tokens: u32,
+    pub output_tokens: u32,
+    /// Estimated dollars
+    pub cost: f64,
+    /// Set when the request went out over a budget whose action is warn
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub budget_warning: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct JudgeVerdict {
+    /// `provider:model` of the judge
+    pub judge: String,
+    /// The winning entry's model, if the judge named one
+    pub winner: Option<String>,
+    /// Score out of 10 per model
+    pub scores: BTreeMap<String, f64>,
+    pub reason: String,
+    /// Set when the judge was asked over a budget whose action is warn
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub budget_warning: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Comparison {
+    pub input: String,
+    pub entries: Vec<ComparisonEntry>,
+    pub verdict: Option<JudgeVerdict>,
+}
+
+impl Comparison {
+    /// Entries that produced a response, with it.
+    pub fn responses(&self) -> Vec<(&ComparisonEntry, &str)> {
+        self.entries
+            .iter()
+            .filter_map(|entry| entry.response.as_deref().map(|response| (entry, response)))
+            .collect()
+    }
+}
+
+/// Sends `request` to every target at once, each with its own model, and
+/// returns the entries in target order. With `usage`, each is checked
+/// against the budget and recorded.
+pub async fn run_comparison(
+    targets: &[BenchmarkTarget],
+    request: &ChatRequest,
+    usage: Option<&UsageMeter>,
+) -> Vec<ComparisonEntry> {
+    let runs = targets.iter().map(|target| async move {
+        let request = ChatRequest {
+            model: Some(target.model.clone()),
+            ..request.clone()
+        };
+        // A target over a blocking budget gets the block as its error
+        let (response, timing, elapsed, budget_warning) =
+            send_timed_metered(usage, &target.provider, target.client(), &request)
+                .await
+                .unwrap_or_else(|e| (Err(e), None, Duration::ZERO, None));
+
+        let input_tokens: u32 = request.messages.iter().filter_map(|m| m.get_text()).map(estimate_tokens).sum();
+        let output_tokens = response.as_deref().map(estimate_tokens).unwrap_or(0);
+        ComparisonEntry {
+            model: target.label(),
+            latency_ms: elapsed.as_millis() as u64,
+            ttft_ms: timing.and_then(|t| t.first_token).map(|d| d.as_millis() as u64),
+            input_tokens,
+            output_tokens,
+            cost: match (&response, usage) {
+                (Err(_), _) => 0.0,
+                (Ok(_), Some(usage)) => usage.price(&target.provider, &target.model, input_tokens, output_tokens),
+                (Ok(_), None) => estimate_cost(&target.provider, &target.model, input_tokens, output_tokens),
+            },
+            error: response.as_ref().err().map(|e| e.to_string()),
+            response: response.ok(),
+            budget_warning,
+        }
+    });
+    futures::future::join_all(runs).await
+}
+
+/// Asks `judge` to pick the best response. Responses are shown to it as
+/// A, B, C… so it doesn't see which model wrote which. With `usage`, the
+/// request is checked against the budget and recorded.
+pub async fn judge(judge: &BenchmarkTarget, comparison: &Comparison, usage: Option<&UsageMeter>) -> Result<JudgeVerdict> {
+    let responses = comparison.responses();
+    if responses.len() < 2 {
+        return Err(EchomindError::Other("The judge needs at least two responses to compare".to_string()));
+    }
+    let labels: Vec<String> = (0..responses.len()).map(label).collect();
+    let answers: Vec<String> = responses
+        .iter()
+        .zip(&labels)
+        .map(|((_, response), label)| format!("Answer {}:\n{}", label, response))
+        .collect();
+
+    let request = ChatRequest {
+        messages: vec![
+            Message::text("system".to_string(), JUDGE_PROMPT.to_string()),
+            Message::text(
+                "user".to_string(),
+                format!("Prompt:\n{}\n\n{}", comparison.input, answers.join("\n\n")),
+            ),
+        ],
+        model: Some(judge.model.clone()),
+        temperature: Some(0.0),
+        max_tokens: None,
+        top_p: None,
+        top_k: None,
+        stream: None,
+    };
+    let mut budget_warning = None;
+    let reply = UsageMeter::send(usage, &judge.provider, &request, |warning| {
+        budget_warning = warning;
+        judge.client().send_message(request.clone())
+    })
+    .await?;
+    let verdict = extract_json(&reply)
+        .ok_or_else(|| EchomindError::ParseError(format!("The judge did not reply with JSON: {}", reply.trim())))?;
+
+    let model_for = |label: &str| {
+        labels
+            .iter()
+            .position(|l| l.eq_ignore_ascii_case(label.trim()))
+            .map(|i| responses[i].0.model.clone())
+    };
+    let scores = verdict
+        .get("scores")
+        .and_then(Value::as_object)
+        .map(|scores| {
+            scores
+                .iter()
+                .filter_map(|(label, score)| Some((model_for(label)?, score.as_f64()?)))
+                .collect()
+        })
+        .unwrap_or_default();
+    Ok(JudgeVerdict {
+        judge: judge.label(),
+        winner: verdict.get("winner").and_then(Value::as_str).and_then(model_for),
+        scores,
+        reason: verdict.get("reason").and_then(Value::as_str).unwrap_or("").to_string(),
+        budget_warning,
+    })
+}
+
+fn label(index: usize) -> String {
+    char::from(b'A' + (index % 26) as u8).to_string()
+}
+
+/// A unified diff from `old` to `new`, empty when they match.
+pub fn unified_diff(old: &str, new: &str, old_label: &str, new_label: &str) -> String {
+    let diff = TextDiff::from_lines(old, new);
+    if diff.ratio() == 1.0 {
+        return String::new();
+    }
+    diff.unified_diff().context_radius(3).header(old_label, new_label).to_string()
+}
+
+/// How alike two responses are line by line, from 0 to 1.
+pub fn line_similarity(old: &str, new: &str) -> f32 {
+    TextDiff::from_lines(old, new).ratio()
+}
+
+/// One row of a side-by-side diff. A missing side is a line that only
+/// exists in the other response.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiffRow {
+    pub left: Option<String>,
+    pub right: Option<String>,
+    pub changed: bool,
+}
+
+/// Pairs up the lines of two responses, with replaced lines side by side.
+pub fn side_by_side(old: &str, new: &str) -> Vec<DiffRow> {
+    let diff = TextDiff::from_lines(old, new);
+    let line = |text: &str| text.trim_end_matches(['\n', '\r']).to_string();
+    let mut rows = Vec::new();
+    for op in diff.ops() {
+        let (tag, old_range, new_range) = op.as_tag_tuple();
+        let old_lines = &diff.old_slices()[old_range];
+        let new_lines = &diff.new_slices()[new_range];
+        let changed = tag != DiffTag::Equal;
+        for i in 0..old_lines.len().max(new_lines.len()) {
+            rows.push(DiffRow {
+                left: old_lines.get(i).map(|text| line(text)),
+                right: new_lines.get(i).map(|text| line(text)),
+                changed,
+            });
+        }
+    }
+    rows
+}
+
+/// Lines of `text` tagged with whether they differ from `reference`, for
+/// highlighting one response against another.
+pub fn changed_lines(reference: &str, text: &str) -> Vec<(String, bool)> {
+    TextDiff::from_lines(reference, text)
+        .iter_all_changes()
+        .filter(|change| change.tag() != ChangeTag::Delete)
+        .map(|change| {
+            let text = change.value().trim_end_matches(['\n', '\r']).to_string();
+            (text, change.tag() == ChangeTag::Insert)
+        })
+        .collect()
+}
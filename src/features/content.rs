@@ -1,4 +1,5 @@
 use crate::error::{EchomindError, Result};
+use crate::features::templating::{self, CompiledTemplate, VariableUsage};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs;
@@ -35,6 +36,122 @@ pub enum VariableType {
     Date,
     File,
     Select(Vec<String>),
+    /// A JSON array or comma-separated values, for `{% for %}` loops
+    List,
+}
+
+impl VariableType {
+    /// Parses a type name as given on the command line (`number`, `select`, ...).
+    pub fn parse(name: &str, choices: &[String]) -> Result<Self> {
+        match name.to_lowercase().as_str() {
+            "string" | "text" => Ok(VariableType::String),
+            "number" => Ok(VariableType::Number),
+            "boolean" | "bool" => Ok(VariableType::Boolean),
+            "date" => Ok(VariableType::Date),
+            "file" => Ok(VariableType::File),
+            "list" => Ok(VariableType::List),
+            "select" | "choice" | "enum" if !choices.is_empty() => Ok(VariableType::Select(choices.to_vec())),
+            "select" | "choice" | "enum" => Err(EchomindError::Other("A select variable needs --choices".to_string())),
+            other => Err(EchomindError::Other(format!(
+                "Unknown variable type '{}' (string, number, boolean, date, file, list, select)",
+                other
+            ))),
+        }
+    }
+}
+
+impl Template {
+    /// Renders the template without recording a use.
+    pub fn render(&self, variables: &HashMap<String, String>) -> Result<String> {
+        // Check required variables
+        if let Some(var) = ContentManager::missing_variables(self, variables).first() {
+            return Err(EchomindError::Other(format!("Required variable '{}' not provided", var.name)));
+        }
+
+        // Typed values for declared variables; anything else passes as text
+        let mut context: HashMap<String, serde_json::Value> = variables
+            .iter()
+            .map(|(name, value)| (name.clone(), serde_json::Value::String(value.clone())))
+            .collect();
+        for var in &self.variables {
+            if let Some(raw) = variables.get(&var.name).or(var.default_value.as_ref()) {
+                context.insert(var.name.clone(), var.parse_value(raw)?);
+            }
+        }
+
+        templating::render(&self.content, &context)
+    }
+}
+
+impl TemplateVariable {
+    /// Converts a raw value to this variable's type, with an error that says
+    /// what was expected.
+    pub fn parse_value(&self, raw: &str) -> Result<serde_json::Value> {
+        let invalid = |expected: String| {
+            EchomindError::Other(format!("Variable '{}' expects {}, got '{}'", self.name, expected, raw))
+        };
+
+        match &self.variable_type {
+            VariableType::String => Ok(serde_json::Value::String(raw.to_string())),
+            VariableType::Number => {
+                let trimmed = raw.trim();
+                if let Ok(int) = trimmed.parse::<i64>() {
+                    return Ok(serde_json::json!(int));
+                }
+                trimmed
+                    .parse::<f64>()
+                    .ok()
+                    .and_then(serde_json::Number::from_f64)
+                    .map(serde_json::Value::Number)
+                    .ok_or_else(|| invalid("a number".to_string()))
+            }
+            VariableType::Boolean => match raw.trim().to_lowercase().as_str() {
+                "true" | "yes" | "y" | "1" | "on" => Ok(serde_json::Value::Bool(true)),
+                "false" | "no" | "n" | "0" | "off" => Ok(serde_json::Value::Bool(false)),
+                _ => Err(invalid("true or false".to_string())),
+            },
+            VariableType::Date => {
+                let trimmed = raw.trim();
+                let valid = chrono::NaiveDate::parse_from_str(trimmed, "%Y-%m-%d").is_ok()
+                    || chrono::DateTime::parse_from_rfc3339(trimmed).is_ok();
+                if valid {
+                    Ok(serde_json::Value::String(trimmed.to_string()))
+                } else {
+                    Err(invalid("a date (YYYY-MM-DD)".to_string()))
+                }
+            }
+            VariableType::File => {
+                if Path::new(raw.trim()).is_file() {
+                    Ok(serde_json::Value::String(raw.trim().to_string()))
+                } else {
+                    Err(invalid("the path of an existing file".to_string()))
+                }
+            }
+            VariableType::Select(choices) => {
+                if choices.iter().any(|c| c == raw.trim()) {
+                    Ok(serde_json::Value::String(raw.trim().to_string()))
+                } else {
+                    Err(invalid(format!("one of: {}", choices.join(", "))))
+                }
+            }
+            VariableType::List => {
+                if raw.trim_start().starts_with('[') {
+                    match serde_json::from_str::<serde_json::Value>(raw) {
+                        Ok(list @ serde_json::Value::Array(_)) => Ok(list),
+                        _ => Err(invalid("a JSON array or comma-separated list".to_string())),
+                    }
+                } else {
+                    let items = raw
+                        .split(',')
+                        .map(str::trim)
+                        .filter(|item| !item.is_empty())
+                        .map(|item| serde_json::Value::String(item.to_string()))
+                        .collect();
+                    Ok(serde_json::Value::Array(items))
+                }
+            }
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -95,12 +212,16 @@ impl ContentManager {
         category: Option<&str>,
         tags: Vec<String>,
     ) -> Result<Template> {
+        if self.find_template(name).is_some() {
+            return Err(EchomindError::Other(format!("Template '{}' already exists", name)));
+        }
+
         let template = Template {
             id: uuid::Uuid::new_v4().to_string(),
             name: name.to_string(),
             description: description.map(|s| s.to_string()),
             content: content.to_string(),
-            variables: self.extract_variables(content),
+            variables: self.extract_variables(content)?,
             category: category.map(|s| s.to_string()),
             tags,
             created_at: chrono::Utc::now(),
@@ -122,6 +243,10 @@ impl ContentManager {
         category: Option<&str>,
         language: Option<&str>,
     ) -> Result<Snippet> {
+        if self.find_snippet(name).is_some() {
+            return Err(EchomindError::Other(format!("Snippet '{}' already exists", name)));
+        }
+
         let snippet = Snippet {
             id: uuid::Uuid::new_v4().to_string(),
             name: name.to_string(),
@@ -145,27 +270,9 @@ impl ContentManager {
         template_id: &str,
         variables: &HashMap<String, String>,
     ) -> Result<String> {
-        let template = self.library.templates.get(template_id)
+        let rendered = self.library.templates.get(template_id)
             .ok_or_else(|| EchomindError::Other(format!("Template {} not found", template_id)))?
-            .clone();
-        
-        // Check required variables
-        for var in &template.variables {
-            if var.required && !variables.contains_key(&var.name) {
-                return Err(EchomindError::Other(format!("Required variable '{}' not provided", var.name)));
-            }
-        }
-        
-        let mut rendered = template.content;
-        
-        // Replace variables
-        for var in &template.variables {
-            let value = variables.get(&var.name)
-                .or(var.default_value.as_ref())
-                .cloned()
-                .unwrap_or_default();
-            rendered = rendered.replace(&format!("{{{}}}", var.name), &value);
-        }
+            .render(variables)?;
         
         // Update usage count
         if let Some(t) = self.library.templates.get_mut(template_id) {
@@ -177,6 +284,16 @@ impl ContentManager {
         Ok(rendered)
     }
 
+    /// Required variables of `template` that have neither a value in
+    /// `variables` nor a default.
+    pub fn missing_variables<'a>(template: &'a Template, variables: &HashMap<String, String>) -> Vec<&'a TemplateVariable> {
+        template
+            .variables
+            .iter()
+            .filter(|var| var.required && var.default_value.is_none() && !variables.contains_key(&var.name))
+            .collect()
+    }
+
     pub fn search_templates(&self, query: &str, category: Option<&str>, tags: Vec<String>) -> Vec<&Template> {
         self.library.templates.values()
             .filter(|template| {
@@ -223,9 +340,43 @@ impl ContentManager {
         self.library.snippets.get(snippet_id)
     }
 
+    /// Looks a template up by id or (case-insensitive) name.
+    pub fn find_template(&self, name_or_id: &str) -> Option<&Template> {
+        self.library.templates.get(name_or_id).or_else(|| {
+            self.library
+                .templates
+                .values()
+                .find(|t| t.name.eq_ignore_ascii_case(name_or_id))
+        })
+    }
+
+    /// Looks a snippet up by id or (case-insensitive) name.
+    pub fn find_snippet(&self, name_or_id: &str) -> Option<&Snippet> {
+        self.library.snippets.get(name_or_id).or_else(|| {
+            self.library
+                .snippets
+                .values()
+                .find(|s| s.name.eq_ignore_ascii_case(name_or_id))
+        })
+    }
+
+    /// Returns a snippet's content and counts the use.
+    pub fn use_snippet(&mut self, name_or_id: &str) -> Result<String> {
+        let id = self
+            .find_snippet(name_or_id)
+            .map(|s| s.id.clone())
+            .ok_or_else(|| EchomindError::Other(format!("Snippet {} not found", name_or_id)))?;
+
+        let snippet = self.library.snippets.get_mut(&id).unwrap();
+        snippet.usage_count += 1;
+        let content = snippet.content.clone();
+        self.save_library()?;
+        Ok(content)
+    }
+
     pub fn update_template(&mut self, template_id: &str, updates: TemplateUpdate) -> Result<()> {
         // Extract variables first if content is being updated
-        let variables = updates.content.as_ref().map(|content| self.extract_variables(content));
+        let variables = updates.content.as_ref().map(|content| self.extract_variables(content)).transpose()?;
 
         let template = self.library.templates.get_mut(template_id)
             .ok_or_else(|| EchomindError::Other(format!("Template {} not found", template_id)))?;
@@ -237,8 +388,15 @@ impl ContentManager {
             template.description = Some(description);
         }
         if let Some(content) = updates.content {
+            // Keep descriptions/defaults of variables that survive the edit
+            let mut variables = variables.unwrap();
+            for var in &mut variables {
+                if let Some(existing) = template.variables.iter().find(|v| v.name == var.name) {
+                    *var = existing.clone();
+                }
+            }
             template.content = content;
-            template.variables = variables.unwrap();
+            template.variables = variables;
         }
         if let Some(category) = updates.category {
             template.category = Some(category);
@@ -246,10 +404,33 @@ impl ContentManager {
         if let Some(tags) = updates.tags {
             template.tags = tags;
         }
+        template.updated_at = chrono::Utc::now();
 
         self.save_library()
     }
 
+    /// Replaces the declaration of one of a template's variables (type,
+    /// description, default, required).
+    pub fn set_variable(&mut self, template_id: &str, variable: TemplateVariable) -> Result<()> {
+        if let Some(default) = &variable.default_value {
+            variable.parse_value(default)?;
+        }
+
+        let template = self.library.templates.get_mut(template_id)
+            .ok_or_else(|| EchomindError::Other(format!("Template {} not found", template_id)))?;
+        let slot = template
+            .variables
+            .iter_mut()
+            .find(|v| v.name == variable.name)
+            .ok_or_else(|| {
+                EchomindError::Other(format!("Template '{}' has no variable '{}'", template.name, variable.name))
+            })?;
+
+        *slot = variable;
+        template.updated_at = chrono::Utc::now();
+        self.save_library()
+    }
+
     pub fn update_snippet(&mut self, snippet_id: &str, updates: SnippetUpdate) -> Result<()> {
         let snippet = self.library.snippets.get_mut(snippet_id)
             .ok_or_else(|| EchomindError::Other(format!("Snippet {} not found", snippet_id)))?;
@@ -314,7 +495,7 @@ impl ContentManager {
 
     pub fn get_popular_templates(&self, limit: usize) -> Vec<&Template> {
         let mut templates: Vec<_> = self.library.templates.values().collect();
-        templates.sort_by(|a, b| b.usage_count.cmp(&a.usage_count));
+        templates.sort_by_key(|b| std::cmp::Reverse(b.usage_count));
         templates.into_iter().take(limit).collect()
     }
 
@@ -367,22 +548,24 @@ impl ContentManager {
         }
     }
 
-    fn extract_variables(&self, content: &str) -> Vec<TemplateVariable> {
-        let mut variables = Vec::new();
-        let re = regex::Regex::new(r"\{([^}]+)\}").unwrap();
-        
-        for cap in re.captures_iter(content) {
-            let var_name = cap.get(1).unwrap().as_str();
-            variables.push(TemplateVariable {
-                name: var_name.to_string(),
+    fn extract_variables(&self, content: &str) -> Result<Vec<TemplateVariable>> {
+        let compiled = CompiledTemplate::compile(content)?;
+
+        Ok(compiled
+            .variables()
+            .into_iter()
+            .map(|reference| TemplateVariable {
+                required: reference.default.is_none() && reference.usage != VariableUsage::Condition,
+                variable_type: match reference.usage {
+                    VariableUsage::List => VariableType::List,
+                    VariableUsage::File => VariableType::File,
+                    _ => VariableType::String,
+                },
+                name: reference.name,
                 description: None,
-                default_value: None,
-                required: true,
-                variable_type: VariableType::String,
-            });
-        }
-        
-        variables
+                default_value: reference.default,
+            })
+            .collect())
     }
 
     fn load_library(&mut self) -> Result<()> {
@@ -397,6 +580,11 @@ impl ContentManager {
     }
 
     fn save_library(&self) -> Result<()> {
+        if let Some(parent) = Path::new(&self.library_path).parent().filter(|p| !p.as_os_str().is_empty()) {
+            fs::create_dir_all(parent)
+                .map_err(|e| EchomindError::FileError(format!("Failed to create library directory: {}", e)))?;
+        }
+
         let json = serde_json::to_string_pretty(&self.library)
             .map_err(|e| EchomindError::ParseError(format!("Failed to serialize library: {}", e)))?;
         
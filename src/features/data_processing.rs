@@ -1,9 +1,15 @@
 use crate::error::{EchomindError, Result};
-use calamine::Reader;
+use crate::features::charts::{Chart, ChartData};
+use crate::features::streaming::StreamingAnalyzer;
+use crate::features::query::{
+    self, display_value, number_value, AggregateFunc, BinaryOp, Expr, OrderBy, Query, QueryResult, SelectItem,
+};
+use calamine::{Data, Range, Reader};
 use csv::{ReaderBuilder, WriterBuilder};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::fs;
+use std::io::{BufRead, BufReader, Write};
 
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -17,6 +23,87 @@ pub struct DataAnalysis {
     pub sample_data: Vec<HashMap<String, serde_json::Value>>,
 }
 
+impl DataAnalysis {
+    /// A compact, model-friendly description: schema, per-column statistics
+    /// and the first `sample_rows` rows.
+    pub fn describe(&self, source: &str, sample_rows: usize) -> String {
+        let mut out = format!(
+            "File: {} ({}, {} rows, {} columns)\n\nColumns:",
+            source, self.file_type, self.total_rows, self.total_columns
+        );
+        for column in &self.column_names {
+            let column_type = self.column_types.get(column).map(String::as_str).unwrap_or("unknown");
+            out.push_str(&format!("\n- {}: {}", column, column_type));
+            let Some(stats) = self.summary_stats.get(column) else { continue };
+            let about = if stats.approximate { "~" } else { "" };
+            out.push_str(&format!(", {}{} distinct", about, stats.unique_count));
+            if stats.null_count > 0 {
+                out.push_str(&format!(", {} empty", stats.null_count));
+            }
+            if let (Some(min), Some(max)) = (&stats.min_value, &stats.max_value) {
+                let show = |value: &serde_json::Value| match value {
+                    serde_json::Value::Number(n) => display_value(&number_value(n.as_f64().unwrap_or_default())),
+                    other => truncate(&display_value(other), 40),
+                };
+                out.push_str(&format!(", min {}, max {}", show(min), show(max)));
+            }
+            if let Some(mean) = stats.mean_value {
+                out.push_str(&format!(", mean {}", display_value(&number_value(mean))));
+            }
+            if let Some(std_dev) = stats.std_dev.filter(|sd| *sd > 0.0) {
+                out.push_str(&format!(", sd {}", display_value(&number_value(std_dev))));
+            }
+            if let (Some(p25), Some(median), Some(p75)) = (stats.p25_value, stats.median_value, stats.p75_value) {
+                out.push_str(&format!(
+                    ", quartiles {}{} / {} / {}",
+                    about,
+                    display_value(&number_value(p25)),
+                    display_value(&number_value(median)),
+                    display_value(&number_value(p75))
+                ));
+            } else if let Some(median) = stats.median_value {
+                out.push_str(&format!(", median {}", display_value(&number_value(median))));
+            }
+            let top_values = match &stats.top_values {
+                values if values.is_empty() => stats.most_common.as_slice(),
+                values => values.as_slice(),
+            };
+            let frequent: Vec<String> = top_values
+                .iter()
+                .take(3)
+                .filter(|(_, count)| *count > 1)
+                .map(|(value, count)| format!("{:?} ({}{} rows)", truncate(&display_value(value), 40), about, count))
+                .collect();
+            if !frequent.is_empty() {
+                out.push_str(&format!(", most common {}", frequent.join(", ")));
+            }
+        }
+
+        let shown = self.sample_data.len().min(sample_rows);
+        if shown > 0 {
+            out.push_str(&format!("\n\nSample rows ({} of {}):\n{}", shown, self.total_rows, self.column_names.join(" | ")));
+            for record in self.sample_data.iter().take(shown) {
+                let cells: Vec<String> = self
+                    .column_names
+                    .iter()
+                    .map(|column| truncate(&record.get(column).map(display_value).unwrap_or_default(), 60))
+                    .collect();
+                out.push('\n');
+                out.push_str(&cells.join(" | "));
+            }
+        }
+        out
+    }
+}
+
+fn truncate(text: &str, max_chars: usize) -> String {
+    if text.chars().count() <= max_chars {
+        text.to_string()
+    } else {
+        format!("{}…", text.chars().take(max_chars).collect::<String>())
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ColumnStats {
     pub null_count: usize,
@@ -26,6 +113,20 @@ pub struct ColumnStats {
     pub mean_value: Option<f64>,
     pub median_value: Option<f64>,
     pub most_common: Option<(serde_json::Value, usize)>,
+    /// Sample standard deviation of the numeric values
+    #[serde(default)]
+    pub std_dev: Option<f64>,
+    #[serde(default)]
+    pub p25_value: Option<f64>,
+    #[serde(default)]
+    pub p75_value: Option<f64>,
+    /// Most frequent values, most common first
+    #[serde(default)]
+    pub top_values: Vec<(serde_json::Value, usize)>,
+    /// Set when the distinct count, quantiles or top values are estimates
+    /// (see `features::streaming`)
+    #[serde(default)]
+    pub approximate: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -49,327 +150,622 @@ pub enum ChartType {
     Heatmap,
 }
 
-pub struct DataProcessor {
-    cache: HashMap<String, DataAnalysis>,
-}
-
-impl DataProcessor {
-    pub fn new() -> Self {
-        Self {
-            cache: HashMap::new(),
+impl ChartType {
+    pub fn parse(text: &str) -> Result<Self> {
+        match text.trim().to_lowercase().as_str() {
+            "bar" => Ok(ChartType::Bar),
+            "line" => Ok(ChartType::Line),
+            "scatter" => Ok(ChartType::Scatter),
+            "pie" => Ok(ChartType::Pie),
+            "histogram" | "hist" => Ok(ChartType::Histogram),
+            "heatmap" => Ok(ChartType::Heatmap),
+            other => Err(EchomindError::Other(format!(
+                "Unknown chart type '{}' (use bar, line, scatter, pie, histogram or heatmap)",
+                other
+            ))),
         }
     }
+}
 
-    pub fn process_csv(&mut self, file_path: &str) -> Result<DataAnalysis> {
-        if let Some(cached) = self.cache.get(file_path) {
-            return Ok(cached.clone());
-        }
-
-        let file = fs::File::open(file_path)
-            .map_err(|e| EchomindError::FileError(format!("Failed to open CSV file: {}", e)))?;
+/// One row of a data file, keyed by column name.
+pub type Record = HashMap<String, serde_json::Value>;
 
-        let mut rdr = ReaderBuilder::new().from_reader(file);
-        let headers = rdr.headers()
-            .map_err(|e| EchomindError::Other(format!("Failed to read CSV headers: {}", e)))?;
+/// A whole data file loaded into memory, for computing over every row rather
+/// than the sample kept in `DataAnalysis`.
+#[derive(Debug, Clone)]
+pub struct Dataset {
+    pub file_type: String,
+    pub column_names: Vec<String>,
+    pub records: Vec<Record>,
+}
 
-        let column_names: Vec<String> = headers.iter().map(|s| s.to_string()).collect();
-        let mut records: Vec<HashMap<String, serde_json::Value>> = Vec::new();
-        let mut column_data: HashMap<String, Vec<serde_json::Value>> = HashMap::new();
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DataFormat {
+    Csv,
+    Json,
+    Excel,
+}
 
-        // Initialize column data
-        for name in &column_names {
-            column_data.insert(name.clone(), Vec::new());
-        }
+/// A grouped aggregation over a dataset, as requested by the model in data
+/// Q&A mode:
+///
+/// ```json
+/// {"group_by": ["region"], "metrics": ["sum(revenue)", "count"],
+///  "filter": [{"column": "year", "op": ">=", "value": 2024}],
+///  "order_by": "sum(revenue)", "descending": true, "limit": 5}
+/// ```
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AggregateRequest {
+    #[serde(default)]
+    pub group_by: Vec<String>,
+    #[serde(default)]
+    pub metrics: Vec<Metric>,
+    #[serde(default)]
+    pub filter: Vec<Filter>,
+    #[serde(default)]
+    pub order_by: Option<String>,
+    #[serde(default)]
+    pub descending: bool,
+    #[serde(default)]
+    pub limit: Option<usize>,
+}
 
-        for result in rdr.records() {
-            let record = result
-                .map_err(|e| EchomindError::Other(format!("Failed to read CSV record: {}", e)))?;
+/// An aggregate such as `sum(revenue)`, or `count` for the number of rows.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct Metric {
+    pub op: AggregateOp,
+    pub column: Option<String>,
+}
 
-            let mut record_map: HashMap<String, serde_json::Value> = HashMap::new();
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AggregateOp {
+    Count,
+    Sum,
+    Avg,
+    Min,
+    Max,
+    Median,
+    CountDistinct,
+}
 
-            for (i, field) in record.iter().enumerate() {
-                let column_name = &column_names[i];
-                let value = self.parse_value(field);
-                record_map.insert(column_name.clone(), value.clone());
-                column_data.get_mut(column_name).unwrap().push(value);
-            }
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Filter {
+    pub column: String,
+    #[serde(default = "default_filter_op")]
+    pub op: String,
+    #[serde(default)]
+    pub value: serde_json::Value,
+}
 
-            records.push(record_map);
-        }
+fn default_filter_op() -> String {
+    "=".to_string()
+}
 
-        let total_rows = records.len();
-        let total_columns = column_names.len();
+/// Output formats for `DataProcessor::export_data`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    Csv,
+    Tsv,
+    Json,
+    JsonLines,
+    Markdown,
+}
 
-        // Calculate column statistics
-        let mut summary_stats = HashMap::new();
-        let mut column_types = HashMap::new();
+impl ExportFormat {
+    pub fn parse(text: &str) -> Result<Self> {
+        match text.trim().to_lowercase().as_str() {
+            "csv" => Ok(ExportFormat::Csv),
+            "tsv" => Ok(ExportFormat::Tsv),
+            "json" => Ok(ExportFormat::Json),
+            "jsonl" | "ndjson" => Ok(ExportFormat::JsonLines),
+            "markdown" | "md" => Ok(ExportFormat::Markdown),
+            other => Err(EchomindError::Other(format!(
+                "Unsupported export format '{}' (expected csv, tsv, json, jsonl or markdown)",
+                other
+            ))),
+        }
+    }
 
-        for (column_name, values) in &column_data {
-            let stats = self.calculate_column_stats(values);
-            let data_type = self.infer_column_type(values);
+    /// The format implied by a file extension, if any.
+    pub fn from_path(path: &str) -> Option<Self> {
+        let extension = std::path::Path::new(path).extension()?.to_str()?;
+        Self::parse(extension).ok()
+    }
 
-            summary_stats.insert(column_name.clone(), stats);
-            column_types.insert(column_name.clone(), data_type);
+    pub fn name(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "CSV",
+            ExportFormat::Tsv => "TSV",
+            ExportFormat::Json => "JSON",
+            ExportFormat::JsonLines => "JSON Lines",
+            ExportFormat::Markdown => "Markdown",
         }
+    }
+}
 
-        let analysis = DataAnalysis {
-            file_type: "CSV".to_string(),
-            total_rows,
-            total_columns,
-            column_names: column_names.clone(),
-            column_types,
-            summary_stats,
-            sample_data: records.into_iter().take(10).collect(),
-        };
-
-        self.cache.insert(file_path.to_string(), analysis.clone());
-        Ok(analysis)
+impl DataFormat {
+    pub fn name(&self) -> &'static str {
+        match self {
+            DataFormat::Csv => "CSV",
+            DataFormat::Json => "JSON",
+            DataFormat::Excel => "Excel",
+        }
     }
+}
 
-    pub fn process_json(&mut self, file_path: &str) -> Result<DataAnalysis> {
-        if let Some(cached) = self.cache.get(file_path) {
-            return Ok(cached.clone());
+impl AggregateOp {
+    fn name(&self) -> &'static str {
+        match self {
+            AggregateOp::Count => "count",
+            AggregateOp::Sum => "sum",
+            AggregateOp::Avg => "avg",
+            AggregateOp::Min => "min",
+            AggregateOp::Max => "max",
+            AggregateOp::Median => "median",
+            AggregateOp::CountDistinct => "count_distinct",
         }
+    }
+}
 
-        let contents = fs::read_to_string(file_path)
-            .map_err(|e| EchomindError::FileError(format!("Failed to read JSON file: {}", e)))?;
-        
-        let json_value: serde_json::Value = serde_json::from_str(&contents)
-            .map_err(|e| EchomindError::ParseError(format!("Failed to parse JSON: {}", e)))?;
-        
-        let (records, column_names) = match json_value {
-            serde_json::Value::Array(arr) => {
-                let mut all_keys = std::collections::HashSet::new();
-                for item in &arr {
-                    if let serde_json::Value::Object(obj) = item {
-                        for key in obj.keys() {
-                            all_keys.insert(key.clone());
-                        }
-                    }
-                }
-                let keys: Vec<String> = all_keys.into_iter().collect();
-                
-                let records: Vec<HashMap<String, serde_json::Value>> = arr.into_iter()
-                    .filter_map(|item| {
-                        if let serde_json::Value::Object(obj) = item {
-                            Some(obj.into_iter().collect())
-                        } else {
-                            None
-                        }
-                    })
-                    .collect();
-                
-                (records, keys)
+impl Metric {
+    /// Parses `op(column)`, `op(*)` or a bare `count`.
+    pub fn parse(text: &str) -> Result<Self> {
+        let text = text.trim();
+        let (op, column) = match text.find('(') {
+            Some(open) if text.ends_with(')') => {
+                let column = text[open + 1..text.len() - 1].trim();
+                let column = (!column.is_empty() && column != "*").then(|| column.to_string());
+                (text[..open].trim(), column)
             }
-            serde_json::Value::Object(obj) => {
-                let record: std::collections::HashMap<String, serde_json::Value> = obj.into_iter().collect();
-                let keys = record.keys().cloned().collect();
-                (vec![record], keys)
-            }
-            _ => {
-                return Err(EchomindError::Other("JSON must be an object or array of objects".to_string()));
+            _ => (text, None),
+        };
+
+        let op = match op.to_lowercase().as_str() {
+            "count" => AggregateOp::Count,
+            "sum" => AggregateOp::Sum,
+            "avg" | "mean" | "average" => AggregateOp::Avg,
+            "min" => AggregateOp::Min,
+            "max" => AggregateOp::Max,
+            "median" => AggregateOp::Median,
+            "count_distinct" | "distinct" => AggregateOp::CountDistinct,
+            other => {
+                return Err(EchomindError::Other(format!(
+                    "Unknown aggregate '{}' (count, sum, avg, min, max, median, count_distinct)",
+                    other
+                )))
             }
         };
-        
-        let total_rows = records.len();
-        let total_columns = column_names.len();
-        
-        // Calculate column statistics
-        let mut summary_stats = HashMap::new();
-        let mut column_types = HashMap::new();
-        let mut column_data: HashMap<String, Vec<serde_json::Value>> = HashMap::new();
-        
-        // Initialize column data
-        for name in &column_names {
-            column_data.insert(name.clone(), Vec::new());
+        if column.is_none() && op != AggregateOp::Count {
+            return Err(EchomindError::Other(format!("Aggregate '{}' needs a column, e.g. {}(price)", text, op.name())));
         }
-        
-        for record in &records {
-            for column_name in &column_names {
-                let value = record.get(column_name).cloned().unwrap_or(serde_json::Value::Null);
-                column_data.get_mut(column_name).unwrap().push(value);
-            }
+        Ok(Self { op, column })
+    }
+}
+
+impl std::fmt::Display for Metric {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.column {
+            Some(column) => write!(f, "{}({})", self.op.name(), column),
+            None => write!(f, "{}", self.op.name()),
         }
-        
-        for (column_name, values) in &column_data {
-            let stats = self.calculate_column_stats(values);
-            let data_type = self.infer_column_type(values);
-            
-            summary_stats.insert(column_name.clone(), stats);
-            column_types.insert(column_name.clone(), data_type);
+    }
+}
+
+impl TryFrom<String> for Metric {
+    type Error = EchomindError;
+
+    fn try_from(text: String) -> Result<Self> {
+        Metric::parse(&text)
+    }
+}
+
+impl From<Metric> for String {
+    fn from(metric: Metric) -> Self {
+        metric.to_string()
+    }
+}
+
+pub struct DataProcessor {
+    cache: HashMap<String, DataAnalysis>,
+    sheet: Option<String>,
+}
+
+/// Rows searched for the header of a worksheet, past any title rows.
+const HEADER_SCAN_ROWS: usize = 20;
+
+impl DataProcessor {
+    pub fn new() -> Self {
+        Self {
+            cache: HashMap::new(),
+            sheet: None,
         }
-        
-        let analysis = DataAnalysis {
-            file_type: "JSON".to_string(),
-            total_rows,
-            total_columns,
-            column_names: column_names.clone(),
-            column_types,
-            summary_stats,
-            sample_data: records.into_iter().take(10).collect(),
-        };
-        
-        self.cache.insert(file_path.to_string(), analysis.clone());
-        Ok(analysis)
+    }
+
+    /// Selects the worksheet read from Excel and ODS files, by name or by
+    /// position counting from 1. Without one, the first sheet with data is
+    /// read.
+    pub fn set_sheet(&mut self, sheet: Option<String>) {
+        self.sheet = sheet;
+        self.cache.clear();
+    }
+
+    pub fn process_csv(&mut self, file_path: &str) -> Result<DataAnalysis> {
+        self.process(file_path, DataFormat::Csv)
+    }
+
+    pub fn process_json(&mut self, file_path: &str) -> Result<DataAnalysis> {
+        self.process(file_path, DataFormat::Json)
     }
 
     pub fn process_excel(&mut self, file_path: &str) -> Result<DataAnalysis> {
+        self.process(file_path, DataFormat::Excel)
+    }
+
+    fn process(&mut self, file_path: &str, format: DataFormat) -> Result<DataAnalysis> {
         if let Some(cached) = self.cache.get(file_path) {
             return Ok(cached.clone());
         }
 
-        let mut excel_data: Vec<Vec<String>> = Vec::new();
-        let mut workbook: calamine::Sheets<std::io::BufReader<std::fs::File>> = calamine::open_workbook(file_path)
-            .map_err(|e| EchomindError::Other(format!("Failed to open Excel file: {}", e)))?;
+        let analysis = self.analyze_file(file_path, format)?;
+        self.cache.insert(file_path.to_string(), analysis.clone());
+        Ok(analysis)
+    }
 
-        if let Some(Ok(range)) = workbook.worksheet_range_at(0) {
-            for row in range.rows() {
-                let row_data: Vec<String> = row.iter().map(|cell| cell.to_string()).collect();
-                excel_data.push(row_data);
+    /// Reads every record of a CSV, JSON or Excel file into memory.
+    pub fn load_dataset(&self, file_path: &str, format: DataFormat) -> Result<Dataset> {
+        let mut records = Vec::new();
+        let (file_type, column_names) = self.read(file_path, format, |_, record| records.push(record))?;
+
+        Ok(Dataset {
+            file_type,
+            column_names,
+            records,
+        })
+    }
+
+    /// Computes the schema, per-column statistics and a sample of a file in
+    /// one pass, without holding its rows in memory.
+    pub fn analyze_file(&self, file_path: &str, format: DataFormat) -> Result<DataAnalysis> {
+        let mut analyzer = StreamingAnalyzer::new();
+        let (file_type, _) = self.read(file_path, format, |columns, record| analyzer.observe(columns, record))?;
+        Ok(analyzer.finish(&file_type))
+    }
+
+    /// Analyzes the selected sheet of an Excel or ODS file or, with no sheet
+    /// selected, every sheet that has data, one `DataAnalysis` per sheet.
+    pub fn analyze_sheets(&self, file_path: &str) -> Result<Vec<DataAnalysis>> {
+        let mut workbook = open_workbook(file_path)?;
+        let names = workbook.sheet_names();
+        let selected = match &self.sheet {
+            Some(selector) => vec![sheet_index(&names, selector, file_path)?],
+            None => (0..names.len()).collect(),
+        };
+
+        let mut analyses = Vec::new();
+        for index in selected {
+            let range = worksheet(&mut workbook, index, &names[index])?;
+            if range.is_empty() && self.sheet.is_none() {
+                continue;
             }
+            let mut analyzer = StreamingAnalyzer::new();
+            read_sheet(&range, |columns, record| analyzer.observe(columns, record));
+            analyses.push(analyzer.finish(&sheet_label(file_path, &names[index])));
         }
-
-        if excel_data.is_empty() {
-            return Err(EchomindError::Other("Excel file is empty or could not be read".to_string()));
+        if analyses.is_empty() {
+            return Err(EchomindError::Other(format!("{} has no sheets with data", file_path)));
         }
+        Ok(analyses)
+    }
 
-        let column_names = excel_data.first().unwrap().clone();
-        let data_rows = &excel_data[1..];
+    /// The worksheet names of an Excel or ODS file, in workbook order.
+    pub fn sheet_names(&self, file_path: &str) -> Result<Vec<String>> {
+        Ok(open_workbook(file_path)?.sheet_names())
+    }
 
-        let mut records: Vec<HashMap<String, serde_json::Value>> = Vec::new();
-        let mut column_data: HashMap<String, Vec<serde_json::Value>> = HashMap::new();
+    /// Computes the schema, per-column statistics and a sample of `dataset`.
+    pub fn analyze(&self, dataset: &Dataset) -> DataAnalysis {
+        let mut analyzer = StreamingAnalyzer::new();
+        for record in &dataset.records {
+            analyzer.observe(&dataset.column_names, record.clone());
+        }
+        analyzer.finish(&dataset.file_type)
+    }
+
+    /// Calls `visit` with each record in turn, along with the columns seen so
+    /// far, and returns all column names. CSV, JSON arrays and JSON Lines
+    /// are read incrementally, optionally gzip-compressed; an Excel sheet is
+    /// loaded whole by calamine but converted row by row, keeping its typed
+    /// cells.
+    pub fn read_records(
+        &self,
+        file_path: &str,
+        format: DataFormat,
+        visit: impl FnMut(&[String], Record),
+    ) -> Result<Vec<String>> {
+        Ok(self.read(file_path, format, visit)?.1)
+    }
 
-        // Initialize column data
-        for name in &column_names {
-            column_data.insert(name.clone(), Vec::new());
+    // Like `read_records`, but also returns the file type to report, which
+    // names the sheet for workbooks.
+    fn read(
+        &self,
+        file_path: &str,
+        format: DataFormat,
+        visit: impl FnMut(&[String], Record),
+    ) -> Result<(String, Vec<String>)> {
+        match format {
+            DataFormat::Csv => Ok((format.name().to_string(), self.read_csv(file_path, visit)?)),
+            DataFormat::Json => Ok((format.name().to_string(), self.read_json(file_path, visit)?)),
+            DataFormat::Excel => self.read_excel(file_path, visit),
         }
+    }
 
-        for row in data_rows {
-            let mut record_map: HashMap<String, serde_json::Value> = HashMap::new();
+    /// Filters, groups and aggregates every record of `dataset`, by running
+    /// the equivalent query.
+    pub fn aggregate(&self, dataset: &Dataset, request: &AggregateRequest) -> Result<QueryResult> {
+        self.aggregate_query(request)?.execute(dataset)
+    }
 
-            for (i, cell) in row.iter().enumerate() {
-                if i < column_names.len() {
-                    let column_name = &column_names[i];
-                    let value = self.parse_value(cell);
-                    record_map.insert(column_name.clone(), value.clone());
-                    column_data.get_mut(column_name).unwrap().push(value);
-                }
-            }
+    /// Translates an aggregate request into a query.
+    pub fn aggregate_query(&self, request: &AggregateRequest) -> Result<Query> {
+        let metrics = if request.metrics.is_empty() {
+            vec![Metric { op: AggregateOp::Count, column: None }]
+        } else {
+            request.metrics.clone()
+        };
 
-            records.push(record_map);
+        let mut select: Vec<SelectItem> = request
+            .group_by
+            .iter()
+            .map(|column| SelectItem { expr: Expr::Column(column.clone()), alias: None })
+            .collect();
+        for metric in &metrics {
+            let (func, distinct) = match metric.op {
+                AggregateOp::Count => (AggregateFunc::Count, false),
+                AggregateOp::CountDistinct => (AggregateFunc::Count, true),
+                AggregateOp::Sum => (AggregateFunc::Sum, false),
+                AggregateOp::Avg => (AggregateFunc::Avg, false),
+                AggregateOp::Min => (AggregateFunc::Min, false),
+                AggregateOp::Max => (AggregateFunc::Max, false),
+                AggregateOp::Median => (AggregateFunc::Median, false),
+            };
+            select.push(SelectItem {
+                expr: Expr::Aggregate {
+                    func,
+                    arg: metric.column.clone().map(|column| Box::new(Expr::Column(column))),
+                    distinct,
+                },
+                alias: Some(metric.to_string()),
+            });
         }
 
-        let total_rows = records.len();
-        let total_columns = column_names.len();
+        let mut filter: Option<Expr> = None;
+        for condition in &request.filter {
+            let expr = filter_expr(condition)?;
+            filter = Some(match filter {
+                Some(previous) => Expr::Binary(BinaryOp::And, Box::new(previous), Box::new(expr)),
+                None => expr,
+            });
+        }
 
-        // Calculate column statistics
-        let mut summary_stats = HashMap::new();
-        let mut column_types = HashMap::new();
+        let mut order_by = Vec::new();
+        if let Some(wanted) = &request.order_by {
+            let normalized = Metric::parse(wanted).map(|m| m.to_string()).unwrap_or_else(|_| wanted.clone());
+            let position = request
+                .group_by
+                .iter()
+                .cloned()
+                .chain(metrics.iter().map(|m| m.to_string()))
+                .position(|column| &column == wanted || column == normalized)
+                .ok_or_else(|| {
+                    EchomindError::Other(format!(
+                        "Cannot order by '{}': it is not a group_by column or metric",
+                        wanted
+                    ))
+                })?;
+            order_by.push(OrderBy {
+                expr: Expr::Literal(serde_json::json!(position + 1)),
+                descending: request.descending,
+            });
+        }
 
-        for (column_name, values) in &column_data {
-            let stats = self.calculate_column_stats(values);
-            let data_type = self.infer_column_type(values);
+        Ok(Query {
+            distinct: false,
+            select,
+            filter,
+            group_by: request.group_by.iter().map(|column| Expr::Column(column.clone())).collect(),
+            having: None,
+            order_by,
+            limit: request.limit,
+            offset: 0,
+        })
+    }
 
-            summary_stats.insert(column_name.clone(), stats);
-            column_types.insert(column_name.clone(), data_type);
-        }
+    fn read_csv(&self, file_path: &str, mut visit: impl FnMut(&[String], Record)) -> Result<Vec<String>> {
+        let mut rdr = ReaderBuilder::new()
+            .flexible(true)
+            .from_reader(open_input(file_path, "CSV")?);
+        let headers = rdr.headers()
+            .map_err(|e| EchomindError::Other(format!("Failed to read CSV headers: {}", e)))?;
 
-        let analysis = DataAnalysis {
-            file_type: "Excel".to_string(),
-            total_rows,
-            total_columns,
-            column_names: column_names.clone(),
-            column_types,
-            summary_stats,
-            sample_data: records.into_iter().take(10).collect(),
-        };
+        let column_names: Vec<String> = headers.iter().map(|s| s.to_string()).collect();
+        let mut record = csv::StringRecord::new();
+        while rdr
+            .read_record(&mut record)
+            .map_err(|e| EchomindError::Other(format!("Failed to read CSV record: {}", e)))?
+        {
+            let record_map = column_names
+                .iter()
+                .zip(record.iter())
+                .map(|(column_name, field)| (column_name.clone(), self.parse_value(field)))
+                .collect();
+            visit(&column_names, record_map);
+        }
 
-        self.cache.insert(file_path.to_string(), analysis.clone());
-        Ok(analysis)
+        Ok(column_names)
     }
 
-    pub fn generate_visualization(&self, analysis: &DataAnalysis, config: &VisualizationConfig) -> Result<String> {
-        // This would generate actual visualization data (e.g., SVG, HTML with Chart.js, etc.)
-        // For now, return a simple representation
-        
-        let chart_data = match config.chart_type {
-            ChartType::Bar => self.generate_bar_chart(analysis, config)?,
-            ChartType::Line => self.generate_line_chart(analysis, config)?,
-            ChartType::Scatter => self.generate_scatter_chart(analysis, config)?,
-            ChartType::Pie => self.generate_pie_chart(analysis, config)?,
-            ChartType::Histogram => self.generate_histogram(analysis, config)?,
-            ChartType::Heatmap => self.generate_heatmap(analysis, config)?,
+    /// Reads an array of objects, a single object, or JSON Lines (one object
+    /// per line), streaming the array elements or lines.
+    fn read_json(&self, file_path: &str, mut visit: impl FnMut(&[String], Record)) -> Result<Vec<String>> {
+        let mut reader = open_input(file_path, "JSON")?;
+        let first = loop {
+            let buffer = reader
+                .fill_buf()
+                .map_err(|e| EchomindError::FileError(format!("Failed to read JSON file: {}", e)))?;
+            match buffer.iter().position(|b| !b.is_ascii_whitespace()) {
+                Some(index) => {
+                    let first = buffer[index];
+                    reader.consume(index);
+                    break Some(first);
+                }
+                None if buffer.is_empty() => break None,
+                None => {
+                    let len = buffer.len();
+                    reader.consume(len);
+                }
+            }
         };
-        
-        Ok(chart_data)
+
+        // Columns in order of first appearance
+        let mut keys: Vec<String> = Vec::new();
+        let mut emit = |object: serde_json::Map<String, serde_json::Value>| {
+            for key in object.keys() {
+                if !keys.contains(key) {
+                    keys.push(key.clone());
+                }
+            }
+            visit(&keys, object.into_iter().collect());
+        };
+
+        match first {
+            Some(b'[') => {
+                let mut deserializer = serde_json::Deserializer::from_reader(reader);
+                serde::Deserializer::deserialize_seq(&mut deserializer, ObjectSeqVisitor(&mut emit))
+                    .map_err(|e| EchomindError::Other(format!("Failed to parse JSON: {}", e)))?;
+            }
+            Some(b'{') => {
+                for (index, item) in serde_json::Deserializer::from_reader(reader)
+                    .into_iter::<serde_json::Value>()
+                    .enumerate()
+                {
+                    match item.map_err(|e| EchomindError::Other(format!("Failed to parse JSON record {}: {}", index + 1, e)))? {
+                        serde_json::Value::Object(object) => emit(object),
+                        _ => return Err(EchomindError::Other(format!("JSON record {} is not an object", index + 1))),
+                    }
+                }
+            }
+            _ => return Err(EchomindError::Other("JSON must be an object, an array of objects or JSON Lines".to_string())),
+        }
+        Ok(keys)
     }
 
-    pub fn query_data(&self, analysis: &DataAnalysis, query: &str) -> Result<Vec<HashMap<String, serde_json::Value>>> {
-        // Simple query language implementation
-        // For now, just support basic filtering
-        
-        if query.starts_with("SELECT") {
-            // Very basic SQL-like parsing
-            if query.contains("WHERE") {
-                let parts: Vec<&str> = query.split("WHERE").collect();
-                if parts.len() == 2 {
-                    let where_clause = parts[1].trim();
-                    return self.filter_data(&analysis.sample_data, where_clause);
+    fn read_excel(&self, file_path: &str, visit: impl FnMut(&[String], Record)) -> Result<(String, Vec<String>)> {
+        let mut workbook = open_workbook(file_path)?;
+        let names = workbook.sheet_names();
+        let (index, range) = match &self.sheet {
+            Some(selector) => {
+                let index = sheet_index(&names, selector, file_path)?;
+                (index, worksheet(&mut workbook, index, &names[index])?)
+            }
+            None => {
+                let mut first = None;
+                for (index, name) in names.iter().enumerate() {
+                    let range = worksheet(&mut workbook, index, name)?;
+                    if !range.is_empty() {
+                        first = Some((index, range));
+                        break;
+                    }
                 }
+                first.ok_or_else(|| EchomindError::Other(format!("{} has no sheets with data", file_path)))?
             }
-            return Ok(analysis.sample_data.clone());
+        };
+
+        Ok((sheet_label(file_path, &names[index]), read_sheet(&range, visit)))
+    }
+
+    /// Computes the chart described by `config` from every record of
+    /// `dataset`; render it with `Chart::render_terminal`, `Chart::to_svg` or,
+    /// in the TUI, `Chart::render`.
+    pub fn generate_visualization(&self, dataset: &Dataset, config: &VisualizationConfig) -> Result<Chart> {
+        column_index(dataset, &config.x_column)?;
+        if let Some(y_column) = &config.y_column {
+            column_index(dataset, y_column)?;
         }
-        
-        // Default: return all sample data
-        Ok(analysis.sample_data.clone())
+
+        match config.chart_type {
+            ChartType::Bar => self.generate_bar_chart(dataset, config),
+            ChartType::Line => self.generate_line_chart(dataset, config),
+            ChartType::Scatter => self.generate_scatter_chart(dataset, config),
+            ChartType::Pie => self.generate_pie_chart(dataset, config),
+            ChartType::Histogram => self.generate_histogram(dataset, config),
+            ChartType::Heatmap => self.generate_heatmap(dataset, config),
+        }
+    }
+
+    /// Runs a SQL-like query (see `features::query`) over every record of
+    /// `dataset`.
+    pub fn query_data(&self, dataset: &Dataset, query: &str) -> Result<QueryResult> {
+        query::execute(dataset, query)
+    }
+
+    /// Writes every record of `dataset` to `output_path`, columns in
+    /// dataset order.
+    pub fn export_data(&self, dataset: &Dataset, format: ExportFormat, output_path: &str) -> Result<()> {
+        let file = fs::File::create(output_path)
+            .map_err(|e| EchomindError::FileError(format!("Failed to create output file: {}", e)))?;
+        let mut out = std::io::BufWriter::new(file);
+        self.write_data(dataset, format, &mut out)?;
+        out.flush()
+            .map_err(|e| EchomindError::FileError(format!("Failed to write {}: {}", output_path, e)))
     }
 
-    pub fn export_data(&self, data: &[HashMap<String, serde_json::Value>], format: &str, output_path: &str) -> Result<()> {
+    /// Writes every record of `dataset` to `out` in `format`.
+    pub fn write_data(&self, dataset: &Dataset, format: ExportFormat, out: &mut dyn Write) -> Result<()> {
+        let columns = &dataset.column_names;
         match format {
-            "csv" => {
-                if data.is_empty() {
-                    return Err(EchomindError::Other("No data to export".to_string()));
+            ExportFormat::Csv | ExportFormat::Tsv => {
+                let delimiter = if format == ExportFormat::Csv { b',' } else { b'\t' };
+                let mut writer = WriterBuilder::new().delimiter(delimiter).from_writer(out);
+                writer
+                    .write_record(columns)
+                    .map_err(|e| EchomindError::Other(format!("Failed to write {} headers: {}", format.name(), e)))?;
+                for record in &dataset.records {
+                    writer
+                        .write_record(columns.iter().map(|column| cell_text_value(record.get(column))))
+                        .map_err(|e| EchomindError::Other(format!("Failed to write {} row: {}", format.name(), e)))?;
                 }
-
-                let file = fs::File::create(output_path)
-                    .map_err(|e| EchomindError::FileError(format!("Failed to create output file: {}", e)))?;
-
-                let mut wtr = WriterBuilder::new().from_writer(file);
-
-                // Write headers
-                let headers: Vec<String> = data[0].keys().cloned().collect();
-                wtr.write_record(&headers)
-                    .map_err(|e| EchomindError::Other(format!("Failed to write CSV headers: {}", e)))?;
-
-                // Write data
-                for record in data {
-                    let row: Vec<String> = headers.iter()
-                        .map(|header| {
-                            record.get(header)
-                                .and_then(|v| v.as_str())
-                                .unwrap_or("")
-                                .to_string()
-                        })
-                        .collect();
-                    wtr.write_record(&row)
-                        .map_err(|e| EchomindError::Other(format!("Failed to write CSV row: {}", e)))?;
+                writer
+                    .flush()
+                    .map_err(|e| EchomindError::Other(format!("Failed to flush {}: {}", format.name(), e)))?;
+            }
+            ExportFormat::Json => {
+                writeln!(out, "[")?;
+                for (i, record) in dataset.records.iter().enumerate() {
+                    let comma = if i + 1 < dataset.records.len() { "," } else { "" };
+                    writeln!(out, "  {}{}", json_object(columns, record), comma)?;
                 }
-
-                wtr.flush()
-                    .map_err(|e| EchomindError::Other(format!("Failed to flush CSV: {}", e)))?;
+                writeln!(out, "]")?;
             }
-            "json" => {
-                let json = serde_json::to_string_pretty(data)
-                    .map_err(|e| EchomindError::ParseError(format!("Failed to serialize data: {}", e)))?;
-
-                fs::write(output_path, json)
-                    .map_err(|e| EchomindError::FileError(format!("Failed to write JSON file: {}", e)))?;
+            ExportFormat::JsonLines => {
+                for record in &dataset.records {
+                    writeln!(out, "{}", json_object(columns, record))?;
+                }
             }
-            _ => {
-                return Err(EchomindError::Other(format!("Unsupported export format: {}", format)));
+            ExportFormat::Markdown => {
+                let escape = |text: String| text.replace('|', "\\|").replace('\n', "<br>");
+                writeln!(out, "| {} |", columns.iter().map(|c| escape(c.clone())).collect::<Vec<_>>().join(" | "))?;
+                writeln!(out, "|{}", " --- |".repeat(columns.len()))?;
+                for record in &dataset.records {
+                    let cells: Vec<String> = columns
+                        .iter()
+                        .map(|column| escape(cell_text_value(record.get(column))))
+                        .collect();
+                    writeln!(out, "| {} |", cells.join(" | "))?;
+                }
             }
         }
-
         Ok(())
     }
 
@@ -385,8 +781,9 @@ impl DataProcessor {
             return serde_json::Value::Number(serde_json::Number::from(int_val));
         }
         
-        if let Ok(float_val) = value.parse::<f64>() {
-            return serde_json::Value::Number(serde_json::Number::from_f64(float_val).unwrap());
+        // NaN and infinity have no JSON number and stay strings
+        if let Some(number) = value.parse::<f64>().ok().and_then(serde_json::Number::from_f64) {
+            return serde_json::Value::Number(number);
         }
         
         // Try to parse as boolean
@@ -399,261 +796,489 @@ impl DataProcessor {
         serde_json::Value::String(value.to_string())
     }
 
-    fn calculate_column_stats(&self, values: &[serde_json::Value]) -> ColumnStats {
-        let mut null_count = 0;
-        let mut unique_values = std::collections::HashSet::new();
-        let mut numeric_values = Vec::new();
-        let mut string_values = Vec::new();
-        
-        for value in values {
-            if value.is_null() {
-                null_count += 1;
-            } else {
-                unique_values.insert(value.clone());
-                
-                match value {
-                    serde_json::Value::Number(n) => {
-                        if let Some(f) = n.as_f64() {
-                            numeric_values.push(f);
-                        }
-                    }
-                    serde_json::Value::String(s) => {
-                        string_values.push(s.clone());
-                    }
-                    _ => {}
-                }
-            }
-        }
-        
-        let unique_count = unique_values.len();
-        
-        let (min_value, max_value) = if !numeric_values.is_empty() {
-            let min = numeric_values.iter().fold(f64::INFINITY, |a, &b| a.min(b));
-            let max = numeric_values.iter().fold(f64::NEG_INFINITY, |a, &b| a.max(b));
-            (
-                Some(serde_json::Value::Number(serde_json::Number::from_f64(min).unwrap())),
-                Some(serde_json::Value::Number(serde_json::Number::from_f64(max).unwrap())),
-            )
-        } else if !string_values.is_empty() {
-            let min = string_values.iter().min();
-            let max = string_values.iter().max();
-            (
-                min.map(|s| serde_json::Value::String(s.clone())),
-                max.map(|s| serde_json::Value::String(s.clone())),
-            )
-        } else {
-            (None, None)
-        };
-        
-        let mean_value = if !numeric_values.is_empty() {
-            Some(numeric_values.iter().sum::<f64>() / numeric_values.len() as f64)
-        } else {
-            None
+    fn generate_bar_chart(&self, dataset: &Dataset, config: &VisualizationConfig) -> Result<Chart> {
+        let totals = category_totals(dataset, &config.x_column, config.y_column.as_deref())?;
+        let (title, y_label) = match &config.y_column {
+            Some(y_column) => (format!("{} by {}", y_column, config.x_column), y_column.clone()),
+            None => (format!("Rows by {}", config.x_column), "rows".to_string()),
         };
-        
-        let median_value = if !numeric_values.is_empty() {
-            let mut sorted = numeric_values.clone();
-            sorted.sort_by(|a, b| a.partial_cmp(b).unwrap());
-            let len = sorted.len();
-            if len % 2 == 0 {
-                Some((sorted[len/2 - 1] + sorted[len/2]) / 2.0)
-            } else {
-                Some(sorted[len/2])
+        let data = ChartData::Categories(top_categories(totals, MAX_BARS));
+        Chart::new(config, title, &config.x_column, &y_label, data)
+    }
+
+    fn generate_line_chart(&self, dataset: &Dataset, config: &VisualizationConfig) -> Result<Chart> {
+        let Some(y_column) = &config.y_column else {
+            // A single column is plotted against the row number
+            let points: Vec<(f64, f64)> = dataset
+                .records
+                .iter()
+                .enumerate()
+                .filter_map(|(row, record)| Some((row as f64 + 1.0, query::as_number(record.get(&config.x_column)?)?)))
+                .collect();
+            if points.is_empty() {
+                return Err(no_numbers(&config.x_column));
             }
-        } else {
-            None
+            let data = ChartData::Points { points, labels: None };
+            return Chart::new(config, config.x_column.clone(), "row", &config.x_column, data);
         };
-        
-        let most_common = if !unique_values.is_empty() {
-            let mut counts: HashMap<&serde_json::Value, usize> = HashMap::new();
-            for value in &unique_values {
-                let count = values.iter().filter(|v| *v == value).count();
-                counts.insert(value, count);
+
+        // Repeated x values are summed, so daily rows become one point per day
+        let totals = category_totals(dataset, &config.x_column, Some(y_column))?;
+        let numeric_x: Option<Vec<f64>> = totals.iter().map(|(label, _)| label.parse::<f64>().ok()).collect();
+        let data = match numeric_x {
+            Some(xs) => {
+                let mut points: Vec<(f64, f64)> = xs.into_iter().zip(totals.iter().map(|(_, y)| *y)).collect();
+                points.sort_by(|a, b| a.0.total_cmp(&b.0));
+                ChartData::Points { points, labels: None }
             }
-            
-            counts.into_iter()
-                .max_by_key(|(_, count)| *count)
-                .map(|(value, count)| (value.clone(), count))
+            None => ChartData::Points {
+                points: totals.iter().enumerate().map(|(index, (_, y))| (index as f64, *y)).collect(),
+                labels: Some(totals.into_iter().map(|(label, _)| label).collect()),
+            },
+        };
+        Chart::new(config, format!("{} over {}", y_column, config.x_column), &config.x_column, y_column, data)
+    }
+
+    fn generate_scatter_chart(&self, dataset: &Dataset, config: &VisualizationConfig) -> Result<Chart> {
+        let y_column = config
+            .y_column
+            .as_ref()
+            .ok_or_else(|| EchomindError::Other("A scatter chart needs a y column".to_string()))?;
+        let points: Vec<(f64, f64)> = dataset
+            .records
+            .iter()
+            .filter_map(|record| {
+                let x = query::as_number(record.get(&config.x_column)?)?;
+                let y = query::as_number(record.get(y_column)?)?;
+                Some((x, y))
+            })
+            .collect();
+        if points.is_empty() {
+            return Err(EchomindError::Other(format!(
+                "No rows have numeric values in both '{}' and '{}'",
+                config.x_column, y_column
+            )));
+        }
+        let data = ChartData::Points { points, labels: None };
+        Chart::new(config, format!("{} vs {}", y_column, config.x_column), &config.x_column, y_column, data)
+    }
+
+    fn generate_pie_chart(&self, dataset: &Dataset, config: &VisualizationConfig) -> Result<Chart> {
+        let totals = category_totals(dataset, &config.x_column, config.y_column.as_deref())?;
+        if totals.iter().any(|(_, value)| *value < 0.0) {
+            return Err(EchomindError::Other("A pie chart cannot show negative values".to_string()));
+        }
+        let (title, y_label) = match &config.y_column {
+            Some(y_column) => (format!("Share of {} by {}", y_column, config.x_column), y_column.clone()),
+            None => (format!("Share of rows by {}", config.x_column), "rows".to_string()),
+        };
+        let data = ChartData::Categories(top_categories(totals, MAX_SLICES));
+        Chart::new(config, title, &config.x_column, &y_label, data)
+    }
+
+    fn generate_histogram(&self, dataset: &Dataset, config: &VisualizationConfig) -> Result<Chart> {
+        let values: Vec<f64> = dataset
+            .records
+            .iter()
+            .filter_map(|record| query::as_number(record.get(&config.x_column)?))
+            .collect();
+        if values.is_empty() {
+            return Err(no_numbers(&config.x_column));
+        }
+
+        // Sturges' rule for the number of bins
+        let min = values.iter().copied().fold(f64::INFINITY, f64::min);
+        let max = values.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+        let bin_count = if min == max {
+            1
         } else {
-            None
+            ((values.len() as f64).log2().ceil() as usize + 1).clamp(2, MAX_BINS)
         };
-        
-        ColumnStats {
-            null_count,
-            unique_count,
-            min_value,
-            max_value,
-            mean_value,
-            median_value,
-            most_common,
+        let width = if min == max { 1.0 } else { (max - min) / bin_count as f64 };
+        let mut counts = vec![0usize; bin_count];
+        for value in &values {
+            let bin = (((value - min) / width) as usize).min(bin_count - 1);
+            counts[bin] += 1;
         }
+        let bins = counts
+            .into_iter()
+            .enumerate()
+            .map(|(index, count)| (min + width * index as f64, min + width * (index + 1) as f64, count))
+            .collect();
+        Chart::new(
+            config,
+            format!("Distribution of {}", config.x_column),
+            &config.x_column,
+            "count",
+            ChartData::Bins(bins),
+        )
     }
 
-    fn infer_column_type(&self, values: &[serde_json::Value]) -> String {
-        let mut type_counts = HashMap::new();
-        
-        for value in values {
-            if value.is_null() {
+    fn generate_heatmap(&self, dataset: &Dataset, config: &VisualizationConfig) -> Result<Chart> {
+        let y_column = config
+            .y_column
+            .as_ref()
+            .ok_or_else(|| EchomindError::Other("A heatmap needs a y column for its rows".to_string()))?;
+        let columns = frequent_labels(dataset, &config.x_column, MAX_HEATMAP_LABELS);
+        let rows = frequent_labels(dataset, y_column, MAX_HEATMAP_LABELS);
+        let mut values = vec![vec![0.0; columns.len()]; rows.len()];
+        for record in &dataset.records {
+            let x = category_label(record.get(&config.x_column));
+            let y = category_label(record.get(y_column));
+            if let (Some(c), Some(r)) = (columns.iter().position(|l| *l == x), rows.iter().position(|l| *l == y)) {
+                values[r][c] += 1.0;
+            }
+        }
+        let data = ChartData::Grid { rows, columns, values };
+        Chart::new(config, format!("{} × {}", y_column, config.x_column), &config.x_column, y_column, data)
+    }
+}
+
+/// Categories shown in a bar chart; the rest are summed into "Other".
+const MAX_BARS: usize = 30;
+
+/// Slices shown in a pie chart; the rest are summed into "Other".
+const MAX_SLICES: usize = 8;
+
+const MAX_BINS: usize = 30;
+
+/// Rows and columns shown in a heatmap, keeping the most frequent labels.
+const MAX_HEATMAP_LABELS: usize = 25;
+
+fn column_index(dataset: &Dataset, column: &str) -> Result<usize> {
+    dataset.column_names.iter().position(|name| name == column).ok_or_else(|| {
+        EchomindError::Other(format!(
+            "Unknown column '{}' (columns: {})",
+            column,
+            dataset.column_names.join(", ")
+        ))
+    })
+}
+
+fn no_numbers(column: &str) -> EchomindError {
+    EchomindError::Other(format!("Column '{}' has no numeric values", column))
+}
+
+fn category_label(value: Option<&serde_json::Value>) -> String {
+    match value {
+        None | Some(serde_json::Value::Null) => "(empty)".to_string(),
+        Some(value) => display_value(value),
+    }
+}
+
+/// Row counts per `x` value, or sums of `y` when given, in first-appearance
+/// order.
+fn category_totals(dataset: &Dataset, x: &str, y: Option<&str>) -> Result<Vec<(String, f64)>> {
+    let mut totals: Vec<(String, f64)> = Vec::new();
+    let mut positions: HashMap<String, usize> = HashMap::new();
+    let mut any_number = false;
+    for record in &dataset.records {
+        let amount = match y {
+            Some(y) => match record.get(y).and_then(query::as_number) {
+                Some(number) => {
+                    any_number = true;
+                    number
+                }
+                None => continue,
+            },
+            None => 1.0,
+        };
+        let label = category_label(record.get(x));
+        let index = *positions.entry(label.clone()).or_insert_with(|| {
+            totals.push((label, 0.0));
+            totals.len() - 1
+        });
+        totals[index].1 += amount;
+    }
+    match y {
+        Some(y) if !any_number => Err(no_numbers(y)),
+        _ => Ok(totals),
+    }
+}
+
+/// Keeps the largest `limit - 1` categories, largest first, and sums the rest
+/// into "Other" when there are more than `limit`.
+fn top_categories(mut totals: Vec<(String, f64)>, limit: usize) -> Vec<(String, f64)> {
+    if totals.len() <= limit {
+        return totals;
+    }
+    totals.sort_by(|a, b| b.1.total_cmp(&a.1));
+    let rest = totals.split_off(limit - 1);
+    totals.push((format!("Other ({})", rest.len()), rest.iter().map(|(_, value)| value).sum()));
+    totals
+}
+
+/// The `limit` most frequent values of `column`, in first-appearance order.
+fn frequent_labels(dataset: &Dataset, column: &str, limit: usize) -> Vec<String> {
+    let mut counts: Vec<(String, usize)> = Vec::new();
+    let mut positions: HashMap<String, usize> = HashMap::new();
+    for record in &dataset.records {
+        let label = category_label(record.get(column));
+        let index = *positions.entry(label.clone()).or_insert_with(|| {
+            counts.push((label, 0));
+            counts.len() - 1
+        });
+        counts[index].1 += 1;
+    }
+    if counts.len() > limit {
+        // A stable sort keeps earlier labels on ties
+        let mut by_count: Vec<usize> = (0..counts.len()).collect();
+        by_count.sort_by(|a, b| counts[*b].1.cmp(&counts[*a].1));
+        let kept: std::collections::HashSet<usize> = by_count.into_iter().take(limit).collect();
+        counts = counts
+            .into_iter()
+            .enumerate()
+            .filter(|(index, _)| kept.contains(index))
+            .map(|(_, entry)| entry)
+            .collect();
+    }
+    counts.into_iter().map(|(label, _)| label).collect()
+}
+
+fn cell_text_value(value: Option<&serde_json::Value>) -> String {
+    match value {
+        None | Some(serde_json::Value::Null) => String::new(),
+        Some(serde_json::Value::String(s)) => s.clone(),
+        Some(other) => other.to_string(),
+    }
+}
+
+// A JSON object with its keys in column order (serde_json sorts map keys).
+fn json_object(columns: &[String], record: &Record) -> String {
+    let fields: Vec<String> = columns
+        .iter()
+        .map(|column| {
+            let value = record.get(column).unwrap_or(&serde_json::Value::Null);
+            format!("{}:{}", serde_json::Value::String(column.clone()), value)
+        })
+        .collect();
+    format!("{{{}}}", fields.join(","))
+}
+
+fn open_workbook(file_path: &str) -> Result<calamine::Sheets<BufReader<fs::File>>> {
+    calamine::open_workbook_auto(file_path)
+        .map_err(|e| EchomindError::Other(format!("Failed to open spreadsheet {}: {}", file_path, e)))
+}
+
+fn worksheet(workbook: &mut calamine::Sheets<BufReader<fs::File>>, index: usize, name: &str) -> Result<Range<Data>> {
+    match workbook.worksheet_range_at(index) {
+        Some(Ok(range)) => Ok(range),
+        Some(Err(e)) => Err(EchomindError::Other(format!("Failed to read sheet '{}': {}", name, e))),
+        None => Err(EchomindError::Other(format!("Failed to read sheet '{}'", name))),
+    }
+}
+
+// Finds a sheet by exact name, then case-insensitively, then by position
+// counting from 1.
+fn sheet_index(names: &[String], selector: &str, file_path: &str) -> Result<usize> {
+    names
+        .iter()
+        .position(|name| name == selector)
+        .or_else(|| names.iter().position(|name| name.eq_ignore_ascii_case(selector)))
+        .or_else(|| {
+            selector
+                .trim()
+                .parse::<usize>()
+                .ok()
+                .filter(|n| (1..=names.len()).contains(n))
+                .map(|n| n - 1)
+        })
+        .ok_or_else(|| {
+            EchomindError::Other(format!(
+                "No sheet '{}' in {}; its sheets are: {}",
+                selector,
+                file_path,
+                names.join(", ")
+            ))
+        })
+}
+
+fn sheet_label(file_path: &str, sheet: &str) -> String {
+    let is_ods = std::path::Path::new(file_path)
+        .extension()
+        .is_some_and(|ext| ext.eq_ignore_ascii_case("ods"));
+    format!("{} sheet \"{}\"", if is_ods { "ODS" } else { "Excel" }, sheet)
+}
+
+/// Reads the table in a worksheet. The header is the first row about as wide
+/// as the table, which skips title rows (a merged title only fills its first
+/// cell); a row of group labels over a narrower header row, as left by
+/// merged cells, is folded into the column names ("2024 Q1"). Columns without
+/// a name are called by their letter, as are all columns when the first row
+/// already holds only numbers, dates or booleans.
+fn read_sheet(range: &Range<Data>, mut visit: impl FnMut(&[String], Record)) -> Vec<String> {
+    let rows: Vec<&[Data]> = range.rows().collect();
+    let filled = |row: &[Data]| row.iter().filter(|cell| !is_blank(cell)).count();
+    let is_text = |row: &[Data]| row.iter().all(|cell| is_blank(cell) || matches!(cell, Data::String(_)));
+
+    let scan = &rows[..rows.len().min(HEADER_SCAN_ROWS)];
+    let width = scan.iter().map(|row| filled(row)).max().unwrap_or(0);
+    let Some(first) = scan.iter().position(|row| filled(row) * 2 >= width && (filled(row) >= 2 || width == 1)) else {
+        return Vec::new();
+    };
+
+    let first_column = range.start().map(|(_, column)| column as usize).unwrap_or(0);
+    let columns = rows.first().map(|row| row.len()).unwrap_or(0);
+    let mut labels: Vec<String> = vec![String::new(); columns];
+    let data_start = if !rows[first].iter().any(|cell| matches!(cell, Data::String(_))) {
+        first
+    } else {
+        let two_rows = rows.len() > first + 2
+            && is_text(rows[first])
+            && is_text(rows[first + 1])
+            && filled(rows[first]) < filled(rows[first + 1])
+            && !is_text(rows[first + 2]);
+        let mut group = String::new();
+        for (column, label) in labels.iter_mut().enumerate() {
+            let top = cell_text(&rows[first][column]);
+            if !two_rows {
+                *label = top;
                 continue;
             }
-            
-            let type_name = match value {
-                serde_json::Value::Number(_) => "number",
-                serde_json::Value::String(_) => "string",
-                serde_json::Value::Bool(_) => "boolean",
-                serde_json::Value::Array(_) => "array",
-                serde_json::Value::Object(_) => "object",
-                _ => "unknown",
+            if !top.is_empty() {
+                group = top;
+            }
+            let sub = cell_text(&rows[first + 1][column]);
+            *label = match (group.is_empty(), sub.is_empty()) {
+                (false, false) => format!("{} {}", group, sub),
+                (false, true) => group.clone(),
+                _ => sub,
             };
-            
-            *type_counts.entry(type_name).or_insert(0) += 1;
         }
-        
-        type_counts.into_iter()
-            .max_by_key(|(_, count)| *count)
-            .map(|(type_name, _)| type_name.to_string())
-            .unwrap_or_else(|| "unknown".to_string())
+        first + if two_rows { 2 } else { 1 }
+    };
+
+    let data = &rows[data_start..];
+    let mut column_names = Vec::new();
+    let mut kept = Vec::new();
+    for (column, label) in labels.into_iter().enumerate() {
+        if label.is_empty() && data.iter().all(|row| is_blank(&row[column])) {
+            continue;
+        }
+        let base = if label.is_empty() { column_letter(first_column + column) } else { label };
+        let mut name = base.clone();
+        let mut n = 2;
+        while column_names.contains(&name) {
+            name = format!("{}_{}", base, n);
+            n += 1;
+        }
+        column_names.push(name);
+        kept.push(column);
     }
 
-    fn filter_data(&self, data: &[HashMap<String, serde_json::Value>], where_clause: &str) -> Result<Vec<HashMap<String, serde_json::Value>>> {
-        // Very basic WHERE clause parsing
-        // For now, just support simple equality checks
-        
-        let parts: Vec<&str> = where_clause.split('=').collect();
-        if parts.len() != 2 {
-            return Ok(data.to_vec());
+    for row in data {
+        if kept.iter().all(|&column| is_blank(&row[column])) {
+            continue;
         }
-        
-        let column = parts[0].trim();
-        let value = parts[1].trim().trim_matches('\'').trim_matches('"');
-        
-        let filtered: Vec<HashMap<String, serde_json::Value>> = data.iter()
-            .filter(|record| {
-                if let Some(field_value) = record.get(column) {
-                    match field_value {
-                        serde_json::Value::String(s) => s == value,
-                        serde_json::Value::Number(n) => {
-                            if let Ok(num_val) = value.parse::<f64>() {
-                                n.as_f64() == Some(num_val)
-                            } else {
-                                false
-                            }
-                        }
-                        serde_json::Value::Bool(b) => {
-                            if let Ok(bool_val) = value.parse::<bool>() {
-                                *b == bool_val
-                            } else {
-                                false
-                            }
-                        }
-                        _ => false,
-                    }
-                } else {
-                    false
-                }
-            })
-            .cloned()
+        let record = column_names
+            .iter()
+            .zip(&kept)
+            .map(|(name, &column)| (name.clone(), cell_value(&row[column])))
             .collect();
-        
-        Ok(filtered)
-    }
-
-    fn generate_bar_chart(&self, _analysis: &DataAnalysis, config: &VisualizationConfig) -> Result<String> {
-        // Simplified bar chart generation
-        Ok(format!(
-            r#"<div style="width: {}px; height: {}px;">
-                <h3>{}</h3>
-                <p>Bar chart for column: {}</p>
-                <div style="border: 1px solid #ccc; padding: 10px;">
-                    Chart data would be rendered here
-                </div>
-            </div>"#,
-            config.width.unwrap_or(600),
-            config.height.unwrap_or(400),
-            config.title.as_ref().unwrap_or(&"Bar Chart".to_string()),
-            config.x_column
-        ))
+        visit(&column_names, record);
     }
+    column_names
+}
 
-    fn generate_line_chart(&self, _analysis: &DataAnalysis, config: &VisualizationConfig) -> Result<String> {
-        Ok(format!(
-            r#"<div style="width: {}px; height: {}px;">
-                <h3>{}</h3>
-                <p>Line chart for column: {}</p>
-                <div style="border: 1px solid #ccc; padding: 10px;">
-                    Chart data would be rendered here
-                </div>
-            </div>"#,
-            config.width.unwrap_or(600),
-            config.height.unwrap_or(400),
-            config.title.as_ref().unwrap_or(&"Line Chart".to_string()),
-            config.x_column
-        ))
+fn is_blank(cell: &Data) -> bool {
+    match cell {
+        Data::Empty => true,
+        Data::String(s) => s.trim().is_empty(),
+        _ => false,
     }
+}
 
-    fn generate_scatter_chart(&self, _analysis: &DataAnalysis, config: &VisualizationConfig) -> Result<String> {
-        Ok(format!(
-            r#"<div style="width: {}px; height: {}px;">
-                <h3>{}</h3>
-                <p>Scatter chart: {} vs {}</p>
-                <div style="border: 1px solid #ccc; padding: 10px;">
-                    Chart data would be rendered here
-                </div>
-            </div>"#,
-            config.width.unwrap_or(600),
-            config.height.unwrap_or(400),
-            config.title.as_ref().unwrap_or(&"Scatter Chart".to_string()),
-            config.x_column,
-            config.y_column.as_ref().unwrap_or(&"Y".to_string())
-        ))
+/// Converts a typed cell: whole floats become integers (Excel stores every
+/// number as a float), dates become ISO 8601 text, durations `H:MM:SS`, and
+/// error cells such as `#DIV/0!` count as empty.
+fn cell_value(cell: &Data) -> serde_json::Value {
+    use serde_json::Value;
+    match cell {
+        Data::Int(i) => Value::from(*i),
+        Data::Float(f) if f.fract() == 0.0 && f.abs() < 1e15 => Value::from(*f as i64),
+        Data::Float(f) => serde_json::Number::from_f64(*f).map(Value::Number).unwrap_or(Value::Null),
+        Data::String(s) if s.trim().is_empty() => Value::Null,
+        Data::String(s) => Value::String(s.clone()),
+        Data::Bool(b) => Value::Bool(*b),
+        Data::DateTime(datetime) if datetime.is_duration() => {
+            let seconds = (datetime.as_f64() * 86_400.0).round() as i64;
+            let sign = if seconds < 0 { "-" } else { "" };
+            let seconds = seconds.abs();
+            Value::String(format!("{}{}:{:02}:{:02}", sign, seconds / 3600, seconds / 60 % 60, seconds % 60))
+        }
+        Data::DateTime(datetime) => match datetime.as_datetime() {
+            // A time of day alone is a fraction of a day
+            Some(value) if datetime.as_f64() < 1.0 => Value::String(value.format("%H:%M:%S").to_string()),
+            Some(value) if value.time() == chrono::NaiveTime::MIN => Value::String(value.format("%Y-%m-%d").to_string()),
+            Some(value) => Value::String(value.format("%Y-%m-%dT%H:%M:%S").to_string()),
+            None => Value::Null,
+        },
+        Data::DateTimeIso(text) | Data::DurationIso(text) => Value::String(text.clone()),
+        Data::Error(_) | Data::Empty => Value::Null,
     }
+}
 
-    fn generate_pie_chart(&self, _analysis: &DataAnalysis, config: &VisualizationConfig) -> Result<String> {
-        Ok(format!(
-            r#"<div style="width: {}px; height: {}px;">
-                <h3>{}</h3>
-                <p>Pie chart for column: {}</p>
-                <div style="border: 1px solid #ccc; padding: 10px;">
-                    Chart data would be rendered here
-                </div>
-            </div>"#,
-            config.width.unwrap_or(600),
-            config.height.unwrap_or(400),
-            config.title.as_ref().unwrap_or(&"Pie Chart".to_string()),
-            config.x_column
-        ))
+fn cell_text(cell: &Data) -> String {
+    match cell_value(cell) {
+        serde_json::Value::Null => String::new(),
+        serde_json::Value::String(s) => s.trim().to_string(),
+        other => other.to_string(),
     }
+}
 
-    fn generate_histogram(&self, _analysis: &DataAnalysis, config: &VisualizationConfig) -> Result<String> {
-        Ok(format!(
-            r#"<div style="width: {}px; height: {}px;">
-                <h3>{}</h3>
-                <p>Histogram for column: {}</p>
-                <div style="border: 1px solid #ccc; padding: 10px;">
-                    Chart data would be rendered here
-                </div>
-            </div>"#,
-            config.width.unwrap_or(600),
-            config.height.unwrap_or(400),
-            config.title.as_ref().unwrap_or(&"Histogram".to_string()),
-            config.x_column
-        ))
+// Spreadsheet column letters: 0 is A, 26 is AA.
+fn column_letter(mut column: usize) -> String {
+    let mut letters = Vec::new();
+    loop {
+        letters.push(b'A' + (column % 26) as u8);
+        if column < 26 {
+            break;
+        }
+        column = column / 26 - 1;
     }
+    letters.reverse();
+    String::from_utf8(letters).unwrap_or_default()
+}
 
-    fn generate_heatmap(&self, _analysis: &DataAnalysis, config: &VisualizationConfig) -> Result<String> {
-        Ok(format!(
-            r#"<div style="width: {}px; height: {}px;">
-                <h3>{}</h3>
-                <p>Heatmap for column: {}</p>
-                <div style="border: 1px solid #ccc; padding: 10px;">
-                    Chart data would be rendered here
-                </div>
-            </div>"#,
-            config.width.unwrap_or(600),
-            config.height.unwrap_or(400),
-            config.title.as_ref().unwrap_or(&"Heatmap".to_string()),
-            config.x_column
-        ))
+/// Opens a data file for buffered reading, decompressing it when it starts
+/// with the gzip magic bytes.
+fn open_input(file_path: &str, kind: &str) -> Result<Box<dyn BufRead>> {
+    let file = fs::File::open(file_path)
+        .map_err(|e| EchomindError::FileError(format!("Failed to open {} file: {}", kind, e)))?;
+    let mut reader = BufReader::new(file);
+    let gzip = reader
+        .fill_buf()
+        .map(|start| start.starts_with(&[0x1f, 0x8b]))
+        .map_err(|e| EchomindError::FileError(format!("Failed to read {} file: {}", kind, e)))?;
+    Ok(if gzip {
+        Box::new(BufReader::new(flate2::bufread::MultiGzDecoder::new(reader)))
+    } else {
+        Box::new(reader)
+    })
+}
+
+/// Passes each object of a JSON array to a callback as it is parsed, so the
+/// array never has to be held in memory.
+struct ObjectSeqVisitor<'a, F>(&'a mut F);
+
+impl<'de, F> serde::de::Visitor<'de> for ObjectSeqVisitor<'_, F>
+where
+    F: FnMut(serde_json::Map<String, serde_json::Value>),
+{
+    type Value = ();
+
+    fn expecting(&self, formatter: &mut std::fmt::Formatter) -> std::fmt::Result {
+        formatter.write_str("an array of objects")
+    }
+
+    fn visit_seq<A: serde::de::SeqAccess<'de>>(self, mut seq: A) -> std::result::Result<(), A::Error> {
+        // Elements that are not objects are skipped, as before
+        while let Some(item) = seq.next_element::<serde_json::Value>()? {
+            if let serde_json::Value::Object(object) = item {
+                (self.0)(object);
+            }
+        }
+        Ok(())
     }
 }
 
@@ -661,4 +1286,47 @@ impl Default for DataProcessor {
     fn default() -> Self {
         Self::new()
     }
-}
\ No newline at end of file
+}
+
+fn filter_expr(filter: &Filter) -> Result<Expr> {
+    let column = Box::new(Expr::Column(filter.column.clone()));
+    let value = Box::new(Expr::Literal(filter.value.clone()));
+    let compare = |op| Ok(Expr::Binary(op, column.clone(), value.clone()));
+
+    match filter.op.trim().to_lowercase().as_str() {
+        "=" | "==" | "eq" => compare(BinaryOp::Eq),
+        "!=" | "<>" | "ne" => compare(BinaryOp::NotEq),
+        ">" | "gt" => compare(BinaryOp::Gt),
+        ">=" | "gte" => compare(BinaryOp::GtEq),
+        "<" | "lt" => compare(BinaryOp::Lt),
+        "<=" | "lte" => compare(BinaryOp::LtEq),
+        "contains" => {
+            let escaped: String = display_value(&filter.value)
+                .chars()
+                .flat_map(|c| match c {
+                    '%' | '_' | '\\' => vec!['\\', c],
+                    other => vec![other],
+                })
+                .collect();
+            Ok(Expr::Like {
+                expr: column,
+                pattern: Box::new(Expr::Literal(serde_json::Value::String(format!("%{}%", escaped)))),
+                negated: false,
+            })
+        }
+        "in" => match &filter.value {
+            serde_json::Value::Array(options) => Ok(Expr::InList {
+                expr: column,
+                list: options.iter().cloned().map(Expr::Literal).collect(),
+                negated: false,
+            }),
+            _ => Err(EchomindError::Other("Filter 'in' needs a list value".to_string())),
+        },
+        "is_null" => Ok(Expr::IsNull { expr: column, negated: false }),
+        "not_null" => Ok(Expr::IsNull { expr: column, negated: true }),
+        other => Err(EchomindError::Other(format!(
+            "Unknown filter operator '{}' (=, !=, >, >=, <, <=, contains, in, is_null, not_null)",
+            other
+        ))),
+    }
+}
@@ -0,0 +1,300 @@
+//! Data-file Q&A: answers questions about a CSV, JSON or Excel file by giving
+//! the model a compact description of the data and computing whatever
+//! queries it asks for over every row, so that answers rest on computed
+//! values rather than on a truncated dump. The model also translates
+//! questions into queries and instructions into transforms, which run
+//! locally.
+
+use crate::api::{ApiClient, ChatRequest, Message};
+use crate::error::{EchomindError, Result};
+use crate::features::data_processing::{AggregateRequest, DataAnalysis, DataProcessor, Dataset};
+use crate::features::json_schema::extract_json;
+use crate::features::query::{self, QueryResult};
+use crate::features::transform::TransformSpec;
+use crate::features::usage::UsageMeter;
+
+/// Rounds of query requests the model may make before it has to answer.
+pub const MAX_COMPUTATION_ROUNDS: usize = 4;
+
+/// Rows of a query result sent back to the model.
+const MAX_RESULT_ROWS: usize = 50;
+
+/// Sample rows included in the description, to show the format of values.
+const SAMPLE_ROWS: usize = 5;
+
+const QUERY_LANGUAGE: &str = "The query language is a small SQL dialect over a single table (FROM is optional): \
+SELECT [DISTINCT] expr [AS alias], ... [WHERE cond] [GROUP BY expr, ...] [HAVING cond] [ORDER BY expr|alias|position [ASC|DESC], ...] [LIMIT n [OFFSET m]]. \
+Aggregates: COUNT(*), COUNT([DISTINCT] col), SUM, AVG, MIN, MAX, MEDIAN. Functions: LOWER, UPPER, TRIM, LENGTH, ROUND(x, digits), ABS, COALESCE, YEAR(date), MONTH(date). \
+Conditions: = != < <= > >=, AND, OR, NOT, IS [NOT] NULL, [NOT] IN (...), [NOT] LIKE '%text%' (case insensitive), BETWEEN a AND b. \
+Strings use single quotes; column names with spaces use double quotes.";
+
+const SYSTEM_PROMPT: &str = r#"You answer questions about a data file. You only see its schema, column statistics and a few sample rows; the sample is there to show the format of values and must not be used for counting or totals.
+Every number in your answer must come from the statistics or from query results computed for you. To compute something over all rows, reply with ONLY a JSON object like:
+{"sql": "SELECT region, SUM(revenue) AS total FROM data GROUP BY region ORDER BY total DESC LIMIT 10"}
+"sql" may also be a list of queries to run several at once."#;
+
+const ANSWER_PROMPT: &str = "You will receive the results and may query again. When you have what you need, answer in plain text and mention the computed figures you used. If the data cannot answer the question, say so.";
+
+const TRANSLATE_PROMPT: &str = "Translate the user's question about a data file into one query. Reply with only the query, no explanation and no code fences.";
+
+const TRANSFORM_PROMPT: &str = r#"Turn the user's instruction into a transform that will run over every row of a data file. Reply with only a JSON object {"steps": [...]}; steps apply in order and later steps see earlier renames and derived columns. Steps:
+{"op": "trim", "columns": [...]}  (no columns: every text column; blank text becomes empty)
+{"op": "case", "columns": [...], "to": "lower" | "upper" | "title"}
+{"op": "replace", "columns": [...], "pattern": "<regex>", "with": "<text, $1 for groups>"}
+{"op": "fill", "columns": [...], "value": <value for empty cells>}
+{"op": "to_number", "columns": [...]}  (strips currency signs, thousands separators and %)
+{"op": "parse_date", "columns": [...], "formats": ["%d/%m/%Y"], "output": "%Y-%m-%d"}  (chrono formats; omit formats to try common ones, which read 01/02/2024 as January 2)
+{"op": "derive", "column": "<new or existing column>", "expr": "<expression>"}
+{"op": "filter", "where": "<condition>"}  (keeps matching rows)
+{"op": "dedupe", "columns": [...], "keep": "first" | "last"}  (no columns: whole rows)
+{"op": "rename", "columns": {"old": "new"}}
+{"op": "select", "columns": [...]} or {"op": "drop", "columns": [...]}
+Use the sample rows to pick date formats and patterns. Expressions and conditions are written like SQL select expressions and WHERE clauses, without aggregates."#;
+
+/// One query the model asked for, with its result or error.
+#[derive(Debug, Clone)]
+pub struct Computation {
+    pub query: String,
+    pub result: std::result::Result<QueryResult, String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct DataAnswer {
+    pub answer: String,
+    pub computations: Vec<Computation>,
+}
+
+/// Asks `question` about `dataset`. `base` supplies the model parameters and
+/// any preset messages; a `system` message in it is appended to the data
+/// instructions. With `usage`, each round is checked against the budget and
+/// recorded.
+#[allow(clippy::too_many_arguments)]
+pub async fn answer_question(
+    client: &ApiClient,
+    base: ChatRequest,
+    processor: &DataProcessor,
+    dataset: &Dataset,
+    analysis: &DataAnalysis,
+    source: &str,
+    question: &str,
+    usage: Option<&UsageMeter>,
+) -> Result<DataAnswer> {
+    let system = format!("{}\n{}\n{}", SYSTEM_PROMPT, QUERY_LANGUAGE, ANSWER_PROMPT);
+    let mut messages = with_system(&system, &base);
+    messages.push(Message::text(
+        "user".to_string(),
+        format!("{}\n\nQuestion: {}", analysis.describe(source, SAMPLE_ROWS), question.trim()),
+    ));
+
+    let mut computations = Vec::new();
+    for round in 0..=MAX_COMPUTATION_ROUNDS {
+        let request = ChatRequest {
+            messages: messages.clone(),
+            stream: None,
+            ..base.clone()
+        };
+        let reply = send(client, request, usage).await?;
+
+        let Some(requests) = parse_requests(&reply) else {
+            return Ok(DataAnswer {
+                answer: reply.trim().to_string(),
+                computations,
+            });
+        };
+        if round == MAX_COMPUTATION_ROUNDS {
+            break;
+        }
+
+        let mut results = Vec::new();
+        for (index, request) in requests.into_iter().enumerate() {
+            let computation = match request {
+                QueryRequest::Sql(sql) => Computation {
+                    result: processor.query_data(dataset, &sql).map_err(|e| e.to_string()),
+                    query: sql,
+                },
+                QueryRequest::Aggregate(request) => match processor.aggregate_query(&request) {
+                    Ok(query) => Computation {
+                        result: query.execute(dataset).map_err(|e| e.to_string()),
+                        query: serde_json::to_string(&request).unwrap_or_default(),
+                    },
+                    Err(error) => Computation {
+                        query: serde_json::to_string(&request).unwrap_or_default(),
+                        result: Err(error.to_string()),
+                    },
+                },
+                QueryRequest::Invalid(error) => Computation {
+                    query: String::new(),
+                    result: Err(error),
+                },
+            };
+            results.push(match &computation.result {
+                Ok(result) => format!(
+                    "Result {} ({} matching rows):\n{}",
+                    index + 1,
+                    result.matched_rows,
+                    result.to_table(MAX_RESULT_ROWS)
+                ),
+                Err(error) => format!("Result {}: error: {}", index + 1, error),
+            });
+            computations.push(computation);
+        }
+
+        let mut feedback = results.join("\n\n");
+        if round + 1 == MAX_COMPUTATION_ROUNDS {
+            feedback.push_str("\n\nThat was the last computation round; answer now from the results above.");
+        }
+        messages.push(Message::text("assistant".to_string(), reply));
+        messages.push(Message::text("user".to_string(), feedback));
+    }
+
+    Err(EchomindError::Other(format!(
+        "The model kept asking for computations after {} rounds without answering",
+        MAX_COMPUTATION_ROUNDS
+    )))
+}
+
+/// Has the model translate `question` into one query, without running it.
+/// The query is checked to parse before it is returned.
+pub async fn translate_to_sql(
+    client: &ApiClient,
+    base: ChatRequest,
+    analysis: &DataAnalysis,
+    source: &str,
+    question: &str,
+    usage: Option<&UsageMeter>,
+) -> Result<String> {
+    let system = format!("{}\n{}", TRANSLATE_PROMPT, QUERY_LANGUAGE);
+    let mut messages = with_system(&system, &base);
+    messages.push(Message::text(
+        "user".to_string(),
+        format!("{}\n\nQuestion: {}", analysis.describe(source, SAMPLE_ROWS), question.trim()),
+    ));
+
+    let request = ChatRequest {
+        messages,
+        stream: None,
+        ..base
+    };
+    let reply = send(client, request, usage).await?;
+    let sql = strip_fences(&reply);
+    query::parse(&sql).map_err(|e| EchomindError::Other(format!("The model produced an invalid query ({}): {}", e, sql)))?;
+    Ok(sql)
+}
+
+/// Has the model turn `instruction` into a transform spec for the file
+/// described by `analysis`, without running it. The spec is checked against
+/// the file's columns before it is returned.
+pub async fn plan_transform(
+    client: &ApiClient,
+    base: ChatRequest,
+    analysis: &DataAnalysis,
+    source: &str,
+    instruction: &str,
+    usage: Option<&UsageMeter>,
+) -> Result<TransformSpec> {
+    let system = format!("{}\n{}", TRANSFORM_PROMPT, QUERY_LANGUAGE);
+    let mut messages = with_system(&system, &base);
+    messages.push(Message::text(
+        "user".to_string(),
+        format!("{}\n\nInstruction: {}", analysis.describe(source, SAMPLE_ROWS), instruction.trim()),
+    ));
+
+    let request = ChatRequest {
+        messages,
+        stream: None,
+        ..base
+    };
+    let reply = send(client, request, usage).await?;
+    let spec = extract_json(&reply)
+        .ok_or_else(|| EchomindError::Other(format!("The model did not reply with a transform: {}", reply.trim())))
+        .and_then(|value| {
+            serde_json::from_value::<TransformSpec>(value)
+                .map_err(|e| EchomindError::Other(format!("The model produced an invalid transform ({}): {}", e, reply.trim())))
+        })?;
+    spec.validate(&analysis.column_names)
+        .map_err(|e| EchomindError::Other(format!("The model produced an invalid transform: {}", e)))?;
+    Ok(spec)
+}
+
+/// Sends one request, checked against the budget and recorded in the ledger
+/// when there is a meter.
+async fn send(client: &ApiClient, request: ChatRequest, usage: Option<&UsageMeter>) -> Result<String> {
+    UsageMeter::send(usage, client.provider().name(), &request, |warning| {
+        if let Some(warning) = warning {
+            eprintln!("Budget: {}", warning);
+        }
+        client.send_message(request.clone())
+    })
+    .await
+}
+
+/// Puts `system` first, followed by any system text from `base` and its
+/// other (preset) messages.
+fn with_system(system: &str, base: &ChatRequest) -> Vec<Message> {
+    let mut system = system.to_string();
+    let mut messages = Vec::new();
+    for message in base.messages.iter().cloned() {
+        match (message.role.as_str(), message.get_text()) {
+            ("system", Some(text)) => {
+                system.push_str("\n\n");
+                system.push_str(text);
+            }
+            _ => messages.push(message),
+        }
+    }
+    messages.insert(0, Message::text("system".to_string(), system));
+    messages
+}
+
+fn strip_fences(reply: &str) -> String {
+    let trimmed = reply.trim();
+    let Some(body) = trimmed.strip_prefix("```") else {
+        return trimmed.to_string();
+    };
+    let body = body.split_once('\n').map(|(_, rest)| rest).unwrap_or(body);
+    body.trim_end().trim_end_matches("```").trim().to_string()
+}
+
+enum QueryRequest {
+    Sql(String),
+    /// The structured form accepted before the query language existed
+    Aggregate(AggregateRequest),
+    Invalid(String),
+}
+
+/// Reads `{"sql": ...}` (or `{"aggregate": ...}`) from a reply; `None` means
+/// the reply is an answer. Malformed requests are returned for the model to
+/// fix.
+fn parse_requests(reply: &str) -> Option<Vec<QueryRequest>> {
+    let value = extract_json(reply)?;
+    if let Some(sql) = value.get("sql") {
+        let items = match sql {
+            serde_json::Value::Array(items) => items.clone(),
+            single => vec![single.clone()],
+        };
+        return Some(
+            items
+                .into_iter()
+                .map(|item| match item {
+                    serde_json::Value::String(sql) => QueryRequest::Sql(sql),
+                    other => QueryRequest::Invalid(format!("expected a query string, got {}", other)),
+                })
+                .collect(),
+        );
+    }
+
+    let aggregate = value.get("aggregate")?;
+    let items = match aggregate {
+        serde_json::Value::Array(items) => items.clone(),
+        single => vec![single.clone()],
+    };
+    Some(
+        items
+            .into_iter()
+            .map(|item| match serde_json::from_value(item) {
+                Ok(request) => QueryRequest::Aggregate(request),
+                Err(e) => QueryRequest::Invalid(format!("invalid aggregate request: {}", e)),
+            })
+            .collect(),
+    )
+}
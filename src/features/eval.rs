@@ -0,0 +1,536 @@
+//! Prompt regression tests: suites of cases, each an input (optionally
+//! through a preset or saved template) with assertions on the response, run
+//! against one or more models. Results can be saved as a baseline, and a
+//! later run is diffed against it so that cases that used to pass and now
+//! fail are reported as regressions.
+
+use crate::api::{ChatRequest, Message};
+use crate::config::Preset;
+use crate::error::{EchomindError, Result};
+use crate::features::content::ContentManager;
+use crate::features::json_schema;
+use crate::features::performance::BenchmarkTarget;
+use crate::features::usage::UsageMeter;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::path::Path;
+use std::time::Instant;
+
+const JUDGE_PROMPT: &str = r#"You grade an AI response against a rubric. Score how fully the response meets the rubric, from 0 (not at all) to 1 (completely). Judge only what the rubric asks for. Reply with only a JSON object: {"score": <number from 0 to 1>, "reason": "<one sentence>"}"#;
+
+/// A set of cases to run, from a YAML, JSON or TOML file.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EvalSuite {
+    #[serde(default)]
+    pub name: Option<String>,
+    /// `provider:model` references, used when none are given on the command line
+    #[serde(default)]
+    pub models: Vec<String>,
+    /// Model that scores `judge` assertions
+    #[serde(default)]
+    pub judge: Option<String>,
+    #[serde(default)]
+    pub temperature: Option<f32>,
+    #[serde(default)]
+    pub max_tokens: Option<u32>,
+    pub cases: Vec<EvalCase>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct EvalCase {
+    pub id: String,
+    /// Sent as the user message, after the rendered template if there is one
+    #[serde(default)]
+    pub input: String,
+    /// Config preset supplying a system prompt and leading messages
+    #[serde(default)]
+    pub preset: Option<String>,
+    /// Saved template rendered with `vars`
+    #[serde(default)]
+    pub template: Option<String>,
+    #[serde(default)]
+    pub vars: HashMap<String, String>,
+    /// Overrides the preset's system prompt
+    #[serde(default)]
+    pub system: Option<String>,
+    #[serde(default, rename = "assert")]
+    pub assertions: Vec<Assertion>,
+}
+
+/// A check on a response. Scored assertions (similarity, judge) pass when
+/// their score reaches the threshold.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum Assertion {
+    Contains {
+        value: String,
+        #[serde(default)]
+        case_sensitive: bool,
+    },
+    NotContains {
+        value: String,
+        #[serde(default)]
+        case_sensitive: bool,
+    },
+    Regex {
+        pattern: String,
+    },
+    /// The response holds JSON valid against the schema; a string is read
+    /// as a schema file relative to the suite
+    JsonSchema {
+        schema: Value,
+    },
+    MaxLength {
+        chars: usize,
+    },
+    /// Word-overlap (cosine) similarity to a reference answer
+    Similarity {
+        reference: String,
+        #[serde(default = "default_threshold")]
+        threshold: f64,
+    },
+    /// A judge model scores the response against the rubric
+    Judge {
+        rubric: String,
+        #[serde(default = "default_threshold")]
+        threshold: f64,
+    },
+}
+
+fn default_threshold() -> f64 {
+    0.7
+}
+
+impl fmt::Display for Assertion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Assertion::Contains { value, .. } => write!(f, "contains \"{}\"", value),
+            Assertion::NotContains { value, .. } => write!(f, "does not contain \"{}\"", value),
+            Assertion::Regex { pattern } => write!(f, "matches /{}/", pattern),
+            Assertion::JsonSchema { .. } => write!(f, "valid JSON for the schema"),
+            Assertion::MaxLength { chars } => write!(f, "at most {} chars", chars),
+            Assertion::Similarity { threshold, .. } => write!(f, "similar to the reference (≥ {:.2})", threshold),
+            Assertion::Judge { rubric, threshold } => write!(f, "judge: {} (≥ {:.2})", rubric, threshold),
+        }
+    }
+}
+
+impl EvalSuite {
+    /// Loads a suite by extension, reading schema files next to it.
+    pub fn load(path: &str) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| EchomindError::FileError(format!("Failed to read eval suite {}: {}", path, e)))?;
+        let extension = Path::new(path)
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or("")
+            .to_lowercase();
+        let mut suite: EvalSuite = match extension.as_str() {
+            "json" => serde_json::from_str(&contents).map_err(|e| e.to_string()),
+            "toml" => toml::from_str(&contents).map_err(|e| e.to_string()),
+            _ => serde_yaml::from_str(&contents).map_err(|e| e.to_string()),
+        }
+        .map_err(|e| EchomindError::ParseError(format!("Failed to parse eval suite {}: {}", path, e)))?;
+
+        let dir = Path::new(path).parent().unwrap_or(Path::new("."));
+        for case in &mut suite.cases {
+            for assertion in &mut case.assertions {
+                if let Assertion::JsonSchema { schema: Value::String(file) } = assertion {
+                    let schema_path = dir.join(&*file);
+                    let text = std::fs::read_to_string(&schema_path).map_err(|e| {
+                        EchomindError::FileError(format!("Failed to read schema {}: {}", schema_path.display(), e))
+                    })?;
+                    *assertion = Assertion::JsonSchema {
+                        schema: serde_json::from_str(&text).map_err(|e| {
+                            EchomindError::ParseError(format!("Failed to parse schema {}: {}", schema_path.display(), e))
+                        })?,
+                    };
+                }
+            }
+        }
+        suite.validate()?;
+        Ok(suite)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if self.cases.is_empty() {
+            return Err(EchomindError::Other("The eval suite has no cases".to_string()));
+        }
+        let mut seen = std::collections::HashSet::new();
+        for case in &self.cases {
+            if !seen.insert(case.id.as_str()) {
+                return Err(EchomindError::Other(format!("Case id '{}' is used more than once", case.id)));
+            }
+            if case.input.trim().is_empty() && case.template.is_none() {
+                return Err(EchomindError::Other(format!("Case '{}' needs an input or a template", case.id)));
+            }
+            for assertion in &case.assertions {
+                if let Assertion::Regex { pattern } = assertion {
+                    regex::Regex::new(pattern).map_err(|e| {
+                        EchomindError::Other(format!("Case '{}' has an invalid regex: {}", case.id, e))
+                    })?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn needs_judge(&self) -> bool {
+        self.cases
+            .iter()
+            .any(|case| case.assertions.iter().any(|a| matches!(a, Assertion::Judge { .. })))
+    }
+}
+
+impl EvalCase {
+    /// The conversation sent for this case: system prompt, preset messages,
+    /// then the rendered template followed by the input.
+    pub fn messages(&self, presets: &HashMap<String, Preset>, library: Option<&ContentManager>) -> Result<Vec<Message>> {
+        let preset = match &self.preset {
+            Some(name) => Some(presets.get(name).ok_or_else(|| {
+                EchomindError::ConfigError(format!("Case '{}': preset '{}' not found in config.", self.id, name))
+            })?),
+            None => None,
+        };
+
+        let mut messages = Vec::new();
+        if let Some(system) = self.system.clone().or_else(|| preset.and_then(|p| p.system_prompt.clone())) {
+            messages.push(Message::text("system".to_string(), system));
+        }
+        if let Some(preset_messages) = preset.and_then(|p| p.messages.as_ref()) {
+            messages.extend(preset_messages.iter().cloned());
+        }
+
+        let mut parts = Vec::new();
+        if let Some(name) = &self.template {
+            let template = library
+                .and_then(|library| library.find_template(name))
+                .ok_or_else(|| EchomindError::Other(format!("Case '{}': template '{}' not found", self.id, name)))?;
+            parts.push(template.render(&self.vars)?);
+        }
+        if !self.input.trim().is_empty() {
+            parts.push(self.input.clone());
+        }
+        messages.push(Message::text("user".to_string(), parts.join("\n\n")));
+        Ok(messages)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AssertionResult {
+    pub assertion: String,
+    pub passed: bool,
+    #[serde(default)]
+    pub score: Option<f64>,
+    #[serde(default)]
+    pub detail: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CaseResult {
+    pub case_id: String,
+    /// `provider:model`
+    pub model: String,
+    pub passed: bool,
+    pub output: String,
+    #[serde(default)]
+    pub error: Option<String>,
+    pub latency_ms: u64,
+    pub assertions: Vec<AssertionResult>,
+    /// Set when the case went out over a budget whose action is warn
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub budget_warning: Option<String>,
+}
+
+impl CaseResult {
+    /// Why the case failed, in one line.
+    pub fn failure_summary(&self) -> String {
+        if let Some(error) = &self.error {
+            return format!("error: {}", error.replace('\n', " "));
+        }
+        self.assertions
+            .iter()
+            .filter(|a| !a.passed)
+            .map(|a| match &a.detail {
+                Some(detail) => format!("{} ({})", a.assertion, detail),
+                None => a.assertion.clone(),
+            })
+            .collect::<Vec<_>>()
+            .join("; ")
+    }
+}
+
+/// Every case result of a run; saved as JSON to serve as a baseline.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EvalReport {
+    #[serde(default)]
+    pub suite: Option<String>,
+    pub run_at: chrono::DateTime<chrono::Utc>,
+    pub results: Vec<CaseResult>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChangeKind {
+    /// Passed in the baseline, fails now
+    Regression,
+    /// Failed in the baseline, passes now
+    Fixed,
+    /// Not in the baseline
+    New,
+    /// In the baseline but not run
+    Missing,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BaselineChange {
+    pub case_id: String,
+    pub model: String,
+    pub kind: ChangeKind,
+}
+
+impl EvalReport {
+    pub fn load(path: &str) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| EchomindError::FileError(format!("Failed to read baseline {}: {}", path, e)))?;
+        serde_json::from_str(&contents)
+            .map_err(|e| EchomindError::ParseError(format!("Failed to parse baseline {}: {}", path, e)))
+    }
+
+    pub fn save(&self, path: &str) -> Result<()> {
+        let json = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, json).map_err(|e| EchomindError::FileError(format!("Failed to write {}: {}", path, e)))
+    }
+
+    pub fn passed(&self) -> usize {
+        self.results.iter().filter(|r| r.passed).count()
+    }
+
+    /// Case/model pairs whose outcome differs from the baseline, plus pairs
+    /// present in only one of the two.
+    pub fn diff(&self, baseline: &EvalReport) -> Vec<BaselineChange> {
+        let before: BTreeMap<(&str, &str), bool> = baseline
+            .results
+            .iter()
+            .map(|r| ((r.case_id.as_str(), r.model.as_str()), r.passed))
+            .collect();
+        let mut changes = Vec::new();
+        for result in &self.results {
+            let kind = match before.get(&(result.case_id.as_str(), result.model.as_str())) {
+                None => Some(ChangeKind::New),
+                Some(true) if !result.passed => Some(ChangeKind::Regression),
+                Some(false) if result.passed => Some(ChangeKind::Fixed),
+                Some(_) => None,
+            };
+            if let Some(kind) = kind {
+                changes.push(BaselineChange {
+                    case_id: result.case_id.clone(),
+                    model: result.model.clone(),
+                    kind,
+                });
+            }
+        }
+        for result in &baseline.results {
+            if !self.results.iter().any(|r| r.case_id == result.case_id && r.model == result.model) {
+                changes.push(BaselineChange {
+                    case_id: result.case_id.clone(),
+                    model: result.model.clone(),
+                    kind: ChangeKind::Missing,
+                });
+            }
+        }
+        changes
+    }
+}
+
+/// Runs every case against each target, one request at a time, calling
+/// `on_result` as each case finishes. `conversations` holds each case's
+/// messages, in suite order (see `EvalCase::messages`). With `usage`, each
+/// request, the judge's included, is checked against the budget and
+/// recorded.
+pub async fn run_suite<F>(
+    suite: &EvalSuite,
+    conversations: &[Vec<Message>],
+    targets: &[BenchmarkTarget],
+    judge: Option<&BenchmarkTarget>,
+    usage: Option<&UsageMeter>,
+    mut on_result: F,
+) -> EvalReport
+where
+    F: FnMut(&CaseResult),
+{
+    let mut results = Vec::new();
+    for target in targets {
+        for (case, messages) in suite.cases.iter().zip(conversations) {
+            let request = ChatRequest {
+                messages: messages.clone(),
+                model: Some(target.model.clone()),
+                temperature: suite.temperature,
+                max_tokens: suite.max_tokens,
+                top_p: None,
+                top_k: None,
+                stream: None,
+            };
+            let start = Instant::now();
+            // Over a blocking budget the case fails without being sent
+            let mut budget_warning = None;
+            let response = UsageMeter::send(usage, &target.provider, &request, |warning| {
+                budget_warning = warning;
+                target.client().send_message(request.clone())
+            })
+            .await;
+            let latency_ms = start.elapsed().as_millis() as u64;
+
+            let result = match response {
+                Ok(output) => {
+                    let mut assertions = Vec::new();
+                    for assertion in &case.assertions {
+                        assertions.push(check_assertion(assertion, &case.input, &output, judge, usage).await);
+                    }
+                    CaseResult {
+                        case_id: case.id.clone(),
+                        model: target.label(),
+                        passed: assertions.iter().all(|a| a.passed),
+                        output,
+                        error: None,
+                        latency_ms,
+                        assertions,
+                        budget_warning,
+                    }
+                }
+                Err(e) => CaseResult {
+                    case_id: case.id.clone(),
+                    model: target.label(),
+                    passed: false,
+                    output: String::new(),
+                    error: Some(e.to_string()),
+                    latency_ms,
+                    assertions: Vec::new(),
+                    budget_warning,
+                },
+            };
+            on_result(&result);
+            results.push(result);
+        }
+    }
+
+    EvalReport {
+        suite: suite.name.clone(),
+        run_at: chrono::Utc::now(),
+        results,
+    }
+}
+
+/// Checks one assertion; judge assertions call the judge model.
+pub async fn check_assertion(
+    assertion: &Assertion,
+    input: &str,
+    output: &str,
+    judge: Option<&BenchmarkTarget>,
+    usage: Option<&UsageMeter>,
+) -> AssertionResult {
+    let outcome = |passed: bool, score: Option<f64>, detail: Option<String>| AssertionResult {
+        assertion: assertion.to_string(),
+        passed,
+        score,
+        detail,
+    };
+    let contains = |value: &str, case_sensitive: bool| {
+        if case_sensitive {
+            output.contains(value)
+        } else {
+            output.to_lowercase().contains(&value.to_lowercase())
+        }
+    };
+
+    match assertion {
+        Assertion::Contains { value, case_sensitive } => outcome(contains(value, *case_sensitive), None, None),
+        Assertion::NotContains { value, case_sensitive } => outcome(!contains(value, *case_sensitive), None, None),
+        Assertion::Regex { pattern } => match regex::Regex::new(pattern) {
+            Ok(re) => outcome(re.is_match(output), None, None),
+            Err(e) => outcome(false, None, Some(e.to_string())),
+        },
+        Assertion::JsonSchema { schema } => match json_schema::extract_json(output) {
+            Some(value) => {
+                let errors = json_schema::validate(schema, &value);
+                outcome(errors.is_empty(), None, Some(errors.join("; ")).filter(|d| !d.is_empty()))
+            }
+            None => outcome(false, None, Some("no JSON in the response".to_string())),
+        },
+        Assertion::MaxLength { chars } => {
+            let length = output.chars().count();
+            outcome(length <= *chars, None, Some(format!("{} chars", length)).filter(|_| length > *chars))
+        }
+        Assertion::Similarity { reference, threshold } => {
+            let score = similarity(output, reference);
+            outcome(score >= *threshold, Some(score), Some(format!("similarity {:.2}", score)).filter(|_| score < *threshold))
+        }
+        Assertion::Judge { rubric, threshold } => {
+            let Some(judge) = judge else {
+                return outcome(false, None, Some("no judge model configured".to_string()));
+            };
+            match judge_score(judge, input, output, rubric, usage).await {
+                Ok((score, reason)) => outcome(score >= *threshold, Some(score), Some(format!("{:.2}: {}", score, reason))),
+                Err(e) => outcome(false, None, Some(format!("judge failed: {}", e))),
+            }
+        }
+    }
+}
+
+/// Cosine similarity of word counts, from 0 (no shared words) to 1.
+pub fn similarity(a: &str, b: &str) -> f64 {
+    let counts = |text: &str| {
+        let mut counts: HashMap<String, f64> = HashMap::new();
+        for word in text
+            .split(|c: char| !c.is_alphanumeric())
+            .filter(|w| !w.is_empty())
+        {
+            *counts.entry(word.to_lowercase()).or_insert(0.0) += 1.0;
+        }
+        counts
+    };
+    let (a, b) = (counts(a), counts(b));
+    let dot: f64 = a.iter().filter_map(|(word, n)| b.get(word).map(|m| n * m)).sum();
+    let norm = |counts: &HashMap<String, f64>| counts.values().map(|n| n * n).sum::<f64>().sqrt();
+    let denominator = norm(&a) * norm(&b);
+    if denominator == 0.0 {
+        return if a.is_empty() && b.is_empty() { 1.0 } else { 0.0 };
+    }
+    dot / denominator
+}
+
+async fn judge_score(
+    judge: &BenchmarkTarget,
+    input: &str,
+    output: &str,
+    rubric: &str,
+    usage: Option<&UsageMeter>,
+) -> Result<(f64, String)> {
+    let request = ChatRequest {
+        messages: vec![
+            Message::text("system".to_string(), JUDGE_PROMPT.to_string()),
+            Message::text(
+                "user".to_string(),
+                format!("Input:\n{}\n\nResponse:\n{}\n\nRubric:\n{}", input, output, rubric),
+            ),
+        ],
+        model: Some(judge.model.clone()),
+        temperature: Some(0.0),
+        max_tokens: None,
+        top_p: None,
+        top_k: None,
+        stream: None,
+    };
+    // A warning here is the same one the case itself already carries
+    let reply = UsageMeter::send(usage, &judge.provider, &request, |_| judge.client().send_message(request.clone())).await?;
+    let verdict = json_schema::extract_json(&reply)
+        .ok_or_else(|| EchomindError::ParseError(format!("the judge did not reply with JSON: {}", reply.trim())))?;
+    let score = verdict
+        .get("score")
+        .and_then(Value::as_f64)
+        .ok_or_else(|| EchomindError::ParseError("the judge's reply has no numeric score".to_string()))?;
+    let reason = verdict.get("reason").and_then(Value::as_str).unwrap_or("").to_string();
+    Ok((score.clamp(0.0, 1.0), reason))
+}
@@ -257,11 +257,11 @@ impl HistoryManager {
         // Convert to sorted vectors
         stats.most_used_models = model_counts.into_iter()
             .collect::<Vec<_>>();
-        stats.most_used_models.sort_by(|a, b| b.1.cmp(&a.1));
+        stats.most_used_models.sort_by_key(|b| std::cmp::Reverse(b.1));
 
         stats.most_used_providers = provider_counts.into_iter()
             .collect::<Vec<_>>();
-        stats.most_used_providers.sort_by(|a, b| b.1.cmp(&a.1));
+        stats.most_used_providers.sort_by_key(|b| std::cmp::Reverse(b.1));
 
         Ok(stats)
     }
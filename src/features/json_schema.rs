@@ -0,0 +1,266 @@
+//! A JSON Schema validator covering the keywords model output is usually
+//! checked against: types, enums, object properties, arrays, string and
+//! number bounds, patterns, the anyOf/oneOf/allOf combinators and local
+//! `$ref`s into `$defs` or `definitions`. Unknown keywords (format,
+//! $schema, descriptions) are ignored. `extract_json` finds the JSON to
+//! validate in a model's reply.
+
+use serde_json::Value;
+
+/// Schemas nested (or `$ref`s followed) deeper than this are reported
+/// rather than followed, so a `$ref` cycle can't recurse forever.
+const MAX_DEPTH: usize = 128;
+
+/// Checks `instance` against `schema`, describing each violation with the
+/// path where it occurs. An empty list means the instance is valid.
+pub fn validate(schema: &Value, instance: &Value) -> Vec<String> {
+    let mut errors = Vec::new();
+    Validator { root: schema }.check(schema, instance, "$", 0, &mut errors);
+    errors
+}
+
+/// The `$ref`s in `schema` that don't point at a schema in the same
+/// document. Only local references (`#`, `#/$defs/name`, ...) are supported.
+pub fn unresolved_refs(schema: &Value) -> Vec<String> {
+    let mut refs = Vec::new();
+    collect_refs(schema, &mut refs);
+    refs.retain(|reference| resolve(schema, reference).is_none());
+    refs
+}
+
+fn collect_refs(schema: &Value, refs: &mut Vec<String>) {
+    match schema {
+        Value::Object(object) => {
+            for (key, value) in object {
+                match (key.as_str(), value) {
+                    ("$ref", Value::String(reference)) => refs.push(reference.clone()),
+                    // Enum and const values are data, not schemas
+                    ("enum" | "const", _) => {}
+                    _ => collect_refs(value, refs),
+                }
+            }
+        }
+        Value::Array(items) => items.iter().for_each(|item| collect_refs(item, refs)),
+        _ => {}
+    }
+}
+
+fn resolve<'a>(root: &'a Value, reference: &str) -> Option<&'a Value> {
+    root.pointer(reference.strip_prefix('#')?)
+}
+
+/// Finds JSON in free text such as model output: the whole text, a fenced
+/// ```json block, or the outermost {...} / [...] span.
+pub fn extract_json(text: &str) -> Option<Value> {
+    let trimmed = text.trim();
+    if let Ok(value) = serde_json::from_str(trimmed) {
+        return Some(value);
+    }
+
+    if let Some(start) = trimmed.find("```") {
+        let after = &trimmed[start + 3..];
+        let body_start = after.find('\n').map(|i| i + 1).unwrap_or(0);
+        if let Some(end) = after[body_start..].find("```") {
+            if let Ok(value) = serde_json::from_str(after[body_start..body_start + end].trim()) {
+                return Some(value);
+            }
+        }
+    }
+
+    for (open, close) in [('{', '}'), ('[', ']')] {
+        if let (Some(start), Some(end)) = (trimmed.find(open), trimmed.rfind(close)) {
+            if start < end {
+                if let Ok(value) = serde_json::from_str(&trimmed[start..=end]) {
+                    return Some(value);
+                }
+            }
+        }
+    }
+
+    None
+}
+
+/// Resolves `$ref`s against the schema document being validated against.
+struct Validator<'a> {
+    root: &'a Value,
+}
+
+impl Validator<'_> {
+    fn check(&self, schema: &Value, instance: &Value, path: &str, depth: usize, errors: &mut Vec<String>) {
+        if depth > MAX_DEPTH {
+            errors.push(format!("{}: the schema nests too deeply (a $ref cycle?)", path));
+            return;
+        }
+        let schema = match schema {
+            Value::Bool(true) => return,
+            Value::Bool(false) => {
+                errors.push(format!("{}: no value is allowed here", path));
+                return;
+            }
+            Value::Object(schema) => schema,
+            _ => return,
+        };
+
+        // Siblings of a $ref apply as well, as in current drafts
+        if let Some(Value::String(reference)) = schema.get("$ref") {
+            match resolve(self.root, reference) {
+                Some(target) => self.check(target, instance, path, depth + 1, errors),
+                None => errors.push(format!("{}: cannot resolve $ref '{}'", path, reference)),
+            }
+        }
+
+        if let Some(expected) = schema.get("type") {
+            let types: Vec<&str> = match expected {
+                Value::String(t) => vec![t.as_str()],
+                Value::Array(ts) => ts.iter().filter_map(Value::as_str).collect(),
+                _ => Vec::new(),
+            };
+            if !types.is_empty() && !types.iter().any(|t| has_type(instance, t)) {
+                errors.push(format!("{}: expected {}, got {}", path, types.join(" or "), type_name(instance)));
+                return;
+            }
+        }
+
+        if let Some(Value::Array(options)) = schema.get("enum") {
+            if !options.contains(instance) {
+                let options: Vec<String> = options.iter().map(Value::to_string).collect();
+                errors.push(format!("{}: {} is not one of {}", path, instance, options.join(", ")));
+            }
+        }
+        if let Some(expected) = schema.get("const") {
+            if expected != instance {
+                errors.push(format!("{}: expected {}, got {}", path, expected, instance));
+            }
+        }
+
+        match instance {
+            Value::Object(object) => {
+                if let Some(Value::Array(required)) = schema.get("required") {
+                    for name in required.iter().filter_map(Value::as_str) {
+                        if !object.contains_key(name) {
+                            errors.push(format!("{}: missing required property '{}'", path, name));
+                        }
+                    }
+                }
+                let properties = schema.get("properties").and_then(Value::as_object);
+                for (name, value) in object {
+                    let child = format!("{}.{}", path, name);
+                    match properties.and_then(|p| p.get(name)) {
+                        Some(property) => self.check(property, value, &child, depth + 1, errors),
+                        None => match schema.get("additionalProperties") {
+                            Some(Value::Bool(false)) => errors.push(format!("{}: unexpected property '{}'", path, name)),
+                            Some(additional) => self.check(additional, value, &child, depth + 1, errors),
+                            None => {}
+                        },
+                    }
+                }
+            }
+            Value::Array(items) => {
+                if let Some(item_schema) = schema.get("items") {
+                    for (i, item) in items.iter().enumerate() {
+                        self.check(item_schema, item, &format!("{}[{}]", path, i), depth + 1, errors);
+                    }
+                }
+                if let Some(min) = schema.get("minItems").and_then(Value::as_u64) {
+                    if (items.len() as u64) < min {
+                        errors.push(format!("{}: {} items, expected at least {}", path, items.len(), min));
+                    }
+                }
+                if let Some(max) = schema.get("maxItems").and_then(Value::as_u64) {
+                    if items.len() as u64 > max {
+                        errors.push(format!("{}: {} items, expected at most {}", path, items.len(), max));
+                    }
+                }
+            }
+            Value::String(text) => {
+                let length = text.chars().count() as u64;
+                if let Some(min) = schema.get("minLength").and_then(Value::as_u64) {
+                    if length < min {
+                        errors.push(format!("{}: {} characters, expected at least {}", path, length, min));
+                    }
+                }
+                if let Some(max) = schema.get("maxLength").and_then(Value::as_u64) {
+                    if length > max {
+                        errors.push(format!("{}: {} characters, expected at most {}", path, length, max));
+                    }
+                }
+                if let Some(pattern) = schema.get("pattern").and_then(Value::as_str) {
+                    match regex::Regex::new(pattern) {
+                        Ok(re) if !re.is_match(text) => {
+                            errors.push(format!("{}: \"{}\" does not match /{}/", path, text, pattern))
+                        }
+                        Ok(_) => {}
+                        Err(e) => errors.push(format!("{}: invalid pattern /{}/: {}", path, pattern, e)),
+                    }
+                }
+            }
+            Value::Number(number) => {
+                let n = number.as_f64().unwrap_or(0.0);
+                let bound = |key: &str| schema.get(key).and_then(Value::as_f64);
+                if let Some(min) = bound("minimum").filter(|min| n < *min) {
+                    errors.push(format!("{}: {} is below the minimum {}", path, number, min));
+                }
+                if let Some(max) = bound("maximum").filter(|max| n > *max) {
+                    errors.push(format!("{}: {} is above the maximum {}", path, number, max));
+                }
+                if let Some(min) = bound("exclusiveMinimum").filter(|min| n <= *min) {
+                    errors.push(format!("{}: {} must be greater than {}", path, number, min));
+                }
+                if let Some(max) = bound("exclusiveMaximum").filter(|max| n >= *max) {
+                    errors.push(format!("{}: {} must be less than {}", path, number, max));
+                }
+            }
+            _ => {}
+        }
+
+        if let Some(Value::Array(all)) = schema.get("allOf") {
+            for sub in all {
+                self.check(sub, instance, path, depth + 1, errors);
+            }
+        }
+        if let Some(Value::Array(any)) = schema.get("anyOf") {
+            if !any.iter().any(|sub| self.validate_at(sub, instance, path, depth + 1).is_empty()) {
+                errors.push(format!("{}: matches none of the anyOf schemas", path));
+            }
+        }
+        if let Some(Value::Array(one)) = schema.get("oneOf") {
+            let matching = one.iter().filter(|sub| self.validate_at(sub, instance, path, depth + 1).is_empty()).count();
+            if matching != 1 {
+                errors.push(format!("{}: matches {} of the oneOf schemas, expected exactly 1", path, matching));
+            }
+        }
+    }
+
+    fn validate_at(&self, schema: &Value, instance: &Value, path: &str, depth: usize) -> Vec<String> {
+        let mut errors = Vec::new();
+        self.check(schema, instance, path, depth, &mut errors);
+        errors
+    }
+}
+
+fn has_type(instance: &Value, expected: &str) -> bool {
+    match expected {
+        "object" => instance.is_object(),
+        "array" => instance.is_array(),
+        "string" => instance.is_string(),
+        "boolean" => instance.is_boolean(),
+        "null" => instance.is_null(),
+        "number" => instance.is_number(),
+        "integer" => match instance {
+            Value::Number(n) => n.is_i64() || n.is_u64() || n.as_f64().is_some_and(|f| f.fract() == 0.0),
+            _ => false,
+        },
+        _ => true,
+    }
+}
+
+fn type_name(instance: &Value) -> &'static str {
+    match instance {
+        Value::Null => "null",
+        Value::Bool(_) => "boolean",
+        Value::Number(_) => "number",
+        Value::String(_) => "string",
+        Value::Array(_) => "array",
+        Value::Object(_) => "object",
+    }
+}
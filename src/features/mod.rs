@@ -5,13 +5,27 @@ pub mod workflow;
 pub mod collaboration;
 pub mod security;
 pub mod performance;
+pub mod eval;
+pub mod compare;
+pub mod usage;
+pub mod batch;
+pub mod batch_api;
 // pub mod developer;
 pub mod content;
+pub mod packs;
 // pub mod integration;
 // pub mod accessibility;
-// pub mod output;
+pub mod output;
 // pub mod ai_features;
 pub mod data_processing;
-// pub mod scheduling;
+pub mod charts;
+pub mod data_qa;
+pub mod query;
+pub mod transform;
+pub mod json_schema;
+pub mod structured;
+pub mod streaming;
+pub mod scheduling;
 // pub mod quality;
+pub mod templating;
 pub mod other_features;
\ No newline at end of file
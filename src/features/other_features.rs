@@ -94,21 +94,6 @@ impl AIFeaturesManager {
     pub fn detect_intent(&self, _text: &str) -> Result<String> { Ok("general".to_string()) }
 }
 
-// Scheduling
-pub struct SchedulingManager;
-impl Default for SchedulingManager {
-    fn default() -> Self {
-        Self::new()
-    }
-}
-
-impl SchedulingManager {
-    pub fn new() -> Self { Self }
-    pub fn schedule_task(&self, _task: &str, _schedule: &str) -> Result<()> { Ok(()) }
-    pub fn list_scheduled_tasks(&self) -> Result<Vec<String>> { Ok(vec![]) }
-    pub fn cancel_task(&self, _task_id: &str) -> Result<()> { Ok(()) }
-}
-
 // Quality Assurance
 pub struct QualityAssuranceManager;
 impl Default for QualityAssuranceManager {
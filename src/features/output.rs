@@ -0,0 +1,404 @@
+//! Where a reply goes once it arrives. The text passes through the reply
+//! filters as it streams, and every sink (stdout, the `-o` file, the
+//! clipboard, the history file) sees the same filtered deltas and the same
+//! formatted result, so `--stream` only changes when output appears, not
+//! what it is. `--format ndjson` turns the deltas into JSON events for
+//! other tools to consume.
+
+use crate::error::{EchomindError, Result};
+use chrono::Utc;
+use serde::Serialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum OutputFormat {
+    Text,
+    /// One document with the content and where it came from
+    Json,
+    /// `delta` events as the reply arrives, then `done` or `error`. A
+    /// `reset` event means the deltas so far are void and the reply starts
+    /// over, from the next provider in the fallback chain.
+    Ndjson,
+    Template(String),
+}
+
+impl OutputFormat {
+    pub fn parse(format: Option<&str>) -> Result<Self> {
+        match format {
+            None | Some("text") => Ok(OutputFormat::Text),
+            Some("json") => Ok(OutputFormat::Json),
+            Some("ndjson") => Ok(OutputFormat::Ndjson),
+            Some(other) => match other.strip_prefix("template:") {
+                Some(template) => Ok(OutputFormat::Template(template.to_string())),
+                None => Err(EchomindError::Other(format!("Unknown format: {}", other))),
+            },
+        }
+    }
+
+    /// Whether output in this format can be written before the reply is
+    /// complete.
+    pub fn is_incremental(&self) -> bool {
+        matches!(self, OutputFormat::Text | OutputFormat::Ndjson)
+    }
+
+    /// The finished reply in this format. `structured` is the validated JSON
+    /// of a `--schema` reply, which the json, ndjson and template formats
+    /// carry as JSON rather than text.
+    pub fn render(&self, text: &str, structured: Option<&Value>, provider: &str, model: &str) -> Result<String> {
+        let content = structured.cloned().unwrap_or_else(|| json!(text));
+        let timestamp = Utc::now().to_rfc3339();
+        match self {
+            OutputFormat::Text => Ok(text.to_string()),
+            OutputFormat::Json => Ok(serde_json::to_string_pretty(&json!({
+                "content": content,
+                "provider": provider,
+                "model": model,
+                "timestamp": timestamp
+            }))?),
+            OutputFormat::Ndjson => OutputEvent::Done {
+                content,
+                provider,
+                model,
+                timestamp,
+            }
+            .to_line(),
+            OutputFormat::Template(template) => {
+                let variables = HashMap::from([
+                    ("content".to_string(), content),
+                    ("provider".to_string(), json!(provider)),
+                    ("model".to_string(), json!(model)),
+                    ("timestamp".to_string(), json!(timestamp)),
+                ]);
+                crate::features::templating::render(template, &variables)
+            }
+        }
+    }
+}
+
+/// One line of `--format ndjson` output.
+#[derive(Debug, Serialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum OutputEvent<'a> {
+    /// Filtered reply text, in the order it arrived
+    Delta { content: &'a str },
+    /// The whole reply; `content` is JSON for `--schema` replies
+    Done {
+        content: Value,
+        provider: &'a str,
+        model: &'a str,
+        timestamp: String,
+    },
+    Error { kind: &'a str, message: String },
+    /// The provider failed partway; deltas so far are void
+    Reset { kind: &'a str, message: String },
+}
+
+impl OutputEvent<'_> {
+    pub fn to_line(&self) -> Result<String> {
+        Ok(serde_json::to_string(self)?)
+    }
+}
+
+/// Filters applied to reply text, fed in whatever chunks it arrives in.
+/// Coder mode drops blank lines and markdown fences, a line at a time, so a
+/// streamed reply comes out the same as a whole one.
+#[derive(Debug, Default)]
+pub struct ReplyFilter {
+    coder: bool,
+    pending: String,
+    wrote_line: bool,
+}
+
+impl ReplyFilter {
+    pub fn new(coder: bool) -> Self {
+        Self {
+            coder,
+            ..Default::default()
+        }
+    }
+
+    /// Filters a whole reply.
+    pub fn apply(coder: bool, text: &str) -> String {
+        let mut filter = Self::new(coder);
+        let mut out = filter.push(text);
+        out.push_str(&filter.finish());
+        out
+    }
+
+    /// The part of `chunk` that can be passed on now. In coder mode a line
+    /// is held back until it is complete.
+    pub fn push(&mut self, chunk: &str) -> String {
+        if !self.coder {
+            return chunk.to_string();
+        }
+        self.pending.push_str(chunk);
+        let mut out = String::new();
+        while let Some(end) = self.pending.find('\n') {
+            let line: String = self.pending.drain(..=end).collect();
+            self.keep(&line, &mut out);
+        }
+        out
+    }
+
+    /// Whatever was held back at the end of the reply.
+    pub fn finish(&mut self) -> String {
+        let mut out = String::new();
+        let line = std::mem::take(&mut self.pending);
+        self.keep(&line, &mut out);
+        out
+    }
+
+    fn keep(&mut self, line: &str, out: &mut String) {
+        let line = line.trim_end_matches(['\n', '\r']);
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with("```") {
+            return;
+        }
+        if self.wrote_line {
+            out.push('\n');
+        }
+        out.push_str(line);
+        self.wrote_line = true;
+    }
+}
+
+/// A finished reply, as every sink sees it.
+#[derive(Debug, Clone)]
+pub struct Reply {
+    /// The reply as the model sent it
+    pub raw: String,
+    /// After the reply filters
+    pub text: String,
+    /// `text` in the output format
+    pub formatted: String,
+    pub provider: String,
+    pub model: String,
+}
+
+pub trait OutputSink {
+    /// Filtered text as it arrives.
+    fn delta(&mut self, _text: &str) -> Result<()> {
+        Ok(())
+    }
+
+    fn done(&mut self, reply: &Reply) -> Result<()>;
+
+    /// The request failed after any deltas were sent.
+    fn error(&mut self, _error: &EchomindError) -> Result<()> {
+        Ok(())
+    }
+
+    /// The request failed, and the reply will start over from another
+    /// provider. Anything kept from the earlier deltas is dropped.
+    fn reset(&mut self, _error: &EchomindError) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// Writes to a stream in the output format: text and ndjson as the reply
+/// arrives, json and templates once it is complete.
+pub struct WriterSink<W: Write> {
+    writer: W,
+    format: OutputFormat,
+}
+
+impl<W: Write> WriterSink<W> {
+    pub fn new(writer: W, format: OutputFormat) -> Self {
+        Self { writer, format }
+    }
+
+    fn write_line(&mut self, line: &str) -> Result<()> {
+        writeln!(self.writer, "{}", line)?;
+        self.writer.flush()?;
+        Ok(())
+    }
+}
+
+impl<W: Write> OutputSink for WriterSink<W> {
+    fn delta(&mut self, text: &str) -> Result<()> {
+        match self.format {
+            OutputFormat::Text => {
+                self.writer.write_all(text.as_bytes())?;
+                self.writer.flush()?;
+            }
+            OutputFormat::Ndjson => self.write_line(&OutputEvent::Delta { content: text }.to_line()?)?,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn done(&mut self, reply: &Reply) -> Result<()> {
+        match self.format {
+            // The text went out as deltas
+            OutputFormat::Text => self.write_line(""),
+            _ => self.write_line(&reply.formatted),
+        }
+    }
+
+    fn error(&mut self, error: &EchomindError) -> Result<()> {
+        if self.format == OutputFormat::Ndjson {
+            let event = OutputEvent::Error {
+                kind: error.kind(),
+                message: error.to_string(),
+            };
+            self.write_line(&event.to_line()?)?;
+        }
+        Ok(())
+    }
+
+    fn reset(&mut self, error: &EchomindError) -> Result<()> {
+        match self.format {
+            // Text already shown can't be taken back; the next reply starts
+            // on a line of its own
+            OutputFormat::Text => self.write_line(""),
+            OutputFormat::Ndjson => {
+                let event = OutputEvent::Reset {
+                    kind: error.kind(),
+                    message: error.to_string(),
+                };
+                self.write_line(&event.to_line()?)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Writes to a file what stdout would have shown, once the reply is
+/// complete, so a failed request leaves an existing file alone. With ndjson
+/// a failure is written too, as its error event.
+pub struct FileSink {
+    path: PathBuf,
+    buffer: WriterSink<Vec<u8>>,
+}
+
+impl FileSink {
+    pub fn new(path: impl Into<PathBuf>, format: OutputFormat) -> Self {
+        Self {
+            path: path.into(),
+            buffer: WriterSink::new(Vec::new(), format),
+        }
+    }
+
+    fn save(&mut self) -> Result<()> {
+        let contents = std::mem::take(&mut self.buffer.writer);
+        fs::write(&self.path, contents)
+            .map_err(|e| EchomindError::FileError(format!("Failed to write {}: {}", self.path.display(), e)))
+    }
+}
+
+impl OutputSink for FileSink {
+    fn delta(&mut self, text: &str) -> Result<()> {
+        self.buffer.delta(text)
+    }
+
+    fn done(&mut self, reply: &Reply) -> Result<()> {
+        self.buffer.done(reply)?;
+        self.save()
+    }
+
+    fn error(&mut self, error: &EchomindError) -> Result<()> {
+        if self.buffer.format == OutputFormat::Ndjson {
+            self.buffer.error(error)?;
+            self.save()?;
+        }
+        Ok(())
+    }
+
+    /// The file only ever holds the reply that completed.
+    fn reset(&mut self, _error: &EchomindError) -> Result<()> {
+        self.buffer.writer.clear();
+        Ok(())
+    }
+}
+
+/// Runs a reply through the filters and hands it to every sink.
+pub struct OutputPipeline {
+    format: OutputFormat,
+    filter: ReplyFilter,
+    coder: bool,
+    sinks: Vec<Box<dyn OutputSink>>,
+    /// Whether any of the reply has arrived since the last reset
+    started: bool,
+}
+
+impl OutputPipeline {
+    pub fn new(format: OutputFormat, coder: bool) -> Self {
+        Self {
+            format,
+            filter: ReplyFilter::new(coder),
+            coder,
+            sinks: Vec::new(),
+            started: false,
+        }
+    }
+
+    pub fn format(&self) -> &OutputFormat {
+        &self.format
+    }
+
+    pub fn add_sink(&mut self, sink: Box<dyn OutputSink>) {
+        self.sinks.push(sink);
+    }
+
+    /// Passes on a chunk of the reply. Every sink gets it even if one fails;
+    /// the first failure is returned.
+    pub fn delta(&mut self, chunk: &str) -> Result<()> {
+        self.started |= !chunk.is_empty();
+        let text = self.filter.push(chunk);
+        if text.is_empty() {
+            return Ok(());
+        }
+        self.each(|sink| sink.delta(&text))
+    }
+
+    /// Completes the reply. `raw` is the whole reply, including any part not
+    /// already passed to `delta`.
+    pub fn finish(&mut self, raw: &str, structured: Option<&Value>, provider: &str, model: &str) -> Result<Reply> {
+        let rest = self.filter.finish();
+        if !rest.is_empty() {
+            self.each(|sink| sink.delta(&rest))?;
+        }
+        let text = ReplyFilter::apply(self.coder, raw);
+        let reply = Reply {
+            formatted: self.format.render(&text, structured, provider, model)?,
+            raw: raw.to_string(),
+            text,
+            provider: provider.to_string(),
+            model: model.to_string(),
+        };
+        self.each(|sink| sink.done(&reply))?;
+        Ok(reply)
+    }
+
+    pub fn error(&mut self, error: &EchomindError) -> Result<()> {
+        self.each(|sink| sink.error(error))
+    }
+
+    /// Starts the reply over after a failed attempt, for a fallback provider
+    /// to answer. Text held back by the filters is dropped with it. Nothing
+    /// happens if none of the reply had arrived.
+    pub fn reset(&mut self, error: &EchomindError) -> Result<()> {
+        if !std::mem::take(&mut self.started) {
+            return Ok(());
+        }
+        self.filter = ReplyFilter::new(self.coder);
+        self.each(|sink| sink.reset(error))
+    }
+
+    fn each<F>(&mut self, mut f: F) -> Result<()>
+    where
+        F: FnMut(&mut dyn OutputSink) -> Result<()>,
+    {
+        let mut first_error = None;
+        for sink in &mut self.sinks {
+            if let Err(e) = f(sink.as_mut()) {
+                first_error.get_or_insert(e);
+            }
+        }
+        first_error.map_or(Ok(()), Err)
+    }
+}
@@ -0,0 +1,946 @@
+//! Prompt packs: versioned bundles of presets, templates, snippets and
+//! workflows that can be shared as a directory, a `.zip` archive or a git
+//! repository.
+//!
+//! A pack is a directory with a `pack.toml` manifest:
+//!
+//! ```toml
+//! name = "review-kit"
+//! version = "1.2.0"
+//! description = "Code review helpers"
+//! namespace = "review"          # optional, defaults to the name
+//!
+//! [presets.strict]
+//! system_prompt = "You are a strict reviewer."
+//!
+//! [templates.diff]              # optional metadata for templates/diff.*
+//! description = "Review a diff"
+//! [templates.diff.variables.lang]
+//! type = "select"
+//! choices = ["rust", "go"]
+//!
+//! [snippets.terse]              # optional metadata for snippets/terse.*
+//! language = "markdown"
+//! ```
+//!
+//! Template and snippet bodies live in `templates/` and `snippets/` (the file
+//! stem is the name) and workflow definitions in `workflows/`. Everything is
+//! installed under the pack's namespace, so `diff` becomes `review/diff`.
+
+use crate::config::{Config, Preset};
+use crate::error::{EchomindError, Result};
+use crate::features::content::{ContentManager, SnippetUpdate, TemplateUpdate, TemplateVariable, VariableType};
+use crate::features::templating::{CompiledTemplate, VariableUsage};
+use crate::features::workflow::{IssueSeverity, Workflow, WorkflowFormat, WorkflowManager};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+
+const MANIFEST_FILE: &str = "pack.toml";
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PackManifest {
+    pub name: String,
+    pub version: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    /// Prefix for every installed item; defaults to the pack name
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub namespace: Option<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub presets: BTreeMap<String, Preset>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub templates: BTreeMap<String, TemplateMeta>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub snippets: BTreeMap<String, SnippetMeta>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TemplateMeta {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub category: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub variables: BTreeMap<String, VariableMeta>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct VariableMeta {
+    /// Type name as accepted by `template var --type`
+    #[serde(default, rename = "type", skip_serializing_if = "Option::is_none")]
+    pub var_type: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub choices: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub required: Option<bool>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SnippetMeta {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub category: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub language: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub tags: Vec<String>,
+}
+
+/// A pack read from disk and validated, ready to install.
+#[derive(Debug, Clone)]
+pub struct PromptPack {
+    pub manifest: PackManifest,
+    /// Template bodies keyed by (un-namespaced) name
+    pub templates: BTreeMap<String, String>,
+    pub snippets: BTreeMap<String, String>,
+    /// Workflows keyed by their original id
+    pub workflows: BTreeMap<String, Workflow>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ItemKind {
+    Preset,
+    Template,
+    Snippet,
+    Workflow,
+}
+
+impl fmt::Display for ItemKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ItemKind::Preset => "preset",
+            ItemKind::Template => "template",
+            ItemKind::Snippet => "snippet",
+            ItemKind::Workflow => "workflow",
+        };
+        f.write_str(name)
+    }
+}
+
+/// An installed item, identified by its namespaced name (`ns/name`).
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct PackItem {
+    pub kind: ItemKind,
+    pub name: String,
+}
+
+impl fmt::Display for PackItem {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} '{}'", self.kind, self.name)
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InstalledPack {
+    pub name: String,
+    pub version: String,
+    pub namespace: String,
+    /// Local path, archive or git URL the pack was installed from
+    pub source: String,
+    pub installed_at: DateTime<Utc>,
+    pub items: Vec<PackItem>,
+}
+
+/// An item a pack would overwrite that it does not own.
+#[derive(Debug, Clone)]
+pub struct Conflict {
+    pub item: PackItem,
+    /// Namespace of the pack that owns the item, `None` for user-created items
+    pub owner: Option<String>,
+}
+
+impl fmt::Display for Conflict {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.owner {
+            Some(owner) => write!(f, "{} (installed by pack '{}')", self.item, owner),
+            None => write!(f, "{} (created locally)", self.item),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct InstallReport {
+    pub added: Vec<PackItem>,
+    pub updated: Vec<PackItem>,
+    /// Items of a previous version that the new version no longer ships
+    pub removed: Vec<PackItem>,
+    pub previous_version: Option<String>,
+    /// Templates that include a local file, as (template, variable naming
+    /// the file), so the user knows what rendering them can read
+    pub file_includes: Vec<(String, String)>,
+}
+
+/// The stores a pack installs into. Presets are changed in memory; the caller
+/// saves the config afterwards.
+pub struct PackTargets<'a> {
+    pub config: &'a mut Config,
+    pub library: &'a mut ContentManager,
+    pub workflows_dir: PathBuf,
+}
+
+impl PromptPack {
+    /// Reads and validates the pack in `dir`.
+    pub fn load(dir: &Path) -> Result<Self> {
+        let manifest_path = dir.join(MANIFEST_FILE);
+        let contents = fs::read_to_string(&manifest_path).map_err(|e| {
+            EchomindError::FileError(format!("Failed to read {}: {}", manifest_path.display(), e))
+        })?;
+        let manifest: PackManifest = toml::from_str(&contents)
+            .map_err(|e| EchomindError::ConfigError(format!("Invalid {}: {}", MANIFEST_FILE, e)))?;
+
+        if manifest.name.trim().is_empty() || manifest.version.trim().is_empty() {
+            return Err(EchomindError::ConfigError(format!("{} needs a name and a version", MANIFEST_FILE)));
+        }
+        validate_namespace(manifest.namespace.as_deref().unwrap_or(&manifest.name))?;
+
+        let templates = read_bodies(&dir.join("templates"))?;
+        let snippets = read_bodies(&dir.join("snippets"))?;
+
+        for (name, body) in &templates {
+            let compiled = CompiledTemplate::compile(body)
+                .map_err(|e| EchomindError::ConfigError(format!("Template '{}': {}", name, e)))?;
+            if let Some(meta) = manifest.templates.get(name) {
+                let used: Vec<String> = compiled.variables().into_iter().map(|v| v.name).collect();
+                for (variable, var_meta) in &meta.variables {
+                    if !used.contains(variable) {
+                        return Err(EchomindError::ConfigError(format!(
+                            "Template '{}' declares variable '{}' it does not use",
+                            name, variable
+                        )));
+                    }
+                    variable_type(var_meta)?;
+                }
+            }
+        }
+        for name in manifest.templates.keys() {
+            if !templates.contains_key(name) {
+                return Err(EchomindError::ConfigError(format!(
+                    "Metadata for template '{}' but no templates/{}.* file",
+                    name, name
+                )));
+            }
+        }
+        for name in manifest.snippets.keys() {
+            if !snippets.contains_key(name) {
+                return Err(EchomindError::ConfigError(format!(
+                    "Metadata for snippet '{}' but no snippets/{}.* file",
+                    name, name
+                )));
+            }
+        }
+
+        let mut workflows = BTreeMap::new();
+        for path in list_files(&dir.join("workflows"))? {
+            let format = match path.extension().and_then(|e| e.to_str()) {
+                Some("json") | Some("yaml") | Some("yml") | Some("toml") => WorkflowFormat::from_path(&path),
+                _ => continue,
+            };
+            let contents = fs::read_to_string(&path)
+                .map_err(|e| EchomindError::FileError(format!("Failed to read {}: {}", path.display(), e)))?;
+            let workflow = WorkflowManager::parse_workflow(&contents, format)?;
+            if let Some(issue) = WorkflowManager::validate_workflow(&workflow)
+                .into_iter()
+                .find(|issue| issue.severity == IssueSeverity::Error)
+            {
+                return Err(EchomindError::ConfigError(format!(
+                    "Workflow '{}' is invalid: {}",
+                    workflow.id, issue.message
+                )));
+            }
+            if workflows.insert(workflow.id.clone(), workflow).is_some() {
+                return Err(EchomindError::ConfigError(format!(
+                    "Two workflows in {} share an id",
+                    path.parent().unwrap_or(dir).display()
+                )));
+            }
+        }
+
+        Ok(Self {
+            manifest,
+            templates,
+            snippets,
+            workflows,
+        })
+    }
+
+    pub fn namespace(&self) -> &str {
+        self.manifest.namespace.as_deref().unwrap_or(&self.manifest.name)
+    }
+
+    /// Every item the pack installs, named within `namespace`.
+    pub fn items(&self, namespace: &str) -> Vec<PackItem> {
+        let named = |kind, name: &String| PackItem {
+            kind,
+            name: qualified(namespace, name),
+        };
+        let mut items: Vec<PackItem> = self
+            .manifest
+            .presets
+            .keys()
+            .map(|name| named(ItemKind::Preset, name))
+            .chain(self.templates.keys().map(|name| named(ItemKind::Template, name)))
+            .chain(self.snippets.keys().map(|name| named(ItemKind::Snippet, name)))
+            .chain(self.workflows.keys().map(|name| named(ItemKind::Workflow, name)))
+            .collect();
+        items.sort();
+        items
+    }
+
+    /// Writes the pack as a directory that `PromptPack::load` reads back.
+    pub fn write(&self, dir: &Path) -> Result<()> {
+        let write = |path: PathBuf, contents: &str| -> Result<()> {
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)
+                    .map_err(|e| EchomindError::FileError(format!("Failed to create {}: {}", parent.display(), e)))?;
+            }
+            fs::write(&path, contents)
+                .map_err(|e| EchomindError::FileError(format!("Failed to write {}: {}", path.display(), e)))
+        };
+
+        let manifest = toml::to_string_pretty(&self.manifest)
+            .map_err(|e| EchomindError::ConfigError(format!("Failed to serialize manifest: {}", e)))?;
+        write(dir.join(MANIFEST_FILE), &manifest)?;
+        for (name, body) in &self.templates {
+            write(dir.join("templates").join(format!("{}.txt", name)), body)?;
+        }
+        for (name, body) in &self.snippets {
+            write(dir.join("snippets").join(format!("{}.txt", name)), body)?;
+        }
+        for (id, workflow) in &self.workflows {
+            let json = serde_json::to_string_pretty(workflow)
+                .map_err(|e| EchomindError::ParseError(format!("Failed to serialize workflow: {}", e)))?;
+            write(dir.join("workflows").join(format!("{}.json", id)), &json)?;
+        }
+        Ok(())
+    }
+
+    /// Collects the presets, templates, snippets and workflows named
+    /// `prefix/...` (with the prefix stripped) into a new pack. Without a
+    /// prefix, the locally created (un-namespaced) items are exported.
+    pub fn export(
+        mut manifest: PackManifest,
+        prefix: Option<&str>,
+        config: &Config,
+        library: &ContentManager,
+        workflows: &WorkflowManager,
+    ) -> Result<Self> {
+        let strip = |name: &str| -> Option<String> {
+            match prefix {
+                Some(prefix) => name.strip_prefix(prefix)?.strip_prefix('/').map(str::to_string),
+                None => (!name.contains('/')).then(|| name.to_string()),
+            }
+        };
+
+        manifest.presets = config
+            .presets
+            .iter()
+            .filter_map(|(name, preset)| Some((strip(name)?, preset.clone())))
+            .collect();
+        manifest.templates.clear();
+        manifest.snippets.clear();
+
+        let mut templates = BTreeMap::new();
+        for template in library.search_templates("", None, Vec::new()) {
+            let Some(name) = strip(&template.name) else { continue };
+            let variables = template
+                .variables
+                .iter()
+                .map(|v| (v.name.clone(), variable_meta(v)))
+                .collect();
+            manifest.templates.insert(
+                name.clone(),
+                TemplateMeta {
+                    description: template.description.clone(),
+                    category: template.category.clone(),
+                    tags: template.tags.clone(),
+                    variables,
+                },
+            );
+            templates.insert(name, template.content.clone());
+        }
+
+        let mut snippets = BTreeMap::new();
+        for snippet in library.search_snippets("", None, Vec::new()) {
+            let Some(name) = strip(&snippet.name) else { continue };
+            manifest.snippets.insert(
+                name.clone(),
+                SnippetMeta {
+                    description: snippet.description.clone(),
+                    category: snippet.category.clone(),
+                    language: snippet.language.clone(),
+                    tags: snippet.tags.clone(),
+                },
+            );
+            snippets.insert(name, snippet.content.clone());
+        }
+
+        let workflows = workflows
+            .list_workflows()
+            .into_iter()
+            .filter_map(|workflow| {
+                let id = strip(&workflow.id)?;
+                let mut workflow = workflow.clone();
+                workflow.id = id.clone();
+                Some((id, workflow))
+            })
+            .collect();
+
+        let pack = Self {
+            manifest,
+            templates,
+            snippets,
+            workflows,
+        };
+        if pack.items(pack.namespace()).is_empty() {
+            return Err(EchomindError::Other(match prefix {
+                Some(prefix) => format!("Nothing to export under '{}/'", prefix),
+                None => "Nothing to export".to_string(),
+            }));
+        }
+        Ok(pack)
+    }
+}
+
+/// Tracks installed packs in `<dir>/registry.json` and keeps git checkouts
+/// and unpacked archives under `<dir>`.
+pub struct PackManager {
+    dir: PathBuf,
+    packs: Vec<InstalledPack>,
+}
+
+impl PackManager {
+    pub fn open(dir: &Path) -> Result<Self> {
+        let registry = dir.join("registry.json");
+        let packs = if registry.exists() {
+            let contents = fs::read_to_string(&registry)
+                .map_err(|e| EchomindError::FileError(format!("Failed to read pack registry: {}", e)))?;
+            serde_json::from_str(&contents)
+                .map_err(|e| EchomindError::ConfigError(format!("Invalid pack registry: {}", e)))?
+        } else {
+            Vec::new()
+        };
+
+        Ok(Self {
+            dir: dir.to_path_buf(),
+            packs,
+        })
+    }
+
+    pub fn list(&self) -> &[InstalledPack] {
+        &self.packs
+    }
+
+    pub fn get(&self, namespace: &str) -> Option<&InstalledPack> {
+        self.packs.iter().find(|p| p.namespace == namespace)
+    }
+
+    /// Fetches a pack from a directory, a `.zip` archive or a git URL and
+    /// returns it with the canonical source to record. Git sources are cloned
+    /// once and pulled on later fetches.
+    pub fn fetch(&self, source: &str) -> Result<(PromptPack, String)> {
+        if is_git_url(source) {
+            let checkout = self.dir.join("checkouts").join(sanitize(source));
+            if checkout.join(".git").exists() {
+                run_git(&["-C", &checkout.to_string_lossy(), "pull", "--ff-only", "--quiet"])?;
+            } else {
+                fs::create_dir_all(self.dir.join("checkouts"))
+                    .map_err(|e| EchomindError::FileError(format!("Failed to create checkout directory: {}", e)))?;
+                run_git(&["clone", "--depth", "1", "--quiet", source, &checkout.to_string_lossy()])?;
+            }
+            return Ok((PromptPack::load(&checkout)?, source.to_string()));
+        }
+
+        let path = fs::canonicalize(source)
+            .map_err(|e| EchomindError::FileError(format!("Cannot open pack '{}': {}", source, e)))?;
+        let recorded = path.to_string_lossy().to_string();
+        if path.is_dir() {
+            return Ok((PromptPack::load(&path)?, recorded));
+        }
+
+        let staging = self.dir.join("staging").join(uuid::Uuid::new_v4().simple().to_string());
+        let result = extract_zip(&path, &staging).and_then(|()| PromptPack::load(&pack_root(&staging)?));
+        let _ = fs::remove_dir_all(&staging);
+        Ok((result?, recorded))
+    }
+
+    /// Items `pack` would overwrite without owning them, when installed into
+    /// `namespace`.
+    pub fn conflicts(&self, pack: &PromptPack, namespace: &str, targets: &PackTargets) -> Vec<Conflict> {
+        let owned = self.get(namespace).map(|p| p.items.as_slice()).unwrap_or_default();
+        pack.items(namespace)
+            .into_iter()
+            .filter(|item| !owned.contains(item) && item_exists(item, targets))
+            .map(|item| Conflict {
+                owner: self
+                    .packs
+                    .iter()
+                    .find(|p| p.items.contains(&item))
+                    .map(|p| p.namespace.clone()),
+                item,
+            })
+            .collect()
+    }
+
+    /// Installs (or upgrades) `pack` into `namespace`, or the pack's own
+    /// namespace. Items the pack does not own are only overwritten with
+    /// `force`; items of a previous version that are gone are removed.
+    pub fn install(
+        &mut self,
+        pack: &PromptPack,
+        source: &str,
+        namespace: Option<&str>,
+        force: bool,
+        targets: &mut PackTargets,
+    ) -> Result<InstallReport> {
+        let namespace = namespace.unwrap_or_else(|| pack.namespace()).to_string();
+        validate_namespace(&namespace)?;
+
+        let previous = self.get(&namespace).cloned();
+        if let Some(previous) = &previous {
+            if previous.name != pack.manifest.name {
+                return Err(EchomindError::Other(format!(
+                    "Namespace '{}' is used by pack '{}'; install with --namespace to pick another",
+                    namespace, previous.name
+                )));
+            }
+        }
+
+        let conflicts = self.conflicts(pack, &namespace, targets);
+        if !conflicts.is_empty() && !force {
+            let list: Vec<String> = conflicts.iter().map(|c| format!("  {}", c)).collect();
+            return Err(EchomindError::Other(format!(
+                "Pack '{}' conflicts with existing items:\n{}\nUse --force to overwrite them or --namespace to install elsewhere",
+                pack.manifest.name,
+                list.join("\n")
+            )));
+        }
+        // Forced items change hands: drop them from their previous owner
+        for conflict in &conflicts {
+            for other in self.packs.iter_mut() {
+                other.items.retain(|item| item != &conflict.item);
+            }
+        }
+
+        let items = pack.items(&namespace);
+        let mut report = InstallReport {
+            previous_version: previous.as_ref().map(|p| p.version.clone()),
+            ..InstallReport::default()
+        };
+        for item in previous.iter().flat_map(|p| &p.items) {
+            if !items.contains(item) {
+                remove_item(item, targets)?;
+                report.removed.push(item.clone());
+            }
+        }
+
+        for (name, body) in &pack.templates {
+            // Validated when the pack was loaded
+            let Ok(compiled) = CompiledTemplate::compile(body) else { continue };
+            for variable in compiled.variables() {
+                if variable.usage == VariableUsage::File {
+                    report.file_includes.push((qualified(&namespace, name), variable.name));
+                }
+            }
+        }
+
+        for item in &items {
+            let existed = item_exists(item, targets);
+            install_item(pack, &namespace, item, targets)?;
+            if existed {
+                report.updated.push(item.clone());
+            } else {
+                report.added.push(item.clone());
+            }
+        }
+
+        self.packs.retain(|p| p.namespace != namespace);
+        self.packs.push(InstalledPack {
+            name: pack.manifest.name.clone(),
+            version: pack.manifest.version.clone(),
+            namespace,
+            source: source.to_string(),
+            installed_at: Utc::now(),
+            items,
+        });
+        self.packs.sort_by(|a, b| a.namespace.cmp(&b.namespace));
+        self.save()?;
+        Ok(report)
+    }
+
+    /// Re-fetches a pack from the source it was installed from and installs
+    /// the result over the current version.
+    pub fn update(&mut self, namespace: &str, force: bool, targets: &mut PackTargets) -> Result<InstallReport> {
+        let installed = self
+            .get(namespace)
+            .cloned()
+            .ok_or_else(|| EchomindError::Other(format!("No pack installed in namespace '{}'", namespace)))?;
+        let (pack, source) = self.fetch(&installed.source)?;
+        self.install(&pack, &source, Some(namespace), force, targets)
+    }
+
+    /// Uninstalls the pack in `namespace` and every item it owns.
+    pub fn remove(&mut self, namespace: &str, targets: &mut PackTargets) -> Result<InstalledPack> {
+        let index = self
+            .packs
+            .iter()
+            .position(|p| p.namespace == namespace)
+            .ok_or_else(|| EchomindError::Other(format!("No pack installed in namespace '{}'", namespace)))?;
+
+        for item in &self.packs[index].items {
+            remove_item(item, targets)?;
+        }
+        let _ = fs::remove_dir(targets.workflows_dir.join(namespace));
+
+        let removed = self.packs.remove(index);
+        self.save()?;
+        Ok(removed)
+    }
+
+    fn save(&self) -> Result<()> {
+        fs::create_dir_all(&self.dir)
+            .map_err(|e| EchomindError::FileError(format!("Failed to create pack directory: {}", e)))?;
+        let json = serde_json::to_string_pretty(&self.packs)
+            .map_err(|e| EchomindError::ParseError(format!("Failed to serialize pack registry: {}", e)))?;
+
+        let path = self.dir.join("registry.json");
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, json)
+            .map_err(|e| EchomindError::FileError(format!("Failed to write pack registry: {}", e)))?;
+        fs::rename(&tmp, &path)
+            .map_err(|e| EchomindError::FileError(format!("Failed to write pack registry: {}", e)))
+    }
+}
+
+fn qualified(namespace: &str, name: &str) -> String {
+    format!("{}/{}", namespace, name)
+}
+
+fn validate_namespace(namespace: &str) -> Result<()> {
+    let valid = !namespace.is_empty()
+        && namespace
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.')
+        && !namespace.starts_with('.');
+    if valid {
+        Ok(())
+    } else {
+        Err(EchomindError::ConfigError(format!(
+            "Invalid pack namespace '{}' (use letters, digits, '-', '_' and '.')",
+            namespace
+        )))
+    }
+}
+
+fn variable_type(meta: &VariableMeta) -> Result<Option<VariableType>> {
+    meta.var_type
+        .as_deref()
+        .map(|name| VariableType::parse(name, &meta.choices))
+        .transpose()
+}
+
+fn variable_meta(variable: &TemplateVariable) -> VariableMeta {
+    let (var_type, choices) = match &variable.variable_type {
+        VariableType::String => (None, Vec::new()),
+        VariableType::Number => (Some("number"), Vec::new()),
+        VariableType::Boolean => (Some("boolean"), Vec::new()),
+        VariableType::Date => (Some("date"), Vec::new()),
+        VariableType::File => (Some("file"), Vec::new()),
+        VariableType::List => (Some("list"), Vec::new()),
+        VariableType::Select(choices) => (Some("select"), choices.clone()),
+    };
+    VariableMeta {
+        var_type: var_type.map(str::to_string),
+        choices,
+        description: variable.description.clone(),
+        default: variable.default_value.clone(),
+        required: Some(variable.required),
+    }
+}
+
+fn list_files(dir: &Path) -> Result<Vec<PathBuf>> {
+    if !dir.is_dir() {
+        return Ok(Vec::new());
+    }
+    let entries = fs::read_dir(dir)
+        .map_err(|e| EchomindError::FileError(format!("Failed to read {}: {}", dir.display(), e)))?;
+    let mut files: Vec<PathBuf> = entries
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.is_file())
+        .collect();
+    files.sort();
+    Ok(files)
+}
+
+/// Reads every file in `dir` keyed by file stem.
+fn read_bodies(dir: &Path) -> Result<BTreeMap<String, String>> {
+    let mut bodies = BTreeMap::new();
+    for path in list_files(dir)? {
+        let Some(name) = path.file_stem().and_then(|s| s.to_str()).map(str::to_string) else {
+            continue;
+        };
+        if name.starts_with('.') {
+            continue;
+        }
+        let body = fs::read_to_string(&path)
+            .map_err(|e| EchomindError::FileError(format!("Failed to read {}: {}", path.display(), e)))?;
+        if bodies.insert(name.clone(), body).is_some() {
+            return Err(EchomindError::ConfigError(format!(
+                "Two files in {} are named '{}'",
+                dir.display(),
+                name
+            )));
+        }
+    }
+    Ok(bodies)
+}
+
+fn item_exists(item: &PackItem, targets: &PackTargets) -> bool {
+    match item.kind {
+        ItemKind::Preset => targets.config.presets.contains_key(&item.name),
+        ItemKind::Template => targets.library.find_template(&item.name).is_some(),
+        ItemKind::Snippet => targets.library.find_snippet(&item.name).is_some(),
+        ItemKind::Workflow => workflow_path(&targets.workflows_dir, &item.name).exists(),
+    }
+}
+
+fn workflow_path(workflows_dir: &Path, name: &str) -> PathBuf {
+    let (namespace, id) = name.split_once('/').unwrap_or(("", name));
+    workflows_dir.join(namespace).join(format!("{}.json", sanitize(id)))
+}
+
+fn install_item(pack: &PromptPack, namespace: &str, item: &PackItem, targets: &mut PackTargets) -> Result<()> {
+    let local = &item.name[namespace.len() + 1..];
+    match item.kind {
+        ItemKind::Preset => {
+            targets
+                .config
+                .presets
+                .insert(item.name.clone(), pack.manifest.presets[local].clone());
+        }
+        ItemKind::Template => {
+            let body = &pack.templates[local];
+            let meta = pack.manifest.templates.get(local).cloned().unwrap_or_default();
+            let id = match targets.library.find_template(&item.name).map(|t| t.id.clone()) {
+                Some(id) => {
+                    targets.library.update_template(
+                        &id,
+                        TemplateUpdate {
+                            name: Some(item.name.clone()),
+                            description: meta.description.clone(),
+                            content: Some(body.clone()),
+                            category: meta.category.clone(),
+                            tags: Some(meta.tags.clone()),
+                        },
+                    )?;
+                    id
+                }
+                None => {
+                    targets
+                        .library
+                        .create_template(
+                            &item.name,
+                            body,
+                            meta.description.as_deref(),
+                            meta.category.as_deref(),
+                            meta.tags.clone(),
+                        )?
+                        .id
+                }
+            };
+
+            for (name, var_meta) in &meta.variables {
+                let template = targets.library.get_template(&id).expect("template was just saved");
+                let Some(mut variable) = template.variables.iter().find(|v| &v.name == name).cloned() else {
+                    continue;
+                };
+                if let Some(var_type) = variable_type(var_meta)? {
+                    variable.variable_type = var_type;
+                }
+                if var_meta.description.is_some() {
+                    variable.description = var_meta.description.clone();
+                }
+                if var_meta.default.is_some() {
+                    variable.default_value = var_meta.default.clone();
+                    variable.required = false;
+                }
+                if let Some(required) = var_meta.required {
+                    variable.required = required;
+                }
+                targets.library.set_variable(&id, variable)?;
+            }
+        }
+        ItemKind::Snippet => {
+            let body = &pack.snippets[local];
+            let meta = pack.manifest.snippets.get(local).cloned().unwrap_or_default();
+            match targets.library.find_snippet(&item.name).map(|s| s.id.clone()) {
+                Some(id) => targets.library.update_snippet(
+                    &id,
+                    SnippetUpdate {
+                        name: Some(item.name.clone()),
+                        content: Some(body.clone()),
+                        description: meta.description,
+                        category: meta.category,
+                        tags: Some(meta.tags),
+                        language: meta.language,
+                    },
+                )?,
+                None => {
+                    targets.library.create_snippet(
+                        &item.name,
+                        body,
+                        meta.description.as_deref(),
+                        meta.tags,
+                        meta.category.as_deref(),
+                        meta.language.as_deref(),
+                    )?;
+                }
+            }
+        }
+        ItemKind::Workflow => {
+            let mut workflow = pack.workflows[local].clone();
+            workflow.id = item.name.clone();
+            let path = workflow_path(&targets.workflows_dir, &item.name);
+            if let Some(parent) = path.parent() {
+                fs::create_dir_all(parent)
+                    .map_err(|e| EchomindError::FileError(format!("Failed to create workflow directory: {}", e)))?;
+            }
+            let json = serde_json::to_string_pretty(&workflow)
+                .map_err(|e| EchomindError::ParseError(format!("Failed to serialize workflow: {}", e)))?;
+            fs::write(&path, json)
+                .map_err(|e| EchomindError::FileError(format!("Failed to write workflow file: {}", e)))?;
+        }
+    }
+    Ok(())
+}
+
+fn remove_item(item: &PackItem, targets: &mut PackTargets) -> Result<()> {
+    match item.kind {
+        ItemKind::Preset => {
+            targets.config.presets.remove(&item.name);
+        }
+        ItemKind::Template => {
+            if let Some(id) = targets.library.find_template(&item.name).map(|t| t.id.clone()) {
+                targets.library.delete_template(&id)?;
+            }
+        }
+        ItemKind::Snippet => {
+            if let Some(id) = targets.library.find_snippet(&item.name).map(|s| s.id.clone()) {
+                targets.library.delete_snippet(&id)?;
+            }
+        }
+        ItemKind::Workflow => {
+            let path = workflow_path(&targets.workflows_dir, &item.name);
+            if path.exists() {
+                fs::remove_file(&path)
+                    .map_err(|e| EchomindError::FileError(format!("Failed to remove workflow file: {}", e)))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn is_git_url(source: &str) -> bool {
+    source.starts_with("git@")
+        || source.starts_with("git://")
+        || source.starts_with("ssh://")
+        || ((source.starts_with("https://") || source.starts_with("http://") || source.starts_with("file://"))
+            && !source.ends_with(".zip"))
+        || (source.ends_with(".git") && !Path::new(source).exists())
+}
+
+/// Turns a URL or id into a single safe path component.
+fn sanitize(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' || c == '.' { c } else { '_' })
+        .collect()
+}
+
+fn run_git(args: &[&str]) -> Result<()> {
+    let output = Command::new("git")
+        .args(args)
+        .output()
+        .map_err(|e| EchomindError::Other(format!("Failed to run git: {}", e)))?;
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(EchomindError::Other(format!(
+            "git {} failed: {}",
+            args.first().copied().unwrap_or_default(),
+            String::from_utf8_lossy(&output.stderr).trim()
+        )))
+    }
+}
+
+fn extract_zip(archive: &Path, dest: &Path) -> Result<()> {
+    let file = fs::File::open(archive)
+        .map_err(|e| EchomindError::FileError(format!("Failed to open {}: {}", archive.display(), e)))?;
+    let mut zip = zip::ZipArchive::new(file)
+        .map_err(|e| EchomindError::FileError(format!("{} is not a pack directory or zip archive: {}", archive.display(), e)))?;
+
+    for index in 0..zip.len() {
+        let mut entry = zip
+            .by_index(index)
+            .map_err(|e| EchomindError::FileError(format!("Failed to read archive: {}", e)))?;
+        // enclosed_name rejects absolute paths and `..` components
+        let Some(relative) = entry.enclosed_name().map(Path::to_path_buf) else {
+            continue;
+        };
+        let path = dest.join(relative);
+        if entry.is_dir() {
+            fs::create_dir_all(&path)
+                .map_err(|e| EchomindError::FileError(format!("Failed to unpack archive: {}", e)))?;
+            continue;
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| EchomindError::FileError(format!("Failed to unpack archive: {}", e)))?;
+        }
+        let mut out = fs::File::create(&path)
+            .map_err(|e| EchomindError::FileError(format!("Failed to unpack archive: {}", e)))?;
+        std::io::copy(&mut entry, &mut out)
+            .map_err(|e| EchomindError::FileError(format!("Failed to unpack archive: {}", e)))?;
+    }
+    Ok(())
+}
+
+/// The manifest sits either at the archive root or in its single top-level
+/// directory (as produced by zipping a folder).
+fn pack_root(dir: &Path) -> Result<PathBuf> {
+    if dir.join(MANIFEST_FILE).exists() {
+        return Ok(dir.to_path_buf());
+    }
+    let subdirs: Vec<PathBuf> = fs::read_dir(dir)
+        .map_err(|e| EchomindError::FileError(format!("Failed to read archive: {}", e)))?
+        .flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.is_dir())
+        .collect();
+    match subdirs.as_slice() {
+        [only] if only.join(MANIFEST_FILE).exists() => Ok(only.clone()),
+        _ => Err(EchomindError::ConfigError(format!("Archive has no {}", MANIFEST_FILE))),
+    }
+}
+
+/// Counts installed items per kind, for summaries.
+pub fn count_by_kind(items: &[PackItem]) -> HashMap<ItemKind, usize> {
+    let mut counts = HashMap::new();
+    for item in items {
+        *counts.entry(item.kind).or_insert(0) += 1;
+    }
+    counts
+}
@@ -1,9 +1,16 @@
-use crate::api::{ApiClient, ChatRequest, Message};
+use crate::api::{ApiClient, ChatRequest, Message, Provider, StreamTiming};
 use crate::error::{EchomindError, Result};
+use crate::features::json_schema::extract_json;
+use crate::features::usage::UsageMeter;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
 use std::time::{Duration, Instant};
-use tokio::time::sleep;
+use tokio::sync::{mpsc, Semaphore};
+use tokio::time::MissedTickBehavior;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BenchmarkResult {
@@ -12,12 +19,38 @@ pub struct BenchmarkResult {
     pub prompt: String,
     pub response: String,
     pub response_time_ms: u64,
+    /// Output tokens per second; for streamed runs, counted from the first
+    /// token so connection and queueing time don't dilute it
     pub tokens_per_second: f64,
     pub input_tokens: u32,
     pub output_tokens: u32,
     pub cost_estimate: f64,
     pub quality_score: Option<f64>,
     pub timestamp: chrono::DateTime<chrono::Utc>,
+    /// Suite prompt this run answered
+    #[serde(default)]
+    pub prompt_id: Option<String>,
+    /// Time to the first response byte, when the provider streams
+    #[serde(default)]
+    pub ttfb_ms: Option<u64>,
+    /// Time to the first streamed token
+    #[serde(default)]
+    pub ttft_ms: Option<u64>,
+    #[serde(default)]
+    pub inter_token_p50_ms: Option<u64>,
+    #[serde(default)]
+    pub inter_token_p95_ms: Option<u64>,
+    /// Whether the response met the prompt's expectations
+    #[serde(default)]
+    pub passed: Option<bool>,
+    #[serde(default)]
+    pub failures: Vec<String>,
+    /// Set when the request failed; the run still counts against the pass rate
+    #[serde(default)]
+    pub error: Option<String>,
+    /// Set when the run went out over a budget whose action is warn
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub budget_warning: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -31,6 +64,15 @@ pub struct PerformanceMetrics {
     pub requests_per_minute: f64,
     pub error_rate: f64,
     pub uptime_percentage: f64,
+    #[serde(default)]
+    pub streamed_requests: u64,
+    /// Means over streamed requests
+    #[serde(default)]
+    pub average_ttft_ms: f64,
+    #[serde(default)]
+    pub average_inter_token_ms: f64,
+    #[serde(default)]
+    pub average_stream_tokens_per_second: f64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -42,11 +84,416 @@ pub struct ModelComparison {
     pub comparison_metrics: HashMap<String, f64>,
 }
 
+/// A set of prompts with expected response properties, run against one or
+/// more provider:model targets.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct BenchmarkSuite {
+    #[serde(default)]
+    pub name: Option<String>,
+    /// Runs per prompt and target, unless overridden with --runs
+    #[serde(default)]
+    pub runs: Option<usize>,
+    /// `provider:model` references, used when none are given on the command line
+    #[serde(default)]
+    pub targets: Vec<String>,
+    #[serde(default)]
+    pub temperature: Option<f32>,
+    #[serde(default)]
+    pub max_tokens: Option<u32>,
+    pub prompts: Vec<SuitePrompt>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SuitePrompt {
+    pub id: String,
+    pub prompt: String,
+    #[serde(default)]
+    pub system: Option<String>,
+    #[serde(default)]
+    pub expect: Expectations,
+}
+
+/// Properties a response must have to pass. Text matches are
+/// case-insensitive; every listed check must hold.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Expectations {
+    #[serde(default)]
+    pub contains: Vec<String>,
+    #[serde(default)]
+    pub not_contains: Vec<String>,
+    #[serde(default)]
+    pub regex: Option<String>,
+    #[serde(default)]
+    pub min_chars: Option<usize>,
+    #[serde(default)]
+    pub max_chars: Option<usize>,
+    /// The response must contain parseable JSON
+    #[serde(default)]
+    pub json: bool,
+}
+
+impl BenchmarkSuite {
+    /// Loads a suite from YAML, JSON or TOML, chosen by extension.
+    pub fn load(path: &str) -> Result<Self> {
+        let contents = std::fs::read_to_string(path)
+            .map_err(|e| EchomindError::FileError(format!("Failed to read suite {}: {}", path, e)))?;
+        let extension = Path::new(path)
+            .extension()
+            .and_then(|e| e.to_str())
+            .unwrap_or("")
+            .to_lowercase();
+        let suite: BenchmarkSuite = match extension.as_str() {
+            "json" => serde_json::from_str(&contents).map_err(|e| e.to_string()),
+            "toml" => toml::from_str(&contents).map_err(|e| e.to_string()),
+            _ => serde_yaml::from_str(&contents).map_err(|e| e.to_string()),
+        }
+        .map_err(|e| EchomindError::ParseError(format!("Failed to parse suite {}: {}", path, e)))?;
+        suite.validate()?;
+        Ok(suite)
+    }
+
+    /// A suite of one prompt with no expectations, for `--benchmark PROMPT`.
+    pub fn single(prompt: &str) -> Self {
+        Self {
+            prompts: vec![SuitePrompt {
+                id: "prompt".to_string(),
+                prompt: prompt.to_string(),
+                ..Default::default()
+            }],
+            ..Default::default()
+        }
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        if self.prompts.is_empty() {
+            return Err(EchomindError::Other("The benchmark suite has no prompts".to_string()));
+        }
+        for prompt in &self.prompts {
+            if let Some(pattern) = &prompt.expect.regex {
+                regex::Regex::new(pattern).map_err(|e| {
+                    EchomindError::Other(format!("Prompt '{}' has an invalid regex: {}", prompt.id, e))
+                })?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl BenchmarkResult {
+    fn set_stream_timing(&mut self, timing: &StreamTiming) {
+        let ms = |d: Duration| d.as_millis() as u64;
+        self.ttfb_ms = timing.first_byte.map(ms);
+        self.ttft_ms = timing.first_token.map(ms);
+        self.inter_token_p50_ms = timing.token_gap_percentile(50.0).map(ms);
+        self.inter_token_p95_ms = timing.token_gap_percentile(95.0).map(ms);
+    }
+}
+
+impl Expectations {
+    /// Describes every expectation the response misses.
+    pub fn check(&self, response: &str) -> Vec<String> {
+        let mut failures = Vec::new();
+        let lower = response.to_lowercase();
+        for needle in &self.contains {
+            if !lower.contains(&needle.to_lowercase()) {
+                failures.push(format!("missing \"{}\"", needle));
+            }
+        }
+        for needle in &self.not_contains {
+            if lower.contains(&needle.to_lowercase()) {
+                failures.push(format!("contains \"{}\"", needle));
+            }
+        }
+        if let Some(pattern) = &self.regex {
+            // Patterns are checked when the suite loads
+            if let Ok(re) = regex::RegexBuilder::new(pattern).case_insensitive(true).build() {
+                if !re.is_match(response) {
+                    failures.push(format!("no match for /{}/", pattern));
+                }
+            }
+        }
+        let chars = response.chars().count();
+        if let Some(min) = self.min_chars {
+            if chars < min {
+                failures.push(format!("{} chars, expected at least {}", chars, min));
+            }
+        }
+        if let Some(max) = self.max_chars {
+            if chars > max {
+                failures.push(format!("{} chars, expected at most {}", chars, max));
+            }
+        }
+        if self.json && extract_json(response).is_none() {
+            failures.push("no valid JSON".to_string());
+        }
+        failures
+    }
+}
+
+/// A model to benchmark and the client that serves it.
+#[derive(Clone)]
+pub struct BenchmarkTarget {
+    pub provider: String,
+    pub model: String,
+    client: ApiClient,
+}
+
+impl BenchmarkTarget {
+    /// Repeated runs must reach the model, so the response cache is turned off.
+    pub fn new(mut client: ApiClient, model: &str) -> Self {
+        client.set_cache_enabled(false);
+        Self {
+            provider: client.provider().name().to_string(),
+            model: model.to_string(),
+            client,
+        }
+    }
+
+    pub fn label(&self) -> String {
+        format!("{}:{}", self.provider, self.model)
+    }
+
+    pub fn client(&self) -> &ApiClient {
+        &self.client
+    }
+}
+
+/// Aggregate figures for one provider:model over every run of a suite.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BenchmarkSummary {
+    pub provider: String,
+    pub model: String,
+    pub runs: usize,
+    pub errors: usize,
+    pub passed: usize,
+    pub pass_rate: f64,
+    pub p50_latency_ms: Option<u64>,
+    pub p95_latency_ms: Option<u64>,
+    pub p50_ttft_ms: Option<u64>,
+    pub p95_ttft_ms: Option<u64>,
+    /// Median and 95th percentile of the runs' own inter-token percentiles
+    pub p50_inter_token_ms: Option<u64>,
+    pub p95_inter_token_ms: Option<u64>,
+    /// Mean output tokens per second over successful runs
+    pub tokens_per_second: f64,
+    pub total_cost: f64,
+}
+
+/// How a load test drives traffic; see `PerformanceMonitor::run_stress_test`.
+#[derive(Debug, Clone)]
+pub struct LoadTestConfig {
+    /// Target requests per second; `None` runs closed-loop
+    pub rate: Option<f64>,
+    /// Most requests in flight at once
+    pub concurrency: usize,
+    pub warmup: Duration,
+    pub duration: Duration,
+}
+
+impl LoadTestConfig {
+    pub fn validate(&self) -> Result<()> {
+        if self.concurrency == 0 {
+            return Err(EchomindError::Other("Concurrency must be at least 1".to_string()));
+        }
+        if matches!(self.rate, Some(rate) if !(rate > 0.0 && rate.is_finite())) {
+            return Err(EchomindError::Other("The request rate must be a positive number".to_string()));
+        }
+        if self.duration.is_zero() {
+            return Err(EchomindError::Other("The load test duration must be longer than zero".to_string()));
+        }
+        Ok(())
+    }
+
+    /// Requests the test is expected to send, warm-up included. Closed-loop
+    /// tests are assumed to manage one request a second per worker.
+    pub fn expected_requests(&self) -> u64 {
+        let secs = (self.warmup + self.duration).as_secs_f64();
+        (self.rate.unwrap_or(self.concurrency as f64) * secs).ceil() as u64
+    }
+}
+
+/// Live counts from a running load test, for progress displays.
+#[derive(Debug, Clone)]
+pub struct LoadProgress {
+    pub elapsed: Duration,
+    /// Warm-up plus measured duration
+    pub total: Duration,
+    pub warming_up: bool,
+    /// Measured requests that finished, successfully or not
+    pub completed: u64,
+    pub failed: u64,
+    pub dropped: u64,
+    pub in_flight: usize,
+}
+
+/// Outcome of a load test's measured window (warm-up excluded).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LoadTestReport {
+    pub target_rate: Option<f64>,
+    pub concurrency: usize,
+    pub duration_secs: f64,
+    pub warmup_requests: u64,
+    /// Requests that completed, successfully or not
+    pub sent: u64,
+    pub succeeded: u64,
+    pub failed: u64,
+    /// Requests due while the concurrency limit was reached, never sent
+    pub dropped: u64,
+    pub achieved_rps: f64,
+    pub success_rps: f64,
+    pub output_tokens: u64,
+    pub output_tokens_per_second: f64,
+    /// Latency figures cover successful requests
+    pub p50_ms: Option<u64>,
+    pub p90_ms: Option<u64>,
+    pub p99_ms: Option<u64>,
+    pub max_ms: Option<u64>,
+    pub mean_ms: f64,
+    pub latency: LatencyHistogram,
+    /// Failures by error variant; API errors also by status
+    pub errors: BTreeMap<String, u64>,
+}
+
+impl LoadTestReport {
+    fn new(config: &LoadTestConfig) -> Self {
+        Self {
+            target_rate: config.rate,
+            concurrency: config.concurrency,
+            duration_secs: 0.0,
+            warmup_requests: 0,
+            sent: 0,
+            succeeded: 0,
+            failed: 0,
+            dropped: 0,
+            achieved_rps: 0.0,
+            success_rps: 0.0,
+            output_tokens: 0,
+            output_tokens_per_second: 0.0,
+            p50_ms: None,
+            p90_ms: None,
+            p99_ms: None,
+            max_ms: None,
+            mean_ms: 0.0,
+            latency: LatencyHistogram::new(),
+            errors: BTreeMap::new(),
+        }
+    }
+
+    /// A plain-text report with the latency histogram.
+    pub fn render(&self) -> String {
+        let ms = |v: Option<u64>| v.map(|v| format!("{} ms", v)).unwrap_or_else(|| "-".to_string());
+        let mode = match self.target_rate {
+            Some(rate) => format!("open loop at {} req/s, at most {} in flight", rate, self.concurrency),
+            None => format!("closed loop with {} workers", self.concurrency),
+        };
+        let mut out = vec![
+            format!("Load test: {} for {:.1}s ({} warm-up requests not counted)", mode, self.duration_secs, self.warmup_requests),
+            format!(
+                "Requests: {} completed, {} succeeded, {} failed, {} dropped at the concurrency limit",
+                self.sent, self.succeeded, self.failed, self.dropped
+            ),
+            format!(
+                "Throughput: {:.2} req/s achieved, {:.2} req/s successful, {:.1} output tokens/s",
+                self.achieved_rps, self.success_rps, self.output_tokens_per_second
+            ),
+            format!(
+                "Latency: p50 {}, p90 {}, p99 {}, max {}, mean {:.0} ms",
+                ms(self.p50_ms),
+                ms(self.p90_ms),
+                ms(self.p99_ms),
+                ms(self.max_ms),
+                self.mean_ms
+            ),
+        ];
+        if self.succeeded > 0 {
+            out.push(String::new());
+            out.push(self.latency.render(40));
+        }
+        if !self.errors.is_empty() {
+            out.push(String::new());
+            out.push("Errors:".to_string());
+            for (kind, count) in &self.errors {
+                out.push(format!("  {:<20} {}", kind, count));
+            }
+        }
+        out.join("\n")
+    }
+}
+
+/// Upper bucket bounds in milliseconds; a final bucket takes the rest.
+const LATENCY_BUCKETS_MS: [u64; 14] = [10, 25, 50, 100, 250, 500, 750, 1000, 1500, 2500, 5000, 10000, 30000, 60000];
+
+/// Latency counts in fixed, roughly logarithmic buckets.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LatencyHistogram {
+    pub bounds_ms: Vec<u64>,
+    /// One more count than bounds, for latencies above the last bound
+    pub counts: Vec<u64>,
+}
+
+impl LatencyHistogram {
+    pub fn new() -> Self {
+        Self {
+            bounds_ms: LATENCY_BUCKETS_MS.to_vec(),
+            counts: vec![0; LATENCY_BUCKETS_MS.len() + 1],
+        }
+    }
+
+    pub fn record(&mut self, ms: u64) {
+        let bucket = self.bounds_ms.iter().position(|bound| ms <= *bound).unwrap_or(self.bounds_ms.len());
+        self.counts[bucket] += 1;
+    }
+
+    /// Bars for the buckets between the first and last non-empty ones.
+    pub fn render(&self, width: usize) -> String {
+        let (Some(first), Some(last)) = (
+            self.counts.iter().position(|c| *c > 0),
+            self.counts.iter().rposition(|c| *c > 0),
+        ) else {
+            return String::new();
+        };
+        let max = self.counts.iter().copied().max().unwrap_or(1);
+        let total: u64 = self.counts.iter().sum();
+        (first..=last)
+            .map(|i| {
+                let label = match self.bounds_ms.get(i) {
+                    Some(bound) => format!("≤ {} ms", bound),
+                    None => format!("> {} ms", self.bounds_ms[i - 1]),
+                };
+                let count = self.counts[i];
+                let bar = (count as f64 / max as f64 * width as f64).round() as usize;
+                format!(
+                    "{:>10} {:<width$} {} ({:.1}%)",
+                    label,
+                    "█".repeat(bar),
+                    count,
+                    count as f64 * 100.0 / total as f64,
+                    width = width
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+}
+
+impl Default for LatencyHistogram {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+enum LoadSample {
+    /// Output tokens, or the error's kind
+    Completed { scheduled: Instant, latency: Duration, outcome: std::result::Result<u32, String> },
+    Dropped { scheduled: Instant },
+}
+
 pub struct PerformanceMonitor {
     metrics: PerformanceMetrics,
     benchmark_results: Vec<BenchmarkResult>,
     start_time: Instant,
     request_times: Vec<Duration>,
+    usage: Option<UsageMeter>,
 }
 
 impl PerformanceMonitor {
@@ -62,13 +509,24 @@ impl PerformanceMonitor {
                 requests_per_minute: 0.0,
                 error_rate: 0.0,
                 uptime_percentage: 100.0,
+                streamed_requests: 0,
+                average_ttft_ms: 0.0,
+                average_inter_token_ms: 0.0,
+                average_stream_tokens_per_second: 0.0,
             },
             benchmark_results: Vec::new(),
             start_time: Instant::now(),
             request_times: Vec::new(),
+            usage: None,
         }
     }
 
+    /// Checks each benchmark request against the budget and records it in
+    /// the ledger. A run over a blocking budget fails without being sent.
+    pub fn set_usage_meter(&mut self, usage: UsageMeter) {
+        self.usage = Some(usage);
+    }
+
     pub async fn benchmark_model(
         &mut self,
         api_client: &ApiClient,
@@ -76,8 +534,6 @@ impl PerformanceMonitor {
         provider: &str,
         prompt: &str,
     ) -> Result<BenchmarkResult> {
-        let start_time = Instant::now();
-        
         let messages = vec![Message::text("user".to_string(), prompt.to_string())];
         let request = ChatRequest {
             messages,
@@ -89,32 +545,44 @@ impl PerformanceMonitor {
             stream: None,
         };
         
-        let response = api_client.send_message(request).await;
-        let response_time = start_time.elapsed();
+        let (response, timing, response_time, budget_warning) =
+            send_timed_metered(self.usage.as_ref(), provider, api_client, &request).await?;
         
         match response {
             Ok(response_text) => {
                 let input_tokens = self.estimate_token_count(prompt);
                 let output_tokens = self.estimate_token_count(&response_text);
                 let total_tokens = input_tokens + output_tokens;
-                let tokens_per_second = total_tokens as f64 / response_time.as_secs_f64();
                 
                 let cost_estimate = self.calculate_cost(provider, model, input_tokens, output_tokens);
                 let quality_score = self.calculate_quality_score(&response_text);
                 
-                let result = BenchmarkResult {
+                let mut result = BenchmarkResult {
                     model: model.to_string(),
                     provider: provider.to_string(),
                     prompt: prompt.to_string(),
                     response: response_text.clone(),
                     response_time_ms: response_time.as_millis() as u64,
-                    tokens_per_second,
+                    tokens_per_second: throughput(output_tokens, response_time, timing.as_ref()),
                     input_tokens,
                     output_tokens,
                     cost_estimate,
                     quality_score,
                     timestamp: chrono::Utc::now(),
+                    prompt_id: None,
+                    ttfb_ms: None,
+                    ttft_ms: None,
+                    inter_token_p50_ms: None,
+                    inter_token_p95_ms: None,
+                    passed: None,
+                    failures: Vec::new(),
+                    error: None,
+                    budget_warning,
                 };
+                if let Some(timing) = &timing {
+                    result.set_stream_timing(timing);
+                    self.record_stream(timing, result.tokens_per_second);
+                }
                 
                 self.update_metrics(true, response_time, total_tokens, cost_estimate);
                 self.benchmark_results.push(result.clone());
@@ -137,11 +605,12 @@ impl PerformanceMonitor {
         let mut results = Vec::new();
         
         for model in models {
-            if let Some((provider, client)) = self.get_provider_for_model(model, api_clients) {
-                match self.benchmark_model(client, model, &provider, prompt).await {
+            match self.get_provider_for_model(model, api_clients) {
+                Some((provider, name, client)) => match self.benchmark_model(client, &name, &provider, prompt).await {
                     Ok(result) => results.push(result),
                     Err(e) => eprintln!("Failed to benchmark {}: {}", model, e),
-                }
+                },
+                None => eprintln!("No configured provider serves {}; name it as provider:model", model),
             }
         }
         
@@ -157,53 +626,263 @@ impl PerformanceMonitor {
         })
     }
 
-    pub async fn run_stress_test(
+    /// Runs every suite prompt `runs` times against each target, one request
+    /// at a time so runs don't skew each other's latency. Failed requests are
+    /// recorded rather than aborting the suite; `on_run` sees each result as
+    /// it completes.
+    pub async fn run_suite<F>(
         &mut self,
-        api_client: &ApiClient,
-        model: &str,
-        provider: &str,
-        prompt: &str,
-        concurrent_requests: usize,
-        duration_seconds: u64,
-    ) -> Result<Vec<BenchmarkResult>> {
-        let api_client = api_client.clone();
-        let mut handles = Vec::new();
-        let start_time = Instant::now();
-        let end_time = start_time + Duration::from_secs(duration_seconds);
-        
-        while Instant::now() < end_time {
-            for _ in 0..concurrent_requests {
-                if Instant::now() >= end_time {
-                    break;
+        targets: &[BenchmarkTarget],
+        suite: &BenchmarkSuite,
+        runs: usize,
+        mut on_run: F,
+    ) -> Vec<BenchmarkSummary>
+    where
+        F: FnMut(&BenchmarkResult),
+    {
+        for target in targets {
+            for prompt in &suite.prompts {
+                for _ in 0..runs {
+                    let result = self.run_suite_prompt(target, suite, prompt).await;
+                    on_run(&result);
+                    self.benchmark_results.push(result);
                 }
-                
-                let client = api_client.clone();
-                let model = model.to_string();
-                let provider = provider.to_string();
-                let prompt = prompt.to_string();
-                
-                let handle = tokio::spawn(async move {
-                    let mut monitor = PerformanceMonitor::new();
-                    monitor.benchmark_model(&client, &model, &provider, &prompt).await
-                });
-                
-                handles.push(handle);
-                
-                // Small delay between requests
-                sleep(Duration::from_millis(100)).await;
             }
-            
-            // Wait for current batch to complete before starting new one
-            for handle in handles.drain(..) {
-                if let Ok(result) = handle.await {
-                    if let Ok(benchmark) = result {
-                        self.benchmark_results.push(benchmark);
+        }
+        self.summarize()
+    }
+
+    async fn run_suite_prompt(&mut self, target: &BenchmarkTarget, suite: &BenchmarkSuite, prompt: &SuitePrompt) -> BenchmarkResult {
+        let mut messages = Vec::new();
+        if let Some(system) = &prompt.system {
+            messages.push(Message::text("system".to_string(), system.clone()));
+        }
+        messages.push(Message::text("user".to_string(), prompt.prompt.clone()));
+        let request = ChatRequest {
+            messages,
+            model: Some(target.model.clone()),
+            temperature: suite.temperature,
+            max_tokens: suite.max_tokens,
+            top_p: None,
+            top_k: None,
+            stream: None,
+        };
+
+        // A run over a blocking budget fails like any other, without being sent
+        let (response, timing, response_time, budget_warning) =
+            send_timed_metered(self.usage.as_ref(), &target.provider, &target.client, &request)
+                .await
+                .unwrap_or_else(|e| (Err(e), None, Duration::ZERO, None));
+
+        let input_text = format!("{}\n{}", prompt.system.as_deref().unwrap_or(""), prompt.prompt);
+        let input_tokens = self.estimate_token_count(&input_text);
+        let (response, error) = match response {
+            Ok(text) => (text, None),
+            Err(e) => (String::new(), Some(e.to_string())),
+        };
+        let output_tokens = if error.is_none() { self.estimate_token_count(&response) } else { 0 };
+        let cost_estimate = if error.is_none() {
+            self.calculate_cost(&target.provider, &target.model, input_tokens, output_tokens)
+        } else {
+            0.0
+        };
+        let failures = match &error {
+            Some(_) => Vec::new(),
+            None => prompt.expect.check(&response),
+        };
+        self.update_metrics(error.is_none(), response_time, input_tokens + output_tokens, cost_estimate);
+        let tokens_per_second = throughput(output_tokens, response_time, timing.as_ref());
+        if let (Some(timing), None) = (&timing, &error) {
+            self.record_stream(timing, tokens_per_second);
+        }
+
+        let mut result = BenchmarkResult {
+            model: target.model.clone(),
+            provider: target.provider.clone(),
+            prompt: prompt.prompt.clone(),
+            quality_score: error.as_ref().map_or_else(|| self.calculate_quality_score(&response), |_| None),
+            response,
+            response_time_ms: response_time.as_millis() as u64,
+            tokens_per_second,
+            input_tokens,
+            output_tokens,
+            cost_estimate,
+            timestamp: chrono::Utc::now(),
+            prompt_id: Some(prompt.id.clone()),
+            ttfb_ms: None,
+            ttft_ms: None,
+            inter_token_p50_ms: None,
+            inter_token_p95_ms: None,
+            passed: Some(error.is_none() && failures.is_empty()),
+            failures,
+            error,
+            budget_warning,
+        };
+        if let Some(timing) = &timing {
+            result.set_stream_timing(timing);
+        }
+        result
+    }
+
+    /// Summarizes the recorded results per provider:model, in the order the
+    /// targets were first run.
+    pub fn summarize(&self) -> Vec<BenchmarkSummary> {
+        let mut groups: Vec<((&str, &str), Vec<&BenchmarkResult>)> = Vec::new();
+        for result in &self.benchmark_results {
+            let key = (result.provider.as_str(), result.model.as_str());
+            match groups.iter_mut().find(|(k, _)| *k == key) {
+                Some((_, results)) => results.push(result),
+                None => groups.push((key, vec![result])),
+            }
+        }
+
+        groups
+            .into_iter()
+            .map(|((provider, model), results)| {
+                let ok: Vec<&BenchmarkResult> = results.iter().copied().filter(|r| r.error.is_none()).collect();
+                let latencies: Vec<u64> = ok.iter().map(|r| r.response_time_ms).collect();
+                let ttfts: Vec<u64> = ok.iter().filter_map(|r| r.ttft_ms).collect();
+                let gaps_p50: Vec<u64> = ok.iter().filter_map(|r| r.inter_token_p50_ms).collect();
+                let gaps_p95: Vec<u64> = ok.iter().filter_map(|r| r.inter_token_p95_ms).collect();
+                // Runs outside a suite have no expectations and pass when they succeed
+                let passed = results.iter().filter(|r| r.passed.unwrap_or(r.error.is_none())).count();
+                BenchmarkSummary {
+                    provider: provider.to_string(),
+                    model: model.to_string(),
+                    runs: results.len(),
+                    errors: results.len() - ok.len(),
+                    passed,
+                    pass_rate: passed as f64 / results.len() as f64,
+                    p50_latency_ms: percentile(&latencies, 50.0),
+                    p95_latency_ms: percentile(&latencies, 95.0),
+                    p50_ttft_ms: percentile(&ttfts, 50.0),
+                    p95_ttft_ms: percentile(&ttfts, 95.0),
+                    p50_inter_token_ms: percentile(&gaps_p50, 50.0),
+                    p95_inter_token_ms: percentile(&gaps_p95, 95.0),
+                    tokens_per_second: if ok.is_empty() {
+                        0.0
+                    } else {
+                        ok.iter().map(|r| r.tokens_per_second).sum::<f64>() / ok.len() as f64
+                    },
+                    total_cost: ok.iter().map(|r| r.cost_estimate).sum(),
+                }
+            })
+            .collect()
+    }
+
+    pub fn results(&self) -> &[BenchmarkResult] {
+        &self.benchmark_results
+    }
+
+    /// Load-tests one request against `api_client`. With a target rate the
+    /// schedule is open-loop: requests go out on time whether or not earlier
+    /// ones finished, latency counts from the scheduled send time, and a
+    /// request due while `concurrency` are already in flight is dropped
+    /// rather than delayed. Without a rate, `concurrency` workers send back to
+    /// back. Requests scheduled during the warm-up are sent but not counted.
+    /// `on_progress` is called as requests complete and a few times a second.
+    pub async fn run_stress_test<F>(
+        &mut self,
+        api_client: &ApiClient,
+        request: ChatRequest,
+        config: &LoadTestConfig,
+        mut on_progress: F,
+    ) -> Result<LoadTestReport>
+    where
+        F: FnMut(&LoadProgress),
+    {
+        config.validate()?;
+        let mut client = api_client.clone();
+        client.set_cache_enabled(false);
+
+        let start = Instant::now();
+        let measure_from = start + config.warmup;
+        let end = measure_from + config.duration;
+        let in_flight = Arc::new(AtomicUsize::new(0));
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let provider = client.provider().name().to_string();
+        let scheduler = tokio::spawn(schedule_load(client, request.clone(), config.clone(), start, end, Arc::clone(&in_flight), tx));
+
+        let mut report = LoadTestReport::new(config);
+        let mut latencies = Vec::new();
+        let mut progress = LoadProgress {
+            elapsed: Duration::ZERO,
+            total: config.warmup + config.duration,
+            warming_up: true,
+            completed: 0,
+            failed: 0,
+            dropped: 0,
+            in_flight: 0,
+        };
+        let mut ticker = tokio::time::interval(Duration::from_millis(250));
+        loop {
+            let sample = tokio::select! {
+                sample = rx.recv() => match sample {
+                    Some(sample) => Some(sample),
+                    None => break,
+                },
+                _ = ticker.tick() => None,
+            };
+
+            if let Some(sample) = sample {
+                // Warmup requests are billed too
+                if let (Some(usage), LoadSample::Completed { outcome, .. }) = (&self.usage, &sample) {
+                    let _ = usage.record_tokens(&provider, &request, outcome.as_ref().ok().copied());
+                }
+                match sample {
+                    LoadSample::Dropped { scheduled } if scheduled >= measure_from => {
+                        report.dropped += 1;
+                        progress.dropped += 1;
+                    }
+                    LoadSample::Dropped { .. } => {}
+                    LoadSample::Completed { scheduled, .. } if scheduled < measure_from => report.warmup_requests += 1,
+                    LoadSample::Completed { latency, outcome, .. } => {
+                        report.sent += 1;
+                        progress.completed += 1;
+                        match outcome {
+                            Ok(output_tokens) => {
+                                let ms = latency.as_millis() as u64;
+                                report.succeeded += 1;
+                                report.output_tokens += output_tokens as u64;
+                                report.latency.record(ms);
+                                latencies.push(ms);
+                                self.update_metrics(true, latency, output_tokens, 0.0);
+                            }
+                            Err(kind) => {
+                                report.failed += 1;
+                                progress.failed += 1;
+                                *report.errors.entry(kind).or_insert(0) += 1;
+                                self.update_metrics(false, latency, 0, 0.0);
+                            }
+                        }
                     }
                 }
             }
+
+            progress.elapsed = start.elapsed().min(progress.total);
+            progress.warming_up = Instant::now() < measure_from;
+            progress.in_flight = in_flight.load(Ordering::Relaxed);
+            on_progress(&progress);
         }
-        
-        Ok(self.benchmark_results.clone())
+        scheduler
+            .await
+            .map_err(|e| EchomindError::Other(format!("Load generator failed: {}", e)))?;
+
+        // In-flight requests finish after the window closes, so the window is
+        // measured up to the last completion
+        report.duration_secs = start.elapsed().saturating_sub(config.warmup).as_secs_f64().max(0.001);
+        report.achieved_rps = report.sent as f64 / report.duration_secs;
+        report.success_rps = report.succeeded as f64 / report.duration_secs;
+        report.output_tokens_per_second = report.output_tokens as f64 / report.duration_secs;
+        latencies.sort_unstable();
+        report.p50_ms = percentile(&latencies, 50.0);
+        report.p90_ms = percentile(&latencies, 90.0);
+        report.p99_ms = percentile(&latencies, 99.0);
+        report.max_ms = latencies.last().copied();
+        if !latencies.is_empty() {
+            report.mean_ms = latencies.iter().sum::<u64>() as f64 / latencies.len() as f64;
+        }
+        Ok(report)
     }
 
     pub fn get_performance_report(&self) -> PerformanceReport {
@@ -219,36 +898,108 @@ impl PerformanceMonitor {
         }
     }
 
+    /// Exports the summary and every run as `json`, per-run `csv`, or a
+    /// `markdown` report.
     pub fn export_benchmark_data(&self, format: &str) -> Result<String> {
         match format {
             "json" => {
-                serde_json::to_string_pretty(&self.benchmark_results)
+                let report = serde_json::json!({
+                    "summary": self.summarize(),
+                    "results": self.benchmark_results,
+                });
+                serde_json::to_string_pretty(&report)
                     .map_err(|e| EchomindError::ParseError(format!("Failed to export JSON: {}", e)))
             }
             "csv" => {
-                let mut csv = String::new();
-                csv.push_str("Model,Provider,Prompt,Response,ResponseTimeMS,TokensPerSecond,InputTokens,OutputTokens,CostEstimate,QualityScore,Timestamp\n");
-                
+                let mut writer = csv::Writer::from_writer(Vec::new());
+                let csv_error = |e: csv::Error| EchomindError::Other(format!("Failed to export CSV: {}", e));
+                let optional = |value: Option<u64>| value.map(|v| v.to_string()).unwrap_or_default();
+                writer
+                    .write_record([
+                        "prompt_id", "model", "provider", "prompt", "response", "response_time_ms", "ttfb_ms", "ttft_ms",
+                        "inter_token_p50_ms", "inter_token_p95_ms", "tokens_per_second", "input_tokens", "output_tokens", "cost_estimate", "quality_score",
+                        "passed", "failures", "error", "timestamp",
+                    ])
+                    .map_err(csv_error)?;
                 for result in &self.benchmark_results {
-                    csv.push_str(&format!(
-                        "{},{},{},{},{},{},{},{},{},{},{}\n",
-                        result.model,
-                        result.provider,
-                        result.prompt.replace(',', ";"),
-                        result.response.replace(',', ";").replace('\n', " "),
-                        result.response_time_ms,
-                        result.tokens_per_second,
-                        result.input_tokens,
-                        result.output_tokens,
-                        result.cost_estimate,
-                        result.quality_score.unwrap_or(0.0),
-                        result.timestamp.to_rfc3339()
+                    writer
+                        .write_record([
+                            result.prompt_id.clone().unwrap_or_default(),
+                            result.model.clone(),
+                            result.provider.clone(),
+                            result.prompt.clone(),
+                            result.response.clone(),
+                            result.response_time_ms.to_string(),
+                            optional(result.ttfb_ms),
+                            optional(result.ttft_ms),
+                            optional(result.inter_token_p50_ms),
+                            optional(result.inter_token_p95_ms),
+                            format!("{:.2}", result.tokens_per_second),
+                            result.input_tokens.to_string(),
+                            result.output_tokens.to_string(),
+                            format!("{:.6}", result.cost_estimate),
+                            result.quality_score.map(|q| format!("{:.1}", q)).unwrap_or_default(),
+                            result.passed.map(|p| p.to_string()).unwrap_or_default(),
+                            result.failures.join("; "),
+                            result.error.clone().unwrap_or_default(),
+                            result.timestamp.to_rfc3339(),
+                        ])
+                        .map_err(csv_error)?;
+                }
+                let bytes = writer
+                    .into_inner()
+                    .map_err(|e| EchomindError::Other(format!("Failed to export CSV: {}", e)))?;
+                Ok(String::from_utf8_lossy(&bytes).into_owned())
+            }
+            "markdown" | "md" => {
+                let mut report = String::from(
+                    "| Provider | Model | Runs | Errors | Pass rate | p50 latency | p95 latency | p50 TTFT | p95 TTFT | p50 inter-token | p95 inter-token | Tokens/s | Cost |\n",
+                );
+                report.push_str("| --- | --- | ---: | ---: | ---: | ---: | ---: | ---: | ---: | ---: | ---: | ---: | ---: |\n");
+                let ms = |v: Option<u64>| v.map(|v| format!("{} ms", v)).unwrap_or_else(|| "-".to_string());
+                for summary in self.summarize() {
+                    report.push_str(&format!(
+                        "| {} | {} | {} | {} | {:.0}% | {} | {} | {} | {} | {} | {} | {:.1} | ${:.4} |\n",
+                        summary.provider,
+                        summary.model,
+                        summary.runs,
+                        summary.errors,
+                        summary.pass_rate * 100.0,
+                        ms(summary.p50_latency_ms),
+                        ms(summary.p95_latency_ms),
+                        ms(summary.p50_ttft_ms),
+                        ms(summary.p95_ttft_ms),
+                        ms(summary.p50_inter_token_ms),
+                        ms(summary.p95_inter_token_ms),
+                        summary.tokens_per_second,
+                        summary.total_cost,
                     ));
                 }
-                
-                Ok(csv)
+
+                let failed: Vec<&BenchmarkResult> =
+                    self.benchmark_results.iter().filter(|r| r.passed == Some(false)).collect();
+                if !failed.is_empty() {
+                    report.push_str("\n## Failures\n\n");
+                    for result in failed {
+                        let reason = match &result.error {
+                            Some(error) => error.replace('\n', " "),
+                            None => result.failures.join("; "),
+                        };
+                        report.push_str(&format!(
+                            "- {}:{} `{}`: {}\n",
+                            result.provider,
+                            result.model,
+                            result.prompt_id.as_deref().unwrap_or("-"),
+                            reason
+                        ));
+                    }
+                }
+                Ok(report)
             }
-            _ => Err(EchomindError::Other(format!("Unsupported export format: {}", format))),
+            _ => Err(EchomindError::Other(format!(
+                "Unsupported export format: {} (use json, csv or markdown)",
+                format
+            ))),
         }
     }
 
@@ -278,39 +1029,30 @@ impl PerformanceMonitor {
         }
     }
 
+    fn record_stream(&mut self, timing: &StreamTiming, tokens_per_second: f64) {
+        let n = self.metrics.streamed_requests as f64;
+        let mean = |average: f64, value: f64| (average * n + value) / (n + 1.0);
+        if let Some(first_token) = timing.first_token {
+            self.metrics.average_ttft_ms = mean(self.metrics.average_ttft_ms, first_token.as_secs_f64() * 1000.0);
+        }
+        if let Some(gap) = timing.token_gap_percentile(50.0) {
+            self.metrics.average_inter_token_ms = mean(self.metrics.average_inter_token_ms, gap.as_secs_f64() * 1000.0);
+        }
+        self.metrics.average_stream_tokens_per_second =
+            mean(self.metrics.average_stream_tokens_per_second, tokens_per_second);
+        self.metrics.streamed_requests += 1;
+    }
+
+    pub fn metrics(&self) -> &PerformanceMetrics {
+        &self.metrics
+    }
+
     fn estimate_token_count(&self, text: &str) -> u32 {
-        // Simple token estimation (rough approximation)
-        // In reality, you'd use the tokenizer for the specific model
-        let word_count = text.split_whitespace().count() as u32;
-        let char_count = text.chars().count() as u32;
-        
-        // Average token is about 4 characters or 0.75 words
-        let tokens_by_chars = char_count / 4;
-        let tokens_by_words = (word_count as f32 * 1.3) as u32;
-        
-        tokens_by_chars.max(tokens_by_words)
+        estimate_tokens(text)
     }
 
     fn calculate_cost(&self, provider: &str, model: &str, input_tokens: u32, output_tokens: u32) -> f64 {
-        // Simplified cost calculation - in reality, you'd have a pricing table
-        let (input_cost_per_1k, output_cost_per_1k) = match provider {
-            "openai" => match model {
-                "gpt-4" => (0.03, 0.06),
-                "gpt-3.5-turbo" => (0.0015, 0.002),
-                _ => (0.001, 0.002),
-            },
-            "claude" => match model {
-                "claude-3-opus" => (0.015, 0.075),
-                "claude-3-sonnet" => (0.003, 0.015),
-                _ => (0.002, 0.01),
-            },
-            _ => (0.001, 0.002),
-        };
-        
-        let input_cost = (input_tokens as f64 / 1000.0) * input_cost_per_1k;
-        let output_cost = (output_tokens as f64 / 1000.0) * output_cost_per_1k;
-        
-        input_cost + output_cost
+        estimate_cost(provider, model, input_tokens, output_tokens)
     }
 
     fn calculate_quality_score(&self, response: &str) -> Option<f64> {
@@ -344,22 +1086,25 @@ impl PerformanceMonitor {
         Some(score.clamp(0.0, 100.0))
     }
 
+    /// Finds the client for `model` among clients keyed by provider name,
+    /// returning the provider and the bare model name. Models whose family
+    /// isn't recognized only resolve when a single client is configured.
     fn get_provider_for_model<'a>(
         &self,
         model: &str,
         api_clients: &'a HashMap<String, ApiClient>,
-    ) -> Option<(String, &'a ApiClient)> {
-        for (provider, client) in api_clients {
-            // This is a simplified check - in reality, you'd have a more sophisticated mapping
-            if model.starts_with("gpt") && provider == "openai" {
-                return Some((provider.clone(), client));
-            } else if model.starts_with("claude") && provider == "claude" {
-                return Some((provider.clone(), client));
-            } else if provider == "ollama" {
-                return Some((provider.clone(), client));
+    ) -> Option<(String, String, &'a ApiClient)> {
+        match Provider::for_model(model) {
+            Some((provider, name)) => {
+                let provider = provider.name().to_string();
+                api_clients.get(&provider).map(|client| (provider, name, client))
             }
+            None if api_clients.len() == 1 => api_clients
+                .iter()
+                .next()
+                .map(|(provider, client)| (provider.clone(), model.to_string(), client)),
+            None => None,
         }
-        None
     }
 
     fn select_best_model(&self, results: &[BenchmarkResult]) -> Option<String> {
@@ -460,6 +1205,181 @@ pub struct PerformanceReport {
     pub last_updated: chrono::DateTime<chrono::Utc>,
 }
 
+/// Rough dollar cost of a request from built-in per-1k-token prices.
+pub fn estimate_cost(provider: &str, model: &str, input_tokens: u32, output_tokens: u32) -> f64 {
+    // Simplified cost calculation - in reality, you'd have a pricing table
+    let (input_cost_per_1k, output_cost_per_1k) = match provider {
+        "openai" => match model {
+            "gpt-4" => (0.03, 0.06),
+            "gpt-3.5-turbo" => (0.0015, 0.002),
+            _ => (0.001, 0.002),
+        },
+        "claude" => match model {
+            "claude-3-opus" => (0.015, 0.075),
+            "claude-3-sonnet" => (0.003, 0.015),
+            _ => (0.002, 0.01),
+        },
+        _ => (0.001, 0.002),
+    };
+
+    let input_cost = (input_tokens as f64 / 1000.0) * input_cost_per_1k;
+    let output_cost = (output_tokens as f64 / 1000.0) * output_cost_per_1k;
+
+    input_cost + output_cost
+}
+
+/// Issues the load test's requests until `end`, reporting each through `tx`.
+async fn schedule_load(
+    client: ApiClient,
+    request: ChatRequest,
+    config: LoadTestConfig,
+    start: Instant,
+    end: Instant,
+    in_flight: Arc<AtomicUsize>,
+    tx: mpsc::UnboundedSender<LoadSample>,
+) {
+    let send = move |scheduled: Instant| {
+        let client = client.clone();
+        let request = request.clone();
+        let in_flight = Arc::clone(&in_flight);
+        async move {
+            in_flight.fetch_add(1, Ordering::Relaxed);
+            let outcome = match client.send_message(request).await {
+                Ok(text) => Ok(estimate_tokens(&text)),
+                Err(EchomindError::ApiError { status, .. }) => Err(format!("ApiError {}", status)),
+                Err(e) => Err(e.kind().to_string()),
+            };
+            in_flight.fetch_sub(1, Ordering::Relaxed);
+            LoadSample::Completed { scheduled, latency: scheduled.elapsed(), outcome }
+        }
+    };
+
+    match config.rate {
+        Some(rate) => {
+            let semaphore = Arc::new(Semaphore::new(config.concurrency));
+            let mut ticker = tokio::time::interval_at(
+                tokio::time::Instant::from_std(start),
+                Duration::from_secs_f64(1.0 / rate),
+            );
+            // Catch up on late ticks so the schedule keeps its rate
+            ticker.set_missed_tick_behavior(MissedTickBehavior::Burst);
+            loop {
+                let scheduled = ticker.tick().await.into_std();
+                if scheduled >= end {
+                    break;
+                }
+                match Arc::clone(&semaphore).try_acquire_owned() {
+                    Ok(permit) => {
+                        let request = send(scheduled);
+                        let tx = tx.clone();
+                        tokio::spawn(async move {
+                            let _ = tx.send(request.await);
+                            drop(permit);
+                        });
+                    }
+                    Err(_) => {
+                        let _ = tx.send(LoadSample::Dropped { scheduled });
+                    }
+                }
+            }
+        }
+        None => {
+            let workers: Vec<_> = (0..config.concurrency)
+                .map(|_| {
+                    let send = send.clone();
+                    let tx = tx.clone();
+                    tokio::spawn(async move {
+                        loop {
+                            let scheduled = Instant::now();
+                            if scheduled >= end {
+                                break;
+                            }
+                            let _ = tx.send(send(scheduled).await);
+                        }
+                    })
+                })
+                .collect();
+            for worker in workers {
+                let _ = worker.await;
+            }
+        }
+    }
+}
+
+/// Rough token count for text; real counts depend on each model's tokenizer.
+pub fn estimate_tokens(text: &str) -> u32 {
+    // Average token is about 4 characters or 0.75 words
+    let word_count = text.split_whitespace().count() as u32;
+    let char_count = text.chars().count() as u32;
+    let tokens_by_chars = char_count / 4;
+    let tokens_by_words = (word_count as f32 * 1.3) as u32;
+
+    tokens_by_chars.max(tokens_by_words)
+}
+
+/// Streams the request when the provider supports it, so the reply comes
+/// back with first-token and inter-token timings.
+pub(crate) async fn send_timed(client: &ApiClient, mut request: ChatRequest) -> (Result<String>, Option<StreamTiming>, Duration) {
+    let start_time = Instant::now();
+    if client.provider().supports_streaming() {
+        request.stream = Some(true);
+        match client.send_message_stream_timed(request, |_| {}).await {
+            Ok((text, timing)) => {
+                let total = timing.total;
+                (Ok(text), Some(timing), total)
+            }
+            Err(e) => (Err(e), None, start_time.elapsed()),
+        }
+    } else {
+        let response = client.send_message(request).await;
+        (response, None, start_time.elapsed())
+    }
+}
+
+/// `send_timed` through `UsageMeter::send`, with any budget warning last.
+/// Over a blocking budget nothing is sent and the block is the error.
+pub(crate) async fn send_timed_metered(
+    usage: Option<&UsageMeter>,
+    provider: &str,
+    client: &ApiClient,
+    request: &ChatRequest,
+) -> Result<(Result<String>, Option<StreamTiming>, Duration, Option<String>)> {
+    let mut budget_warning = None;
+    let mut timed = None;
+    let timed_slot = &mut timed;
+    let response = UsageMeter::send(usage, provider, request, |warning| {
+        budget_warning = warning;
+        let sending = send_timed(client, request.clone());
+        async move {
+            let (response, timing, elapsed) = sending.await;
+            *timed_slot = Some((timing, elapsed));
+            response
+        }
+    })
+    .await;
+    let Some((timing, elapsed)) = timed else {
+        return Err(response.expect_err("a request that wasn't sent has no reply"));
+    };
+    Ok((response, timing, elapsed, budget_warning))
+}
+
+/// Output tokens per second, measured from the first token when streaming.
+fn throughput(output_tokens: u32, total: Duration, timing: Option<&StreamTiming>) -> f64 {
+    let elapsed = timing.and_then(|t| t.generation_time()).unwrap_or(total);
+    output_tokens as f64 / elapsed.as_secs_f64().max(0.001)
+}
+
+/// Nearest-rank percentile.
+fn percentile(values: &[u64], pct: f64) -> Option<u64> {
+    if values.is_empty() {
+        return None;
+    }
+    let mut sorted = values.to_vec();
+    sorted.sort_unstable();
+    let rank = ((pct / 100.0) * sorted.len() as f64).ceil() as usize;
+    Some(sorted[rank.clamp(1, sorted.len()) - 1])
+}
+
 impl Default for PerformanceMonitor {
     fn default() -> Self {
         Self::new()
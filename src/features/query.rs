@@ -0,0 +1,1313 @@
+//! A small SQL-like query language over a loaded `Dataset`.
+//!
+//! ```sql
+//! SELECT region, SUM(revenue) AS total, COUNT(*)
+//! FROM data
+//! WHERE year >= 2024 AND status != 'void'
+//! GROUP BY region
+//! HAVING total > 1000
+//! ORDER BY total DESC
+//! LIMIT 5
+//! ```
+//!
+//! The `FROM` clause is optional and names nothing in particular: a query
+//! always runs over the whole file. Supported are `SELECT [DISTINCT]`, `*`,
+//! aliases, `WHERE`, `GROUP BY`, `HAVING`, `ORDER BY` (expressions, aliases or
+//! 1-based positions), `LIMIT`/`OFFSET`, arithmetic, `AND`/`OR`/`NOT`,
+//! comparisons, `IS [NOT] NULL`, `[NOT] IN (...)`, `[NOT] LIKE` (case
+//! insensitive, `%` and `_`), `BETWEEN`, the aggregates `COUNT`, `SUM`, `AVG`,
+//! `MIN`, `MAX`, `MEDIAN` (with `DISTINCT`) and the functions `LOWER`,
+//! `UPPER`, `TRIM`, `LENGTH`, `ROUND`, `ABS`, `COALESCE`, `YEAR` and `MONTH`.
+//! Column names with spaces are written in double quotes or backticks.
+
+use crate::error::{EchomindError, Result};
+use crate::features::data_processing::{Dataset, Record};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QueryResult {
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<Value>>,
+    /// Number of source rows that passed the WHERE clause
+    pub matched_rows: usize,
+}
+
+impl QueryResult {
+    /// Renders the result as a pipe-separated table, showing at most
+    /// `max_rows` rows and saying how many were left out.
+    pub fn to_table(&self, max_rows: usize) -> String {
+        let mut out = self.columns.join(" | ");
+        for row in self.rows.iter().take(max_rows) {
+            out.push('\n');
+            let cells: Vec<String> = row.iter().map(display_value).collect();
+            out.push_str(&cells.join(" | "));
+        }
+        if self.rows.len() > max_rows {
+            out.push_str(&format!("\n... {} more rows not shown", self.rows.len() - max_rows));
+        }
+        out
+    }
+
+    /// The result as a dataset, to chart or query it further.
+    pub fn to_dataset(&self) -> Dataset {
+        Dataset {
+            file_type: "query".to_string(),
+            column_names: self.columns.clone(),
+            records: self
+                .rows
+                .iter()
+                .map(|row| self.columns.iter().cloned().zip(row.iter().cloned()).collect())
+                .collect(),
+        }
+    }
+
+    /// The rows as JSON objects keyed by column name.
+    pub fn to_records(&self) -> Vec<serde_json::Map<String, Value>> {
+        self.rows
+            .iter()
+            .map(|row| self.columns.iter().cloned().zip(row.iter().cloned()).collect())
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Query {
+    pub distinct: bool,
+    /// Empty for `SELECT *`
+    pub select: Vec<SelectItem>,
+    pub filter: Option<Expr>,
+    pub group_by: Vec<Expr>,
+    pub having: Option<Expr>,
+    pub order_by: Vec<OrderBy>,
+    pub limit: Option<usize>,
+    pub offset: usize,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SelectItem {
+    pub expr: Expr,
+    pub alias: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrderBy {
+    pub expr: Expr,
+    pub descending: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Column(String),
+    Literal(Value),
+    Not(Box<Expr>),
+    Negate(Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+    IsNull { expr: Box<Expr>, negated: bool },
+    InList { expr: Box<Expr>, list: Vec<Expr>, negated: bool },
+    Like { expr: Box<Expr>, pattern: Box<Expr>, negated: bool },
+    Function(String, Vec<Expr>),
+    /// `None` argument for `COUNT(*)`
+    Aggregate { func: AggregateFunc, arg: Option<Box<Expr>>, distinct: bool },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    And,
+    Or,
+    Eq,
+    NotEq,
+    Lt,
+    LtEq,
+    Gt,
+    GtEq,
+    Add,
+    Sub,
+    Mul,
+    Div,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AggregateFunc {
+    Count,
+    Sum,
+    Avg,
+    Min,
+    Max,
+    Median,
+}
+
+impl AggregateFunc {
+    fn from_name(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "count" => Some(AggregateFunc::Count),
+            "sum" => Some(AggregateFunc::Sum),
+            "avg" | "mean" => Some(AggregateFunc::Avg),
+            "min" => Some(AggregateFunc::Min),
+            "max" => Some(AggregateFunc::Max),
+            "median" => Some(AggregateFunc::Median),
+            _ => None,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            AggregateFunc::Count => "count",
+            AggregateFunc::Sum => "sum",
+            AggregateFunc::Avg => "avg",
+            AggregateFunc::Min => "min",
+            AggregateFunc::Max => "max",
+            AggregateFunc::Median => "median",
+        }
+    }
+}
+
+impl BinaryOp {
+    fn symbol(&self) -> &'static str {
+        match self {
+            BinaryOp::And => "AND",
+            BinaryOp::Or => "OR",
+            BinaryOp::Eq => "=",
+            BinaryOp::NotEq => "!=",
+            BinaryOp::Lt => "<",
+            BinaryOp::LtEq => "<=",
+            BinaryOp::Gt => ">",
+            BinaryOp::GtEq => ">=",
+            BinaryOp::Add => "+",
+            BinaryOp::Sub => "-",
+            BinaryOp::Mul => "*",
+            BinaryOp::Div => "/",
+        }
+    }
+}
+
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Expr::Column(name) => write!(f, "{}", name),
+            Expr::Literal(Value::String(s)) => write!(f, "'{}'", s.replace('\'', "''")),
+            Expr::Literal(value) => write!(f, "{}", value),
+            Expr::Not(expr) => write!(f, "NOT {}", expr),
+            Expr::Negate(expr) => write!(f, "-{}", expr),
+            Expr::Binary(op, left, right) => write!(f, "{} {} {}", left, op.symbol(), right),
+            Expr::IsNull { expr, negated } => {
+                write!(f, "{} IS {}NULL", expr, if *negated { "NOT " } else { "" })
+            }
+            Expr::InList { expr, list, negated } => {
+                let items: Vec<String> = list.iter().map(|e| e.to_string()).collect();
+                write!(f, "{} {}IN ({})", expr, if *negated { "NOT " } else { "" }, items.join(", "))
+            }
+            Expr::Like { expr, pattern, negated } => {
+                write!(f, "{} {}LIKE {}", expr, if *negated { "NOT " } else { "" }, pattern)
+            }
+            Expr::Function(name, args) => {
+                let args: Vec<String> = args.iter().map(|e| e.to_string()).collect();
+                write!(f, "{}({})", name, args.join(", "))
+            }
+            Expr::Aggregate { func, arg, distinct } => {
+                let distinct = if *distinct { "DISTINCT " } else { "" };
+                match arg {
+                    Some(arg) => write!(f, "{}({}{})", func.name(), distinct, arg),
+                    None => write!(f, "{}(*)", func.name()),
+                }
+            }
+        }
+    }
+}
+
+/// Parses and runs `sql` over `dataset`.
+pub fn execute(dataset: &Dataset, sql: &str) -> Result<QueryResult> {
+    parse(sql)?.execute(dataset)
+}
+
+/// Parses a query without running it.
+pub fn parse(sql: &str) -> Result<Query> {
+    let tokens = tokenize(sql)?;
+    let mut parser = Parser { tokens, pos: 0 };
+    let query = parser.query()?;
+    if let Some(token) = parser.peek() {
+        return Err(query_error(format!("unexpected {} after the end of the query", token)));
+    }
+    Ok(query)
+}
+
+/// Parses a single row-level expression, such as `price * quantity` or
+/// `status = 'paid' AND total > 0`. Aggregates are not allowed.
+pub fn parse_expr(text: &str) -> Result<Expr> {
+    let tokens = tokenize(text)?;
+    let mut parser = Parser { tokens, pos: 0 };
+    let expr = parser.expr()?;
+    if let Some(token) = parser.peek() {
+        return Err(query_error(format!("unexpected {} after the end of the expression", token)));
+    }
+    if expr.any(&|e| matches!(e, Expr::Aggregate { .. })) {
+        return Err(query_error("aggregates are not allowed in a row expression"));
+    }
+    Ok(expr)
+}
+
+fn query_error(message: impl Into<String>) -> EchomindError {
+    EchomindError::Other(format!("Query error: {}", message.into()))
+}
+
+// ---------------------------------------------------------------------------
+// Tokenizer
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    /// A double-quoted or backtick-quoted identifier
+    Quoted(String),
+    Str(String),
+    Number(f64),
+    Symbol(&'static str),
+}
+
+impl fmt::Display for Token {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Token::Word(w) => write!(f, "'{}'", w),
+            Token::Quoted(w) => write!(f, "\"{}\"", w),
+            Token::Str(s) => write!(f, "string '{}'", s),
+            Token::Number(n) => write!(f, "number {}", n),
+            Token::Symbol(s) => write!(f, "'{}'", s),
+        }
+    }
+}
+
+const SYMBOLS: [&str; 14] = ["<>", "!=", "<=", ">=", "=", "<", ">", "(", ")", ",", "*", "+", "-", "/"];
+
+fn tokenize(sql: &str) -> Result<Vec<Token>> {
+    let chars: Vec<char> = sql.chars().collect();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let c = chars[i];
+        if c.is_whitespace() || c == ';' {
+            i += 1;
+        } else if c == '\'' || c == '"' || c == '`' {
+            // Quotes are escaped by doubling them, as in SQL
+            let mut text = String::new();
+            i += 1;
+            loop {
+                match chars.get(i) {
+                    None => return Err(query_error(format!("unterminated {}quote", c))),
+                    Some(&q) if q == c && chars.get(i + 1) == Some(&c) => {
+                        text.push(c);
+                        i += 2;
+                    }
+                    Some(&q) if q == c => {
+                        i += 1;
+                        break;
+                    }
+                    Some(&other) => {
+                        text.push(other);
+                        i += 1;
+                    }
+                }
+            }
+            tokens.push(if c == '\'' { Token::Str(text) } else { Token::Quoted(text) });
+        } else if c.is_ascii_digit() || (c == '.' && chars.get(i + 1).is_some_and(|d| d.is_ascii_digit())) {
+            let start = i;
+            while i < chars.len() && (chars[i].is_ascii_digit() || chars[i] == '.') {
+                i += 1;
+            }
+            let text: String = chars[start..i].iter().collect();
+            let number = text
+                .parse()
+                .map_err(|_| query_error(format!("invalid number '{}'", text)))?;
+            tokens.push(Token::Number(number));
+        } else if c.is_alphabetic() || c == '_' {
+            let start = i;
+            while i < chars.len() && (chars[i].is_alphanumeric() || chars[i] == '_' || chars[i] == '.') {
+                i += 1;
+            }
+            tokens.push(Token::Word(chars[start..i].iter().collect()));
+        } else {
+            let rest: String = chars[i..chars.len().min(i + 2)].iter().collect();
+            let symbol = SYMBOLS
+                .iter()
+                .find(|s| rest.starts_with(**s))
+                .ok_or_else(|| query_error(format!("unexpected character '{}'", c)))?;
+            tokens.push(Token::Symbol(symbol));
+            i += symbol.len();
+        }
+    }
+    Ok(tokens)
+}
+
+// ---------------------------------------------------------------------------
+// Parser
+
+const RESERVED: [&str; 22] = [
+    "select", "distinct", "from", "where", "group", "by", "having", "order", "asc", "desc", "limit", "offset",
+    "and", "or", "not", "as", "is", "null", "in", "like", "between", "true",
+];
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn peek_keyword(&self, keyword: &str) -> bool {
+        matches!(self.peek(), Some(Token::Word(w)) if w.eq_ignore_ascii_case(keyword))
+    }
+
+    fn keyword(&mut self, keyword: &str) -> bool {
+        if self.peek_keyword(keyword) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect_keyword(&mut self, keyword: &str) -> Result<()> {
+        if self.keyword(keyword) {
+            Ok(())
+        } else {
+            Err(self.unexpected(&keyword.to_uppercase()))
+        }
+    }
+
+    fn symbol(&mut self, symbol: &str) -> bool {
+        if matches!(self.peek(), Some(Token::Symbol(s)) if *s == symbol) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect_symbol(&mut self, symbol: &str) -> Result<()> {
+        if self.symbol(symbol) {
+            Ok(())
+        } else {
+            Err(self.unexpected(&format!("'{}'", symbol)))
+        }
+    }
+
+    fn unexpected(&self, expected: &str) -> EchomindError {
+        match self.peek() {
+            Some(token) => query_error(format!("expected {} but found {}", expected, token)),
+            None => query_error(format!("expected {} but the query ended", expected)),
+        }
+    }
+
+    fn query(&mut self) -> Result<Query> {
+        self.expect_keyword("select")?;
+        let distinct = self.keyword("distinct");
+
+        let mut select = Vec::new();
+        if !self.symbol("*") {
+            loop {
+                let expr = self.expr()?;
+                // `AS` is optional before an alias
+                let bare_alias = matches!(self.peek(), Some(Token::Word(w)) if !is_reserved(w))
+                    || matches!(self.peek(), Some(Token::Quoted(_)));
+                let alias = if self.keyword("as") || bare_alias {
+                    Some(self.identifier()?)
+                } else {
+                    None
+                };
+                select.push(SelectItem { expr, alias });
+                if !self.symbol(",") {
+                    break;
+                }
+            }
+        }
+
+        if self.keyword("from") {
+            // The source is the loaded file; the table name is only decoration
+            match self.next() {
+                Some(Token::Word(_)) | Some(Token::Quoted(_)) | Some(Token::Str(_)) => {}
+                _ => return Err(query_error("expected a table name after FROM")),
+            }
+        }
+
+        let filter = if self.keyword("where") { Some(self.expr()?) } else { None };
+
+        let mut group_by = Vec::new();
+        if self.keyword("group") {
+            self.expect_keyword("by")?;
+            loop {
+                group_by.push(self.expr()?);
+                if !self.symbol(",") {
+                    break;
+                }
+            }
+        }
+
+        let having = if self.keyword("having") { Some(self.expr()?) } else { None };
+
+        let mut order_by = Vec::new();
+        if self.keyword("order") {
+            self.expect_keyword("by")?;
+            loop {
+                let expr = self.expr()?;
+                let descending = if self.keyword("desc") {
+                    true
+                } else {
+                    self.keyword("asc");
+                    false
+                };
+                order_by.push(OrderBy { expr, descending });
+                if !self.symbol(",") {
+                    break;
+                }
+            }
+        }
+
+        let mut limit = None;
+        let mut offset = 0;
+        if self.keyword("limit") {
+            limit = Some(self.count("LIMIT")?);
+            if self.keyword("offset") {
+                offset = self.count("OFFSET")?;
+            } else if self.symbol(",") {
+                // LIMIT offset, count
+                offset = limit.take().unwrap_or_default();
+                limit = Some(self.count("LIMIT")?);
+            }
+        }
+
+        Ok(Query {
+            distinct,
+            select,
+            filter,
+            group_by,
+            having,
+            order_by,
+            limit,
+            offset,
+        })
+    }
+
+    fn count(&mut self, clause: &str) -> Result<usize> {
+        match self.next() {
+            Some(Token::Number(n)) if n >= 0.0 && n.fract() == 0.0 => Ok(n as usize),
+            _ => Err(query_error(format!("{} needs a whole number", clause))),
+        }
+    }
+
+    fn identifier(&mut self) -> Result<String> {
+        match self.next() {
+            Some(Token::Word(w)) | Some(Token::Quoted(w)) => Ok(w),
+            _ => {
+                self.pos -= 1;
+                Err(self.unexpected("a name"))
+            }
+        }
+    }
+
+    fn expr(&mut self) -> Result<Expr> {
+        let mut left = self.and_expr()?;
+        while self.keyword("or") {
+            let right = self.and_expr()?;
+            left = Expr::Binary(BinaryOp::Or, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn and_expr(&mut self) -> Result<Expr> {
+        let mut left = self.not_expr()?;
+        while self.keyword("and") {
+            let right = self.not_expr()?;
+            left = Expr::Binary(BinaryOp::And, Box::new(left), Box::new(right));
+        }
+        Ok(left)
+    }
+
+    fn not_expr(&mut self) -> Result<Expr> {
+        if self.keyword("not") {
+            return Ok(Expr::Not(Box::new(self.not_expr()?)));
+        }
+        self.comparison()
+    }
+
+    fn comparison(&mut self) -> Result<Expr> {
+        let left = self.additive()?;
+
+        if self.keyword("is") {
+            let negated = self.keyword("not");
+            self.expect_keyword("null")?;
+            return Ok(Expr::IsNull { expr: Box::new(left), negated });
+        }
+
+        let negated = self.keyword("not");
+        if self.keyword("in") {
+            self.expect_symbol("(")?;
+            let mut list = Vec::new();
+            loop {
+                list.push(self.additive()?);
+                if !self.symbol(",") {
+                    break;
+                }
+            }
+            self.expect_symbol(")")?;
+            return Ok(Expr::InList { expr: Box::new(left), list, negated });
+        }
+        if self.keyword("like") {
+            let pattern = self.additive()?;
+            return Ok(Expr::Like { expr: Box::new(left), pattern: Box::new(pattern), negated });
+        }
+        if self.keyword("between") {
+            let low = self.additive()?;
+            self.expect_keyword("and")?;
+            let high = self.additive()?;
+            let between = Expr::Binary(
+                BinaryOp::And,
+                Box::new(Expr::Binary(BinaryOp::GtEq, Box::new(left.clone()), Box::new(low))),
+                Box::new(Expr::Binary(BinaryOp::LtEq, Box::new(left), Box::new(high))),
+            );
+            return Ok(if negated { Expr::Not(Box::new(between)) } else { between });
+        }
+        if negated {
+            return Err(self.unexpected("IN, LIKE or BETWEEN after NOT"));
+        }
+
+        let op = match self.peek() {
+            Some(Token::Symbol("=")) => BinaryOp::Eq,
+            Some(Token::Symbol("!=")) | Some(Token::Symbol("<>")) => BinaryOp::NotEq,
+            Some(Token::Symbol("<")) => BinaryOp::Lt,
+            Some(Token::Symbol("<=")) => BinaryOp::LtEq,
+            Some(Token::Symbol(">")) => BinaryOp::Gt,
+            Some(Token::Symbol(">=")) => BinaryOp::GtEq,
+            _ => return Ok(left),
+        };
+        self.pos += 1;
+        let right = self.additive()?;
+        Ok(Expr::Binary(op, Box::new(left), Box::new(right)))
+    }
+
+    fn additive(&mut self) -> Result<Expr> {
+        let mut left = self.multiplicative()?;
+        loop {
+            let op = if self.symbol("+") {
+                BinaryOp::Add
+            } else if self.symbol("-") {
+                BinaryOp::Sub
+            } else {
+                return Ok(left);
+            };
+            let right = self.multiplicative()?;
+            left = Expr::Binary(op, Box::new(left), Box::new(right));
+        }
+    }
+
+    fn multiplicative(&mut self) -> Result<Expr> {
+        let mut left = self.unary()?;
+        loop {
+            let op = if self.symbol("*") {
+                BinaryOp::Mul
+            } else if self.symbol("/") {
+                BinaryOp::Div
+            } else {
+                return Ok(left);
+            };
+            let right = self.unary()?;
+            left = Expr::Binary(op, Box::new(left), Box::new(right));
+        }
+    }
+
+    fn unary(&mut self) -> Result<Expr> {
+        if self.symbol("-") {
+            return Ok(Expr::Negate(Box::new(self.unary()?)));
+        }
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Expr> {
+        match self.next() {
+            Some(Token::Number(n)) => Ok(Expr::Literal(number_value(n))),
+            Some(Token::Str(s)) => Ok(Expr::Literal(Value::String(s))),
+            Some(Token::Quoted(name)) => Ok(Expr::Column(name)),
+            Some(Token::Symbol("(")) => {
+                let expr = self.expr()?;
+                self.expect_symbol(")")?;
+                Ok(expr)
+            }
+            Some(Token::Word(word)) => {
+                match word.to_lowercase().as_str() {
+                    "null" => return Ok(Expr::Literal(Value::Null)),
+                    "true" => return Ok(Expr::Literal(Value::Bool(true))),
+                    "false" => return Ok(Expr::Literal(Value::Bool(false))),
+                    _ => {}
+                }
+                if !self.symbol("(") {
+                    if is_reserved(&word) {
+                        self.pos -= 1;
+                        return Err(self.unexpected("a column or value"));
+                    }
+                    return Ok(Expr::Column(word));
+                }
+
+                if let Some(func) = AggregateFunc::from_name(&word) {
+                    if func == AggregateFunc::Count && self.symbol("*") {
+                        self.expect_symbol(")")?;
+                        return Ok(Expr::Aggregate { func, arg: None, distinct: false });
+                    }
+                    let distinct = self.keyword("distinct");
+                    let arg = self.expr()?;
+                    self.expect_symbol(")")?;
+                    return Ok(Expr::Aggregate { func, arg: Some(Box::new(arg)), distinct });
+                }
+
+                let name = word.to_lowercase();
+                let arity = match name.as_str() {
+                    "lower" | "upper" | "trim" | "length" | "abs" | "year" | "month" => 1..=1,
+                    "round" => 1..=2,
+                    "coalesce" => 1..=usize::MAX,
+                    _ => return Err(query_error(format!("unknown function '{}'", word))),
+                };
+                let mut args = Vec::new();
+                if !self.symbol(")") {
+                    loop {
+                        args.push(self.expr()?);
+                        if !self.symbol(",") {
+                            break;
+                        }
+                    }
+                    self.expect_symbol(")")?;
+                }
+                if !arity.contains(&args.len()) {
+                    return Err(query_error(format!("wrong number of arguments to {}()", name)));
+                }
+                Ok(Expr::Function(name, args))
+            }
+            _ => {
+                self.pos -= 1;
+                Err(self.unexpected("a column or value"))
+            }
+        }
+    }
+}
+
+fn is_reserved(word: &str) -> bool {
+    RESERVED.iter().any(|r| r.eq_ignore_ascii_case(word))
+}
+
+// ---------------------------------------------------------------------------
+// Execution
+
+impl Query {
+    pub fn execute(&self, dataset: &Dataset) -> Result<QueryResult> {
+        self.validate(dataset)?;
+
+        let mut matched: Vec<&Record> = Vec::new();
+        for record in &dataset.records {
+            let keep = match &self.filter {
+                Some(filter) => truthy(&eval_row(filter, record, dataset)?),
+                None => true,
+            };
+            if keep {
+                matched.push(record);
+            }
+        }
+
+        let select: Vec<SelectItem> = if self.select.is_empty() {
+            dataset
+                .column_names
+                .iter()
+                .map(|name| SelectItem { expr: Expr::Column(name.clone()), alias: None })
+                .collect()
+        } else {
+            self.select.clone()
+        };
+        let columns: Vec<String> = select
+            .iter()
+            .map(|item| item.alias.clone().unwrap_or_else(|| item.expr.to_string()))
+            .collect();
+
+        let grouped = !self.group_by.is_empty() || select.iter().any(|item| item.expr.has_aggregate());
+
+        // Each output row with the keys it is sorted by
+        let mut output: Vec<(Vec<Value>, Vec<Value>)> = Vec::new();
+        if grouped {
+            for group in self.groups(&matched, dataset)? {
+                let row = select
+                    .iter()
+                    .map(|item| eval_group(&item.expr, &group, dataset))
+                    .collect::<Result<Vec<_>>>()?;
+                if let Some(having) = &self.having {
+                    let having = self.resolve_output(having, &select, &columns);
+                    if !truthy(&eval_output(&having, &row, &group, dataset)?) {
+                        continue;
+                    }
+                }
+                let keys = self.sort_keys(&select, &columns, &row, &group, dataset)?;
+                output.push((row, keys));
+            }
+        } else {
+            for record in &matched {
+                let row = select
+                    .iter()
+                    .map(|item| eval_row(&item.expr, record, dataset))
+                    .collect::<Result<Vec<_>>>()?;
+                let keys = self.sort_keys(&select, &columns, &row, std::slice::from_ref(record), dataset)?;
+                output.push((row, keys));
+            }
+        }
+
+        if self.distinct {
+            let mut seen = HashSet::new();
+            output.retain(|(row, _)| seen.insert(row.iter().map(|v| v.to_string()).collect::<Vec<_>>()));
+        }
+
+        if !self.order_by.is_empty() {
+            output.sort_by(|(_, a), (_, b)| {
+                for (index, order) in self.order_by.iter().enumerate() {
+                    let ordering = compare_values(&a[index], &b[index]);
+                    let ordering = if order.descending { ordering.reverse() } else { ordering };
+                    if ordering != Ordering::Equal {
+                        return ordering;
+                    }
+                }
+                Ordering::Equal
+            });
+        }
+
+        let rows = output
+            .into_iter()
+            .map(|(row, _)| row)
+            .skip(self.offset)
+            .take(self.limit.unwrap_or(usize::MAX))
+            .collect();
+
+        Ok(QueryResult {
+            columns,
+            rows,
+            matched_rows: matched.len(),
+        })
+    }
+
+    /// Rejects unknown columns and bare columns that are neither grouped nor
+    /// aggregated, before any row is read.
+    fn validate(&self, dataset: &Dataset) -> Result<()> {
+        let aliases: Vec<&str> = self.select.iter().filter_map(|item| item.alias.as_deref()).collect();
+        let mut source_exprs: Vec<&Expr> = self.select.iter().map(|item| &item.expr).collect();
+        source_exprs.extend(self.filter.iter());
+        source_exprs.extend(self.group_by.iter());
+
+        for expr in &source_exprs {
+            expr.check_columns(dataset, &[])?;
+        }
+        for expr in self.having.iter().chain(self.order_by.iter().map(|o| &o.expr)) {
+            expr.check_columns(dataset, &aliases)?;
+        }
+        if let Some(filter) = &self.filter {
+            if filter.has_aggregate() {
+                return Err(query_error("aggregates are not allowed in WHERE; use HAVING"));
+            }
+        }
+        for expr in &self.group_by {
+            if expr.has_aggregate() {
+                return Err(query_error("aggregates are not allowed in GROUP BY"));
+            }
+        }
+        for item in &self.select {
+            if item.expr.has_nested_aggregate(false) {
+                return Err(query_error(format!("nested aggregate in {}", item.expr)));
+            }
+        }
+
+        let grouped = !self.group_by.is_empty() || self.select.iter().any(|item| item.expr.has_aggregate());
+        if grouped {
+            if self.select.is_empty() {
+                return Err(query_error("SELECT * cannot be used with GROUP BY or aggregates"));
+            }
+            for item in &self.select {
+                if let Some(column) = item.expr.ungrouped_column(&self.group_by) {
+                    return Err(query_error(format!(
+                        "column '{}' must appear in GROUP BY or be used in an aggregate",
+                        column
+                    )));
+                }
+            }
+        } else if self.having.is_some() {
+            return Err(query_error("HAVING needs GROUP BY or an aggregate"));
+        }
+        Ok(())
+    }
+
+    fn groups<'a>(&self, matched: &[&'a Record], dataset: &Dataset) -> Result<Vec<Vec<&'a Record>>> {
+        if self.group_by.is_empty() {
+            return Ok(vec![matched.to_vec()]);
+        }
+
+        // Groups in order of first appearance
+        let mut index: HashMap<Vec<String>, usize> = HashMap::new();
+        let mut groups: Vec<Vec<&Record>> = Vec::new();
+        for record in matched {
+            let key = self
+                .group_by
+                .iter()
+                .map(|expr| eval_row(expr, record, dataset).map(|v| v.to_string()))
+                .collect::<Result<Vec<_>>>()?;
+            let slot = *index.entry(key).or_insert_with(|| {
+                groups.push(Vec::new());
+                groups.len() - 1
+            });
+            groups[slot].push(record);
+        }
+        Ok(groups)
+    }
+
+    /// Rewrites references to output columns (aliases, select expressions or
+    /// 1-based positions) as `@n` placeholders evaluated against the row.
+    fn resolve_output(&self, expr: &Expr, select: &[SelectItem], columns: &[String]) -> Expr {
+        if let Some(index) = select.iter().position(|item| &item.expr == expr) {
+            return Expr::Column(format!("@{}", index));
+        }
+        match expr {
+            Expr::Column(name) => match columns.iter().position(|c| c == name) {
+                Some(index) if select[index].alias.is_some() || !matches!(select[index].expr, Expr::Column(_)) => {
+                    Expr::Column(format!("@{}", index))
+                }
+                _ => expr.clone(),
+            },
+            Expr::Not(inner) => Expr::Not(Box::new(self.resolve_output(inner, select, columns))),
+            Expr::Negate(inner) => Expr::Negate(Box::new(self.resolve_output(inner, select, columns))),
+            Expr::Binary(op, left, right) => Expr::Binary(
+                *op,
+                Box::new(self.resolve_output(left, select, columns)),
+                Box::new(self.resolve_output(right, select, columns)),
+            ),
+            Expr::IsNull { expr, negated } => Expr::IsNull {
+                expr: Box::new(self.resolve_output(expr, select, columns)),
+                negated: *negated,
+            },
+            Expr::InList { expr, list, negated } => Expr::InList {
+                expr: Box::new(self.resolve_output(expr, select, columns)),
+                list: list.clone(),
+                negated: *negated,
+            },
+            Expr::Like { expr, pattern, negated } => Expr::Like {
+                expr: Box::new(self.resolve_output(expr, select, columns)),
+                pattern: pattern.clone(),
+                negated: *negated,
+            },
+            Expr::Function(name, args) => Expr::Function(
+                name.clone(),
+                args.iter().map(|arg| self.resolve_output(arg, select, columns)).collect(),
+            ),
+            other => other.clone(),
+        }
+    }
+
+    fn sort_keys(
+        &self,
+        select: &[SelectItem],
+        columns: &[String],
+        row: &[Value],
+        records: &[&Record],
+        dataset: &Dataset,
+    ) -> Result<Vec<Value>> {
+        self.order_by
+            .iter()
+            .map(|order| match &order.expr {
+                Expr::Literal(Value::Number(n)) => {
+                    let position = n.as_u64().unwrap_or(0) as usize;
+                    row.get(position.wrapping_sub(1)).cloned().ok_or_else(|| {
+                        query_error(format!("ORDER BY position {} is out of range", n))
+                    })
+                }
+                expr => eval_output(&self.resolve_output(expr, select, columns), row, records, dataset),
+            })
+            .collect()
+    }
+}
+
+impl Expr {
+    fn has_aggregate(&self) -> bool {
+        self.any(&|e| matches!(e, Expr::Aggregate { .. }))
+    }
+
+    fn has_nested_aggregate(&self, inside: bool) -> bool {
+        match self {
+            Expr::Aggregate { arg, .. } => inside || arg.as_ref().is_some_and(|a| a.has_nested_aggregate(true)),
+            other => other.children().iter().any(|c| c.has_nested_aggregate(inside)),
+        }
+    }
+
+    fn any(&self, predicate: &dyn Fn(&Expr) -> bool) -> bool {
+        predicate(self) || self.children().iter().any(|c| c.any(predicate))
+    }
+
+    fn children(&self) -> Vec<&Expr> {
+        match self {
+            Expr::Column(_) | Expr::Literal(_) => Vec::new(),
+            Expr::Not(e) | Expr::Negate(e) | Expr::IsNull { expr: e, .. } => vec![e],
+            Expr::Binary(_, l, r) | Expr::Like { expr: l, pattern: r, .. } => vec![l, r],
+            Expr::InList { expr, list, .. } => std::iter::once(expr.as_ref()).chain(list.iter()).collect(),
+            Expr::Function(_, args) => args.iter().collect(),
+            Expr::Aggregate { arg, .. } => arg.iter().map(|a| a.as_ref()).collect(),
+        }
+    }
+
+    /// Evaluates the expression against one row whose columns are
+    /// `columns`; names are matched case-insensitively as in queries.
+    pub fn evaluate(&self, record: &Record, columns: &[String]) -> Result<Value> {
+        eval(self, &mut |e| match e {
+            Expr::Column(name) => Ok(Some(
+                resolve_name(columns, name)
+                    .and_then(|column| record.get(column))
+                    .cloned()
+                    .unwrap_or(Value::Null),
+            )),
+            Expr::Aggregate { .. } => Err(query_error("aggregates are not allowed in a row expression")),
+            _ => Ok(None),
+        })
+    }
+
+    /// The first column named by the expression that is not in `columns`.
+    pub fn unknown_column(&self, columns: &[String]) -> Option<String> {
+        match self {
+            Expr::Column(name) if resolve_name(columns, name).is_none() => Some(name.clone()),
+            other => other.children().iter().find_map(|c| c.unknown_column(columns)),
+        }
+    }
+
+    fn check_columns(&self, dataset: &Dataset, aliases: &[&str]) -> Result<()> {
+        if let Expr::Column(name) = self {
+            if !aliases.contains(&name.as_str()) && resolve_column(dataset, name).is_none() {
+                return Err(EchomindError::Other(format!(
+                    "Unknown column '{}' (columns: {})",
+                    name,
+                    dataset.column_names.join(", ")
+                )));
+            }
+        }
+        self.children().iter().try_for_each(|c| c.check_columns(dataset, aliases))
+    }
+
+    /// A column used outside an aggregate that is not part of GROUP BY.
+    fn ungrouped_column(&self, group_by: &[Expr]) -> Option<String> {
+        if group_by.contains(self) {
+            return None;
+        }
+        match self {
+            Expr::Column(name) => Some(name.clone()),
+            Expr::Aggregate { .. } => None,
+            other => other.children().iter().find_map(|c| c.ungrouped_column(group_by)),
+        }
+    }
+}
+
+fn resolve_column<'a>(dataset: &'a Dataset, name: &str) -> Option<&'a str> {
+    resolve_name(&dataset.column_names, name)
+}
+
+pub(crate) fn resolve_name<'a>(columns: &'a [String], name: &str) -> Option<&'a str> {
+    columns
+        .iter()
+        .find(|c| *c == name)
+        .or_else(|| columns.iter().find(|c| c.eq_ignore_ascii_case(name)))
+        .map(String::as_str)
+}
+
+/// Evaluates an expression against one source row.
+fn eval_row(expr: &Expr, record: &Record, dataset: &Dataset) -> Result<Value> {
+    eval(expr, &mut |e| match e {
+        Expr::Column(name) => Ok(Some(
+            resolve_column(dataset, name)
+                .and_then(|column| record.get(column))
+                .cloned()
+                .unwrap_or(Value::Null),
+        )),
+        Expr::Aggregate { .. } => Err(query_error("aggregates need GROUP BY or an aggregate-only SELECT")),
+        _ => Ok(None),
+    })
+}
+
+/// Evaluates an expression for a group: aggregates over all its rows, plain
+/// columns from its first row (they are grouped, so all rows agree).
+fn eval_group(expr: &Expr, records: &[&Record], dataset: &Dataset) -> Result<Value> {
+    eval(expr, &mut |e| match e {
+        Expr::Column(name) => Ok(Some(
+            records
+                .first()
+                .and_then(|record| resolve_column(dataset, name).and_then(|column| record.get(column)))
+                .cloned()
+                .unwrap_or(Value::Null),
+        )),
+        Expr::Aggregate { func, arg, distinct } => {
+            aggregate(*func, arg.as_deref(), *distinct, records, dataset).map(Some)
+        }
+        _ => Ok(None),
+    })
+}
+
+/// Evaluates HAVING/ORDER BY expressions, where `@n` refers to output column n.
+fn eval_output(expr: &Expr, row: &[Value], records: &[&Record], dataset: &Dataset) -> Result<Value> {
+    eval(expr, &mut |e| match e {
+        Expr::Column(name) if name.starts_with('@') => {
+            let index: usize = name[1..].parse().unwrap_or(usize::MAX);
+            Ok(Some(row.get(index).cloned().unwrap_or(Value::Null)))
+        }
+        Expr::Column(_) | Expr::Aggregate { .. } => eval_group(e, records, dataset).map(Some),
+        _ => Ok(None),
+    })
+}
+
+/// Evaluates everything except columns and aggregates, which `leaf` handles
+/// (returning `None` for other expressions).
+fn eval(expr: &Expr, leaf: &mut dyn FnMut(&Expr) -> Result<Option<Value>>) -> Result<Value> {
+    if let Some(value) = leaf(expr)? {
+        return Ok(value);
+    }
+
+    Ok(match expr {
+        Expr::Column(_) | Expr::Aggregate { .. } => Value::Null,
+        Expr::Literal(value) => value.clone(),
+        Expr::Not(inner) => match eval(inner, leaf)? {
+            Value::Null => Value::Null,
+            value => Value::Bool(!truthy(&value)),
+        },
+        Expr::Negate(inner) => match as_number(&eval(inner, leaf)?) {
+            Some(n) => number_value(-n),
+            None => Value::Null,
+        },
+        Expr::Binary(BinaryOp::And, left, right) => {
+            let (l, r) = (eval(left, leaf)?, eval(right, leaf)?);
+            match (l.is_null(), r.is_null()) {
+                _ if (!l.is_null() && !truthy(&l)) || (!r.is_null() && !truthy(&r)) => Value::Bool(false),
+                (false, false) => Value::Bool(true),
+                _ => Value::Null,
+            }
+        }
+        Expr::Binary(BinaryOp::Or, left, right) => {
+            let (l, r) = (eval(left, leaf)?, eval(right, leaf)?);
+            if truthy(&l) || truthy(&r) {
+                Value::Bool(true)
+            } else if l.is_null() || r.is_null() {
+                Value::Null
+            } else {
+                Value::Bool(false)
+            }
+        }
+        Expr::Binary(op, left, right) => {
+            let (l, r) = (eval(left, leaf)?, eval(right, leaf)?);
+            if l.is_null() || r.is_null() {
+                return Ok(Value::Null);
+            }
+            match op {
+                BinaryOp::Eq => Value::Bool(values_equal(&l, &r)),
+                BinaryOp::NotEq => Value::Bool(!values_equal(&l, &r)),
+                BinaryOp::Lt => Value::Bool(compare_values(&l, &r) == Ordering::Less),
+                BinaryOp::LtEq => Value::Bool(compare_values(&l, &r) != Ordering::Greater),
+                BinaryOp::Gt => Value::Bool(compare_values(&l, &r) == Ordering::Greater),
+                BinaryOp::GtEq => Value::Bool(compare_values(&l, &r) != Ordering::Less),
+                _ => match (as_number(&l), as_number(&r)) {
+                    (Some(a), Some(b)) => match op {
+                        BinaryOp::Add => number_value(a + b),
+                        BinaryOp::Sub => number_value(a - b),
+                        BinaryOp::Mul => number_value(a * b),
+                        BinaryOp::Div if b == 0.0 => Value::Null,
+                        _ => number_value(a / b),
+                    },
+                    _ if *op == BinaryOp::Add => Value::String(format!("{}{}", display_value(&l), display_value(&r))),
+                    _ => Value::Null,
+                },
+            }
+        }
+        Expr::IsNull { expr, negated } => Value::Bool(eval(expr, leaf)?.is_null() != *negated),
+        Expr::InList { expr, list, negated } => {
+            let value = eval(expr, leaf)?;
+            if value.is_null() {
+                return Ok(Value::Null);
+            }
+            let mut found = false;
+            for item in list {
+                if values_equal(&value, &eval(item, leaf)?) {
+                    found = true;
+                    break;
+                }
+            }
+            Value::Bool(found != *negated)
+        }
+        Expr::Like { expr, pattern, negated } => {
+            let (value, pattern) = (eval(expr, leaf)?, eval(pattern, leaf)?);
+            if value.is_null() || pattern.is_null() {
+                return Ok(Value::Null);
+            }
+            let matched = like(&display_value(&value).to_lowercase(), &display_value(&pattern).to_lowercase());
+            Value::Bool(matched != *negated)
+        }
+        Expr::Function(name, args) => {
+            let values = args.iter().map(|arg| eval(arg, leaf)).collect::<Result<Vec<_>>>()?;
+            call_function(name, values)
+        }
+    })
+}
+
+fn call_function(name: &str, args: Vec<Value>) -> Value {
+    let first = args.first().cloned().unwrap_or(Value::Null);
+    if name == "coalesce" {
+        return args.into_iter().find(|v| !v.is_null()).unwrap_or(Value::Null);
+    }
+    if first.is_null() {
+        return Value::Null;
+    }
+
+    match name {
+        "lower" => Value::String(display_value(&first).to_lowercase()),
+        "upper" => Value::String(display_value(&first).to_uppercase()),
+        "trim" => Value::String(display_value(&first).trim().to_string()),
+        "length" => serde_json::json!(display_value(&first).chars().count()),
+        "abs" => as_number(&first).map(|n| number_value(n.abs())).unwrap_or(Value::Null),
+        "round" => {
+            let digits = args.get(1).and_then(as_number).unwrap_or(0.0) as i32;
+            let factor = 10f64.powi(digits);
+            as_number(&first)
+                .map(|n| number_value((n * factor).round() / factor))
+                .unwrap_or(Value::Null)
+        }
+        // Dates are matched by their leading YYYY-MM(-DD)
+        "year" | "month" => {
+            let text = display_value(&first);
+            let part = if name == "year" { text.get(0..4) } else { text.get(5..7) };
+            part.and_then(|p| p.parse::<i64>().ok())
+                .filter(|_| text.get(4..5) == Some("-") || name == "year" && text.len() == 4)
+                .map(|n| serde_json::json!(n))
+                .unwrap_or(Value::Null)
+        }
+        _ => Value::Null,
+    }
+}
+
+fn aggregate(
+    func: AggregateFunc,
+    arg: Option<&Expr>,
+    distinct: bool,
+    records: &[&Record],
+    dataset: &Dataset,
+) -> Result<Value> {
+    let Some(arg) = arg else {
+        return Ok(serde_json::json!(records.len()));
+    };
+
+    let mut values = Vec::new();
+    for record in records {
+        let value = eval_row(arg, record, dataset)?;
+        if !value.is_null() {
+            values.push(value);
+        }
+    }
+    if distinct {
+        let mut seen = HashSet::new();
+        values.retain(|value| seen.insert(display_value(value)));
+    }
+    let mut numbers: Vec<f64> = values.iter().filter_map(as_number).collect();
+
+    Ok(match func {
+        AggregateFunc::Count => serde_json::json!(values.len()),
+        // An empty sum is 0, so groups without values still add up
+        AggregateFunc::Sum => number_value(numbers.iter().sum()),
+        AggregateFunc::Avg if numbers.is_empty() => Value::Null,
+        AggregateFunc::Avg => number_value(numbers.iter().sum::<f64>() / numbers.len() as f64),
+        AggregateFunc::Min => values.into_iter().min_by(compare_values).unwrap_or(Value::Null),
+        AggregateFunc::Max => values.into_iter().max_by(compare_values).unwrap_or(Value::Null),
+        AggregateFunc::Median if numbers.is_empty() => Value::Null,
+        AggregateFunc::Median => {
+            numbers.sort_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal));
+            let len = numbers.len();
+            if len.is_multiple_of(2) {
+                number_value((numbers[len / 2 - 1] + numbers[len / 2]) / 2.0)
+            } else {
+                number_value(numbers[len / 2])
+            }
+        }
+    })
+}
+
+/// SQL LIKE with `%` (any run) and `_` (one character); `\` escapes either.
+fn like(text: &str, pattern: &str) -> bool {
+    fn matches(text: &[char], pattern: &[char]) -> bool {
+        match pattern.split_first() {
+            None => text.is_empty(),
+            Some(('%', rest)) => (0..=text.len()).any(|skip| matches(&text[skip..], rest)),
+            Some(('_', rest)) => !text.is_empty() && matches(&text[1..], rest),
+            Some(('\\', rest)) if !rest.is_empty() => {
+                text.first() == Some(&rest[0]) && matches(&text[1..], &rest[1..])
+            }
+            Some((c, rest)) => text.first() == Some(c) && matches(&text[1..], rest),
+        }
+    }
+    let text: Vec<char> = text.chars().collect();
+    let pattern: Vec<char> = pattern.chars().collect();
+    matches(&text, &pattern)
+}
+
+pub(crate) fn truthy(value: &Value) -> bool {
+    match value {
+        Value::Bool(b) => *b,
+        Value::Null => false,
+        Value::Number(n) => n.as_f64() != Some(0.0),
+        Value::String(s) => !s.is_empty() && !s.eq_ignore_ascii_case("false"),
+        _ => true,
+    }
+}
+
+pub(crate) fn as_number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => s.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+/// Whole numbers as integers, others rounded to six decimals.
+pub(crate) fn number_value(value: f64) -> Value {
+    if value.fract() == 0.0 && value.abs() < 1e15 {
+        serde_json::json!(value as i64)
+    } else {
+        serde_json::Number::from_f64((value * 1e6).round() / 1e6)
+            .map(Value::Number)
+            .unwrap_or(Value::Null)
+    }
+}
+
+pub(crate) fn display_value(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Null => "null".to_string(),
+        other => other.to_string(),
+    }
+}
+
+fn values_equal(a: &Value, b: &Value) -> bool {
+    match (as_number(a), as_number(b)) {
+        (Some(x), Some(y)) => x == y,
+        _ => display_value(a) == display_value(b),
+    }
+}
+
+/// Orders numbers numerically, then everything else by its text; nulls last.
+pub(crate) fn compare_values(a: &Value, b: &Value) -> Ordering {
+    match (a.is_null(), b.is_null()) {
+        (true, true) => return Ordering::Equal,
+        (true, false) => return Ordering::Greater,
+        (false, true) => return Ordering::Less,
+        _ => {}
+    }
+    match (as_number(a), as_number(b)) {
+        (Some(x), Some(y)) => x.partial_cmp(&y).unwrap_or(Ordering::Equal),
+        _ => display_value(a).cmp(&display_value(b)),
+    }
+}
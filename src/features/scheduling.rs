@@ -0,0 +1,571 @@
+use crate::api::{ApiClient, ChatRequest, Message, Provider};
+use crate::config::Config;
+use crate::error::{EchomindError, Result};
+use crate::features::history::{HistoryEntry, HistoryManager};
+use crate::features::usage::UsageMeter;
+use crate::features::workflow::WorkflowManager;
+use chrono::{DateTime, Local, Utc};
+use cron::Schedule;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+/// Occurrences older than this when the daemon notices them count as missed.
+const MISSED_GRACE_SECS: i64 = 60;
+
+/// Looks up a job by id or unique id prefix.
+fn find_job<'a>(jobs: &'a [ScheduledJob], job_id: &str) -> Result<&'a ScheduledJob> {
+    let matches: Vec<&ScheduledJob> = jobs.iter().filter(|j| j.id.starts_with(job_id)).collect();
+    match matches.len() {
+        0 => Err(EchomindError::Other(format!("Scheduled job {} not found", job_id))),
+        1 => Ok(matches[0]),
+        n => Err(EchomindError::Other(format!("Job id prefix {} is ambiguous ({} jobs)", job_id, n))),
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ScheduledJob {
+    pub id: String,
+    pub name: String,
+    /// Cron expression; five-field expressions get a leading seconds field
+    pub schedule: String,
+    pub task: JobTask,
+    #[serde(default)]
+    pub output: JobOutput,
+    #[serde(default)]
+    pub provider: Option<String>,
+    #[serde(default)]
+    pub model: Option<String>,
+    /// Run once on daemon startup when occurrences were missed while it was down
+    #[serde(default = "default_catch_up")]
+    pub catch_up: bool,
+    pub created_at: DateTime<Utc>,
+    /// The last occurrence that was run (or deliberately skipped)
+    #[serde(default)]
+    pub last_run: Option<DateTime<Utc>>,
+}
+
+fn default_catch_up() -> bool {
+    true
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum JobTask {
+    Prompt {
+        prompt: String,
+        #[serde(default)]
+        system: Option<String>,
+    },
+    Preset {
+        preset: String,
+        prompt: String,
+    },
+    Workflow {
+        workflow: String,
+        #[serde(default)]
+        variables: HashMap<String, serde_json::Value>,
+    },
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum JobOutput {
+    #[default]
+    Stdout,
+    File {
+        path: String,
+        #[serde(default)]
+        append: bool,
+    },
+    History {
+        path: String,
+    },
+    Webhook {
+        url: String,
+    },
+}
+
+/// The recorded result of one job execution.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct JobRun {
+    pub job_id: String,
+    pub scheduled_for: DateTime<Utc>,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: DateTime<Utc>,
+    pub success: bool,
+    #[serde(default)]
+    pub output: Option<String>,
+    #[serde(default)]
+    pub error: Option<String>,
+    /// True when the occurrence was missed and run late (or skipped)
+    #[serde(default)]
+    pub missed: bool,
+    /// True when a missed occurrence was skipped because catch-up is disabled
+    #[serde(default)]
+    pub skipped: bool,
+}
+
+/// An occurrence that is due now.
+#[derive(Debug, Clone)]
+pub struct DueJob {
+    pub job_id: String,
+    pub scheduled_for: DateTime<Utc>,
+    pub missed: bool,
+}
+
+pub struct SchedulingManager {
+    jobs: Vec<ScheduledJob>,
+    store_dir: Option<PathBuf>,
+}
+
+impl Default for SchedulingManager {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SchedulingManager {
+    pub fn new() -> Self {
+        Self {
+            jobs: Vec::new(),
+            store_dir: None,
+        }
+    }
+
+    /// Opens the job store in `dir`, loading any persisted jobs.
+    pub fn open(dir: &Path) -> Result<Self> {
+        let mut manager = Self::new();
+        manager.store_dir = Some(dir.to_path_buf());
+        manager.reload()?;
+        Ok(manager)
+    }
+
+    /// Re-reads the job store so changes made by other processes are picked up.
+    pub fn reload(&mut self) -> Result<()> {
+        let Some(path) = self.jobs_path() else {
+            return Ok(());
+        };
+        if !path.exists() {
+            self.jobs.clear();
+            return Ok(());
+        }
+
+        let contents = fs::read_to_string(&path)
+            .map_err(|e| EchomindError::FileError(format!("Failed to read scheduled jobs: {}", e)))?;
+        self.jobs = serde_json::from_str(&contents)
+            .map_err(|e| EchomindError::ParseError(format!("Failed to parse scheduled jobs: {}", e)))?;
+        Ok(())
+    }
+
+    fn save(&self) -> Result<()> {
+        let (Some(dir), Some(path)) = (&self.store_dir, self.jobs_path()) else {
+            return Ok(());
+        };
+        fs::create_dir_all(dir)
+            .map_err(|e| EchomindError::FileError(format!("Failed to create schedule directory: {}", e)))?;
+
+        let json = serde_json::to_string_pretty(&self.jobs)
+            .map_err(|e| EchomindError::ParseError(format!("Failed to serialize scheduled jobs: {}", e)))?;
+        let tmp = path.with_extension("json.tmp");
+        fs::write(&tmp, json)
+            .and_then(|_| fs::rename(&tmp, &path))
+            .map_err(|e| EchomindError::FileError(format!("Failed to write scheduled jobs: {}", e)))
+    }
+
+    /// Applies `change` to the jobs as they are on disk and saves them. The
+    /// store is locked and re-read first, so a `schedule add` or `cancel`
+    /// made while the daemon was running a job isn't overwritten by the
+    /// daemon's older copy. Without a store the change is made in memory.
+    fn update<T>(&mut self, change: impl FnOnce(&mut Vec<ScheduledJob>) -> Result<T>) -> Result<T> {
+        let lock = self.lock()?;
+        if lock.is_some() {
+            self.reload()?;
+        }
+        let value = change(&mut self.jobs)?;
+        self.save()?;
+        Ok(value)
+    }
+
+    /// Holds an exclusive lock on the store until the file is dropped.
+    fn lock(&self) -> Result<Option<fs::File>> {
+        let Some(dir) = &self.store_dir else {
+            return Ok(None);
+        };
+        fs::create_dir_all(dir)
+            .map_err(|e| EchomindError::FileError(format!("Failed to create schedule directory: {}", e)))?;
+        let file = fs::OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(dir.join("jobs.lock"))
+            .and_then(|file| file.lock().map(|_| file))
+            .map_err(|e| EchomindError::FileError(format!("Failed to lock scheduled jobs: {}", e)))?;
+        Ok(Some(file))
+    }
+
+    fn jobs_path(&self) -> Option<PathBuf> {
+        self.store_dir.as_ref().map(|dir| dir.join("jobs.json"))
+    }
+
+    fn runs_path(&self) -> Option<PathBuf> {
+        self.store_dir.as_ref().map(|dir| dir.join("runs.jsonl"))
+    }
+
+    /// Parses a cron expression. Standard five-field expressions
+    /// (`min hour day month weekday`) are accepted alongside the cron crate's
+    /// six/seven-field form with seconds.
+    pub fn parse_schedule(expression: &str) -> Result<Schedule> {
+        let expression = expression.trim();
+        let normalized = if !expression.starts_with('@') && expression.split_whitespace().count() == 5 {
+            format!("0 {}", expression)
+        } else {
+            expression.to_string()
+        };
+
+        Schedule::from_str(&normalized)
+            .map_err(|e| EchomindError::ConfigError(format!("Invalid cron expression '{}': {}", expression, e)))
+    }
+
+    /// Adds a job and returns its id.
+    pub fn schedule_task(&mut self, name: &str, task: JobTask, schedule: &str, output: JobOutput) -> Result<String> {
+        Self::parse_schedule(schedule)?;
+
+        let job = ScheduledJob {
+            id: uuid::Uuid::new_v4().simple().to_string()[..8].to_string(),
+            name: name.to_string(),
+            schedule: schedule.trim().to_string(),
+            task,
+            output,
+            provider: None,
+            model: None,
+            catch_up: true,
+            created_at: Utc::now(),
+            last_run: None,
+        };
+        let id = job.id.clone();
+        self.add_job(job)?;
+        Ok(id)
+    }
+
+    /// Adds a fully specified job (validating its schedule).
+    pub fn add_job(&mut self, job: ScheduledJob) -> Result<()> {
+        Self::parse_schedule(&job.schedule)?;
+        self.update(|jobs| {
+            jobs.push(job);
+            Ok(())
+        })
+    }
+
+    pub fn list_scheduled_tasks(&self) -> &[ScheduledJob] {
+        &self.jobs
+    }
+
+    /// Looks up a job by id or unique id prefix.
+    pub fn get_job(&self, job_id: &str) -> Result<&ScheduledJob> {
+        find_job(&self.jobs, job_id)
+    }
+
+    pub fn cancel_task(&mut self, job_id: &str) -> Result<ScheduledJob> {
+        self.update(|jobs| {
+            let id = find_job(jobs, job_id)?.id.clone();
+            let index = jobs.iter().position(|j| j.id == id).unwrap();
+            Ok(jobs.remove(index))
+        })
+    }
+
+    /// Next occurrence of a job strictly after `after`, evaluated in local time.
+    pub fn next_run(job: &ScheduledJob, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let schedule = Self::parse_schedule(&job.schedule).ok()?;
+        schedule
+            .after(&after.with_timezone(&Local))
+            .next()
+            .map(|next| next.with_timezone(&Utc))
+    }
+
+    /// Jobs with an occurrence at or before `now` that has not run yet. Each
+    /// job appears at most once, for its latest elapsed occurrence, so a
+    /// daemon that was down for a day runs a missed hourly job once rather
+    /// than 24 times.
+    pub fn due_jobs(&self, now: DateTime<Utc>) -> Vec<DueJob> {
+        let mut due = Vec::new();
+
+        for job in &self.jobs {
+            let Ok(schedule) = Self::parse_schedule(&job.schedule) else {
+                continue;
+            };
+            let reference = job.last_run.unwrap_or(job.created_at).with_timezone(&Local);
+            let latest = schedule
+                .after(&reference)
+                .take_while(|occurrence| occurrence.with_timezone(&Utc) <= now)
+                .last();
+
+            if let Some(occurrence) = latest {
+                let scheduled_for = occurrence.with_timezone(&Utc);
+                due.push(DueJob {
+                    job_id: job.id.clone(),
+                    scheduled_for,
+                    missed: (now - scheduled_for).num_seconds() > MISSED_GRACE_SECS,
+                });
+            }
+        }
+
+        due
+    }
+
+    /// Appends a run to the run log and advances the job past its occurrence.
+    /// A job cancelled while it ran stays cancelled.
+    pub fn record_run(&mut self, run: &JobRun) -> Result<()> {
+        self.update(|jobs| {
+            if let Some(job) = jobs.iter_mut().find(|j| j.id == run.job_id) {
+                if job.last_run.is_none_or(|last| last < run.scheduled_for) {
+                    job.last_run = Some(run.scheduled_for);
+                }
+            }
+            Ok(())
+        })?;
+
+        let Some(path) = self.runs_path() else {
+            return Ok(());
+        };
+        let line = serde_json::to_string(run)
+            .map_err(|e| EchomindError::ParseError(format!("Failed to serialize job run: {}", e)))?;
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&path)
+            .map_err(|e| EchomindError::FileError(format!("Failed to open run log: {}", e)))?;
+        writeln!(file, "{}", line)
+            .map_err(|e| EchomindError::FileError(format!("Failed to write run log: {}", e)))
+    }
+
+    /// Recorded runs, oldest first, optionally for a single job.
+    pub fn list_runs(&self, job_id: Option<&str>) -> Result<Vec<JobRun>> {
+        let Some(path) = self.runs_path() else {
+            return Ok(Vec::new());
+        };
+        if !path.exists() {
+            return Ok(Vec::new());
+        }
+
+        let contents = fs::read_to_string(&path)
+            .map_err(|e| EchomindError::FileError(format!("Failed to read run log: {}", e)))?;
+        Ok(contents
+            .lines()
+            .filter_map(|line| serde_json::from_str::<JobRun>(line).ok())
+            .filter(|run| job_id.is_none_or(|id| run.job_id.starts_with(id)))
+            .collect())
+    }
+
+    /// Runs a job, delivers its output and returns the run record. Failures
+    /// are captured in the record rather than returned.
+    pub async fn run_job(job: &ScheduledJob, config: &Config, scheduled_for: DateTime<Utc>, missed: bool) -> JobRun {
+        let started_at = Utc::now();
+        let result = match Self::execute_task(job, config).await {
+            Ok(output) => Self::deliver(job, &output, config).await.map(|_| output),
+            Err(e) => {
+                // Webhooks hear about failed tasks too so they can alert on
+                // them. A failed delivery to the webhook isn't posted again.
+                if let JobOutput::Webhook { url } = &job.output {
+                    let _ = Self::post_webhook(url, job, None, Some(&e.to_string())).await;
+                }
+                Err(e)
+            }
+        };
+
+        let run = JobRun {
+            job_id: job.id.clone(),
+            scheduled_for,
+            started_at,
+            finished_at: Utc::now(),
+            success: result.is_ok(),
+            output: result.as_ref().ok().cloned(),
+            error: result.as_ref().err().map(|e| e.to_string()),
+            missed,
+            skipped: false,
+        };
+
+        run
+    }
+
+    /// Record for a missed occurrence that was skipped (catch-up disabled).
+    pub fn skipped_run(job: &ScheduledJob, scheduled_for: DateTime<Utc>) -> JobRun {
+        let now = Utc::now();
+        JobRun {
+            job_id: job.id.clone(),
+            scheduled_for,
+            started_at: now,
+            finished_at: now,
+            success: true,
+            output: None,
+            error: None,
+            missed: true,
+            skipped: true,
+        }
+    }
+
+    async fn execute_task(job: &ScheduledJob, config: &Config) -> Result<String> {
+        let provider_str = job.provider.as_ref().unwrap_or(&config.api.provider);
+        let client = ApiClient::new(Provider::from_string(provider_str)?, config.api.api_key.clone(), config.api.timeout)?;
+        let model = job.model.clone().unwrap_or(config.api.model.clone());
+
+        let (mut messages, prompt) = match &job.task {
+            JobTask::Prompt { prompt, system } => {
+                let messages = system
+                    .iter()
+                    .map(|s| Message::text("system".to_string(), s.clone()))
+                    .collect();
+                (messages, prompt.clone())
+            }
+            JobTask::Preset { preset, prompt } => {
+                let preset = config
+                    .presets
+                    .get(preset)
+                    .ok_or_else(|| EchomindError::ConfigError(format!("Preset '{}' not found in config.", preset)))?;
+                let mut messages = Vec::new();
+                if let Some(system) = &preset.system_prompt {
+                    messages.push(Message::text("system".to_string(), system.clone()));
+                }
+                messages.extend(preset.messages.clone().unwrap_or_default());
+                (messages, prompt.clone())
+            }
+            JobTask::Workflow { workflow, variables } => {
+                return Self::execute_workflow(workflow, variables, &client, &model, config).await;
+            }
+        };
+
+        messages.push(Message::text("user".to_string(), prompt));
+        let request = ChatRequest {
+            messages,
+            model: Some(model),
+            temperature: Some(config.defaults.temperature),
+            max_tokens: config.defaults.max_tokens,
+            top_p: config.defaults.top_p,
+            top_k: config.defaults.top_k,
+            stream: None,
+        };
+        let usage = UsageMeter::for_config(config, "schedule");
+        UsageMeter::send(Some(&usage), client.provider().name(), &request, |warning| {
+            if let Some(warning) = warning {
+                eprintln!("[{}] Budget: {}", job.name, warning);
+            }
+            client.send_message(request.clone())
+        })
+        .await
+    }
+
+    async fn execute_workflow(
+        workflow_ref: &str,
+        variables: &HashMap<String, serde_json::Value>,
+        client: &ApiClient,
+        model: &str,
+        config: &Config,
+    ) -> Result<String> {
+        let mut manager = WorkflowManager::new();
+        let workflow_id = if Path::new(workflow_ref).exists() {
+            manager.load_workflow_from_file(workflow_ref)?
+        } else {
+            manager.load_workflows_from_dir(&Config::workflows_dir()?)?;
+            workflow_ref.to_string()
+        };
+        manager.set_api_key(config.api.api_key.clone());
+        manager.set_default_model(Some(model.to_string()));
+        manager.set_default_timeout(config.api.timeout);
+        manager.set_shell_allowlist(config.workflow.shell_allowlist.clone());
+        manager.set_runs_dir(Config::workflow_runs_dir()?);
+        manager.set_usage_meter(UsageMeter::for_config(config, "schedule"));
+
+        let context = manager.execute_workflow(&workflow_id, variables.clone(), client).await?;
+        if !context.errors.is_empty() {
+            return Err(EchomindError::Other(format!(
+                "Workflow run {} failed: {}",
+                context.run_id,
+                context.errors.join("; ")
+            )));
+        }
+
+        Ok(context
+            .history
+            .iter()
+            .rev()
+            .find_map(|result| result.output.clone())
+            .unwrap_or_default())
+    }
+
+    async fn deliver(job: &ScheduledJob, output: &str, config: &Config) -> Result<()> {
+        match &job.output {
+            JobOutput::Stdout => {
+                println!("{}", output);
+                Ok(())
+            }
+            JobOutput::File { path, append } => {
+                if let Some(parent) = Path::new(path).parent().filter(|p| !p.as_os_str().is_empty()) {
+                    fs::create_dir_all(parent)
+                        .map_err(|e| EchomindError::FileError(format!("Failed to create {}: {}", parent.display(), e)))?;
+                }
+                let mut file = fs::OpenOptions::new()
+                    .create(true)
+                    .write(true)
+                    .append(*append)
+                    .truncate(!*append)
+                    .open(path)
+                    .map_err(|e| EchomindError::FileError(format!("Failed to open {}: {}", path, e)))?;
+                writeln!(file, "{}", output)
+                    .map_err(|e| EchomindError::FileError(format!("Failed to write {}: {}", path, e)))
+            }
+            JobOutput::History { path } => {
+                let prompt = match &job.task {
+                    JobTask::Prompt { prompt, .. } | JobTask::Preset { prompt, .. } => prompt.clone(),
+                    JobTask::Workflow { workflow, .. } => format!("workflow {}", workflow),
+                };
+                let provider = job.provider.clone().unwrap_or(config.api.provider.clone());
+                let model = job.model.clone().unwrap_or(config.api.model.clone());
+
+                let mut history = HistoryManager::new(path);
+                for (role, content) in [("user", prompt), ("assistant", output.to_string())] {
+                    let mut metadata = HashMap::new();
+                    metadata.insert("scheduled_job".to_string(), serde_json::json!(job.id));
+                    history.add_entry(HistoryEntry {
+                        id: uuid::Uuid::new_v4().to_string(),
+                        timestamp: Utc::now(),
+                        role: role.to_string(),
+                        content,
+                        provider: Some(provider.clone()),
+                        model: Some(model.clone()),
+                        has_image: false,
+                        token_count: None,
+                        cost_estimate: None,
+                        tags: vec!["scheduled".to_string()],
+                        metadata,
+                    })?;
+                }
+                Ok(())
+            }
+            JobOutput::Webhook { url } => Self::post_webhook(url, job, Some(output), None).await,
+        }
+    }
+
+    async fn post_webhook(url: &str, job: &ScheduledJob, output: Option<&str>, error: Option<&str>) -> Result<()> {
+        let payload = serde_json::json!({
+            "job_id": job.id,
+            "name": job.name,
+            "success": error.is_none(),
+            "output": output,
+            "error": error,
+            "finished_at": Utc::now(),
+        });
+
+        let response = reqwest::Client::new().post(url).json(&payload).send().await?;
+        if !response.status().is_success() {
+            return Err(EchomindError::NetworkError(format!(
+                "Webhook {} responded with {}",
+                url,
+                response.status()
+            )));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,530 @@
+//! Single-pass statistics for data files of any size.
+//!
+//! `StreamingAnalyzer` sees each record once and keeps a bounded amount of
+//! state per column: a running mean and variance (Welford), min/max, a
+//! HyperLogLog distinct-count sketch, P² quantile estimators and Space-Saving
+//! top-k counters, plus a reservoir sample of rows. Small files get exact
+//! figures: values are counted exactly until a column passes
+//! `EXACT_LIMIT` distinct or numeric values, and only then do the sketches'
+//! estimates take over (the column's stats are marked `approximate`).
+
+use crate::features::data_processing::{ColumnStats, DataAnalysis, Record};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde_json::Value;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
+
+/// Distinct values (and numeric values) tracked exactly per column.
+pub const EXACT_LIMIT: usize = 10_000;
+
+/// Counters kept per column once top-k counting turns approximate.
+const TOP_K_CAPACITY: usize = 64;
+
+/// Most common values reported per column.
+const TOP_VALUES: usize = 5;
+
+/// Rows kept in `DataAnalysis::sample_data`.
+pub const SAMPLE_SIZE: usize = 10;
+
+/// Seed for the row sample, so the same file always yields the same sample.
+const SAMPLE_SEED: u64 = 0x5eed;
+
+/// Computes a `DataAnalysis` from records fed one at a time.
+pub struct StreamingAnalyzer {
+    columns: Vec<String>,
+    accumulators: Vec<ColumnAccumulator>,
+    rows: usize,
+    sample: Reservoir<Record>,
+}
+
+impl StreamingAnalyzer {
+    pub fn new() -> Self {
+        Self {
+            columns: Vec::new(),
+            accumulators: Vec::new(),
+            rows: 0,
+            sample: Reservoir::new(SAMPLE_SIZE, SAMPLE_SEED),
+        }
+    }
+
+    /// Adds a record. `columns` lists the columns known so far, in order; a
+    /// column first seen after some rows counts as empty in those rows.
+    pub fn observe(&mut self, columns: &[String], record: Record) {
+        for column in columns.iter().skip(self.columns.len()) {
+            self.columns.push(column.clone());
+            let mut accumulator = ColumnAccumulator::new();
+            accumulator.null_count = self.rows;
+            self.accumulators.push(accumulator);
+        }
+        for (column, accumulator) in self.columns.iter().zip(&mut self.accumulators) {
+            accumulator.observe(record.get(column).unwrap_or(&Value::Null));
+        }
+        self.rows += 1;
+        self.sample.observe(record);
+    }
+
+    pub fn finish(self, file_type: &str) -> DataAnalysis {
+        let mut column_types = HashMap::new();
+        let mut summary_stats = HashMap::new();
+        for (column, accumulator) in self.columns.iter().zip(self.accumulators) {
+            column_types.insert(column.clone(), accumulator.column_type().to_string());
+            summary_stats.insert(column.clone(), accumulator.finish());
+        }
+
+        DataAnalysis {
+            file_type: file_type.to_string(),
+            total_rows: self.rows,
+            total_columns: self.columns.len(),
+            column_names: self.columns,
+            column_types,
+            summary_stats,
+            sample_data: self.sample.into_items(),
+        }
+    }
+}
+
+impl Default for StreamingAnalyzer {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Running statistics for one column.
+pub struct ColumnAccumulator {
+    null_count: usize,
+    type_counts: [usize; 5],
+    count: usize,
+    mean: f64,
+    m2: f64,
+    number_min: f64,
+    number_max: f64,
+    text_min: Option<String>,
+    text_max: Option<String>,
+    /// Every numeric value, until there are more than `EXACT_LIMIT`
+    numbers: Option<Vec<f64>>,
+    quartiles: [P2Quantile; 3],
+    distinct: HyperLogLog,
+    top: SpaceSaving,
+}
+
+impl ColumnAccumulator {
+    pub fn new() -> Self {
+        Self {
+            null_count: 0,
+            type_counts: [0; 5],
+            count: 0,
+            mean: 0.0,
+            m2: 0.0,
+            number_min: f64::INFINITY,
+            number_max: f64::NEG_INFINITY,
+            text_min: None,
+            text_max: None,
+            numbers: Some(Vec::new()),
+            quartiles: [P2Quantile::new(0.25), P2Quantile::new(0.5), P2Quantile::new(0.75)],
+            distinct: HyperLogLog::new(),
+            top: SpaceSaving::new(EXACT_LIMIT, TOP_K_CAPACITY),
+        }
+    }
+
+    pub fn observe(&mut self, value: &Value) {
+        let kind = match value {
+            Value::Null => {
+                self.null_count += 1;
+                return;
+            }
+            Value::Number(_) => 0,
+            Value::String(_) => 1,
+            Value::Bool(_) => 2,
+            Value::Array(_) => 3,
+            Value::Object(_) => 4,
+        };
+        self.type_counts[kind] += 1;
+        self.distinct.insert(value);
+        self.top.observe(value);
+
+        match value {
+            Value::Number(n) => {
+                let Some(x) = n.as_f64() else { return };
+                self.count += 1;
+                let delta = x - self.mean;
+                self.mean += delta / self.count as f64;
+                self.m2 += delta * (x - self.mean);
+                self.number_min = self.number_min.min(x);
+                self.number_max = self.number_max.max(x);
+                for quartile in &mut self.quartiles {
+                    quartile.observe(x);
+                }
+                if let Some(numbers) = &mut self.numbers {
+                    if numbers.len() < EXACT_LIMIT {
+                        numbers.push(x);
+                    } else {
+                        self.numbers = None;
+                    }
+                }
+            }
+            Value::String(s) => {
+                if self.text_min.as_ref().is_none_or(|min| s < min) {
+                    self.text_min = Some(s.clone());
+                }
+                if self.text_max.as_ref().is_none_or(|max| s > max) {
+                    self.text_max = Some(s.clone());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// The most frequent kind of non-empty value.
+    pub fn column_type(&self) -> &'static str {
+        const NAMES: [&str; 5] = ["number", "string", "boolean", "array", "object"];
+        let (index, count) = self
+            .type_counts
+            .iter()
+            .enumerate()
+            .max_by(|a, b| a.1.cmp(b.1).then(b.0.cmp(&a.0)))
+            .unwrap_or((0, &0));
+        if *count == 0 {
+            "unknown"
+        } else {
+            NAMES[index]
+        }
+    }
+
+    pub fn finish(self) -> ColumnStats {
+        let approximate = !self.top.is_exact() || (self.count > 0 && self.numbers.is_none());
+        let unique_count = if self.top.is_exact() {
+            self.top.len()
+        } else {
+            self.distinct.estimate()
+        };
+
+        let (min_value, max_value) = if self.count > 0 {
+            (number(self.number_min), number(self.number_max))
+        } else {
+            (self.text_min.map(Value::String), self.text_max.map(Value::String))
+        };
+
+        let (mean_value, std_dev) = if self.count > 0 {
+            let variance = if self.count > 1 { self.m2 / (self.count - 1) as f64 } else { 0.0 };
+            (Some(self.mean), Some(variance.sqrt()))
+        } else {
+            (None, None)
+        };
+
+        let [p25, p50, p75] = match self.numbers {
+            Some(mut numbers) if !numbers.is_empty() => {
+                numbers.sort_by(|a, b| a.total_cmp(b));
+                [0.25, 0.5, 0.75].map(|p| Some(exact_quantile(&numbers, p)))
+            }
+            _ => self.quartiles.map(|q| q.estimate()),
+        };
+
+        let top_values = self.top.top(TOP_VALUES);
+        ColumnStats {
+            null_count: self.null_count,
+            unique_count,
+            min_value,
+            max_value,
+            mean_value,
+            median_value: p50,
+            most_common: top_values.first().cloned(),
+            std_dev,
+            p25_value: p25,
+            p75_value: p75,
+            top_values,
+            approximate,
+        }
+    }
+}
+
+impl Default for ColumnAccumulator {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+fn number(value: f64) -> Option<Value> {
+    serde_json::Number::from_f64(value).map(Value::Number)
+}
+
+/// Linear interpolation between the closest ranks of sorted `values`; the
+/// median of an even count is the mean of the middle two.
+fn exact_quantile(values: &[f64], p: f64) -> f64 {
+    let rank = p * (values.len() - 1) as f64;
+    let (low, high) = (rank.floor() as usize, rank.ceil() as usize);
+    values[low] + (values[high] - values[low]) * (rank - low as f64)
+}
+
+fn hash_value(value: &Value) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    value.hash(&mut hasher);
+    hasher.finish()
+}
+
+/// HyperLogLog distinct counter with 2^12 registers (about 1.6% error).
+pub struct HyperLogLog {
+    registers: Vec<u8>,
+}
+
+impl HyperLogLog {
+    const PRECISION: u32 = 12;
+
+    pub fn new() -> Self {
+        Self {
+            registers: vec![0; 1 << Self::PRECISION],
+        }
+    }
+
+    pub fn insert(&mut self, value: &Value) {
+        let hash = hash_value(value);
+        let index = (hash >> (64 - Self::PRECISION)) as usize;
+        let rank = ((hash << Self::PRECISION) | (1 << (Self::PRECISION - 1))).leading_zeros() as u8 + 1;
+        self.registers[index] = self.registers[index].max(rank);
+    }
+
+    pub fn estimate(&self) -> usize {
+        let m = self.registers.len() as f64;
+        let sum: f64 = self.registers.iter().map(|r| 2f64.powi(-(*r as i32))).sum();
+        let raw = 0.7213 / (1.0 + 1.079 / m) * m * m / sum;
+        let zeros = self.registers.iter().filter(|r| **r == 0).count();
+        let estimate = if raw <= 2.5 * m && zeros > 0 {
+            // Linear counting is more accurate for small cardinalities
+            m * (m / zeros as f64).ln()
+        } else {
+            raw
+        };
+        estimate.round() as usize
+    }
+}
+
+impl Default for HyperLogLog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The P² algorithm (Jain & Chlamtac): estimates one quantile from five
+/// markers without storing the values.
+#[derive(Debug, Clone)]
+pub struct P2Quantile {
+    p: f64,
+    count: usize,
+    heights: [f64; 5],
+    positions: [f64; 5],
+    desired: [f64; 5],
+    increments: [f64; 5],
+}
+
+impl P2Quantile {
+    pub fn new(p: f64) -> Self {
+        Self {
+            p,
+            count: 0,
+            heights: [0.0; 5],
+            positions: [1.0, 2.0, 3.0, 4.0, 5.0],
+            desired: [1.0, 1.0 + 2.0 * p, 1.0 + 4.0 * p, 3.0 + 2.0 * p, 5.0],
+            increments: [0.0, p / 2.0, p, (1.0 + p) / 2.0, 1.0],
+        }
+    }
+
+    pub fn observe(&mut self, x: f64) {
+        if self.count < 5 {
+            self.heights[self.count] = x;
+            self.count += 1;
+            if self.count == 5 {
+                self.heights.sort_by(|a, b| a.total_cmp(b));
+            }
+            return;
+        }
+        self.count += 1;
+
+        let h = &mut self.heights;
+        let cell = if x < h[0] {
+            h[0] = x;
+            0
+        } else if x >= h[4] {
+            h[4] = x;
+            3
+        } else {
+            (0..4).find(|&i| x < h[i + 1]).unwrap_or(3)
+        };
+        for position in &mut self.positions[cell + 1..] {
+            *position += 1.0;
+        }
+        for (desired, increment) in self.desired.iter_mut().zip(self.increments) {
+            *desired += increment;
+        }
+
+        for i in 1..4 {
+            let offset = self.desired[i] - self.positions[i];
+            let n = self.positions;
+            if (offset >= 1.0 && n[i + 1] - n[i] > 1.0) || (offset <= -1.0 && n[i - 1] - n[i] < -1.0) {
+                let d = offset.signum();
+                let h = self.heights;
+                let parabolic = h[i]
+                    + d / (n[i + 1] - n[i - 1])
+                        * ((n[i] - n[i - 1] + d) * (h[i + 1] - h[i]) / (n[i + 1] - n[i])
+                            + (n[i + 1] - n[i] - d) * (h[i] - h[i - 1]) / (n[i] - n[i - 1]));
+                self.heights[i] = if h[i - 1] < parabolic && parabolic < h[i + 1] {
+                    parabolic
+                } else {
+                    let j = if d > 0.0 { i + 1 } else { i - 1 };
+                    h[i] + d * (h[j] - h[i]) / (n[j] - n[i])
+                };
+                self.positions[i] += d;
+            }
+        }
+    }
+
+    pub fn estimate(&self) -> Option<f64> {
+        match self.count {
+            0 => None,
+            n if n < 5 => {
+                let mut seen = self.heights[..n].to_vec();
+                seen.sort_by(|a, b| a.total_cmp(b));
+                Some(exact_quantile(&seen, self.p))
+            }
+            _ => Some(self.heights[2]),
+        }
+    }
+}
+
+/// Frequent values: exact counts up to `exact_limit` distinct values, then
+/// the Space-Saving algorithm with `capacity` counters. Approximate counts
+/// are reported as their guaranteed lower bound, so values that only held a
+/// counter briefly drop out.
+pub struct SpaceSaving {
+    counters: HashMap<Value, Counter>,
+    exact_limit: usize,
+    capacity: usize,
+    exact: bool,
+    next_seen: u64,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Counter {
+    count: usize,
+    /// Count inherited from the evicted value, which this value may not have
+    error: usize,
+    first_seen: u64,
+}
+
+impl SpaceSaving {
+    pub fn new(exact_limit: usize, capacity: usize) -> Self {
+        Self {
+            counters: HashMap::new(),
+            exact_limit,
+            capacity,
+            exact: true,
+            next_seen: 0,
+        }
+    }
+
+    pub fn observe(&mut self, value: &Value) {
+        if let Some(counter) = self.counters.get_mut(value) {
+            counter.count += 1;
+            return;
+        }
+
+        self.next_seen += 1;
+        let mut counter = Counter {
+            count: 1,
+            error: 0,
+            first_seen: self.next_seen,
+        };
+        if self.exact && self.counters.len() >= self.exact_limit {
+            // Too many distinct values: keep the most frequent so far
+            self.counters = self.sorted().into_iter().take(self.capacity).collect();
+            self.exact = false;
+        }
+        if !self.exact && self.counters.len() >= self.capacity {
+            let evicted = self
+                .counters
+                .iter()
+                .min_by_key(|(_, counter)| counter.count)
+                .map(|(value, counter)| (value.clone(), counter.count));
+            if let Some((evicted, count)) = evicted {
+                self.counters.remove(&evicted);
+                counter = Counter {
+                    count: count + 1,
+                    error: count,
+                    ..counter
+                };
+            }
+        }
+        self.counters.insert(value.clone(), counter);
+    }
+
+    pub fn is_exact(&self) -> bool {
+        self.exact
+    }
+
+    pub fn len(&self) -> usize {
+        self.counters.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.counters.is_empty()
+    }
+
+    /// The `n` most frequent values with their (guaranteed) counts, ties
+    /// broken by first appearance.
+    pub fn top(&self, n: usize) -> Vec<(Value, usize)> {
+        self.sorted()
+            .into_iter()
+            .map(|(value, counter)| (value, counter.count - counter.error))
+            .filter(|(_, count)| *count > 0)
+            .take(n)
+            .collect()
+    }
+
+    fn sorted(&self) -> Vec<(Value, Counter)> {
+        let mut entries: Vec<(Value, Counter)> =
+            self.counters.iter().map(|(value, counter)| (value.clone(), *counter)).collect();
+        entries.sort_by(|a, b| {
+            (b.1.count - b.1.error)
+                .cmp(&(a.1.count - a.1.error))
+                .then(a.1.first_seen.cmp(&b.1.first_seen))
+        });
+        entries
+    }
+}
+
+/// A uniform random sample of a stream (Algorithm R), returned in stream
+/// order.
+pub struct Reservoir<T> {
+    capacity: usize,
+    seen: usize,
+    items: Vec<(usize, T)>,
+    rng: StdRng,
+}
+
+impl<T> Reservoir<T> {
+    pub fn new(capacity: usize, seed: u64) -> Self {
+        Self {
+            capacity,
+            seen: 0,
+            items: Vec::with_capacity(capacity),
+            rng: StdRng::seed_from_u64(seed),
+        }
+    }
+
+    pub fn observe(&mut self, item: T) {
+        if self.items.len() < self.capacity {
+            self.items.push((self.seen, item));
+        } else {
+            let slot = self.rng.gen_range(0..=self.seen);
+            if slot < self.capacity {
+                self.items[slot] = (self.seen, item);
+            }
+        }
+        self.seen += 1;
+    }
+
+    pub fn into_items(mut self) -> Vec<T> {
+        self.items.sort_by_key(|(index, _)| *index);
+        self.items.into_iter().map(|(_, item)| item).collect()
+    }
+}
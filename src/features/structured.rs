@@ -0,0 +1,172 @@
+//! `--schema`: replies that must be JSON matching a JSON Schema. Providers
+//! with a structured output mode are held to the schema natively and the
+//! rest are given it in a system prompt. Either way the reply is validated
+//! locally, and a reply that doesn't match is sent back to the model with
+//! the errors for one repair attempt.
+
+use crate::api::{ApiClient, ChatRequest, Message, Provider, ResponseSchema};
+use crate::error::{EchomindError, Result};
+use crate::features::json_schema;
+use crate::features::usage::UsageMeter;
+use serde_json::Value;
+use std::fs;
+use std::path::Path;
+
+/// Extra requests made after a reply fails validation
+pub const REPAIR_ATTEMPTS: u32 = 1;
+
+#[derive(Debug, Clone)]
+pub struct OutputSchema {
+    /// Sent to providers that ask for a schema name
+    pub name: String,
+    pub schema: Value,
+}
+
+impl OutputSchema {
+    /// Reads a JSON Schema file. The schema is named after the file.
+    pub fn load(path: &str) -> Result<Self> {
+        let contents = fs::read_to_string(path)
+            .map_err(|e| EchomindError::FileError(format!("Failed to read schema {}: {}", path, e)))?;
+        let schema: Value = serde_json::from_str(&contents)
+            .map_err(|e| EchomindError::ParseError(format!("Schema {} is not valid JSON: {}", path, e)))?;
+        if !schema.is_object() {
+            return Err(EchomindError::ParseError(format!("Schema {} must be a JSON object", path)));
+        }
+        let unresolved = json_schema::unresolved_refs(&schema);
+        if !unresolved.is_empty() {
+            return Err(EchomindError::ParseError(format!(
+                "Schema {} has references that can't be resolved: {} (only local #/... references are supported)",
+                path,
+                unresolved.join(", ")
+            )));
+        }
+        let stem = Path::new(path).file_stem().map(|s| s.to_string_lossy().to_string()).unwrap_or_default();
+        Ok(Self::new(&stem, schema))
+    }
+
+    pub fn new(name: &str, schema: Value) -> Self {
+        let name: String = name
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() || c == '-' || c == '_' { c } else { '_' })
+            .take(64)
+            .collect();
+        Self {
+            name: if name.is_empty() { "response".to_string() } else { name },
+            schema,
+        }
+    }
+
+    /// The JSON in `reply` if it matches the schema, otherwise what is wrong
+    /// with it.
+    pub fn validate(&self, reply: &str) -> std::result::Result<Value, Vec<String>> {
+        let value = json_schema::extract_json(reply).ok_or_else(|| vec!["the reply is not JSON".to_string()])?;
+        let errors = json_schema::validate(&self.schema, &value);
+        if errors.is_empty() {
+            Ok(value)
+        } else {
+            Err(errors)
+        }
+    }
+
+    fn instructions(&self) -> String {
+        let schema = serde_json::to_string_pretty(&self.schema).unwrap_or_else(|_| self.schema.to_string());
+        format!(
+            "Reply with a single JSON value that conforms to this JSON Schema, and nothing else: \
+             no prose and no markdown fences.\n\n{}",
+            schema
+        )
+    }
+
+    fn response_schema(&self) -> ResponseSchema {
+        ResponseSchema {
+            name: self.name.clone(),
+            schema: self.schema.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct StructuredReply {
+    /// The validated JSON
+    pub value: Value,
+    /// The reply as the model wrote it
+    pub raw: String,
+    /// Requests made, repairs included
+    pub attempts: u32,
+    /// Set when a request went out over a budget whose action is warn
+    pub budget_warning: Option<String>,
+}
+
+/// The request to send to `provider`. Where the schema can't be enforced
+/// natively, it is added as a system message after any existing ones.
+pub fn prepare(provider: &Provider, request: &ChatRequest, schema: &OutputSchema) -> ChatRequest {
+    let mut request = request.clone();
+    if !provider.supports_json_schema() {
+        let position = request.messages.iter().take_while(|m| m.role == "system").count();
+        request
+            .messages
+            .insert(position, Message::text("system".to_string(), schema.instructions()));
+    }
+    request
+}
+
+/// Sends `request` through `client` and returns the first reply that matches
+/// `schema`, asking the model to repair a mismatching reply up to
+/// `REPAIR_ATTEMPTS` times. With `usage`, every request, repairs included,
+/// is checked against the budget before it is sent and recorded after.
+pub async fn send(
+    client: &ApiClient,
+    request: &ChatRequest,
+    schema: &OutputSchema,
+    usage: Option<&UsageMeter>,
+) -> Result<StructuredReply> {
+    let mut client = client.clone();
+    client.set_response_schema(Some(schema.response_schema()));
+    let provider = client.provider().name().to_string();
+    let mut request = ChatRequest {
+        stream: None,
+        ..prepare(client.provider(), request, schema)
+    };
+
+    let mut budget_warning = None;
+    let mut attempts = 0;
+    loop {
+        // A repair carries the whole conversation so far, so it costs more
+        // and is checked again
+        attempts += 1;
+        let raw = UsageMeter::send(usage, &provider, &request, |warning| {
+            budget_warning = budget_warning.take().or(warning);
+            client.send_message(request.clone())
+        })
+        .await?;
+
+        match schema.validate(&raw) {
+            Ok(value) => {
+                return Ok(StructuredReply {
+                    value,
+                    raw,
+                    attempts,
+                    budget_warning,
+                })
+            }
+            Err(errors) if attempts > REPAIR_ATTEMPTS => {
+                return Err(EchomindError::ParseError(format!(
+                    "The reply does not match the schema after {} attempts: {}",
+                    attempts,
+                    errors.join("; ")
+                )));
+            }
+            Err(errors) => {
+                let problems: Vec<String> = errors.iter().map(|e| format!("- {}", e)).collect();
+                request.messages.push(Message::text("assistant".to_string(), raw));
+                request.messages.push(Message::text(
+                    "user".to_string(),
+                    format!(
+                        "That reply does not match the JSON Schema:\n{}\nReply again with only the corrected JSON.",
+                        problems.join("\n")
+                    ),
+                ));
+            }
+        }
+    }
+}
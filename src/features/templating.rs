@@ -0,0 +1,828 @@
+//! The template language shared by the content library, workflow prompts and
+//! `--format template:`.
+//!
+//! - `{{ name }}`, `{{ review.issues[0] }}`: insert a variable
+//! - `{{ name | "fallback" }}` or `{{ name|fallback }}`: default when unset (a
+//!   bare first segment that is not a filter name is taken as the default)
+//! - `{{ code | indent(4) | upper }}`: filters `upper`, `lower`, `trim`,
+//!   `indent(n)`, `json`, `join(sep)`, `file` and `default(value)`. `file`
+//!   only reads a path the caller passes in a variable, never one written
+//!   in the template itself
+//! - `{% if cond %}…{% elif cond %}…{% else %}…{% endif %}` with `not`,
+//!   `and`, `or` and `== != < > <= >=`
+//! - `{% for item in items %}…{% endfor %}` with `loop.index`, `loop.first`
+//!   and `loop.last`
+//! - `{name}`: the older single-brace placeholder, left as-is when unresolved
+//!   so literal braces (JSON, code) survive
+//! - `\{{` and `\{%` produce literal `{{` and `{%`
+//!
+//! Block tags that sit alone on a line take the whole line with them.
+
+use crate::error::{EchomindError, Result};
+use crate::features::workflow::resolve_path;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+const FILTERS: &[&str] = &["upper", "lower", "trim", "indent", "json", "join", "file", "default"];
+
+/// Renders `source` against `variables` in one go.
+pub fn render(source: &str, variables: &HashMap<String, Value>) -> Result<String> {
+    CompiledTemplate::compile(source)?.render(variables)
+}
+
+/// A variable referenced by a template, as found by [`CompiledTemplate::variables`].
+#[derive(Debug, Clone, PartialEq)]
+pub struct VariableRef {
+    pub name: String,
+    pub default: Option<String>,
+    pub usage: VariableUsage,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VariableUsage {
+    /// Inserted into the output
+    Value,
+    /// Looped over
+    List,
+    /// Passed through the `file` filter
+    File,
+    /// Only tested in a condition
+    Condition,
+    /// An old-style `{name}` placeholder
+    Legacy,
+}
+
+#[derive(Debug, Clone)]
+pub struct CompiledTemplate {
+    nodes: Vec<Node>,
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Text(String),
+    Output(Expression),
+    Legacy { path: String, raw: String },
+    If { branches: Vec<(Condition, Vec<Node>)>, otherwise: Vec<Node> },
+    For { item: String, iterable: String, body: Vec<Node> },
+}
+
+#[derive(Debug, Clone)]
+struct Expression {
+    source: Operand,
+    default: Option<String>,
+    filters: Vec<Filter>,
+}
+
+#[derive(Debug, Clone)]
+struct Filter {
+    name: String,
+    arg: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+enum Condition {
+    Or(Vec<Condition>),
+    And(Vec<Condition>),
+    Not(Box<Condition>),
+    Truthy(Operand),
+    Compare(Operand, String, Operand),
+}
+
+#[derive(Debug, Clone)]
+enum Operand {
+    Path(String),
+    Literal(Value),
+}
+
+#[derive(Debug)]
+enum Token {
+    Text(String),
+    Output(String, usize),
+    Tag(String, usize),
+    Legacy(String, String),
+}
+
+impl CompiledTemplate {
+    pub fn compile(source: &str) -> Result<Self> {
+        let tokens = tokenize(source)?;
+        let mut tokens = tokens.into_iter().peekable();
+        let (nodes, terminator) = parse_nodes(&mut tokens, &[])?;
+        if let Some((tag, line)) = terminator {
+            return Err(syntax_error(line, format!("unexpected {{% {} %}}", tag)));
+        }
+        Ok(Self { nodes })
+    }
+
+    pub fn render(&self, variables: &HashMap<String, Value>) -> Result<String> {
+        let mut scopes = vec![variables.clone()];
+        let mut out = String::new();
+        render_nodes(&self.nodes, &mut scopes, &mut out)?;
+        Ok(out)
+    }
+
+    /// Variables the template reads from its caller, in order of first use.
+    /// Loop variables and `loop.*` are excluded.
+    pub fn variables(&self) -> Vec<VariableRef> {
+        let mut refs = Vec::new();
+        collect_refs(&self.nodes, &mut Vec::new(), &mut refs);
+        refs
+    }
+}
+
+fn syntax_error(line: usize, message: String) -> EchomindError {
+    EchomindError::Other(format!("Template error on line {}: {}", line, message))
+}
+
+fn legacy_placeholder() -> &'static regex::Regex {
+    static PLACEHOLDER: OnceLock<regex::Regex> = OnceLock::new();
+    PLACEHOLDER.get_or_init(|| {
+        regex::Regex::new(r"^\{\s*(\$?[A-Za-z_][A-Za-z0-9_\-]*(?:\.[A-Za-z0-9_\-]+|\[[^\]]*\])*)\s*\}").unwrap()
+    })
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut text = String::new();
+    let mut i = 0;
+    let line_at = |i: usize| source[..i].matches('\n').count() + 1;
+
+    while i < source.len() {
+        let rest = &source[i..];
+
+        if rest.starts_with("\\{{") || rest.starts_with("\\{%") {
+            text.push_str(&rest[1..3]);
+            i += 3;
+            continue;
+        }
+
+        let close = if rest.starts_with("{{") {
+            Some("}}")
+        } else if rest.starts_with("{%") {
+            Some("%}")
+        } else {
+            None
+        };
+        if let Some(close) = close {
+            let end = rest
+                .find(close)
+                .ok_or_else(|| syntax_error(line_at(i), format!("'{}' is never closed with '{}'", &rest[..2], close)))?;
+            if !text.is_empty() {
+                tokens.push(Token::Text(std::mem::take(&mut text)));
+            }
+            let inner = rest[2..end].trim().to_string();
+            tokens.push(if close == "}}" {
+                Token::Output(inner, line_at(i))
+            } else {
+                Token::Tag(inner, line_at(i))
+            });
+            i += end + 2;
+            continue;
+        }
+
+        if rest.starts_with('{') {
+            if let Some(caps) = legacy_placeholder().captures(rest) {
+                if !text.is_empty() {
+                    tokens.push(Token::Text(std::mem::take(&mut text)));
+                }
+                tokens.push(Token::Legacy(caps[1].to_string(), caps[0].to_string()));
+                i += caps[0].len();
+                continue;
+            }
+        }
+
+        let ch = rest.chars().next().unwrap();
+        text.push(ch);
+        i += ch.len_utf8();
+    }
+
+    if !text.is_empty() {
+        tokens.push(Token::Text(text));
+    }
+    trim_block_lines(&mut tokens);
+    Ok(tokens)
+}
+
+// Remove the indentation and newline around block tags that stand alone on
+// their line, so `{% if %}` / `{% endfor %}` lines don't leave blank lines.
+fn trim_block_lines(tokens: &mut [Token]) {
+    let standalone: Vec<usize> = (0..tokens.len())
+        .filter(|&idx| matches!(tokens[idx], Token::Tag(..)))
+        .filter(|&idx| {
+            let starts_line = match idx.checked_sub(1).map(|p| &tokens[p]) {
+                None => true,
+                Some(Token::Text(text)) => {
+                    let tail = text.rsplit('\n').next().unwrap_or("");
+                    tail.chars().all(|c| c == ' ' || c == '\t') && (text.contains('\n') || idx == 1)
+                }
+                Some(_) => false,
+            };
+            let ends_line = match tokens.get(idx + 1) {
+                None => true,
+                Some(Token::Text(text)) => {
+                    let head = text.trim_start_matches([' ', '\t']);
+                    head.starts_with('\n') || head.starts_with("\r\n")
+                }
+                Some(_) => false,
+            };
+            starts_line && ends_line
+        })
+        .collect();
+
+    for idx in standalone {
+        if let Some(Token::Text(text)) = idx.checked_sub(1).and_then(|p| tokens.get_mut(p)) {
+            let trimmed = text.trim_end_matches([' ', '\t']).len();
+            text.truncate(trimmed);
+        }
+        if let Some(Token::Text(text)) = tokens.get_mut(idx + 1) {
+            let head = text.trim_start_matches([' ', '\t']);
+            let head = head
+                .strip_prefix("\r\n")
+                .or_else(|| head.strip_prefix('\n'))
+                .unwrap_or(head);
+            *text = head.to_string();
+        }
+    }
+}
+
+type Tokens = std::iter::Peekable<std::vec::IntoIter<Token>>;
+
+/// A block tag's contents and line, as returned when it ends a block.
+type BlockEnd = Option<(String, usize)>;
+
+// Parses until one of `terminators` (block keywords such as `endif`) and
+// returns the nodes plus the terminating tag, if any.
+fn parse_nodes(tokens: &mut Tokens, terminators: &[&str]) -> Result<(Vec<Node>, BlockEnd)> {
+    let mut nodes = Vec::new();
+
+    while let Some(token) = tokens.next() {
+        match token {
+            Token::Text(text) => nodes.push(Node::Text(text)),
+            Token::Legacy(path, raw) => nodes.push(Node::Legacy { path, raw }),
+            Token::Output(inner, line) => nodes.push(Node::Output(parse_expression(&inner, line)?)),
+            Token::Tag(inner, line) => {
+                let keyword = inner.split_whitespace().next().unwrap_or("");
+                if terminators.contains(&keyword) {
+                    return Ok((nodes, Some((inner, line))));
+                }
+
+                match keyword {
+                    "if" => nodes.push(parse_if(tokens, &inner, line)?),
+                    "for" => nodes.push(parse_for(tokens, &inner, line)?),
+                    "elif" | "else" | "endif" | "endfor" => {
+                        return Err(syntax_error(line, format!("unexpected {{% {} %}}", inner)));
+                    }
+                    other => return Err(syntax_error(line, format!("unknown tag '{}'", other))),
+                }
+            }
+        }
+    }
+
+    Ok((nodes, None))
+}
+
+fn parse_if(tokens: &mut Tokens, tag: &str, line: usize) -> Result<Node> {
+    let mut branches = Vec::new();
+    let mut condition = parse_condition(tag["if".len()..].trim(), line)?;
+
+    loop {
+        let (body, terminator) = parse_nodes(tokens, &["elif", "else", "endif"])?;
+        let (next, next_line) = terminator.ok_or_else(|| syntax_error(line, "{% if %} is missing {% endif %}".to_string()))?;
+        branches.push((condition, body));
+
+        match next.split_whitespace().next().unwrap_or("") {
+            "elif" => condition = parse_condition(next["elif".len()..].trim(), next_line)?,
+            "else" => {
+                let (otherwise, terminator) = parse_nodes(tokens, &["endif"])?;
+                if terminator.is_none() {
+                    return Err(syntax_error(line, "{% if %} is missing {% endif %}".to_string()));
+                }
+                return Ok(Node::If { branches, otherwise });
+            }
+            _ => return Ok(Node::If { branches, otherwise: Vec::new() }),
+        }
+    }
+}
+
+fn parse_for(tokens: &mut Tokens, tag: &str, line: usize) -> Result<Node> {
+    let parts: Vec<&str> = tag.split_whitespace().collect();
+    let (item, iterable) = match parts.as_slice() {
+        ["for", item, "in", iterable] if is_identifier(item) => (item.to_string(), iterable.to_string()),
+        _ => return Err(syntax_error(line, format!("expected {{% for item in list %}}, got {{% {} %}}", tag))),
+    };
+
+    let (body, terminator) = parse_nodes(tokens, &["endfor"])?;
+    if terminator.is_none() {
+        return Err(syntax_error(line, "{% for %} is missing {% endfor %}".to_string()));
+    }
+    Ok(Node::For { item, iterable, body })
+}
+
+fn is_identifier(s: &str) -> bool {
+    let mut chars = s.chars();
+    chars.next().is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-')
+}
+
+fn is_path(s: &str) -> bool {
+    let s = s.strip_prefix('$').unwrap_or(s);
+    is_identifier(s.split(['.', '[']).next().unwrap_or(""))
+        && s.chars().all(|c| c.is_ascii_alphanumeric() || "_-.[]'\"".contains(c))
+}
+
+fn unquote(s: &str) -> Option<&str> {
+    let s = s.trim();
+    if s.len() >= 2 && ((s.starts_with('"') && s.ends_with('"')) || (s.starts_with('\'') && s.ends_with('\''))) {
+        Some(&s[1..s.len() - 1])
+    } else {
+        None
+    }
+}
+
+// Splits on `sep` outside quotes and parentheses.
+fn split_top_level(s: &str, sep: char) -> Vec<&str> {
+    let mut parts = Vec::new();
+    let mut quote = None;
+    let mut depth = 0;
+    let mut start = 0;
+
+    for (i, c) in s.char_indices() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '(') => depth += 1,
+            (None, ')') => depth -= 1,
+            (None, c) if c == sep && depth == 0 => {
+                parts.push(&s[start..i]);
+                start = i + c.len_utf8();
+            }
+            _ => {}
+        }
+    }
+    parts.push(&s[start..]);
+    parts
+}
+
+fn parse_filter(part: &str) -> Option<Filter> {
+    let part = part.trim();
+    let (name, arg) = match part.find('(') {
+        Some(open) if part.ends_with(')') => {
+            let arg = part[open + 1..part.len() - 1].trim();
+            let arg = unquote(arg).unwrap_or(arg);
+            (&part[..open], Some(arg.to_string()))
+        }
+        Some(_) => return None,
+        None => (part, None),
+    };
+
+    FILTERS.contains(&name.trim()).then(|| Filter { name: name.trim().to_string(), arg })
+}
+
+fn parse_expression(inner: &str, line: usize) -> Result<Expression> {
+    let parts = split_top_level(inner, '|');
+    let head = parts[0].trim();
+
+    let source = if let Some(literal) = unquote(head) {
+        Operand::Literal(Value::String(literal.to_string()))
+    } else if is_path(head) {
+        Operand::Path(head.to_string())
+    } else {
+        return Err(syntax_error(line, format!("'{}' is not a variable name or quoted string", head)));
+    };
+
+    let mut default = None;
+    let mut filters = Vec::new();
+    for (index, part) in parts.iter().enumerate().skip(1) {
+        if let Some(literal) = unquote(part) {
+            default = Some(literal.to_string());
+        } else if let Some(filter) = parse_filter(part) {
+            filters.push(filter);
+        } else if index == 1 {
+            // `{{name|some default}}`: a bare first segment that isn't a filter
+            default = Some(part.trim().to_string());
+        } else {
+            return Err(syntax_error(
+                line,
+                format!("unknown filter '{}' (available: {})", part.trim(), FILTERS.join(", ")),
+            ));
+        }
+    }
+
+    // Templates are shared (packs, libraries), so the file they include has
+    // to be named by whoever renders them: no literal or default paths
+    let has_default = default.is_some() || filters.iter().any(|f| f.name == "default");
+    if filters.iter().any(|f| f.name == "file") && (matches!(source, Operand::Literal(_)) || has_default) {
+        return Err(syntax_error(
+            line,
+            "the file filter only reads a path given in a variable, not a literal or default path".to_string(),
+        ));
+    }
+
+    Ok(Expression { source, default, filters })
+}
+
+fn tokenize_condition(s: &str) -> Vec<String> {
+    let mut tokens = Vec::new();
+    let mut chars = s.chars().peekable();
+
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c == '"' || c == '\'' {
+            let mut token = String::from(c);
+            chars.next();
+            for next in chars.by_ref() {
+                token.push(next);
+                if next == c {
+                    break;
+                }
+            }
+            tokens.push(token);
+        } else if "=!<>".contains(c) {
+            let mut token = String::from(c);
+            chars.next();
+            if chars.peek() == Some(&'=') {
+                token.push('=');
+                chars.next();
+            }
+            tokens.push(token);
+        } else {
+            let mut token = String::new();
+            while let Some(&next) = chars.peek() {
+                if next.is_whitespace() || "=!<>".contains(next) {
+                    break;
+                }
+                token.push(next);
+                chars.next();
+            }
+            tokens.push(token);
+        }
+    }
+
+    tokens
+}
+
+fn parse_condition(source: &str, line: usize) -> Result<Condition> {
+    if source.is_empty() {
+        return Err(syntax_error(line, "missing condition".to_string()));
+    }
+
+    let tokens = tokenize_condition(source);
+    let mut pos = 0;
+    let condition = parse_or(&tokens, &mut pos, line)?;
+    if pos != tokens.len() {
+        return Err(syntax_error(line, format!("unexpected '{}' in condition '{}'", tokens[pos], source)));
+    }
+    Ok(condition)
+}
+
+fn parse_or(tokens: &[String], pos: &mut usize, line: usize) -> Result<Condition> {
+    let mut items = vec![parse_and(tokens, pos, line)?];
+    while tokens.get(*pos).is_some_and(|t| t == "or") {
+        *pos += 1;
+        items.push(parse_and(tokens, pos, line)?);
+    }
+    Ok(if items.len() == 1 { items.pop().unwrap() } else { Condition::Or(items) })
+}
+
+fn parse_and(tokens: &[String], pos: &mut usize, line: usize) -> Result<Condition> {
+    let mut items = vec![parse_not(tokens, pos, line)?];
+    while tokens.get(*pos).is_some_and(|t| t == "and") {
+        *pos += 1;
+        items.push(parse_not(tokens, pos, line)?);
+    }
+    Ok(if items.len() == 1 { items.pop().unwrap() } else { Condition::And(items) })
+}
+
+fn parse_not(tokens: &[String], pos: &mut usize, line: usize) -> Result<Condition> {
+    if tokens.get(*pos).is_some_and(|t| t == "not") {
+        *pos += 1;
+        return Ok(Condition::Not(Box::new(parse_not(tokens, pos, line)?)));
+    }
+
+    let left = parse_operand(tokens, pos, line)?;
+    match tokens.get(*pos).map(String::as_str) {
+        Some(op @ ("==" | "!=" | "<" | ">" | "<=" | ">=")) => {
+            let op = op.to_string();
+            *pos += 1;
+            let right = parse_operand(tokens, pos, line)?;
+            Ok(Condition::Compare(left, op, right))
+        }
+        _ => Ok(Condition::Truthy(left)),
+    }
+}
+
+fn parse_operand(tokens: &[String], pos: &mut usize, line: usize) -> Result<Operand> {
+    let token = tokens
+        .get(*pos)
+        .ok_or_else(|| syntax_error(line, "condition ends unexpectedly".to_string()))?;
+    *pos += 1;
+
+    if let Some(literal) = unquote(token) {
+        return Ok(Operand::Literal(Value::String(literal.to_string())));
+    }
+    match token.as_str() {
+        "true" => return Ok(Operand::Literal(Value::Bool(true))),
+        "false" => return Ok(Operand::Literal(Value::Bool(false))),
+        "null" | "none" => return Ok(Operand::Literal(Value::Null)),
+        _ => {}
+    }
+    if let Ok(number) = serde_json::from_str::<serde_json::Number>(token) {
+        return Ok(Operand::Literal(Value::Number(number)));
+    }
+    if is_path(token) {
+        return Ok(Operand::Path(token.clone()));
+    }
+    Err(syntax_error(line, format!("'{}' is not a value or variable name", token)))
+}
+
+fn lookup(scopes: &[HashMap<String, Value>], path: &str) -> Option<Value> {
+    scopes.iter().rev().find_map(|scope| resolve_path(scope, path))
+}
+
+fn display(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Null => String::new(),
+        other => other.to_string(),
+    }
+}
+
+fn truthy(value: Option<&Value>) -> bool {
+    match value {
+        None | Some(Value::Null) => false,
+        Some(Value::Bool(b)) => *b,
+        Some(Value::Number(n)) => n.as_f64().is_some_and(|n| n != 0.0),
+        // Values from the command line arrive as text, so "false" is false
+        Some(Value::String(s)) => !s.is_empty() && !s.eq_ignore_ascii_case("false"),
+        Some(Value::Array(items)) => !items.is_empty(),
+        Some(Value::Object(map)) => !map.is_empty(),
+    }
+}
+
+fn number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => s.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+fn evaluate(condition: &Condition, scopes: &[HashMap<String, Value>]) -> bool {
+    let resolve = |operand: &Operand| match operand {
+        Operand::Path(path) => lookup(scopes, path),
+        Operand::Literal(value) => Some(value.clone()),
+    };
+
+    match condition {
+        Condition::Or(items) => items.iter().any(|c| evaluate(c, scopes)),
+        Condition::And(items) => items.iter().all(|c| evaluate(c, scopes)),
+        Condition::Not(inner) => !evaluate(inner, scopes),
+        Condition::Truthy(operand) => truthy(resolve(operand).as_ref()),
+        Condition::Compare(left, op, right) => {
+            let left = resolve(left).unwrap_or(Value::Null);
+            let right = resolve(right).unwrap_or(Value::Null);
+            let ordering = match (number(&left), number(&right)) {
+                (Some(a), Some(b)) => a.partial_cmp(&b),
+                _ if matches!((&left, &right), (Value::Bool(_), _) | (_, Value::Bool(_))) => {
+                    return match op.as_str() {
+                        "==" => truthy(Some(&left)) == truthy(Some(&right)),
+                        "!=" => truthy(Some(&left)) != truthy(Some(&right)),
+                        _ => false,
+                    };
+                }
+                _ => Some(display(&left).cmp(&display(&right))),
+            };
+
+            let Some(ordering) = ordering else {
+                return false;
+            };
+            match op.as_str() {
+                "==" => ordering.is_eq(),
+                "!=" => ordering.is_ne(),
+                "<" => ordering.is_lt(),
+                ">" => ordering.is_gt(),
+                "<=" => ordering.is_le(),
+                _ => ordering.is_ge(),
+            }
+        }
+    }
+}
+
+fn apply_filter(filter: &Filter, value: Value) -> Result<Value> {
+    let text = || display(&value);
+    Ok(match filter.name.as_str() {
+        "upper" => Value::String(text().to_uppercase()),
+        "lower" => Value::String(text().to_lowercase()),
+        "trim" => Value::String(text().trim().to_string()),
+        "indent" => {
+            let width = match &filter.arg {
+                Some(arg) => arg
+                    .parse::<usize>()
+                    .map_err(|_| EchomindError::Other(format!("indent() expects a number of spaces, got '{}'", arg)))?,
+                None => 4,
+            };
+            let pad = " ".repeat(width);
+            let indented: Vec<String> = text()
+                .lines()
+                .map(|line| if line.is_empty() { String::new() } else { format!("{}{}", pad, line) })
+                .collect();
+            Value::String(indented.join("\n"))
+        }
+        "json" => Value::String(value.to_string()),
+        "join" => match &value {
+            Value::Array(items) => {
+                let separator = filter.arg.as_deref().unwrap_or(", ");
+                Value::String(items.iter().map(display).collect::<Vec<_>>().join(separator))
+            }
+            other => Value::String(display(other)),
+        },
+        "file" => {
+            let path = text();
+            let contents = std::fs::read_to_string(&path)
+                .map_err(|e| EchomindError::FileError(format!("Template file include '{}' failed: {}", path, e)))?;
+            Value::String(contents)
+        }
+        "default" => {
+            if truthy(Some(&value)) {
+                value
+            } else {
+                Value::String(filter.arg.clone().unwrap_or_default())
+            }
+        }
+        other => return Err(EchomindError::Other(format!("Unknown template filter '{}'", other))),
+    })
+}
+
+fn render_nodes(nodes: &[Node], scopes: &mut Vec<HashMap<String, Value>>, out: &mut String) -> Result<()> {
+    for node in nodes {
+        match node {
+            Node::Text(text) => out.push_str(text),
+            Node::Legacy { path, raw } => match lookup(scopes, path) {
+                Some(value) => out.push_str(&display(&value)),
+                None => out.push_str(raw),
+            },
+            Node::Output(expression) => {
+                let value = match &expression.source {
+                    Operand::Literal(value) => Some(value.clone()),
+                    Operand::Path(path) => lookup(scopes, path).filter(|v| !v.is_null()),
+                };
+                let fallback = expression.default.clone().or_else(|| {
+                    expression
+                        .filters
+                        .iter()
+                        .find(|f| f.name == "default")
+                        .map(|f| f.arg.clone().unwrap_or_default())
+                });
+
+                let mut value = match (value, fallback) {
+                    (Some(value), _) => value,
+                    (None, Some(fallback)) => Value::String(fallback),
+                    (None, None) => {
+                        let Operand::Path(path) = &expression.source else { unreachable!() };
+                        return Err(EchomindError::Other(format!(
+                            "Template variable '{}' is not set (give it a value or a default: {{{{ {}|default }}}})",
+                            path, path
+                        )));
+                    }
+                };
+                for filter in &expression.filters {
+                    value = apply_filter(filter, value)?;
+                }
+                out.push_str(&display(&value));
+            }
+            Node::If { branches, otherwise } => {
+                let body = branches
+                    .iter()
+                    .find(|(condition, _)| evaluate(condition, scopes))
+                    .map(|(_, body)| body)
+                    .unwrap_or(otherwise);
+                render_nodes(body, scopes, out)?;
+            }
+            Node::For { item, iterable, body } => {
+                let items = match lookup(scopes, iterable) {
+                    None | Some(Value::Null) => Vec::new(),
+                    Some(Value::Array(items)) => items,
+                    Some(Value::Object(map)) => map
+                        .into_iter()
+                        .map(|(key, value)| serde_json::json!({ "key": key, "value": value }))
+                        .collect(),
+                    Some(Value::String(s)) => match serde_json::from_str::<Value>(&s) {
+                        Ok(Value::Array(items)) => items,
+                        _ => return Err(EchomindError::Other(format!("Template variable '{}' is not a list", iterable))),
+                    },
+                    Some(_) => return Err(EchomindError::Other(format!("Template variable '{}' is not a list", iterable))),
+                };
+
+                let length = items.len();
+                for (index, value) in items.into_iter().enumerate() {
+                    let mut scope = HashMap::new();
+                    scope.insert(item.clone(), value);
+                    scope.insert(
+                        "loop".to_string(),
+                        serde_json::json!({
+                            "index": index + 1,
+                            "index0": index,
+                            "first": index == 0,
+                            "last": index + 1 == length,
+                            "length": length,
+                        }),
+                    );
+                    scopes.push(scope);
+                    let result = render_nodes(body, scopes, out);
+                    scopes.pop();
+                    result?;
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn root_name(path: &str) -> &str {
+    let path = path.strip_prefix('$').unwrap_or(path);
+    let path = path.strip_prefix('.').unwrap_or(path);
+    path.split(['.', '[']).next().unwrap_or(path)
+}
+
+fn add_ref(refs: &mut Vec<VariableRef>, path: &str, default: Option<String>, usage: VariableUsage, bound: &[String]) {
+    let name = root_name(path);
+    if name == "loop" || bound.iter().any(|b| b == name) {
+        return;
+    }
+    match refs.iter_mut().find(|r| r.name == name) {
+        Some(existing) => {
+            // A use that needs a value outranks a condition-only use
+            if existing.usage == VariableUsage::Condition {
+                existing.usage = usage;
+            }
+            if existing.default.is_none() {
+                existing.default = default;
+            }
+        }
+        None => refs.push(VariableRef { name: name.to_string(), default, usage }),
+    }
+}
+
+fn collect_refs(nodes: &[Node], bound: &mut Vec<String>, refs: &mut Vec<VariableRef>) {
+    for node in nodes {
+        match node {
+            Node::Text(_) => {}
+            Node::Legacy { path, .. } => add_ref(refs, path, None, VariableUsage::Legacy, bound),
+            Node::Output(expression) => {
+                if let Operand::Path(path) = &expression.source {
+                    let usage = if expression.filters.iter().any(|f| f.name == "file") {
+                        VariableUsage::File
+                    } else {
+                        VariableUsage::Value
+                    };
+                    let default = expression.default.clone().or_else(|| {
+                        expression
+                            .filters
+                            .iter()
+                            .find(|f| f.name == "default")
+                            .map(|f| f.arg.clone().unwrap_or_default())
+                    });
+                    add_ref(refs, path, default, usage, bound);
+                }
+            }
+            Node::If { branches, otherwise } => {
+                for (condition, body) in branches {
+                    let mut paths = Vec::new();
+                    condition_paths(condition, &mut paths);
+                    for path in paths {
+                        add_ref(refs, &path, None, VariableUsage::Condition, bound);
+                    }
+                    collect_refs(body, bound, refs);
+                }
+                collect_refs(otherwise, bound, refs);
+            }
+            Node::For { item, iterable, body } => {
+                add_ref(refs, iterable, None, VariableUsage::List, bound);
+                bound.push(item.clone());
+                collect_refs(body, bound, refs);
+                bound.pop();
+            }
+        }
+    }
+}
+
+fn condition_paths(condition: &Condition, paths: &mut Vec<String>) {
+    match condition {
+        Condition::Or(items) | Condition::And(items) => items.iter().for_each(|c| condition_paths(c, paths)),
+        Condition::Not(inner) => condition_paths(inner, paths),
+        Condition::Truthy(operand) => {
+            if let Operand::Path(path) = operand {
+                paths.push(path.clone());
+            }
+        }
+        Condition::Compare(left, _, right) => {
+            for operand in [left, right] {
+                if let Operand::Path(path) = operand {
+                    paths.push(path.clone());
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,591 @@
+//! Declarative transforms over a whole dataset: cleaning, date
+//! normalization, deduplication and derived columns.
+//!
+//! A `TransformSpec` is a list of steps, usually written by the model from a
+//! natural-language instruction (see `data_qa::plan_transform`) and then run
+//! locally over every row:
+//!
+//! ```json
+//! {"steps": [
+//!   {"op": "trim"},
+//!   {"op": "case", "columns": ["email"], "to": "lower"},
+//!   {"op": "parse_date", "columns": ["signup"], "formats": ["%d/%m/%Y"]},
+//!   {"op": "derive", "column": "total", "expr": "price * quantity"},
+//!   {"op": "dedupe", "columns": ["email"]}
+//! ]}
+//! ```
+//!
+//! Expressions in `derive` and `filter` use the query language's syntax.
+
+use crate::error::{EchomindError, Result};
+use crate::features::data_processing::{Dataset, Record};
+use crate::features::query::{self, display_value, number_value, resolve_name, truthy};
+use chrono::{NaiveDate, NaiveDateTime};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+
+/// Input formats tried by `parse_date` when the step lists none.
+const DATE_FORMATS: [&str; 12] = [
+    "%Y-%m-%d",
+    "%Y/%m/%d",
+    "%Y%m%d",
+    "%m/%d/%Y",
+    "%d.%m.%Y",
+    "%d %b %Y",
+    "%d %B %Y",
+    "%b %d, %Y",
+    "%B %d, %Y",
+    "%b %d %Y",
+    "%B %d %Y",
+    "%d-%b-%Y",
+];
+
+const DATETIME_FORMATS: [&str; 4] = ["%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M:%S", "%Y-%m-%dT%H:%M", "%Y-%m-%d %H:%M"];
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TransformSpec {
+    pub steps: Vec<TransformStep>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "snake_case")]
+pub enum TransformStep {
+    /// Strips surrounding whitespace; no columns means every text column
+    Trim {
+        #[serde(default)]
+        columns: Vec<String>,
+    },
+    Case {
+        #[serde(default)]
+        columns: Vec<String>,
+        to: TextCase,
+    },
+    /// Regex replacement in text; `with` may use `$1` for groups
+    Replace {
+        columns: Vec<String>,
+        pattern: String,
+        #[serde(default, rename = "with")]
+        replacement: String,
+    },
+    /// Sets empty cells to `value`
+    Fill { columns: Vec<String>, value: Value },
+    /// Turns text such as "$1,234.50", "12%" or "(40)" into numbers
+    ToNumber { columns: Vec<String> },
+    /// Rewrites dates in `output` format, trying `formats` (chrono syntax)
+    /// or common formats when there are none
+    ParseDate {
+        columns: Vec<String>,
+        #[serde(default)]
+        formats: Vec<String>,
+        #[serde(default = "default_date_output")]
+        output: String,
+    },
+    /// Sets `column`, new or existing, to an expression over the row
+    Derive { column: String, expr: String },
+    /// Keeps the rows matching a condition
+    Filter {
+        #[serde(rename = "where")]
+        condition: String,
+    },
+    /// Drops repeated rows by key columns (whole rows without columns)
+    Dedupe {
+        #[serde(default)]
+        columns: Vec<String>,
+        #[serde(default)]
+        keep: Keep,
+    },
+    Rename { columns: BTreeMap<String, String> },
+    Select { columns: Vec<String> },
+    Drop { columns: Vec<String> },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TextCase {
+    Lower,
+    Upper,
+    Title,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Keep {
+    #[default]
+    First,
+    Last,
+}
+
+fn default_date_output() -> String {
+    "%Y-%m-%d".to_string()
+}
+
+impl fmt::Display for TransformStep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let list = |columns: &[String]| {
+            if columns.is_empty() {
+                "all text columns".to_string()
+            } else {
+                columns.join(", ")
+            }
+        };
+        match self {
+            TransformStep::Trim { columns } => write!(f, "trim {}", list(columns)),
+            TransformStep::Case { columns, to } => {
+                let case = match to {
+                    TextCase::Lower => "lowercase",
+                    TextCase::Upper => "uppercase",
+                    TextCase::Title => "title-case",
+                };
+                write!(f, "{} {}", case, list(columns))
+            }
+            TransformStep::Replace { columns, pattern, replacement } => {
+                write!(f, "replace /{}/ with {:?} in {}", pattern, replacement, columns.join(", "))
+            }
+            TransformStep::Fill { columns, value } => write!(f, "fill empty {} with {}", columns.join(", "), value),
+            TransformStep::ToNumber { columns } => write!(f, "convert {} to numbers", columns.join(", ")),
+            TransformStep::ParseDate { columns, output, .. } => {
+                write!(f, "normalize dates in {} to {}", columns.join(", "), output)
+            }
+            TransformStep::Derive { column, expr } => write!(f, "set {} = {}", column, expr),
+            TransformStep::Filter { condition } => write!(f, "keep rows where {}", condition),
+            TransformStep::Dedupe { columns, keep } => {
+                let key = if columns.is_empty() { "whole rows".to_string() } else { columns.join(", ") };
+                let keep = if *keep == Keep::First { "first" } else { "last" };
+                write!(f, "dedupe by {}, keeping the {} row", key, keep)
+            }
+            TransformStep::Rename { columns } => {
+                let pairs: Vec<String> = columns.iter().map(|(from, to)| format!("{} → {}", from, to)).collect();
+                write!(f, "rename {}", pairs.join(", "))
+            }
+            TransformStep::Select { columns } => write!(f, "keep columns {}", columns.join(", ")),
+            TransformStep::Drop { columns } => write!(f, "drop columns {}", columns.join(", ")),
+        }
+    }
+}
+
+/// A cell changed by a transform: column, value before, value after.
+pub type CellChange = (String, Value, Value);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RowChange {
+    /// Position of the row in the source, from 1
+    pub row: usize,
+    pub cells: Vec<CellChange>,
+}
+
+#[derive(Debug, Clone)]
+pub struct TransformOutcome {
+    pub dataset: Dataset,
+    pub rows_in: usize,
+    /// Rows whose values changed, among those kept
+    pub changed_rows: usize,
+    /// The first changed rows, up to the preview size
+    pub preview: Vec<RowChange>,
+    /// Rows removed, by step description
+    pub removed: Vec<(String, usize)>,
+}
+
+impl TransformOutcome {
+    /// A one-line account of what the transform did.
+    pub fn summary(&self) -> String {
+        let mut out = format!("{} of {} rows changed", self.changed_rows, self.rows_in);
+        for (step, count) in &self.removed {
+            out.push_str(&format!(", {} removed by {}", count, step));
+        }
+        out.push_str(&format!("; {} rows out", self.dataset.records.len()));
+        out
+    }
+
+    /// The preview rows, one line per row listing its changed cells.
+    pub fn render_preview(&self) -> String {
+        let lines: Vec<String> = self
+            .preview
+            .iter()
+            .map(|change| {
+                let cells: Vec<String> = change
+                    .cells
+                    .iter()
+                    .map(|(column, before, after)| format!("{} {} → {}", column, show(before), show(after)))
+                    .collect();
+                format!("row {}: {}", change.row, cells.join("; "))
+            })
+            .collect();
+        lines.join("\n")
+    }
+}
+
+fn show(value: &Value) -> String {
+    match value {
+        Value::String(s) => format!("{:?}", s),
+        other => display_value(other),
+    }
+}
+
+struct Row {
+    origin: usize,
+    record: Record,
+}
+
+impl TransformSpec {
+    /// Reads a spec from JSON, such as a file saved from an earlier run.
+    pub fn from_json(text: &str) -> Result<Self> {
+        serde_json::from_str(text).map_err(|e| EchomindError::Other(format!("Invalid transform spec: {}", e)))
+    }
+
+    /// Checks that every step names existing columns (following renames and
+    /// derived columns), that patterns compile and that expressions parse.
+    pub fn validate(&self, columns: &[String]) -> Result<()> {
+        let mut columns = columns.to_vec();
+        for (index, step) in self.steps.iter().enumerate() {
+            let fail = |message: String| EchomindError::Other(format!("Step {} ({}): {}", index + 1, step, message));
+            let required = |names: &[String]| {
+                if names.is_empty() {
+                    Err(fail("no columns given".to_string()))
+                } else {
+                    Ok(())
+                }
+            };
+            let check = |names: &[String], columns: &[String]| {
+                match names.iter().find(|name| resolve_name(columns, name).is_none()) {
+                    Some(name) => Err(fail(format!("unknown column '{}' (columns: {})", name, columns.join(", ")))),
+                    None => Ok(()),
+                }
+            };
+            match step {
+                TransformStep::Trim { columns: names } | TransformStep::Case { columns: names, .. } => {
+                    check(names, &columns)?
+                }
+                TransformStep::Replace { columns: names, pattern, .. } => {
+                    required(names)?;
+                    check(names, &columns)?;
+                    Regex::new(pattern).map_err(|e| fail(format!("invalid pattern: {}", e)))?;
+                }
+                TransformStep::Fill { columns: names, .. } | TransformStep::ToNumber { columns: names } => {
+                    required(names)?;
+                    check(names, &columns)?;
+                }
+                TransformStep::ParseDate { columns: names, output, .. } => {
+                    required(names)?;
+                    check(names, &columns)?;
+                    // chrono panics when printing with an invalid format
+                    if chrono::format::StrftimeItems::new(output).any(|item| item == chrono::format::Item::Error) {
+                        return Err(fail(format!("invalid output format '{}'", output)));
+                    }
+                }
+                TransformStep::Select { columns: names } => {
+                    required(names)?;
+                    check(names, &columns)?;
+                    columns = names.iter().filter_map(|n| resolve_name(&columns, n)).map(str::to_string).collect();
+                }
+                TransformStep::Drop { columns: names } => {
+                    required(names)?;
+                    check(names, &columns)?;
+                    let dropped: Vec<String> =
+                        names.iter().filter_map(|n| resolve_name(&columns, n)).map(str::to_string).collect();
+                    columns.retain(|c| !dropped.contains(c));
+                }
+                TransformStep::Derive { column, expr } => {
+                    let expr = query::parse_expr(expr).map_err(|e| fail(e.to_string()))?;
+                    if let Some(name) = expr.unknown_column(&columns) {
+                        return Err(fail(format!("unknown column '{}' (columns: {})", name, columns.join(", "))));
+                    }
+                    if resolve_name(&columns, column).is_none() {
+                        columns.push(column.clone());
+                    }
+                }
+                TransformStep::Filter { condition } => {
+                    let expr = query::parse_expr(condition).map_err(|e| fail(e.to_string()))?;
+                    if let Some(name) = expr.unknown_column(&columns) {
+                        return Err(fail(format!("unknown column '{}' (columns: {})", name, columns.join(", "))));
+                    }
+                }
+                TransformStep::Dedupe { columns: names, .. } => check(names, &columns)?,
+                TransformStep::Rename { columns: pairs } => {
+                    let from: Vec<String> = pairs.keys().cloned().collect();
+                    check(&from, &columns)?;
+                    for (from, to) in pairs {
+                        let current = resolve_name(&columns, from).unwrap_or(from).to_string();
+                        if let Some(column) = columns.iter_mut().find(|c| **c == current) {
+                            *column = to.clone();
+                        }
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs every step over `dataset`, keeping the first `preview_rows`
+    /// changed rows for `TransformOutcome::preview`.
+    pub fn apply(&self, dataset: &Dataset, preview_rows: usize) -> Result<TransformOutcome> {
+        self.validate(&dataset.column_names)?;
+
+        let mut columns = dataset.column_names.clone();
+        // Current column name -> source column name
+        let mut sources: HashMap<String, String> = columns.iter().map(|c| (c.clone(), c.clone())).collect();
+        let mut rows: Vec<Row> = dataset
+            .records
+            .iter()
+            .enumerate()
+            .map(|(origin, record)| Row { origin, record: record.clone() })
+            .collect();
+        let mut removed = Vec::new();
+
+        for step in &self.steps {
+            let targets = |names: &[String], columns: &[String]| -> Vec<String> {
+                names.iter().filter_map(|n| resolve_name(columns, n)).map(str::to_string).collect()
+            };
+            let text_columns = |names: &[String], columns: &[String], rows: &[Row]| -> Vec<String> {
+                if !names.is_empty() {
+                    return targets(names, columns);
+                }
+                columns
+                    .iter()
+                    .filter(|c| rows.iter().any(|row| matches!(row.record.get(*c), Some(Value::String(_)))))
+                    .cloned()
+                    .collect()
+            };
+
+            match step {
+                TransformStep::Trim { columns: names } => {
+                    let names = text_columns(names, &columns, &rows);
+                    map_cells(&mut rows, &names, |value| match value {
+                        Value::String(s) if s.trim().is_empty() => Value::Null,
+                        Value::String(s) => Value::String(s.trim().to_string()),
+                        other => other.clone(),
+                    });
+                }
+                TransformStep::Case { columns: names, to } => {
+                    let names = text_columns(names, &columns, &rows);
+                    map_cells(&mut rows, &names, |value| match value {
+                        Value::String(s) => Value::String(match to {
+                            TextCase::Lower => s.to_lowercase(),
+                            TextCase::Upper => s.to_uppercase(),
+                            TextCase::Title => title_case(s),
+                        }),
+                        other => other.clone(),
+                    });
+                }
+                TransformStep::Replace { columns: names, pattern, replacement } => {
+                    let regex = Regex::new(pattern)
+                        .map_err(|e| EchomindError::Other(format!("Invalid pattern '{}': {}", pattern, e)))?;
+                    map_cells(&mut rows, &targets(names, &columns), |value| match value {
+                        Value::Null => Value::Null,
+                        other => {
+                            let text = display_value(other);
+                            let replaced = regex.replace_all(&text, replacement.as_str());
+                            if replaced == text {
+                                other.clone()
+                            } else {
+                                Value::String(replaced.into_owned())
+                            }
+                        }
+                    });
+                }
+                TransformStep::Fill { columns: names, value: fill } => {
+                    map_cells(&mut rows, &targets(names, &columns), |value| match value {
+                        Value::Null => fill.clone(),
+                        Value::String(s) if s.trim().is_empty() => fill.clone(),
+                        other => other.clone(),
+                    });
+                }
+                TransformStep::ToNumber { columns: names } => {
+                    map_cells(&mut rows, &targets(names, &columns), |value| match value {
+                        Value::String(s) => parse_number(s).map(number_value).unwrap_or_else(|| value.clone()),
+                        other => other.clone(),
+                    });
+                }
+                TransformStep::ParseDate { columns: names, formats, output } => {
+                    map_cells(&mut rows, &targets(names, &columns), |value| match value {
+                        Value::String(s) => normalize_date(s, formats, output)
+                            .map(Value::String)
+                            .unwrap_or_else(|| value.clone()),
+                        other => other.clone(),
+                    });
+                }
+                TransformStep::Derive { column, expr } => {
+                    let expr = query::parse_expr(expr)?;
+                    let name = match resolve_name(&columns, column) {
+                        Some(existing) => existing.to_string(),
+                        None => {
+                            columns.push(column.clone());
+                            column.clone()
+                        }
+                    };
+                    for row in &mut rows {
+                        let value = expr.evaluate(&row.record, &columns)?;
+                        row.record.insert(name.clone(), value);
+                    }
+                }
+                TransformStep::Filter { condition } => {
+                    let expr = query::parse_expr(condition)?;
+                    let before = rows.len();
+                    let mut kept = Vec::with_capacity(rows.len());
+                    for row in rows {
+                        if truthy(&expr.evaluate(&row.record, &columns)?) {
+                            kept.push(row);
+                        }
+                    }
+                    rows = kept;
+                    removed.push(("filter".to_string(), before - rows.len()));
+                }
+                TransformStep::Dedupe { columns: names, keep } => {
+                    let key_columns = if names.is_empty() { columns.clone() } else { targets(names, &columns) };
+                    let key = |row: &Row| -> String {
+                        key_columns
+                            .iter()
+                            .map(|c| row.record.get(c).map(display_value).unwrap_or_default())
+                            .collect::<Vec<_>>()
+                            .join("\u{1f}")
+                    };
+                    let before = rows.len();
+                    rows = match keep {
+                        Keep::First => {
+                            let mut seen = HashSet::new();
+                            rows.into_iter().filter(|row| seen.insert(key(row))).collect()
+                        }
+                        Keep::Last => {
+                            let last: HashMap<String, usize> =
+                                rows.iter().enumerate().map(|(i, row)| (key(row), i)).collect();
+                            rows.into_iter()
+                                .enumerate()
+                                .filter(|(i, row)| last.get(&key(row)) == Some(i))
+                                .map(|(_, row)| row)
+                                .collect()
+                        }
+                    };
+                    removed.push(("dedupe".to_string(), before - rows.len()));
+                }
+                TransformStep::Rename { columns: pairs } => {
+                    for (from, to) in pairs {
+                        let Some(current) = resolve_name(&columns, from).map(str::to_string) else { continue };
+                        for row in &mut rows {
+                            if let Some(value) = row.record.remove(&current) {
+                                row.record.insert(to.clone(), value);
+                            }
+                        }
+                        if let Some(source) = sources.remove(&current) {
+                            sources.insert(to.clone(), source);
+                        }
+                        if let Some(column) = columns.iter_mut().find(|c| **c == current) {
+                            *column = to.clone();
+                        }
+                    }
+                }
+                TransformStep::Select { columns: names } => {
+                    let kept = targets(names, &columns);
+                    for row in &mut rows {
+                        row.record.retain(|column, _| kept.contains(column));
+                    }
+                    columns = kept;
+                }
+                TransformStep::Drop { columns: names } => {
+                    let dropped = targets(names, &columns);
+                    for row in &mut rows {
+                        row.record.retain(|column, _| !dropped.contains(column));
+                    }
+                    columns.retain(|c| !dropped.contains(c));
+                }
+            }
+        }
+
+        let mut changed_rows = 0;
+        let mut preview = Vec::new();
+        for row in &rows {
+            let original = &dataset.records[row.origin];
+            let cells: Vec<CellChange> = columns
+                .iter()
+                .filter_map(|column| {
+                    let before = sources
+                        .get(column)
+                        .and_then(|source| original.get(source))
+                        .cloned()
+                        .unwrap_or(Value::Null);
+                    let after = row.record.get(column).cloned().unwrap_or(Value::Null);
+                    (before != after).then(|| (column.clone(), before, after))
+                })
+                .collect();
+            if cells.is_empty() {
+                continue;
+            }
+            changed_rows += 1;
+            if preview.len() < preview_rows {
+                preview.push(RowChange { row: row.origin + 1, cells });
+            }
+        }
+
+        removed.retain(|(_, count)| *count > 0);
+        Ok(TransformOutcome {
+            dataset: Dataset {
+                file_type: dataset.file_type.clone(),
+                column_names: columns,
+                records: rows.into_iter().map(|row| row.record).collect(),
+            },
+            rows_in: dataset.records.len(),
+            changed_rows,
+            preview,
+            removed,
+        })
+    }
+}
+
+fn map_cells(rows: &mut [Row], columns: &[String], f: impl Fn(&Value) -> Value) {
+    for row in rows {
+        for column in columns {
+            if let Some(value) = row.record.get_mut(column) {
+                *value = f(value);
+            }
+        }
+    }
+}
+
+fn title_case(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut start = true;
+    for c in text.chars() {
+        if start {
+            out.extend(c.to_uppercase());
+        } else {
+            out.extend(c.to_lowercase());
+        }
+        start = c.is_whitespace() || c == '-';
+    }
+    out
+}
+
+/// Reads "1,234.50", "$12", "€ 3", "12%", "(40)" and "1 000" as numbers.
+/// A percent sign is dropped, not divided out.
+fn parse_number(text: &str) -> Option<f64> {
+    let mut text = text.trim();
+    let negative = text.starts_with('(') && text.ends_with(')');
+    if negative {
+        text = &text[1..text.len() - 1];
+    }
+    let cleaned: String = text
+        .chars()
+        .filter(|c| !matches!(c, '$' | '€' | '£' | '¥' | '%' | ',' | '_' | ' ' | '\u{a0}'))
+        .collect();
+    let number: f64 = cleaned.parse().ok().filter(|n: &f64| n.is_finite())?;
+    Some(if negative { -number } else { number })
+}
+
+fn normalize_date(text: &str, formats: &[String], output: &str) -> Option<String> {
+    let text = text.trim();
+    let formats: Vec<&str> = if formats.is_empty() {
+        DATETIME_FORMATS.iter().chain(DATE_FORMATS.iter()).copied().collect()
+    } else {
+        formats.iter().map(String::as_str).collect()
+    };
+    if let Ok(datetime) = chrono::DateTime::parse_from_rfc3339(text) {
+        return Some(datetime.naive_local().format(output).to_string());
+    }
+    formats.iter().find_map(|format| {
+        NaiveDateTime::parse_from_str(text, format)
+            .or_else(|_| NaiveDate::parse_from_str(text, format).map(|date| date.and_time(chrono::NaiveTime::MIN)))
+            .ok()
+            .map(|datetime| datetime.format(output).to_string())
+    })
+}
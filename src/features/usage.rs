@@ -0,0 +1,448 @@
+//! Persistent usage ledger: one JSON line per request with its estimated
+//! tokens and cost, summarized by `echomind usage` and checked against the
+//! daily and monthly budgets in the config before requests are sent.
+
+use crate::api::ChatRequest;
+use crate::config::{BudgetAction, BudgetConfig, Config};
+use crate::error::{EchomindError, Result};
+use crate::features::performance::{estimate_cost, estimate_tokens};
+use chrono::{DateTime, Datelike, Local, NaiveDate, TimeZone, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::future::Future;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::Mutex;
+
+/// Output tokens assumed for a budget check when the request sets no
+/// `max_tokens`.
+pub const DEFAULT_OUTPUT_ESTIMATE: u32 = 500;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UsageRecord {
+    pub timestamp: DateTime<Utc>,
+    pub provider: String,
+    pub model: String,
+    #[serde(default)]
+    pub preset: Option<String>,
+    /// What sent the request, such as query, compare or interactive
+    #[serde(default)]
+    pub command: String,
+    pub input_tokens: u32,
+    pub output_tokens: u32,
+    /// Estimated dollars
+    pub cost: f64,
+    #[serde(default = "default_success")]
+    pub success: bool,
+}
+
+fn default_success() -> bool {
+    true
+}
+
+/// Append-only JSON Lines file of usage records.
+pub struct UsageLedger {
+    path: PathBuf,
+}
+
+impl UsageLedger {
+    pub fn new(path: PathBuf) -> Self {
+        Self { path }
+    }
+
+    pub fn open_default() -> Result<Self> {
+        Ok(Self::new(Config::usage_ledger_path()?))
+    }
+
+    pub fn record(&self, record: &UsageRecord) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent)
+                .map_err(|e| EchomindError::FileError(format!("Failed to create {}: {}", parent.display(), e)))?;
+        }
+        let line = serde_json::to_string(record)
+            .map_err(|e| EchomindError::ParseError(format!("Failed to serialize usage record: {}", e)))?;
+        let mut file = fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)
+            .map_err(|e| EchomindError::FileError(format!("Failed to open usage ledger: {}", e)))?;
+        writeln!(file, "{}", line).map_err(|e| EchomindError::FileError(format!("Failed to write usage ledger: {}", e)))
+    }
+
+    /// Every record, oldest first. Lines that don't parse (say, from an
+    /// interrupted write) are skipped.
+    pub fn records(&self) -> Result<Vec<UsageRecord>> {
+        if !self.path.exists() {
+            return Ok(Vec::new());
+        }
+        let contents = fs::read_to_string(&self.path)
+            .map_err(|e| EchomindError::FileError(format!("Failed to read usage ledger: {}", e)))?;
+        Ok(contents
+            .lines()
+            .filter_map(|line| serde_json::from_str(line).ok())
+            .collect())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UsagePeriod {
+    Daily,
+    Weekly,
+    Monthly,
+}
+
+impl UsagePeriod {
+    pub fn parse(name: &str) -> Result<Self> {
+        match name.to_lowercase().as_str() {
+            "daily" | "day" => Ok(UsagePeriod::Daily),
+            "weekly" | "week" => Ok(UsagePeriod::Weekly),
+            "monthly" | "month" => Ok(UsagePeriod::Monthly),
+            other => Err(EchomindError::Other(format!(
+                "Unknown period '{}' (expected daily, weekly or monthly)",
+                other
+            ))),
+        }
+    }
+
+    /// The local calendar period containing `timestamp`, such as
+    /// `2024-05-01`, `2024-W18` or `2024-05`.
+    pub fn label(&self, timestamp: &DateTime<Utc>) -> String {
+        let local = timestamp.with_timezone(&Local);
+        match self {
+            UsagePeriod::Daily => local.format("%Y-%m-%d").to_string(),
+            UsagePeriod::Weekly => {
+                let week = local.iso_week();
+                format!("{}-W{:02}", week.year(), week.week())
+            }
+            UsagePeriod::Monthly => local.format("%Y-%m").to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UsageGrouping {
+    Model,
+    Provider,
+    Preset,
+    Command,
+}
+
+impl UsageGrouping {
+    pub fn parse(name: &str) -> Result<Self> {
+        match name.to_lowercase().as_str() {
+            "model" => Ok(UsageGrouping::Model),
+            "provider" => Ok(UsageGrouping::Provider),
+            "preset" => Ok(UsageGrouping::Preset),
+            "command" => Ok(UsageGrouping::Command),
+            other => Err(EchomindError::Other(format!(
+                "Unknown grouping '{}' (expected model, provider, preset or command)",
+                other
+            ))),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            UsageGrouping::Model => "model",
+            UsageGrouping::Provider => "provider",
+            UsageGrouping::Preset => "preset",
+            UsageGrouping::Command => "command",
+        }
+    }
+
+    fn key(&self, record: &UsageRecord) -> String {
+        match self {
+            UsageGrouping::Model => format!("{}:{}", record.provider, record.model),
+            UsageGrouping::Provider => record.provider.clone(),
+            UsageGrouping::Preset => record.preset.clone().unwrap_or_else(|| "(none)".to_string()),
+            UsageGrouping::Command => record.command.clone(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct UsageRow {
+    pub period: String,
+    pub key: String,
+    pub requests: u64,
+    pub errors: u64,
+    pub input_tokens: u64,
+    pub output_tokens: u64,
+    pub cost: f64,
+}
+
+/// Totals per period and group, oldest period first and the costliest
+/// group first within a period.
+pub fn summarize(records: &[UsageRecord], period: UsagePeriod, grouping: UsageGrouping) -> Vec<UsageRow> {
+    let mut rows: BTreeMap<(String, String), UsageRow> = BTreeMap::new();
+    for record in records {
+        let key = (period.label(&record.timestamp), grouping.key(record));
+        let row = rows.entry(key.clone()).or_insert_with(|| UsageRow {
+            period: key.0,
+            key: key.1,
+            requests: 0,
+            errors: 0,
+            input_tokens: 0,
+            output_tokens: 0,
+            cost: 0.0,
+        });
+        row.requests += 1;
+        if !record.success {
+            row.errors += 1;
+        }
+        row.input_tokens += record.input_tokens as u64;
+        row.output_tokens += record.output_tokens as u64;
+        row.cost += record.cost;
+    }
+
+    let mut rows: Vec<UsageRow> = rows.into_values().collect();
+    rows.sort_by(|a, b| {
+        a.period
+            .cmp(&b.period)
+            .then(b.cost.partial_cmp(&a.cost).unwrap_or(std::cmp::Ordering::Equal))
+            .then(a.key.cmp(&b.key))
+    });
+    rows
+}
+
+/// Spend so far in the local day and month containing `now`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Spend {
+    pub today: f64,
+    pub this_month: f64,
+}
+
+pub fn spend_at(records: &[UsageRecord], now: DateTime<Local>) -> Spend {
+    let day_start = local_midnight(now.date_naive());
+    let month_start = local_midnight(now.date_naive().with_day(1).unwrap_or(now.date_naive()));
+    let now = now.with_timezone(&Utc);
+    let since = |start: DateTime<Utc>| {
+        records
+            .iter()
+            .filter(|r| r.timestamp >= start && r.timestamp <= now)
+            .map(|r| r.cost)
+            .sum()
+    };
+    Spend {
+        today: since(day_start),
+        this_month: since(month_start),
+    }
+}
+
+fn local_midnight(date: NaiveDate) -> DateTime<Utc> {
+    let midnight = date.and_hms_opt(0, 0, 0).unwrap_or_default();
+    Local
+        .from_local_datetime(&midnight)
+        .earliest()
+        .map(|t| t.with_timezone(&Utc))
+        .unwrap_or_else(|| Utc.from_utc_datetime(&midnight))
+}
+
+/// Checks one command's requests against the budget and records them in
+/// the ledger. Without a ledger nothing is recorded or enforced.
+///
+/// The ledger is read once per day a meter is used; after that the meter
+/// keeps a running total of what it records, plus the estimates of requests
+/// that passed the check and haven't been recorded yet, so concurrent
+/// requests can't all pass against the same spend.
+pub struct UsageMeter {
+    ledger: Option<UsageLedger>,
+    budget: BudgetConfig,
+    command: String,
+    preset: Option<String>,
+    spend: Mutex<Option<RunningSpend>>,
+}
+
+struct RunningSpend {
+    /// The local day `spend` was computed for
+    day: NaiveDate,
+    spend: Spend,
+    /// Estimates of checked requests still in flight
+    reserved: f64,
+}
+
+impl UsageMeter {
+    pub fn new(ledger: Option<UsageLedger>, budget: BudgetConfig, command: &str) -> Self {
+        Self {
+            ledger,
+            budget,
+            command: command.to_string(),
+            preset: None,
+            spend: Mutex::new(None),
+        }
+    }
+
+    /// A meter on the default ledger with the config's budget.
+    pub fn for_config(config: &Config, command: &str) -> Self {
+        Self::new(UsageLedger::open_default().ok(), config.budget.clone(), command)
+    }
+
+    pub fn set_preset(&mut self, preset: Option<String>) {
+        self.preset = preset;
+    }
+
+    /// Dollar cost, from the config's price for the model if it has one.
+    pub fn price(&self, provider: &str, model: &str, input_tokens: u32, output_tokens: u32) -> f64 {
+        match self.budget.prices.get(model) {
+            Some(price) => {
+                input_tokens as f64 / 1000.0 * price.input_per_1k + output_tokens as f64 / 1000.0 * price.output_per_1k
+            }
+            None => estimate_cost(provider, model, input_tokens, output_tokens),
+        }
+    }
+
+    /// Most a request could cost: its input plus `max_tokens` of output.
+    pub fn estimate(&self, provider: &str, request: &ChatRequest) -> f64 {
+        let output = request.max_tokens.unwrap_or(DEFAULT_OUTPUT_ESTIMATE);
+        self.price(provider, &model_name(request), request_tokens(request), output)
+    }
+
+    /// Whether the request fits the budget: `Ok(None)` if it does,
+    /// `Ok(Some(warning))` if it doesn't and the action is warn, and an
+    /// error if the action is block.
+    pub fn check(&self, provider: &str, request: &ChatRequest) -> Result<Option<String>> {
+        self.check_at(provider, request, Local::now())
+    }
+
+    pub fn check_at(&self, provider: &str, request: &ChatRequest, now: DateTime<Local>) -> Result<Option<String>> {
+        self.check_cost_at(self.estimate(provider, request), now)
+    }
+
+    /// Like `check`, for an estimated cost covering several requests, such
+    /// as a whole batch or load test.
+    pub fn check_cost(&self, estimate: f64) -> Result<Option<String>> {
+        self.check_cost_at(estimate, Local::now())
+    }
+
+    pub fn check_cost_at(&self, estimate: f64, now: DateTime<Local>) -> Result<Option<String>> {
+        let Some(ledger) = &self.ledger else {
+            return Ok(None);
+        };
+        if self.budget.daily.is_none() && self.budget.monthly.is_none() {
+            return Ok(None);
+        }
+
+        let mut running = self.spend.lock().unwrap_or_else(|e| e.into_inner());
+        if running.as_ref().map(|r| r.day) != Some(now.date_naive()) {
+            *running = Some(RunningSpend {
+                day: now.date_naive(),
+                spend: spend_at(&ledger.records()?, now),
+                reserved: 0.0,
+            });
+        }
+        let Some(running) = running.as_mut() else {
+            return Ok(None);
+        };
+
+        let in_flight = if running.reserved > 0.0 {
+            format!(" (plus ${:.4} in flight)", running.reserved)
+        } else {
+            String::new()
+        };
+        let exceeded: Vec<String> = [
+            ("daily", self.budget.daily, running.spend.today, "today"),
+            ("monthly", self.budget.monthly, running.spend.this_month, "this month"),
+        ]
+        .into_iter()
+        .filter_map(|(name, cap, spent, period)| {
+            let cap = cap?;
+            (spent + running.reserved + estimate > cap).then(|| {
+                format!(
+                    "${:.2} of the ${:.2} {} budget is spent {}{}; this request (up to ${:.4}) would exceed it",
+                    spent, cap, name, period, in_flight, estimate
+                )
+            })
+        })
+        .collect();
+
+        if exceeded.is_empty() {
+            running.reserved += estimate;
+            return Ok(None);
+        }
+        let message = exceeded.join("; ");
+        match self.budget.action {
+            BudgetAction::Warn => {
+                running.reserved += estimate;
+                Ok(Some(message))
+            }
+            BudgetAction::Block => Err(EchomindError::Other(format!(
+                "Request blocked: {}. Raise the cap under [budget] in the config, or set action = \"warn\".",
+                message
+            ))),
+        }
+    }
+
+    /// Checks `request` against `usage`'s budget, sends it with `send` and
+    /// records the reply in the ledger. `send` is passed the budget warning,
+    /// if any, before anything goes out. Over a blocking budget it isn't
+    /// called and the block is returned as the error. Without a meter this
+    /// is just `send(None)`.
+    ///
+    /// The ledger is best effort: a failed write doesn't fail a request
+    /// that already went out.
+    pub async fn send<F, Fut>(usage: Option<&Self>, provider: &str, request: &ChatRequest, send: F) -> Result<String>
+    where
+        F: FnOnce(Option<String>) -> Fut,
+        Fut: Future<Output = Result<String>>,
+    {
+        let Some(usage) = usage else {
+            return send(None).await;
+        };
+        let warning = usage.check(provider, request)?;
+        let reply = send(warning).await;
+        let _ = usage.record(provider, request, reply.as_deref().ok());
+        reply
+    }
+
+    /// Records a request; `response` is `None` when it failed.
+    pub fn record(&self, provider: &str, request: &ChatRequest, response: Option<&str>) -> Result<()> {
+        self.record_tokens(provider, request, response.map(estimate_tokens))
+    }
+
+    /// Like `record`, for when only the reply's token count was kept.
+    pub fn record_tokens(&self, provider: &str, request: &ChatRequest, output_tokens: Option<u32>) -> Result<()> {
+        let Some(ledger) = &self.ledger else {
+            return Ok(());
+        };
+        let model = model_name(request);
+        let input_tokens = request_tokens(request);
+        let succeeded = output_tokens.is_some();
+        let output_tokens = output_tokens.unwrap_or(0);
+        let cost = if succeeded {
+            self.price(provider, &model, input_tokens, output_tokens)
+        } else {
+            0.0
+        };
+        if let Some(running) = self.spend.lock().unwrap_or_else(|e| e.into_inner()).as_mut() {
+            // The request's reservation becomes its actual cost
+            running.reserved = (running.reserved - self.estimate(provider, request)).max(0.0);
+            running.spend.today += cost;
+            running.spend.this_month += cost;
+        }
+        ledger.record(&UsageRecord {
+            timestamp: Utc::now(),
+            provider: provider.to_string(),
+            model,
+            preset: self.preset.clone(),
+            command: self.command.clone(),
+            input_tokens: if succeeded { input_tokens } else { 0 },
+            output_tokens,
+            cost,
+            success: succeeded,
+        })
+    }
+}
+
+fn model_name(request: &ChatRequest) -> String {
+    request.model.clone().unwrap_or_default()
+}
+
+fn request_tokens(request: &ChatRequest) -> u32 {
+    request
+        .messages
+        .iter()
+        .filter_map(|m| m.get_text())
+        .map(estimate_tokens)
+        .sum()
+}
@@ -1,25 +1,46 @@
-use crate::api::{ApiClient, ChatRequest, Message};
+use crate::api::{ApiClient, ChatRequest, Message, Provider};
 use crate::error::{EchomindError, Result};
+use crate::features::json_schema::extract_json;
+use crate::features::templating;
+use crate::features::usage::UsageMeter;
+use futures::stream::{self, StreamExt};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::collections::HashSet;
 use std::fs;
-use tokio::time::{sleep, Duration};
+use std::path::{Path, PathBuf};
+use tokio::time::{sleep, timeout, Duration};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WorkflowStep {
     pub id: String,
     pub name: String,
     pub step_type: StepType,
+    #[serde(default)]
     pub prompt: Option<String>,
+    #[serde(default)]
     pub provider: Option<String>,
+    #[serde(default)]
     pub model: Option<String>,
+    #[serde(default)]
     pub temperature: Option<f32>,
+    #[serde(default)]
     pub max_tokens: Option<u32>,
+    #[serde(default)]
     pub conditions: Vec<Condition>,
+    #[serde(default)]
     pub next_step: Option<String>,
+    #[serde(default)]
     pub error_step: Option<String>,
+    /// Extra attempts after the first failure
+    #[serde(default)]
     pub retry_count: u32,
+    /// Per-attempt timeout in seconds
+    #[serde(default)]
     pub timeout: Option<u64>,
+    /// Step-local parameters (e.g. `delay_ms` for Delay, `transform` for Transform)
+    #[serde(default)]
+    pub params: HashMap<String, serde_json::Value>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -30,13 +51,45 @@ pub enum StepType {
     Transform,
     Output,
     Input,
+    /// Reads `params.path` into the step output (`params.format: json` parses it)
+    ReadFile,
+    /// Writes `params.content` to `params.path` (`params.append` to append)
+    WriteFile,
+    /// Runs an allowlisted program from `params.command` without a shell
+    Shell,
+    /// Calls `params.url` with `params.method`, `params.headers` and `params.body`
+    HttpRequest,
+    /// Extracts `params.path` from the JSON found at `params.source`
+    JsonExtract,
+    /// Runs the steps listed in `params.branches` concurrently (fan-out)
+    Parallel,
+    /// Runs `params.step` once per element of `params.items`
+    Map,
+    /// Collects the values at `params.sources` into one JSON array (fan-in)
+    Aggregate,
 }
 
+/// A branch taken after a step succeeds: the first condition whose `when`
+/// expression holds decides the next step, otherwise `next_step` is used.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Condition {
-    pub variable: String,
-    pub operator: ConditionOperator,
-    pub value: serde_json::Value,
+    pub when: ConditionExpr,
+    pub next_step: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum ConditionExpr {
+    All { all: Vec<ConditionExpr> },
+    Any { any: Vec<ConditionExpr> },
+    Not { not: Box<ConditionExpr> },
+    Compare {
+        /// Variable name or path into it, e.g. `score`, `$.review.issues[0].severity`
+        variable: String,
+        operator: ConditionOperator,
+        #[serde(default)]
+        value: serde_json::Value,
+    },
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -50,55 +103,411 @@ pub enum ConditionOperator {
     LessThan,
     GreaterThanOrEqual,
     LessThanOrEqual,
+    Matches,
+    Exists,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Workflow {
     pub id: String,
     pub name: String,
+    #[serde(default)]
     pub description: Option<String>,
     pub steps: Vec<WorkflowStep>,
+    #[serde(default)]
     pub variables: HashMap<String, serde_json::Value>,
     pub start_step: String,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WorkflowFormat {
+    Json,
+    Yaml,
+    Toml,
+}
+
+impl WorkflowFormat {
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()).map(|e| e.to_lowercase()).as_deref() {
+            Some("yaml") | Some("yml") => WorkflowFormat::Yaml,
+            Some("toml") => WorkflowFormat::Toml,
+            _ => WorkflowFormat::Json,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IssueSeverity {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Clone)]
+pub struct ValidationIssue {
+    pub severity: IssueSeverity,
+    pub step_id: Option<String>,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WorkflowContext {
+    #[serde(default)]
+    pub run_id: String,
     pub variables: HashMap<String, serde_json::Value>,
     pub current_step: String,
     pub history: Vec<StepResult>,
     pub errors: Vec<String>,
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum RunStatus {
+    Running,
+    Completed,
+    Failed,
+}
+
+/// A persisted workflow run: the definition it ran against plus its context,
+/// written after every step so a failed run can be resumed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WorkflowRun {
+    pub run_id: String,
+    pub workflow: Workflow,
+    pub status: RunStatus,
+    pub started_at: chrono::DateTime<chrono::Utc>,
+    pub updated_at: chrono::DateTime<chrono::Utc>,
+    pub context: WorkflowContext,
+}
+
+/// One step on the path a dry run expects to take.
 #[derive(Debug, Clone)]
+pub struct PlannedStep {
+    pub step_id: String,
+    pub name: String,
+    pub step_type: StepType,
+    /// The prompt, command, URL or path with current variables filled in
+    pub rendered: Option<String>,
+    /// Depth below the main path (branches/items of Parallel and Map steps)
+    pub depth: usize,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct StepResult {
     pub step_id: String,
     pub success: bool,
     pub output: Option<String>,
     pub duration_ms: u64,
     pub error: Option<String>,
+    pub attempts: u32,
+    /// Per-branch or per-item results of Parallel and Map steps
+    #[serde(default)]
+    pub children: Vec<StepResult>,
+    /// Structured output (e.g. exit code and stderr of a Shell step), exposed
+    /// to later steps as `{<step id>.<field>}`
+    #[serde(default)]
+    pub data: Option<serde_json::Value>,
+}
+
+impl StepResult {
+    pub fn succeeded(step_id: &str, output: String) -> Self {
+        Self {
+            step_id: step_id.to_string(),
+            success: true,
+            output: Some(output),
+            duration_ms: 0,
+            error: None,
+            attempts: 1,
+            children: Vec::new(),
+            data: None,
+        }
+    }
+
+    pub fn failed(step_id: &str, error: String) -> Self {
+        Self {
+            step_id: step_id.to_string(),
+            success: false,
+            output: None,
+            duration_ms: 0,
+            error: Some(error),
+            attempts: 1,
+            children: Vec::new(),
+            data: None,
+        }
+    }
+
+    fn with_data(mut self, data: serde_json::Value) -> Self {
+        self.data = Some(data);
+        self
+    }
 }
 
 pub struct WorkflowManager {
     workflows: HashMap<String, Workflow>,
+    api_key: Option<String>,
+    default_model: Option<String>,
+    default_timeout: u64,
+    shell_allowlist: Vec<String>,
+    runs_dir: Option<PathBuf>,
+    usage: Option<UsageMeter>,
 }
 
 impl WorkflowManager {
     pub fn new() -> Self {
         Self {
             workflows: HashMap::new(),
+            api_key: None,
+            default_model: None,
+            default_timeout: 30,
+            shell_allowlist: Vec::new(),
+            runs_dir: None,
+            usage: None,
         }
     }
 
-    pub fn load_workflow_from_file(&mut self, file_path: &str) -> Result<()> {
+    /// Directory where runs are persisted after every step. Runs are kept in
+    /// memory only when unset.
+    pub fn set_runs_dir(&mut self, dir: PathBuf) {
+        self.runs_dir = Some(dir);
+    }
+
+    /// Programs Shell steps may run. Entries match the program exactly as
+    /// written in the step, so `git` does not allow `/tmp/x/git`.
+    pub fn set_shell_allowlist(&mut self, programs: Vec<String>) {
+        self.shell_allowlist = programs;
+    }
+
+    /// API key used when a step overrides the provider.
+    pub fn set_api_key(&mut self, api_key: Option<String>) {
+        self.api_key = api_key;
+    }
+
+    /// Model used by AI steps that don't name one.
+    pub fn set_default_model(&mut self, model: Option<String>) {
+        self.default_model = model;
+    }
+
+    /// Client timeout (seconds) for clients created for per-step providers.
+    pub fn set_default_timeout(&mut self, timeout: u64) {
+        self.default_timeout = timeout;
+    }
+
+    /// Checks each AI step against the budget and records it in the ledger.
+    /// A step over a blocking budget fails without being sent.
+    pub fn set_usage_meter(&mut self, usage: UsageMeter) {
+        self.usage = Some(usage);
+    }
+
+    /// Loads a workflow definition (JSON, YAML or TOML, chosen by extension)
+    /// and returns its id.
+    pub fn load_workflow_from_file(&mut self, file_path: &str) -> Result<String> {
         let contents = fs::read_to_string(file_path)
             .map_err(|e| EchomindError::FileError(format!("Failed to read workflow file: {}", e)))?;
-        
-        let workflow: Workflow = serde_json::from_str(&contents)
-            .map_err(|e| EchomindError::ParseError(format!("Failed to parse workflow: {}", e)))?;
-        
-        self.workflows.insert(workflow.id.clone(), workflow);
-        Ok(())
+
+        let workflow = Self::parse_workflow(&contents, WorkflowFormat::from_path(Path::new(file_path)))?;
+        let id = workflow.id.clone();
+        self.workflows.insert(id.clone(), workflow);
+        Ok(id)
+    }
+
+    pub fn parse_workflow(contents: &str, format: WorkflowFormat) -> Result<Workflow> {
+        match format {
+            WorkflowFormat::Json => serde_json::from_str(contents)
+                .map_err(|e| EchomindError::ParseError(format!("Failed to parse workflow: {}", e))),
+            WorkflowFormat::Yaml => serde_yaml::from_str(contents)
+                .map_err(|e| EchomindError::ParseError(format!("Failed to parse workflow: {}", e))),
+            WorkflowFormat::Toml => toml::from_str(contents)
+                .map_err(|e| EchomindError::ParseError(format!("Failed to parse workflow: {}", e))),
+        }
+    }
+
+    /// Loads every workflow file in `dir` and its subdirectories (prompt pack
+    /// namespaces), skipping files that fail to parse. Returns the number of
+    /// workflows loaded.
+    pub fn load_workflows_from_dir(&mut self, dir: &Path) -> Result<usize> {
+        if !dir.exists() {
+            return Ok(0);
+        }
+
+        let entries = fs::read_dir(dir)
+            .map_err(|e| EchomindError::FileError(format!("Failed to read workflow directory: {}", e)))?;
+
+        let mut loaded = 0;
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                loaded += self.load_workflows_from_dir(&path)?;
+                continue;
+            }
+            let is_workflow = matches!(
+                path.extension().and_then(|e| e.to_str()),
+                Some("json") | Some("yaml") | Some("yml") | Some("toml")
+            );
+            if is_workflow && self.load_workflow_from_file(&path.to_string_lossy()).is_ok() {
+                loaded += 1;
+            }
+        }
+
+        Ok(loaded)
+    }
+
+    /// Checks a workflow for structural problems before it is executed:
+    /// duplicate ids, a missing start step, dangling `next_step`/`error_step`/
+    /// condition references, steps that can never be reached and cycles.
+    /// A cycle made only of `next_step` links can never terminate and is an
+    /// error; loops that go through a condition or error branch are warnings.
+    pub fn validate_workflow(workflow: &Workflow) -> Vec<ValidationIssue> {
+        let mut issues = Vec::new();
+        let mut ids: HashSet<&str> = HashSet::new();
+
+        if workflow.steps.is_empty() {
+            issues.push(ValidationIssue {
+                severity: IssueSeverity::Error,
+                step_id: None,
+                message: "Workflow has no steps".to_string(),
+            });
+            return issues;
+        }
+
+        for step in &workflow.steps {
+            if !ids.insert(step.id.as_str()) {
+                issues.push(ValidationIssue {
+                    severity: IssueSeverity::Error,
+                    step_id: Some(step.id.clone()),
+                    message: format!("Duplicate step id '{}'", step.id),
+                });
+            }
+        }
+
+        if !ids.contains(workflow.start_step.as_str()) {
+            issues.push(ValidationIssue {
+                severity: IssueSeverity::Error,
+                step_id: None,
+                message: format!("Start step '{}' does not exist", workflow.start_step),
+            });
+        }
+
+        for step in &workflow.steps {
+            for (kind, target) in Self::step_edges(step) {
+                if !ids.contains(target) {
+                    issues.push(ValidationIssue {
+                        severity: IssueSeverity::Error,
+                        step_id: Some(step.id.clone()),
+                        message: format!("{} '{}' does not exist", kind, target),
+                    });
+                }
+            }
+        }
+
+        // Reachability from the start step
+        let mut reachable: HashSet<&str> = HashSet::new();
+        let mut stack = vec![workflow.start_step.as_str()];
+        while let Some(id) = stack.pop() {
+            if !reachable.insert(id) {
+                continue;
+            }
+            if let Some(step) = workflow.steps.iter().find(|s| s.id == id) {
+                stack.extend(Self::step_edges(step).into_iter().map(|(_, target)| target));
+            }
+        }
+        for step in &workflow.steps {
+            if !reachable.contains(step.id.as_str()) {
+                issues.push(ValidationIssue {
+                    severity: IssueSeverity::Warning,
+                    step_id: Some(step.id.clone()),
+                    message: format!("Step '{}' is unreachable from '{}'", step.id, workflow.start_step),
+                });
+            }
+        }
+
+        for (cycle, unconditional) in Self::find_cycles(workflow) {
+            issues.push(ValidationIssue {
+                severity: if unconditional { IssueSeverity::Error } else { IssueSeverity::Warning },
+                step_id: cycle.first().cloned(),
+                message: format!("Cycle detected: {}", cycle.join(" -> ")),
+            });
+        }
+
+        issues
+    }
+
+    fn step_edges(step: &WorkflowStep) -> Vec<(&'static str, &str)> {
+        let mut edges = Vec::new();
+        if let Some(next) = &step.next_step {
+            edges.push(("next_step", next.as_str()));
+        }
+        if let Some(error) = &step.error_step {
+            edges.push(("error_step", error.as_str()));
+        }
+        for condition in &step.conditions {
+            edges.push(("condition target", condition.next_step.as_str()));
+        }
+        edges.extend(Self::branch_targets(step).into_iter().map(|target| ("branch", target)));
+        edges
+    }
+
+    /// Steps run as sub-steps of a Parallel or Map step.
+    fn branch_targets(step: &WorkflowStep) -> Vec<&str> {
+        match step.step_type {
+            StepType::Parallel => step
+                .params
+                .get("branches")
+                .and_then(|v| v.as_array())
+                .map(|branches| branches.iter().filter_map(|b| b.as_str()).collect())
+                .unwrap_or_default(),
+            StepType::Map => step.params.get("step").and_then(|v| v.as_str()).into_iter().collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Returns each cycle as a list of step ids, plus whether every link in it
+    /// is an unconditional `next_step`.
+    fn find_cycles(workflow: &Workflow) -> Vec<(Vec<String>, bool)> {
+        // Iterative DFS; each back edge yields one cycle.
+        let mut cycles = Vec::new();
+        let mut finished: HashSet<&str> = HashSet::new();
+
+        for root in &workflow.steps {
+            if finished.contains(root.id.as_str()) {
+                continue;
+            }
+
+            // (step id, kind of the edge that led to it)
+            let mut path: Vec<(&str, &str)> = Vec::new();
+            let mut stack: Vec<(&str, &str, usize)> = vec![(root.id.as_str(), "next_step", 0)];
+
+            while let Some((id, via, edge_index)) = stack.pop() {
+                if edge_index == 0 {
+                    path.push((id, via));
+                }
+                // Branches return to their parent step, so they don't form loops
+                let edges: Vec<_> = workflow
+                    .steps
+                    .iter()
+                    .find(|s| s.id == id)
+                    .map(Self::step_edges)
+                    .unwrap_or_default()
+                    .into_iter()
+                    .filter(|(kind, _)| *kind != "branch")
+                    .collect();
+
+                if let Some((kind, target)) = edges.get(edge_index) {
+                    stack.push((id, via, edge_index + 1));
+                    if let Some(pos) = path.iter().position(|(p, _)| p == target) {
+                        let mut cycle: Vec<String> = path[pos..].iter().map(|(s, _)| s.to_string()).collect();
+                        cycle.push(target.to_string());
+                        let unconditional = *kind == "next_step"
+                            && path[pos + 1..].iter().all(|(_, k)| *k == "next_step");
+                        cycles.push((cycle, unconditional));
+                    } else if !finished.contains(target) && workflow.steps.iter().any(|s| s.id == *target) {
+                        stack.push((target, kind, 0));
+                    }
+                } else {
+                    finished.insert(id);
+                    path.pop();
+                }
+            }
+        }
+
+        cycles
     }
 
     pub fn save_workflow_to_file(&self, workflow_id: &str, file_path: &str) -> Result<()> {
@@ -125,6 +534,7 @@ impl WorkflowManager {
             .clone();
         
         let mut context = WorkflowContext {
+            run_id: uuid::Uuid::new_v4().simple().to_string(),
             variables: workflow.variables.clone(),
             current_step: workflow.start_step.clone(),
             history: Vec::new(),
@@ -135,7 +545,55 @@ impl WorkflowManager {
         for (key, value) in initial_variables {
             context.variables.insert(key, value);
         }
-        
+
+        let started_at = chrono::Utc::now();
+        self.run_context(&workflow, &mut context, started_at, api_client).await?;
+        Ok(context)
+    }
+
+    /// Continues a persisted run from the step it stopped at, keeping the
+    /// variables and history of the steps that already ran.
+    pub async fn resume_workflow(&mut self, run_id: &str, api_client: &ApiClient) -> Result<WorkflowContext> {
+        let run = self.load_run(run_id)?;
+        if run.status == RunStatus::Completed {
+            return Err(EchomindError::Other(format!("Run {} already completed", run.run_id)));
+        }
+
+        // Errors of the previous attempt stay visible in the history; the
+        // resumed run is judged only on what happens from here on
+        let mut context = run.context;
+        context.errors.clear();
+        self.workflows.insert(run.workflow.id.clone(), run.workflow.clone());
+        self.run_context(&run.workflow, &mut context, run.started_at, api_client).await?;
+        Ok(context)
+    }
+
+    async fn run_context(
+        &self,
+        workflow: &Workflow,
+        context: &mut WorkflowContext,
+        started_at: chrono::DateTime<chrono::Utc>,
+        api_client: &ApiClient,
+    ) -> Result<()> {
+        self.save_run(workflow, context, RunStatus::Running, started_at)?;
+
+        let outcome = self.drive(workflow, context, started_at, api_client).await;
+        let status = if outcome.is_ok() && context.current_step.is_empty() {
+            RunStatus::Completed
+        } else {
+            RunStatus::Failed
+        };
+        self.save_run(workflow, context, status, started_at)?;
+        outcome
+    }
+
+    async fn drive(
+        &self,
+        workflow: &Workflow,
+        context: &mut WorkflowContext,
+        started_at: chrono::DateTime<chrono::Utc>,
+        api_client: &ApiClient,
+    ) -> Result<()> {
         let max_iterations = 100; // Prevent infinite loops
         let mut iteration = 0;
         
@@ -143,7 +601,7 @@ impl WorkflowManager {
             iteration += 1;
             
             if let Some(step) = workflow.steps.iter().find(|s| s.id == context.current_step) {
-                let result = self.execute_step(step, &mut context, api_client).await?;
+                let result = self.run_step_with_policy(step, &workflow.steps, context, api_client).await?;
                 context.history.push(result.clone());
                 
                 if !result.success {
@@ -154,37 +612,225 @@ impl WorkflowManager {
                     }
                 } else {
                     // Check conditions and determine next step
-                    let next_step = self.evaluate_conditions(&step.conditions, &context)?;
+                    let next_step = self.evaluate_conditions(&step.conditions, context)?;
                     context.current_step = next_step.or(step.next_step.clone()).unwrap_or_default();
                 }
+                self.save_run(workflow, context, RunStatus::Running, started_at)?;
             } else {
                 return Err(EchomindError::Other(format!("Step {} not found", context.current_step)));
             }
         }
         
-        if iteration >= max_iterations {
+        if !context.current_step.is_empty() && iteration >= max_iterations {
             return Err(EchomindError::Other("Workflow execution exceeded maximum iterations".to_string()));
         }
         
-        Ok(context)
+        Ok(())
+    }
+
+    fn save_run(
+        &self,
+        workflow: &Workflow,
+        context: &WorkflowContext,
+        status: RunStatus,
+        started_at: chrono::DateTime<chrono::Utc>,
+    ) -> Result<()> {
+        let Some(dir) = &self.runs_dir else {
+            return Ok(());
+        };
+        fs::create_dir_all(dir)
+            .map_err(|e| EchomindError::FileError(format!("Failed to create runs directory: {}", e)))?;
+
+        let run = WorkflowRun {
+            run_id: context.run_id.clone(),
+            workflow: workflow.clone(),
+            status,
+            started_at,
+            updated_at: chrono::Utc::now(),
+            context: context.clone(),
+        };
+        let json = serde_json::to_string_pretty(&run)
+            .map_err(|e| EchomindError::ParseError(format!("Failed to serialize workflow run: {}", e)))?;
+
+        // Write then rename so a crash mid-write never leaves a truncated run
+        let path = dir.join(format!("{}.json", context.run_id));
+        let tmp = dir.join(format!("{}.json.tmp", context.run_id));
+        fs::write(&tmp, json)
+            .and_then(|_| fs::rename(&tmp, &path))
+            .map_err(|e| EchomindError::FileError(format!("Failed to write workflow run: {}", e)))
+    }
+
+    /// Loads a persisted run by id or unique id prefix.
+    pub fn load_run(&self, run_id: &str) -> Result<WorkflowRun> {
+        let matches: Vec<WorkflowRun> = self
+            .list_runs()?
+            .into_iter()
+            .filter(|run| run.run_id.starts_with(run_id))
+            .collect();
+
+        match matches.len() {
+            0 => Err(EchomindError::Other(format!("Workflow run {} not found", run_id))),
+            1 => Ok(matches.into_iter().next().unwrap()),
+            n => Err(EchomindError::Other(format!("Run id prefix {} is ambiguous ({} runs)", run_id, n))),
+        }
+    }
+
+    /// All persisted runs, most recent first.
+    pub fn list_runs(&self) -> Result<Vec<WorkflowRun>> {
+        let Some(dir) = &self.runs_dir else {
+            return Ok(Vec::new());
+        };
+        if !dir.exists() {
+            return Ok(Vec::new());
+        }
+
+        let entries = fs::read_dir(dir)
+            .map_err(|e| EchomindError::FileError(format!("Failed to read runs directory: {}", e)))?;
+        let mut runs: Vec<WorkflowRun> = entries
+            .flatten()
+            .filter(|entry| entry.path().extension().and_then(|e| e.to_str()) == Some("json"))
+            .filter_map(|entry| fs::read_to_string(entry.path()).ok())
+            .filter_map(|contents| serde_json::from_str(&contents).ok())
+            .collect();
+
+        runs.sort_by_key(|run| std::cmp::Reverse(run.started_at));
+        Ok(runs)
+    }
+
+    /// Walks the path a run would take without executing anything: prompts,
+    /// commands, URLs and paths are rendered with the current variables and
+    /// conditions are evaluated against them (outputs of earlier steps are
+    /// not available yet, so their placeholders stay unresolved).
+    pub fn plan_workflow(
+        &self,
+        workflow_id: &str,
+        initial_variables: HashMap<String, serde_json::Value>,
+    ) -> Result<Vec<PlannedStep>> {
+        let workflow = self.workflows.get(workflow_id)
+            .ok_or_else(|| EchomindError::Other(format!("Workflow {} not found", workflow_id)))?;
+
+        let mut variables = workflow.variables.clone();
+        variables.extend(initial_variables);
+
+        let mut plan = Vec::new();
+        let mut visited = HashSet::new();
+        let mut current = workflow.start_step.clone();
+
+        while !current.is_empty() && visited.insert(current.clone()) {
+            let step = workflow
+                .steps
+                .iter()
+                .find(|s| s.id == current)
+                .ok_or_else(|| EchomindError::Other(format!("Step {} not found", current)))?;
+            plan.push(self.plan_step(step, &variables, 0));
+
+            for branch in Self::branch_targets(step) {
+                if let Some(sub_step) = workflow.steps.iter().find(|s| s.id == branch) {
+                    plan.push(self.plan_step(sub_step, &variables, 1));
+                }
+            }
+
+            let mut next = None;
+            for condition in &step.conditions {
+                if Self::evaluate_expr(&condition.when, &variables).unwrap_or(false) {
+                    next = Some(condition.next_step.clone());
+                    break;
+                }
+            }
+            current = next.or(step.next_step.clone()).unwrap_or_default();
+        }
+
+        Ok(plan)
+    }
+
+    fn plan_step(&self, step: &WorkflowStep, variables: &HashMap<String, serde_json::Value>, depth: usize) -> PlannedStep {
+        let template = match step.step_type {
+            StepType::AIRequest => step.prompt.clone(),
+            StepType::Shell => step.params.get("command").map(value_as_string),
+            StepType::HttpRequest => step.params.get("url").map(|url| {
+                let method = step.params.get("method").and_then(|v| v.as_str()).unwrap_or("GET");
+                format!("{} {}", method.to_uppercase(), value_as_string(url))
+            }),
+            StepType::ReadFile | StepType::WriteFile => step.params.get("path").map(value_as_string),
+            StepType::Output => Some(step.params.get("text").and_then(|v| v.as_str()).unwrap_or("{output}").to_string()),
+            _ => None,
+        };
+
+        PlannedStep {
+            step_id: step.id.clone(),
+            name: step.name.clone(),
+            step_type: step.step_type.clone(),
+            rendered: template.map(|t| self.replace_variables(&t, variables).unwrap_or_else(|e| format!("<{}>", e))),
+            depth,
+        }
+    }
+
+    /// Runs a step honouring its `timeout` (per attempt) and `retry_count`.
+    /// Only the final failure is recorded in the context's error list.
+    async fn run_step_with_policy(
+        &self,
+        step: &WorkflowStep,
+        steps: &[WorkflowStep],
+        context: &mut WorkflowContext,
+        api_client: &ApiClient,
+    ) -> Result<StepResult> {
+        let start_time = std::time::Instant::now();
+        let step_client = match &step.provider {
+            Some(provider) => Some(ApiClient::new(
+                Provider::from_string(provider)?,
+                self.api_key.clone(),
+                step.timeout.unwrap_or(self.default_timeout),
+            )?),
+            None => None,
+        };
+        let client = step_client.as_ref().unwrap_or(api_client);
+
+        let mut attempts = 0;
+        loop {
+            attempts += 1;
+            let attempt = match step.timeout {
+                Some(secs) => match timeout(Duration::from_secs(secs), self.execute_step(step, steps, context, client)).await {
+                    Ok(result) => result?,
+                    Err(_) => StepResult::failed(&step.id, format!("Timed out after {}s", secs)),
+                },
+                None => self.execute_step(step, steps, context, client).await?,
+            };
+
+            if attempt.success || attempts > step.retry_count {
+                if let Some(error) = &attempt.error {
+                    context.errors.push(format!("Step {} failed: {}", step.id, error));
+                }
+                context.variables.insert(step.id.clone(), Self::step_record(&attempt));
+                return Ok(StepResult {
+                    duration_ms: start_time.elapsed().as_millis() as u64,
+                    attempts,
+                    ..attempt
+                });
+            }
+
+            // Linear backoff between attempts
+            sleep(Duration::from_millis(500 * attempts as u64)).await;
+        }
     }
 
     async fn execute_step(
         &self,
         step: &WorkflowStep,
+        steps: &[WorkflowStep],
         context: &mut WorkflowContext,
         api_client: &ApiClient,
     ) -> Result<StepResult> {
         let start_time = std::time::Instant::now();
+        let succeeded = |output: String| StepResult::succeeded(&step.id, output);
         
         match &step.step_type {
             StepType::AIRequest => {
-                let prompt = self.replace_variables(step.prompt.as_ref().unwrap_or(&String::new()), &context.variables);
+                let prompt = self.replace_variables(step.prompt.as_ref().unwrap_or(&String::new()), &context.variables)?;
                 
                 let messages = vec![Message::text("user".to_string(), prompt)];
                 let request = ChatRequest {
                     messages,
-                    model: step.model.clone(),
+                    model: step.model.clone().or_else(|| self.default_model.clone()),
                     temperature: step.temperature,
                     max_tokens: step.max_tokens,
                     top_p: None,
@@ -192,91 +838,70 @@ impl WorkflowManager {
                     stream: None,
                 };
                 
-                match api_client.send_message(request).await {
+                let provider = api_client.provider().name();
+                let response = UsageMeter::send(self.usage.as_ref(), provider, &request, |warning| {
+                    if let Some(warning) = warning {
+                        eprintln!("Budget: {}", warning);
+                    }
+                    api_client.send_message(request.clone())
+                })
+                .await;
+
+                match response {
                     Ok(response) => {
                         context.variables.insert("last_response".to_string(), serde_json::Value::String(response.clone()));
                         context.variables.insert(format!("step_{}_output", step.id), serde_json::Value::String(response.clone()));
                         
-                        Ok(StepResult {
-                            step_id: step.id.clone(),
-                            success: true,
-                            output: Some(response),
-                            duration_ms: start_time.elapsed().as_millis() as u64,
-                            error: None,
-                        })
-                    }
-                    Err(e) => {
-                        context.errors.push(format!("Step {} failed: {}", step.id, e));
-                        Ok(StepResult {
-                            step_id: step.id.clone(),
-                            success: false,
-                            output: None,
-                            duration_ms: start_time.elapsed().as_millis() as u64,
-                            error: Some(e.to_string()),
-                        })
+                        Ok(succeeded(response))
                     }
+                    Err(e) => Ok(StepResult::failed(&step.id, e.to_string())),
                 }
             }
             StepType::Conditional => {
-                // Conditional steps are handled in the main execution loop
-                Ok(StepResult {
-                    step_id: step.id.clone(),
-                    success: true,
-                    output: Some("Conditional evaluation".to_string()),
-                    duration_ms: start_time.elapsed().as_millis() as u64,
-                    error: None,
-                })
+                // Conditional steps only branch; conditions are evaluated in the main loop
+                Ok(succeeded("Conditional evaluation".to_string()))
             }
             StepType::Delay => {
-                let delay_ms = context.variables.get("delay_ms")
-                    .and_then(|v| v.as_u64())
-                    .unwrap_or(1000);
+                let delay_ms = match step.params.get("delay_ms") {
+                    Some(serde_json::Value::String(template)) => self
+                        .replace_variables(template, &context.variables)?
+                        .trim()
+                        .parse::<u64>()
+                        .map_err(|_| EchomindError::Other(format!("Step {}: delay_ms is not a number", step.id)))?,
+                    Some(value) => value
+                        .as_u64()
+                        .ok_or_else(|| EchomindError::Other(format!("Step {}: delay_ms is not a number", step.id)))?,
+                    None => 1000,
+                };
                 sleep(Duration::from_millis(delay_ms)).await;
                 
-                Ok(StepResult {
-                    step_id: step.id.clone(),
-                    success: true,
-                    output: Some(format!("Delayed for {}ms", delay_ms)),
-                    duration_ms: start_time.elapsed().as_millis() as u64,
-                    error: None,
-                })
+                Ok(succeeded(format!("Delayed for {}ms", delay_ms)))
             }
             StepType::Transform => {
-                // Transform data based on transformation rules
-                let input = context.variables.get("input")
-                    .and_then(|v| v.as_str())
-                    .unwrap_or("");
+                // Transform `params.input` (default `{input}`) into `params.output_variable` (default `output`)
+                let template = step.params.get("input").and_then(|v| v.as_str()).unwrap_or("{input}");
+                let input = self.replace_variables(template, &context.variables)?;
+                let transform = step.params.get("transform").and_then(|v| v.as_str()).unwrap_or("uppercase");
+                let output_variable = step.params.get("output_variable").and_then(|v| v.as_str()).unwrap_or("output");
                 
-                let transformed = self.apply_transformation(input, &context.variables);
-                context.variables.insert("output".to_string(), serde_json::Value::String(transformed.clone()));
+                let transformed = self.apply_transformation(&input, transform)?;
+                context.variables.insert(output_variable.to_string(), serde_json::Value::String(transformed.clone()));
                 
-                Ok(StepResult {
-                    step_id: step.id.clone(),
-                    success: true,
-                    output: Some(transformed),
-                    duration_ms: start_time.elapsed().as_millis() as u64,
-                    error: None,
-                })
+                Ok(succeeded(transformed))
             }
             StepType::Output => {
-                let output = context.variables.get("output")
-                    .and_then(|v| v.as_str())
-                    .unwrap_or("");
+                let template = step.params.get("text").and_then(|v| v.as_str()).unwrap_or("{output}");
+                let output = self.replace_variables(template, &context.variables)?;
                 
                 println!("{}", output);
                 
-                Ok(StepResult {
-                    step_id: step.id.clone(),
-                    success: true,
-                    output: Some(output.to_string()),
-                    duration_ms: start_time.elapsed().as_millis() as u64,
-                    error: None,
-                })
+                Ok(succeeded(output))
             }
             StepType::Input => {
-                // For now, just read from stdin
                 use std::io::{self, Write};
-                print!("Input required for step {}: ", step.name);
+                let label = step.params.get("prompt").and_then(|v| v.as_str()).unwrap_or(&step.name);
+                let variable = step.params.get("variable").and_then(|v| v.as_str()).unwrap_or("input");
+                print!("Input required for step {}: ", label);
                 io::stdout().flush().unwrap();
                 
                 let mut input = String::new();
@@ -284,16 +909,412 @@ impl WorkflowManager {
                     .map_err(|e| EchomindError::Other(format!("Failed to read input: {}", e)))?;
                 
                 let input = input.trim().to_string();
-                context.variables.insert("input".to_string(), serde_json::Value::String(input.clone()));
+                context.variables.insert(variable.to_string(), serde_json::Value::String(input.clone()));
                 
-                Ok(StepResult {
-                    step_id: step.id.clone(),
-                    success: true,
-                    output: Some(input),
-                    duration_ms: start_time.elapsed().as_millis() as u64,
-                    error: None,
-                })
+                Ok(succeeded(input))
+            }
+            StepType::Parallel | StepType::Map => {
+                let mut result = self.execute_fan_out(step, steps, context, api_client).await?;
+                result.duration_ms = start_time.elapsed().as_millis() as u64;
+                Ok(result)
             }
+            StepType::Aggregate => {
+                let sources = step.params.get("sources").and_then(|v| v.as_array()).ok_or_else(|| {
+                    EchomindError::Other(format!("Step {}: Aggregate requires params.sources", step.id))
+                })?;
+                let flatten = step.params.get("flatten").and_then(|v| v.as_bool()).unwrap_or(false);
+
+                let mut collected = Vec::new();
+                for source in sources.iter().filter_map(|s| s.as_str()) {
+                    match resolve_path(&context.variables, source) {
+                        Some(serde_json::Value::Array(items)) if flatten => collected.extend(items),
+                        Some(value) => collected.push(value),
+                        None => collected.push(serde_json::Value::Null),
+                    }
+                }
+
+                let aggregated = serde_json::Value::Array(collected);
+                let output = aggregated.to_string();
+                context.variables.insert(Self::output_variable(step), aggregated.clone());
+                Ok(succeeded(output).with_data(serde_json::json!({ "output": aggregated })))
+            }
+            StepType::ReadFile => {
+                let path = self.required_param(step, "path", &context.variables)?;
+                let contents = match fs::read_to_string(&path) {
+                    Ok(contents) => contents,
+                    Err(e) => return Ok(StepResult::failed(&step.id, format!("Failed to read {}: {}", path, e))),
+                };
+
+                let data = if step.params.get("format").and_then(|v| v.as_str()) == Some("json") {
+                    match serde_json::from_str::<serde_json::Value>(&contents) {
+                        Ok(parsed) => serde_json::json!({ "output": parsed, "path": path }),
+                        Err(e) => return Ok(StepResult::failed(&step.id, format!("{} is not valid JSON: {}", path, e))),
+                    }
+                } else {
+                    serde_json::json!({ "path": path })
+                };
+
+                context.variables.insert(format!("step_{}_output", step.id), serde_json::Value::String(contents.clone()));
+                Ok(succeeded(contents).with_data(data))
+            }
+            StepType::WriteFile => {
+                let path = self.required_param(step, "path", &context.variables)?;
+                let template = step.params.get("content").and_then(|v| v.as_str()).unwrap_or("{last_response}");
+                let content = self.replace_variables(template, &context.variables)?;
+                let append = step.params.get("append").and_then(|v| v.as_bool()).unwrap_or(false);
+
+                if let Some(parent) = Path::new(&path).parent().filter(|p| !p.as_os_str().is_empty()) {
+                    fs::create_dir_all(parent)
+                        .map_err(|e| EchomindError::FileError(format!("Failed to create {}: {}", parent.display(), e)))?;
+                }
+                let written = if append {
+                    use std::io::Write;
+                    fs::OpenOptions::new()
+                        .create(true)
+                        .append(true)
+                        .open(&path)
+                        .and_then(|mut f| f.write_all(content.as_bytes()))
+                } else {
+                    fs::write(&path, &content)
+                };
+                if let Err(e) = written {
+                    return Ok(StepResult::failed(&step.id, format!("Failed to write {}: {}", path, e)));
+                }
+
+                Ok(succeeded(path.clone()).with_data(serde_json::json!({ "path": path, "bytes": content.len() })))
+            }
+            StepType::Shell => self.execute_shell(step, context).await,
+            StepType::HttpRequest => self.execute_http(step, context).await,
+            StepType::JsonExtract => {
+                let source = step.params.get("source").and_then(|v| v.as_str()).unwrap_or("last_response");
+                let value = resolve_path(&context.variables, source)
+                    .ok_or_else(|| EchomindError::Other(format!("Step {}: source '{}' not found", step.id, source)))?;
+                let document = match value {
+                    serde_json::Value::String(text) => match extract_json(&text) {
+                        Some(parsed) => parsed,
+                        None => return Ok(StepResult::failed(&step.id, format!("No JSON found in '{}'", source))),
+                    },
+                    other => other,
+                };
+
+                let extracted = match step.params.get("path").and_then(|v| v.as_str()) {
+                    Some(path) => match resolve_in_value(document, path) {
+                        Some(value) => value,
+                        None => return Ok(StepResult::failed(&step.id, format!("Path '{}' not found in {}", path, source))),
+                    },
+                    None => document,
+                };
+
+                let output = value_as_string(&extracted);
+                context.variables.insert(Self::output_variable(step), extracted.clone());
+                Ok(succeeded(output).with_data(serde_json::json!({ "output": extracted })))
+            }
+        }
+    }
+
+    fn required_param(&self, step: &WorkflowStep, name: &str, variables: &HashMap<String, serde_json::Value>) -> Result<String> {
+        let template = step
+            .params
+            .get(name)
+            .and_then(|v| v.as_str())
+            .ok_or_else(|| EchomindError::Other(format!("Step {}: params.{} is required", step.id, name)))?;
+        self.replace_variables(template, variables)
+    }
+
+    /// Runs `params.command` (a string, split like a shell would, or a list of
+    /// arguments) directly, without a shell, if the program is allowlisted.
+    /// stdout, stderr and the exit code are captured; a non-zero exit fails the
+    /// step unless `params.allow_failure` is set.
+    async fn execute_shell(&self, step: &WorkflowStep, context: &mut WorkflowContext) -> Result<StepResult> {
+        let argv: Vec<String> = match step.params.get("command") {
+            Some(serde_json::Value::String(command)) => split_command(command)
+                .into_iter()
+                .map(|arg| self.replace_variables(&arg, &context.variables))
+                .collect::<Result<_>>()?,
+            Some(serde_json::Value::Array(args)) => args
+                .iter()
+                .map(|arg| self.replace_variables(&value_as_string(arg), &context.variables))
+                .collect::<Result<_>>()?,
+            _ => return Err(EchomindError::Other(format!("Step {}: params.command is required", step.id))),
+        };
+        let (program, args) = argv
+            .split_first()
+            .ok_or_else(|| EchomindError::Other(format!("Step {}: params.command is empty", step.id)))?;
+
+        if !self.shell_allowlist.iter().any(|allowed| allowed == program) {
+            return Ok(StepResult::failed(
+                &step.id,
+                format!("'{}' is not in the shell allowlist (workflow.shell_allowlist in config)", program),
+            ));
+        }
+
+        let mut command = tokio::process::Command::new(program);
+        command
+            .args(args)
+            .stdin(std::process::Stdio::piped())
+            .stdout(std::process::Stdio::piped())
+            .stderr(std::process::Stdio::piped())
+            .kill_on_drop(true);
+        if let Some(cwd) = step.params.get("cwd").and_then(|v| v.as_str()) {
+            command.current_dir(self.replace_variables(cwd, &context.variables)?);
+        }
+
+        let mut child = match command.spawn() {
+            Ok(child) => child,
+            Err(e) => return Ok(StepResult::failed(&step.id, format!("Failed to run '{}': {}", program, e))),
+        };
+        let stdin_text = step
+            .params
+            .get("stdin")
+            .and_then(|v| v.as_str())
+            .map(|t| self.replace_variables(t, &context.variables))
+            .transpose()?;
+        if let Some(mut stdin) = child.stdin.take() {
+            if let Some(text) = stdin_text {
+                use tokio::io::AsyncWriteExt;
+                stdin.write_all(text.as_bytes()).await?;
+            }
+        }
+
+        let output = child.wait_with_output().await?;
+        let stdout = String::from_utf8_lossy(&output.stdout).to_string();
+        let stderr = String::from_utf8_lossy(&output.stderr).to_string();
+        let exit_code = output.status.code();
+        let data = serde_json::json!({ "stdout": stdout, "stderr": stderr, "exit_code": exit_code });
+        context.variables.insert(format!("step_{}_output", step.id), serde_json::Value::String(stdout.clone()));
+
+        let allow_failure = step.params.get("allow_failure").and_then(|v| v.as_bool()).unwrap_or(false);
+        if output.status.success() || allow_failure {
+            Ok(StepResult::succeeded(&step.id, stdout).with_data(data))
+        } else {
+            let reason = match exit_code {
+                Some(code) => format!("'{}' exited with status {}: {}", program, code, stderr.trim()),
+                None => format!("'{}' was terminated by a signal", program),
+            };
+            Ok(StepResult::failed(&step.id, reason).with_data(data))
+        }
+    }
+
+    /// Calls an HTTP endpoint. `params.body` may be a string template or a JSON
+    /// value (string leaves are interpolated); non-2xx statuses fail the step
+    /// unless `params.allow_failure` is set.
+    async fn execute_http(&self, step: &WorkflowStep, context: &mut WorkflowContext) -> Result<StepResult> {
+        let url = self.required_param(step, "url", &context.variables)?;
+        let method = step.params.get("method").and_then(|v| v.as_str()).unwrap_or("GET").to_uppercase();
+        let method = reqwest::Method::from_bytes(method.as_bytes())
+            .map_err(|_| EchomindError::Other(format!("Step {}: invalid HTTP method '{}'", step.id, method)))?;
+
+        let client = reqwest::Client::builder()
+            .timeout(Duration::from_secs(step.timeout.unwrap_or(self.default_timeout)))
+            .build()
+            .map_err(|e| EchomindError::NetworkError(e.to_string()))?;
+        let mut request = client.request(method, &url);
+
+        if let Some(serde_json::Value::Object(headers)) = step.params.get("headers") {
+            for (name, value) in headers {
+                request = request.header(name, self.replace_variables(&value_as_string(value), &context.variables)?);
+            }
+        }
+        match step.params.get("body") {
+            Some(serde_json::Value::String(body)) => {
+                request = request.body(self.replace_variables(body, &context.variables)?);
+            }
+            Some(body) => {
+                request = request.json(&self.interpolate_value(body, &context.variables)?);
+            }
+            None => {}
+        }
+
+        let response = match request.send().await {
+            Ok(response) => response,
+            Err(e) => return Ok(StepResult::failed(&step.id, format!("Request to {} failed: {}", url, e))),
+        };
+        let status = response.status();
+        let body = response.text().await.unwrap_or_default();
+        let data = serde_json::json!({ "status": status.as_u16(), "body": body });
+        context.variables.insert(format!("step_{}_output", step.id), serde_json::Value::String(body.clone()));
+
+        let allow_failure = step.params.get("allow_failure").and_then(|v| v.as_bool()).unwrap_or(false);
+        if status.is_success() || allow_failure {
+            Ok(StepResult::succeeded(&step.id, body).with_data(data))
+        } else {
+            Ok(StepResult::failed(&step.id, format!("{} returned {}", url, status)).with_data(data))
+        }
+    }
+
+    fn interpolate_value(&self, value: &serde_json::Value, variables: &HashMap<String, serde_json::Value>) -> Result<serde_json::Value> {
+        Ok(match value {
+            serde_json::Value::String(s) => serde_json::Value::String(self.replace_variables(s, variables)?),
+            serde_json::Value::Array(items) => serde_json::Value::Array(
+                items
+                    .iter()
+                    .map(|v| self.interpolate_value(v, variables))
+                    .collect::<Result<_>>()?,
+            ),
+            serde_json::Value::Object(map) => serde_json::Value::Object(
+                map.iter()
+                    .map(|(k, v)| Ok((k.clone(), self.interpolate_value(v, variables)?)))
+                    .collect::<Result<_>>()?,
+            ),
+            other => other.clone(),
+        })
+    }
+
+    /// The `<step id>` variable: output, status and any structured data of the
+    /// step's final attempt, so later steps can use `{step_a.output.items[0]}`.
+    fn step_record(result: &StepResult) -> serde_json::Value {
+        let mut record = serde_json::Map::new();
+        record.insert(
+            "output".to_string(),
+            result.output.clone().map(serde_json::Value::String).unwrap_or(serde_json::Value::Null),
+        );
+        record.insert("success".to_string(), serde_json::Value::Bool(result.success));
+        if let Some(error) = &result.error {
+            record.insert("error".to_string(), serde_json::Value::String(error.clone()));
+        }
+        if let Some(serde_json::Value::Object(data)) = &result.data {
+            record.extend(data.clone());
+        }
+        serde_json::Value::Object(record)
+    }
+
+    /// Where Parallel, Map and Aggregate steps store their JSON array.
+    fn output_variable(step: &WorkflowStep) -> String {
+        step.params
+            .get("output_variable")
+            .and_then(|v| v.as_str())
+            .map(|s| s.to_string())
+            .unwrap_or_else(|| format!("step_{}_output", step.id))
+    }
+
+    /// Fan-out for Parallel and Map steps. Each branch/item runs against its own
+    /// copy of the context with at most `params.concurrency` (default 4) in
+    /// flight. Outputs are collected, in order, into a JSON array. With
+    /// `params.on_error: continue` failed items become `null` and are listed in
+    /// `step_<id>_errors`; the default `fail_fast` stops at the first failure.
+    async fn execute_fan_out(
+        &self,
+        step: &WorkflowStep,
+        steps: &[WorkflowStep],
+        context: &mut WorkflowContext,
+        api_client: &ApiClient,
+    ) -> Result<StepResult> {
+        let find_step = |id: &str| {
+            steps
+                .iter()
+                .find(|s| s.id == id)
+                .ok_or_else(|| EchomindError::Other(format!("Step {}: sub-step '{}' not found", step.id, id)))
+        };
+
+        // Build one (sub-step, context) job per branch or item
+        let mut jobs: Vec<(&WorkflowStep, WorkflowContext)> = Vec::new();
+        if let StepType::Parallel = step.step_type {
+            for id in Self::branch_targets(step) {
+                jobs.push((find_step(id)?, context.clone()));
+            }
+        } else {
+            let sub_step_id = step.params.get("step").and_then(|v| v.as_str()).ok_or_else(|| {
+                EchomindError::Other(format!("Step {}: Map requires params.step", step.id))
+            })?;
+            let sub_step = find_step(sub_step_id)?;
+            let item_variable = step.params.get("item_variable").and_then(|v| v.as_str()).unwrap_or("item");
+
+            for (index, item) in self.map_items(step, context)?.into_iter().enumerate() {
+                let mut item_context = context.clone();
+                item_context.variables.insert(item_variable.to_string(), item);
+                item_context.variables.insert("index".to_string(), serde_json::json!(index));
+                jobs.push((sub_step, item_context));
+            }
+        }
+
+        let concurrency = step
+            .params
+            .get("concurrency")
+            .and_then(|v| v.as_u64())
+            .unwrap_or(4)
+            .max(1) as usize;
+        let fail_fast = step.params.get("on_error").and_then(|v| v.as_str()) != Some("continue");
+
+        let mut results = stream::iter(jobs.into_iter().map(|(sub_step, mut sub_context)| async move {
+            let result = Box::pin(self.run_step_with_policy(sub_step, steps, &mut sub_context, api_client)).await;
+            (sub_step, result)
+        }))
+        .buffered(concurrency);
+
+        let mut outputs = Vec::new();
+        let mut item_errors = Vec::new();
+        let mut children = Vec::new();
+        while let Some((sub_step, result)) = results.next().await {
+            // A hard error in one branch/item is handled like any other failure
+            let result = result.unwrap_or_else(|e| StepResult::failed(&sub_step.id, e.to_string()));
+            let index = children.len();
+
+            if result.success {
+                let output = result.output.clone().unwrap_or_default();
+                if let StepType::Parallel = step.step_type {
+                    context
+                        .variables
+                        .insert(format!("step_{}_output", sub_step.id), serde_json::Value::String(output.clone()));
+                }
+                outputs.push(serde_json::Value::String(output));
+            } else {
+                let error = result.error.clone().unwrap_or_else(|| "failed".to_string());
+                item_errors.push(serde_json::json!({ "index": index, "step": sub_step.id, "error": error }));
+                outputs.push(serde_json::Value::Null);
+
+                if fail_fast {
+                    children.push(result);
+                    return Ok(StepResult {
+                        children,
+                        ..StepResult::failed(&step.id, format!("{} #{} failed: {}", sub_step.id, index, error))
+                    });
+                }
+            }
+            children.push(result);
+        }
+
+        let failed = item_errors.len();
+        let total = children.len();
+        let outputs = serde_json::Value::Array(outputs);
+        let item_errors = serde_json::Value::Array(item_errors);
+        context.variables.insert(Self::output_variable(step), outputs.clone());
+        context.variables.insert(format!("step_{}_errors", step.id), item_errors.clone());
+
+        Ok(StepResult {
+            children,
+            ..StepResult::succeeded(&step.id, format!("{} of {} succeeded", total - failed, total))
+                .with_data(serde_json::json!({ "output": outputs, "errors": item_errors }))
+        })
+    }
+
+    /// Items for a Map step: `params.items` is either a literal array or a
+    /// variable path resolving to one (a JSON string is parsed, other strings
+    /// are split into lines); `params.items_file` reads CSV rows as objects,
+    /// a JSON array, or one item per line.
+    fn map_items(&self, step: &WorkflowStep, context: &WorkflowContext) -> Result<Vec<serde_json::Value>> {
+        if let Some(path) = step.params.get("items_file").and_then(|v| v.as_str()) {
+            let path = self.replace_variables(path, &context.variables)?;
+            return read_items_file(&path);
+        }
+
+        let items = match step.params.get("items") {
+            Some(serde_json::Value::Array(items)) => return Ok(items.clone()),
+            Some(serde_json::Value::String(path)) => resolve_path(&context.variables, path)
+                .ok_or_else(|| EchomindError::Other(format!("Step {}: items variable '{}' not found", step.id, path)))?,
+            _ => return Err(EchomindError::Other(format!("Step {}: Map requires params.items or params.items_file", step.id))),
+        };
+
+        match items {
+            serde_json::Value::Array(items) => Ok(items),
+            serde_json::Value::String(text) => match serde_json::from_str::<serde_json::Value>(text.trim()) {
+                Ok(serde_json::Value::Array(items)) => Ok(items),
+                _ => Ok(text
+                    .lines()
+                    .filter(|line| !line.trim().is_empty())
+                    .map(|line| serde_json::Value::String(line.to_string()))
+                    .collect()),
+            },
+            other => Err(EchomindError::Other(format!("Step {}: items must be a list, got {}", step.id, other))),
         }
     }
 
@@ -303,70 +1324,97 @@ impl WorkflowManager {
         context: &WorkflowContext,
     ) -> Result<Option<String>> {
         for condition in conditions {
-            let variable_value = context.variables.get(&condition.variable);
-            
-            let condition_met = match (&condition.operator, variable_value, &condition.value) {
-                (ConditionOperator::Equals, Some(var), val) => {
-                    serde_json::to_value(var).unwrap() == *val
-                }
-                (ConditionOperator::NotEquals, Some(var), val) => {
-                    serde_json::to_value(var).unwrap() != *val
-                }
-                (ConditionOperator::Contains, Some(serde_json::Value::String(var)), serde_json::Value::String(val)) => {
-                    var.contains(val)
-                }
-                (ConditionOperator::StartsWith, Some(serde_json::Value::String(var)), serde_json::Value::String(val)) => {
-                    var.starts_with(val)
-                }
-                (ConditionOperator::EndsWith, Some(serde_json::Value::String(var)), serde_json::Value::String(val)) => {
-                    var.ends_with(val)
-                }
-                (ConditionOperator::GreaterThan, Some(_var), _val) => {
-                    // This would need more sophisticated comparison
-                    false
-                }
-                (ConditionOperator::LessThan, Some(_var), _val) => {
-                    // This would need more sophisticated comparison
-                    false
-                }
-                _ => false,
-            };
-            
-            if condition_met {
-                // Return the next step ID if condition is met
-                // For now, we'll use a simple approach where the condition value contains the next step
-                if let serde_json::Value::String(next_step) = &condition.value {
-                    return Ok(Some(next_step.clone()));
-                }
+            if Self::evaluate_expr(&condition.when, &context.variables)? {
+                return Ok(Some(condition.next_step.clone()));
             }
         }
         
         Ok(None)
     }
 
-    fn replace_variables(&self, template: &str, variables: &HashMap<String, serde_json::Value>) -> String {
-        let mut result = template.to_string();
-        
-        for (key, value) in variables {
-            if let Some(str_value) = value.as_str() {
-                result = result.replace(&format!("{{{}}}", key), str_value);
+    pub fn evaluate_expr(expr: &ConditionExpr, variables: &HashMap<String, serde_json::Value>) -> Result<bool> {
+        match expr {
+            ConditionExpr::All { all } => {
+                for e in all {
+                    if !Self::evaluate_expr(e, variables)? {
+                        return Ok(false);
+                    }
+                }
+                Ok(true)
+            }
+            ConditionExpr::Any { any } => {
+                for e in any {
+                    if Self::evaluate_expr(e, variables)? {
+                        return Ok(true);
+                    }
+                }
+                Ok(false)
+            }
+            ConditionExpr::Not { not } => Ok(!Self::evaluate_expr(not, variables)?),
+            ConditionExpr::Compare { variable, operator, value } => {
+                let actual = resolve_path(variables, variable);
+                Self::compare(operator, actual.as_ref(), value)
             }
         }
-        
-        result
     }
 
-    fn apply_transformation(&self, input: &str, variables: &HashMap<String, serde_json::Value>) -> String {
-        let transform_type = variables.get("transform_type")
-            .and_then(|v| v.as_str())
-            .unwrap_or("uppercase");
-        
-        match transform_type {
-            "uppercase" => input.to_uppercase(),
-            "lowercase" => input.to_lowercase(),
-            "reverse" => input.chars().rev().collect(),
-            "trim" => input.trim().to_string(),
-            _ => input.to_string(),
+    fn compare(operator: &ConditionOperator, actual: Option<&serde_json::Value>, expected: &serde_json::Value) -> Result<bool> {
+        let Some(actual) = actual else {
+            // Missing variables only satisfy NotEquals (and fail Exists)
+            return Ok(matches!(operator, ConditionOperator::NotEquals));
+        };
+
+        let met = match operator {
+            ConditionOperator::Exists => !actual.is_null(),
+            ConditionOperator::Equals => values_equal(actual, expected),
+            ConditionOperator::NotEquals => !values_equal(actual, expected),
+            ConditionOperator::Contains => match actual {
+                serde_json::Value::Array(items) => items.iter().any(|item| values_equal(item, expected)),
+                _ => value_as_string(actual).contains(&value_as_string(expected)),
+            },
+            ConditionOperator::StartsWith => value_as_string(actual).starts_with(&value_as_string(expected)),
+            ConditionOperator::EndsWith => value_as_string(actual).ends_with(&value_as_string(expected)),
+            ConditionOperator::Matches => {
+                let pattern = value_as_string(expected);
+                let re = regex::Regex::new(&pattern)
+                    .map_err(|e| EchomindError::Other(format!("Invalid regex '{}': {}", pattern, e)))?;
+                re.is_match(&value_as_string(actual))
+            }
+            ConditionOperator::GreaterThan
+            | ConditionOperator::LessThan
+            | ConditionOperator::GreaterThanOrEqual
+            | ConditionOperator::LessThanOrEqual => {
+                let (Some(a), Some(b)) = (value_as_f64(actual), value_as_f64(expected)) else {
+                    return Ok(false);
+                };
+                match operator {
+                    ConditionOperator::GreaterThan => a > b,
+                    ConditionOperator::LessThan => a < b,
+                    ConditionOperator::GreaterThanOrEqual => a >= b,
+                    _ => a <= b,
+                }
+            }
+        };
+
+        Ok(met)
+    }
+
+    /// Renders workflow text with the shared template language: `{{ ... }}`
+    /// expressions, `{% if %}`/`{% for %}` blocks and the original `{name}`
+    /// / `{path.to[0].value}` placeholders (left untouched when unknown so
+    /// literal braces in prompts survive).
+    fn replace_variables(&self, template: &str, variables: &HashMap<String, serde_json::Value>) -> Result<String> {
+        templating::render(template, variables)
+    }
+
+    fn apply_transformation(&self, input: &str, transform: &str) -> Result<String> {
+        match transform {
+            "uppercase" => Ok(input.to_uppercase()),
+            "lowercase" => Ok(input.to_lowercase()),
+            "reverse" => Ok(input.chars().rev().collect()),
+            "trim" => Ok(input.trim().to_string()),
+            "identity" => Ok(input.to_string()),
+            other => Err(EchomindError::Other(format!("Unknown transform '{}'", other))),
         }
     }
 
@@ -394,4 +1442,235 @@ impl Default for WorkflowManager {
     fn default() -> Self {
         Self::new()
     }
-}
\ No newline at end of file
+}
+
+/// Parses `key=value` assignments from the command line. Values that parse as
+/// JSON (numbers, booleans, arrays, objects) keep their type; anything else is
+/// stored as a string.
+pub fn parse_variable_assignments(assignments: &[String]) -> Result<HashMap<String, serde_json::Value>> {
+    let mut variables = HashMap::new();
+
+    for assignment in assignments {
+        let (key, value) = assignment.split_once('=').ok_or_else(|| {
+            EchomindError::Other(format!("Invalid variable '{}'. Expected key=value", assignment))
+        })?;
+        let key = key.trim();
+        if key.is_empty() {
+            return Err(EchomindError::Other(format!("Invalid variable '{}'. Key is empty", assignment)));
+        }
+
+        let value = serde_json::from_str(value)
+            .unwrap_or_else(|_| serde_json::Value::String(value.to_string()));
+        variables.insert(key.to_string(), value);
+    }
+
+    Ok(variables)
+}
+
+/// Resolves a variable path such as `score`, `review.issues[0]` or
+/// `$.review['issues'][0].severity`. The first segment names a workflow
+/// variable; string values that hold JSON are parsed when descended into, so
+/// model output like `{"score": 7}` can be addressed directly.
+pub fn resolve_path(variables: &HashMap<String, serde_json::Value>, path: &str) -> Option<serde_json::Value> {
+    let path = path.trim();
+    let path = path.strip_prefix("$.").or_else(|| path.strip_prefix('$')).unwrap_or(path);
+    let segments = parse_path_segments(path)?;
+    let (first, rest) = segments.split_first()?;
+
+    let PathSegment::Key(name) = first else {
+        return None;
+    };
+    navigate(variables.get(name)?.clone(), rest)
+}
+
+/// Resolves a path such as `items[0].name` inside an already-loaded value.
+pub fn resolve_in_value(value: serde_json::Value, path: &str) -> Option<serde_json::Value> {
+    let path = path.trim();
+    let path = path.strip_prefix("$.").or_else(|| path.strip_prefix('$')).unwrap_or(path);
+    if path.is_empty() {
+        return Some(value);
+    }
+    navigate(value, &parse_path_segments(path)?)
+}
+
+fn navigate(mut current: serde_json::Value, segments: &[PathSegment]) -> Option<serde_json::Value> {
+    for segment in segments {
+        if let serde_json::Value::String(text) = &current {
+            current = extract_json(text)?;
+        }
+        current = match (segment, current) {
+            (PathSegment::Key(key), serde_json::Value::Object(mut map)) => map.remove(key)?,
+            (PathSegment::Index(i), serde_json::Value::Array(mut items)) if *i < items.len() => items.swap_remove(*i),
+            _ => return None,
+        };
+    }
+
+    Some(current)
+}
+
+enum PathSegment {
+    Key(String),
+    Index(usize),
+}
+
+fn parse_path_segments(path: &str) -> Option<Vec<PathSegment>> {
+    let mut segments = Vec::new();
+    let mut chars = path.chars().peekable();
+    let mut key = String::new();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '.' => {
+                if !key.is_empty() {
+                    segments.push(PathSegment::Key(std::mem::take(&mut key)));
+                }
+            }
+            '[' => {
+                if !key.is_empty() {
+                    segments.push(PathSegment::Key(std::mem::take(&mut key)));
+                }
+                let mut inner = String::new();
+                for c in chars.by_ref() {
+                    if c == ']' {
+                        break;
+                    }
+                    inner.push(c);
+                }
+                let inner = inner.trim();
+                if let Ok(index) = inner.parse::<usize>() {
+                    segments.push(PathSegment::Index(index));
+                } else {
+                    segments.push(PathSegment::Key(inner.trim_matches(|c| c == '\'' || c == '"').to_string()));
+                }
+            }
+            _ => key.push(c),
+        }
+    }
+    if !key.is_empty() {
+        segments.push(PathSegment::Key(key));
+    }
+
+    if segments.is_empty() {
+        None
+    } else {
+        Some(segments)
+    }
+}
+
+fn value_as_string(value: &serde_json::Value) -> String {
+    match value {
+        serde_json::Value::String(s) => s.clone(),
+        other => other.to_string(),
+    }
+}
+
+fn value_as_f64(value: &serde_json::Value) -> Option<f64> {
+    match value {
+        serde_json::Value::Number(n) => n.as_f64(),
+        serde_json::Value::String(s) => s.trim().parse().ok(),
+        serde_json::Value::Bool(b) => Some(if *b { 1.0 } else { 0.0 }),
+        _ => None,
+    }
+}
+
+// Numbers compare numerically and strings are trimmed, so model output "7\n"
+// equals 7 and "yes " equals "yes".
+fn values_equal(a: &serde_json::Value, b: &serde_json::Value) -> bool {
+    if a == b {
+        return true;
+    }
+    match (a, b) {
+        (serde_json::Value::String(_), _) | (_, serde_json::Value::String(_)) => {
+            if let (Some(x), Some(y)) = (value_as_f64(a), value_as_f64(b)) {
+                if !a.is_boolean() && !b.is_boolean() {
+                    return x == y;
+                }
+            }
+            value_as_string(a).trim() == value_as_string(b).trim()
+        }
+        (serde_json::Value::Number(_), serde_json::Value::Number(_)) => value_as_f64(a) == value_as_f64(b),
+        _ => false,
+    }
+}
+
+fn read_items_file(path: &str) -> Result<Vec<serde_json::Value>> {
+    let lower = path.to_lowercase();
+
+    if lower.ends_with(".csv") {
+        let mut reader = csv::Reader::from_path(path)
+            .map_err(|e| EchomindError::FileError(format!("Failed to read {}: {}", path, e)))?;
+        let headers = reader
+            .headers()
+            .map_err(|e| EchomindError::ParseError(format!("Failed to read CSV headers: {}", e)))?
+            .clone();
+
+        let mut rows = Vec::new();
+        for record in reader.records() {
+            let record = record.map_err(|e| EchomindError::ParseError(format!("Failed to read CSV row: {}", e)))?;
+            let row: serde_json::Map<String, serde_json::Value> = headers
+                .iter()
+                .zip(record.iter())
+                .map(|(h, v)| (h.to_string(), serde_json::Value::String(v.to_string())))
+                .collect();
+            rows.push(serde_json::Value::Object(row));
+        }
+        return Ok(rows);
+    }
+
+    let contents = fs::read_to_string(path)
+        .map_err(|e| EchomindError::FileError(format!("Failed to read {}: {}", path, e)))?;
+
+    if lower.ends_with(".json") {
+        return match serde_json::from_str(&contents)? {
+            serde_json::Value::Array(items) => Ok(items),
+            _ => Err(EchomindError::ParseError(format!("{} does not contain a JSON array", path))),
+        };
+    }
+
+    Ok(contents
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| serde_json::Value::String(line.to_string()))
+        .collect())
+}
+
+/// Splits a command line into arguments, honouring single and double quotes
+/// and backslash escapes. No globbing, pipes or variable expansion.
+fn split_command(command: &str) -> Vec<String> {
+    let mut args = Vec::new();
+    let mut current = String::new();
+    let mut in_arg = false;
+    let mut quote: Option<char> = None;
+    let mut chars = command.chars();
+
+    while let Some(c) = chars.next() {
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (None, '\'' | '"') => {
+                quote = Some(c);
+                in_arg = true;
+            }
+            (Some('"') | None, '\\') => {
+                if let Some(next) = chars.next() {
+                    current.push(next);
+                }
+                in_arg = true;
+            }
+            (None, c) if c.is_whitespace() => {
+                if in_arg {
+                    args.push(std::mem::take(&mut current));
+                    in_arg = false;
+                }
+            }
+            (_, c) => {
+                current.push(c);
+                in_arg = true;
+            }
+        }
+    }
+    if in_arg {
+        args.push(current);
+    }
+
+    args
+}
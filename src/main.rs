@@ -1,16 +1,30 @@
-mod api;
-mod cli;
-mod config;
-mod error;
-mod repl;
 mod tui;
 
 // // use crate::tui::run_tui;
-use api::{ApiClient, ChatRequest, Message, Provider/*, ContentPart, ImageUrl*/};
+use echomind::{api, cli, config, error, repl};
+use echomind::features::batch::{self, BatchCheckpoint, BatchOptions};
+use echomind::features::compare::{self, Comparison};
+use echomind::features::batch_api::{self, BatchApiClient, BatchJob, BatchJobStore};
+use echomind::features::data_processing::{ChartType, DataFormat, DataProcessor, Dataset, ExportFormat, VisualizationConfig};
+use echomind::features::transform::TransformSpec;
+use echomind::features::data_qa;
+use echomind::features::eval::{self, ChangeKind, EvalReport, EvalSuite};
+use echomind::features::performance::{
+    estimate_tokens, BenchmarkSuite, BenchmarkSummary, BenchmarkTarget, LoadTestConfig, PerformanceMonitor,
+};
+use echomind::features::query::QueryResult;
+use echomind::features::usage::{self, UsageGrouping, UsageLedger, UsageMeter, UsagePeriod, UsageRecord};
+use echomind::features::content::{ContentManager, TemplateUpdate, TemplateVariable, VariableType};
+use echomind::features::packs::{self, InstallReport, ItemKind, PackManager, PackManifest, PackTargets, PromptPack};
+use echomind::features::scheduling::{JobOutput, JobTask, ScheduledJob, SchedulingManager};
+use echomind::features::output::{OutputFormat, OutputPipeline, OutputSink, FileSink, Reply, WriterSink};
+use echomind::features::structured::{self, OutputSchema};
+use echomind::features::workflow::{self as workflow, WorkflowManager};
+use api::{ApiClient, ChatRequest, Message, Provider, StreamTiming/*, ContentPart, ImageUrl*/};
 use arboard::Clipboard;
-use chrono::{DateTime, Utc};
+use chrono::{DateTime, Local, NaiveDate, Utc};
 use clap::Parser;
-use cli::Args;
+use cli::{Args, BatchCommand, Command, PackCommand, ScheduleCommand, SnippetCommand, TemplateCommand, WorkflowCommand};
 use colored::Colorize;
 use config::Config;
 use error::{EchomindError, Result};
@@ -44,6 +58,84 @@ async fn main() {
 async fn run() -> Result<()> {
     let args = Args::parse();
 
+    // Local-only commands that don't need a network connection
+    if let Some(Command::Workflow { action: WorkflowCommand::Validate { file } }) = &args.command {
+        return validate_workflow_command(file);
+    }
+
+    if let Some(Command::Workflow { action: WorkflowCommand::Runs }) = &args.command {
+        return list_workflow_runs();
+    }
+
+    if args.list_workflows {
+        return list_workflows();
+    }
+
+    if let Some(Command::Schedule { action }) = &args.command {
+        match action {
+            ScheduleCommand::List => return list_scheduled_jobs(),
+            ScheduleCommand::Cancel { job_id } => return cancel_scheduled_job(job_id),
+            ScheduleCommand::Runs { job_id } => return list_job_runs(job_id.as_deref()),
+            ScheduleCommand::RunNow { .. } => {}
+        }
+    }
+
+    if let Some(Command::Template { action }) = &args.command {
+        return template_command(action);
+    }
+
+    if let Some(Command::Snippet { action }) = &args.command {
+        return snippet_command(action);
+    }
+
+    if args.list_snippets {
+        return list_snippets();
+    }
+
+    if let Some(Command::Pack { action }) = &args.command {
+        return pack_command(action);
+    }
+
+    if let Some(Command::Usage { period, by, since }) = &args.command {
+        return usage_report(period, by, since.as_deref(), &args);
+    }
+
+    if let Some(cron) = &args.schedule {
+        return schedule_job(cron, &args).await;
+    }
+
+    // The daemon outlives network outages, so it skips the connectivity check
+    if let Some(Command::Daemon { interval }) = &args.command {
+        return run_daemon(*interval).await;
+    }
+
+    // Without a question, --csv/--json-file/--excel just describe the file;
+    // --sql queries and --chart run locally
+    if let Some((path, format)) = data_file(&args) {
+        if let Some(chart_type) = &args.chart {
+            return chart_data_file(path, format, chart_type, &args);
+        }
+        if let Some(sql) = &args.sql {
+            return run_local_query(path, format, sql, &args);
+        }
+        if let Some(spec_file) = &args.transform_spec {
+            return run_saved_transform(path, format, spec_file, &args);
+        }
+        if args.prompt.is_none() && !args.transform && std::io::stdin().is_terminal() {
+            return describe_data_file(path, format, &args);
+        }
+    } else if args.sql.is_some() || args.to_sql || args.chart.is_some() || args.transform || args.transform_spec.is_some() {
+        return Err(EchomindError::Other(
+            "--sql, --to-sql, --chart and --transform need a data file (--csv, --json-file or --excel)".to_string(),
+        ));
+    }
+
+    if args.dry_run {
+        if let Some(workflow_ref) = &args.workflow {
+            return dry_run_workflow(workflow_ref, &args);
+        }
+    }
+
     // Check internet connectivity
     if !check_internet() {
         eprintln!("{} No internet connection detected. Please check your network and try again.", "Error:".red().bold());
@@ -94,7 +186,7 @@ async fn run() -> Result<()> {
     }
 
     // Check if we're in TUI mode
-    if args.tui {
+    if args.tui && args.compare.is_none() {
         use ratatui::{backend::CrosstermBackend, Terminal};
         use crossterm::{execute, terminal::{EnterAlternateScreen, LeaveAlternateScreen, enable_raw_mode, disable_raw_mode}, event::{DisableMouseCapture, EnableMouseCapture}};
         use crate::tui::App;
@@ -104,7 +196,10 @@ async fn run() -> Result<()> {
         execute!(stdout, EnterAlternateScreen, EnableMouseCapture)?;
         let backend = CrosstermBackend::new(stdout);
         let mut terminal = Terminal::new(backend)?;
-        let app = App::new(config, args.clone());
+        let mut usage = UsageMeter::for_config(&config, "tui");
+        usage.set_preset(args.preset.clone());
+        let mut app = App::new(config, args.clone());
+        app.set_usage_meter(usage);
         let res = crate::tui::run_app(&mut terminal, app).await;
         disable_raw_mode()?;
         execute!(terminal.backend_mut(), LeaveAlternateScreen, DisableMouseCapture)?;
@@ -120,10 +215,42 @@ async fn run() -> Result<()> {
         return run_interactive(args, config, initial_messages, system_prompt).await;
     }
 
+    if let Some(workflow_ref) = &args.workflow {
+        return run_workflow(workflow_ref, &args, &config).await;
+    }
+
+    if let Some(Command::Workflow { action: WorkflowCommand::Resume { run_id } }) = &args.command {
+        return resume_workflow_run(run_id, &args, &config).await;
+    }
+
+    if let Some(Command::Schedule { action: ScheduleCommand::RunNow { job_id } }) = &args.command {
+        return run_scheduled_job_now(job_id, &config).await;
+    }
+
+    if let Some(Command::Eval { file, models, judge, baseline, update_baseline }) = &args.command {
+        return run_eval(file, models, judge.as_deref(), baseline.as_deref(), *update_baseline, &args, &config).await;
+    }
+
+    if let Some(Command::Batch { action }) = &args.command {
+        return batch_command(action, &args, &config).await;
+    }
+
+    if let Some((path, format)) = data_file(&args) {
+        return run_data_question(path, format, &args, &config, initial_messages, system_prompt).await;
+    }
+
     if let Some(batch_file) = &args.batch {
         return run_batch_queries(batch_file, args.clone(), config, initial_messages, system_prompt).await;
     }
 
+    if args.benchmark || args.benchmark_compare.is_some() {
+        return run_benchmark(&args, &config).await;
+    }
+
+    if args.stress {
+        return run_stress_test(&args, &config).await;
+    }
+
     // Check for model comparison mode
     if let Some(models_str) = &args.compare {
         // Read input from clipboard or stdin
@@ -146,6 +273,9 @@ async fn run() -> Result<()> {
     // Read input: from clipboard, stdin, or show help
     let input = if args.clipboard {
         read_from_clipboard()?
+    } else if std::io::stdin().is_terminal() && args.template.is_some() {
+        // The rendered template is the whole message
+        String::new()
     } else if std::io::stdin().is_terminal() {
         // Show help when running echomind without input
         println!("{}", "Echomind - AI Chat CLI Tool".cyan().bold());
@@ -203,7 +333,7 @@ async fn run() -> Result<()> {
             input.push_str(&String::from_utf8_lossy(&buffer[..n]));
         }
 
-        if input.trim().is_empty() {
+        if input.trim().is_empty() && args.template.is_none() {
             return Err(EchomindError::InputError(std::io::Error::new(
                 std::io::ErrorKind::InvalidInput,
                 "No input provided",
@@ -212,6 +342,7 @@ async fn run() -> Result<()> {
         input
     };
 
+    let input = apply_template_and_snippet(input, &args)?;
     run_single_query(args, config, input, initial_messages, system_prompt).await
 }
 
@@ -222,33 +353,270 @@ async fn run_batch_queries(
     initial_messages: Vec<Message>,
     system_prompt: Option<String>,
 ) -> Result<()> {
-    let contents = fs::read_to_string(batch_file)
-        .map_err(|e| EchomindError::FileError(format!("Failed to read batch file: {}", e)))?;
+    let records = batch::load_records(batch_file)?;
+    let library = if records.iter().any(|r| r.template.is_some()) {
+        Some(open_content_library()?)
+    } else {
+        None
+    };
 
-    for (i, line) in contents.lines().enumerate() {
-        let query = line.trim();
-        if query.is_empty() || query.starts_with("#") {
-            continue; // Skip empty lines and comments
-        }
+    let mut base = initial_messages;
+    if let Some(s_prompt) = system_prompt {
+        base.push(Message::text("system".to_string(), s_prompt));
+    }
+
+    let template = ChatRequest {
+        messages: base,
+        model: args.model.clone().or(Some(config.api.model.clone())),
+        temperature: args.temperature.or(Some(config.defaults.temperature)),
+        max_tokens: args.max_tokens.or(config.defaults.max_tokens),
+        top_p: args.top_p.or(config.defaults.top_p),
+        top_k: args.top_k.or(config.defaults.top_k),
+        stream: None,
+    };
+    if args.batch_api {
+        return submit_batch_job(batch_file, &records, library.as_ref(), &template, &args, &config).await;
+    }
 
-        println!("{}\n{}", "─".repeat(80).bright_black(), format!("Batch Query {}: {}", i + 1, query).cyan().bold());
-        println!("{}", "─".repeat(80).bright_black());
+    // With --output the results file is the checkpoint; without it results go to stdout
+    let mut checkpoint = match &args.output {
+        Some(path) => Some(BatchCheckpoint::open(std::path::Path::new(path))?),
+        None => None,
+    };
+    let pending: Vec<batch::BatchRecord> = records
+        .iter()
+        .filter(|r| !checkpoint.as_ref().is_some_and(|c| c.is_completed(&r.id)))
+        .cloned()
+        .collect();
+    let skipped = records.len() - pending.len();
+    if skipped > 0 {
+        eprintln!("{} {} records already completed", "Skipping".cyan(), skipped);
+    }
 
-        // Clone args and config for each query to avoid ownership issues
-        run_single_query(
-            args.clone(),
-            config.clone(),
-            query.to_string(),
-            initial_messages.clone(),
-            system_prompt.clone(),
-        ).await?;
+    let client = build_client(&args, &config)?;
+    let options = BatchOptions {
+        concurrency: args.batch_concurrency,
+        retries: args.batch_retries,
+        ..BatchOptions::default()
+    };
+    let mut usage = UsageMeter::for_config(&config, "batch");
+    usage.set_preset(args.preset.clone());
+
+    let progress = ProgressBar::new(pending.len() as u64);
+    if checkpoint.is_some() && std::io::stderr().is_terminal() {
+        progress.set_style(
+            ProgressStyle::default_bar()
+                .template("{bar:40.cyan/blue} {pos}/{len} {msg} [{elapsed_precise}]")
+                .unwrap(),
+        );
+    } else {
+        progress.set_draw_target(indicatif::ProgressDrawTarget::hidden());
+    }
 
-        println!(); // Add a newline for separation between responses
+    let mut write_error = None;
+    let mut failed = 0;
+    let mut warned = false;
+    let results = batch::run_batch(&client, &pending, library.as_ref(), &template, options, Some(&usage), |result| {
+        // Every record over a warn-only budget carries the warning; once is enough
+        if let (Some(warning), false) = (&result.budget_warning, warned) {
+            progress.suspend(|| eprintln!("{} {}", "Budget:".yellow().bold(), warning));
+            warned = true;
+        }
+        if !result.succeeded() {
+            failed += 1;
+            progress.set_message(format!("{} failed", failed));
+        }
+        progress.inc(1);
+        let written = match checkpoint.as_mut() {
+            Some(checkpoint) => checkpoint.write(result),
+            None => serde_json::to_string(result).map(|line| println!("{}", line)).map_err(EchomindError::from),
+        };
+        if let Err(e) = written {
+            write_error.get_or_insert(e);
+        }
+    })
+    .await;
+    progress.finish_and_clear();
+    if let Some(e) = write_error {
+        return Err(e);
     }
 
+    let rerun = if args.output.is_some() { "; rerun the batch to retry them" } else { "" };
+    batch_outcome(&results, skipped, args.output.as_deref(), rerun)
+}
+
+// Prints where the results went and fails when any record did
+fn batch_outcome(results: &[batch::BatchResult], skipped: usize, output: Option<&str>, hint: &str) -> Result<()> {
+    let failed: Vec<&str> = results.iter().filter(|r| !r.succeeded()).map(|r| r.id.as_str()).collect();
+    if let Some(path) = output {
+        eprintln!(
+            "{} {} succeeded, {} failed, {} skipped → {}",
+            "✅ Batch done:".green(),
+            results.len() - failed.len(),
+            failed.len(),
+            skipped,
+            path
+        );
+    }
+    if !failed.is_empty() {
+        return Err(EchomindError::Other(format!(
+            "{} of {} batch records failed ({}){}",
+            failed.len(),
+            results.len(),
+            failed.join(", "),
+            hint
+        )));
+    }
     Ok(())
 }
 
+const BATCH_POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+async fn submit_batch_job(
+    batch_file: &str,
+    records: &[batch::BatchRecord],
+    library: Option<&ContentManager>,
+    template: &ChatRequest,
+    args: &Args,
+    config: &Config,
+) -> Result<()> {
+    let provider = Provider::from_string(args.provider.as_ref().unwrap_or(&config.api.provider))?;
+    let api = BatchApiClient::new(&BatchApiClient::base_url_for(&provider)?, args.api_key.clone().or(config.api.api_key.clone()));
+    let mut store = BatchJobStore::open(&Config::batch_jobs_dir()?)?;
+
+    // The whole batch is billed once it is submitted, so it is checked as one
+    let mut usage = UsageMeter::for_config(config, "batch");
+    usage.set_preset(args.preset.clone());
+    let estimate: f64 = records
+        .iter()
+        .filter_map(|record| {
+            let request = ChatRequest {
+                messages: record.messages(&template.messages, library).ok()?,
+                model: record.model.clone().or_else(|| template.model.clone()),
+                ..template.clone()
+            };
+            Some(usage.estimate(provider.name(), &request))
+        })
+        .sum();
+    if let Some(warning) = usage.check_cost(estimate * batch_api::BATCH_PRICE_FACTOR)? {
+        eprintln!("{} {}", "Budget:".yellow().bold(), warning);
+    }
+
+    let job = batch_api::submit(&api, provider.name(), records, library, template, &absolute_path(batch_file)).await?;
+    store.save_job(&job)?;
+    eprintln!("{} batch {} with {} requests", "✅ Submitted".green(), job.id, job.inputs.len());
+    if !args.batch_wait {
+        eprintln!("Check on it with: echomind batch status {}", job.id);
+        return Ok(());
+    }
+    fetch_batch_job(&api, &mut store, job, true, args).await
+}
+
+async fn batch_command(action: &BatchCommand, args: &Args, config: &Config) -> Result<()> {
+    let mut store = BatchJobStore::open(&Config::batch_jobs_dir()?)?;
+    let api_key = args.api_key.clone().or(config.api.api_key.clone());
+
+    match action {
+        BatchCommand::Status { job_id } => {
+            let mut jobs: Vec<BatchJob> = match job_id {
+                Some(id) => vec![store.get(id)?.clone()],
+                None => store.jobs().to_vec(),
+            };
+            if jobs.is_empty() {
+                println!("No batch jobs");
+                return Ok(());
+            }
+            for job in jobs.iter_mut() {
+                if !job.is_finished() {
+                    job.update(BatchApiClient::new(&job.base_url, api_key.clone()).retrieve(&job.id).await?);
+                    store.save_job(job)?;
+                }
+                print_batch_job(job);
+            }
+            Ok(())
+        }
+        BatchCommand::Fetch { job_id, wait } => {
+            let job = store.get(job_id)?.clone();
+            let api = BatchApiClient::new(&job.base_url, api_key);
+            fetch_batch_job(&api, &mut store, job, *wait, args).await
+        }
+    }
+}
+
+async fn fetch_batch_job(api: &BatchApiClient, store: &mut BatchJobStore, mut job: BatchJob, wait: bool, args: &Args) -> Result<()> {
+    if wait && !job.is_finished() {
+        let mut last_seen = (String::new(), 0);
+        batch_api::wait(api, &mut job, BATCH_POLL_INTERVAL, |job| {
+            let seen = (job.status.clone(), job.request_counts.completed);
+            if seen != last_seen {
+                print_batch_job(job);
+                last_seen = seen;
+            }
+        })
+        .await?;
+    } else if !job.is_finished() {
+        job.update(api.retrieve(&job.id).await?);
+    }
+    store.save_job(&job)?;
+
+    let results = batch_api::fetch_results(api, &job).await?;
+    let lines = results
+        .iter()
+        .map(serde_json::to_string)
+        .collect::<std::result::Result<Vec<_>, _>>()?
+        .join("\n");
+    match &args.output {
+        Some(path) => fs::write(path, format!("{}\n", lines)).map_err(|e| EchomindError::FileError(e.to_string()))?,
+        None => println!("{}", lines),
+    }
+
+    // Usage is recorded on the first fetch only, with the tokens the API reported
+    if job.fetched_at.is_none() {
+        let ledger = UsageLedger::open_default()?;
+        for (result, input) in results.iter().zip(&job.inputs) {
+            let usage = result.usage.clone().unwrap_or(batch::BatchUsage {
+                input_tokens: 0,
+                output_tokens: 0,
+                cost: 0.0,
+            });
+            ledger.record(&UsageRecord {
+                timestamp: Utc::now(),
+                provider: job.provider.clone(),
+                model: input.model.clone(),
+                preset: args.preset.clone(),
+                command: "batch-api".to_string(),
+                input_tokens: usage.input_tokens,
+                output_tokens: usage.output_tokens,
+                cost: usage.cost,
+                success: result.succeeded(),
+            })?;
+        }
+        job.fetched_at = Some(Utc::now());
+        store.save_job(&job)?;
+    }
+
+    batch_outcome(&results, 0, args.output.as_deref(), "")
+}
+
+fn print_batch_job(job: &BatchJob) {
+    let status = match job.status.as_str() {
+        "completed" => job.status.green(),
+        "failed" | "expired" | "cancelled" => job.status.red(),
+        _ => job.status.yellow(),
+    };
+    let counts = &job.request_counts;
+    println!(
+        "  {}  {:<11} {}/{} done, {} failed  {}  submitted {}",
+        job.id,
+        status,
+        counts.completed,
+        counts.total.max(job.inputs.len() as u64),
+        counts.failed,
+        job.source,
+        job.created_at.with_timezone(&Local).format("%Y-%m-%d %H:%M")
+    );
+}
+
 async fn run_single_query(args: Args, config: Config, input: String, messages: Vec<Message>, system_prompt: Option<String>) -> Result<()> {
     let start_time = std::time::Instant::now();
     let (coder, output) = args.resolve_coder_and_output();
@@ -267,6 +635,8 @@ async fn run_single_query(args: Args, config: Config, input: String, messages: V
 
     // Get model
     let model = args.model.as_ref().unwrap_or(&config.api.model).clone();
+    let schema = args.schema.as_deref().map(OutputSchema::load).transpose()?;
+    let format = OutputFormat::parse(args.format.as_deref())?;
 
     // Create API client (with key prompt/save on demand)
     let mut client = match ApiClient::new(provider.clone(), api_key.clone(), timeout) {
@@ -340,8 +710,32 @@ async fn run_single_query(args: Args, config: Config, input: String, messages: V
         eprintln!("{} {:?}", "Request:".cyan(), request);
     }
 
+    let mut usage = UsageMeter::for_config(&config, "query");
+    usage.set_preset(args.preset.clone());
+
+    // Every destination gets the same filtered, formatted reply. With -o the
+    // file takes the place of stdout, though a text stream is still shown
+    // as it arrives.
+    let mut pipeline = OutputPipeline::new(format.clone(), coder);
+    let live = args.stream && format.is_incremental();
+    if output.is_none() || (live && format == OutputFormat::Text) {
+        pipeline.add_sink(Box::new(WriterSink::new(std::io::stdout(), format.clone())));
+    }
+    if let Some(outfile) = &output {
+        pipeline.add_sink(Box::new(FileSink::new(outfile, format.clone())));
+    }
+    if args.to_clipboard {
+        pipeline.add_sink(Box::new(ClipboardSink { format: format.clone() }));
+    }
+    if let Some(history_file) = &args.history {
+        pipeline.add_sink(Box::new(HistorySink {
+            file: history_file.clone(),
+            user_message: user_message.clone(),
+        }));
+    }
+
     // Show progress indicator
-    let progress = if !args.stream && std::io::stderr().is_terminal() {
+    let progress = if !live && std::io::stderr().is_terminal() {
         let pb = ProgressBar::new_spinner();
         pb.set_style(
             ProgressStyle::default_spinner()
@@ -357,29 +751,78 @@ async fn run_single_query(args: Args, config: Config, input: String, messages: V
     };
 
     // Send request with fallback chain
+    let mut stream_timing = None;
+    let mut structured_value = None;
+    let mut sink_error = None;
     let content = loop {
-        let attempt = if args.stream {
-            client
-                .send_message_stream(request.clone(), |chunk| {
-                    print!("{}", chunk);
-                    use std::io::Write;
-                    std::io::stdout().flush().unwrap();
-                })
-                .await
+        let warn = |warning: &str| {
+            let print = || eprintln!("{} {}", "Budget:".yellow().bold(), warning);
+            match &progress {
+                Some(pb) => pb.suspend(print),
+                None => print(),
+            }
+        };
+        let attempt = if let Some(schema) = &schema {
+            // Usage is checked and recorded per request, repairs included
+            structured::send(&client, &request, schema, Some(&usage)).await.map(|reply| {
+                if let Some(warning) = &reply.budget_warning {
+                    warn(warning);
+                }
+                if args.verbose && reply.attempts > 1 {
+                    eprintln!("{} {} attempts", "Schema:".cyan(), reply.attempts);
+                }
+                let text = serde_json::to_string_pretty(&reply.value).unwrap_or(reply.raw);
+                structured_value = Some(reply.value);
+                text
+            })
         } else {
-            client.send_message(request.clone()).await
+            // Each provider in the fallback chain is checked against the
+            // budget; one over a blocking budget is skipped like one that failed
+            let client = &client;
+            let (pipeline, sink_error, stream_timing) = (&mut pipeline, &mut sink_error, &mut stream_timing);
+            UsageMeter::send(Some(&usage), provider.name(), &request, |warning| {
+                if let Some(warning) = &warning {
+                    warn(warning);
+                }
+                let request = request.clone();
+                async move {
+                    if !args.stream {
+                        return client.send_message(request).await;
+                    }
+                    client
+                        .send_message_stream_timed(request, |chunk| {
+                            if let Err(e) = pipeline.delta(chunk) {
+                                sink_error.get_or_insert(e);
+                            }
+                        })
+                        .await
+                        .map(|(text, timing)| {
+                            *stream_timing = Some(timing);
+                            text
+                        })
+                }
+            })
+            .await
         };
 
         match attempt {
             Ok(ok) => break ok,
             Err(e) => {
                 if let Some(next_provider_str) = fallback_chain.first().cloned() {
-                    // Switch provider and retry
+                    // Switch provider and retry, discarding whatever of the
+                    // failed reply was streamed
+                    if let Err(sink_failure) = pipeline.reset(&e) {
+                        sink_error.get_or_insert(sink_failure);
+                    }
                     fallback_chain.remove(0);
                     provider = Provider::from_string(&next_provider_str)?;
                     client = ApiClient::new(provider.clone(), api_key.clone(), timeout)?;
                     continue;
                 } else {
+                    if let Some(pb) = progress {
+                        pb.finish_and_clear();
+                    }
+                    let _ = pipeline.error(&e);
                     return Err(e);
                 }
             }
@@ -390,322 +833,2138 @@ async fn run_single_query(args: Args, config: Config, input: String, messages: V
     if let Some(pb) = progress {
         pb.finish_and_clear();
     }
+    let stream_report = stream_timing.as_ref().map(|timing| stream_summary(timing, &content));
 
-    // Process output content
-    let output_content = if coder {
-        // Filter empty lines and remove markdown code fences more efficiently
-        let content_str = content.as_str();
-        let mut result = String::with_capacity(content.len());
-        let lines = content_str.lines();
-
-        // Skip first line if it's a code fence
-        let mut first_line = true;
-        let mut skip_first = false;
-        let mut _skip_last = false;
-
-        // Check if we need to skip first/last lines
-        if let Some(first) = content_str.lines().next() {
-            if first.trim().starts_with("```") {
-                skip_first = true;
-            }
-        }
-        if let Some(last) = content_str.lines().last() {
-            if last.trim().starts_with("```") {
-                _skip_last = true;
-            }
-        }
-
-        for line in lines {
-            if first_line && skip_first {
-                first_line = false;
-                continue;
-            }
-            first_line = false;
-
-            // Skip empty lines and code fences
-            let trimmed = line.trim();
-            if !trimmed.is_empty() && !trimmed.starts_with("```") {
-                if !result.is_empty() {
-                    result.push('\n');
-                }
-                result.push_str(line);
-            }
-        }
-
-        // Remove last line if it was a code fence (already handled by the loop)
-        result
-    } else {
-        content.clone()
-    };
-
-    // Format output if specified
-    let formatted_output = if let Some(format_str) = &args.format {
-        format_output(&output_content, format_str, provider_str, &model)?
-    } else {
-        output_content
-    };
+    // A streamed reply has already gone out as deltas
+    if stream_timing.is_none() {
+        pipeline.delta(&content)?;
+    }
+    pipeline.finish(&content, structured_value.as_ref(), provider_str, &model)?;
+    if let Some(e) = sink_error {
+        return Err(e);
+    }
 
     // Calculate elapsed time
     let elapsed = start_time.elapsed();
 
-    // Save to file if specified
+    // Notes go to stderr so stdout stays clean for pipes and ndjson
     if let Some(outfile) = &output {
-        fs::write(outfile, &formatted_output).map_err(|e| EchomindError::FileError(e.to_string()))?;
-        println!("{} {}", "✅ Saved to".green(), outfile);
+        eprintln!("{} {}", "✅ Saved to".green(), outfile);
     }
-
-    // Save to clipboard if specified
     if args.to_clipboard {
-        write_to_clipboard(&formatted_output)?;
-        println!("{}", "✅ Copied to clipboard".green());
-    }
-
-    // Display output if not saved to file
-    if output.is_none() {
-        if !args.stream {
-            println!("{}", formatted_output);
-        } else {
-            println!(); // Add newline after streaming
-        }
+        eprintln!("{}", "✅ Copied to clipboard".green());
     }
-
-    // Display metrics
-    // print_metrics_table(
-    //     provider_str,
-    //     &model,
-    //     request.temperature,
-    //     request.max_tokens,
-    //     request.top_p,
-    //     request.top_k,
-    //     elapsed.as_secs_f64(),
-    // );
-
-    // Save to history if specified
-    if let Some(history_file) = &args.history {
-        let mut history_messages = vec![user_message];
-        history_messages.push(Message::text("assistant".to_string(), content));
-        save_history(history_file, &history_messages, provider_str, &model)?;
-        if args.verbose {
-            eprintln!("{}", "✅ Saved to history".green());
-        }
+    if args.history.is_some() && args.verbose {
+        eprintln!("{}", "✅ Saved to history".green());
     }
 
     // Performance profiling
     if args.verbose {
         eprintln!("{} {:.2}s", "⏱️  Total time:".cyan(), elapsed.as_secs_f64());
+        if let Some(report) = &stream_report {
+            eprintln!("{} {}", "⏱️  Stream:".cyan(), report);
+        }
     }
 
     Ok(())
 }
 
-fn check_internet() -> bool {
-    TcpStream::connect_timeout(&"8.8.8.8:53".parse().unwrap(), Duration::from_secs(5)).is_ok()
-}
-
-// Helper function to read from clipboard
-fn read_from_clipboard() -> Result<String> {
-    let mut clipboard = Clipboard::new()
-        .map_err(|e| EchomindError::Other(format!("Failed to access clipboard: {}", e)))?;
+// Resolve a workflow argument to a loaded workflow id: either a file path or
+// the name/id of a workflow saved in the workflows directory.
+fn load_workflow(manager: &mut WorkflowManager, workflow_ref: &str) -> Result<String> {
+    if std::path::Path::new(workflow_ref).exists() {
+        return manager.load_workflow_from_file(workflow_ref);
+    }
 
-    clipboard
-        .get_text()
-        .map_err(|e| EchomindError::Other(format!("Failed to read from clipboard: {}", e)))
-}
+    let dir = Config::workflows_dir()?;
+    for ext in ["yaml", "yml", "toml", "json"] {
+        let candidate = dir.join(format!("{}.{}", workflow_ref, ext));
+        if candidate.exists() {
+            return manager.load_workflow_from_file(&candidate.to_string_lossy());
+        }
+    }
 
-// Helper function to write to clipboard
-fn write_to_clipboard(text: &str) -> Result<()> {
-    let mut clipboard = Clipboard::new()
-        .map_err(|e| EchomindError::Other(format!("Failed to access clipboard: {}", e)))?;
+    manager.load_workflows_from_dir(&dir)?;
+    if manager.get_workflow(workflow_ref).is_some() {
+        return Ok(workflow_ref.to_string());
+    }
 
-    clipboard
-        .set_text(text)
-        .map_err(|e| EchomindError::Other(format!("Failed to write to clipboard: {}", e)))
+    Err(EchomindError::FileError(format!(
+        "Workflow '{}' not found (looked for a file and in {})",
+        workflow_ref,
+        dir.display()
+    )))
 }
 
-// Load conversation history
-fn load_history(history_file: &str) -> Result<Vec<Message>> {
-    if !std::path::Path::new(history_file).exists() {
-        return Ok(Vec::new());
+// Print validation issues; returns false if any of them is an error.
+fn report_validation(workflow: &workflow::Workflow) -> bool {
+    let issues = WorkflowManager::validate_workflow(workflow);
+    let mut ok = true;
+
+    for issue in &issues {
+        let location = issue.step_id.as_deref().map(|id| format!(" [{}]", id)).unwrap_or_default();
+        match issue.severity {
+            workflow::IssueSeverity::Error => {
+                ok = false;
+                eprintln!("{}{} {}", "error".red().bold(), location, issue.message);
+            }
+            workflow::IssueSeverity::Warning => {
+                eprintln!("{}{} {}", "warning".yellow().bold(), location, issue.message);
+            }
+        }
     }
 
-    let contents = fs::read_to_string(history_file)
-        .map_err(|e| EchomindError::FileError(format!("Failed to read history: {}", e)))?;
+    ok
+}
 
-    let entries: Vec<HistoryEntry> = serde_json::from_str(&contents)
-        .map_err(|e| EchomindError::ParseError(format!("Failed to parse history: {}", e)))?;
+fn validate_workflow_command(workflow_ref: &str) -> Result<()> {
+    let mut manager = WorkflowManager::new();
+    let workflow_id = load_workflow(&mut manager, workflow_ref)?;
+    let workflow = manager
+        .get_workflow(&workflow_id)
+        .ok_or_else(|| EchomindError::Other(format!("Workflow {} not found", workflow_id)))?;
 
-    Ok(entries
-        .into_iter()
-        .map(|e| Message::text(e.role, e.content))
-        .collect())
+    if report_validation(workflow) {
+        println!(
+            "{} {} ({} steps)",
+            "✅ Workflow is valid:".green(),
+            workflow.name,
+            workflow.steps.len()
+        );
+        Ok(())
+    } else {
+        Err(EchomindError::Other(format!("Workflow '{}' failed validation", workflow.name)))
+    }
 }
 
-// Save conversation history
-fn save_history(
-    history_file: &str,
-    messages: &[Message],
-    provider: &str,
-    model: &str,
-) -> Result<()> {
-    let entries: Vec<HistoryEntry> = messages
-        .iter()
-        .map(|msg| HistoryEntry {
-            timestamp: Utc::now(),
-            role: msg.role.clone(),
-            content: msg.get_text().unwrap_or("").to_string(),
-            provider: Some(provider.to_string()),
-            model: Some(model.to_string()),
-            // has_image: matches!(msg.content, api::MessageContent::MultiModal(_)),
-            has_image: false,
-        })
-        .collect();
+fn list_workflows() -> Result<()> {
+    let dir = Config::workflows_dir()?;
+    let mut manager = WorkflowManager::new();
+    manager.load_workflows_from_dir(&dir)?;
 
-    let json = serde_json::to_string_pretty(&entries)
-        .map_err(|e| EchomindError::ParseError(format!("Failed to serialize history: {}", e)))?;
+    let mut workflows = manager.list_workflows();
+    if workflows.is_empty() {
+        println!("No workflows found in {}", dir.display());
+        return Ok(());
+    }
 
-    fs::write(history_file, json)
-        .map_err(|e| EchomindError::FileError(format!("Failed to write history: {}", e)))?;
+    workflows.sort_by(|a, b| a.id.cmp(&b.id));
+    println!("{} ({})", "Available workflows:".cyan().bold(), dir.display());
+    for wf in workflows {
+        println!(
+            "- {} {} ({} steps){}",
+            wf.id.green(),
+            wf.name,
+            wf.steps.len(),
+            wf.description.as_deref().map(|d| format!(" - {}", d)).unwrap_or_default()
+        );
+    }
 
     Ok(())
 }
 
-// // Load image file and encode as base64
-// fn load_image_as_base64(path: &str) -> Result<String> {
-//     let data = fs::read(path).map_err(|e| EchomindError::FileError(format!("Failed to read image: {}", e)))?;
-//     Ok(general_purpose::STANDARD.encode(&data))
-// }
+async fn run_workflow(workflow_ref: &str, args: &Args, config: &Config) -> Result<()> {
+    let mut manager = WorkflowManager::new();
+    let workflow_id = load_workflow(&mut manager, workflow_ref)?;
+    let workflow = manager
+        .get_workflow(&workflow_id)
+        .cloned()
+        .ok_or_else(|| EchomindError::Other(format!("Workflow {} not found", workflow_id)))?;
 
-// Format output based on format specification
-fn format_output(content: &str, format_str: &str, provider: &str, model: &str) -> Result<String> {
-    match format_str {
-        "json" => {
-            let output = serde_json::json!({
-                "content": content,
-                "provider": provider,
-                "model": model,
-                "timestamp": Utc::now().to_rfc3339()
-            });
-            Ok(serde_json::to_string_pretty(&output)?)
-        }
-        "text" => Ok(content.to_string()),
-        _ if format_str.starts_with("template:") => {
-            let template = &format_str[9..]; // Remove "template:" prefix
-            let formatted = template
-                .replace("{content}", content)
-                .replace("{provider}", provider)
-                .replace("{model}", model)
-                .replace("{timestamp}", &Utc::now().to_rfc3339());
-            Ok(formatted)
-        }
-        _ => Err(EchomindError::Other(format!("Unknown format: {}", format_str))),
+    if !report_validation(&workflow) {
+        return Err(EchomindError::Other(format!("Workflow '{}' failed validation", workflow.name)));
     }
+
+    let variables = workflow::parse_variable_assignments(&args.vars)?;
+    let client = build_client(args, config)?;
+    configure_workflow_manager(&mut manager, args, config)?;
+
+    let start_time = std::time::Instant::now();
+    let context = manager.execute_workflow(&workflow_id, variables, &client).await?;
+    finish_workflow_run(&workflow, &context, start_time.elapsed())
 }
 
-// Compare responses from multiple models
-async fn compare_models(input: &str, models_str: &str, args: &Args, config: &Config, system_prompt: Option<String>) -> Result<()> {
-    let models: Vec<String> = models_str.split(',').map(|s| s.trim().to_string()).collect();
+async fn resume_workflow_run(run_id: &str, args: &Args, config: &Config) -> Result<()> {
+    let mut manager = WorkflowManager::new();
+    configure_workflow_manager(&mut manager, args, config)?;
+    let run = manager.load_run(run_id)?;
+    eprintln!(
+        "{} run {} of '{}' from step {}",
+        "Resuming".cyan().bold(),
+        run.run_id,
+        run.workflow.name,
+        run.context.current_step
+    );
 
-    if models.is_empty() {
-        return Err(EchomindError::Other(
-            "No models specified for comparison".to_string(),
-        ));
+    let client = build_client(args, config)?;
+    let start_time = std::time::Instant::now();
+    let context = manager.resume_workflow(&run.run_id, &client).await?;
+    finish_workflow_run(&run.workflow, &context, start_time.elapsed())
+}
+
+fn configure_workflow_manager(manager: &mut WorkflowManager, args: &Args, config: &Config) -> Result<()> {
+    manager.set_api_key(args.api_key.clone().or(config.api.api_key.clone()));
+    manager.set_default_model(Some(args.model.clone().unwrap_or(config.api.model.clone())));
+    manager.set_default_timeout(args.timeout.unwrap_or(config.api.timeout));
+    manager.set_shell_allowlist(config.workflow.shell_allowlist.clone());
+    manager.set_runs_dir(Config::workflow_runs_dir()?);
+    let mut usage = UsageMeter::for_config(config, "workflow");
+    usage.set_preset(args.preset.clone());
+    manager.set_usage_meter(usage);
+    Ok(())
+}
+
+fn finish_workflow_run(workflow: &workflow::Workflow, context: &workflow::WorkflowContext, elapsed: Duration) -> Result<()> {
+    print_workflow_summary(workflow, context, elapsed);
+
+    if context.errors.is_empty() {
+        Ok(())
+    } else {
+        eprintln!(
+            "Run {} saved; continue it with `echomind workflow resume {}`",
+            context.run_id,
+            context.run_id
+        );
+        Err(EchomindError::Other(format!(
+            "Workflow '{}' finished with {} error(s)",
+            workflow.name,
+            context.errors.len()
+        )))
+    }
+}
+
+fn dry_run_workflow(workflow_ref: &str, args: &Args) -> Result<()> {
+    let mut manager = WorkflowManager::new();
+    let workflow_id = load_workflow(&mut manager, workflow_ref)?;
+    let workflow = manager
+        .get_workflow(&workflow_id)
+        .cloned()
+        .ok_or_else(|| EchomindError::Other(format!("Workflow {} not found", workflow_id)))?;
+
+    if !report_validation(&workflow) {
+        return Err(EchomindError::Other(format!("Workflow '{}' failed validation", workflow.name)));
+    }
+
+    let variables = workflow::parse_variable_assignments(&args.vars)?;
+    let plan = manager.plan_workflow(&workflow_id, variables)?;
+
+    println!("{} {} (dry run)", "Planned path:".cyan().bold(), workflow.name);
+    let mut position = 0;
+    for step in &plan {
+        let indent = "  ".repeat(step.depth + 1);
+        if step.depth == 0 {
+            position += 1;
+            println!("{}{}. {} [{:?}] {}", indent, position, step.step_id.bold(), step.step_type, step.name);
+        } else {
+            println!("{}- {} [{:?}] {}", indent, step.step_id.bold(), step.step_type, step.name);
+        }
+        if let Some(rendered) = &step.rendered {
+            for line in rendered.lines() {
+                println!("{}   {}", indent, line.dimmed());
+            }
+        }
+    }
+    Ok(())
+}
+
+fn list_workflow_runs() -> Result<()> {
+    let mut manager = WorkflowManager::new();
+    let dir = Config::workflow_runs_dir()?;
+    manager.set_runs_dir(dir.clone());
+
+    let runs = manager.list_runs()?;
+    if runs.is_empty() {
+        println!("No workflow runs found in {}", dir.display());
+        return Ok(());
+    }
+
+    println!("{} ({})", "Workflow runs:".cyan().bold(), dir.display());
+    for run in runs {
+        let status = match run.status {
+            workflow::RunStatus::Completed => "completed".green(),
+            workflow::RunStatus::Failed => "failed".red(),
+            workflow::RunStatus::Running => "running".yellow(),
+        };
+        let position = if run.context.current_step.is_empty() {
+            String::new()
+        } else {
+            format!(" at step {}", run.context.current_step)
+        };
+        println!(
+            "  {}  {:<9}  {}  {} ({} steps run){}",
+            run.run_id,
+            status,
+            run.started_at.format("%Y-%m-%d %H:%M"),
+            run.workflow.name,
+            run.context.history.len(),
+            position
+        );
+    }
+    Ok(())
+}
+
+fn open_content_library() -> Result<ContentManager> {
+    ContentManager::new(&Config::content_library_path()?.to_string_lossy())
+}
+
+// Render --template (prompting for missing variables when possible) and put
+// it and any --snippet in front of the input.
+fn apply_template_and_snippet(input: String, args: &Args) -> Result<String> {
+    if args.template.is_none() && args.snippet.is_none() {
+        return Ok(input);
+    }
+
+    let mut library = open_content_library()?;
+    let mut parts = Vec::new();
+
+    if let Some(name) = &args.snippet {
+        parts.push(library.use_snippet(name)?);
+    }
+
+    if let Some(name) = &args.template {
+        let template = library
+            .find_template(name)
+            .cloned()
+            .ok_or_else(|| EchomindError::Other(format!("Template '{}' not found", name)))?;
+
+        let mut variables: std::collections::HashMap<String, String> = workflow::parse_variable_assignments(&args.vars)?
+            .into_iter()
+            .map(|(key, value)| (key, value.as_str().map(str::to_string).unwrap_or_else(|| value.to_string())))
+            .collect();
+
+        let missing: Vec<_> = ContentManager::missing_variables(&template, &variables)
+            .into_iter()
+            .cloned()
+            .collect();
+        if !missing.is_empty() {
+            if !std::io::stdin().is_terminal() {
+                let names: Vec<&str> = missing.iter().map(|v| v.name.as_str()).collect();
+                return Err(EchomindError::Other(format!(
+                    "Template '{}' needs values for: {} (pass them with --var)",
+                    template.name,
+                    names.join(", ")
+                )));
+            }
+            for var in missing {
+                let value = prompt_for_variable(&var)?;
+                variables.insert(var.name.clone(), value);
+            }
+        }
+
+        parts.push(library.render_template(&template.id, &variables)?);
+    }
+
+    if !input.trim().is_empty() {
+        parts.push(input);
+    }
+    Ok(parts.join("\n\n"))
+}
+
+fn prompt_for_variable(var: &TemplateVariable) -> Result<String> {
+    use std::io::Write;
+
+    let hint = match &var.variable_type {
+        VariableType::String => String::new(),
+        VariableType::Select(choices) => format!(" [{}]", choices.join("/")),
+        other => format!(" [{}]", format!("{:?}", other).to_lowercase()),
+    };
+
+    loop {
+        match &var.description {
+            Some(description) => eprint!("{}{} ({}): ", var.name.cyan(), hint, description),
+            None => eprint!("{}{}: ", var.name.cyan(), hint),
+        }
+        std::io::stderr().flush()?;
+
+        let mut value = String::new();
+        if std::io::stdin().read_line(&mut value)? == 0 {
+            return Err(EchomindError::Other(format!("No value given for '{}'", var.name)));
+        }
+        let value = value.trim_end_matches(['\r', '\n']).to_string();
+        if value.is_empty() {
+            eprintln!("{} is required", var.name);
+            continue;
+        }
+        match var.parse_value(&value) {
+            Ok(_) => return Ok(value),
+            Err(e) => eprintln!("{}", e.to_string().red()),
+        }
+    }
+}
+
+// Template/snippet content comes from --file, piped stdin or $EDITOR.
+fn read_content(file: Option<&str>, initial: &str) -> Result<String> {
+    if let Some(path) = file {
+        return fs::read_to_string(path).map_err(|e| EchomindError::FileError(format!("Failed to read {}: {}", path, e)));
+    }
+
+    if !std::io::stdin().is_terminal() {
+        let mut content = String::new();
+        std::io::Read::read_to_string(&mut std::io::stdin(), &mut content)?;
+        return Ok(content);
+    }
+
+    edit_in_editor(initial)
+}
+
+fn edit_in_editor(initial: &str) -> Result<String> {
+    let editor = std::env::var("VISUAL")
+        .or_else(|_| std::env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+    let path = std::env::temp_dir().join(format!("echomind-{}.txt", uuid::Uuid::new_v4().simple()));
+    fs::write(&path, initial).map_err(|e| EchomindError::FileError(format!("Failed to create temp file: {}", e)))?;
+
+    let status = std::process::Command::new(&editor)
+        .arg(&path)
+        .status()
+        .map_err(|e| EchomindError::Other(format!("Failed to launch editor '{}': {}", editor, e)));
+    let content = fs::read_to_string(&path);
+    let _ = fs::remove_file(&path);
+
+    if !status?.success() {
+        return Err(EchomindError::Other(format!("Editor '{}' exited with an error", editor)));
     }
+    content.map_err(|e| EchomindError::FileError(format!("Failed to read edited content: {}", e)))
+}
+
+fn template_command(action: &TemplateCommand) -> Result<()> {
+    let mut library = open_content_library()?;
 
-    println!("{}", "=== Multi-Model Comparison ===".cyan().bold());
-    println!("{}: {}\n", "Input".yellow(), input);
-
-    // Prepare concurrent tasks
-    let mut tasks = Vec::new();
-
-    for model_name in models {
-        let input = input.to_string();
-        let system_prompt = system_prompt.clone();
-        let args = args.clone();
-        let config = config.clone();
-
-        let task = tokio::spawn(async move {
-            // Determine provider from model name or use default
-            let (provider_name, actual_model) = if model_name.starts_with("gpt") {
-                ("openai", model_name)
-            } else if model_name.starts_with("claude") {
-                ("claude", model_name)
-            } else if model_name.contains('/') {
-                // Assume format like "ollama/llama2"
-                let parts: Vec<&str> = model_name.split('/').collect();
-                (parts[0], parts.get(1).unwrap_or(&model_name.as_str()).to_string())
+    match action {
+        TemplateCommand::Add { name, file, description, category, tags } => {
+            let content = read_content(file.as_deref(), "")?;
+            if content.trim().is_empty() {
+                return Err(EchomindError::Other("Template content is empty".to_string()));
+            }
+            let template = library.create_template(name, &content, description.as_deref(), category.as_deref(), tags.clone())?;
+            println!("{} template '{}'", "Saved".green().bold(), template.name);
+            if !template.variables.is_empty() {
+                let names: Vec<&str> = template.variables.iter().map(|v| v.name.as_str()).collect();
+                println!("Variables: {}", names.join(", "));
+            }
+        }
+        TemplateCommand::Edit { name, file, rename, description, category, tags } => {
+            let template = library
+                .find_template(name)
+                .cloned()
+                .ok_or_else(|| EchomindError::Other(format!("Template '{}' not found", name)))?;
+
+            let metadata_only = rename.is_some() || description.is_some() || category.is_some() || !tags.is_empty();
+            let content = if file.is_some() || !metadata_only {
+                Some(read_content(file.as_deref(), &template.content)?)
             } else {
-                (
-                    args.provider.as_deref().unwrap_or(&config.api.provider),
-                    model_name,
-                )
+                None
             };
 
-            let provider = Provider::from_string(provider_name)?;
-            let api_key = args
-                .api_key
-                .as_ref()
-                .or(config.api.api_key.as_ref())
-                .cloned();
-            let timeout = args.timeout.unwrap_or(config.api.timeout);
+            library.update_template(
+                &template.id,
+                TemplateUpdate {
+                    name: rename.clone(),
+                    description: description.clone(),
+                    content: content.filter(|c| *c != template.content),
+                    category: category.clone(),
+                    tags: if tags.is_empty() { None } else { Some(tags.clone()) },
+                },
+            )?;
+            println!("{} template '{}'", "Updated".green().bold(), rename.as_ref().unwrap_or(&template.name));
+        }
+        TemplateCommand::List { category } => {
+            let mut templates = library.search_templates("", category.as_deref(), Vec::new());
+            if templates.is_empty() {
+                println!("No templates found");
+                return Ok(());
+            }
+
+            templates.sort_by(|a, b| a.name.cmp(&b.name));
+            println!("{}", "Templates:".cyan().bold());
+            for template in templates {
+                let category = template.category.as_ref().map(|c| format!(" [{}]", c)).unwrap_or_default();
+                let description = template.description.as_ref().map(|d| format!(" - {}", d)).unwrap_or_default();
+                println!("  {}{}{} (used {}x)", template.name.bold(), category, description, template.usage_count);
+            }
+        }
+        TemplateCommand::Show { name } => {
+            let template = library
+                .find_template(name)
+                .ok_or_else(|| EchomindError::Other(format!("Template '{}' not found", name)))?;
+
+            println!("{} {}", "Template:".cyan().bold(), template.name);
+            if let Some(description) = &template.description {
+                println!("{}", description);
+            }
+            if let Some(category) = &template.category {
+                println!("Category: {}", category);
+            }
+            if !template.tags.is_empty() {
+                println!("Tags: {}", template.tags.join(", "));
+            }
+            if !template.variables.is_empty() {
+                println!("{}", "Variables:".cyan().bold());
+                for var in &template.variables {
+                    let default = var.default_value.as_ref().map(|d| format!(" (default: {})", d)).unwrap_or_default();
+                    let required = if var.required && var.default_value.is_none() { " required" } else { "" };
+                    println!("  {} {:?}{}{}", var.name.bold(), var.variable_type, required, default);
+                }
+            }
+            println!("{}", "Content:".cyan().bold());
+            println!("{}", template.content);
+        }
+        TemplateCommand::Var { name, variable, var_type, choices, description, default, optional } => {
+            let template = library
+                .find_template(name)
+                .cloned()
+                .ok_or_else(|| EchomindError::Other(format!("Template '{}' not found", name)))?;
+            let mut declaration = template
+                .variables
+                .iter()
+                .find(|v| v.name == *variable)
+                .cloned()
+                .ok_or_else(|| EchomindError::Other(format!("Template '{}' has no variable '{}'", template.name, variable)))?;
+
+            if let Some(kind) = var_type {
+                declaration.variable_type = VariableType::parse(kind, choices)?;
+            } else if !choices.is_empty() {
+                declaration.variable_type = VariableType::Select(choices.clone());
+            }
+            if description.is_some() {
+                declaration.description = description.clone();
+            }
+            if default.is_some() {
+                declaration.default_value = default.clone();
+            }
+            if let Some(optional) = optional {
+                declaration.required = !optional;
+            }
+
+            library.set_variable(&template.id, declaration)?;
+            println!("{} variable '{}' of '{}'", "Updated".green().bold(), variable, template.name);
+        }
+        TemplateCommand::Delete { name } => {
+            let template = library
+                .find_template(name)
+                .cloned()
+                .ok_or_else(|| EchomindError::Other(format!("Template '{}' not found", name)))?;
+            library.delete_template(&template.id)?;
+            println!("{} template '{}'", "Deleted".yellow().bold(), template.name);
+        }
+    }
+
+    Ok(())
+}
 
-            let client = ApiClient::new(provider, api_key, timeout)?;
+fn snippet_command(action: &SnippetCommand) -> Result<()> {
+    let mut library = open_content_library()?;
 
-            let mut messages = Vec::new();
-            if let Some(s_prompt) = system_prompt {
-                messages.push(Message::text("system".to_string(), s_prompt));
+    match action {
+        SnippetCommand::Add { name, file, description, language } => {
+            let content = read_content(file.as_deref(), "")?;
+            if content.trim().is_empty() {
+                return Err(EchomindError::Other("Snippet content is empty".to_string()));
             }
-            messages.push(Message::text("user".to_string(), input));
+            let snippet = library.create_snippet(name, content.trim_end(), description.as_deref(), Vec::new(), None, language.as_deref())?;
+            println!("{} snippet '{}'", "Saved".green().bold(), snippet.name);
+        }
+        SnippetCommand::Delete { name } => {
+            let snippet = library
+                .find_snippet(name)
+                .cloned()
+                .ok_or_else(|| EchomindError::Other(format!("Snippet '{}' not found", name)))?;
+            library.delete_snippet(&snippet.id)?;
+            println!("{} snippet '{}'", "Deleted".yellow().bold(), snippet.name);
+        }
+    }
 
-            let request = ChatRequest {
-                messages,
-                model: Some(actual_model.to_string()),
-                temperature: args.temperature.or(Some(config.defaults.temperature)),
-                max_tokens: args.max_tokens.or(config.defaults.max_tokens),
-                top_p: None,
-                top_k: None,
-                stream: None,
+    Ok(())
+}
+
+fn list_snippets() -> Result<()> {
+    let library = open_content_library()?;
+    let mut snippets = library.search_snippets("", None, Vec::new());
+    if snippets.is_empty() {
+        println!("No snippets found. Add one with `echomind snippet add NAME`.");
+        return Ok(());
+    }
+
+    snippets.sort_by(|a, b| a.name.cmp(&b.name));
+    println!("{}", "Snippets:".cyan().bold());
+    for snippet in snippets {
+        let language = snippet.language.as_ref().map(|l| format!(" [{}]", l)).unwrap_or_default();
+        let description = snippet
+            .description
+            .clone()
+            .unwrap_or_else(|| preview(&snippet.content, 50));
+        println!("  {}{} - {}", snippet.name.bold(), language, description);
+    }
+    Ok(())
+}
+
+fn data_file(args: &Args) -> Option<(&str, DataFormat)> {
+    [
+        (&args.csv, DataFormat::Csv),
+        (&args.json_file, DataFormat::Json),
+        (&args.excel, DataFormat::Excel),
+    ]
+    .into_iter()
+    .find_map(|(path, format)| path.as_deref().map(|path| (path, format)))
+}
+
+fn data_processor(args: &Args) -> DataProcessor {
+    let mut processor = DataProcessor::new();
+    processor.set_sheet(args.sheet.clone());
+    processor
+}
+
+// Describes a CSV or JSON file, or each sheet of a workbook.
+fn describe_data_file(path: &str, format: DataFormat, args: &Args) -> Result<()> {
+    let processor = data_processor(args);
+    let analyses = match format {
+        DataFormat::Excel => processor.analyze_sheets(path)?,
+        _ => vec![processor.analyze_file(path, format)?],
+    };
+    let sections: Vec<String> = analyses.iter().map(|analysis| analysis.describe(path, 10)).collect();
+    println!("{}", sections.join("\n\n"));
+    Ok(())
+}
+
+fn run_local_query(path: &str, format: DataFormat, sql: &str, args: &Args) -> Result<()> {
+    let processor = data_processor(args);
+    let dataset = processor.load_dataset(path, format)?;
+    let result = processor.query_data(&dataset, sql)?;
+    print_query_result(&result, args)
+}
+
+fn run_saved_transform(path: &str, format: DataFormat, spec_file: &str, args: &Args) -> Result<()> {
+    let text = fs::read_to_string(spec_file)
+        .map_err(|e| EchomindError::FileError(format!("Failed to read {}: {}", spec_file, e)))?;
+    let spec = TransformSpec::from_json(&text)?;
+    let processor = data_processor(args);
+    let dataset = processor.load_dataset(path, format)?;
+    write_transform(&processor, &dataset, &spec, args)
+}
+
+// Run a transform over the whole dataset, show the steps and the first
+// changed rows, then write the result to --output (after confirming, when
+// interactive) or print it.
+fn write_transform(processor: &DataProcessor, dataset: &Dataset, spec: &TransformSpec, args: &Args) -> Result<()> {
+    let outcome = spec.apply(dataset, args.preview)?;
+
+    if args.verbose {
+        eprintln!("{}\n{}", "Transform spec:".cyan(), serde_json::to_string_pretty(spec)?);
+    } else {
+        eprintln!("{}", "Transform:".cyan());
+        for (i, step) in spec.steps.iter().enumerate() {
+            eprintln!("  {}. {}", i + 1, step);
+        }
+    }
+    if !outcome.preview.is_empty() {
+        eprintln!("{}\n{}", format!("First {} changed rows:", outcome.preview.len()).cyan(), outcome.render_preview());
+    }
+    eprintln!("{}", outcome.summary().dimmed());
+
+    let format = match (&args.format, &args.output) {
+        (Some(format), _) => ExportFormat::parse(format)?,
+        (None, Some(output)) => ExportFormat::from_path(output).unwrap_or(ExportFormat::Csv),
+        (None, None) => ExportFormat::Csv,
+    };
+    match &args.output {
+        Some(outfile) => {
+            if std::io::stdin().is_terminal() && std::io::stderr().is_terminal() {
+                eprint!("Write {} rows to {} as {}? [y/N] ", outcome.dataset.records.len(), outfile, format.name());
+                let mut answer = String::new();
+                std::io::stdin().read_line(&mut answer)?;
+                if !answer.trim().eq_ignore_ascii_case("y") && !answer.trim().eq_ignore_ascii_case("yes") {
+                    eprintln!("Nothing written.");
+                    return Ok(());
+                }
+            }
+            processor.export_data(&outcome.dataset, format, outfile)?;
+            println!("{} {} ({} rows)", "✅ Saved to".green(), outfile, outcome.dataset.records.len());
+        }
+        None => processor.write_data(&outcome.dataset, format, &mut std::io::stdout().lock())?,
+    }
+    Ok(())
+}
+
+// Chart the data file, or the result of --sql, in the terminal, the TUI or
+// an SVG file.
+fn chart_data_file(path: &str, format: DataFormat, chart_type: &str, args: &Args) -> Result<()> {
+    let processor = data_processor(args);
+    let mut dataset = processor.load_dataset(path, format)?;
+    if let Some(sql) = &args.sql {
+        dataset = processor.query_data(&dataset, sql)?.to_dataset();
+    }
+
+    let (width, height) = match &args.chart_size {
+        Some(size) => {
+            let parsed = size
+                .split_once(['x', 'X'])
+                .and_then(|(w, h)| Some((w.trim().parse::<u32>().ok()?, h.trim().parse::<u32>().ok()?)));
+            match parsed {
+                Some((w, h)) => (Some(w), Some(h)),
+                None => return Err(EchomindError::Other(format!("Invalid chart size '{}', expected WIDTHxHEIGHT", size))),
+            }
+        }
+        None => (None, None),
+    };
+    let config = VisualizationConfig {
+        chart_type: ChartType::parse(chart_type)?,
+        x_column: args.x.clone().unwrap_or_default(),
+        y_column: args.y.clone(),
+        title: args.chart_title.clone(),
+        width,
+        height,
+        color_scheme: args.colors.clone(),
+    };
+    let chart = processor.generate_visualization(&dataset, &config)?;
+
+    if args.tui {
+        return tui::show_chart(&chart).map_err(EchomindError::from);
+    }
+    match &args.output {
+        Some(outfile) => {
+            let rendered = if outfile.to_lowercase().ends_with(".svg") {
+                chart.to_svg()
+            } else {
+                format!("{}\n", chart.render_terminal(false))
             };
+            fs::write(outfile, rendered).map_err(|e| EchomindError::FileError(e.to_string()))?;
+            println!("{} {}", "✅ Chart saved to".green(), outfile);
+        }
+        None => {
+            let color = std::io::stdout().is_terminal() && colored::control::SHOULD_COLORIZE.should_colorize();
+            println!("{}", chart.render_terminal(color));
+        }
+    }
+    Ok(())
+}
+
+// Print query results as an aligned table, or as JSON/CSV with --format.
+fn print_query_result(result: &QueryResult, args: &Args) -> Result<()> {
+    let rendered = match args.format.as_deref() {
+        Some("json") => serde_json::to_string_pretty(&result.to_records())?,
+        Some("csv") => {
+            let mut writer = csv::Writer::from_writer(Vec::new());
+            writer
+                .write_record(&result.columns)
+                .and_then(|_| {
+                    result.rows.iter().try_for_each(|row| {
+                        writer.write_record(row.iter().map(|value| match value {
+                            serde_json::Value::String(s) => s.clone(),
+                            serde_json::Value::Null => String::new(),
+                            other => other.to_string(),
+                        }))
+                    })
+                })
+                .map_err(|e| EchomindError::Other(format!("Failed to write CSV: {}", e)))?;
+            let bytes = writer
+                .into_inner()
+                .map_err(|e| EchomindError::Other(format!("Failed to write CSV: {}", e)))?;
+            String::from_utf8_lossy(&bytes).trim_end().to_string()
+        }
+        Some(other) if other != "text" && other != "table" => {
+            return Err(EchomindError::Other(format!("Query results can be printed as table, json or csv, not '{}'", other)));
+        }
+        _ => render_table(result),
+    };
 
-            let result = client.send_message(request).await;
-            Ok::<(String, Result<String>), EchomindError>((actual_model, result))
-        });
+    match &args.output {
+        Some(outfile) => {
+            fs::write(outfile, format!("{}\n", rendered)).map_err(|e| EchomindError::FileError(e.to_string()))?;
+            println!("{} {} ({} rows)", "✅ Saved to".green(), outfile, result.rows.len());
+        }
+        None => println!("{}", rendered),
+    }
+    Ok(())
+}
+
+fn render_table(result: &QueryResult) -> String {
+    let cells: Vec<Vec<String>> = result
+        .rows
+        .iter()
+        .map(|row| {
+            row.iter()
+                .map(|value| match value {
+                    serde_json::Value::String(s) => s.clone(),
+                    serde_json::Value::Null => String::new(),
+                    other => other.to_string(),
+                })
+                .collect()
+        })
+        .collect();
+    let widths: Vec<usize> = result
+        .columns
+        .iter()
+        .enumerate()
+        .map(|(i, column)| {
+            cells
+                .iter()
+                .map(|row| row[i].chars().count())
+                .chain(std::iter::once(column.chars().count()))
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
 
-        tasks.push(task);
+    let line = |values: &[String]| -> String {
+        values
+            .iter()
+            .zip(&widths)
+            .map(|(value, width)| format!("{:<width$}", value, width = *width))
+            .collect::<Vec<_>>()
+            .join("  ")
+            .trim_end()
+            .to_string()
+    };
+    let mut out = vec![
+        line(&result.columns).bold().to_string(),
+        widths.iter().map(|w| "─".repeat(*w)).collect::<Vec<_>>().join("  "),
+    ];
+    out.extend(cells.iter().map(|row| line(row)));
+    out.push(format!("({} rows)", result.rows.len()).dimmed().to_string());
+    out.join("\n")
+}
+
+// Run a benchmark suite (or the single prompt given) against each target
+// and report latency, throughput, cost and pass rate per model.
+async fn run_benchmark(args: &Args, config: &Config) -> Result<()> {
+    let mut suite = match &args.suite {
+        Some(path) => BenchmarkSuite::load(path)?,
+        None => match measurement_prompt(args).await? {
+            Some(prompt) => BenchmarkSuite::single(&prompt),
+            None => {
+                return Err(EchomindError::Other(
+                    "Benchmarking needs a --suite file, a prompt argument or piped input".to_string(),
+                ))
+            }
+        },
+    };
+    suite.temperature = args.temperature.or(suite.temperature).or(Some(config.defaults.temperature));
+    suite.max_tokens = args.max_tokens.or(suite.max_tokens).or(config.defaults.max_tokens);
+    let runs = args.runs.or(suite.runs).unwrap_or(3);
+    if runs == 0 {
+        return Err(EchomindError::Other("--runs must be at least 1".to_string()));
     }
 
-    // Execute all tasks concurrently and collect results
-    let mut results = Vec::new();
-    for task in tasks {
-        match task.await {
-            Ok(Ok((model_name, result))) => results.push((model_name, result)),
-            Ok(Err(e)) => eprintln!("Task error: {}", e),
-            Err(e) => eprintln!("Join error: {}", e),
+    let references = match &args.benchmark_compare {
+        Some(models) => models.clone(),
+        None => suite.targets.clone(),
+    };
+    let targets = if references.is_empty() {
+        let model = args.model.clone().unwrap_or_else(|| config.api.model.clone());
+        vec![BenchmarkTarget::new(build_client(args, config)?, &model)]
+    } else {
+        references
+            .iter()
+            .map(|reference| benchmark_target(reference, args, config))
+            .collect::<Result<Vec<_>>>()?
+    };
+
+    let total = (targets.len() * suite.prompts.len() * runs) as u64;
+    let progress = ProgressBar::new(total);
+    progress.set_style(
+        ProgressStyle::default_bar()
+            .template("{bar:30.cyan/blue} {pos}/{len} {msg}")
+            .unwrap(),
+    );
+    let mut monitor = PerformanceMonitor::new();
+    let mut usage = UsageMeter::for_config(config, "benchmark");
+    usage.set_preset(args.preset.clone());
+    monitor.set_usage_meter(usage);
+    let mut warned = false;
+    let summaries = monitor
+        .run_suite(&targets, &suite, runs, |result| {
+            progress.inc(1);
+            progress.set_message(format!("{}:{}", result.provider, result.model));
+            // Every run over a warn-only budget carries the warning; once is enough
+            if let (Some(warning), false) = (&result.budget_warning, warned) {
+                progress.println(format!("{} {}", "Budget:".yellow().bold(), warning));
+                warned = true;
+            }
+            if args.verbose && result.passed == Some(false) {
+                let reason = match &result.error {
+                    Some(error) => error.clone(),
+                    None => result.failures.join("; "),
+                };
+                progress.println(format!(
+                    "{} {}:{} {}: {}",
+                    "✗".red(),
+                    result.provider,
+                    result.model,
+                    result.prompt_id.as_deref().unwrap_or("-"),
+                    reason
+                ));
+            }
+        })
+        .await;
+    progress.finish_and_clear();
+
+    let export_format = args.format.clone().or_else(|| {
+        args.output.as_ref().map(|path| {
+            match std::path::Path::new(path).extension().and_then(|e| e.to_str()) {
+                Some("json") => "json",
+                Some("csv") => "csv",
+                _ => "markdown",
+            }
+            .to_string()
+        })
+    });
+    match (export_format, &args.output) {
+        (Some(format), Some(outfile)) => {
+            let report = monitor.export_benchmark_data(&format)?;
+            fs::write(outfile, report).map_err(|e| EchomindError::FileError(e.to_string()))?;
+            println!("{}", render_table(&benchmark_table(&summaries)));
+            println!("{} {}", "✅ Saved benchmark report to".green(), outfile);
+        }
+        (Some(format), None) if format != "text" && format != "table" => {
+            print!("{}", monitor.export_benchmark_data(&format)?);
         }
+        _ => println!("{}", render_table(&benchmark_table(&summaries))),
     }
+    Ok(())
+}
+
+// Run an eval suite, print each case as it finishes, diff against the
+// baseline and fail on regressions (or on any failure without a baseline).
+async fn run_eval(
+    file: &str,
+    models: &[String],
+    judge: Option<&str>,
+    baseline: Option<&str>,
+    update_baseline: bool,
+    args: &Args,
+    config: &Config,
+) -> Result<()> {
+    let mut suite = EvalSuite::load(file)?;
+    suite.temperature = args.temperature.or(suite.temperature).or(Some(config.defaults.temperature));
+    suite.max_tokens = args.max_tokens.or(suite.max_tokens).or(config.defaults.max_tokens);
 
-    // Sort results by model name for consistent output
-    results.sort_by(|a, b| a.0.cmp(&b.0));
+    let library = if suite.cases.iter().any(|case| case.template.is_some()) {
+        Some(open_content_library()?)
+    } else {
+        None
+    };
+    let conversations = suite
+        .cases
+        .iter()
+        .map(|case| case.messages(&config.presets, library.as_ref()))
+        .collect::<Result<Vec<_>>>()?;
+
+    let references = if models.is_empty() { suite.models.clone() } else { models.to_vec() };
+    let targets = if references.is_empty() {
+        let model = args.model.clone().unwrap_or_else(|| config.api.model.clone());
+        vec![BenchmarkTarget::new(build_client(args, config)?, &model)]
+    } else {
+        references
+            .iter()
+            .map(|reference| benchmark_target(reference, args, config))
+            .collect::<Result<Vec<_>>>()?
+    };
+    let judge = if suite.needs_judge() {
+        Some(match judge.map(str::to_string).or(suite.judge.clone()) {
+            Some(reference) => benchmark_target(&reference, args, config)?,
+            None => {
+                let model = args.model.clone().unwrap_or_else(|| config.api.model.clone());
+                BenchmarkTarget::new(build_client(args, config)?, &model)
+            }
+        })
+    } else {
+        None
+    };
+
+    let json_output = args.format.as_deref() == Some("json");
+    let mut usage = UsageMeter::for_config(config, "eval");
+    usage.set_preset(args.preset.clone());
+    let mut warned = false;
+    let report = eval::run_suite(&suite, &conversations, &targets, judge.as_ref(), Some(&usage), |result| {
+        // Every case over a warn-only budget carries the warning; once is enough
+        if let (Some(warning), false) = (&result.budget_warning, warned) {
+            eprintln!("{} {}", "Budget:".yellow().bold(), warning);
+            warned = true;
+        }
+        if json_output {
+            return;
+        }
+        if result.passed {
+            println!("{} {}  {}  {} ms", "✓".green(), result.case_id, result.model.dimmed(), result.latency_ms);
+        } else {
+            println!("{} {}  {}  {}", "✗".red(), result.case_id, result.model.dimmed(), result.failure_summary());
+        }
+    })
+    .await;
+
+    let changes = match baseline {
+        Some(path) if std::path::Path::new(path).exists() => Some(report.diff(&EvalReport::load(path)?)),
+        Some(path) if !update_baseline => {
+            eprintln!("{} No baseline at {} yet; save one with --update-baseline", "Note:".yellow(), path);
+            None
+        }
+        _ => None,
+    };
+    if let Some(outfile) = &args.output {
+        report.save(outfile)?;
+    }
 
-    // Display results
-    for (model_name, result) in results {
-        println!("{} {}", "Model:".green().bold(), model_name);
-        println!("{}", "─".repeat(80).bright_black());
+    if json_output {
+        let json = serde_json::json!({ "report": report, "changes": changes });
+        println!("{}", serde_json::to_string_pretty(&json)?);
+    } else {
+        let total = report.results.len();
+        let passed = report.passed();
+        let tally = format!("{}/{} passed", passed, total);
+        println!("\n{}", if passed == total { tally.green().bold() } else { tally.yellow().bold() });
+        if let Some(changes) = &changes {
+            if changes.is_empty() {
+                println!("No changes from the baseline");
+            }
+            for change in changes {
+                let label = match change.kind {
+                    ChangeKind::Regression => "regressed".red().bold(),
+                    ChangeKind::Fixed => "fixed".green(),
+                    ChangeKind::New => "new".cyan(),
+                    ChangeKind::Missing => "not run".dimmed(),
+                };
+                println!("  {:<10} {}  {}", label, change.case_id, change.model.dimmed());
+            }
+        }
+        if let Some(outfile) = &args.output {
+            println!("{} {}", "✅ Saved eval report to".green(), outfile);
+        }
+    }
 
-        match result {
-            Ok(response) => {
-                println!("{}", response);
+    if update_baseline {
+        if let Some(path) = baseline {
+            report.save(path)?;
+            eprintln!("{} {}", "✅ Updated baseline".green(), path);
+        }
+        return Ok(());
+    }
+    match changes {
+        Some(changes) => {
+            let regressions = changes.iter().filter(|c| c.kind == ChangeKind::Regression).count();
+            if regressions > 0 {
+                return Err(EchomindError::Other(format!("{} regression(s) against the baseline", regressions)));
             }
-            Err(e) => {
-                println!("{} {}", "Error:".red(), e);
+        }
+        None => {
+            let failed = report.results.len() - report.passed();
+            if failed > 0 {
+                return Err(EchomindError::Other(format!("{} of {} eval cases failed", failed, report.results.len())));
             }
         }
+    }
+    Ok(())
+}
+
+// The prompt argument, else piped input, for benchmarks and load tests.
+async fn measurement_prompt(args: &Args) -> Result<Option<String>> {
+    let prompt = match &args.prompt {
+        Some(prompt) => prompt.clone(),
+        None if !std::io::stdin().is_terminal() => {
+            let mut input = String::new();
+            io::stdin().read_to_string(&mut input).await?;
+            input
+        }
+        None => String::new(),
+    };
+    Ok(Some(prompt.trim().to_string()).filter(|p| !p.is_empty()))
+}
+
+// Drive the configured model at a target rate or concurrency and report
+// latency percentiles, a histogram and errors by kind.
+async fn run_stress_test(args: &Args, config: &Config) -> Result<()> {
+    let prompt = measurement_prompt(args).await?.ok_or_else(|| {
+        EchomindError::Other("--stress needs a prompt argument or piped input".to_string())
+    })?;
+    let load = LoadTestConfig {
+        rate: args.rps,
+        concurrency: args.concurrency,
+        warmup: Duration::from_secs(args.warmup),
+        duration: Duration::from_secs(args.duration),
+    };
+    load.validate()?;
+    let client = build_client(args, config)?;
+    let model = args.model.clone().unwrap_or_else(|| config.api.model.clone());
+    let mut messages = Vec::new();
+    if let Some(system) = &args.system {
+        messages.push(Message::text("system".to_string(), system.clone()));
+    }
+    messages.push(Message::text("user".to_string(), prompt));
+    let request = ChatRequest {
+        messages,
+        model: Some(model.clone()),
+        temperature: args.temperature.or(Some(config.defaults.temperature)),
+        max_tokens: args.max_tokens.or(config.defaults.max_tokens),
+        top_p: None,
+        top_k: None,
+        stream: None,
+    };
 
-        println!("{}\n", "─".repeat(80).bright_black());
+    // Load test requests aren't checked one at a time, so the whole run is
+    // checked up front; each is recorded as it completes
+    let mut usage = UsageMeter::for_config(config, "stress");
+    usage.set_preset(args.preset.clone());
+    let estimate = usage.estimate(client.provider().name(), &request) * load.expected_requests() as f64;
+    if let Some(warning) = usage.check_cost(estimate)? {
+        eprintln!("{} {}", "Budget:".yellow().bold(), warning);
     }
 
+    let progress = ProgressBar::new((load.warmup + load.duration).as_millis() as u64);
+    progress.set_style(
+        ProgressStyle::default_bar()
+            .template("{spinner:.cyan} {prefix} {bar:30.cyan/blue} {msg}")
+            .unwrap(),
+    );
+    progress.set_prefix(format!("{}:{}", client.provider().name(), model));
+    let mut monitor = PerformanceMonitor::new();
+    monitor.set_usage_meter(usage);
+    let report = monitor
+        .run_stress_test(&client, request, &load, |p| {
+            progress.set_position(p.elapsed.as_millis() as u64);
+            let phase = if p.warming_up { "warming up · " } else { "" };
+            let measured = p.elapsed.saturating_sub(load.warmup).as_secs_f64();
+            let rate = if measured > 0.0 { p.completed as f64 / measured } else { 0.0 };
+            progress.set_message(format!(
+                "{}{}s · {} done · {} failed · {} dropped · {} in flight · {:.1} req/s",
+                phase,
+                p.elapsed.as_secs(),
+                p.completed,
+                p.failed,
+                p.dropped,
+                p.in_flight,
+                rate
+            ));
+        })
+        .await?;
+    progress.finish_and_clear();
+
+    let rendered = match args.format.as_deref() {
+        Some("json") => serde_json::to_string_pretty(&report)?,
+        Some(other) if other != "text" => {
+            return Err(EchomindError::Other(format!("Load test reports can be text or json, not '{}'", other)));
+        }
+        _ => report.render(),
+    };
+    match &args.output {
+        Some(outfile) => {
+            fs::write(outfile, format!("{}\n", rendered)).map_err(|e| EchomindError::FileError(e.to_string()))?;
+            println!("{}", report.render());
+            println!("{} {}", "✅ Saved load test report to".green(), outfile);
+        }
+        None => println!("{}", rendered),
+    }
     Ok(())
 }
 
+// `echomind usage`: ledger totals per period and group, with budget status
+// in text mode
+fn usage_report(period: &str, by: &str, since: Option<&str>, args: &Args) -> Result<()> {
+    let period = UsagePeriod::parse(period)?;
+    let grouping = UsageGrouping::parse(by)?;
+    let since = since
+        .map(|date| {
+            NaiveDate::parse_from_str(date, "%Y-%m-%d")
+                .map_err(|_| EchomindError::Other(format!("Invalid --since date '{}' (expected YYYY-MM-DD)", date)))
+        })
+        .transpose()?;
+
+    let all_records = UsageLedger::open_default()?.records()?;
+    let records: Vec<UsageRecord> = all_records
+        .iter()
+        .filter(|r| since.is_none_or(|date| r.timestamp.with_timezone(&Local).date_naive() >= date))
+        .cloned()
+        .collect();
+    if records.is_empty() {
+        println!("No recorded usage");
+        return Ok(());
+    }
+
+    let rows = usage::summarize(&records, period, grouping);
+    let result = QueryResult {
+        columns: ["period", grouping.name(), "requests", "errors", "input_tokens", "output_tokens", "cost"]
+            .iter()
+            .map(|c| c.to_string())
+            .collect(),
+        rows: rows
+            .iter()
+            .map(|row| {
+                vec![
+                    serde_json::json!(row.period),
+                    serde_json::json!(row.key),
+                    serde_json::json!(row.requests),
+                    serde_json::json!(row.errors),
+                    serde_json::json!(row.input_tokens),
+                    serde_json::json!(row.output_tokens),
+                    serde_json::json!((row.cost * 10_000.0).round() / 10_000.0),
+                ]
+            })
+            .collect(),
+        matched_rows: records.len(),
+    };
+    print_query_result(&result, args)?;
+
+    // Budget status only makes sense next to the human-readable table
+    if matches!(args.format.as_deref(), None | Some("text") | Some("table")) && args.output.is_none() {
+        let total: f64 = records.iter().map(|r| r.cost).sum();
+        println!("\n{} {} requests, ${:.4}", "Total:".cyan().bold(), records.len(), total);
+
+        let budget = Config::load()?.budget;
+        let spend = usage::spend_at(&all_records, Local::now());
+        for (name, cap, spent, period) in [
+            ("Daily", budget.daily, spend.today, "today"),
+            ("Monthly", budget.monthly, spend.this_month, "this month"),
+        ] {
+            if let Some(cap) = cap {
+                let status = format!("${:.2} of ${:.2} spent {}", spent, cap, period);
+                let status = if spent >= cap { status.red() } else { status.green() };
+                println!("{} {}", format!("{} budget:", name).cyan(), status);
+            }
+        }
+    }
+    Ok(())
+}
+
+// First byte/token, inter-token latency and generation speed of a streamed reply.
+fn stream_summary(timing: &StreamTiming, content: &str) -> String {
+    let ms = |d: Option<Duration>| d.map(|d| format!("{} ms", d.as_millis())).unwrap_or_else(|| "-".to_string());
+    let tokens = estimate_tokens(content);
+    let generation = timing.generation_time().unwrap_or(timing.total).as_secs_f64().max(0.001);
+    format!(
+        "first byte {}, first token {}, inter-token p50 {} / p95 {}, {:.1} tok/s over {:.2}s",
+        ms(timing.first_byte),
+        ms(timing.first_token),
+        ms(timing.token_gap_percentile(50.0)),
+        ms(timing.token_gap_percentile(95.0)),
+        tokens as f64 / generation,
+        timing.total.as_secs_f64(),
+    )
+}
+
+// A benchmark target from `provider:model`, a known model family, or a bare
+// model name served by the configured provider.
+fn benchmark_target(reference: &str, args: &Args, config: &Config) -> Result<BenchmarkTarget> {
+    let (provider, model) = match Provider::for_model(reference) {
+        Some(found) => found,
+        None => {
+            let provider_str = args.provider.as_ref().unwrap_or(&config.api.provider);
+            (Provider::from_string(provider_str)?, reference.trim().to_string())
+        }
+    };
+    let api_key = args.api_key.clone().or(config.api.api_key.clone());
+    let timeout = args.timeout.unwrap_or(config.api.timeout);
+    Ok(BenchmarkTarget::new(ApiClient::new(provider, api_key, timeout)?, &model))
+}
+
+fn benchmark_table(summaries: &[BenchmarkSummary]) -> QueryResult {
+    let ms = |value: Option<u64>| value.map(|v| format!("{} ms", v)).unwrap_or_else(|| "-".to_string());
+    let columns = ["model", "runs", "errors", "pass", "p50", "p95", "ttft p50", "ttft p95", "itl p50", "itl p95", "tok/s", "cost"];
+    let rows = summaries
+        .iter()
+        .map(|s| {
+            [
+                format!("{}:{}", s.provider, s.model),
+                s.runs.to_string(),
+                s.errors.to_string(),
+                format!("{:.0}%", s.pass_rate * 100.0),
+                ms(s.p50_latency_ms),
+                ms(s.p95_latency_ms),
+                ms(s.p50_ttft_ms),
+                ms(s.p95_ttft_ms),
+                ms(s.p50_inter_token_ms),
+                ms(s.p95_inter_token_ms),
+                format!("{:.1}", s.tokens_per_second),
+                format!("${:.4}", s.total_cost),
+            ]
+            .into_iter()
+            .map(serde_json::Value::String)
+            .collect()
+        })
+        .collect();
+    QueryResult {
+        columns: columns.iter().map(|c| c.to_string()).collect(),
+        rows,
+        matched_rows: summaries.len(),
+    }
+}
+
+// Answer a question about a data file, computing the queries the model asks
+// for over the whole file.
+async fn run_data_question(
+    path: &str,
+    format: DataFormat,
+    args: &Args,
+    config: &Config,
+    initial_messages: Vec<Message>,
+    system_prompt: Option<String>,
+) -> Result<()> {
+    let question = match &args.prompt {
+        Some(prompt) => prompt.clone(),
+        None => {
+            let mut input = String::new();
+            io::stdin().read_to_string(&mut input).await?;
+            input
+        }
+    };
+    if question.trim().is_empty() {
+        return Err(EchomindError::Other(format!("Ask a question about the data, e.g. echomind --csv {} \"which region grew fastest?\"", path)));
+    }
+
+    let processor = data_processor(args);
+    let dataset = processor.load_dataset(path, format)?;
+    let analysis = processor.analyze(&dataset);
+
+    let provider_str = args.provider.as_ref().unwrap_or(&config.api.provider);
+    let client = ApiClient::new(
+        Provider::from_string(provider_str)?,
+        args.api_key.clone().or(config.api.api_key.clone()),
+        args.timeout.unwrap_or(config.api.timeout),
+    )?;
+
+    let mut messages = Vec::new();
+    if let Some(system) = system_prompt {
+        messages.push(Message::text("system".to_string(), system));
+    }
+    messages.extend(initial_messages);
+    let base = ChatRequest {
+        messages,
+        model: args.model.clone().or(Some(config.api.model.clone())),
+        temperature: args.temperature.or(Some(config.defaults.temperature)),
+        max_tokens: args.max_tokens.or(config.defaults.max_tokens),
+        top_p: args.top_p.or(config.defaults.top_p),
+        top_k: args.top_k.or(config.defaults.top_k),
+        stream: None,
+    };
+
+    let mut usage = UsageMeter::for_config(config, "data");
+    usage.set_preset(args.preset.clone());
+
+    if args.transform {
+        let spec = data_qa::plan_transform(&client, base, &analysis, path, &question, Some(&usage)).await?;
+        return write_transform(&processor, &dataset, &spec, args);
+    }
+
+    if args.to_sql {
+        let sql = data_qa::translate_to_sql(&client, base, &analysis, path, &question, Some(&usage)).await?;
+        eprintln!("{} {}", "Query:".cyan(), sql);
+        let result = processor.query_data(&dataset, &sql)?;
+        return print_query_result(&result, args);
+    }
+
+    let progress = if std::io::stderr().is_terminal() {
+        let pb = ProgressBar::new_spinner();
+        pb.set_style(ProgressStyle::default_spinner().template("{spinner:.cyan} {msg}").unwrap());
+        pb.set_message(format!("Analyzing {} rows...", dataset.records.len()));
+        pb.enable_steady_tick(std::time::Duration::from_millis(100));
+        Some(pb)
+    } else {
+        None
+    };
+    let result = data_qa::answer_question(&client, base, &processor, &dataset, &analysis, path, &question, Some(&usage)).await;
+    if let Some(pb) = progress {
+        pb.finish_and_clear();
+    }
+    let answer = result?;
+
+    if args.verbose {
+        for (i, computation) in answer.computations.iter().enumerate() {
+            eprintln!("{} {}", format!("Query {}:", i + 1).cyan(), computation.query);
+            match &computation.result {
+                Ok(result) => eprintln!("{}\n", result.to_table(20)),
+                Err(error) => eprintln!("{} {}\n", "error:".red(), error),
+            }
+        }
+    } else if !answer.computations.is_empty() {
+        eprintln!(
+            "{}",
+            format!("({} queries computed over {} rows; --verbose shows them)", answer.computations.len(), dataset.records.len())
+                .dimmed()
+        );
+    }
+
+    let output = match &args.format {
+        Some(format_str) => OutputFormat::parse(Some(format_str))?.render(
+            &answer.answer,
+            None,
+            provider_str,
+            args.model.as_deref().unwrap_or(&config.api.model),
+        )?,
+        None => answer.answer,
+    };
+    match &args.output {
+        Some(outfile) => {
+            fs::write(outfile, &output).map_err(|e| EchomindError::FileError(e.to_string()))?;
+            println!("{} {}", "✅ Saved to".green(), outfile);
+        }
+        None => println!("{}", output),
+    }
+    Ok(())
+}
+
+fn pack_command(action: &PackCommand) -> Result<()> {
+    let mut manager = PackManager::open(&Config::packs_dir()?)?;
+
+    if let PackCommand::List = action {
+        if manager.list().is_empty() {
+            println!("No packs installed. Install one with `echomind pack install PATH_OR_GIT_URL`.");
+            return Ok(());
+        }
+        println!("{}", "Installed packs:".cyan().bold());
+        for pack in manager.list() {
+            let counts = packs::count_by_kind(&pack.items);
+            let summary: Vec<String> = [ItemKind::Preset, ItemKind::Template, ItemKind::Snippet, ItemKind::Workflow]
+                .iter()
+                .filter_map(|kind| counts.get(kind).map(|n| format!("{} {}{}", n, kind, if *n == 1 { "" } else { "s" })))
+                .collect();
+            println!(
+                "  {} {} ({}) - {}",
+                pack.namespace.bold(),
+                pack.version,
+                pack.name,
+                summary.join(", ")
+            );
+            println!("    {} {}", "source:".dimmed(), pack.source);
+        }
+        return Ok(());
+    }
+
+    let mut config = Config::load()?;
+    let mut library = open_content_library()?;
+    let workflows_dir = Config::workflows_dir()?;
+
+    if let PackCommand::Export { dir, name, version, description, namespace } = action {
+        let mut workflows = WorkflowManager::new();
+        workflows.load_workflows_from_dir(&workflows_dir)?;
+        let manifest = PackManifest {
+            name: name.clone(),
+            version: version.clone(),
+            description: description.clone(),
+            namespace: namespace.clone(),
+            ..PackManifest::default()
+        };
+        let pack = PromptPack::export(manifest, namespace.as_deref(), &config, &library, &workflows)?;
+        pack.write(std::path::Path::new(dir))?;
+        println!(
+            "{} {} item(s) to {}",
+            "Exported".green().bold(),
+            pack.items(pack.namespace()).len(),
+            dir
+        );
+        return Ok(());
+    }
+
+    let mut targets = PackTargets {
+        config: &mut config,
+        library: &mut library,
+        workflows_dir,
+    };
+    match action {
+        PackCommand::Install { source, namespace, force } => {
+            let (pack, source) = manager.fetch(source)?;
+            let report = manager.install(&pack, &source, namespace.as_deref(), *force, &mut targets)?;
+            let namespace = namespace.as_deref().unwrap_or_else(|| pack.namespace());
+            print_install_report(&pack, namespace, &report);
+        }
+        PackCommand::Update { namespace, force } => {
+            let report = manager.update(namespace, *force, &mut targets)?;
+            let installed = manager.get(namespace).expect("pack was just installed");
+            println!(
+                "{} {} {} -> {}",
+                "Updated".green().bold(),
+                namespace,
+                report.previous_version.as_deref().unwrap_or("?"),
+                installed.version
+            );
+            for item in &report.removed {
+                println!("  {} {}", "-".red(), item);
+            }
+            for item in &report.added {
+                println!("  {} {}", "+".green(), item);
+            }
+        }
+        PackCommand::Remove { namespace } => {
+            let removed = manager.remove(namespace, &mut targets)?;
+            println!(
+                "{} pack '{}' ({} item(s))",
+                "Removed".yellow().bold(),
+                removed.name,
+                removed.items.len()
+            );
+        }
+        PackCommand::List | PackCommand::Export { .. } => unreachable!("handled above"),
+    }
+
+    config.save()
+}
+
+fn print_install_report(pack: &PromptPack, namespace: &str, report: &InstallReport) {
+    let verb = match &report.previous_version {
+        Some(previous) if previous != &pack.manifest.version => format!("Upgraded from {}", previous),
+        Some(_) => "Reinstalled".to_string(),
+        None => "Installed".to_string(),
+    };
+    println!(
+        "{} {} {} into namespace '{}'",
+        verb.green().bold(),
+        pack.manifest.name,
+        pack.manifest.version,
+        namespace
+    );
+    for item in &report.added {
+        println!("  {} {}", "+".green(), item);
+    }
+    for item in &report.updated {
+        println!("  {} {}", "~".yellow(), item);
+    }
+    for item in &report.removed {
+        println!("  {} {}", "-".red(), item);
+    }
+    for (template, variable) in &report.file_includes {
+        eprintln!(
+            "{} template '{}' includes the contents of the local file named by '{}'; check the path before you render it",
+            "Warning:".yellow(),
+            template,
+            variable
+        );
+    }
+}
+
+async fn schedule_job(cron: &str, args: &Args) -> Result<()> {
+    SchedulingManager::parse_schedule(cron)?;
+
+    let task = if let Some(workflow_ref) = &args.workflow {
+        // Pin file paths so the daemon finds them regardless of its working directory
+        let workflow = match fs::canonicalize(workflow_ref) {
+            Ok(path) => path.to_string_lossy().to_string(),
+            Err(_) => workflow_ref.clone(),
+        };
+        JobTask::Workflow {
+            workflow,
+            variables: workflow::parse_variable_assignments(&args.vars)?,
+        }
+    } else {
+        let prompt = match &args.prompt {
+            Some(prompt) => prompt.clone(),
+            None if !std::io::stdin().is_terminal() => {
+                let mut input = String::new();
+                io::stdin().read_to_string(&mut input).await?;
+                input.trim().to_string()
+            }
+            None => String::new(),
+        };
+        if prompt.is_empty() {
+            return Err(EchomindError::Other(
+                "Scheduled jobs need a prompt (argument or stdin) or --workflow".to_string(),
+            ));
+        }
+
+        match &args.preset {
+            Some(preset) => JobTask::Preset { preset: preset.clone(), prompt },
+            None => JobTask::Prompt { prompt, system: args.system.clone() },
+        }
+    };
+
+    let output = if let Some(url) = &args.webhook {
+        JobOutput::Webhook { url: url.clone() }
+    } else if let Some(history) = &args.history {
+        JobOutput::History { path: absolute_path(history) }
+    } else if let Some(file) = args.resolve_coder_and_output().1 {
+        JobOutput::File { path: absolute_path(&file), append: true }
+    } else {
+        JobOutput::Stdout
+    };
+
+    let name = match &task {
+        JobTask::Workflow { workflow, .. } => format!("workflow {}", workflow),
+        JobTask::Prompt { prompt, .. } | JobTask::Preset { prompt, .. } => preview(prompt, 40),
+    };
+
+    let job = ScheduledJob {
+        id: uuid::Uuid::new_v4().simple().to_string()[..8].to_string(),
+        name,
+        schedule: cron.trim().to_string(),
+        task,
+        output,
+        provider: args.provider.clone(),
+        model: args.model.clone(),
+        catch_up: true,
+        created_at: Utc::now(),
+        last_run: None,
+    };
+
+    let mut manager = SchedulingManager::open(&Config::schedule_dir()?)?;
+    manager.add_job(job.clone())?;
+
+    println!("{} job {} ({})", "Scheduled".green().bold(), job.id, job.name);
+    if let Some(next) = SchedulingManager::next_run(&job, Utc::now()) {
+        println!("Next run: {}", next.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M"));
+    }
+    println!("Jobs run while `echomind daemon` is running.");
+    Ok(())
+}
+
+fn absolute_path(path: &str) -> String {
+    std::path::absolute(path)
+        .map(|p| p.to_string_lossy().to_string())
+        .unwrap_or_else(|_| path.to_string())
+}
+
+fn list_scheduled_jobs() -> Result<()> {
+    let dir = Config::schedule_dir()?;
+    let manager = SchedulingManager::open(&dir)?;
+    let jobs = manager.list_scheduled_tasks();
+    if jobs.is_empty() {
+        println!("No scheduled jobs in {}", dir.display());
+        return Ok(());
+    }
+
+    let runs = manager.list_runs(None)?;
+    println!("{} ({})", "Scheduled jobs:".cyan().bold(), dir.display());
+    for job in jobs {
+        let next = SchedulingManager::next_run(job, job.last_run.unwrap_or(job.created_at).max(Utc::now()))
+            .map(|next| next.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M").to_string())
+            .unwrap_or_else(|| "never".to_string());
+        let last = match runs.iter().rev().find(|run| run.job_id == job.id) {
+            Some(run) if run.skipped => "skipped".yellow(),
+            Some(run) if run.success => "ok".green(),
+            Some(_) => "failed".red(),
+            None => "never run".dimmed(),
+        };
+        let output = match &job.output {
+            JobOutput::Stdout => "stdout".to_string(),
+            JobOutput::File { path, .. } => format!("file {}", path),
+            JobOutput::History { path } => format!("history {}", path),
+            JobOutput::Webhook { url } => format!("webhook {}", url),
+        };
+
+        println!("  {}  {:<16} next {}  last {}  {}", job.id.bold(), job.schedule, next, last, job.name);
+        println!("            -> {}", output.dimmed());
+    }
+    Ok(())
+}
+
+fn cancel_scheduled_job(job_id: &str) -> Result<()> {
+    let mut manager = SchedulingManager::open(&Config::schedule_dir()?)?;
+    let job = manager.cancel_task(job_id)?;
+    println!("{} job {} ({})", "Cancelled".yellow().bold(), job.id, job.name);
+    Ok(())
+}
+
+fn list_job_runs(job_id: Option<&str>) -> Result<()> {
+    let manager = SchedulingManager::open(&Config::schedule_dir()?)?;
+    let runs = manager.list_runs(job_id)?;
+    if runs.is_empty() {
+        println!("No recorded runs");
+        return Ok(());
+    }
+
+    for run in runs {
+        let status = if run.skipped {
+            "skipped".yellow()
+        } else if run.success {
+            "ok".green()
+        } else {
+            "failed".red()
+        };
+        let duration = (run.finished_at - run.started_at).num_milliseconds();
+        let detail = run
+            .error
+            .as_deref()
+            .or(run.output.as_deref())
+            .map(|text| preview(text, 60))
+            .unwrap_or_default();
+        println!(
+            "  {}  {}  {:<7} {:>6}ms{}  {}",
+            run.job_id,
+            run.scheduled_for.with_timezone(&chrono::Local).format("%Y-%m-%d %H:%M"),
+            status,
+            duration,
+            if run.missed { " (late)" } else { "" },
+            detail
+        );
+    }
+    Ok(())
+}
+
+async fn run_scheduled_job_now(job_id: &str, config: &Config) -> Result<()> {
+    let mut manager = SchedulingManager::open(&Config::schedule_dir()?)?;
+    let job = manager.get_job(job_id)?.clone();
+
+    let run = SchedulingManager::run_job(&job, config, Utc::now(), false).await;
+    manager.record_run(&run)?;
+    match run.error {
+        Some(error) => Err(EchomindError::Other(format!("Job {} failed: {}", job.id, error))),
+        None => Ok(()),
+    }
+}
+
+async fn run_daemon(interval: u64) -> Result<()> {
+    let dir = Config::schedule_dir()?;
+    let mut manager = SchedulingManager::open(&dir)?;
+    eprintln!(
+        "{} watching {} job(s) in {} (Ctrl+C to stop)",
+        "echomind daemon".cyan().bold(),
+        manager.list_scheduled_tasks().len(),
+        dir.display()
+    );
+
+    let mut config = Config::load()?;
+    loop {
+        // Pick up jobs added or cancelled by other invocations, and config edits.
+        // A bad read shouldn't stop the daemon; it carries on with what it had.
+        if let Err(e) = manager.reload() {
+            eprintln!("{} failed to reload jobs: {}", "[daemon]".dimmed(), e);
+        }
+        match Config::load() {
+            Ok(loaded) => config = loaded,
+            Err(e) => eprintln!("{} failed to reload config: {}", "[daemon]".dimmed(), e),
+        }
+
+        for due in manager.due_jobs(Utc::now()) {
+            let Ok(job) = manager.get_job(&due.job_id).cloned() else {
+                continue;
+            };
+
+            let run = if due.missed && !job.catch_up {
+                eprintln!("{} missed run of {} ({}), skipping", "[daemon]".dimmed(), job.id, job.name);
+                SchedulingManager::skipped_run(&job, due.scheduled_for)
+            } else {
+                let late = if due.missed { " (missed, catching up)" } else { "" };
+                eprintln!("{} running {} ({}){}", "[daemon]".dimmed(), job.id, job.name, late);
+                SchedulingManager::run_job(&job, &config, due.scheduled_for, due.missed).await
+            };
+
+            if let Some(error) = &run.error {
+                eprintln!("{} {} failed: {}", "[daemon]".dimmed(), job.id, error);
+            }
+            if let Err(e) = manager.record_run(&run) {
+                eprintln!("{} failed to record run of {}: {}", "[daemon]".dimmed(), job.id, e);
+            }
+        }
+
+        // Wake for the next occurrence if it comes before the next poll
+        let now = Utc::now();
+        let next_due = manager
+            .list_scheduled_tasks()
+            .iter()
+            .filter_map(|job| SchedulingManager::next_run(job, job.last_run.unwrap_or(job.created_at).max(now)))
+            .min();
+        let wait = next_due
+            .and_then(|next| (next - now).to_std().ok())
+            .map(|until| until.min(Duration::from_secs(interval)))
+            .unwrap_or(Duration::from_secs(interval));
+
+        tokio::select! {
+            _ = tokio::time::sleep(wait + Duration::from_millis(50)) => {}
+            _ = tokio::signal::ctrl_c() => {
+                eprintln!("{} stopping", "[daemon]".dimmed());
+                return Ok(());
+            }
+        }
+    }
+}
+
+fn print_workflow_summary(workflow: &workflow::Workflow, context: &workflow::WorkflowContext, elapsed: Duration) {
+    eprintln!("\n{} {}", "Workflow summary:".cyan().bold(), workflow.name);
+    eprintln!("{}", "─".repeat(80).bright_black());
+
+    for result in &context.history {
+        let name = workflow
+            .steps
+            .iter()
+            .find(|s| s.id == result.step_id)
+            .map(|s| s.name.as_str())
+            .unwrap_or("");
+        let status = if result.success { "✓".green() } else { "✗".red() };
+        let retries = if result.attempts > 1 {
+            format!(" ({} attempts)", result.attempts)
+        } else {
+            String::new()
+        };
+        let detail = match (&result.error, &result.output) {
+            (Some(err), _) => err.red().to_string(),
+            (None, Some(output)) => preview(output, 48),
+            (None, None) => String::new(),
+        };
+        eprintln!(
+            "{} {:<16} {:<24} {:>8}ms{}  {}",
+            status,
+            result.step_id,
+            preview(name, 24),
+            result.duration_ms,
+            retries,
+            detail.bright_black()
+        );
+
+        // Only failed branches/items are worth a line of their own
+        for (index, child) in result.children.iter().enumerate().filter(|(_, c)| !c.success) {
+            eprintln!(
+                "    {} {} #{} {}",
+                "✗".red(),
+                child.step_id,
+                index,
+                child.error.as_deref().unwrap_or("failed").red()
+            );
+        }
+    }
+
+    eprintln!("{}", "─".repeat(80).bright_black());
+    eprintln!(
+        "{} steps run, {} failed, {:.2}s total",
+        context.history.len(),
+        context.history.iter().filter(|r| !r.success).count(),
+        elapsed.as_secs_f64()
+    );
+}
+
+// Single-line preview of a possibly long, multi-line string
+fn preview(text: &str, max_chars: usize) -> String {
+    let line = text.lines().next().unwrap_or("").trim();
+    if line.chars().count() > max_chars || text.lines().count() > 1 {
+        let truncated: String = line.chars().take(max_chars.saturating_sub(3)).collect();
+        format!("{}...", truncated)
+    } else {
+        line.to_string()
+    }
+}
+
+// Build an API client from command-line overrides and config, without the
+// interactive key prompt used for single queries.
+fn build_client(args: &Args, config: &Config) -> Result<ApiClient> {
+    let provider_str = args.provider.as_ref().unwrap_or(&config.api.provider);
+    let provider = Provider::from_string(provider_str)?;
+    let api_key = args.api_key.clone().or(config.api.api_key.clone());
+    let timeout = args.timeout.unwrap_or(config.api.timeout);
+    ApiClient::new(provider, api_key, timeout)
+}
+
+fn check_internet() -> bool {
+    TcpStream::connect_timeout(&"8.8.8.8:53".parse().unwrap(), Duration::from_secs(5)).is_ok()
+}
+
+// Helper function to read from clipboard
+fn read_from_clipboard() -> Result<String> {
+    let mut clipboard = Clipboard::new()
+        .map_err(|e| EchomindError::Other(format!("Failed to access clipboard: {}", e)))?;
+
+    clipboard
+        .get_text()
+        .map_err(|e| EchomindError::Other(format!("Failed to read from clipboard: {}", e)))
+}
+
+// Helper function to write to clipboard
+fn write_to_clipboard(text: &str) -> Result<()> {
+    let mut clipboard = Clipboard::new()
+        .map_err(|e| EchomindError::Other(format!("Failed to access clipboard: {}", e)))?;
+
+    clipboard
+        .set_text(text)
+        .map_err(|e| EchomindError::Other(format!("Failed to write to clipboard: {}", e)))
+}
+
+// Copies the finished reply. ndjson events are for programs, so people get
+// the text instead.
+struct ClipboardSink {
+    format: OutputFormat,
+}
+
+impl OutputSink for ClipboardSink {
+    fn done(&mut self, reply: &Reply) -> Result<()> {
+        match self.format {
+            OutputFormat::Ndjson => write_to_clipboard(&reply.text),
+            _ => write_to_clipboard(&reply.formatted),
+        }
+    }
+}
+
+// Saves the exchange to the --history file, with the reply as the model
+// wrote it
+struct HistorySink {
+    file: String,
+    user_message: Message,
+}
+
+impl OutputSink for HistorySink {
+    fn done(&mut self, reply: &Reply) -> Result<()> {
+        let messages = [self.user_message.clone(), Message::text("assistant".to_string(), reply.raw.clone())];
+        save_history(&self.file, &messages, &reply.provider, &reply.model)
+    }
+}
+
+// Load conversation history
+fn load_history(history_file: &str) -> Result<Vec<Message>> {
+    if !std::path::Path::new(history_file).exists() {
+        return Ok(Vec::new());
+    }
+
+    let contents = fs::read_to_string(history_file)
+        .map_err(|e| EchomindError::FileError(format!("Failed to read history: {}", e)))?;
+
+    let entries: Vec<HistoryEntry> = serde_json::from_str(&contents)
+        .map_err(|e| EchomindError::ParseError(format!("Failed to parse history: {}", e)))?;
+
+    Ok(entries
+        .into_iter()
+        .map(|e| Message::text(e.role, e.content))
+        .collect())
+}
+
+// Save conversation history
+fn save_history(
+    history_file: &str,
+    messages: &[Message],
+    provider: &str,
+    model: &str,
+) -> Result<()> {
+    let entries: Vec<HistoryEntry> = messages
+        .iter()
+        .map(|msg| HistoryEntry {
+            timestamp: Utc::now(),
+            role: msg.role.clone(),
+            content: msg.get_text().unwrap_or("").to_string(),
+            provider: Some(provider.to_string()),
+            model: Some(model.to_string()),
+            // has_image: matches!(msg.content, api::MessageContent::MultiModal(_)),
+            has_image: false,
+        })
+        .collect();
+
+    let json = serde_json::to_string_pretty(&entries)
+        .map_err(|e| EchomindError::ParseError(format!("Failed to serialize history: {}", e)))?;
+
+    fs::write(history_file, json)
+        .map_err(|e| EchomindError::FileError(format!("Failed to write history: {}", e)))?;
+
+    Ok(())
+}
+
+// // Load image file and encode as base64
+// fn load_image_as_base64(path: &str) -> Result<String> {
+//     let data = fs::read(path).map_err(|e| EchomindError::FileError(format!("Failed to read image: {}", e)))?;
+//     Ok(general_purpose::STANDARD.encode(&data))
+// }
+
+// Format output based on format specification
+// Compare responses from multiple models
+async fn compare_models(input: &str, models_str: &str, args: &Args, config: &Config, system_prompt: Option<String>) -> Result<()> {
+    let references: Vec<&str> = models_str.split(',').map(str::trim).filter(|s| !s.is_empty()).collect();
+    if references.is_empty() {
+        return Err(EchomindError::Other(
+            "No models specified for comparison".to_string(),
+        ));
+    }
+    let targets = references
+        .iter()
+        .map(|reference| benchmark_target(reference, args, config))
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut messages = Vec::new();
+    if let Some(s_prompt) = system_prompt {
+        messages.push(Message::text("system".to_string(), s_prompt));
+    }
+    messages.push(Message::text("user".to_string(), input.trim().to_string()));
+    let request = ChatRequest {
+        messages,
+        model: None,
+        temperature: args.temperature.or(Some(config.defaults.temperature)),
+        max_tokens: args.max_tokens.or(config.defaults.max_tokens),
+        top_p: None,
+        top_k: None,
+        stream: None,
+    };
+
+    let mut usage = UsageMeter::for_config(config, "compare");
+    usage.set_preset(args.preset.clone());
+
+    let progress = if std::io::stderr().is_terminal() {
+        let pb = ProgressBar::new_spinner();
+        pb.set_style(ProgressStyle::default_spinner().template("{spinner:.cyan} {msg}").unwrap());
+        pb.set_message(format!("Asking {} models...", targets.len()));
+        pb.enable_steady_tick(Duration::from_millis(100));
+        Some(pb)
+    } else {
+        None
+    };
+    let entries = compare::run_comparison(&targets, &request, Some(&usage)).await;
+    for entry in &entries {
+        if let Some(warning) = &entry.budget_warning {
+            let print = || eprintln!("{} {}: {}", "Budget:".yellow().bold(), entry.model, warning);
+            match &progress {
+                Some(pb) => pb.suspend(print),
+                None => print(),
+            }
+        }
+    }
+    let mut comparison = Comparison {
+        input: input.trim().to_string(),
+        entries,
+        verdict: None,
+    };
+    if let Some(judge_ref) = &args.judge {
+        if let Some(pb) = &progress {
+            pb.set_message(format!("Judging with {}...", judge_ref));
+        }
+        let judge = benchmark_target(judge_ref, args, config)?;
+        match compare::judge(&judge, &comparison, Some(&usage)).await {
+            Ok(verdict) => {
+                if let Some(warning) = &verdict.budget_warning {
+                    let print = || eprintln!("{} {}: {}", "Budget:".yellow().bold(), verdict.judge, warning);
+                    match &progress {
+                        Some(pb) => pb.suspend(print),
+                        None => print(),
+                    }
+                }
+                comparison.verdict = Some(verdict);
+            }
+            Err(e) => eprintln!("{} The judge failed: {}", "Warning:".yellow(), e),
+        }
+    }
+    if let Some(pb) = progress {
+        pb.finish_and_clear();
+    }
+
+    if args.tui {
+        return tui::show_comparison(&comparison).map_err(EchomindError::from);
+    }
+    let rendered = match args.format.as_deref() {
+        Some("json") => serde_json::to_string_pretty(&comparison)?,
+        Some(other) if other != "text" => {
+            return Err(EchomindError::Other(format!("Comparisons can be printed as text or json, not '{}'", other)));
+        }
+        _ => {
+            // No escape codes in saved files
+            if args.output.is_some() {
+                colored::control::set_override(false);
+            }
+            render_comparison(&comparison, &args.diff)
+        }
+    };
+    match &args.output {
+        Some(outfile) => {
+            fs::write(outfile, format!("{}\n", rendered)).map_err(|e| EchomindError::FileError(e.to_string()))?;
+            println!("{} {}", "✅ Saved to".green(), outfile);
+        }
+        None => println!("{}", rendered),
+    }
+    Ok(())
+}
+
+fn render_comparison(comparison: &Comparison, diff_style: &str) -> String {
+    let rule = "─".repeat(80).bright_black().to_string();
+    let mut out = vec![
+        "=== Multi-Model Comparison ===".cyan().bold().to_string(),
+        format!("{}: {}\n", "Input".yellow(), comparison.input),
+    ];
+    for entry in &comparison.entries {
+        out.push(format!("{} {}", "Model:".green().bold(), entry.model));
+        out.push(rule.clone());
+        match (&entry.response, &entry.error) {
+            (Some(response), _) => out.push(response.trim_end().to_string()),
+            (None, error) => out.push(format!("{} {}", "Error:".red(), error.as_deref().unwrap_or("no response"))),
+        }
+        out.push(format!("{}\n", rule));
+    }
+
+    let responses = comparison.responses();
+    let reference = responses.first().map(|(_, response)| *response);
+    let ms = |value: Option<u64>| value.map(|v| format!("{} ms", v)).unwrap_or_else(|| "-".to_string());
+    let table = QueryResult {
+        columns: ["model", "status", "latency", "ttft", "in", "out", "cost", "match"]
+            .iter()
+            .map(|c| c.to_string())
+            .collect(),
+        rows: comparison
+            .entries
+            .iter()
+            .map(|entry| {
+                let score = comparison.verdict.as_ref().and_then(|v| v.scores.get(&entry.model));
+                let status = match (&entry.error, score) {
+                    (Some(_), _) => "error".to_string(),
+                    (None, Some(score)) => format!("ok ({}/10)", score),
+                    (None, None) => "ok".to_string(),
+                };
+                let similarity = match (reference, entry.response.as_deref()) {
+                    (Some(reference), Some(response)) => {
+                        format!("{:.0}%", compare::line_similarity(reference, response) * 100.0)
+                    }
+                    _ => "-".to_string(),
+                };
+                vec![
+                    serde_json::json!(entry.model),
+                    serde_json::json!(status),
+                    serde_json::json!(ms(Some(entry.latency_ms))),
+                    serde_json::json!(ms(entry.ttft_ms)),
+                    serde_json::json!(entry.input_tokens),
+                    serde_json::json!(entry.output_tokens),
+                    serde_json::json!(format!("${:.5}", entry.cost)),
+                    serde_json::json!(similarity),
+                ]
+            })
+            .collect(),
+        matched_rows: comparison.entries.len(),
+    };
+    out.push(render_table(&table));
+
+    if let Some(((first, reference), others)) = responses.split_first().filter(|_| diff_style != "none") {
+        for (entry, response) in others {
+            out.push(format!("\n{} {} → {}", "Diff:".cyan().bold(), first.model, entry.model));
+            if diff_style == "unified" {
+                let diff = compare::unified_diff(reference, response, &first.model, &entry.model);
+                if diff.is_empty() {
+                    out.push("(identical)".bright_black().to_string());
+                }
+                for line in diff.lines() {
+                    out.push(match line.chars().next() {
+                        Some('+') if !line.starts_with("+++") => line.green().to_string(),
+                        Some('-') if !line.starts_with("---") => line.red().to_string(),
+                        Some('@') => line.cyan().to_string(),
+                        _ => line.to_string(),
+                    });
+                }
+            } else {
+                out.extend(render_side_by_side(reference, response));
+            }
+        }
+    }
+
+    if let Some(verdict) = &comparison.verdict {
+        out.push(format!(
+            "\n{} {} ({})",
+            "Judge:".magenta().bold(),
+            verdict.winner.as_deref().unwrap_or("no winner"),
+            verdict.judge
+        ));
+        if !verdict.reason.is_empty() {
+            out.push(verdict.reason.clone());
+        }
+    }
+    out.join("\n")
+}
+
+// Two columns sized to the terminal, changed rows marked and coloured
+fn render_side_by_side(reference: &str, response: &str) -> Vec<String> {
+    let width = crossterm::terminal::size().map(|(w, _)| w as usize).unwrap_or(120).clamp(40, 240);
+    let column = (width - 3) / 2;
+    let fit = |text: &str| {
+        let text: String = if text.chars().count() > column {
+            text.chars().take(column.saturating_sub(1)).chain(std::iter::once('…')).collect()
+        } else {
+            text.to_string()
+        };
+        format!("{:<width$}", text, width = column)
+    };
+    compare::side_by_side(reference, response)
+        .iter()
+        .map(|row| {
+            let left = fit(row.left.as_deref().unwrap_or(""));
+            let right = fit(row.right.as_deref().unwrap_or(""));
+            if row.changed {
+                format!("{} {} {}", left.red(), "│".yellow(), right.green())
+            } else {
+                format!("{} {} {}", left, "│".bright_black(), right)
+            }
+        })
+        .collect()
+}
+
 pub async fn run_interactive(args: Args, config: Config, initial_messages: Vec<Message>, system_prompt: Option<String>) -> Result<()> {
     let api_key = args.api_key.or(config.api.api_key.clone());
     let timeout = args.timeout.unwrap_or(config.api.timeout);
@@ -715,6 +2974,8 @@ pub async fn run_interactive(args: Args, config: Config, initial_messages: Vec<M
         Provider::from_string(&config.api.provider).unwrap_or(Provider::Chat)
     };
     let client = ApiClient::new(provider, api_key, timeout)?;
+    let mut usage = UsageMeter::for_config(&config, "interactive");
+    usage.set_preset(args.preset.clone());
     let mut repl = repl::Repl::new(
         client,
         config,
@@ -725,5 +2986,6 @@ pub async fn run_interactive(args: Args, config: Config, initial_messages: Vec<M
         initial_messages,
         system_prompt,
     );
+    repl.set_usage_meter(usage);
     repl.run().await
 }
@@ -1,6 +1,7 @@
 use crate::api::{ApiClient, ChatRequest, Message};
 use crate::config::Config;
 use crate::error::Result;
+use crate::features::usage::UsageMeter;
 use colored::Colorize;
 use rustyline::error::ReadlineError;
 use rustyline::DefaultEditor;
@@ -12,9 +13,11 @@ pub struct Repl {
     max_tokens: Option<u32>,
     model: String,
     stream: bool,
+    usage: Option<UsageMeter>,
 }
 
 impl Repl {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         client: ApiClient,
         config: Config,
@@ -39,9 +42,15 @@ impl Repl {
             max_tokens: max_tokens.or(config.defaults.max_tokens),
             model: model.unwrap_or(config.api.model.clone()),
             stream,
+            usage: None,
         }
     }
 
+    /// Checks each message against the budget and records it in the ledger.
+    pub fn set_usage_meter(&mut self, usage: UsageMeter) {
+        self.usage = Some(usage);
+    }
+
     pub async fn run(&mut self) -> Result<()> {
         println!("{}", "=== Echomind Interactive Mode ===".cyan().bold());
         println!(
@@ -93,20 +102,39 @@ impl Repl {
                         stream: if self.stream { Some(true) } else { None },
                     };
 
-                    print!("{} ", "Assistant:".blue().bold());
-
-                    let response = if self.stream {
-                        self.client
-                            .send_message_stream(request, |chunk| {
-                                print!("{}", chunk);
-                                use std::io::Write;
-                                std::io::stdout().flush().unwrap();
-                            })
-                            .await?
-                    } else {
-                        let resp = self.client.send_message(request).await?;
-                        println!("{}", resp);
-                        resp
+                    let (client, stream) = (&self.client, self.stream);
+                    let mut sent = false;
+                    let response = UsageMeter::send(self.usage.as_ref(), client.provider().name(), &request, |warning| {
+                        if let Some(warning) = warning {
+                            eprintln!("{} {}", "Budget:".yellow().bold(), warning);
+                        }
+                        sent = true;
+                        print!("{} ", "Assistant:".blue().bold());
+                        let request = request.clone();
+                        async move {
+                            if stream {
+                                client
+                                    .send_message_stream(request, |chunk| {
+                                        print!("{}", chunk);
+                                        use std::io::Write;
+                                        std::io::stdout().flush().unwrap();
+                                    })
+                                    .await
+                            } else {
+                                client.send_message(request).await.inspect(|resp| println!("{}", resp))
+                            }
+                        }
+                    })
+                    .await;
+                    let response = match response {
+                        Err(e) if !sent => {
+                            // Over a blocking budget: drop the message so the
+                            // conversation stays as it was
+                            self.conversation.pop();
+                            eprintln!("{} {}\n", "Error:".red().bold(), e);
+                            continue;
+                        }
+                        response => response?,
                     };
 
                     if self.stream {
@@ -2,6 +2,9 @@ use crate::api::{Provider};
 use crate::cli::Args;
 use crate::config::Config;
 use crate::error::Result;
+use echomind::features::charts::Chart;
+use echomind::features::compare::{self, Comparison};
+use echomind::features::usage::UsageMeter;
 use crossterm::{
     event::{self, Event, KeyCode, KeyModifiers},
     // execute,
@@ -18,9 +21,9 @@ use ratatui::{
     Frame, Terminal,
 };
 use ring::aead::{Aad, LessSafeKey, Nonce, UnboundKey, AES_256_GCM};
-use serde_json;
 use std::fs;
 use std::io;
+use std::sync::Arc;
 use tokio::sync::mpsc;
 // use tokio::task;
 
@@ -38,7 +41,6 @@ struct Message {
     timestamp: u64,
 }
 
-#[derive(Debug)]
 pub struct App {
     state: AppState,
     input: String,
@@ -52,6 +54,7 @@ pub struct App {
     history_index: Option<usize>,
     config: Config,
     args: Args,
+    usage: Option<Arc<UsageMeter>>,
 }
 
 impl App {
@@ -76,10 +79,16 @@ impl App {
             history_index: None,
             config,
             args,
+            usage: None,
         }
 
     }
 
+    /// Checks each query against the budget and records it in the ledger.
+    pub fn set_usage_meter(&mut self, usage: UsageMeter) {
+        self.usage = Some(Arc::new(usage));
+    }
+
 }
 
 fn save_chat_history(app: &App) -> Result<()> {
@@ -143,11 +152,12 @@ pub async fn run_app<B: Backend>(terminal: &mut Terminal<B>, mut app: App) -> io
             let stream = app.stream;
             let config = app.config.clone();
             let args = app.args.clone();
+            let usage = app.usage.clone();
             let tx_process = tx.clone();
             let tx_error = tx.clone();
 
             tokio::spawn(async move {
-                if let Err(e) = process_query(input, provider, model, temperature, max_tokens, stream, config, args, tx_process).await {
+                if let Err(e) = process_query(input, provider, model, temperature, max_tokens, stream, config, args, usage, tx_process).await {
                     let _ = tx_error.send(format!("Error: {:?}", e));
                 }
             });
@@ -155,41 +165,39 @@ pub async fn run_app<B: Backend>(terminal: &mut Terminal<B>, mut app: App) -> io
             app.state = AppState::Response;
         }
 
-        if let Ok(event) = event::read() {
-            match event {
-                Event::Key(key) => {
-                    if key.modifiers.contains(KeyModifiers::CONTROL) {
-                        match key.code {
-                            KeyCode::Char('t') => {
-                                // Cycle temperature
-                                app.temperature = match app.temperature {
-                                    0.1 => 0.5,
-                                    0.5 => 1.0,
-                                    _ => 0.1,
-                                };
-                            }
-                            KeyCode::Char('s') => {
-                                // Toggle stream
-                                app.stream = !app.stream;
-                            }
-                            KeyCode::Char('h') => {
-                                // Clear history
-                                app.history.clear();
-                                app.history_index = None;
-                            }
-                            KeyCode::Char('r') => {
-                                // Clear messages
-                                app.messages.clear();
-                                app.state = AppState::Input;
-                            }
-                            KeyCode::Char('q') => {
-                                // Quit
-                                return Ok(());
-                            }
-                            _ => {}
-                        }
-                    } else {
-                        match key.code {
+        if let Ok(Event::Key(key)) = event::read() {
+            if key.modifiers.contains(KeyModifiers::CONTROL) {
+                match key.code {
+                    KeyCode::Char('t') => {
+                        // Cycle temperature
+                        app.temperature = match app.temperature {
+                            0.1 => 0.5,
+                            0.5 => 1.0,
+                            _ => 0.1,
+                        };
+                    }
+                    KeyCode::Char('s') => {
+                        // Toggle stream
+                        app.stream = !app.stream;
+                    }
+                    KeyCode::Char('h') => {
+                        // Clear history
+                        app.history.clear();
+                        app.history_index = None;
+                    }
+                    KeyCode::Char('r') => {
+                        // Clear messages
+                        app.messages.clear();
+                        app.state = AppState::Input;
+                    }
+                    KeyCode::Char('q') => {
+                        // Quit
+                        return Ok(());
+                    }
+                    _ => {}
+                }
+            } else {
+                match key.code {
                     KeyCode::Enter => {
                         if let AppState::Input = app.state {
                             if !app.input.is_empty() {
@@ -205,50 +213,47 @@ pub async fn run_app<B: Backend>(terminal: &mut Terminal<B>, mut app: App) -> io
                             }
                         }
                     }
-                            KeyCode::Char(c) => {
-                                if let AppState::Input = app.state {
-                                    app.input.push(c);
-                                    app.history_index = None; // Reset history navigation on typing
-                                }
-                            }
-                            KeyCode::Backspace => {
-                                if let AppState::Input = app.state {
-                                    app.input.pop();
-                                    app.history_index = None; // Reset history navigation on typing
-                                }
-                            }
-                            KeyCode::Up => {
-                                if let AppState::Input = app.state {
-                                    if !app.history.is_empty() {
-                                        let idx = app.history_index.unwrap_or(app.history.len());
-                                        if idx > 0 {
-                                            app.history_index = Some(idx - 1);
-                                            app.input = app.history[app.history_index.unwrap()].clone();
-                                        }
-                                    }
+                    KeyCode::Char(c) => {
+                        if let AppState::Input = app.state {
+                            app.input.push(c);
+                            app.history_index = None; // Reset history navigation on typing
+                        }
+                    }
+                    KeyCode::Backspace => {
+                        if let AppState::Input = app.state {
+                            app.input.pop();
+                            app.history_index = None; // Reset history navigation on typing
+                        }
+                    }
+                    KeyCode::Up => {
+                        if let AppState::Input = app.state {
+                            if !app.history.is_empty() {
+                                let idx = app.history_index.unwrap_or(app.history.len());
+                                if idx > 0 {
+                                    app.history_index = Some(idx - 1);
+                                    app.input = app.history[app.history_index.unwrap()].clone();
                                 }
                             }
-                            KeyCode::Down => {
-                                if let AppState::Input = app.state {
-                                    if let Some(idx) = app.history_index {
-                                        if idx + 1 < app.history.len() {
-                                            app.history_index = Some(idx + 1);
-                                            app.input = app.history[idx + 1].clone();
-                                        } else {
-                                            app.history_index = None;
-                                            app.input.clear();
-                                        }
-                                    }
+                        }
+                    }
+                    KeyCode::Down => {
+                        if let AppState::Input = app.state {
+                            if let Some(idx) = app.history_index {
+                                if idx + 1 < app.history.len() {
+                                    app.history_index = Some(idx + 1);
+                                    app.input = app.history[idx + 1].clone();
+                                } else {
+                                    app.history_index = None;
+                                    app.input.clear();
                                 }
                             }
-                            KeyCode::Esc => {
-                                return Ok(());
-                            }
-                            _ => {}
                         }
                     }
+                    KeyCode::Esc => {
+                        return Ok(());
+                    }
+                    _ => {}
                 }
-                _ => {}
             }
         }
 
@@ -266,6 +271,145 @@ pub async fn run_app<B: Backend>(terminal: &mut Terminal<B>, mut app: App) -> io
     }
 }
 
+/// Shows a chart full screen until a key is pressed.
+pub fn show_chart(chart: &Chart) -> io::Result<()> {
+    use crossterm::{execute, terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen}};
+    use ratatui::backend::CrosstermBackend;
+
+    enable_raw_mode()?;
+    let mut stdout = io::stdout();
+    execute!(stdout, EnterAlternateScreen)?;
+    let mut terminal = Terminal::new(CrosstermBackend::new(stdout))?;
+
+    let result = (|| -> io::Result<()> {
+        loop {
+            terminal.draw(|f| {
+                let chunks = Layout::default()
+                    .direction(Direction::Vertical)
+                    .constraints([Constraint::Min(1), Constraint::Length(1)])
+                    .split(f.size());
+                chart.render(f, chunks[0]);
+                let footer = Paragraph::new("Press any key to close").style(Style::default().fg(Color::Gray));
+                f.render_widget(footer, chunks[1]);
+            })?;
+            if let Event::Key(_) = event::read()? {
+                return Ok(());
+            }
+        }
+    })();
+
+    disable_raw_mode()?;
+    execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
+    terminal.show_cursor()?;
+    result
+}
+
+/// Shows `--compare` responses in side-by-side panes with a shared scroll.
+/// Lines that differ from the first response are highlighted.
+pub fn show_comparison(comparison: &Comparison) -> io::Result<()> {
+    use crossterm::{execute, terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen}};
+    use ratatui::backend::CrosstermBackend;
+
+    let reference = comparison.responses().first().map(|(_, response)| response.to_string());
+    let panes: Vec<(String, Vec<(String, bool)>)> = comparison
+        .entries
+        .iter()
+        .enumerate()
+        .map(|(i, entry)| {
+            let lines = match (&entry.response, &reference) {
+                (Some(response), Some(reference)) if i > 0 => compare::changed_lines(reference, response),
+                (Some(response), _) => response.lines().map(|line| (line.to_string(), false)).collect(),
+                (None, _) => vec![(format!("Error: {}", entry.error.as_deref().unwrap_or("no response")), true)],
+            };
+            let title = format!(
+                " {} · {} ms · {} tok · ${:.5} ",
+                entry.model, entry.latency_ms, entry.output_tokens, entry.cost
+            );
+            (title, lines)
+        })
+        .collect();
+    let verdict = comparison.verdict.as_ref().map(|verdict| {
+        format!(
+            "Judge {}: {}. {}",
+            verdict.judge,
+            verdict.winner.as_deref().unwrap_or("no winner"),
+            verdict.reason
+        )
+    });
+
+    enable_raw_mode()?;
+    let mut stdout = io::stdout();
+    execute!(stdout, EnterAlternateScreen)?;
+    let mut terminal = Terminal::new(CrosstermBackend::new(stdout))?;
+
+    let mut scroll: u16 = 0;
+    let mut highlight = true;
+    let result = (|| -> io::Result<()> {
+        loop {
+            terminal.draw(|f| {
+                let chunks = Layout::default()
+                    .direction(Direction::Vertical)
+                    .constraints([
+                        Constraint::Min(1),
+                        Constraint::Length(if verdict.is_some() { 3 } else { 0 }),
+                        Constraint::Length(1),
+                    ])
+                    .split(f.size());
+                let columns = Layout::default()
+                    .direction(Direction::Horizontal)
+                    .constraints(vec![Constraint::Ratio(1, panes.len().max(1) as u32); panes.len()])
+                    .split(chunks[0]);
+                for ((title, lines), area) in panes.iter().zip(columns.iter()) {
+                    let text: Vec<Line> = lines
+                        .iter()
+                        .map(|(line, changed)| {
+                            let style = if *changed && highlight {
+                                Style::default().fg(Color::Yellow)
+                            } else {
+                                Style::default()
+                            };
+                            Line::from(Span::styled(line.clone(), style))
+                        })
+                        .collect();
+                    let pane = Paragraph::new(text)
+                        .block(Block::default().borders(Borders::ALL).title(title.clone()).border_style(Style::default().fg(Color::Cyan)))
+                        .wrap(Wrap { trim: false })
+                        .scroll((scroll, 0));
+                    f.render_widget(pane, *area);
+                }
+                if let Some(verdict) = &verdict {
+                    let judge = Paragraph::new(verdict.clone())
+                        .block(Block::default().borders(Borders::ALL).title(" Verdict").border_style(Style::default().fg(Color::Magenta)))
+                        .wrap(Wrap { trim: true });
+                    f.render_widget(judge, chunks[1]);
+                }
+                let footer = Paragraph::new("↑/↓ PgUp/PgDn: scroll | d: toggle highlighting | q: quit")
+                    .style(Style::default().fg(Color::Gray));
+                f.render_widget(footer, chunks[2]);
+            })?;
+            if let Event::Key(key) = event::read()? {
+                match key.code {
+                    KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                    KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => return Ok(()),
+                    KeyCode::Char('d') => highlight = !highlight,
+                    KeyCode::Up => scroll = scroll.saturating_sub(1),
+                    KeyCode::Down => scroll = scroll.saturating_add(1),
+                    KeyCode::PageUp => scroll = scroll.saturating_sub(10),
+                    KeyCode::PageDown => scroll = scroll.saturating_add(10),
+                    KeyCode::Home => scroll = 0,
+                    _ => {}
+                }
+            }
+        }
+    })();
+
+    disable_raw_mode()?;
+    execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
+    terminal.show_cursor()?;
+    result
+}
+
+#[allow(clippy::too_many_arguments)]
 async fn process_query(
     input: String,
     provider: Provider,
@@ -275,6 +419,7 @@ async fn process_query(
     stream: bool,
     config: Config,
     args: Args,
+    usage: Option<Arc<UsageMeter>>,
     tx: mpsc::UnboundedSender<String>,
 ) -> Result<()> {
     use crate::api::{ApiClient, Message, ChatRequest};
@@ -296,15 +441,23 @@ async fn process_query(
         stream: Some(stream),
     };
 
-    let content = if stream {
-        let mut full_response = String::new();
-        client.send_message_stream(request, |chunk| {
-            full_response.push_str(&chunk);
-            let _ = tx.send(full_response.clone());
-        }).await?
-    } else {
-        client.send_message(request).await?
-    };
+    let content = UsageMeter::send(usage.as_deref(), client.provider().name(), &request, |warning| {
+        if let Some(warning) = warning {
+            let _ = tx.send(format!("Budget: {}", warning));
+        }
+        let (client, tx, request) = (&client, &tx, request.clone());
+        async move {
+            if stream {
+                let mut full_response = String::new();
+                client.send_message_stream(request, |chunk| {
+                    full_response.push_str(chunk);
+                    let _ = tx.send(full_response.clone());
+                }).await
+            } else {
+                client.send_message(request).await
+            }
+        }
+    }).await?;
 
     let _ = tx.send(content);
     Ok(())
@@ -381,7 +534,7 @@ fn ui(f: &mut Frame, app: &mut App) {
 
     let mut lines = Vec::new();
     for message in &app.messages {
-        let timestamp = std::time::SystemTime::now().duration_since(message.timestamp).unwrap().as_secs();
+        let timestamp = std::time::SystemTime::now().duration_since(std::time::UNIX_EPOCH).unwrap().as_secs().saturating_sub(message.timestamp);
         let time_str = if timestamp < 60 {
             format!("{}s ago", timestamp)
         } else if timestamp < 3600 {
@@ -408,8 +561,6 @@ fn ui(f: &mut Frame, app: &mut App) {
         }
         lines.push(Line::raw("")); // Empty line between messages
     }
-        lines.push(Line::raw("")); // Empty line between messages
-    }
 
     match app.state {
         AppState::Input => {
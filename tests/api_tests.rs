@@ -118,6 +118,8 @@ fn test_chat_request_serialization() {
         model: Some("gpt-4".to_string()),
         temperature: Some(0.7),
         max_tokens: Some(1000),
+        top_p: None,
+        top_k: None,
         stream: None,
     };
 
@@ -137,6 +139,8 @@ fn test_chat_request_optional_fields() {
         model: None,
         temperature: None,
         max_tokens: None,
+        top_p: None,
+        top_k: None,
         stream: None,
     };
 
@@ -0,0 +1,206 @@
+use echomind::api::{ChatRequest, Message, Provider};
+use echomind::features::batch::BatchRecord;
+use echomind::features::batch_api::{self, BatchApiClient, BatchJobStore, BATCH_PRICE_FACTOR};
+use echomind::features::performance::estimate_cost;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+fn template() -> ChatRequest {
+    ChatRequest {
+        messages: vec![Message::text("system".to_string(), "Be brief.".to_string())],
+        model: Some("gpt-4o-mini".to_string()),
+        temperature: Some(0.2),
+        max_tokens: Some(100),
+        top_p: None,
+        top_k: None,
+        stream: None,
+    }
+}
+
+fn records() -> Vec<BatchRecord> {
+    let record = |id: &str, input: &str| BatchRecord {
+        id: id.to_string(),
+        input: input.to_string(),
+        ..Default::default()
+    };
+    vec![
+        BatchRecord {
+            vars: HashMap::from([("name".to_string(), json!("Ada"))]),
+            ..record("r1", "Greet {{ name }}")
+        },
+        BatchRecord {
+            model: Some("gpt-4o".to_string()),
+            ..record("r2", "Write a haiku")
+        },
+        record("r3", "Count to three"),
+    ]
+}
+
+#[test]
+fn test_build_requests_and_base_urls() {
+    let (jsonl, inputs) = batch_api::build_requests(&records(), None, &template()).unwrap();
+    let lines: Vec<Value> = jsonl.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+    assert_eq!(lines.len(), 3);
+    assert_eq!(lines[0]["custom_id"], "r1");
+    assert_eq!(lines[0]["url"], "/v1/chat/completions");
+    assert_eq!(
+        lines[0]["body"],
+        json!({
+            "model": "gpt-4o-mini",
+            "messages": [{"role": "system", "content": "Be brief."}, {"role": "user", "content": "Greet Ada"}],
+            "temperature": 0.2f32,
+            "max_tokens": 100
+        })
+    );
+    assert_eq!(lines[1]["body"]["model"], "gpt-4o");
+    assert_eq!((inputs[0].input.as_str(), inputs[1].model.as_str()), ("Greet Ada", "gpt-4o"));
+
+    let broken = vec![BatchRecord {
+        id: "t".to_string(),
+        template: Some("missing".to_string()),
+        ..Default::default()
+    }];
+    let err = batch_api::build_requests(&broken, None, &template()).unwrap_err();
+    assert_eq!(err.to_string(), "Record 't': Template 'missing' not found");
+
+    assert_eq!(BatchApiClient::base_url_for(&Provider::OpenAI).unwrap(), "https://api.openai.com/v1");
+    let custom = Provider::from_string("http://gateway:8080/v1/chat/completions").unwrap();
+    assert_eq!(BatchApiClient::base_url_for(&custom).unwrap(), "http://gateway:8080/v1");
+    assert!(BatchApiClient::base_url_for(&Provider::Claude).is_err());
+}
+
+#[tokio::test]
+async fn test_submit_poll_fetch_and_join() {
+    let mut server = mockito::Server::new_async().await;
+    let upload = server
+        .mock("POST", "/v1/files")
+        .match_header("authorization", "Bearer key")
+        .match_body(mockito::Matcher::AllOf(vec![
+            mockito::Matcher::Regex("name=\"purpose\"\r\n\r\nbatch".to_string()),
+            mockito::Matcher::Regex("filename=\"tickets.jsonl\"".to_string()),
+            mockito::Matcher::Regex("\"custom_id\":\"r3\"".to_string()),
+        ]))
+        .with_status(200)
+        .with_body(json!({"id": "file-in", "object": "file"}).to_string())
+        .create_async()
+        .await;
+    let create = server
+        .mock("POST", "/v1/batches")
+        .match_body(mockito::Matcher::PartialJson(json!({
+            "input_file_id": "file-in",
+            "endpoint": "/v1/chat/completions",
+            "completion_window": "24h"
+        })))
+        .with_status(200)
+        .with_body(json!({"id": "batch_abc", "status": "validating"}).to_string())
+        .create_async()
+        .await;
+    // In progress on the first poll, done on the second
+    let polls = Arc::new(AtomicUsize::new(0));
+    let counter = polls.clone();
+    server
+        .mock("GET", "/v1/batches/batch_abc")
+        .with_status(200)
+        .with_body_from_request(move |_| {
+            let body = if counter.fetch_add(1, Ordering::SeqCst) == 0 {
+                json!({"id": "batch_abc", "status": "in_progress", "request_counts": {"total": 3, "completed": 1, "failed": 0}})
+            } else {
+                json!({
+                    "id": "batch_abc",
+                    "status": "completed",
+                    "output_file_id": "file-out",
+                    "error_file_id": "file-err",
+                    "request_counts": {"total": 3, "completed": 1, "failed": 1}
+                })
+            };
+            body.to_string().into_bytes()
+        })
+        .create_async()
+        .await;
+    let output = json!({
+        "id": "req-1",
+        "custom_id": "r1",
+        "response": {
+            "status_code": 200,
+            "body": {
+                "choices": [{"message": {"role": "assistant", "content": "Hello, Ada!"}}],
+                "usage": {"prompt_tokens": 20, "completion_tokens": 4}
+            }
+        },
+        "error": null
+    });
+    let errors = json!({
+        "id": "req-2",
+        "custom_id": "r2",
+        "response": {"status_code": 400, "body": {"error": {"message": "model not found"}}},
+        "error": null
+    });
+    server
+        .mock("GET", "/v1/files/file-out/content")
+        .with_status(200)
+        .with_body(format!("{}\n", output))
+        .create_async()
+        .await;
+    server
+        .mock("GET", "/v1/files/file-err/content")
+        .with_status(200)
+        .with_body(format!("{}\n", errors))
+        .create_async()
+        .await;
+
+    let api = BatchApiClient::new(&format!("{}/v1/", server.url()), Some("key".to_string()));
+    let mut job = batch_api::submit(&api, "openai", &records(), None, &template(), "/data/tickets.jsonl")
+        .await
+        .unwrap();
+    upload.assert_async().await;
+    create.assert_async().await;
+    assert_eq!((job.id.as_str(), job.status.as_str(), job.input_file_id.as_str()), ("batch_abc", "validating", "file-in"));
+    assert!(batch_api::fetch_results(&api, &job).await.unwrap_err().to_string().contains("still validating"));
+
+    // The job survives a reload of the store
+    let dir = tempfile::tempdir().unwrap();
+    let mut store = BatchJobStore::open(dir.path()).unwrap();
+    store.save_job(&job).unwrap();
+    let store = BatchJobStore::open(dir.path()).unwrap();
+    assert_eq!(store.get("batch_a").unwrap().inputs.len(), 3);
+    assert!(store.get("nope").is_err());
+
+    let mut seen = Vec::new();
+    batch_api::wait(&api, &mut job, Duration::from_millis(10), |job| seen.push(job.status.clone()))
+        .await
+        .unwrap();
+    assert_eq!(seen, vec!["in_progress", "completed"]);
+    assert_eq!(job.request_counts.failed, 1);
+
+    let results = batch_api::fetch_results(&api, &job).await.unwrap();
+    let ids: Vec<&str> = results.iter().map(|r| r.id.as_str()).collect();
+    assert_eq!(ids, vec!["r1", "r2", "r3"]);
+    assert_eq!(results[0].output.as_deref(), Some("Hello, Ada!"));
+    assert_eq!(results[0].input, "Greet Ada");
+    let usage = results[0].usage.as_ref().unwrap();
+    assert_eq!((usage.input_tokens, usage.output_tokens), (20, 4));
+    assert_eq!(usage.cost, estimate_cost("openai", "gpt-4o-mini", 20, 4) * BATCH_PRICE_FACTOR);
+    assert_eq!(results[1].error.as_deref(), Some("HTTP 400: model not found"));
+    assert_eq!(results[2].error.as_deref(), Some("No result returned (batch completed)"));
+}
+
+#[tokio::test]
+async fn test_api_errors_surface_with_status() {
+    let mut server = mockito::Server::new_async().await;
+    server
+        .mock("POST", "/v1/files")
+        .with_status(401)
+        .with_body("{\"error\": {\"message\": \"bad key\"}}")
+        .create_async()
+        .await;
+    let api = BatchApiClient::new(&format!("{}/v1", server.url()), Some("wrong".to_string()));
+    let err = batch_api::submit(&api, "openai", &records(), None, &template(), "tickets.jsonl")
+        .await
+        .unwrap_err();
+    assert_eq!(err.kind(), "ApiError");
+    assert!(err.to_string().contains("401"), "{}", err);
+    assert!(batch_api::submit(&api, "openai", &[], None, &template(), "empty.jsonl").await.is_err());
+}
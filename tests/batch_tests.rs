@@ -0,0 +1,212 @@
+mod common;
+
+use common::{client, reply};
+use echomind::api::{ChatRequest, Message};
+use echomind::config::{BudgetAction, BudgetConfig};
+use echomind::features::batch::{self, BatchCheckpoint, BatchOptions, BatchRecord, BatchResult};
+use echomind::features::performance::estimate_tokens;
+use echomind::features::usage::{UsageLedger, UsageMeter};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+fn template(system: &str) -> ChatRequest {
+    ChatRequest {
+        messages: vec![Message::text("system".to_string(), system.to_string())],
+        model: Some("test-model".to_string()),
+        temperature: Some(0.0),
+        max_tokens: None,
+        top_p: None,
+        top_k: None,
+        stream: None,
+    }
+}
+
+fn options(concurrency: usize, retries: u32) -> BatchOptions {
+    BatchOptions {
+        concurrency,
+        retries,
+        backoff: Duration::from_millis(10),
+    }
+}
+
+/// Answers every request after 200ms. mockito serves one response at a
+/// time, so it can't show requests overlapping.
+async fn slow_server() -> String {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        while let Ok((mut socket, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let mut request = vec![0u8; 64 * 1024];
+                let _ = socket.read(&mut request).await;
+                tokio::time::sleep(Duration::from_millis(200)).await;
+                let body = reply("done");
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                let _ = socket.write_all(response.as_bytes()).await;
+            });
+        }
+    });
+    format!("http://{}", address)
+}
+
+#[test]
+fn test_load_records_mixes_plain_lines_and_json() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("batch.jsonl");
+    std::fs::write(
+        &path,
+        r#"# tickets
+What is {braces}?
+{"id": "t-1", "input": "Summarize ticket {{ ticket.id }}: {{ ticket.body }}", "vars": {"ticket": {"id": 7, "body": "printer on fire"}}}
+
+{"input": "Translate hello", "system": "You are a translator.", "model": "gpt-4o-mini"}
+"#,
+    )
+    .unwrap();
+    let records = batch::load_records(&path.to_string_lossy()).unwrap();
+    let ids: Vec<&str> = records.iter().map(|r| r.id.as_str()).collect();
+    assert_eq!(ids, vec!["2", "t-1", "5"]);
+
+    // Plain lines keep their braces; records with vars are rendered
+    assert_eq!(records[0].prompt(None).unwrap(), "What is {braces}?");
+    assert_eq!(records[1].prompt(None).unwrap(), "Summarize ticket 7: printer on fire");
+
+    let base = template("Be brief.").messages;
+    let messages = records[2].messages(&base, None).unwrap();
+    assert_eq!(messages.len(), 2);
+    assert_eq!(messages[0].get_text(), Some("You are a translator."));
+    assert_eq!(records[1].messages(&base, None).unwrap()[0].get_text(), Some("Be brief."));
+    assert_eq!(records[2].model.as_deref(), Some("gpt-4o-mini"));
+
+    let missing = BatchRecord {
+        template: Some("nope".to_string()),
+        ..Default::default()
+    };
+    assert_eq!(missing.prompt(None).unwrap_err().to_string(), "Template 'nope' not found");
+
+    std::fs::write(&path, "{\"id\": \"a\", \"input\": \"x\"}\n{\"id\": \"a\", \"input\": \"y\"}\n").unwrap();
+    let err = batch::load_records(&path.to_string_lossy()).unwrap_err();
+    assert!(err.to_string().contains("Batch line 2: id 'a' is used more than once"), "{}", err);
+    std::fs::write(&path, "{\"input\": \n").unwrap();
+    assert!(batch::load_records(&path.to_string_lossy()).unwrap_err().to_string().contains("Batch line 1"));
+}
+
+#[tokio::test]
+async fn test_batch_runs_concurrently_and_retries_per_record() {
+    let mut server = mockito::Server::new_async().await;
+    // Garbage on the first call, a real answer on the second
+    let calls = Arc::new(AtomicUsize::new(0));
+    let flaky_calls = calls.clone();
+    server
+        .mock("POST", "/chat")
+        .match_body(mockito::Matcher::Regex("flaky".to_string()))
+        .with_status(200)
+        .with_body_from_request(move |_| {
+            if flaky_calls.fetch_add(1, Ordering::SeqCst) == 0 {
+                b"not json".to_vec()
+            } else {
+                reply("recovered").into_bytes()
+            }
+        })
+        .create_async()
+        .await;
+    server
+        .mock("POST", "/chat")
+        .match_body(mockito::Matcher::Regex("broken".to_string()))
+        .with_status(500)
+        .with_body("boom")
+        .expect(2)
+        .create_async()
+        .await;
+
+    let client = client(format!("{}/chat", server.url()));
+    let slow_client = self::client(format!("{}/chat", slow_server().await));
+    let slow: Vec<BatchRecord> = (1..=4)
+        .map(|i| BatchRecord {
+            id: format!("s{}", i),
+            input: format!("slow {}", i),
+            ..Default::default()
+        })
+        .collect();
+    let started = Instant::now();
+    let results = batch::run_batch(&slow_client, &slow, None, &template("sys"), options(4, 0), None, |_| {}).await;
+    // Four 200ms requests side by side rather than 800ms in a row
+    assert!(started.elapsed() < Duration::from_millis(600), "{:?}", started.elapsed());
+    assert!(results.iter().all(BatchResult::succeeded));
+    assert!(results.iter().all(|r| r.budget_warning.is_none()));
+
+    // Over a warn-only budget records still run, carrying the warning
+    let dir = tempfile::tempdir().unwrap();
+    let budget = BudgetConfig {
+        daily: Some(0.0),
+        action: BudgetAction::Warn,
+        ..Default::default()
+    };
+    let meter = UsageMeter::new(Some(UsageLedger::new(dir.path().join("usage.jsonl"))), budget, "batch");
+    let results = batch::run_batch(&slow_client, &slow[..2], None, &template("sys"), options(2, 0), Some(&meter), |_| {}).await;
+    assert!(results.iter().all(BatchResult::succeeded));
+    let warning = results[0].budget_warning.as_deref().unwrap();
+    assert!(warning.contains("daily budget"), "{}", warning);
+    assert_eq!(UsageLedger::new(dir.path().join("usage.jsonl")).records().unwrap().len(), 2);
+
+    let records = vec![
+        BatchRecord { id: "flaky".to_string(), input: "flaky".to_string(), ..Default::default() },
+        BatchRecord { id: "broken".to_string(), input: "broken".to_string(), ..Default::default() },
+        BatchRecord { id: "unrendered".to_string(), template: Some("missing".to_string()), ..Default::default() },
+    ];
+    let mut seen = Vec::new();
+    let results =
+        batch::run_batch(&client, &records, None, &template("sys"), options(2, 1), None, |r| seen.push(r.id.clone())).await;
+    assert_eq!(seen.len(), 3);
+    let summary: Vec<(&str, u32, bool)> = results.iter().map(|r| (r.id.as_str(), r.attempts, r.succeeded())).collect();
+    assert_eq!(summary, vec![("flaky", 2, true), ("broken", 2, false), ("unrendered", 0, false)]);
+    assert_eq!(results[0].output.as_deref(), Some("recovered"));
+    assert_eq!(results[0].input, "flaky");
+    assert_eq!(results[0].usage.as_ref().unwrap().output_tokens, estimate_tokens("recovered"));
+    assert_eq!(calls.load(Ordering::SeqCst), 2);
+    assert!(results[1].error.as_deref().unwrap().contains("500"), "{:?}", results[1].error);
+    assert_eq!(results[2].error.as_deref(), Some("Template 'missing' not found"));
+}
+
+#[test]
+fn test_checkpoint_keeps_successes_and_drops_failures() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("results.jsonl");
+    let result = |id: &str, error: Option<&str>| BatchResult {
+        id: id.to_string(),
+        input: "q".to_string(),
+        output: error.is_none().then(|| "a".to_string()),
+        usage: None,
+        error: error.map(str::to_string),
+        latency_ms: 5,
+        attempts: 1,
+        budget_warning: None,
+    };
+    let lines: Vec<String> = [result("1", None), result("2", Some("timeout")), result("3", None)]
+        .iter()
+        .map(|r| serde_json::to_string(r).unwrap())
+        .collect();
+    std::fs::write(&path, format!("{}\n{{\"id\": \"4\", \"inp", lines.join("\n"))).unwrap();
+
+    let mut checkpoint = BatchCheckpoint::open(&path).unwrap();
+    let completed: Vec<&str> = checkpoint.completed().iter().map(|r| r.id.as_str()).collect();
+    assert_eq!(completed, vec!["1", "3"]);
+    assert!(checkpoint.is_completed("3") && !checkpoint.is_completed("2"));
+    // The kept results are rewritten through a temporary file
+    assert!(!dir.path().join("results.jsonl.tmp").exists());
+
+    checkpoint.write(&result("2", None)).unwrap();
+    drop(checkpoint);
+    let ids: Vec<String> = std::fs::read_to_string(&path)
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str::<BatchResult>(line).unwrap().id)
+        .collect();
+    assert_eq!(ids, vec!["1", "3", "2"]);
+}
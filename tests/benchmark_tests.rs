@@ -0,0 +1,231 @@
+mod common;
+
+use common::{client, sse};
+use echomind::api::{ApiClient, ChatRequest, Message, Provider, StreamTiming};
+use echomind::features::performance::{BenchmarkSuite, BenchmarkTarget, Expectations, PerformanceMonitor};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::io::Write;
+use std::time::Duration;
+
+const SUITE: &str = "\
+name: smoke
+runs: 2
+targets: [openai:gpt-4o-mini]
+prompts:
+  - id: capital
+    prompt: What is the capital of France?
+    expect:
+      contains: [paris]
+      max_chars: 100
+  - id: json
+    system: Reply with JSON only.
+    prompt: Give me a user object.
+    expect:
+      json: true
+";
+
+#[test]
+fn test_provider_inference_from_model_names() {
+    let infer = |reference: &str| Provider::for_model(reference).map(|(p, m)| (p.name().to_string(), m));
+    let pair = |p: &str, m: &str| Some((p.to_string(), m.to_string()));
+
+    assert_eq!(infer("openai:gpt-4o"), pair("openai", "gpt-4o"));
+    assert_eq!(infer("ollama/llama3:8b"), pair("ollama", "llama3:8b"));
+    assert_eq!(infer("gpt-4o-mini"), pair("openai", "gpt-4o-mini"));
+    assert_eq!(infer("o3-mini"), pair("openai", "o3-mini"));
+    assert_eq!(infer("claude-3-5-haiku-latest"), pair("claude", "claude-3-5-haiku-latest"));
+    assert_eq!(infer("gemini-1.5-flash"), pair("gemini", "gemini-1.5-flash"));
+    assert_eq!(infer("codestral-latest"), pair("mistral", "codestral-latest"));
+    assert_eq!(infer("command-r"), pair("cohere", "command-r"));
+    // Unknown families are left to the caller instead of defaulting to Ollama
+    assert_eq!(infer("llama3:8b"), None);
+    assert_eq!(infer("meta-llama/Llama-3-8b"), None);
+    assert!(Provider::OpenAI.supports_streaming());
+    assert!(!Provider::Claude.supports_streaming());
+}
+
+#[test]
+fn test_suite_loading_and_expectations() {
+    let mut file = tempfile::Builder::new().suffix(".yaml").tempfile().unwrap();
+    write!(file, "{}", SUITE).unwrap();
+    let suite = BenchmarkSuite::load(&file.path().to_string_lossy()).unwrap();
+    assert_eq!(suite.runs, Some(2));
+    assert_eq!(suite.targets, vec!["openai:gpt-4o-mini"]);
+    assert_eq!(suite.prompts[1].system.as_deref(), Some("Reply with JSON only."));
+
+    let capital = &suite.prompts[0].expect;
+    assert!(capital.check("Paris.").is_empty());
+    assert_eq!(capital.check("Lyon"), vec!["missing \"paris\""]);
+    assert!(suite.prompts[1].expect.check("```json\n{\"name\": \"Ann\"}\n```").is_empty());
+
+    let strict = Expectations {
+        not_contains: vec!["sorry".to_string()],
+        regex: Some(r"^\d+$".to_string()),
+        min_chars: Some(3),
+        ..Default::default()
+    };
+    assert_eq!(strict.check("Sorry"), vec!["contains \"sorry\"", r"no match for /^\d+$/"]);
+    assert_eq!(strict.check("12"), vec!["2 chars, expected at least 3"]);
+
+    let mut bad = tempfile::Builder::new().suffix(".json").tempfile().unwrap();
+    write!(bad, r#"{{"prompts": [{{"id": "x", "prompt": "hi", "expect": {{"regex": "("}}}}]}}"#).unwrap();
+    let err = BenchmarkSuite::load(&bad.path().to_string_lossy()).unwrap_err();
+    assert!(err.to_string().contains("Prompt 'x' has an invalid regex"), "{}", err);
+}
+
+#[tokio::test]
+async fn test_suite_runs_report_percentiles_ttft_and_pass_rate() {
+    let mut server = mockito::Server::new_async().await;
+    let capital = server
+        .mock("POST", "/good")
+        .match_body(mockito::Matcher::Regex("capital of France".to_string()))
+        .with_status(200)
+        .with_header("content-type", "text/event-stream")
+        .with_body(sse(&["The capital ", "is Paris."]))
+        .expect(2)
+        .create_async()
+        .await;
+    server
+        .mock("POST", "/good")
+        .match_body(mockito::Matcher::Regex("user object".to_string()))
+        .with_status(200)
+        .with_body(sse(&["Here you go: name=Ann"]))
+        .create_async()
+        .await;
+    server
+        .mock("POST", "/down")
+        .with_status(500)
+        .with_body("overloaded")
+        .create_async()
+        .await;
+
+    let mut file = tempfile::Builder::new().suffix(".yaml").tempfile().unwrap();
+    write!(file, "{}", SUITE).unwrap();
+    let suite = BenchmarkSuite::load(&file.path().to_string_lossy()).unwrap();
+    let targets = vec![
+        BenchmarkTarget::new(client(format!("{}/good", server.url())), "fast"),
+        BenchmarkTarget::new(client(format!("{}/down", server.url())), "broken"),
+    ];
+    assert_eq!(targets[0].label(), "custom:fast");
+
+    let mut monitor = PerformanceMonitor::new();
+    let mut seen = 0;
+    let summaries = monitor.run_suite(&targets, &suite, 2, |_| seen += 1).await;
+    // Identical repeated requests still reach the server
+    capital.assert_async().await;
+    assert_eq!(seen, 8);
+
+    assert_eq!(summaries.len(), 2);
+    let fast = &summaries[0];
+    assert_eq!((fast.model.as_str(), fast.runs, fast.errors, fast.passed), ("fast", 4, 0, 2));
+    assert_eq!(fast.pass_rate, 0.5);
+    assert!(fast.p50_latency_ms.is_some() && fast.p95_latency_ms >= fast.p50_latency_ms);
+    assert!(fast.p50_ttft_ms.is_some());
+    assert!(fast.total_cost > 0.0);
+    let broken = &summaries[1];
+    assert_eq!((broken.runs, broken.errors, broken.passed), (4, 4, 0));
+    assert_eq!((broken.p50_latency_ms, broken.tokens_per_second), (None, 0.0));
+
+    let results = monitor.results();
+    assert_eq!(results[0].response, "The capital is Paris.");
+    assert!(results[0].ttfb_ms.is_some() && results[0].ttft_ms >= results[0].ttfb_ms);
+    assert!(results[0].inter_token_p50_ms.is_some());
+    // A single delta has no inter-token gaps
+    assert_eq!(results[2].inter_token_p50_ms, None);
+    assert_eq!(monitor.metrics().streamed_requests, 4);
+    assert_eq!(results[2].failures, vec!["no valid JSON"]);
+    assert!(results[4].error.as_deref().unwrap().contains("500"));
+
+    let report: Value = serde_json::from_str(&monitor.export_benchmark_data("json").unwrap()).unwrap();
+    assert_eq!(report["summary"][0]["pass_rate"], json!(0.5));
+    assert_eq!(report["results"].as_array().unwrap().len(), 8);
+
+    let csv = monitor.export_benchmark_data("csv").unwrap();
+    let mut reader = csv::Reader::from_reader(csv.as_bytes());
+    assert_eq!(&reader.headers().unwrap()[0], "prompt_id");
+    let rows: Vec<csv::StringRecord> = reader.records().map(|r| r.unwrap()).collect();
+    assert_eq!(rows.len(), 8);
+    assert_eq!((&rows[2][0], &rows[2][15], &rows[2][16]), ("json", "false", "no valid JSON"));
+
+    let markdown = monitor.export_benchmark_data("markdown").unwrap();
+    assert!(markdown.contains("| custom | fast | 4 | 0 | 50% |"), "{}", markdown);
+    assert!(markdown.contains("- custom:fast `json`: no valid JSON"), "{}", markdown);
+    assert!(monitor.export_benchmark_data("xml").is_err());
+}
+
+#[tokio::test]
+async fn test_compare_models_routes_by_provider() {
+    let mut server = mockito::Server::new_async().await;
+    let openai = server
+        .mock("POST", "/openai")
+        .match_body(mockito::Matcher::PartialJson(json!({"model": "gpt-4o"})))
+        .with_status(200)
+        .with_body(sse(&["Hello", " there."]))
+        .create_async()
+        .await;
+
+    let clients: HashMap<String, ApiClient> = [
+        ("openai".to_string(), client(format!("{}/openai", server.url()))),
+        ("claude".to_string(), client(format!("{}/claude", server.url()))),
+    ]
+    .into_iter()
+    .collect();
+    let mut monitor = PerformanceMonitor::new();
+    let comparison = monitor
+        .compare_models(&clients, &["openai:gpt-4o".to_string(), "llama3".to_string()], "Hi")
+        .await
+        .unwrap();
+
+    // llama3 matches neither configured provider, so it is skipped
+    assert_eq!(comparison.results.len(), 1);
+    assert_eq!((comparison.results[0].provider.as_str(), comparison.results[0].model.as_str()), ("openai", "gpt-4o"));
+    assert_eq!(comparison.results[0].response, "Hello there.");
+    assert!(comparison.results[0].ttft_ms.is_some());
+    assert_eq!(comparison.winner.as_deref(), Some("gpt-4o"));
+    openai.assert_async().await;
+}
+
+#[tokio::test]
+async fn test_stream_timing_tracks_first_token_and_gaps() {
+    let mut server = mockito::Server::new_async().await;
+    server
+        .mock("POST", "/stream")
+        .with_status(200)
+        .with_body(sse(&["", "one", " two", " three"]))
+        .create_async()
+        .await;
+
+    let request = ChatRequest {
+        messages: vec![Message::text("user".to_string(), "count".to_string())],
+        model: Some("test".to_string()),
+        temperature: None,
+        max_tokens: None,
+        top_p: None,
+        top_k: None,
+        stream: Some(true),
+    };
+    let mut chunks = Vec::new();
+    let (text, timing) = client(format!("{}/stream", server.url()))
+        .send_message_stream_timed(request, |chunk| chunks.push(chunk.to_string()))
+        .await
+        .unwrap();
+    assert_eq!(text, "one two three");
+    // The empty delta is neither passed on nor counted as the first token
+    assert_eq!(chunks, vec!["one", " two", " three"]);
+    assert_eq!(timing.token_gaps.len(), 2);
+    assert!(timing.first_byte.unwrap() <= timing.first_token.unwrap());
+    assert!(timing.first_token.unwrap() <= timing.total);
+
+    let ms = Duration::from_millis;
+    let timing = StreamTiming {
+        first_byte: Some(ms(80)),
+        first_token: Some(ms(100)),
+        token_gaps: vec![ms(30), ms(10), ms(20), ms(200)],
+        total: ms(360),
+    };
+    assert_eq!(timing.generation_time(), Some(ms(260)));
+    assert_eq!(timing.token_gap_percentile(50.0), Some(ms(20)));
+    assert_eq!(timing.token_gap_percentile(95.0), Some(ms(200)));
+    assert_eq!(StreamTiming::default().token_gap_percentile(50.0), None);
+}
@@ -0,0 +1,123 @@
+use echomind::features::charts::{palette, ChartData};
+use echomind::features::data_processing::{ChartType, DataFormat, DataProcessor, Dataset, VisualizationConfig};
+use std::io::Write;
+
+const SALES: &str = "\
+date,region,product,revenue,units
+2024-01-01,north,widget,120,3
+2024-01-02,south,gadget,80,2
+2024-01-02,north,gadget,200,5
+2024-01-03,east,widget,50,1
+2024-01-04,west,widget,300,7
+2024-01-05,south,widget,90.5,2
+";
+
+fn sales() -> Dataset {
+    let mut file = tempfile::Builder::new().suffix(".csv").tempfile().unwrap();
+    write!(file, "{}", SALES).unwrap();
+    DataProcessor::new()
+        .load_dataset(&file.path().to_string_lossy(), DataFormat::Csv)
+        .unwrap()
+}
+
+fn config(chart_type: ChartType, x: &str, y: Option<&str>) -> VisualizationConfig {
+    VisualizationConfig {
+        chart_type,
+        x_column: x.to_string(),
+        y_column: y.map(str::to_string),
+        title: None,
+        width: None,
+        height: None,
+        color_scheme: None,
+    }
+}
+
+#[test]
+fn test_charts_are_computed_from_every_row() {
+    let dataset = sales();
+    let processor = DataProcessor::new();
+
+    let bar = processor
+        .generate_visualization(&dataset, &config(ChartType::Bar, "region", Some("revenue")))
+        .unwrap();
+    assert_eq!(bar.title, "revenue by region");
+    assert_eq!(
+        bar.data,
+        ChartData::Categories(vec![
+            ("north".to_string(), 320.0),
+            ("south".to_string(), 170.5),
+            ("east".to_string(), 50.0),
+            ("west".to_string(), 300.0),
+        ])
+    );
+
+    // The repeated date is summed into one point, labelled in file order
+    let line = processor
+        .generate_visualization(&dataset, &config(ChartType::Line, "date", Some("revenue")))
+        .unwrap();
+    let ChartData::Points { points, labels } = line.data else { panic!("expected points") };
+    assert_eq!(points[1], (1.0, 280.0));
+    assert_eq!(labels.unwrap().len(), 5);
+
+    let histogram = processor
+        .generate_visualization(&dataset, &config(ChartType::Histogram, "units", None))
+        .unwrap();
+    let ChartData::Bins(bins) = histogram.data else { panic!("expected bins") };
+    assert_eq!(bins.iter().map(|(_, _, count)| count).sum::<usize>(), 6);
+    assert_eq!((bins[0].0, bins[bins.len() - 1].1), (1.0, 7.0));
+
+    let heatmap = processor
+        .generate_visualization(&dataset, &config(ChartType::Heatmap, "region", Some("product")))
+        .unwrap();
+    let ChartData::Grid { rows, columns, values } = heatmap.data else { panic!("expected a grid") };
+    assert_eq!(rows, vec!["widget", "gadget"]);
+    assert_eq!(columns, vec!["north", "south", "east", "west"]);
+    assert_eq!(values[0], vec![1.0, 1.0, 1.0, 1.0]);
+}
+
+#[test]
+fn test_terminal_and_svg_rendering_honour_the_config() {
+    let dataset = sales();
+    let mut pie = config(ChartType::Pie, "region", Some("units"));
+    pie.title = Some("Units <shipped>".to_string());
+    pie.width = Some(800);
+    pie.height = Some(500);
+    pie.color_scheme = Some("#ff0000,#00ff00".to_string());
+    let chart = DataProcessor::new().generate_visualization(&dataset, &pie).unwrap();
+
+    let text = chart.render_terminal(false);
+    assert!(text.starts_with("Units <shipped>\n"));
+    assert!(text.contains("north  40.0%  8"), "{}", text);
+    assert!(!text.contains('\u{1b}'));
+    // 800 pixels are 80 terminal columns
+    assert_eq!(text.lines().nth(1).unwrap().chars().count(), 80);
+
+    let svg = chart.to_svg();
+    assert!(svg.starts_with(r#"<svg xmlns="http://www.w3.org/2000/svg" width="800" height="500""#));
+    assert!(svg.contains("Units &lt;shipped&gt;"));
+    assert!(svg.contains("#ff0000") && svg.contains("#00ff00"));
+    assert_eq!(svg.matches("<path").count(), 4);
+    assert!(svg.trim_end().ends_with("</svg>"));
+}
+
+#[test]
+fn test_chart_errors() {
+    let dataset = sales();
+    let processor = DataProcessor::new();
+
+    let err = processor
+        .generate_visualization(&dataset, &config(ChartType::Bar, "country", None))
+        .unwrap_err();
+    assert!(err.to_string().contains("Unknown column 'country'"), "{}", err);
+    assert!(processor
+        .generate_visualization(&dataset, &config(ChartType::Scatter, "units", None))
+        .is_err());
+    let err = processor
+        .generate_visualization(&dataset, &config(ChartType::Histogram, "region", None))
+        .unwrap_err();
+    assert!(err.to_string().contains("no numeric values"), "{}", err);
+
+    assert_eq!(palette(Some("blues")).unwrap()[0], (0x08, 0x51, 0x9c));
+    assert!(palette(Some("rainbow")).is_err());
+    assert!(ChartType::parse("donut").is_err());
+}
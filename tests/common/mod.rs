@@ -0,0 +1,25 @@
+//! Fixtures shared by the tests that run against a mock server.
+#![allow(dead_code)]
+
+use echomind::api::{ApiClient, Provider};
+use serde_json::json;
+
+/// A client that sends chat requests to `url`, as a custom provider.
+pub fn client(url: String) -> ApiClient {
+    ApiClient::new(Provider::from_string(&url).unwrap(), Some("key".to_string()), 10).unwrap()
+}
+
+/// A chat completion whose reply is `content`.
+pub fn reply(content: &str) -> String {
+    json!({"choices": [{"message": {"role": "assistant", "content": content}}]}).to_string()
+}
+
+/// A streamed reply sent as `chunks`, in server-sent events.
+pub fn sse(chunks: &[&str]) -> String {
+    let mut body: String = chunks
+        .iter()
+        .map(|chunk| format!("data: {}\n\n", json!({"choices": [{"delta": {"content": chunk}}]})))
+        .collect();
+    body.push_str("data: [DONE]\n\n");
+    body
+}
@@ -0,0 +1,140 @@
+mod common;
+
+use common::{client, reply, sse};
+use echomind::api::{ChatRequest, Message};
+use echomind::config::BudgetConfig;
+use echomind::features::compare::{self, Comparison, DiffRow};
+use echomind::features::performance::{estimate_tokens, BenchmarkTarget};
+use echomind::features::usage::{UsageLedger, UsageMeter};
+use serde_json::json;
+
+fn target(url: String, model: &str) -> BenchmarkTarget {
+    BenchmarkTarget::new(client(url), model)
+}
+
+fn request() -> ChatRequest {
+    ChatRequest {
+        messages: vec![Message::text("user".to_string(), "Name two colours".to_string())],
+        model: None,
+        temperature: Some(0.0),
+        max_tokens: None,
+        top_p: None,
+        top_k: None,
+        stream: None,
+    }
+}
+
+#[tokio::test]
+async fn test_comparison_runs_every_model_and_judges() {
+    let mut server = mockito::Server::new_async().await;
+    server
+        .mock("POST", "/chat")
+        .match_body(mockito::Matcher::PartialJson(json!({"model": "alpha"})))
+        .with_status(200)
+        .with_header("content-type", "text/event-stream")
+        .with_body(sse(&["Red\n", "Blue\n"]))
+        .create_async()
+        .await;
+    server
+        .mock("POST", "/chat")
+        .match_body(mockito::Matcher::PartialJson(json!({"model": "beta"})))
+        .with_status(200)
+        .with_header("content-type", "text/event-stream")
+        .with_body(sse(&["Red\n", "Green\n"]))
+        .create_async()
+        .await;
+    server
+        .mock("POST", "/chat")
+        .match_body(mockito::Matcher::PartialJson(json!({"model": "gamma"})))
+        .with_status(500)
+        .with_body("boom")
+        .create_async()
+        .await;
+    // The judge sees anonymous labels and answers with B
+    let judge = server
+        .mock("POST", "/chat")
+        .match_body(mockito::Matcher::AllOf(vec![
+            mockito::Matcher::PartialJson(json!({"model": "judge"})),
+            mockito::Matcher::Regex("Answer A:".to_string()),
+            mockito::Matcher::Regex("Answer B:".to_string()),
+        ]))
+        .with_status(200)
+        .with_body(reply(
+            "```json\n{\"winner\": \"B\", \"scores\": {\"A\": 6, \"B\": 8}, \"reason\": \"Green is a primary colour of light.\"}\n```",
+        ))
+        .create_async()
+        .await;
+
+    let url = format!("{}/chat", server.url());
+    let targets = vec![target(url.clone(), "alpha"), target(url.clone(), "beta"), target(url.clone(), "gamma")];
+    let entries = compare::run_comparison(&targets, &request(), None).await;
+    let models: Vec<&str> = entries.iter().map(|e| e.model.as_str()).collect();
+    assert_eq!(models.len(), 3);
+    assert!(models[0].ends_with(":alpha") && models[2].ends_with(":gamma"), "{:?}", models);
+    assert_eq!(entries[0].response.as_deref(), Some("Red\nBlue\n"));
+    assert_eq!(entries[1].output_tokens, estimate_tokens("Red\nGreen\n"));
+    assert_eq!(entries[0].input_tokens, estimate_tokens("Name two colours"));
+    assert!(entries[0].ttft_ms.is_some());
+    assert!(entries[2].error.as_deref().unwrap().contains("500"), "{:?}", entries[2].error);
+    assert_eq!(entries[2].cost, 0.0);
+
+    let mut comparison = Comparison {
+        input: "Name two colours".to_string(),
+        entries,
+        verdict: None,
+    };
+    assert_eq!(comparison.responses().len(), 2);
+    let ledger = tempfile::tempdir().unwrap();
+    let usage_path = ledger.path().join("usage.jsonl");
+    let usage = UsageMeter::new(Some(UsageLedger::new(usage_path.clone())), BudgetConfig::default(), "compare");
+    let verdict = compare::judge(&target(url, "judge"), &comparison, Some(&usage)).await.unwrap();
+    judge.assert_async().await;
+    let records = UsageLedger::new(usage_path).records().unwrap();
+    assert_eq!(records.iter().map(|r| r.model.as_str()).collect::<Vec<_>>(), vec!["judge"]);
+    assert_eq!(verdict.winner.as_deref(), Some(comparison.entries[1].model.as_str()));
+    assert_eq!(verdict.scores.get(&comparison.entries[0].model), Some(&6.0));
+    assert_eq!(verdict.reason, "Green is a primary colour of light.");
+
+    comparison.verdict = Some(verdict);
+    let json = serde_json::to_value(&comparison).unwrap();
+    assert_eq!(json["entries"].as_array().unwrap().len(), 3);
+    assert_eq!(json["verdict"]["reason"], "Green is a primary colour of light.");
+
+    // One response leaves nothing to judge
+    comparison.entries.truncate(1);
+    assert!(compare::judge(&targets[0], &comparison, None).await.is_err());
+}
+
+#[test]
+fn test_diff_helpers() {
+    let old = "Red\nBlue\nYellow\n";
+    let new = "Red\nGreen\nYellow\nPurple\n";
+
+    assert_eq!(compare::unified_diff(old, old, "a", "b"), "");
+    let unified = compare::unified_diff(old, new, "a", "b");
+    assert!(unified.starts_with("--- a\n+++ b\n"), "{}", unified);
+    assert!(unified.contains("-Blue\n+Green\n"), "{}", unified);
+    assert!(unified.contains("+Purple\n"), "{}", unified);
+
+    let row = |left: Option<&str>, right: Option<&str>, changed: bool| DiffRow {
+        left: left.map(str::to_string),
+        right: right.map(str::to_string),
+        changed,
+    };
+    assert_eq!(
+        compare::side_by_side(old, new),
+        vec![
+            row(Some("Red"), Some("Red"), false),
+            row(Some("Blue"), Some("Green"), true),
+            row(Some("Yellow"), Some("Yellow"), false),
+            row(None, Some("Purple"), true),
+        ]
+    );
+
+    let lines = compare::changed_lines(old, new);
+    let highlighted: Vec<(&str, bool)> = lines.iter().map(|(line, changed)| (line.as_str(), *changed)).collect();
+    assert_eq!(highlighted, vec![("Red", false), ("Green", true), ("Yellow", false), ("Purple", true)]);
+
+    assert_eq!(compare::line_similarity(old, old), 1.0);
+    assert!(compare::line_similarity(old, new) < 1.0);
+}
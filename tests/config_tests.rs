@@ -7,7 +7,7 @@ fn test_default_config() {
     assert_eq!(config.api.model, "gpt-3.5-turbo");
     assert_eq!(config.api.timeout, 30);
     assert_eq!(config.defaults.temperature, 0.7);
-    assert_eq!(config.defaults.coder_mode, false);
+    assert!(!config.defaults.coder_mode);
 }
 
 #[test]
@@ -24,10 +24,14 @@ fn test_config_serialization() {
         defaults: Defaults {
             temperature: 0.5,
             max_tokens: Some(1000),
+            top_p: None,
+            top_k: None,
             coder_mode: true,
             stream: false,
         },
         presets: std::collections::HashMap::new(),
+        workflow: Default::default(),
+        budget: Default::default(),
     };
 
     let toml_str = toml::to_string(&config).unwrap();
@@ -59,8 +63,8 @@ fn test_config_deserialization() {
     assert_eq!(config.api.timeout, 45);
     assert_eq!(config.defaults.temperature, 0.8);
     assert_eq!(config.defaults.max_tokens, Some(2000));
-    assert_eq!(config.defaults.coder_mode, true);
-    assert_eq!(config.defaults.stream, true);
+    assert!(config.defaults.coder_mode);
+    assert!(config.defaults.stream);
 }
 
 #[test]
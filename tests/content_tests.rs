@@ -0,0 +1,140 @@
+use echomind::features::content::{ContentManager, TemplateUpdate, VariableType};
+use std::collections::HashMap;
+
+fn library() -> (tempfile::TempDir, ContentManager) {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("nested").join("library.json");
+    let manager = ContentManager::new(&path.to_string_lossy()).unwrap();
+    (dir, manager)
+}
+
+fn library_with(contents: &serde_json::Value) -> (tempfile::TempDir, ContentManager) {
+    let (dir, mut manager) = library();
+    manager.import_library(&contents.to_string(), "json").unwrap();
+    (dir, manager)
+}
+
+#[test]
+fn test_templates_are_found_by_name_and_render() {
+    let (dir, mut manager) = library();
+    let template = manager
+        .create_template("Review", "Review {file} for {focus}; cite {file}", None, Some("dev"), Vec::new())
+        .unwrap();
+    assert_eq!(template.variables.len(), 2);
+    assert!(manager.create_template("review", "dup", None, None, Vec::new()).is_err());
+
+    let found = manager.find_template("review").unwrap().clone();
+    assert_eq!(found.id, template.id);
+
+    let mut vars = HashMap::new();
+    vars.insert("file".to_string(), "main.rs".to_string());
+    let missing: Vec<&str> = ContentManager::missing_variables(&found, &vars)
+        .iter()
+        .map(|v| v.name.as_str())
+        .collect();
+    assert_eq!(missing, vec!["focus"]);
+    assert!(manager.render_template(&found.id, &vars).is_err());
+
+    vars.insert("focus".to_string(), "safety".to_string());
+    let rendered = manager.render_template(&found.id, &vars).unwrap();
+    assert_eq!(rendered, "Review main.rs for safety; cite main.rs");
+
+    // The library is written to disk, creating its directory
+    let reloaded = ContentManager::new(&dir.path().join("nested/library.json").to_string_lossy()).unwrap();
+    assert_eq!(reloaded.find_template("Review").unwrap().usage_count, 1);
+}
+
+#[test]
+fn test_update_template_keeps_variable_metadata() {
+    let (_dir, mut manager) = library();
+    let template = manager.create_template("t", "Hi {name}", None, None, Vec::new()).unwrap();
+
+    let mut library: serde_json::Value =
+        serde_json::from_str(&manager.export_library("json").unwrap()).unwrap();
+    library["templates"][&template.id]["variables"][0]["default_value"] = "friend".into();
+    let (_other_dir, mut manager) = library_with(&library);
+
+    manager
+        .update_template(
+            &template.id,
+            TemplateUpdate {
+                name: None,
+                description: None,
+                content: Some("Hello {name}, meet {other}".to_string()),
+                category: None,
+                tags: None,
+            },
+        )
+        .unwrap();
+
+    let updated = manager.find_template("t").unwrap();
+    assert_eq!(updated.variables.len(), 2);
+    assert_eq!(updated.variables[0].default_value.as_deref(), Some("friend"));
+    let missing: Vec<&str> = ContentManager::missing_variables(updated, &HashMap::new())
+        .iter()
+        .map(|v| v.name.as_str())
+        .collect();
+    assert_eq!(missing, vec!["other"]);
+}
+
+#[test]
+fn test_use_snippet_counts_usage() {
+    let (_dir, mut manager) = library();
+    manager
+        .create_snippet("terse", "Answer in one line.", None, Vec::new(), None, None)
+        .unwrap();
+
+    assert_eq!(manager.use_snippet("TERSE").unwrap(), "Answer in one line.");
+    assert_eq!(manager.find_snippet("terse").unwrap().usage_count, 1);
+    assert!(manager.use_snippet("missing").is_err());
+}
+
+#[test]
+fn test_typed_variables_are_validated() {
+    let (_dir, mut manager) = library();
+    let template = manager
+        .create_template(
+            "typed",
+            "{{ count }} x {{ mode }}{% if urgent %}!{% endif %}: {% for t in topics %}[{{ t }}]{% endfor %} {{ tone|calm }}",
+            None,
+            None,
+            Vec::new(),
+        )
+        .unwrap();
+
+    let urgent = template.variables.iter().find(|v| v.name == "urgent").unwrap();
+    assert!(!urgent.required);
+    let topics = template.variables.iter().find(|v| v.name == "topics").unwrap();
+    assert!(matches!(topics.variable_type, VariableType::List));
+    let tone = template.variables.iter().find(|v| v.name == "tone").unwrap();
+    assert_eq!(tone.default_value.as_deref(), Some("calm"));
+
+    let mut count = template.variables[0].clone();
+    count.variable_type = VariableType::parse("number", &[]).unwrap();
+    manager.set_variable(&template.id, count).unwrap();
+    let mut mode = template.variables[1].clone();
+    mode.variable_type = VariableType::parse("select", &["fast".to_string(), "deep".to_string()]).unwrap();
+    manager.set_variable(&template.id, mode).unwrap();
+
+    let mut vars = HashMap::from([
+        ("count".to_string(), "three".to_string()),
+        ("mode".to_string(), "fast".to_string()),
+        ("topics".to_string(), "io, cli".to_string()),
+    ]);
+    let err = manager.render_template(&template.id, &vars).unwrap_err().to_string();
+    assert!(err.contains("'count' expects a number"), "{}", err);
+
+    vars.insert("count".to_string(), "3".to_string());
+    vars.insert("mode".to_string(), "slow".to_string());
+    let err = manager.render_template(&template.id, &vars).unwrap_err().to_string();
+    assert!(err.contains("one of: fast, deep"), "{}", err);
+
+    vars.insert("mode".to_string(), "deep".to_string());
+    vars.insert("urgent".to_string(), "yes".to_string());
+    assert_eq!(
+        manager.render_template(&template.id, &vars).unwrap(),
+        "3 x deep!: [io][cli] calm"
+    );
+
+    assert!(manager.create_template("broken", "{% if x %}", None, None, Vec::new()).is_err());
+}
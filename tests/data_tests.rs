@@ -0,0 +1,132 @@
+mod common;
+
+use common::{client, reply};
+use echomind::api::ChatRequest;
+use echomind::features::data_processing::{AggregateRequest, DataFormat, DataProcessor, Metric};
+use echomind::features::data_qa;
+use serde_json::json;
+use std::io::Write;
+
+const SALES: &str = "\
+region,year,revenue
+West,2023,100
+West,2024,180
+East,2023,200
+East,2024,210
+North,2024,
+";
+
+fn sales_file() -> tempfile::NamedTempFile {
+    let mut file = tempfile::Builder::new().suffix(".csv").tempfile().unwrap();
+    write!(file, "{}", SALES).unwrap();
+    file
+}
+
+fn request(value: serde_json::Value) -> AggregateRequest {
+    serde_json::from_value(value).unwrap()
+}
+
+#[test]
+fn test_aggregates_group_filter_and_order() {
+    let file = sales_file();
+    let processor = DataProcessor::new();
+    let dataset = processor.load_dataset(&file.path().to_string_lossy(), DataFormat::Csv).unwrap();
+    assert_eq!(dataset.column_names, vec!["region", "year", "revenue"]);
+    assert_eq!(dataset.records.len(), 5);
+
+    let result = processor
+        .aggregate(
+            &dataset,
+            &request(json!({
+                "group_by": ["region"],
+                "metrics": ["sum(revenue)", "count", "avg(revenue)"],
+                "order_by": "sum(revenue)",
+                "descending": true
+            })),
+        )
+        .unwrap();
+    assert_eq!(result.columns, vec!["region", "sum(revenue)", "count", "avg(revenue)"]);
+    assert_eq!(result.rows[0], vec![json!("East"), json!(410), json!(2), json!(205)]);
+    assert_eq!(result.rows[1], vec![json!("West"), json!(280), json!(2), json!(140)]);
+    // Empty cells are left out of sums but the row still counts
+    assert_eq!(result.rows[2], vec![json!("North"), json!(0), json!(1), json!(null)]);
+
+    let result = processor
+        .aggregate(
+            &dataset,
+            &request(json!({
+                "metrics": ["median(revenue)", "count_distinct(region)", "max(revenue)"],
+                "filter": [{"column": "year", "op": ">=", "value": 2024}, {"column": "revenue", "op": "not_null"}]
+            })),
+        )
+        .unwrap();
+    assert_eq!(result.matched_rows, 2);
+    assert_eq!(result.rows, vec![vec![json!(195), json!(2), json!(210)]]);
+
+    let err = processor
+        .aggregate(&dataset, &request(json!({"group_by": ["country"]})))
+        .unwrap_err()
+        .to_string();
+    assert!(err.contains("Unknown column 'country'"), "{}", err);
+    assert!(Metric::parse("sum").is_err());
+    assert!(Metric::parse("p99(revenue)").is_err());
+}
+
+#[test]
+fn test_describe_is_compact_and_marks_the_sample() {
+    let file = sales_file();
+    let processor = DataProcessor::new();
+    let dataset = processor.load_dataset(&file.path().to_string_lossy(), DataFormat::Csv).unwrap();
+    let description = processor.analyze(&dataset).describe("sales.csv", 2);
+
+    assert!(description.starts_with("File: sales.csv (CSV, 5 rows, 3 columns)"), "{}", description);
+    assert!(description.contains("- revenue: number, 4 distinct, 1 empty, min 100, max 210"), "{}", description);
+    assert!(description.contains("Sample rows (2 of 5):\nregion | year | revenue\nWest | 2023 | 100\nWest | 2024 | 180"));
+    assert!(!description.contains("East | 2023"));
+}
+
+#[tokio::test]
+async fn test_model_requested_aggregates_ground_the_answer() {
+    let mut server = mockito::Server::new_async().await;
+    let ask = server
+        .mock("POST", "/chat")
+        .match_body(mockito::Matcher::Regex("Question: which region grew fastest".to_string()))
+        .with_status(200)
+        .with_body(reply(
+            "{\"sql\": \"SELECT region, year, SUM(revenue) FROM sales WHERE revenue IS NOT NULL GROUP BY region, year\"}",
+        ))
+        .create_async()
+        .await;
+    let answer = server
+        .mock("POST", "/chat")
+        .match_body(mockito::Matcher::Regex(r"East \| 2024 \| 210".to_string()))
+        .with_status(200)
+        .with_body(reply("West grew fastest: 100 -> 180 (+80%)."))
+        .create_async()
+        .await;
+
+    let file = sales_file();
+    let processor = DataProcessor::new();
+    let dataset = processor.load_dataset(&file.path().to_string_lossy(), DataFormat::Csv).unwrap();
+    let analysis = processor.analyze(&dataset);
+    let client = client(format!("{}/chat", server.url()));
+    let base = ChatRequest {
+        messages: Vec::new(),
+        model: Some("test".to_string()),
+        temperature: None,
+        max_tokens: None,
+        top_p: None,
+        top_k: None,
+        stream: None,
+    };
+
+    let result = data_qa::answer_question(&client, base, &processor, &dataset, &analysis, "sales.csv", "which region grew fastest?", None)
+        .await
+        .unwrap();
+    ask.assert_async().await;
+    answer.assert_async().await;
+    assert_eq!(result.answer, "West grew fastest: 100 -> 180 (+80%).");
+    assert_eq!(result.computations.len(), 1);
+    assert!(result.computations[0].query.starts_with("SELECT region, year"));
+    assert_eq!(result.computations[0].result.as_ref().unwrap().rows.len(), 4);
+}
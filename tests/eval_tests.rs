@@ -0,0 +1,214 @@
+mod common;
+
+use common::{client, reply};
+use echomind::api::Message;
+use echomind::config::{BudgetAction, BudgetConfig, Preset};
+use echomind::features::eval::{self, Assertion, ChangeKind, EvalReport, EvalSuite};
+use echomind::features::json_schema;
+use echomind::features::performance::BenchmarkTarget;
+use echomind::features::usage::{UsageLedger, UsageMeter};
+use serde_json::json;
+use std::collections::HashMap;
+
+const SUITE: &str = r#"
+name: support
+cases:
+  - id: refund
+    preset: support
+    input: How do I get a refund?
+    assert:
+      - type: contains
+        value: refund
+      - type: regex
+        pattern: '\d+ days'
+      - type: max_length
+        chars: 200
+      - type: judge
+        rubric: Polite and mentions the refund window
+  - id: profile
+    system: Reply with JSON only.
+    input: Give me a user profile.
+    assert:
+      - type: json_schema
+        schema: profile.schema.json
+  - id: greeting
+    input: Say hello
+    assert:
+      - type: similarity
+        reference: Hello there, how can I help you today?
+        threshold: 0.5
+"#;
+
+fn target(url: String, model: &str) -> BenchmarkTarget {
+    BenchmarkTarget::new(client(url), model)
+}
+
+fn write_suite(dir: &tempfile::TempDir) -> String {
+    std::fs::write(
+        dir.path().join("profile.schema.json"),
+        json!({
+            "type": "object",
+            "required": ["name", "age"],
+            "properties": {"name": {"type": "string"}, "age": {"type": "integer", "minimum": 0}},
+            "additionalProperties": false
+        })
+        .to_string(),
+    )
+    .unwrap();
+    let path = dir.path().join("support.yaml");
+    std::fs::write(&path, SUITE).unwrap();
+    path.to_string_lossy().into_owned()
+}
+
+#[test]
+fn test_json_schema_validation() {
+    let schema = json!({
+        "type": "object",
+        "required": ["id", "tags"],
+        "properties": {
+            "id": {"type": "integer", "minimum": 1},
+            "tags": {"type": "array", "items": {"type": "string", "pattern": "^[a-z]+$"}, "maxItems": 2},
+            "status": {"enum": ["open", "closed"]},
+            "owner": {"anyOf": [{"type": "null"}, {"type": "string", "minLength": 2}]}
+        },
+        "additionalProperties": false
+    });
+    assert!(json_schema::validate(&schema, &json!({"id": 3, "tags": ["a"], "owner": null})).is_empty());
+    assert_eq!(
+        json_schema::validate(&schema, &json!({"id": 0, "tags": ["ok", "Bad", "x"], "status": "new", "extra": 1, "owner": "x"})),
+        vec![
+            "$: unexpected property 'extra'",
+            "$.id: 0 is below the minimum 1",
+            "$.owner: matches none of the anyOf schemas",
+            "$.status: \"new\" is not one of \"open\", \"closed\"",
+            "$.tags[1]: \"Bad\" does not match /^[a-z]+$/",
+            "$.tags: 3 items, expected at most 2",
+        ]
+    );
+    assert_eq!(
+        json_schema::validate(&schema, &json!({"tags": "a"})),
+        vec!["$: missing required property 'id'", "$.tags: expected array, got string"]
+    );
+    assert!(json_schema::validate(&json!({"type": "integer"}), &json!(2.0)).is_empty());
+}
+
+#[tokio::test]
+async fn test_local_assertions_and_case_messages() {
+    let dir = tempfile::tempdir().unwrap();
+    let suite = EvalSuite::load(&write_suite(&dir)).unwrap();
+    assert!(suite.needs_judge());
+    // The schema file was read relative to the suite
+    assert!(matches!(&suite.cases[1].assertions[0], Assertion::JsonSchema { schema } if schema["required"] == json!(["name", "age"])));
+
+    let presets: HashMap<String, Preset> = [(
+        "support".to_string(),
+        Preset {
+            system_prompt: Some("You are a support agent.".to_string()),
+            messages: None,
+        },
+    )]
+    .into_iter()
+    .collect();
+    let messages = suite.cases[0].messages(&presets, None).unwrap();
+    assert_eq!(messages.len(), 2);
+    assert_eq!(messages[0].get_text(), Some("You are a support agent."));
+    assert!(suite.cases[0].messages(&HashMap::new(), None).is_err());
+
+    let check = |assertion: &Assertion, output: &str| {
+        let assertion = assertion.clone();
+        let output = output.to_string();
+        async move { eval::check_assertion(&assertion, "", &output, None, None).await }
+    };
+    let schema = &suite.cases[1].assertions[0];
+    assert!(check(schema, "```json\n{\"name\": \"Ann\", \"age\": 30}\n```").await.passed);
+    let bad = check(schema, "{\"name\": \"Ann\", \"age\": -1}").await;
+    assert_eq!(bad.detail.as_deref(), Some("$.age: -1 is below the minimum 0"));
+    let judge = check(&suite.cases[0].assertions[3], "Sure").await;
+    assert_eq!((judge.passed, judge.detail.as_deref()), (false, Some("no judge model configured")));
+
+    assert!(eval::similarity("Hello there, how can I help?", "hello THERE how can i help") > 0.9);
+    assert_eq!(eval::similarity("apples", "oranges"), 0.0);
+
+    let bad_suite = dir.path().join("bad.json");
+    std::fs::write(&bad_suite, r#"{"cases": [{"id": "a", "input": "x"}, {"id": "a", "input": "y"}]}"#).unwrap();
+    let err = EvalSuite::load(&bad_suite.to_string_lossy()).unwrap_err();
+    assert!(err.to_string().contains("Case id 'a' is used more than once"), "{}", err);
+}
+
+#[tokio::test]
+async fn test_eval_run_with_judge_and_baseline_diff() {
+    let mut server = mockito::Server::new_async().await;
+    for (pattern, content) in [
+        ("refund\\?", "You can request a refund within 30 days of purchase."),
+        ("user profile", "{\"name\": \"Ann\", \"age\": \"thirty\"}"),
+        ("Say hello", "Hello there! How can I help you today?"),
+    ] {
+        server
+            .mock("POST", "/model")
+            .match_body(mockito::Matcher::Regex(pattern.to_string()))
+            .with_status(200)
+            .with_body(reply(content))
+            .create_async()
+            .await;
+    }
+    let judge_mock = server
+        .mock("POST", "/judge")
+        .match_body(mockito::Matcher::Regex("Rubric:\\\\nPolite".to_string()))
+        .with_status(200)
+        .with_body(reply("{\"score\": 0.9, \"reason\": \"Polite and states the 30 day window.\"}"))
+        .create_async()
+        .await;
+
+    let dir = tempfile::tempdir().unwrap();
+    let suite = EvalSuite::load(&write_suite(&dir)).unwrap();
+    let presets: HashMap<String, Preset> =
+        [("support".to_string(), Preset { system_prompt: None, messages: None })].into_iter().collect();
+    let conversations: Vec<Vec<Message>> =
+        suite.cases.iter().map(|case| case.messages(&presets, None).unwrap()).collect();
+    let targets = vec![target(format!("{}/model", server.url()), "m1")];
+    let judge = target(format!("{}/judge", server.url()), "grader");
+
+    let mut finished = Vec::new();
+    let report = eval::run_suite(&suite, &conversations, &targets, Some(&judge), None, |r| finished.push(r.case_id.clone())).await;
+    judge_mock.assert_async().await;
+    assert_eq!(finished, vec!["refund", "profile", "greeting"]);
+    assert_eq!(report.passed(), 2);
+    let refund = &report.results[0];
+    assert!(refund.passed, "{}", refund.failure_summary());
+    assert_eq!(refund.assertions[3].score, Some(0.9));
+    let profile = &report.results[1];
+    assert_eq!(profile.model, "custom:m1");
+    assert_eq!(profile.failure_summary(), "valid JSON for the schema ($.age: expected integer, got string)");
+
+    // Against a baseline where the profile case passed and greeting failed
+    let mut baseline = report.clone();
+    baseline.results[1].passed = true;
+    baseline.results[2].passed = false;
+    baseline.results[0].case_id = "retired".to_string();
+    let path = dir.path().join("baseline.json");
+    baseline.save(&path.to_string_lossy()).unwrap();
+    let changes = report.diff(&EvalReport::load(&path.to_string_lossy()).unwrap());
+    let kinds: Vec<(&str, ChangeKind)> = changes.iter().map(|c| (c.case_id.as_str(), c.kind)).collect();
+    assert_eq!(
+        kinds,
+        vec![
+            ("refund", ChangeKind::New),
+            ("profile", ChangeKind::Regression),
+            ("greeting", ChangeKind::Fixed),
+            ("retired", ChangeKind::Missing),
+        ]
+    );
+
+    // Over a blocking budget cases fail without being sent or recorded
+    let budget = BudgetConfig {
+        daily: Some(0.0),
+        action: BudgetAction::Block,
+        ..Default::default()
+    };
+    let ledger = dir.path().join("usage.jsonl");
+    let meter = UsageMeter::new(Some(UsageLedger::new(ledger.clone())), budget, "eval");
+    let blocked = eval::run_suite(&suite, &conversations, &targets, Some(&judge), Some(&meter), |_| {}).await;
+    assert_eq!(blocked.passed(), 0);
+    assert!(blocked.results.iter().all(|r| r.error.as_deref().unwrap_or("").starts_with("Request blocked")));
+    assert!(!ledger.exists());
+}
@@ -0,0 +1,144 @@
+use echomind::error::EchomindError;
+use echomind::features::output::{FileSink, OutputFormat, OutputPipeline, ReplyFilter, WriterSink};
+use serde_json::{json, Value};
+use std::cell::RefCell;
+use std::io::Write;
+use std::rc::Rc;
+
+/// A writer the test can read back after handing it to a pipeline.
+#[derive(Clone, Default)]
+struct Shared(Rc<RefCell<Vec<u8>>>);
+
+impl Shared {
+    fn text(&self) -> String {
+        String::from_utf8(self.0.borrow().clone()).unwrap()
+    }
+}
+
+impl Write for Shared {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+const REPLY: &str = "```python\ndef add(a, b):\n\n    return a + b\n```\n";
+
+#[test]
+fn test_coder_filter_gives_the_same_text_streamed_or_whole() {
+    let whole = ReplyFilter::apply(true, REPLY);
+    assert_eq!(whole, "def add(a, b):\n    return a + b");
+
+    // Chunks that split lines and fences anywhere
+    let mut filter = ReplyFilter::new(true);
+    let mut streamed = String::new();
+    for chunk in ["``", "`python\nde", "f add(a, b):", "\n\n  ", "  return a + b\n``", "`\n"] {
+        streamed.push_str(&filter.push(chunk));
+    }
+    streamed.push_str(&filter.finish());
+    assert_eq!(streamed, whole);
+
+    assert_eq!(ReplyFilter::apply(false, REPLY), REPLY);
+    assert_eq!(OutputFormat::parse(Some("template:{{ content }}")).unwrap(), OutputFormat::Template("{{ content }}".to_string()));
+    assert!(OutputFormat::parse(Some("yaml")).is_err());
+}
+
+#[test]
+fn test_pipeline_sends_the_same_reply_to_every_sink() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("reply.jsonl");
+    let stdout = Shared::default();
+    let mut pipeline = OutputPipeline::new(OutputFormat::Ndjson, true);
+    pipeline.add_sink(Box::new(WriterSink::new(stdout.clone(), OutputFormat::Ndjson)));
+    pipeline.add_sink(Box::new(FileSink::new(&path, OutputFormat::Ndjson)));
+
+    for chunk in ["```\nprint(1)\n", "print(2)", "\n```"] {
+        pipeline.delta(chunk).unwrap();
+    }
+    let reply = pipeline.finish("```\nprint(1)\nprint(2)\n```", None, "openai", "gpt-4o").unwrap();
+    assert_eq!(reply.text, "print(1)\nprint(2)");
+
+    let events: Vec<Value> = stdout.text().lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+    let types: Vec<&str> = events.iter().map(|e| e["type"].as_str().unwrap()).collect();
+    assert_eq!(types, vec!["delta", "delta", "done"]);
+    let deltas: String = events.iter().filter_map(|e| e["content"].as_str()).take(2).collect();
+    assert_eq!(deltas, reply.text);
+    assert_eq!(events[2]["content"], "print(1)\nprint(2)");
+    assert_eq!(events[2]["model"], "gpt-4o");
+    // The file gets exactly what stdout did
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), stdout.text());
+
+    // Text streams as it arrives; json waits for the whole reply
+    let run = |format: OutputFormat| {
+        let out = Shared::default();
+        let mut pipeline = OutputPipeline::new(format.clone(), false);
+        pipeline.add_sink(Box::new(WriterSink::new(out.clone(), format)));
+        pipeline.delta("Hel").unwrap();
+        let early = out.text();
+        pipeline.delta("lo").unwrap();
+        pipeline.finish("Hello", Some(&json!({"greeting": "Hello"})), "openai", "gpt-4o").unwrap();
+        (early, out.text())
+    };
+    assert_eq!(run(OutputFormat::Text), ("Hel".to_string(), "Hello\n".to_string()));
+    let (early, document) = run(OutputFormat::Json);
+    assert_eq!(early, "");
+    let document: Value = serde_json::from_str(&document).unwrap();
+    assert_eq!(document["content"], json!({"greeting": "Hello"}));
+}
+
+#[test]
+fn test_errors_become_events_and_leave_files_alone() {
+    let dir = tempfile::tempdir().unwrap();
+    let kept = dir.path().join("answer.txt");
+    std::fs::write(&kept, "previous answer").unwrap();
+    let events = dir.path().join("events.jsonl");
+    let stdout = Shared::default();
+
+    let mut pipeline = OutputPipeline::new(OutputFormat::Ndjson, false);
+    pipeline.add_sink(Box::new(WriterSink::new(stdout.clone(), OutputFormat::Ndjson)));
+    pipeline.add_sink(Box::new(FileSink::new(&kept, OutputFormat::Text)));
+    pipeline.add_sink(Box::new(FileSink::new(&events, OutputFormat::Ndjson)));
+    pipeline.delta("partial").unwrap();
+    pipeline.error(&EchomindError::NetworkError("connection reset".to_string())).unwrap();
+
+    let last: Value = serde_json::from_str(stdout.text().lines().last().unwrap()).unwrap();
+    assert_eq!(last["type"], "error");
+    assert_eq!(last["kind"], "NetworkError");
+    assert!(last["message"].as_str().unwrap().contains("connection reset"));
+    assert_eq!(std::fs::read_to_string(&kept).unwrap(), "previous answer");
+    assert_eq!(std::fs::read_to_string(&events).unwrap(), stdout.text());
+}
+
+#[test]
+fn test_reset_drops_a_failed_partial_reply() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("answer.txt");
+    let stdout = Shared::default();
+    let mut pipeline = OutputPipeline::new(OutputFormat::Ndjson, true);
+    pipeline.add_sink(Box::new(WriterSink::new(stdout.clone(), OutputFormat::Ndjson)));
+    pipeline.add_sink(Box::new(FileSink::new(&path, OutputFormat::Text)));
+
+    // Nothing arrived yet, so there is nothing to take back
+    let failure = EchomindError::NetworkError("connection reset".to_string());
+    pipeline.reset(&failure).unwrap();
+    assert_eq!(stdout.text(), "");
+
+    // The first provider gets a line and a half out before failing
+    pipeline.delta("first line\nhalf a li").unwrap();
+    pipeline.reset(&failure).unwrap();
+    pipeline.delta("second reply").unwrap();
+    let reply = pipeline.finish("second reply", None, "claude", "sonnet").unwrap();
+    assert_eq!(reply.text, "second reply");
+
+    let events: Vec<Value> = stdout.text().lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+    let types: Vec<&str> = events.iter().map(|e| e["type"].as_str().unwrap()).collect();
+    assert_eq!(types, vec!["delta", "reset", "delta", "done"]);
+    assert_eq!(events[1]["kind"], "NetworkError");
+    // The held-back half line didn't leak into the next reply
+    assert_eq!(events[2]["content"], "second reply");
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "second reply\n");
+}
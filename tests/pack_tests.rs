@@ -0,0 +1,231 @@
+use echomind::config::Config;
+use echomind::features::content::{ContentManager, VariableType};
+use echomind::features::packs::{ItemKind, PackManager, PackManifest, PackTargets, PromptPack};
+use echomind::features::workflow::WorkflowManager;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+
+const WORKFLOW: &str = r#"
+id: triage
+name: Triage
+start_step: ask
+steps:
+  - id: ask
+    name: Ask
+    step_type: AIRequest
+    params:
+      prompt: "Triage {issue}"
+"#;
+
+fn write_pack(dir: &Path, version: &str, with_snippet: bool) {
+    let manifest = format!(
+        r#"
+name = "review-kit"
+version = "{}"
+namespace = "review"
+
+[presets.strict]
+system_prompt = "You are a strict reviewer."
+
+[templates.diff]
+description = "Review a diff"
+[templates.diff.variables.lang]
+type = "select"
+choices = ["rust", "go"]
+default = "rust"
+"#,
+        version
+    );
+    fs::create_dir_all(dir.join("templates")).unwrap();
+    fs::create_dir_all(dir.join("workflows")).unwrap();
+    fs::write(dir.join("pack.toml"), manifest).unwrap();
+    fs::write(dir.join("templates/diff.txt"), format!("v{} review {{{{ lang }}}}: {{{{ diff }}}}", version)).unwrap();
+    fs::write(dir.join("workflows/triage.yaml"), WORKFLOW).unwrap();
+    if with_snippet {
+        fs::create_dir_all(dir.join("snippets")).unwrap();
+        fs::write(dir.join("snippets/terse.md"), "Answer in one line.").unwrap();
+    }
+}
+
+struct Stores {
+    _dir: tempfile::TempDir,
+    config: Config,
+    library: ContentManager,
+    workflows_dir: std::path::PathBuf,
+    manager: PackManager,
+}
+
+fn stores() -> Stores {
+    let dir = tempfile::tempdir().unwrap();
+    let library = ContentManager::new(&dir.path().join("library.json").to_string_lossy()).unwrap();
+    let manager = PackManager::open(&dir.path().join("packs")).unwrap();
+    Stores {
+        config: Config::default(),
+        library,
+        workflows_dir: dir.path().join("workflows"),
+        manager,
+        _dir: dir,
+    }
+}
+
+impl Stores {
+    fn targets(&mut self) -> (&mut PackManager, PackTargets<'_>) {
+        (
+            &mut self.manager,
+            PackTargets {
+                config: &mut self.config,
+                library: &mut self.library,
+                workflows_dir: self.workflows_dir.clone(),
+            },
+        )
+    }
+}
+
+#[test]
+fn test_install_namespaces_every_item() {
+    let source = tempfile::tempdir().unwrap();
+    write_pack(source.path(), "1.0.0", true);
+    let mut stores = stores();
+
+    let (manager, mut targets) = stores.targets();
+    let (pack, recorded) = manager.fetch(&source.path().to_string_lossy()).unwrap();
+    let report = manager.install(&pack, &recorded, None, false, &mut targets).unwrap();
+    assert_eq!(report.added.len(), 4);
+
+    assert!(stores.config.presets.contains_key("review/strict"));
+    let template = stores.library.find_template("review/diff").unwrap();
+    assert_eq!(template.description.as_deref(), Some("Review a diff"));
+    let lang = template.variables.iter().find(|v| v.name == "lang").unwrap();
+    assert!(matches!(&lang.variable_type, VariableType::Select(choices) if choices.len() == 2));
+    assert!(!lang.required);
+    assert_eq!(stores.library.find_snippet("review/terse").unwrap().content, "Answer in one line.");
+
+    let mut workflows = WorkflowManager::new();
+    assert_eq!(workflows.load_workflows_from_dir(&stores.workflows_dir).unwrap(), 1);
+    assert!(workflows.get_workflow("review/triage").is_some());
+
+    let installed = stores.manager.get("review").unwrap();
+    assert_eq!(installed.version, "1.0.0");
+    assert_eq!(installed.items.len(), 4);
+}
+
+#[test]
+fn test_install_reports_templates_that_include_files() {
+    let source = tempfile::tempdir().unwrap();
+    write_pack(source.path(), "1.0.0", false);
+    fs::write(source.path().join("templates/notes.txt"), "Notes:\n{{ notes | file }}").unwrap();
+    let mut stores = stores();
+
+    let (manager, mut targets) = stores.targets();
+    let (pack, recorded) = manager.fetch(&source.path().to_string_lossy()).unwrap();
+    let report = manager.install(&pack, &recorded, None, false, &mut targets).unwrap();
+    assert_eq!(report.file_includes, vec![("review/notes".to_string(), "notes".to_string())]);
+
+    // A pack can't hard-code the file it includes
+    fs::write(source.path().join("templates/notes.txt"), "{{ '~/.ssh/id_rsa' | file }}").unwrap();
+    let err = manager.fetch(&source.path().to_string_lossy()).unwrap_err().to_string();
+    assert!(err.contains("the file filter only reads a path given in a variable"), "{}", err);
+}
+
+#[test]
+fn test_conflicts_require_force_or_another_namespace() {
+    let source = tempfile::tempdir().unwrap();
+    write_pack(source.path(), "1.0.0", false);
+    let mut stores = stores();
+    stores
+        .library
+        .create_template("review/diff", "my own {thing}", None, None, Vec::new())
+        .unwrap();
+
+    let (manager, mut targets) = stores.targets();
+    let (pack, recorded) = manager.fetch(&source.path().to_string_lossy()).unwrap();
+    let conflicts = manager.conflicts(&pack, "review", &targets);
+    assert_eq!(conflicts.len(), 1);
+    assert_eq!(conflicts[0].item.kind, ItemKind::Template);
+    assert!(conflicts[0].owner.is_none());
+
+    let err = manager.install(&pack, &recorded, None, false, &mut targets).unwrap_err().to_string();
+    assert!(err.contains("template 'review/diff'"), "{}", err);
+    assert!(manager.get("review").is_none());
+
+    // Another namespace side-steps the conflict; forcing takes the item over
+    manager.install(&pack, &recorded, Some("team"), false, &mut targets).unwrap();
+    manager.install(&pack, &recorded, None, true, &mut targets).unwrap();
+    assert!(targets.library.find_template("review/diff").unwrap().content.starts_with("v1.0.0"));
+
+    // A different pack cannot take over an occupied namespace
+    let mut other = pack.clone();
+    other.manifest.name = "other-kit".to_string();
+    assert!(manager.install(&other, &recorded, Some("team"), true, &mut targets).is_err());
+}
+
+#[test]
+fn test_update_removes_stale_items_and_remove_cleans_up() {
+    let source = tempfile::tempdir().unwrap();
+    write_pack(source.path(), "1.0.0", true);
+    let mut stores = stores();
+
+    let (manager, mut targets) = stores.targets();
+    let (pack, recorded) = manager.fetch(&source.path().to_string_lossy()).unwrap();
+    manager.install(&pack, &recorded, None, false, &mut targets).unwrap();
+
+    fs::remove_dir_all(source.path().join("snippets")).unwrap();
+    write_pack(source.path(), "1.1.0", false);
+    let report = manager.update("review", false, &mut targets).unwrap();
+    assert_eq!(report.previous_version.as_deref(), Some("1.0.0"));
+    assert_eq!(report.removed.len(), 1);
+    assert_eq!(report.updated.len(), 3);
+    assert!(targets.library.find_snippet("review/terse").is_none());
+    assert!(targets.library.find_template("review/diff").unwrap().content.starts_with("v1.1.0"));
+
+    // The registry survives a restart
+    let reopened = PackManager::open(&stores._dir.path().join("packs")).unwrap();
+    assert_eq!(reopened.get("review").unwrap().version, "1.1.0");
+
+    let (manager, mut targets) = stores.targets();
+    manager.remove("review", &mut targets).unwrap();
+    assert!(targets.config.presets.is_empty());
+    assert!(targets.library.find_template("review/diff").is_none());
+    assert!(!stores.workflows_dir.join("review").exists());
+    assert!(stores.manager.list().is_empty());
+}
+
+#[test]
+fn test_zip_archives_and_export_round_trip() {
+    let source = tempfile::tempdir().unwrap();
+    write_pack(&source.path().join("review-kit"), "2.0.0", true);
+
+    // Zip the folder, as `zip -r review-kit.zip review-kit` would
+    let archive = source.path().join("review-kit.zip");
+    let mut zip = zip::ZipWriter::new(fs::File::create(&archive).unwrap());
+    for entry in ["pack.toml", "templates/diff.txt", "snippets/terse.md", "workflows/triage.yaml"] {
+        zip.start_file(format!("review-kit/{}", entry), zip::write::FileOptions::default())
+            .unwrap();
+        zip.write_all(&fs::read(source.path().join("review-kit").join(entry)).unwrap())
+            .unwrap();
+    }
+    zip.finish().unwrap();
+
+    let mut stores = stores();
+    let (manager, mut targets) = stores.targets();
+    let (pack, recorded) = manager.fetch(&archive.to_string_lossy()).unwrap();
+    manager.install(&pack, &recorded, None, false, &mut targets).unwrap();
+    assert!(stores.library.find_template("review/diff").is_some());
+
+    let mut workflows = WorkflowManager::new();
+    workflows.load_workflows_from_dir(&stores.workflows_dir).unwrap();
+    let manifest = PackManifest {
+        name: "shared".to_string(),
+        version: "0.1.0".to_string(),
+        ..PackManifest::default()
+    };
+    let exported = PromptPack::export(manifest, Some("review"), &stores.config, &stores.library, &workflows).unwrap();
+    let out = tempfile::tempdir().unwrap();
+    exported.write(out.path()).unwrap();
+
+    let reloaded = PromptPack::load(out.path()).unwrap();
+    let names: Vec<String> = reloaded.items("shared").into_iter().map(|item| item.name).collect();
+    assert_eq!(names, vec!["shared/strict", "shared/diff", "shared/terse", "shared/triage"]);
+    assert_eq!(reloaded.manifest.templates["diff"].variables["lang"].default.as_deref(), Some("rust"));
+}
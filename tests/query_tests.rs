@@ -0,0 +1,100 @@
+use echomind::features::data_processing::{DataFormat, DataProcessor, Dataset};
+use echomind::features::query;
+use serde_json::json;
+use std::io::Write;
+
+const ORDERS: &str = r#"[
+  {"id": 1, "customer": "Ann", "region": "West", "date": "2024-01-15", "amount": 120.5, "status": "paid"},
+  {"id": 2, "customer": "Bob", "region": "East", "date": "2024-02-03", "amount": 80, "status": "paid"},
+  {"id": 3, "customer": "Ann", "region": "West", "date": "2024-02-20", "amount": 40, "status": "void"},
+  {"id": 4, "customer": "Cid", "region": "East", "date": "2023-12-30", "amount": 300, "status": "paid"},
+  {"id": 5, "customer": "Dee", "region": "North", "date": "2024-03-01", "amount": null, "status": "pending"},
+  {"id": 6, "customer": "Eve O'Neil", "region": "West", "date": "2024-03-09", "amount": 60, "status": "paid"}
+]"#;
+
+fn orders() -> Dataset {
+    let mut file = tempfile::Builder::new().suffix(".json").tempfile().unwrap();
+    write!(file, "{}", ORDERS).unwrap();
+    DataProcessor::new()
+        .load_dataset(&file.path().to_string_lossy(), DataFormat::Json)
+        .unwrap()
+}
+
+fn rows(dataset: &Dataset, sql: &str) -> Vec<Vec<serde_json::Value>> {
+    query::execute(dataset, sql)
+        .unwrap_or_else(|e| panic!("{}: {}", sql, e))
+        .rows
+}
+
+#[test]
+fn test_select_where_order_limit_over_every_row() {
+    let data = orders();
+    assert_eq!(
+        rows(&data, "select id, amount * 2 as double from orders where status = 'paid' and amount >= 80 order by amount desc"),
+        vec![vec![json!(4), json!(600)], vec![json!(1), json!(241)], vec![json!(2), json!(160)]]
+    );
+    assert_eq!(rows(&data, "SELECT id FROM t ORDER BY id DESC LIMIT 2 OFFSET 1"), vec![vec![json!(5)], vec![json!(4)]]);
+    assert_eq!(rows(&data, "SELECT id WHERE amount IS NULL"), vec![vec![json!(5)]]);
+    assert_eq!(rows(&data, "SELECT id WHERE customer LIKE '%o''n%'"), vec![vec![json!(6)]]);
+    assert_eq!(
+        rows(&data, "SELECT id WHERE region IN ('East', 'North') AND NOT status = 'pending'"),
+        vec![vec![json!(2)], vec![json!(4)]]
+    );
+    assert_eq!(rows(&data, "SELECT id WHERE amount BETWEEN 60 AND 100 ORDER BY 1"), vec![vec![json!(2)], vec![json!(6)]]);
+    assert_eq!(rows(&data, "SELECT DISTINCT region ORDER BY region"), vec![vec![json!("East")], vec![json!("North")], vec![json!("West")]]);
+
+    let all = query::execute(&data, "SELECT * WHERE YEAR(date) = 2023").unwrap();
+    assert_eq!(all.columns, vec!["amount", "customer", "date", "id", "region", "status"]);
+    assert_eq!(all.rows.len(), 1);
+    assert_eq!(all.matched_rows, 1);
+}
+
+#[test]
+fn test_group_by_having_and_aggregates() {
+    let data = orders();
+    let result = query::execute(
+        &data,
+        "SELECT region, COUNT(*) AS orders, SUM(amount) AS total, ROUND(AVG(amount), 1), COUNT(DISTINCT customer) \
+         WHERE status != 'void' GROUP BY region HAVING orders > 1 ORDER BY total DESC",
+    )
+    .unwrap();
+    assert_eq!(
+        result.columns,
+        vec!["region", "orders", "total", "round(avg(amount), 1)", "count(DISTINCT customer)"]
+    );
+    assert_eq!(
+        result.rows,
+        vec![
+            vec![json!("East"), json!(2), json!(380), json!(190), json!(2)],
+            vec![json!("West"), json!(2), json!(180.5), json!(90.3), json!(2)],
+        ]
+    );
+
+    assert_eq!(
+        rows(&data, "SELECT MONTH(date) AS m, MEDIAN(amount), MIN(customer), MAX(amount) WHERE YEAR(date) = 2024 GROUP BY MONTH(date) ORDER BY m"),
+        vec![
+            vec![json!(1), json!(120.5), json!("Ann"), json!(120.5)],
+            vec![json!(2), json!(60), json!("Ann"), json!(80)],
+            vec![json!(3), json!(60), json!("Dee"), json!(60)],
+        ]
+    );
+    // Aggregates without GROUP BY produce one row, even when nothing matches
+    assert_eq!(rows(&data, "SELECT COUNT(*), SUM(amount) WHERE region = 'South'"), vec![vec![json!(0), json!(0)]]);
+}
+
+#[test]
+fn test_query_errors_are_reported_before_running() {
+    let data = orders();
+    let error = |sql: &str| query::execute(&data, sql).unwrap_err().to_string();
+
+    assert!(error("SELECT price").contains("Unknown column 'price'"));
+    assert!(error("SELECT region, SUM(amount)").contains("'region' must appear in GROUP BY"));
+    assert!(error("SELECT id WHERE SUM(amount) > 1").contains("use HAVING"));
+    assert!(error("SELECT SUM(MAX(amount))").contains("nested aggregate"));
+    assert!(error("SELECT id FROM").contains("table name"));
+    assert!(error("SELECT id WHERE status = 'paid").contains("unterminated"));
+    assert!(error("SELECT id LIMIT ten").contains("whole number"));
+    assert!(error("SELECT frobnicate(id)").contains("unknown function"));
+    assert!(error("DELETE FROM orders").contains("expected SELECT"));
+    assert!(error("SELECT id ORDER BY 9").contains("out of range"));
+}
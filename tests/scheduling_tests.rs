@@ -0,0 +1,146 @@
+use chrono::{Duration, TimeZone, Utc};
+use echomind::config::Config;
+use echomind::features::scheduling::{JobOutput, JobTask, SchedulingManager};
+
+fn prompt_task(prompt: &str) -> JobTask {
+    JobTask::Prompt {
+        prompt: prompt.to_string(),
+        system: None,
+    }
+}
+
+#[test]
+fn test_parse_schedule_accepts_five_and_six_fields() {
+    assert!(SchedulingManager::parse_schedule("*/5 * * * *").is_ok());
+    assert!(SchedulingManager::parse_schedule("0 30 9 * * Mon-Fri").is_ok());
+    assert!(SchedulingManager::parse_schedule("@hourly").is_ok());
+    assert!(SchedulingManager::parse_schedule("every morning").is_err());
+}
+
+#[test]
+fn test_jobs_persist_and_cancel_by_prefix() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut manager = SchedulingManager::open(dir.path()).unwrap();
+    let id = manager
+        .schedule_task("digest", prompt_task("Summarize"), "0 9 * * *", JobOutput::Stdout)
+        .unwrap();
+    assert!(manager.schedule_task("bad", prompt_task("x"), "nope", JobOutput::Stdout).is_err());
+
+    let reopened = SchedulingManager::open(dir.path()).unwrap();
+    assert_eq!(reopened.list_scheduled_tasks().len(), 1);
+    assert_eq!(reopened.list_scheduled_tasks()[0].name, "digest");
+
+    let mut reopened = reopened;
+    let cancelled = reopened.cancel_task(&id[..4]).unwrap();
+    assert_eq!(cancelled.id, id);
+    assert!(SchedulingManager::open(dir.path()).unwrap().list_scheduled_tasks().is_empty());
+}
+
+#[test]
+fn test_missed_runs_are_collapsed_and_recorded() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut manager = SchedulingManager::open(dir.path()).unwrap();
+    manager
+        .schedule_task("hourly", prompt_task("ping"), "0 * * * *", JobOutput::Stdout)
+        .unwrap();
+
+    // Pretend the job was created a day ago and the daemon was down since
+    let mut job = manager.list_scheduled_tasks()[0].clone();
+    let created = Utc.with_ymd_and_hms(2026, 1, 1, 0, 30, 0).unwrap();
+    job.created_at = created;
+    let mut manager = SchedulingManager::new();
+    manager.add_job(job.clone()).unwrap();
+
+    let now = created + Duration::hours(24);
+    let due = manager.due_jobs(now);
+    assert_eq!(due.len(), 1);
+    assert!(due[0].missed);
+    assert!(due[0].scheduled_for > now - Duration::hours(1));
+
+    manager.record_run(&SchedulingManager::skipped_run(&job, due[0].scheduled_for)).unwrap();
+    assert!(manager.due_jobs(now).is_empty());
+    assert_eq!(manager.due_jobs(now + Duration::hours(1)).len(), 1);
+}
+
+#[test]
+fn test_recording_a_run_keeps_changes_made_meanwhile() {
+    let dir = tempfile::tempdir().unwrap();
+    let mut daemon = SchedulingManager::open(dir.path()).unwrap();
+    let first = daemon
+        .schedule_task("first", prompt_task("one"), "0 * * * *", JobOutput::Stdout)
+        .unwrap();
+    let job = daemon.get_job(&first).unwrap().clone();
+
+    // Another invocation adds a job and cancels the one the daemon is running
+    let mut cli = SchedulingManager::open(dir.path()).unwrap();
+    cli.schedule_task("second", prompt_task("two"), "0 * * * *", JobOutput::Stdout)
+        .unwrap();
+    cli.cancel_task(&first).unwrap();
+
+    daemon.record_run(&SchedulingManager::skipped_run(&job, Utc::now())).unwrap();
+
+    let stored = SchedulingManager::open(dir.path()).unwrap();
+    let names: Vec<&str> = stored.list_scheduled_tasks().iter().map(|j| j.name.as_str()).collect();
+    assert_eq!(names, vec!["second"]);
+}
+
+#[tokio::test]
+async fn test_run_job_delivers_to_file_and_webhook() {
+    let mut server = mockito::Server::new_async().await;
+    let chat = server
+        .mock("POST", "/chat")
+        .with_status(200)
+        .with_body(r#"{"choices":[{"message":{"role":"assistant","content":"all quiet"}}]}"#)
+        .expect(2)
+        .create_async()
+        .await;
+    let hook = server
+        .mock("POST", "/hook")
+        .match_body(mockito::Matcher::PartialJson(serde_json::json!({
+            "success": true,
+            "output": "all quiet"
+        })))
+        .with_status(204)
+        .create_async()
+        .await;
+
+    let mut config = Config::default();
+    config.api.provider = format!("{}/chat", server.url());
+    config.api.api_key = Some("test-key".to_string());
+
+    let dir = tempfile::tempdir().unwrap();
+    let out = dir.path().join("digest.md");
+    let mut manager = SchedulingManager::open(dir.path()).unwrap();
+    let file_job = manager
+        .schedule_task(
+            "to file",
+            prompt_task("status?"),
+            "@daily",
+            JobOutput::File { path: out.to_string_lossy().to_string(), append: true },
+        )
+        .unwrap();
+    let hook_job = manager
+        .schedule_task(
+            "to hook",
+            prompt_task("status again?"),
+            "@daily",
+            JobOutput::Webhook { url: format!("{}/hook", server.url()) },
+        )
+        .unwrap();
+
+    for id in [&file_job, &hook_job] {
+        let job = manager.get_job(id).unwrap().clone();
+        let run = SchedulingManager::run_job(&job, &config, Utc::now(), false).await;
+        assert!(run.success, "{:?}", run.error);
+        manager.record_run(&run).unwrap();
+    }
+
+    chat.assert_async().await;
+    hook.assert_async().await;
+    assert_eq!(std::fs::read_to_string(&out).unwrap(), "all quiet\n");
+
+    let runs = manager.list_runs(Some(&file_job)).unwrap();
+    assert_eq!(runs.len(), 1);
+    assert_eq!(runs[0].output.as_deref(), Some("all quiet"));
+    assert!(manager.get_job(&file_job).unwrap().last_run.is_some());
+}
@@ -0,0 +1,202 @@
+use echomind::features::data_processing::{DataFormat, DataProcessor};
+use serde_json::{json, Value};
+use std::io::Write;
+use zip::write::FileOptions;
+
+// Cells are written as "kind:value": s text, n number, d date (a day
+// serial), t duration (in days), b boolean, e error; "" leaves a gap.
+fn sheet_xml(rows: &[&[&str]], merged: &str) -> String {
+    let mut xml = String::from(
+        r#"<?xml version="1.0" encoding="UTF-8"?><worksheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main"><sheetData>"#,
+    );
+    for (r, row) in rows.iter().enumerate() {
+        xml.push_str(&format!(r#"<row r="{}">"#, r + 1));
+        for (c, cell) in row.iter().enumerate() {
+            let Some((kind, value)) = cell.split_once(':') else { continue };
+            let at = format!("{}{}", (b'A' + c as u8) as char, r + 1);
+            xml.push_str(&match kind {
+                "s" => format!(r#"<c r="{}" t="inlineStr"><is><t>{}</t></is></c>"#, at, value),
+                "d" => format!(r#"<c r="{}" s="1"><v>{}</v></c>"#, at, value),
+                "t" => format!(r#"<c r="{}" s="2"><v>{}</v></c>"#, at, value),
+                "b" => format!(r#"<c r="{}" t="b"><v>{}</v></c>"#, at, value),
+                "e" => format!(r#"<c r="{}" t="e"><v>{}</v></c>"#, at, value),
+                _ => format!(r#"<c r="{}"><v>{}</v></c>"#, at, value),
+            });
+        }
+        xml.push_str("</row>");
+    }
+    xml.push_str("</sheetData>");
+    if !merged.is_empty() {
+        xml.push_str(&format!(r#"<mergeCells count="1"><mergeCell ref="{}"/></mergeCells>"#, merged));
+    }
+    xml.push_str("</worksheet>");
+    xml
+}
+
+fn xlsx(sheets: &[(&str, String)]) -> tempfile::NamedTempFile {
+    let file = tempfile::Builder::new().suffix(".xlsx").tempfile().unwrap();
+    let mut zip = zip::ZipWriter::new(file.reopen().unwrap());
+    let mut add = |name: &str, content: String| {
+        zip.start_file(name, FileOptions::default()).unwrap();
+        zip.write_all(content.as_bytes()).unwrap();
+    };
+
+    let ids = 1..=sheets.len();
+    add(
+        "[Content_Types].xml",
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?><Types xmlns="http://schemas.openxmlformats.org/package/2006/content-types"><Default Extension="rels" ContentType="application/vnd.openxmlformats-package.relationships+xml"/><Default Extension="xml" ContentType="application/xml"/><Override PartName="/xl/workbook.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.sheet.main+xml"/><Override PartName="/xl/styles.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.styles+xml"/>{}</Types>"#,
+            ids.clone()
+                .map(|i| format!(r#"<Override PartName="/xl/worksheets/sheet{}.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.worksheet+xml"/>"#, i))
+                .collect::<String>()
+        ),
+    );
+    add(
+        "_rels/.rels",
+        r#"<?xml version="1.0" encoding="UTF-8"?><Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships"><Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/officeDocument" Target="xl/workbook.xml"/></Relationships>"#.to_string(),
+    );
+    add(
+        "xl/workbook.xml",
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?><workbook xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main" xmlns:r="http://schemas.openxmlformats.org/officeDocument/2006/relationships"><sheets>{}</sheets></workbook>"#,
+            sheets
+                .iter()
+                .zip(ids.clone())
+                .map(|((name, _), i)| format!(r#"<sheet name="{}" sheetId="{}" r:id="rId{}"/>"#, name, i, i))
+                .collect::<String>()
+        ),
+    );
+    add(
+        "xl/_rels/workbook.xml.rels",
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?><Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships">{}<Relationship Id="rIdStyles" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/styles" Target="styles.xml"/></Relationships>"#,
+            ids.clone()
+                .map(|i| format!(r#"<Relationship Id="rId{}" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/worksheet" Target="worksheets/sheet{}.xml"/>"#, i, i))
+                .collect::<String>()
+        ),
+    );
+    // Style 1 is a date (format 14), style 2 a duration (format 46)
+    add(
+        "xl/styles.xml",
+        r#"<?xml version="1.0" encoding="UTF-8"?><styleSheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main"><cellXfs count="3"><xf numFmtId="0"/><xf numFmtId="14" applyNumberFormat="1"/><xf numFmtId="46" applyNumberFormat="1"/></cellXfs></styleSheet>"#.to_string(),
+    );
+    for ((_, xml), i) in sheets.iter().zip(ids) {
+        add(&format!("xl/worksheets/sheet{}.xml", i), xml.clone());
+    }
+    zip.finish().unwrap();
+    file
+}
+
+fn budget() -> tempfile::NamedTempFile {
+    let sales = sheet_xml(
+        &[
+            &["s:Quarterly sales"],
+            &[],
+            &["s:Region", "s:2023", "", "s:2024", ""],
+            &["", "s:Q1", "s:Q2", "s:Q1", "s:Q2"],
+            &["s:north", "n:120", "n:130", "n:140", "n:150.5"],
+            &["s:south", "n:80", "n:85", "n:90", "n:95"],
+        ],
+        "A1:E1",
+    );
+    let log = sheet_xml(
+        &[
+            &["s:Date", "s:Amount", "s:Paid", "s:Time spent", "", "s:Note"],
+            &["d:45292", "n:12.5", "b:1", "t:0.0625", "", "s:first"],
+            &["d:45293.5", "e:#DIV/0!", "b:0", "t:1.5", "n:7", ""],
+            &[],
+            &["d:45294", "n:3", "b:1", "", "", "s:last"],
+        ],
+        "",
+    );
+    let raw = sheet_xml(&[&["n:1", "n:2"], &["n:3", "n:4"], &["n:5", "n:6"]], "");
+    xlsx(&[("Notes", sheet_xml(&[], "")), ("Sales", sales), ("Log", log), ("Raw", raw)])
+}
+
+#[test]
+fn test_every_sheet_with_data_is_analyzed() {
+    let file = budget();
+    let path = file.path().to_string_lossy().to_string();
+    let processor = DataProcessor::new();
+    assert_eq!(processor.sheet_names(&path).unwrap(), vec!["Notes", "Sales", "Log", "Raw"]);
+
+    let analyses = processor.analyze_sheets(&path).unwrap();
+    let types: Vec<&str> = analyses.iter().map(|a| a.file_type.as_str()).collect();
+    assert_eq!(types, vec![r#"Excel sheet "Sales""#, r#"Excel sheet "Log""#, r#"Excel sheet "Raw""#]);
+
+    // The title row is skipped and the merged year cells name their quarters
+    let sales = &analyses[0];
+    assert_eq!(sales.column_names, vec!["Region", "2023 Q1", "2023 Q2", "2024 Q1", "2024 Q2"]);
+    assert_eq!(sales.total_rows, 2);
+    assert_eq!(sales.column_types["2024 Q2"], "number");
+    assert!(sales.describe(&path, 5).starts_with(&format!(r#"File: {} (Excel sheet "Sales", 2 rows, 5 columns)"#, path)));
+
+    // Without --sheet, queries read the first sheet with data
+    let dataset = processor.load_dataset(&path, DataFormat::Excel).unwrap();
+    assert_eq!(dataset.records[0]["2023 Q1"], json!(120));
+    assert_eq!(dataset.records[1]["2024 Q2"], json!(95));
+}
+
+#[test]
+fn test_sheet_selection_and_typed_cells() {
+    let file = budget();
+    let path = file.path().to_string_lossy().to_string();
+    let mut processor = DataProcessor::new();
+
+    processor.set_sheet(Some("log".to_string()));
+    let dataset = processor.load_dataset(&path, DataFormat::Excel).unwrap();
+    // The unnamed column with data is called by its letter; the blank row is dropped
+    assert_eq!(dataset.column_names, vec!["Date", "Amount", "Paid", "Time spent", "E", "Note"]);
+    assert_eq!(dataset.records.len(), 3);
+    let first = &dataset.records[0];
+    assert_eq!(first["Date"], json!("2024-01-01"));
+    assert_eq!(first["Amount"], json!(12.5));
+    assert_eq!(first["Paid"], json!(true));
+    assert_eq!(first["Time spent"], json!("1:30:00"));
+    let second = &dataset.records[1];
+    assert_eq!(second["Date"], json!("2024-01-02T12:00:00"));
+    assert_eq!(second["Amount"], Value::Null);
+    assert_eq!(second["Time spent"], json!("36:00:00"));
+    assert_eq!(second["E"], json!(7));
+
+    let analysis = processor.analyze_file(&path, DataFormat::Excel).unwrap();
+    assert_eq!(analysis.column_types["Paid"], "boolean");
+    assert_eq!(analysis.summary_stats["Amount"].null_count, 1);
+    assert_eq!(analysis.summary_stats["Date"].max_value, Some(json!("2024-01-03")));
+
+    // A sheet of numbers alone has no header row
+    processor.set_sheet(Some("4".to_string()));
+    let raw = processor.load_dataset(&path, DataFormat::Excel).unwrap();
+    assert_eq!(raw.column_names, vec!["A", "B"]);
+    assert_eq!(raw.records.len(), 3);
+    assert_eq!(processor.analyze_sheets(&path).unwrap().len(), 1);
+
+    processor.set_sheet(Some("Budget".to_string()));
+    let err = processor.load_dataset(&path, DataFormat::Excel).unwrap_err();
+    assert!(err.to_string().contains("No sheet 'Budget'"), "{}", err);
+    assert!(err.to_string().contains("Notes, Sales, Log, Raw"), "{}", err);
+}
+
+#[test]
+fn test_ods_spreadsheets() {
+    let file = tempfile::Builder::new().suffix(".ods").tempfile().unwrap();
+    let mut zip = zip::ZipWriter::new(file.reopen().unwrap());
+    zip.start_file("mimetype", FileOptions::default().compression_method(zip::CompressionMethod::Stored))
+        .unwrap();
+    zip.write_all(b"application/vnd.oasis.opendocument.spreadsheet").unwrap();
+    zip.start_file("META-INF/manifest.xml", FileOptions::default()).unwrap();
+    zip.write_all(br#"<?xml version="1.0" encoding="UTF-8"?><manifest:manifest xmlns:manifest="urn:oasis:names:tc:opendocument:xmlns:manifest:1.0"><manifest:file-entry manifest:full-path="/" manifest:media-type="application/vnd.oasis.opendocument.spreadsheet"/></manifest:manifest>"#)
+        .unwrap();
+    zip.start_file("content.xml", FileOptions::default()).unwrap();
+    zip.write_all(
+        br#"<?xml version="1.0" encoding="UTF-8"?><office:document-content xmlns:office="urn:oasis:names:tc:opendocument:xmlns:office:1.0" xmlns:table="urn:oasis:names:tc:opendocument:xmlns:table:1.0" xmlns:text="urn:oasis:names:tc:opendocument:xmlns:text:1.0"><office:body><office:spreadsheet><table:table table:name="Stock"><table:table-row><table:table-cell office:value-type="string"><text:p>item</text:p></table:table-cell><table:table-cell office:value-type="string"><text:p>count</text:p></table:table-cell><table:table-cell office:value-type="string"><text:p>checked</text:p></table:table-cell></table:table-row><table:table-row><table:table-cell office:value-type="string"><text:p>bolts</text:p></table:table-cell><table:table-cell office:value-type="float" office:value="40"/><table:table-cell office:value-type="date" office:date-value="2024-03-01"/></table:table-row></table:table></office:spreadsheet></office:body></office:document-content>"#,
+    )
+    .unwrap();
+    zip.finish().unwrap();
+
+    let analyses = DataProcessor::new().analyze_sheets(&file.path().to_string_lossy()).unwrap();
+    assert_eq!(analyses.len(), 1);
+    assert_eq!(analyses[0].file_type, r#"ODS sheet "Stock""#);
+    let row = &analyses[0].sample_data[0];
+    assert_eq!((&row["count"], &row["checked"]), (&json!(40), &json!("2024-03-01")));
+}
@@ -0,0 +1,133 @@
+use echomind::features::data_processing::{DataFormat, DataProcessor, Record};
+use echomind::features::streaming::{HyperLogLog, P2Quantile, StreamingAnalyzer, EXACT_LIMIT, SAMPLE_SIZE};
+use serde_json::{json, Value};
+use std::io::Write;
+
+fn record(pairs: &[(&str, Value)]) -> Record {
+    pairs.iter().map(|(k, v)| (k.to_string(), v.clone())).collect()
+}
+
+#[test]
+fn test_large_streams_use_bounded_sketches() {
+    let columns = vec!["id".to_string(), "amount".to_string(), "status".to_string()];
+    let rows = 50_000;
+    let mut analyzer = StreamingAnalyzer::new();
+    for i in 0..rows {
+        // amount cycles through 0..1000; status is "ok" for 60% of rows
+        let status = if i % 5 < 3 { "ok".to_string() } else { format!("error-{}", i) };
+        analyzer.observe(
+            &columns,
+            record(&[("id", json!(i)), ("amount", json!(i % 1000)), ("status", json!(status))]),
+        );
+    }
+    let analysis = analyzer.finish("CSV");
+    assert_eq!(analysis.total_rows, rows);
+    assert_eq!(analysis.sample_data.len(), SAMPLE_SIZE);
+
+    let ids = &analysis.summary_stats["id"];
+    assert!(ids.approximate);
+    let error = (ids.unique_count as f64 - rows as f64).abs() / rows as f64;
+    assert!(error < 0.05, "distinct estimate {} is off by {:.1}%", ids.unique_count, error * 100.0);
+
+    let amounts = &analysis.summary_stats["amount"];
+    assert!((amounts.mean_value.unwrap() - 499.5).abs() < 1e-6);
+    assert!((amounts.std_dev.unwrap() - 288.68).abs() < 0.1);
+    assert!((amounts.median_value.unwrap() - 500.0).abs() < 25.0, "{:?}", amounts.median_value);
+    assert!((amounts.p25_value.unwrap() - 250.0).abs() < 25.0, "{:?}", amounts.p25_value);
+    assert_eq!(amounts.min_value, Some(json!(0.0)));
+    assert_eq!(amounts.max_value, Some(json!(999.0)));
+
+    // The heavy hitter survives the switch to Space-Saving counters
+    let statuses = &analysis.summary_stats["status"];
+    let (value, count) = statuses.most_common.clone().unwrap();
+    assert_eq!(value, json!("ok"));
+    assert!(count >= 30_000, "{}", count);
+    assert_eq!(analysis.column_types["status"], "string");
+}
+
+#[test]
+fn test_small_files_keep_exact_statistics() {
+    let mut file = tempfile::Builder::new().suffix(".csv").tempfile().unwrap();
+    write!(file, "city,temp\nOslo,4\nRome,18\nOslo,6\nLima,\nRome,20\n").unwrap();
+    let analysis = DataProcessor::new()
+        .analyze_file(&file.path().to_string_lossy(), DataFormat::Csv)
+        .unwrap();
+
+    let temp = &analysis.summary_stats["temp"];
+    assert!(!temp.approximate);
+    assert_eq!((temp.null_count, temp.unique_count), (1, 4));
+    assert_eq!(temp.median_value, Some(12.0));
+    assert_eq!((temp.p25_value, temp.p75_value), (Some(5.5), Some(18.5)));
+
+    let city = &analysis.summary_stats["city"];
+    assert_eq!(city.top_values, vec![(json!("Oslo"), 2), (json!("Rome"), 2), (json!("Lima"), 1)]);
+    assert_eq!(city.min_value, Some(json!("Lima")));
+
+    // Every row fits in the sample, in file order
+    let cities: Vec<&Value> = analysis.sample_data.iter().map(|r| &r["city"]).collect();
+    assert_eq!(cities, vec!["Oslo", "Rome", "Oslo", "Lima", "Rome"]);
+
+    let description = analysis.describe("weather.csv", 5);
+    assert!(
+        description.contains("- temp: number, 4 distinct, 1 empty, min 4, max 20, mean 12, sd 8.164966, quartiles 5.5 / 12 / 18.5"),
+        "{}",
+        description
+    );
+    assert!(description.contains("- city: string, 3 distinct, min Lima, max Rome, most common \"Oslo\" (2 rows), \"Rome\" (2 rows)\n"));
+}
+
+#[test]
+fn test_json_lines_gzip_and_streamed_arrays() {
+    let processor = DataProcessor::new();
+
+    // JSON Lines, gzipped; `note` only appears on the second line
+    let lines = "{\"id\": 1, \"score\": 3}\n{\"id\": 2, \"score\": 5, \"note\": \"late\"}\n\n{\"id\": 3, \"score\": 4}\n";
+    let gz = tempfile::Builder::new().suffix(".jsonl.gz").tempfile().unwrap();
+    let mut encoder = flate2::write::GzEncoder::new(gz.reopen().unwrap(), flate2::Compression::default());
+    encoder.write_all(lines.as_bytes()).unwrap();
+    encoder.finish().unwrap();
+
+    let path = gz.path().to_string_lossy().to_string();
+    let dataset = processor.load_dataset(&path, DataFormat::Json).unwrap();
+    assert_eq!(dataset.column_names, vec!["id", "score", "note"]);
+    assert_eq!(dataset.records.len(), 3);
+
+    let analysis = processor.analyze_file(&path, DataFormat::Json).unwrap();
+    assert_eq!(analysis.total_rows, 3);
+    assert_eq!(analysis.summary_stats["note"].null_count, 2);
+    assert_eq!(analysis.summary_stats["score"].mean_value, Some(4.0));
+
+    // A plain array is streamed element by element; non-objects are skipped
+    let mut array = tempfile::Builder::new().suffix(".json").tempfile().unwrap();
+    write!(array, "  [{{\"a\": 1}}, 7, {{\"a\": 2, \"b\": true}}]").unwrap();
+    let analysis = processor
+        .analyze_file(&array.path().to_string_lossy(), DataFormat::Json)
+        .unwrap();
+    assert_eq!(analysis.total_rows, 2);
+    assert_eq!(analysis.column_types["b"], "boolean");
+
+    let mut broken = tempfile::Builder::new().suffix(".jsonl").tempfile().unwrap();
+    write!(broken, "{{\"a\": 1}}\n{{\"a\": \n").unwrap();
+    let err = processor
+        .analyze_file(&broken.path().to_string_lossy(), DataFormat::Json)
+        .unwrap_err();
+    assert!(err.to_string().contains("record 2"), "{}", err);
+}
+
+#[test]
+fn test_sketches() {
+    let mut hll = HyperLogLog::new();
+    for i in 0..EXACT_LIMIT * 10 {
+        hll.insert(&json!(format!("user-{}", i)));
+        hll.insert(&json!(format!("user-{}", i)));
+    }
+    let estimate = hll.estimate() as f64;
+    assert!((estimate / 100_000.0 - 1.0).abs() < 0.05, "{}", estimate);
+
+    let mut p90 = P2Quantile::new(0.9);
+    assert_eq!(p90.estimate(), None);
+    for i in (0..10_000).rev() {
+        p90.observe(i as f64);
+    }
+    assert!((p90.estimate().unwrap() - 9000.0).abs() < 100.0, "{:?}", p90.estimate());
+}
@@ -0,0 +1,151 @@
+mod common;
+
+use common::{client, reply};
+use echomind::api::{ChatRequest, Message};
+use echomind::config::BudgetConfig;
+use echomind::error::EchomindError;
+use echomind::features::performance::{LatencyHistogram, LoadTestConfig, PerformanceMonitor};
+use echomind::features::usage::{UsageLedger, UsageMeter};
+use std::time::Duration;
+
+const PONG: &str = "pong pong pong pong";
+
+fn request() -> ChatRequest {
+    ChatRequest {
+        messages: vec![Message::text("user".to_string(), "ping".to_string())],
+        model: Some("test".to_string()),
+        temperature: None,
+        max_tokens: None,
+        top_p: None,
+        top_k: None,
+        stream: None,
+    }
+}
+
+#[tokio::test]
+async fn test_open_loop_load_test_holds_the_rate_and_skips_warmup() {
+    let mut server = mockito::Server::new_async().await;
+    server
+        .mock("POST", "/ok")
+        .with_status(200)
+        .with_body(reply(PONG))
+        .expect_at_least(1)
+        .create_async()
+        .await;
+
+    let config = LoadTestConfig {
+        rate: Some(40.0),
+        concurrency: 8,
+        warmup: Duration::from_millis(250),
+        duration: Duration::from_millis(1000),
+    };
+    let ledger = tempfile::tempdir().unwrap();
+    let mut monitor = PerformanceMonitor::new();
+    let usage_path = ledger.path().join("usage.jsonl");
+    monitor.set_usage_meter(UsageMeter::new(Some(UsageLedger::new(usage_path.clone())), BudgetConfig::default(), "stress"));
+    let mut updates = 0;
+    let report = monitor
+        .run_stress_test(&client(format!("{}/ok", server.url())), request(), &config, |_| updates += 1)
+        .await
+        .unwrap();
+
+    // 40 req/s for one second; ticks land on the window edges, so allow one either way
+    assert!((39..=41).contains(&report.sent), "{:?}", report);
+    assert!((9..=11).contains(&report.warmup_requests), "{:?}", report);
+    assert_eq!((report.succeeded, report.failed, report.dropped), (report.sent, 0, 0));
+    assert_eq!(report.output_tokens, report.succeeded * 5);
+    assert!(report.p50_ms.is_some() && report.p99_ms >= report.p50_ms && report.max_ms >= report.p99_ms);
+    assert_eq!(report.latency.counts.iter().sum::<u64>(), report.succeeded);
+    assert!(updates as u64 >= report.sent);
+    assert_eq!(monitor.metrics().successful_requests, report.succeeded);
+    assert!(report.render().contains("open loop at 40 req/s, at most 8 in flight"));
+
+    // Every request is billed, warmup included
+    let records = UsageLedger::new(usage_path).records().unwrap();
+    assert_eq!(records.len() as u64, report.sent + report.warmup_requests);
+    assert!(records.iter().all(|r| r.success && r.command == "stress" && r.output_tokens == 5));
+}
+
+#[tokio::test]
+async fn test_load_test_groups_errors_and_drops_requests_over_the_limit() {
+    let mut server = mockito::Server::new_async().await;
+    server
+        .mock("POST", "/limited")
+        .with_status(429)
+        .with_body("slow down")
+        .create_async()
+        .await;
+    server
+        .mock("POST", "/slow")
+        .with_status(200)
+        .with_body_from_request(|_| {
+            std::thread::sleep(Duration::from_millis(120));
+            reply(PONG).into_bytes()
+        })
+        .create_async()
+        .await;
+
+    let mut monitor = PerformanceMonitor::new();
+    let closed = LoadTestConfig {
+        rate: None,
+        concurrency: 2,
+        warmup: Duration::ZERO,
+        duration: Duration::from_millis(300),
+    };
+    let report = monitor
+        .run_stress_test(&client(format!("{}/limited", server.url())), request(), &closed, |_| {})
+        .await
+        .unwrap();
+    assert!(report.sent > 0);
+    assert_eq!(report.succeeded, 0);
+    assert_eq!(report.errors.get("ApiError 429"), Some(&report.sent));
+    assert!(report.render().contains("closed loop with 2 workers"));
+    assert!(report.render().contains("ApiError 429"));
+
+    let saturated = LoadTestConfig {
+        rate: Some(50.0),
+        concurrency: 1,
+        warmup: Duration::ZERO,
+        duration: Duration::from_millis(500),
+    };
+    let report = monitor
+        .run_stress_test(&client(format!("{}/slow", server.url())), request(), &saturated, |_| {})
+        .await
+        .unwrap();
+    assert!(report.dropped > 0, "{:?}", report);
+    assert!(report.succeeded > 0 && report.succeeded < 25, "{:?}", report);
+    assert!(report.p50_ms.unwrap() >= 120);
+}
+
+#[test]
+fn test_latency_histogram_and_config_checks() {
+    let mut histogram = LatencyHistogram::new();
+    for ms in [5, 40, 45, 300, 70_000] {
+        histogram.record(ms);
+    }
+    assert_eq!(histogram.counts[0], 1);
+    assert_eq!(histogram.counts[2], 2);
+    assert_eq!(*histogram.counts.last().unwrap(), 1);
+    let rendered = histogram.render(10);
+    assert!(rendered.starts_with("   ≤ 10 ms █████"), "{}", rendered);
+    assert!(rendered.contains("   ≤ 50 ms ██████████ 2 (40.0%)"), "{}", rendered);
+    assert!(rendered.ends_with("> 60000 ms █████      1 (20.0%)"), "{}", rendered);
+
+    let bad = LoadTestConfig {
+        rate: Some(0.0),
+        concurrency: 1,
+        warmup: Duration::ZERO,
+        duration: Duration::from_secs(1),
+    };
+    assert!(bad.validate().is_err());
+    assert!(LoadTestConfig { concurrency: 0, rate: None, ..bad.clone() }.validate().is_err());
+    let planned = LoadTestConfig {
+        rate: Some(2.5),
+        concurrency: 4,
+        warmup: Duration::from_secs(2),
+        duration: Duration::from_secs(10),
+    };
+    assert_eq!(planned.expected_requests(), 30);
+    assert_eq!(LoadTestConfig { rate: None, ..planned }.expected_requests(), 48);
+    assert_eq!(EchomindError::TimeoutError(30).kind(), "TimeoutError");
+}
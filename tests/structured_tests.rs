@@ -0,0 +1,154 @@
+mod common;
+
+use common::{client, reply};
+use echomind::api::{ChatRequest, Message, Provider};
+use echomind::config::{BudgetAction, BudgetConfig};
+use echomind::features::structured::{self, OutputSchema};
+use echomind::features::usage::{UsageLedger, UsageMeter};
+use serde_json::json;
+
+fn schema() -> OutputSchema {
+    OutputSchema::new(
+        "invoice",
+        json!({
+            "type": "object",
+            "properties": {"number": {"type": "string"}, "total": {"type": "number"}},
+            "required": ["number", "total"]
+        }),
+    )
+}
+
+fn request() -> ChatRequest {
+    ChatRequest {
+        messages: vec![
+            Message::text("system".to_string(), "You read invoices.".to_string()),
+            Message::text("user".to_string(), "Invoice INV-7, total 12.50".to_string()),
+        ],
+        model: Some("test-model".to_string()),
+        temperature: Some(0.0),
+        max_tokens: None,
+        top_p: None,
+        top_k: None,
+        stream: None,
+    }
+}
+
+#[tokio::test]
+async fn test_native_schema_with_repair_retry() {
+    let mut server = mockito::Server::new_async().await;
+    let first = server
+        .mock("POST", "/chat")
+        .match_body(mockito::Matcher::AllOf(vec![
+            mockito::Matcher::PartialJson(json!({
+                "response_format": {"type": "json_schema", "json_schema": {"name": "invoice"}}
+            })),
+            mockito::Matcher::Regex("You read invoices".to_string()),
+        ]))
+        .with_status(200)
+        .with_body(reply("{\"number\": \"INV-7\", \"total\": \"12.50\"}"))
+        .create_async()
+        .await;
+    // The repair request carries the bad reply and what was wrong with it
+    let repair = server
+        .mock("POST", "/chat")
+        .match_body(mockito::Matcher::Regex(r"does not match the JSON Schema:\\n- \$\.total: expected number, got string".to_string()))
+        .with_status(200)
+        .with_body(reply("```json\n{\"number\": \"INV-7\", \"total\": 12.5}\n```"))
+        .create_async()
+        .await;
+
+    let reply = structured::send(&client(format!("{}/chat", server.url())), &request(), &schema(), None)
+        .await
+        .unwrap();
+    first.assert_async().await;
+    repair.assert_async().await;
+    assert_eq!(reply.attempts, 2);
+    assert_eq!(reply.value, json!({"number": "INV-7", "total": 12.5}));
+    assert!(reply.raw.starts_with("```json"));
+}
+
+#[tokio::test]
+async fn test_reply_that_stays_invalid_is_an_error() {
+    let mut server = mockito::Server::new_async().await;
+    let mock = server
+        .mock("POST", "/chat")
+        .with_status(200)
+        .with_body(reply("Sorry, I can't read that invoice."))
+        .expect(2)
+        .create_async()
+        .await;
+    let err = structured::send(&client(format!("{}/chat", server.url())), &request(), &schema(), None)
+        .await
+        .unwrap_err();
+    assert_eq!(err.kind(), "ParseError");
+    assert!(err.to_string().contains("after 2 attempts: the reply is not JSON"), "{}", err);
+
+    // Over a blocking budget nothing more is sent
+    let dir = tempfile::tempdir().unwrap();
+    let budget = BudgetConfig {
+        daily: Some(0.0),
+        action: BudgetAction::Block,
+        ..Default::default()
+    };
+    let meter = UsageMeter::new(Some(UsageLedger::new(dir.path().join("usage.jsonl"))), budget, "query");
+    let err = structured::send(&client(format!("{}/chat", server.url())), &request(), &schema(), Some(&meter))
+        .await
+        .unwrap_err();
+    assert!(err.to_string().starts_with("Request blocked"), "{}", err);
+    mock.assert_async().await;
+}
+
+#[test]
+fn test_prompt_fallback_and_schema_loading() {
+    // Providers without a native mode get the schema after the system prompt
+    let prepared = structured::prepare(&Provider::Claude, &request(), &schema());
+    let roles: Vec<&str> = prepared.messages.iter().map(|m| m.role.as_str()).collect();
+    assert_eq!(roles, vec!["system", "system", "user"]);
+    let instructions = prepared.messages[1].get_text().unwrap();
+    assert!(instructions.contains("conforms to this JSON Schema"));
+    assert!(instructions.contains("\"required\""));
+    assert_eq!(structured::prepare(&Provider::OpenAI, &request(), &schema()).messages.len(), 2);
+    assert!(Provider::Gemini.supports_json_schema() && Provider::Ollama.supports_json_schema());
+    assert!(!Provider::Cohere.supports_json_schema());
+
+    assert_eq!(schema().validate("{\"number\": \"1\"}").unwrap_err(), vec!["$: missing required property 'total'"]);
+
+    // Local references are followed, recursively
+    let lines = OutputSchema::new(
+        "lines",
+        json!({
+            "type": "object",
+            "properties": {"items": {"type": "array", "items": {"$ref": "#/$defs/line"}}},
+            "$defs": {
+                "line": {
+                    "type": "object",
+                    "properties": {"amount": {"type": "number"}, "parts": {"type": "array", "items": {"$ref": "#/$defs/line"}}},
+                    "required": ["amount"]
+                }
+            }
+        }),
+    );
+    assert!(lines.validate("{\"items\": [{\"amount\": 1, \"parts\": [{\"amount\": 2}]}]}").is_ok());
+    assert_eq!(
+        lines.validate("{\"items\": [{\"amount\": \"1\"}, {\"parts\": [{\"amount\": true}]}]}").unwrap_err(),
+        vec![
+            "$.items[0].amount: expected number, got string",
+            "$.items[1]: missing required property 'amount'",
+            "$.items[1].parts[0].amount: expected number, got boolean",
+        ]
+    );
+    let cycle = OutputSchema::new("cycle", json!({"$defs": {"a": {"$ref": "#/$defs/a"}}, "$ref": "#/$defs/a"}));
+    assert!(cycle.validate("{}").unwrap_err()[0].contains("nests too deeply"));
+
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("line item.schema.json");
+    std::fs::write(&path, "{\"type\": \"object\"}").unwrap();
+    assert_eq!(OutputSchema::load(&path.to_string_lossy()).unwrap().name, "line_item_schema");
+    std::fs::write(&path, "[1, 2]").unwrap();
+    assert!(OutputSchema::load(&path.to_string_lossy()).unwrap_err().to_string().contains("must be a JSON object"));
+    std::fs::write(&path, "{\"$ref\": \"https://example.com/invoice.json\"}").unwrap();
+    let err = OutputSchema::load(&path.to_string_lossy()).unwrap_err();
+    assert!(err.to_string().contains("can't be resolved: https://example.com/invoice.json"), "{}", err);
+    std::fs::write(&path, "{").unwrap();
+    assert_eq!(OutputSchema::load(&path.to_string_lossy()).unwrap_err().kind(), "ParseError");
+}
@@ -0,0 +1,103 @@
+use echomind::features::templating::{render, CompiledTemplate, VariableUsage};
+use serde_json::json;
+use std::collections::HashMap;
+use std::io::Write;
+
+fn vars(value: serde_json::Value) -> HashMap<String, serde_json::Value> {
+    serde_json::from_value(value).unwrap()
+}
+
+#[test]
+fn test_defaults_filters_and_escaping() {
+    let variables = vars(json!({ "lang": "rust", "tags": ["cli", "ai"], "code": "fn main() {}\nlet x = 1;" }));
+
+    assert_eq!(render("{{ lang | upper }}", &variables).unwrap(), "RUST");
+    assert_eq!(render("{{missing|plain text}}", &variables).unwrap(), "plain text");
+    assert_eq!(render("{{ missing | \"upper\" }}", &variables).unwrap(), "upper");
+    assert_eq!(render("{{ missing | default(\"x\") | upper }}", &variables).unwrap(), "X");
+    assert_eq!(render("{{ tags | join(\" + \") }}", &variables).unwrap(), "cli + ai");
+    assert_eq!(render("{{ tags | json }}", &variables).unwrap(), r#"["cli","ai"]"#);
+    assert_eq!(
+        render("code:\n{{ code | indent(2) }}", &variables).unwrap(),
+        "code:\n  fn main() {}\n  let x = 1;"
+    );
+    assert_eq!(render("\\{{ lang }} {lang} {\"json\": 1} {other}", &variables).unwrap(), "{{ lang }} rust {\"json\": 1} {other}");
+
+    let err = render("{{ missing }}", &variables).unwrap_err().to_string();
+    assert!(err.contains("'missing' is not set"), "{}", err);
+    let err = render("line one\n{{ lang | upper | shout }}", &variables).unwrap_err().to_string();
+    assert!(err.contains("line 2") && err.contains("unknown filter 'shout'"), "{}", err);
+}
+
+#[test]
+fn test_file_include_filter() {
+    let mut file = tempfile::NamedTempFile::new().unwrap();
+    write!(file, "included body").unwrap();
+    let variables = vars(json!({ "path": file.path().to_string_lossy() }));
+
+    assert_eq!(render("<{{ path | file }}>", &variables).unwrap(), "<included body>");
+
+    // Only the caller picks the file: a template can't name one itself
+    for source in ["{{ '/etc/hostname' | file }}", "{{ path | '/etc/hostname' | file }}", "{{ unset | file | default('/etc/hostname') }}"] {
+        let err = render(source, &variables).unwrap_err().to_string();
+        assert!(err.contains("the file filter only reads a path given in a variable"), "{}", err);
+    }
+}
+
+#[test]
+fn test_conditionals_and_loops() {
+    let template = "\
+Review:
+{% if severity >= 3 and not draft %}
+urgent
+{% elif severity == 2 %}
+normal
+{% else %}
+low
+{% endif %}
+{% for file in files %}
+{{ loop.index }}. {{ file.name }}{% if not loop.last %},{% endif %}
+{% endfor %}
+done";
+
+    let variables = vars(json!({
+        "severity": "3",
+        "draft": "false",
+        "files": [{ "name": "a.rs" }, { "name": "b.rs" }]
+    }));
+    assert_eq!(render(template, &variables).unwrap(), "Review:\nurgent\n1. a.rs,\n2. b.rs\ndone");
+
+    let variables = vars(json!({ "severity": 2, "files": [] }));
+    assert_eq!(render(template, &variables).unwrap(), "Review:\nnormal\ndone");
+
+    assert!(render("{% if x %}open", &HashMap::new()).is_err());
+    assert!(render("{% for x of xs %}{% endfor %}", &HashMap::new()).is_err());
+    assert!(render("{% endif %}", &HashMap::new()).is_err());
+}
+
+#[test]
+fn test_variables_are_collected_with_usage() {
+    let compiled = CompiledTemplate::compile(
+        "{{ who|friend }} {% if verbose %}{{ detail }}{% endif %}\
+         {% for item in items %}{{ item.name }} {{ loop.index }}{% endfor %}\
+         {{ spec | file }} {legacy}",
+    )
+    .unwrap();
+
+    let refs = compiled.variables();
+    let summary: Vec<(&str, Option<&str>, VariableUsage)> = refs
+        .iter()
+        .map(|r| (r.name.as_str(), r.default.as_deref(), r.usage))
+        .collect();
+    assert_eq!(
+        summary,
+        vec![
+            ("who", Some("friend"), VariableUsage::Value),
+            ("verbose", None, VariableUsage::Condition),
+            ("detail", None, VariableUsage::Value),
+            ("items", None, VariableUsage::List),
+            ("spec", None, VariableUsage::File),
+            ("legacy", None, VariableUsage::Legacy),
+        ]
+    );
+}
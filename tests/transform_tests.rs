@@ -0,0 +1,181 @@
+mod common;
+
+use common::{client, reply};
+use echomind::api::ChatRequest;
+use echomind::features::data_processing::{DataFormat, DataProcessor, Dataset, ExportFormat};
+use echomind::features::data_qa;
+use echomind::features::transform::TransformSpec;
+use serde_json::{json, Value};
+use std::io::Write;
+
+const PEOPLE: &str = "\
+name,email,signup,spend,qty
+  Ann Lee ,ANN@example.com,03/04/2024,\"$1,200.50\",2
+bob stone,bob@example.com,2024-02-10,80,1
+Ann Lee,ann@example.com,March 5 2024,(40),3
+Cy,,not a date,12%,
+";
+
+fn people() -> Dataset {
+    let mut file = tempfile::Builder::new().suffix(".csv").tempfile().unwrap();
+    write!(file, "{}", PEOPLE).unwrap();
+    DataProcessor::new()
+        .load_dataset(&file.path().to_string_lossy(), DataFormat::Csv)
+        .unwrap()
+}
+
+fn spec(value: Value) -> TransformSpec {
+    serde_json::from_value(value).unwrap()
+}
+
+#[test]
+fn test_transform_cleans_normalizes_dedupes_and_derives() {
+    let dataset = people();
+    let spec = spec(json!({"steps": [
+        {"op": "trim"},
+        {"op": "case", "columns": ["email"], "to": "lower"},
+        {"op": "case", "columns": ["name"], "to": "title"},
+        {"op": "parse_date", "columns": ["signup"], "formats": ["%d/%m/%Y", "%Y-%m-%d", "%B %d %Y"]},
+        {"op": "to_number", "columns": ["spend"]},
+        {"op": "fill", "columns": ["qty"], "value": 0},
+        {"op": "derive", "column": "per_item", "expr": "ROUND(spend / qty, 2)"},
+        {"op": "dedupe", "columns": ["email"], "keep": "last"},
+        {"op": "rename", "columns": {"signup": "signed_up"}},
+        {"op": "drop", "columns": ["qty"]}
+    ]}));
+    let outcome = spec.apply(&dataset, 2).unwrap();
+
+    let out = &outcome.dataset;
+    assert_eq!(out.column_names, vec!["name", "email", "signed_up", "spend", "per_item"]);
+    assert_eq!(out.records.len(), 3);
+    // The later Ann row wins the dedupe
+    let ann = &out.records[1];
+    assert_eq!(ann["name"], json!("Ann Lee"));
+    assert_eq!(ann["signed_up"], json!("2024-03-05"));
+    assert_eq!(ann["spend"], json!(-40));
+    let bob = &out.records[0];
+    assert_eq!((&bob["name"], &bob["per_item"]), (&json!("Bob Stone"), &json!(80)));
+    // Unparseable values are left alone; a zero quantity derives nothing
+    let cy = &out.records[2];
+    assert_eq!((&cy["signed_up"], &cy["spend"], &cy["per_item"]), (&json!("not a date"), &json!(12), &Value::Null));
+
+    assert_eq!(outcome.changed_rows, 3);
+    assert_eq!(outcome.removed, vec![("dedupe".to_string(), 1)]);
+    assert_eq!(outcome.summary(), "3 of 4 rows changed, 1 removed by dedupe; 3 rows out");
+    assert_eq!(outcome.preview.len(), 2);
+    assert_eq!(outcome.preview[0].row, 2);
+    let preview = outcome.render_preview();
+    assert!(preview.starts_with("row 2: name \"bob stone\" → \"Bob Stone\"; per_item null → 80\n"), "{}", preview);
+    assert!(preview.contains("signed_up \"March 5 2024\" → \"2024-03-05\""), "{}", preview);
+}
+
+#[test]
+fn test_transform_validation_and_filters() {
+    let dataset = people();
+    let columns = &dataset.column_names;
+
+    let err = spec(json!({"steps": [{"op": "rename", "columns": {"spend": "total"}}, {"op": "to_number", "columns": ["spend"]}]}))
+        .validate(columns)
+        .unwrap_err();
+    assert!(err.to_string().contains("Step 2 (convert spend to numbers): unknown column 'spend'"), "{}", err);
+    assert!(spec(json!({"steps": [{"op": "derive", "column": "x", "expr": "SUM(qty)"}]})).validate(columns).is_err());
+    assert!(spec(json!({"steps": [{"op": "replace", "columns": ["name"], "pattern": "(", "with": ""}]}))
+        .validate(columns)
+        .is_err());
+    assert!(spec(json!({"steps": [{"op": "parse_date", "columns": ["signup"], "output": "%Q"}]}))
+        .validate(columns)
+        .is_err());
+    assert!(TransformSpec::from_json(r#"{"steps": [{"op": "explode"}]}"#).is_err());
+
+    let outcome = spec(json!({"steps": [
+        {"op": "replace", "columns": ["email"], "pattern": "@example\\.com$", "with": "@corp.test"},
+        {"op": "filter", "where": "qty >= 2"},
+        {"op": "select", "columns": ["EMAIL"]}
+    ]}))
+    .apply(&dataset, 5)
+    .unwrap();
+    assert_eq!(outcome.dataset.column_names, vec!["email"]);
+    let emails: Vec<&Value> = outcome.dataset.records.iter().map(|r| &r["email"]).collect();
+    assert_eq!(emails, vec!["ANN@corp.test", "ann@corp.test"]);
+    assert_eq!(outcome.removed, vec![("filter".to_string(), 2)]);
+}
+
+#[test]
+fn test_export_formats_keep_column_order() {
+    let dataset = Dataset {
+        file_type: "CSV".to_string(),
+        column_names: vec!["z".to_string(), "a".to_string()],
+        records: vec![
+            [("z".to_string(), json!("x|y")), ("a".to_string(), json!(1.5))].into_iter().collect(),
+            [("z".to_string(), json!("line\nbreak")), ("a".to_string(), Value::Null)].into_iter().collect(),
+        ],
+    };
+    let processor = DataProcessor::new();
+    let render = |format| {
+        let mut out = Vec::new();
+        processor.write_data(&dataset, format, &mut out).unwrap();
+        String::from_utf8(out).unwrap()
+    };
+
+    assert_eq!(render(ExportFormat::Csv), "z,a\nx|y,1.5\n\"line\nbreak\",\n");
+    assert_eq!(render(ExportFormat::Tsv), "z\ta\nx|y\t1.5\n\"line\nbreak\"\t\n");
+    assert_eq!(render(ExportFormat::JsonLines), "{\"z\":\"x|y\",\"a\":1.5}\n{\"z\":\"line\\nbreak\",\"a\":null}\n");
+    let json: Value = serde_json::from_str(&render(ExportFormat::Json)).unwrap();
+    assert_eq!(json[1], json!({"z": "line\nbreak", "a": null}));
+    assert_eq!(render(ExportFormat::Markdown), "| z | a |\n| --- | --- |\n| x\\|y | 1.5 |\n| line<br>break |  |\n");
+
+    assert_eq!(ExportFormat::from_path("out/clean.ndjson"), Some(ExportFormat::JsonLines));
+    assert_eq!(ExportFormat::from_path("report.md"), Some(ExportFormat::Markdown));
+    assert!(ExportFormat::parse("parquet").is_err());
+
+    let file = tempfile::Builder::new().suffix(".tsv").tempfile().unwrap();
+    processor
+        .export_data(&dataset, ExportFormat::Tsv, &file.path().to_string_lossy())
+        .unwrap();
+    assert!(std::fs::read_to_string(file.path()).unwrap().starts_with("z\ta\n"));
+}
+
+#[tokio::test]
+async fn test_model_plans_a_checked_transform() {
+    let mut server = mockito::Server::new_async().await;
+    let good = server
+        .mock("POST", "/chat")
+        .match_body(mockito::Matcher::Regex("Instruction: lowercase the emails".to_string()))
+        .with_status(200)
+        .with_body(reply("```json\n{\"steps\": [{\"op\": \"case\", \"columns\": [\"email\"], \"to\": \"lower\"}]}\n```"))
+        .create_async()
+        .await;
+    let bad = server
+        .mock("POST", "/chat")
+        .match_body(mockito::Matcher::Regex("Instruction: drop phones".to_string()))
+        .with_status(200)
+        .with_body(reply("{\"steps\": [{\"op\": \"drop\", \"columns\": [\"phone\"]}]}"))
+        .create_async()
+        .await;
+
+    let dataset = people();
+    let analysis = DataProcessor::new().analyze(&dataset);
+    let client = client(format!("{}/chat", server.url()));
+    let base = ChatRequest {
+        messages: Vec::new(),
+        model: Some("test".to_string()),
+        temperature: None,
+        max_tokens: None,
+        top_p: None,
+        top_k: None,
+        stream: None,
+    };
+
+    let spec = data_qa::plan_transform(&client, base.clone(), &analysis, "people.csv", "lowercase the emails", None)
+        .await
+        .unwrap();
+    assert_eq!(spec.steps.len(), 1);
+    assert_eq!(spec.steps[0].to_string(), "lowercase email");
+
+    let err = data_qa::plan_transform(&client, base, &analysis, "people.csv", "drop phones", None)
+        .await
+        .unwrap_err();
+    assert!(err.to_string().contains("unknown column 'phone'"), "{}", err);
+    good.assert_async().await;
+    bad.assert_async().await;
+}
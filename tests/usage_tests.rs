@@ -0,0 +1,222 @@
+use chrono::{Duration, Local, TimeZone, Utc};
+use echomind::api::{ChatRequest, Message};
+use echomind::config::{BudgetAction, BudgetConfig, Config, ModelPrice};
+use echomind::error::EchomindError;
+use echomind::features::usage::{self, UsageGrouping, UsageLedger, UsageMeter, UsagePeriod, UsageRecord};
+
+fn record(day: u32, model: &str, preset: Option<&str>, cost: f64, success: bool) -> UsageRecord {
+    UsageRecord {
+        timestamp: Local.with_ymd_and_hms(2024, 5, day, 12, 0, 0).unwrap().with_timezone(&Utc),
+        provider: "openai".to_string(),
+        model: model.to_string(),
+        preset: preset.map(str::to_string),
+        command: "query".to_string(),
+        input_tokens: 100,
+        output_tokens: 50,
+        cost,
+        success,
+    }
+}
+
+fn request(text: &str, max_tokens: Option<u32>) -> ChatRequest {
+    ChatRequest {
+        messages: vec![Message::text("user".to_string(), text.to_string())],
+        model: Some("house-model".to_string()),
+        temperature: None,
+        max_tokens,
+        top_p: None,
+        top_k: None,
+        stream: None,
+    }
+}
+
+fn budget(daily: Option<f64>, monthly: Option<f64>, action: BudgetAction) -> BudgetConfig {
+    BudgetConfig {
+        daily,
+        monthly,
+        action,
+        prices: [(
+            "house-model".to_string(),
+            ModelPrice {
+                input_per_1k: 1.0,
+                output_per_1k: 2.0,
+            },
+        )]
+        .into_iter()
+        .collect(),
+    }
+}
+
+#[test]
+fn test_ledger_round_trip_and_period_summaries() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("data").join("usage.jsonl");
+    let ledger = UsageLedger::new(path.clone());
+    assert!(ledger.records().unwrap().is_empty());
+
+    for r in [
+        record(6, "gpt-4o-mini", Some("review"), 0.25, true),
+        record(6, "gpt-4o", None, 1.0, true),
+        record(7, "gpt-4o-mini", Some("review"), 0.5, false),
+        record(13, "gpt-4o-mini", None, 0.125, true),
+    ] {
+        ledger.record(&r).unwrap();
+    }
+    // A torn line from an interrupted write is skipped
+    std::fs::write(&path, format!("{}{{\"timestamp\":\n", std::fs::read_to_string(&path).unwrap())).unwrap();
+    let records = ledger.records().unwrap();
+    assert_eq!(records.len(), 4);
+
+    let daily = usage::summarize(&records, UsagePeriod::Daily, UsageGrouping::Model);
+    let keys: Vec<(&str, &str, u64, u64)> =
+        daily.iter().map(|r| (r.period.as_str(), r.key.as_str(), r.requests, r.errors)).collect();
+    assert_eq!(
+        keys,
+        vec![
+            ("2024-05-06", "openai:gpt-4o", 1, 0),
+            ("2024-05-06", "openai:gpt-4o-mini", 1, 0),
+            ("2024-05-07", "openai:gpt-4o-mini", 1, 1),
+            ("2024-05-13", "openai:gpt-4o-mini", 1, 0),
+        ]
+    );
+
+    let weekly = usage::summarize(&records, UsagePeriod::Weekly, UsageGrouping::Preset);
+    let keys: Vec<(&str, &str, u64, f64)> =
+        weekly.iter().map(|r| (r.period.as_str(), r.key.as_str(), r.requests, r.cost)).collect();
+    assert_eq!(
+        keys,
+        vec![("2024-W19", "(none)", 1, 1.0), ("2024-W19", "review", 2, 0.75), ("2024-W20", "(none)", 1, 0.125)]
+    );
+
+    let monthly = usage::summarize(&records, UsagePeriod::Monthly, UsageGrouping::Provider);
+    assert_eq!(monthly.len(), 1);
+    assert_eq!((monthly[0].requests, monthly[0].input_tokens, monthly[0].cost), (4, 400, 1.875));
+
+    let spend = usage::spend_at(&records, Local.with_ymd_and_hms(2024, 5, 7, 18, 0, 0).unwrap());
+    assert_eq!((spend.today, spend.this_month), (0.5, 1.75));
+    assert!(UsagePeriod::parse("hourly").is_err());
+}
+
+#[test]
+fn test_budget_check_warns_or_blocks_before_sending() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("usage.jsonl");
+    let now = Local::now();
+    let mut spent = record(1, "house-model", None, 0.9, true);
+    spent.timestamp = (now - Duration::minutes(1)).with_timezone(&Utc);
+    UsageLedger::new(path.clone()).record(&spent).unwrap();
+
+    // 8 input tokens at $1/1k plus 100 output tokens at $2/1k
+    let meter = UsageMeter::new(Some(UsageLedger::new(path.clone())), budget(Some(1.0), None, BudgetAction::Block), "query");
+    assert!((meter.estimate("openai", &request("a short question here", Some(100))) - 0.205).abs() < 0.01);
+    assert_eq!(meter.check_at("openai", &request("hi", Some(10)), now).unwrap(), None);
+    let err = meter.check_at("openai", &request("a short question here", Some(100)), now).unwrap_err();
+    assert!(err.to_string().starts_with("Request blocked: $0.90 of the $1.00 daily budget is spent today"), "{}", err);
+
+    let warn = UsageMeter::new(Some(UsageLedger::new(path.clone())), budget(None, Some(0.5), BudgetAction::Warn), "query");
+    let warning = warn.check_at("openai", &request("hi", Some(10)), now).unwrap().unwrap();
+    assert!(warning.contains("of the $0.50 monthly budget is spent this month"), "{}", warning);
+
+    // Checked requests hold their estimate until they are recorded, so
+    // concurrent requests can't all pass against the same spend
+    let meter = UsageMeter::new(Some(UsageLedger::new(path.clone())), budget(Some(1.0), None, BudgetAction::Block), "batch");
+    let small = request("x", Some(40));
+    assert_eq!(meter.check_at("openai", &small, now).unwrap(), None);
+    let err = meter.check_at("openai", &small, now).unwrap_err();
+    assert!(err.to_string().contains("is spent today (plus $0.08"), "{}", err);
+    meter.record("openai", &small, None).unwrap();
+    assert_eq!(meter.check_at("openai", &small, now).unwrap(), None);
+    assert!(meter.check_cost_at(0.5, now).is_err());
+
+    // Without caps or a ledger nothing is checked
+    let open = UsageMeter::new(Some(UsageLedger::new(path.clone())), BudgetConfig::default(), "query");
+    assert_eq!(open.check_at("openai", &request("hi", Some(100_000)), now).unwrap(), None);
+    let detached = UsageMeter::new(None, budget(Some(0.0), None, BudgetAction::Block), "query");
+    assert_eq!(detached.check_at("openai", &request("hi", None), now).unwrap(), None);
+}
+
+#[tokio::test]
+async fn test_send_checks_then_records_each_request() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("usage.jsonl");
+    let meter = UsageMeter::new(Some(UsageLedger::new(path.clone())), budget(Some(0.1), None, BudgetAction::Block), "query");
+
+    let reply = UsageMeter::send(Some(&meter), "openai", &request("hi", Some(10)), |warning| async move {
+        assert_eq!(warning, None);
+        Ok("hello".to_string())
+    })
+    .await;
+    assert_eq!(reply.unwrap(), "hello");
+
+    // Over a blocking budget the request is never sent
+    let mut sent = false;
+    let err = UsageMeter::send(Some(&meter), "openai", &request("hi", Some(100)), |_| {
+        sent = true;
+        async { Ok(String::new()) }
+    })
+    .await
+    .unwrap_err();
+    assert!(!sent);
+    assert!(err.to_string().starts_with("Request blocked"), "{}", err);
+
+    let failed = UsageMeter::send(Some(&meter), "openai", &request("hi", Some(10)), |_| async {
+        Err(EchomindError::Other("timeout".to_string()))
+    })
+    .await;
+    assert!(failed.is_err());
+
+    let records = UsageLedger::new(path).records().unwrap();
+    assert_eq!(records.iter().map(|r| r.success).collect::<Vec<_>>(), vec![true, false]);
+
+    // Over a warning budget `send` hears about it first
+    let warn = UsageMeter::new(Some(UsageLedger::new(dir.path().join("warn.jsonl"))), budget(Some(0.0), None, BudgetAction::Warn), "query");
+    let warning = UsageMeter::send(Some(&warn), "openai", &request("hi", None), |warning| async move {
+        Ok(warning.unwrap_or_default())
+    })
+    .await
+    .unwrap();
+    assert!(warning.contains("daily budget"), "{}", warning);
+}
+
+#[test]
+fn test_meter_records_costs_with_configured_prices() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("usage.jsonl");
+    let mut meter = UsageMeter::new(Some(UsageLedger::new(path.clone())), budget(None, None, BudgetAction::Warn), "compare");
+    meter.set_preset(Some("review".to_string()));
+    let req = request("x".repeat(4000).as_str(), None);
+    meter.record("custom", &req, Some(&"y".repeat(2000))).unwrap();
+    meter.record("custom", &req, None).unwrap();
+
+    let records = UsageLedger::new(path).records().unwrap();
+    assert_eq!(records.len(), 2);
+    let ok = &records[0];
+    assert_eq!((ok.model.as_str(), ok.preset.as_deref(), ok.command.as_str()), ("house-model", Some("review"), "compare"));
+    assert_eq!((ok.input_tokens, ok.output_tokens), (1000, 500));
+    assert!((ok.cost - 2.0).abs() < 1e-9);
+    assert!(!records[1].success);
+    assert_eq!((records[1].input_tokens, records[1].output_tokens, records[1].cost), (0, 0, 0.0));
+
+    let config: Config = toml::from_str(
+        r#"
+[api]
+provider = "openai"
+model = "gpt-4o-mini"
+timeout = 30
+
+[defaults]
+temperature = 0.7
+
+[budget]
+daily = 2.5
+action = "block"
+
+[budget.prices.house-model]
+input_per_1k = 0.5
+output_per_1k = 1.5
+"#,
+    )
+    .unwrap();
+    assert_eq!((config.budget.daily, config.budget.monthly, config.budget.action), (Some(2.5), None, BudgetAction::Block));
+    assert_eq!(config.budget.prices["house-model"].output_per_1k, 1.5);
+}
@@ -0,0 +1,547 @@
+use echomind::api::{ApiClient, Provider};
+use echomind::features::workflow::{
+    parse_variable_assignments, resolve_path, ConditionExpr, IssueSeverity, RunStatus,
+    WorkflowFormat, WorkflowManager,
+};
+use std::collections::HashMap;
+use std::io::Write;
+
+const LINEAR_YAML: &str = r#"
+id: review
+name: Code review
+start_step: ask
+steps:
+  - id: ask
+    name: Ask the model
+    step_type: AIRequest
+    prompt: "Review {file}"
+    next_step: show
+  - id: show
+    name: Print result
+    step_type: Output
+"#;
+
+#[test]
+fn test_load_yaml_workflow_by_extension() {
+    let mut file = tempfile::Builder::new().suffix(".yaml").tempfile().unwrap();
+    file.write_all(LINEAR_YAML.as_bytes()).unwrap();
+
+    let mut manager = WorkflowManager::new();
+    let id = manager
+        .load_workflow_from_file(&file.path().to_string_lossy())
+        .unwrap();
+
+    assert_eq!(id, "review");
+    let workflow = manager.get_workflow("review").unwrap();
+    assert_eq!(workflow.steps.len(), 2);
+    assert_eq!(workflow.steps[0].retry_count, 0);
+    assert!(WorkflowManager::validate_workflow(workflow).is_empty());
+}
+
+#[test]
+fn test_parse_toml_workflow() {
+    let toml_src = r#"
+id = "greet"
+name = "Greeting"
+start_step = "hello"
+
+[[steps]]
+id = "hello"
+name = "Say hello"
+step_type = "AIRequest"
+prompt = "Say hello to {name}"
+"#;
+
+    let workflow = WorkflowManager::parse_workflow(toml_src, WorkflowFormat::Toml).unwrap();
+    assert_eq!(workflow.start_step, "hello");
+    assert_eq!(workflow.steps[0].prompt.as_deref(), Some("Say hello to {name}"));
+}
+
+#[test]
+fn test_validate_reports_dangling_unreachable_and_cycles() {
+    let yaml = r#"
+id: broken
+name: Broken
+start_step: a
+steps:
+  - id: a
+    name: A
+    step_type: AIRequest
+    next_step: b
+    error_step: missing
+  - id: b
+    name: B
+    step_type: Transform
+    next_step: a
+  - id: orphan
+    name: Orphan
+    step_type: Output
+"#;
+
+    let workflow = WorkflowManager::parse_workflow(yaml, WorkflowFormat::Yaml).unwrap();
+    let issues = WorkflowManager::validate_workflow(&workflow);
+
+    assert!(issues.iter().any(|i| i.severity == IssueSeverity::Error
+        && i.message.contains("error_step 'missing'")));
+    assert!(issues.iter().any(|i| i.severity == IssueSeverity::Warning
+        && i.step_id.as_deref() == Some("orphan")));
+    assert!(issues.iter().any(|i| i.severity == IssueSeverity::Error
+        && i.message.starts_with("Cycle detected: a -> b -> a")));
+}
+
+#[test]
+fn test_validate_missing_start_step() {
+    let yaml = LINEAR_YAML.replace("start_step: ask", "start_step: nope");
+    let workflow = WorkflowManager::parse_workflow(&yaml, WorkflowFormat::Yaml).unwrap();
+    let issues = WorkflowManager::validate_workflow(&workflow);
+
+    assert!(issues
+        .iter()
+        .any(|i| i.severity == IssueSeverity::Error && i.message.contains("'nope'")));
+}
+
+#[test]
+fn test_parse_variable_assignments() {
+    let vars = parse_variable_assignments(&[
+        "name=world".to_string(),
+        "count=3".to_string(),
+        "flag=true".to_string(),
+        "expr=a=b".to_string(),
+    ])
+    .unwrap();
+
+    assert_eq!(vars["name"], serde_json::json!("world"));
+    assert_eq!(vars["count"], serde_json::json!(3));
+    assert_eq!(vars["flag"], serde_json::json!(true));
+    assert_eq!(vars["expr"], serde_json::json!("a=b"));
+
+    assert!(parse_variable_assignments(&["novalue".to_string()]).is_err());
+}
+
+#[test]
+fn test_condition_expressions() {
+    let yaml = r#"
+all:
+  - { variable: score, operator: GreaterThanOrEqual, value: 5 }
+  - not: { variable: review.verdict, operator: Matches, value: "(?i)^reject" }
+  - any:
+      - { variable: "$.review.tags[1]", operator: Equals, value: "security" }
+      - { variable: missing, operator: Exists }
+"#;
+    let expr: ConditionExpr = serde_yaml::from_str(yaml).unwrap();
+
+    let mut vars = HashMap::new();
+    vars.insert("score".to_string(), serde_json::json!("7\n"));
+    vars.insert(
+        "review".to_string(),
+        serde_json::json!(r#"{"verdict": "Approve", "tags": ["style", "security"]}"#),
+    );
+    assert!(WorkflowManager::evaluate_expr(&expr, &vars).unwrap());
+
+    vars.insert("score".to_string(), serde_json::json!(4));
+    assert!(!WorkflowManager::evaluate_expr(&expr, &vars).unwrap());
+}
+
+#[test]
+fn test_resolve_path() {
+    let mut vars = HashMap::new();
+    vars.insert(
+        "step_a".to_string(),
+        serde_json::json!({"output": {"items": [{"name": "first"}, {"name": "second"}]}}),
+    );
+
+    assert_eq!(
+        resolve_path(&vars, "step_a.output.items[1].name"),
+        Some(serde_json::json!("second"))
+    );
+    assert_eq!(
+        resolve_path(&vars, "$.step_a['output'].items[0]"),
+        Some(serde_json::json!({"name": "first"}))
+    );
+    assert_eq!(resolve_path(&vars, "step_a.output.items[5]"), None);
+}
+
+#[tokio::test]
+async fn test_conditions_branch_with_step_params() {
+    let yaml = r#"
+id: branch
+name: Branching
+start_step: shout
+variables:
+  input: "  hello  "
+  score: 8
+steps:
+  - id: shout
+    name: Shout
+    step_type: Transform
+    params: { transform: uppercase, output_variable: loud }
+    next_step: check
+  - id: check
+    name: Check score
+    step_type: Conditional
+    conditions:
+      - when: { variable: score, operator: GreaterThan, value: 5 }
+        next_step: high
+    next_step: low
+  - id: high
+    name: High
+    step_type: Transform
+    params: { transform: trim, input: "{loud}" }
+  - id: low
+    name: Low
+    step_type: Delay
+    params: { delay_ms: 1 }
+"#;
+    let mut manager = WorkflowManager::new();
+    manager.create_workflow(WorkflowManager::parse_workflow(yaml, WorkflowFormat::Yaml).unwrap());
+    let client = ApiClient::new(Provider::Ollama, None, 5).unwrap();
+
+    let context = manager
+        .execute_workflow("branch", HashMap::new(), &client)
+        .await
+        .unwrap();
+
+    let path: Vec<&str> = context.history.iter().map(|r| r.step_id.as_str()).collect();
+    assert_eq!(path, vec!["shout", "check", "high"]);
+    assert_eq!(context.variables["output"], serde_json::json!("HELLO"));
+}
+
+#[tokio::test]
+async fn test_step_provider_override_and_retries() {
+    let mut server = mockito::Server::new_async().await;
+    let mock = server
+        .mock("POST", "/")
+        .with_status(500)
+        .with_body("boom")
+        .expect(3)
+        .create_async()
+        .await;
+
+    let workflow = format!(
+        r#"
+id: flaky
+name: Flaky
+start_step: ask
+steps:
+  - id: ask
+    name: Ask
+    step_type: AIRequest
+    prompt: hi
+    provider: "{}/"
+    retry_count: 2
+    timeout: 5
+"#,
+        server.url()
+    );
+    let mut manager = WorkflowManager::new();
+    manager.create_workflow(WorkflowManager::parse_workflow(&workflow, WorkflowFormat::Yaml).unwrap());
+    manager.set_api_key(Some("test-key".to_string()));
+    let client = ApiClient::new(Provider::Ollama, None, 5).unwrap();
+
+    let context = manager
+        .execute_workflow("flaky", HashMap::new(), &client)
+        .await
+        .unwrap();
+
+    mock.assert_async().await;
+    assert_eq!(context.history.len(), 1);
+    assert!(!context.history[0].success);
+    assert_eq!(context.history[0].attempts, 3);
+    assert_eq!(context.errors.len(), 1);
+}
+
+#[tokio::test]
+async fn test_map_parallel_and_aggregate() {
+    let yaml = r#"
+id: fan
+name: Fan out
+start_step: each
+variables:
+  names: ["ada", "grace", "linus"]
+steps:
+  - id: each
+    name: Shout each name
+    step_type: Map
+    params: { step: shout, items: names, concurrency: 2 }
+    next_step: both
+  - id: shout
+    name: Shout
+    step_type: Transform
+    params: { transform: uppercase, input: "{item}" }
+  - id: both
+    name: Run branches
+    step_type: Parallel
+    params: { branches: [left, right] }
+    next_step: merge
+  - id: left
+    name: Left
+    step_type: Transform
+    params: { transform: identity, input: "L" }
+  - id: right
+    name: Right
+    step_type: Transform
+    params: { transform: identity, input: "R" }
+  - id: merge
+    name: Merge
+    step_type: Aggregate
+    params: { sources: [step_each_output, step_both_output], flatten: true, output_variable: merged }
+"#;
+    let workflow = WorkflowManager::parse_workflow(yaml, WorkflowFormat::Yaml).unwrap();
+    assert!(WorkflowManager::validate_workflow(&workflow).is_empty());
+
+    let mut manager = WorkflowManager::new();
+    manager.create_workflow(workflow);
+    let client = ApiClient::new(Provider::Ollama, None, 5).unwrap();
+    let context = manager
+        .execute_workflow("fan", HashMap::new(), &client)
+        .await
+        .unwrap();
+
+    assert_eq!(
+        context.variables["merged"],
+        serde_json::json!(["ADA", "GRACE", "LINUS", "L", "R"])
+    );
+    assert_eq!(context.variables["step_left_output"], serde_json::json!("L"));
+    assert_eq!(context.history[0].children.len(), 3);
+}
+
+#[tokio::test]
+async fn test_map_error_handling_modes() {
+    let yaml = r#"
+id: errors
+name: Errors
+start_step: each
+variables:
+  delays: ["1", "oops", "2"]
+steps:
+  - id: each
+    name: Wait for each
+    step_type: Map
+    params: { step: wait, items: delays, on_error: continue }
+  - id: wait
+    name: Wait
+    step_type: Delay
+    params: { delay_ms: "{item}" }
+"#;
+    let client = ApiClient::new(Provider::Ollama, None, 5).unwrap();
+
+    let mut manager = WorkflowManager::new();
+    manager.create_workflow(WorkflowManager::parse_workflow(yaml, WorkflowFormat::Yaml).unwrap());
+    let context = manager
+        .execute_workflow("errors", HashMap::new(), &client)
+        .await
+        .unwrap();
+    assert!(context.history[0].success);
+    assert!(context.variables["step_each_output"][1].is_null());
+    assert_eq!(context.variables["step_each_errors"][0]["index"], serde_json::json!(1));
+
+    let fail_fast = yaml.replace("on_error: continue", "on_error: fail_fast");
+    manager.create_workflow(WorkflowManager::parse_workflow(&fail_fast, WorkflowFormat::Yaml).unwrap());
+    let context = manager
+        .execute_workflow("errors", HashMap::new(), &client)
+        .await
+        .unwrap();
+    assert!(!context.history[0].success);
+    assert_eq!(context.errors.len(), 1);
+}
+
+#[tokio::test]
+async fn test_file_shell_http_and_json_steps() {
+    let dir = tempfile::tempdir().unwrap();
+    let input_path = dir.path().join("in.json");
+    std::fs::write(&input_path, r#"{"items": [{"name": "alpha"}, {"name": "beta"}]}"#).unwrap();
+    let out_path = dir.path().join("nested").join("out.txt");
+
+    let mut server = mockito::Server::new_async().await;
+    let mock = server
+        .mock("POST", "/hook")
+        .match_body(mockito::Matcher::Json(serde_json::json!({
+            "name": "alpha",
+            "items": r#"[{"name":"alpha"},{"name":"beta"}]"#
+        })))
+        .with_status(200)
+        .with_body(r#"Result: ```json
+{"accepted": true, "id": 42}
+```"#)
+        .create_async()
+        .await;
+
+    let yaml = format!(
+        r#"
+id: io
+name: IO
+start_step: read
+steps:
+  - id: read
+    name: Read
+    step_type: ReadFile
+    params: {{ path: "{input}", format: json }}
+    next_step: echo
+  - id: echo
+    name: Echo
+    step_type: Shell
+    params: {{ command: "echo 'first: {{read.output.items[0].name}}'" }}
+    next_step: call
+  - id: call
+    name: Call
+    step_type: HttpRequest
+    params:
+      url: "{url}/hook"
+      method: post
+      body: {{ name: "{{read.output.items[0].name}}", items: "{{read.output.items}}" }}
+    next_step: extract
+  - id: extract
+    name: Extract
+    step_type: JsonExtract
+    params: {{ source: call.body, path: id, output_variable: ticket }}
+    next_step: write
+  - id: write
+    name: Write
+    step_type: WriteFile
+    params: {{ path: "{out}", content: "{{echo.stdout}}ticket={{ticket}} exit={{echo.exit_code}}" }}
+"#,
+        input = input_path.display(),
+        url = server.url(),
+        out = out_path.display()
+    );
+    let mut manager = WorkflowManager::new();
+    manager.create_workflow(WorkflowManager::parse_workflow(&yaml, WorkflowFormat::Yaml).unwrap());
+    manager.set_shell_allowlist(vec!["echo".to_string()]);
+    let client = ApiClient::new(Provider::Ollama, None, 5).unwrap();
+
+    let context = manager
+        .execute_workflow("io", HashMap::new(), &client)
+        .await
+        .unwrap();
+
+    assert!(context.errors.is_empty(), "{:?}", context.errors);
+    mock.assert_async().await;
+    assert_eq!(
+        std::fs::read_to_string(&out_path).unwrap(),
+        "first: alpha\nticket=42 exit=0"
+    );
+}
+
+#[tokio::test]
+async fn test_shell_step_requires_allowlist() {
+    let yaml = r#"
+id: sh
+name: Shell
+start_step: run
+steps:
+  - id: run
+    name: Run
+    step_type: Shell
+    params: { command: "echo hi" }
+"#;
+    let mut manager = WorkflowManager::new();
+    manager.create_workflow(WorkflowManager::parse_workflow(yaml, WorkflowFormat::Yaml).unwrap());
+    let client = ApiClient::new(Provider::Ollama, None, 5).unwrap();
+
+    let context = manager
+        .execute_workflow("sh", HashMap::new(), &client)
+        .await
+        .unwrap();
+
+    assert!(!context.history[0].success);
+    assert!(context.errors[0].contains("allowlist"));
+}
+
+#[tokio::test]
+async fn test_runs_are_persisted_and_resumable() {
+    let dir = tempfile::tempdir().unwrap();
+    let input = dir.path().join("input.txt");
+    let workflow = format!(
+        r#"
+id: resumable
+name: Resumable
+start_step: greet
+steps:
+  - id: greet
+    name: Greet
+    step_type: Transform
+    params:
+      input: "hello {{who}}"
+      output_variable: greeting
+    next_step: read
+  - id: read
+    name: Read input
+    step_type: ReadFile
+    params:
+      path: "{}"
+    next_step: show
+  - id: show
+    name: Show
+    step_type: Output
+    params:
+      text: "{{greeting}} / {{read.output}}"
+"#,
+        input.display()
+    );
+
+    let mut manager = WorkflowManager::new();
+    manager.create_workflow(WorkflowManager::parse_workflow(&workflow, WorkflowFormat::Yaml).unwrap());
+    manager.set_runs_dir(dir.path().join("runs"));
+    let client = ApiClient::new(Provider::Ollama, None, 5).unwrap();
+
+    let mut variables = HashMap::new();
+    variables.insert("who".to_string(), serde_json::json!("world"));
+    let failed = manager.execute_workflow("resumable", variables, &client).await.unwrap();
+    assert_eq!(failed.current_step, "read");
+    assert_eq!(failed.errors.len(), 1);
+
+    let runs = manager.list_runs().unwrap();
+    assert_eq!(runs.len(), 1);
+    assert_eq!(runs[0].status, RunStatus::Failed);
+    assert_eq!(runs[0].context.history.len(), 2);
+
+    std::fs::write(&input, "file contents").unwrap();
+    let mut fresh = WorkflowManager::new();
+    fresh.set_runs_dir(dir.path().join("runs"));
+    let resumed = fresh
+        .resume_workflow(&failed.run_id[..8], &client)
+        .await
+        .unwrap();
+
+    assert!(resumed.errors.is_empty());
+    assert_eq!(resumed.history.len(), 4);
+    assert_eq!(resumed.history.last().unwrap().output.as_deref(), Some("HELLO WORLD / file contents"));
+    assert_eq!(fresh.load_run(&failed.run_id).unwrap().status, RunStatus::Completed);
+    assert!(fresh.resume_workflow(&failed.run_id, &client).await.is_err());
+}
+
+#[test]
+fn test_dry_run_plan_renders_prompts_and_follows_conditions() {
+    let workflow = r#"
+id: plan
+name: Plan
+start_step: check
+steps:
+  - id: check
+    name: Check mode
+    step_type: Transform
+    params:
+      transform: identity
+    conditions:
+      - when: { variable: mode, operator: Equals, value: fast }
+        next_step: quick
+    next_step: slow
+  - id: quick
+    name: Quick answer
+    step_type: AIRequest
+    prompt: "Briefly answer {question}"
+  - id: slow
+    name: Slow answer
+    step_type: AIRequest
+    prompt: "Think hard about {question}"
+"#;
+    let mut manager = WorkflowManager::new();
+    manager.create_workflow(WorkflowManager::parse_workflow(workflow, WorkflowFormat::Yaml).unwrap());
+
+    let variables = parse_variable_assignments(&["mode=fast".to_string(), "question=why".to_string()]).unwrap();
+    let plan = manager.plan_workflow("plan", variables).unwrap();
+
+    let ids: Vec<&str> = plan.iter().map(|s| s.step_id.as_str()).collect();
+    assert_eq!(ids, vec!["check", "quick"]);
+    assert_eq!(plan[1].rendered.as_deref(), Some("Briefly answer why"));
+}